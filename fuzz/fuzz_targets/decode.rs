@@ -0,0 +1,22 @@
+//! Decodes arbitrary words, which should never panic: whatever a word
+//! decodes to has to encode to a word that decodes the same. Each 32-bit
+//! word's low half is tried as a compressed instruction too.
+//!
+//! ```text
+//! cargo +nightly fuzz run decode
+//! ```
+
+#![no_main]
+
+use brubeck::rv32_i::Instruction;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for bytes in data.chunks_exact(4) {
+        let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if let Ok(instruction) = Instruction::decode(word) {
+            assert_eq!(Instruction::decode(instruction.encode()), Ok(instruction));
+        }
+        let _ = Instruction::decode_compressed(word as u16);
+    }
+});
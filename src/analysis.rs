@@ -0,0 +1,194 @@
+//! Static checks on a program before it runs.
+//!
+//! [lint] follows every path through a program from its first instruction
+//! and warns about code that can never run, and about jumps and branches that
+//! leave the program or land between instructions. Indirect jumps (`JALR`)
+//! can't be followed, so a call's return address is assumed to be reached.
+//!
+//! Programs don't have labels or data sections yet, so a branch "to an
+//! undefined label" shows up as a target outside the program.
+//!
+//! ```
+//! use brubeck::analysis::{lint, Lint};
+//! use brubeck::interpreter::parse_listing;
+//!
+//! let program = parse_listing("JAL zero, 4\nADDI x1, zero, 1\nNOP").unwrap();
+//! let warnings = lint(&program.into_iter().map(|(_, i)| i).collect::<Vec<_>>());
+//! assert_eq!(warnings.len(), 1);
+//! assert_eq!(warnings[0].index, 1);
+//! assert_eq!(warnings[0].lint, Lint::Unreachable);
+//! ```
+
+use std::fmt::Display;
+
+use crate::rv32_i::{Instruction, Register};
+
+/// Something suspicious about a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// No path from the start of the program reaches this instruction (or
+    /// the ones following it, up to the next reachable one)
+    Unreachable,
+    /// Jumps or branches to this address, which is outside the program
+    TargetOutsideProgram(u32),
+    /// Jumps or branches to this address, which isn't a multiple of 4
+    MisalignedTarget(u32),
+}
+
+/// A [Lint] found at an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Position of the instruction in the program
+    pub index: usize,
+    pub lint: Lint,
+}
+
+impl Warning {
+    /// Address of the instruction, relative to the start of the program
+    pub fn offset(&self) -> u32 {
+        self.index as u32 * Instruction::LENGTH
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.lint {
+            Lint::Unreachable => write!(f, "unreachable code"),
+            Lint::TargetOutsideProgram(target) => {
+                write!(f, "jumps to 0x{target:x}, outside the program")
+            }
+            Lint::MisalignedTarget(target) => {
+                write!(f, "jumps to 0x{target:x}, which isn't word aligned")
+            }
+        }
+    }
+}
+
+/// Checks a program laid out from address zero, returning warnings in
+/// program order
+pub fn lint(program: &[Instruction]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    let mut reachable = vec![false; program.len()];
+    let mut pending = if program.is_empty() { vec![] } else { vec![0] };
+
+    while let Some(index) = pending.pop() {
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+
+        let (target, falls_through) = successors(program[index], index);
+
+        if falls_through && index + 1 < program.len() {
+            pending.push(index + 1);
+        }
+
+        let Some(target) = target else {
+            continue;
+        };
+        let lint = if target % Instruction::LENGTH != 0 {
+            Lint::MisalignedTarget(target)
+        } else if target as usize / Instruction::LENGTH as usize >= program.len() {
+            Lint::TargetOutsideProgram(target)
+        } else {
+            pending.push(target as usize / Instruction::LENGTH as usize);
+            continue;
+        };
+        warnings.push(Warning { index, lint });
+    }
+
+    // one warning for each run of unreachable instructions
+    for index in 0..program.len() {
+        if !reachable[index] && (index == 0 || reachable[index - 1]) {
+            warnings.push(Warning {
+                index,
+                lint: Lint::Unreachable,
+            });
+        }
+    }
+
+    warnings.sort_by_key(|w| w.index);
+    warnings
+}
+
+/// Where control can go after an instruction: a known jump or branch target,
+/// and whether it can continue to the next instruction
+fn successors(instruction: Instruction, index: usize) -> (Option<u32>, bool) {
+    let pc = index as u32 * Instruction::LENGTH;
+    match instruction {
+        // a call returns to the next instruction; a plain jump doesn't
+        Instruction::JAL(j) => (Some(j.target(pc)), j.rd != Register::X0),
+        Instruction::JALR(i) => (None, i.rd != Register::X0),
+        Instruction::BEQ(b)
+        | Instruction::BNE(b)
+        | Instruction::BLT(b)
+        | Instruction::BGE(b)
+        | Instruction::BLTU(b)
+        | Instruction::BGEU(b) => (Some(b.target(pc)), true),
+        _ => (None, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::parse_listing;
+    use crate::rv32_i::{BType, JType};
+
+    fn lint_listing(listing: &str) -> Vec<Warning> {
+        let program = parse_listing(listing).unwrap();
+        lint(&program.into_iter().map(|(_, i)| i).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn reachability() {
+        // a backwards loop, and a call whose return point is reachable
+        let mut loop_back = BType::default();
+        loop_back.imm.set_signed(-2).unwrap();
+        let program = parse_listing("ADDI x1, x1, 1\nNOP\nJAL ra, 4\nJAL zero, 0\nNOP").unwrap();
+        let mut program: Vec<_> = program.into_iter().map(|(_, i)| i).collect();
+        program[1] = Instruction::BNE(loop_back);
+        assert_eq!(lint(&program), vec![]);
+
+        // skipped over, but the branch target after it is fine
+        assert_eq!(
+            lint_listing("JAL zero, 6\nNOP\nNOP\nADDI x1, zero, 1\nJALR zero, ra, 0\nNOP"),
+            vec![
+                Warning {
+                    index: 1,
+                    lint: Lint::Unreachable
+                },
+                Warning {
+                    index: 5,
+                    lint: Lint::Unreachable
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_targets() {
+        assert_eq!(
+            lint_listing("BEQ x1, x2, 8\nBNE x1, x2, 1\nNOP"),
+            vec![
+                Warning {
+                    index: 0,
+                    lint: Lint::TargetOutsideProgram(16)
+                },
+                Warning {
+                    index: 1,
+                    lint: Lint::MisalignedTarget(6)
+                },
+            ]
+        );
+
+        let mut backwards = JType::default();
+        backwards.imm.set_signed(-2).unwrap();
+        assert_eq!(
+            lint(&[Instruction::JAL(backwards)])
+                .first()
+                .map(|w| w.to_string()),
+            Some("jumps to 0xfffffffc, outside the program".to_owned())
+        );
+    }
+}
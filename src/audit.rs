@@ -0,0 +1,191 @@
+//! Cross-checks that every instruction is supported everywhere it should be.
+//!
+//! Instructions are defined in one table (see
+//! [Instruction::all](crate::rv32_i::Instruction::all)), but supporting one
+//! fully touches the parser, the encoder, the metadata, and the CPU. [audit]
+//! tries each instruction against each of those and reports the gaps, so a
+//! new instruction can't be half added without anyone noticing.
+//!
+//! ```
+//! use brubeck::audit::{audit, Check};
+//!
+//! let report = audit();
+//! let fence = report.iter().find(|f| f.mnemonic == "FENCE").unwrap();
+//! assert!(fence.missing.contains(&Check::Executes));
+//! ```
+
+use std::fmt::Display;
+
+use crate::interpreter::{self, Command};
+use crate::metadata;
+use crate::rv32_i::{CpuConfig, Error, Instruction, Xlen, CPU};
+
+/// Something every instruction should have
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Check {
+    /// An example written with the documented syntax parses back to it
+    Parser,
+    /// Encodes to a 32-bit instruction word
+    Encoder,
+    /// Machine code decodes back to the instruction
+    Decoder,
+    /// Is shown in assembly syntax that parses back to it
+    Display,
+    /// Has a [metadata] entry
+    Metadata,
+    /// The CPU runs it rather than reporting it isn't implemented
+    Executes,
+}
+
+impl Check {
+    pub const ALL: [Check; 6] = [
+        Check::Parser,
+        Check::Encoder,
+        Check::Decoder,
+        Check::Display,
+        Check::Metadata,
+        Check::Executes,
+    ];
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Parser => "parser",
+            Self::Encoder => "encoder",
+            Self::Decoder => "decoder",
+            Self::Display => "display",
+            Self::Metadata => "metadata",
+            Self::Executes => "executes",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The checks an instruction failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub mnemonic: &'static str,
+    /// The example the parser and CPU were tried with
+    pub example: String,
+    pub missing: Vec<Check>,
+}
+
+/// Checks every instruction, in alphabetical order
+pub fn audit() -> Vec<Finding> {
+    Instruction::all()
+        .into_iter()
+        .map(|instruction| {
+            let example = example(instruction);
+            let parsed = match interpreter::parse(&example) {
+                Ok(Command::Exec(parsed)) if parsed.mnemonic() == instruction.mnemonic() => {
+                    Some(parsed)
+                }
+                _ => None,
+            };
+
+            let missing = Check::ALL
+                .into_iter()
+                .filter(|check| !passes(*check, instruction, parsed))
+                .collect();
+
+            Finding {
+                mnemonic: instruction.mnemonic(),
+                example,
+                missing,
+            }
+        })
+        .collect()
+}
+
+fn passes(check: Check, instruction: Instruction, parsed: Option<Instruction>) -> bool {
+    match check {
+        Check::Parser => parsed.is_some(),
+        // every 32-bit instruction has its low two bits set
+        Check::Encoder => instruction.encode() & 0b11 == 0b11,
+        Check::Decoder => {
+            let instruction = parsed.unwrap_or(instruction);
+            Instruction::decode(instruction.encode()) == Ok(instruction)
+        }
+        Check::Display => {
+            let instruction = parsed.unwrap_or(instruction);
+            matches!(
+                interpreter::parse(&instruction.to_string()),
+                Ok(Command::Exec(shown)) if shown == instruction
+            )
+        }
+        Check::Metadata => metadata::lookup(instruction.mnemonic()).is_some(),
+        Check::Executes => {
+            let mut cpu = CPU::with_config(CpuConfig {
+                memory_size: 64,
+                xlen: match instruction.is_rv64() {
+                    true => Xlen::Rv64,
+                    false => Xlen::Rv32,
+                },
+                ..CpuConfig::default()
+            });
+            let result = cpu.execute(parsed.unwrap_or(instruction));
+            !matches!(result, Err(Error::NotImplemented(_)))
+        }
+    }
+}
+
+/// The instruction written out with made up operands, following the
+/// syntax in its metadata (eg: `ADDI x1, x2, 4`)
+fn example(instruction: Instruction) -> String {
+    let mnemonic = instruction.mnemonic();
+    let Some(info) = metadata::lookup(mnemonic) else {
+        return mnemonic.to_owned();
+    };
+
+    let operands: Vec<&str> = info
+        .syntax()
+        .split(", ")
+        .filter(|operand| !operand.is_empty())
+        .map(|operand| match operand {
+            "rd" => "x1",
+            "rs1" => "x2",
+            "rs2" => "x3",
+            "csr" => "mscratch",
+            "uimm" | "shamt" => "1",
+            _ => "4",
+        })
+        .collect();
+
+    format!("{mnemonic} {}", operands.join(", "))
+        .trim_end()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaps_are_found() {
+        let report = audit();
+        assert_eq!(report.len(), metadata::INSTRUCTIONS.len());
+
+        let find = |name| report.iter().find(|f| f.mnemonic == name).unwrap();
+        assert_eq!(find("ADDI").example, "ADDI x1, x2, 4");
+        assert_eq!(find("CSRRSI").example, "CSRRSI x1, mscratch, 1");
+        assert_eq!(find("ECALL").example, "ECALL");
+        assert_eq!(find("ADD").missing, vec![]);
+        assert!(find("FENCE").missing.contains(&Check::Executes));
+
+        for finding in &report {
+            for check in [
+                Check::Parser,
+                Check::Encoder,
+                Check::Display,
+                Check::Metadata,
+            ] {
+                assert!(
+                    !finding.missing.contains(&check),
+                    "{} fails the {check} check",
+                    finding.mnemonic
+                );
+            }
+        }
+    }
+}
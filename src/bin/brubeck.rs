@@ -1,21 +1,292 @@
+use brubeck::board::Board;
 use brubeck::interpreter::Interpreter;
+use brubeck::rv32_i::Xlen;
 
-use std::io;
+mod formatting;
+mod repl;
+
+use formatting::json::OutputFormat;
+use formatting::sink::{Tee, Terminal, Watch};
+use formatting::Style;
+use repl::args::Args;
+use repl::config::{self, Config};
+use repl::input::{Editor, Entry};
+use repl::transcript::Transcript;
+use repl::workspace::Workspace;
+use std::io::{self, IsTerminal};
 
 fn main() -> io::Result<()> {
-    let mut interpreter = Interpreter::new();
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| fail(&e));
+    if args.help {
+        println!("{}", repl::args::USAGE);
+        return Ok(());
+    }
 
-    println!("Brubeck: A RISC-V REPL");
-    println!("Ctrl-C to quit\n");
+    if let Some(directory) = &args.riscv_tests {
+        riscv_tests(directory);
+    }
 
-    loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
+    // the config file gives defaults; flags override them
+    let config = match args
+        .config
+        .clone()
+        .map(Into::into)
+        .or_else(config::config_path)
+    {
+        Some(path) => Config::load(&path).unwrap_or_else(|e| fail(&e)),
+        None => Config::default(),
+    };
+
+    let mut board = match &args.board {
+        Some(path) => Board::load(path).unwrap_or_else(|e| fail(&e.to_string())),
+        None => Board {
+            memory_size: config.memory_size.unwrap_or(Board::default().memory_size),
+            ..Board::default()
+        },
+    };
+    if let Some(size) = args.memory {
+        board.memory_size = size;
+    }
+    if let Some(base) = args.memory_base {
+        board.memory_base = base;
+    }
+    if let Some(address) = args.reset_vector {
+        board.reset_vector = Some(address);
+    }
+    if let Some(sp) = args.stack_pointer {
+        board.stack_pointer = sp;
+    }
+    if let Some(gp) = args.global_pointer {
+        board.global_pointer = Some(gp);
+    }
+    if let Some(extensions) = args.extensions {
+        board.extensions = extensions;
+    }
+    let mut interpreter = Interpreter::with_board(&board).unwrap_or_else(|e| fail(&e.to_string()));
+
+    if let Some(format) = args.format.or(config.number_format) {
+        interpreter.set_register_format(format);
+    }
+    let history_limit = args.history_limit.unwrap_or(config.history_limit);
+
+    if args.rv64 {
+        interpreter.cpu.config.xlen = Xlen::Rv64;
+    }
+
+    if args.double_check {
+        interpreter.set_double_check(true);
+    }
+
+    if args.readable_numbers {
+        interpreter.set_readable_numbers(true);
+    }
+
+    if let Some(root) = &args.semihosting_root {
+        interpreter.set_semihosting_root(root);
+    }
+
+    let workspace = match &args.workspace {
+        Some(path) => Some(Workspace::open(path)?),
+        None => None,
+    };
+
+    // transcripts are kept in the workspace unless asked for elsewhere
+    let transcript = match (&args.transcript, &workspace) {
+        (Some(path), _) => Some(Transcript::create(path)?),
+        (None, Some(workspace)) => {
+            let path = workspace.transcript_path()?;
+            Some(Transcript::create(&path.to_string_lossy())?)
+        }
+        (None, None) => None,
+    };
+
+    // when input is piped in, there's no banner or color, and the exit code
+    // says whether it all worked (a guest exit ends the process with its code)
+    let batch = !io::stdin().is_terminal();
+
+    let output = match args.json {
+        true => OutputFormat::Json,
+        false => OutputFormat::Text,
+    };
+
+    // JSON is never colored
+    let color = args.color.unwrap_or(config.color) && !batch;
+    let style = if color && io::stdout().is_terminal() && output == OutputFormat::Text {
+        Style::Color
+    } else {
+        Style::Plain
+    };
+
+    // everything shown on the terminal is also recorded in the transcript
+    let mut out = Tee::default();
+    out.push(Terminal::new(style));
+    if let Some(t) = &transcript {
+        out.push(t.clone());
+    }
+
+    let mut session = repl::commands::Session {
+        workspace,
+        // devices are drawn once they change
+        drawings: interpreter.cpu.devices.renders(),
+        output,
+        max_steps: args.max_steps,
+        ..Default::default()
+    };
 
-        let output = match interpreter.interpret(&buffer) {
-            Ok(s) => format!("✅ {}", s),
-            Err(s) => format!("❌ {}", s),
+    repl::interrupt::install(interpreter.cancel_token());
+
+    if let Some(path) = &args.load_elf {
+        if !repl::commands::is_elf(path) {
+            fail(&format!("{path} isn't an ELF executable"));
+        }
+        session.program = Some(path.clone());
+        let mut watch = Watch::new(&mut out);
+        repl::commands::load(path, None, true, &mut interpreter, &mut watch)?;
+        if watch.failed {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &args.script {
+        let passed = repl::script::run(path, &mut interpreter, &mut session, &mut out)?;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    // one line, then the guest's exit code (or 1 if the line failed)
+    if let Some(line) = &args.eval {
+        let mut watch = Watch::new(&mut out);
+        repl::handle(line, &mut interpreter, &mut session, &mut watch)?;
+        if watch.failed {
+            std::process::exit(1);
+        }
+        std::process::exit(interpreter.exit_code().unwrap_or(0) as i32);
+    }
+
+    if !batch && output == OutputFormat::Text {
+        println!("Brubeck: A RISC-V REPL");
+        println!("/help for help, Ctrl-C to stop a running program or quit\n");
+    }
+
+    // people typing at a terminal get completion and history, which is kept
+    // for next time
+    let mut editor = match !batch && output == OutputFormat::Text {
+        true => {
+            let mut editor = Editor::new(style == Style::Color);
+            if let Some(path) = config::history_path() {
+                editor.keep_history(path, history_limit);
+            }
+            Some(editor)
+        }
+        false => None,
+    };
+
+    // whether the program's exit has been shown, so it's only shown once
+    let mut exit_reported = false;
+    loop {
+        let entry = match &mut editor {
+            Some(editor) => editor.read_line()?,
+            None => {
+                let mut buffer = String::new();
+                match io::stdin().read_line(&mut buffer)? {
+                    0 => Entry::End,
+                    _ => Entry::Line(buffer),
+                }
+            }
         };
-        println!("=> {}", output);
+        let buffer = match entry {
+            Entry::Line(buffer) => buffer,
+            // as Ctrl-C at the prompt always has
+            Entry::Interrupted => std::process::exit(130),
+            Entry::End => {
+                let code = interpreter.exit_code().unwrap_or(0);
+                return finish(code, &session, batch, &mut out);
+            }
+        };
+
+        if let Some(t) = &transcript {
+            t.input(&buffer)?;
+        }
+
+        // until the input has been handled, Ctrl-C stops it rather than
+        // quitting
+        let _running = repl::interrupt::Running::start();
+
+        let command = repl::commands::is_command(&buffer);
+        let mut watch = Watch::new(&mut out);
+        repl::handle(&buffer, &mut interpreter, &mut session, &mut watch)?;
+        if watch.failed {
+            session.lines_failed += 1;
+        }
+
+        // commands (eg: /run) report an exit themselves
+        let reported = std::mem::replace(&mut exit_reported, interpreter.exit_code().is_some());
+        if command || reported {
+            continue;
+        }
+        if let Some(code) = interpreter.exit_code() {
+            match output {
+                OutputFormat::Text => formatting::exit(&mut out, code)?,
+                OutputFormat::Json => formatting::json::exit(&mut out, code)?,
+            }
+            if batch {
+                finish(code, &session, batch, &mut out)?;
+            }
+        }
+    }
+}
+
+/// Exits with the guest's exit code, unless a line failed (eg: an `/assert`)
+/// while input was piped in, in which case the exit code is 1 so the failure
+/// is noticed
+fn finish(
+    code: u32,
+    session: &repl::commands::Session,
+    batch: bool,
+    out: &mut dyn formatting::Sink,
+) -> io::Result<()> {
+    if !batch {
+        std::process::exit(code as i32);
+    }
+
+    // JSON results already say what failed
+    let failed = session.assertions_failed;
+    let summary = match (failed, session.lines_failed) {
+        (0, 0) => std::process::exit(code as i32),
+        (0, 1) => "1 line failed".to_owned(),
+        (0, lines) => format!("{lines} lines failed"),
+        (failed, _) => {
+            let checked = failed + session.assertions_passed;
+            format!("{failed} of {checked} assertions failed")
+        }
+    };
+    if session.output == OutputFormat::Text {
+        formatting::error(out, &summary)?;
     }
+    std::process::exit(1)
+}
+
+/// Runs the riscv-tests in a directory, listing how each went, then exits
+/// (with 1 if any failed)
+fn riscv_tests(directory: &str) -> ! {
+    let reports = brubeck::riscv_tests::run_directory(directory.as_ref(), "rv32ui-p-")
+        .unwrap_or_else(|e| fail(&format!("{directory}: {e}")));
+    if reports.is_empty() {
+        fail(&format!("{directory} has no rv32ui-p- tests"));
+    }
+
+    for report in &reports {
+        match report.passed() {
+            true => println!("PASS {}", report.name),
+            false => println!("FAIL {}: {}", report.name, report.outcome),
+        }
+    }
+    let passed = reports.iter().filter(|r| r.passed()).count();
+    println!("{passed} of {} tests passed", reports.len());
+    std::process::exit(if passed == reports.len() { 0 } else { 1 });
+}
+
+/// Reports a problem with the command line or config file, then exits
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
 }
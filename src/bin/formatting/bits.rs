@@ -0,0 +1,81 @@
+//! Shows a value bit by bit, for teaching masks and shifts.
+
+use std::io;
+
+use brubeck::bits::BitRange;
+
+use super::{Color, Sink};
+
+/// Shows `value` in binary under bit indices, grouped in nibbles, followed by
+/// the bits in `range` if one is given:
+///
+/// ```text
+/// x5 = 0x0000002a
+/// 31   27   23   19   15   11   7    3
+/// 0000 0000 0000 0000 0000 0000 0010 1010
+/// x5[7:4] = 0b0010 = 2 (0x2)
+/// ```
+pub fn bits(out: &mut dyn Sink, name: &str, value: u32, range: Option<BitRange>) -> io::Result<()> {
+    out.write(&format!("{name} = 0x{value:08x}"), Color::Default)?;
+    out.end_line()?;
+
+    out.write(&indices(), Color::Cyan)?;
+    out.end_line()?;
+
+    for nibble in (0..8).rev() {
+        let bits = format!("{:04b}", (value >> (nibble * 4)) & 0xf);
+        let in_range = range.is_some_and(|r| r.mask() & (0xf << (nibble * 4)) != 0);
+        let color = if in_range {
+            Color::Green
+        } else {
+            Color::Default
+        };
+        out.write(&bits, color)?;
+        if nibble > 0 {
+            out.write(" ", Color::Default)?;
+        }
+    }
+    out.end_line()?;
+
+    if let Some(range) = range {
+        let field = range.extract(value);
+        let width = range.width() as usize;
+        out.write(
+            &format!("{name}[{range}] = 0b{field:0width$b} = {field} (0x{field:x})"),
+            Color::Default,
+        )?;
+        out.end_line()?;
+    }
+
+    Ok(())
+}
+
+/// The index of the top bit of each nibble, lined up over the binary digits
+fn indices() -> String {
+    (0..8)
+        .rev()
+        .map(|nibble| format!("{:<5}", nibble * 4 + 3))
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+
+    #[test]
+    fn binary_with_indices() {
+        let mut buffer = Buffer::default();
+        bits(&mut buffer, "x5", 42, BitRange::parse("7:4")).unwrap();
+
+        assert_eq!(
+            buffer.text,
+            "x5 = 0x0000002a\n\
+             31   27   23   19   15   11   7    3\n\
+             0000 0000 0000 0000 0000 0000 0010 1010\n\
+             x5[7:4] = 0b0010 = 2 (0x2)\n"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! CSRs: a table of all of them, or one broken into its fields.
+
+use std::io;
+
+use brubeck::rv32_i::csr::{self, CsrDef};
+use brubeck::rv32_i::CPU;
+
+use super::{Color, Sink};
+
+/// Lists every CSR the CPU has, with its address, value, and whether it can
+/// be written, after the current privilege level. CSRs that can't be read
+/// (eg: the counters without Zicntr) are shown as unavailable.
+pub fn csrs(out: &mut dyn Sink, cpu: &CPU) -> io::Result<()> {
+    out.write(&format!("Privilege: {}", cpu.privilege), Color::Cyan)?;
+    out.end_line()?;
+
+    for (def, _) in cpu.csrs.iter() {
+        let value = match cpu.read_csr(def.address) {
+            Ok(value) => format!("0x{value:08x}"),
+            Err(_) => "unavailable".to_owned(),
+        };
+        let access = match csr::is_read_only(def.address) {
+            true => "read-only",
+            false => "read/write",
+        };
+        let line = format!(
+            "  {:<10} 0x{:03x}  {value:<11}  {access}",
+            def.name, def.address
+        );
+        out.write(&line, Color::Default)?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+/// Shows one CSR's value, then each of its fields (most significant first)
+/// with the bits it takes up
+pub fn csr(out: &mut dyn Sink, cpu: &CPU, def: &CsrDef) -> io::Result<()> {
+    let value = match cpu.read_csr(def.address) {
+        Ok(value) => value,
+        Err(_) => {
+            let message = format!("{} isn't available on this CPU", def.name);
+            out.write(&message, Color::Red)?;
+            return out.end_line();
+        }
+    };
+
+    let heading = format!(
+        "{} (0x{:03x}) = 0x{value:08x} = {value}",
+        def.name, def.address
+    );
+    out.write(&heading, Color::Cyan)?;
+    out.end_line()?;
+
+    for field in csr::fields(def.address) {
+        let bits = match field.high == field.low {
+            true => format!("[{}]", field.low),
+            false => format!("[{}:{}]", field.high, field.low),
+        };
+        out.write(
+            &format!("  {bits:<8} {}", field.describe(value)),
+            Color::Default,
+        )?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+
+    #[test]
+    fn table_and_fields() {
+        let mut cpu = CPU::default();
+        cpu.config.extensions.zicntr = false;
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MPP | csr::MSTATUS_MIE)
+            .unwrap();
+
+        let mut buffer = Buffer::default();
+        csrs(&mut buffer, &cpu).unwrap();
+        let lines: Vec<&str> = buffer.text.lines().collect();
+        assert_eq!(lines[0], "Privilege: machine");
+        assert_eq!(lines[1], "  mstatus    0x300  0x00001808   read/write");
+        assert!(lines.contains(&"  cycle      0xc00  unavailable  read-only"));
+        assert_eq!(lines.len(), 1 + csr::STANDARD.len());
+
+        let mut buffer = Buffer::default();
+        let mstatus = cpu.csrs.get(csr::MSTATUS).unwrap();
+        csr(&mut buffer, &cpu, mstatus).unwrap();
+        assert_eq!(
+            buffer.text,
+            "mstatus (0x300) = 0x00001808 = 6152\n\
+             \x20 [12:11]  MPP=Machine\n\
+             \x20 [7]      MPIE=0\n\
+             \x20 [3]      MIE=1\n"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Disassembly listings, with the instruction at the PC marked.
+
+use std::io;
+
+use brubeck::disassembler::Line;
+
+use super::{highlight, Color, Sink};
+
+/// Writes each line, marking the one at `pc` with `=>`
+pub fn disassembly(out: &mut dyn Sink, lines: &[Line], pc: u32) -> io::Result<()> {
+    for line in lines {
+        let marker = if line.address == pc { "=> " } else { "   " };
+        out.write(marker, Color::Green)?;
+        out.write(&format!("0x{:08x}:", line.address), Color::Cyan)?;
+
+        out.write(&format!(" {}  ", line.raw()), Color::Default)?;
+
+        match line.instruction {
+            Some(_) => highlight::line(out, &line.text())?,
+            None => out.write(&line.text(), Color::Red)?,
+        }
+
+        if let Some(comment) = line.comment() {
+            highlight::line(out, &format!("  # {comment}"))?;
+        }
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+    use brubeck::disassembler::disassemble;
+    use brubeck::rv32_i::CPU;
+
+    #[test]
+    fn marks_the_pc() {
+        let mut cpu = CPU::new(8);
+        cpu.write_memory(4, &0x0000_0013_u32.to_le_bytes()).unwrap();
+
+        let mut buffer = Buffer::default();
+        disassembly(&mut buffer, &disassemble(&cpu, 0, 2), 4).unwrap();
+        assert_eq!(
+            buffer.text,
+            "   0x00000000: 00000000  (not an instruction)\n\
+             => 0x00000004: 00000013  nop\n"
+        );
+    }
+}
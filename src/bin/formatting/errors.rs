@@ -0,0 +1,293 @@
+//! Hints that suggest what to do about an error.
+//!
+//! Execution errors are terse (eg: `MisalignedJump(6)`), which is fine once
+//! you know the machine, but not much help while learning it. [hint] looks
+//! at the kind of error and the machine it happened on, and [hints] shows
+//! the result under the error. For a mistake in the input, [caret] points at
+//! the part that's wrong.
+
+use std::io;
+
+use brubeck::interpreter::Error;
+use brubeck::rv32_i::{self, csr, DecodeError, Instruction, Xlen, CPU};
+
+use super::{Color, Sink};
+
+/// A suggestion for an error, if there's anything useful to say
+pub fn hint(error: &Error, cpu: &CPU) -> Option<String> {
+    if let Error::Statement { error, .. } | Error::At { error, .. } = error {
+        return hint(error, cpu);
+    }
+    let suggestions = error.suggestions();
+    if !suggestions.is_empty() {
+        return Some(format!("did you mean {}?", alternatives(&suggestions)));
+    }
+    let Error::Cpu(error) = error else {
+        return None;
+    };
+
+    Some(match error.clone() {
+        rv32_i::Error::MisalignedJump(target) if target % 2 == 0 => format!(
+            "the target 0x{target:08x} is not 4-byte aligned; branch and jump \
+             offsets count 2-byte steps, so a target 4 bytes away is an offset of 2"
+        ),
+        rv32_i::Error::MisalignedJump(target) => format!(
+            "the target 0x{target:08x} is odd; JALR clears bit 0 of rs1 + offset, \
+             but branch and jump offsets must be even"
+        ),
+        rv32_i::Error::AccessViolation(address) => match memory(cpu) {
+            Some((first, last)) if (first..=last).contains(&address) => format!(
+                "0x{address:08x} is in memory, but the access runs past the end of \
+                 it at 0x{last:08x}"
+            ),
+            Some((first, last)) => format!(
+                "0x{address:08x} isn't memory or a device; memory is \
+                 0x{first:08x} to 0x{last:08x}, and /devices shows the devices"
+            ),
+            None => format!("0x{address:08x} isn't memory or a device"),
+        },
+        rv32_i::Error::IllegalCsr(address)
+            if csr::is_counter(address) && !cpu.config.extensions.zicntr =>
+        {
+            "the counter CSRs need the Zicntr extension, which this board leaves out".to_owned()
+        }
+        rv32_i::Error::IllegalCsr(address) => match cpu.csrs.get(address).map(|def| def.name) {
+            Some(name) if csr::is_read_only(address) => format!(
+                "{name} is read-only (CSRs whose addresses start 0b11 can't be \
+                 written); use CSRRS or CSRRC with zero to read it"
+            ),
+            Some(name) => format!("{name} isn't available on this CPU"),
+            None => format!(
+                "there's no CSR at 0x{address:03x}; try one by name, eg: mstatus, \
+                 mtvec, mepc, mcause, or mscratch"
+            ),
+        },
+        rv32_i::Error::IllegalInstruction(DecodeError::Compressed(_))
+            if !cpu.config.extensions.c =>
+        {
+            "this looks like compressed code (gcc builds rv32imac by default); \
+             isa = \"rv32imac\" in a board file turns on the C extension"
+                .to_owned()
+        }
+        rv32_i::Error::IllegalInstruction(_) => format!(
+            "the PC (0x{:08x}) may have run into data or unused memory; programs \
+             usually end with an ECALL (a7 = 93) rather than running off the end",
+            cpu.pc
+        ),
+        rv32_i::Error::NotImplemented(Instruction::ECALL(_)) if cpu.syscalls.is_none() => {
+            "this board has no system call handler, so ECALL can't do anything".to_owned()
+        }
+        rv32_i::Error::NotImplemented(Instruction::ECALL(_)) => format!(
+            "system call {} (in a7) isn't supported; try 63 (read), 64 (write), \
+             93 (exit), or 169 (gettimeofday)",
+            cpu.x17
+        ),
+        rv32_i::Error::NotImplemented(instruction)
+            if instruction.is_multiply() && !cpu.config.extensions.m =>
+        {
+            format!(
+                "{} is part of the M extension, which this board leaves out",
+                instruction.mnemonic()
+            )
+        }
+        rv32_i::Error::NotImplemented(instruction)
+            if instruction.is_atomic() && !cpu.config.extensions.a =>
+        {
+            format!(
+                "{} is part of the A extension, which this board leaves out",
+                instruction.mnemonic()
+            )
+        }
+        rv32_i::Error::NotImplemented(instruction)
+            if instruction.is_rv64() && cpu.config.xlen == Xlen::Rv32 =>
+        {
+            format!(
+                "{} is only in RV64I; brubeck --rv64 starts a 64-bit CPU",
+                instruction.mnemonic()
+            )
+        }
+        rv32_i::Error::NotImplemented(instruction) if cpu.config.xlen == Xlen::Rv64 => format!(
+            "{} isn't available in RV64 mode, which leaves out the M and A extensions",
+            instruction.mnemonic()
+        ),
+        rv32_i::Error::NotImplemented(instruction) => format!(
+            "{} isn't implemented yet; /audit lists what's missing",
+            instruction.mnemonic()
+        ),
+        rv32_i::Error::Breakpoint(pc) => {
+            format!("EBREAK at 0x{pc:08x} stops execution, like a debugger breakpoint")
+        }
+        rv32_i::Error::MisalignedAccess(instruction, address) if instruction.is_atomic() => {
+            format!(
+                "{instruction} accesses a word at 0x{address:08x}, which isn't a multiple \
+                 of 4; atomic instructions always need aligned addresses"
+            )
+        }
+        rv32_i::Error::MisalignedAccess(instruction, address) => {
+            let (verb, size, name) = match instruction {
+                Instruction::LW(_) => ("loads", 4, "word"),
+                Instruction::SW(_) => ("stores", 4, "word"),
+                Instruction::SH(_) => ("stores", 2, "halfword"),
+                Instruction::LWU(_) => ("loads", 4, "word"),
+                Instruction::LD(_) => ("loads", 8, "doubleword"),
+                Instruction::SD(_) => ("stores", 8, "doubleword"),
+                _ => ("loads", 2, "halfword"),
+            };
+            format!(
+                "{instruction} {verb} a {name} at 0x{address:08x}, which isn't a multiple \
+                 of {size}; this board doesn't allow misaligned accesses (misaligned = \
+                 \"emulate\" in a board file does)"
+            )
+        }
+        rv32_i::Error::EnvironmentCall(_) => "this board stops on ECALL; a7 = 93 with the \
+             exit code in a0 is the usual way for a program to finish"
+            .to_owned(),
+    })
+}
+
+/// Names as a list to choose from: `a`, `a or b`, or `a, b, or c`
+pub fn alternatives(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => name.clone(),
+        [first, second] => format!("{first} or {second}"),
+        [rest @ .., last] => format!("{}, or {last}", rest.join(", ")),
+    }
+}
+
+/// Shows the hint for an error, if there is one
+pub fn hints(out: &mut dyn Sink, error: &Error, cpu: &CPU) -> io::Result<()> {
+    match hint(error, cpu) {
+        Some(hint) => {
+            out.write(&format!("   hint: {hint}"), Color::Yellow)?;
+            out.end_line()
+        }
+        None => Ok(()),
+    }
+}
+
+/// Shows the line of input an error is about, with `^` under the part it's
+/// about, if the error says where that is
+pub fn caret(out: &mut dyn Sink, input: &str, error: &Error) -> io::Result<()> {
+    let Some(span) = error.span() else {
+        return Ok(());
+    };
+    let (Some(before), Some(part)) = (input.get(..span.start), input.get(span.start..span.end))
+    else {
+        return Ok(());
+    };
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let column = before[start..].chars().count();
+    let width = part.lines().next().unwrap_or("").chars().count().max(1);
+
+    out.write(
+        &format!("   {}", input[start..end].replace('\t', " ")),
+        Color::Default,
+    )?;
+    out.end_line()?;
+    let carets = format!("   {}{}", " ".repeat(column), "^".repeat(width));
+    out.write(&carets, Color::Red)?;
+    out.end_line()
+}
+
+/// The first and last address of the segment holding the lowest addresses
+/// (usually all of memory)
+fn memory(cpu: &CPU) -> Option<(u32, u32)> {
+    let segment = cpu.address_map.segments().first()?;
+    let size = cpu.memory.len().checked_sub(segment.offset)?;
+    let last = (segment.last - segment.base).min(size.checked_sub(1)? as u32);
+    Some((segment.base, segment.base + last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_for_cpu_errors() {
+        let cpu = CPU::new(1024);
+        let hint = |e| hint(&Error::Cpu(e), &cpu).unwrap();
+
+        assert!(hint(rv32_i::Error::MisalignedJump(6)).contains("0x00000006 is not 4-byte"));
+        assert_eq!(
+            hint(rv32_i::Error::AccessViolation(0x2000)),
+            "0x00002000 isn't memory or a device; memory is 0x00000000 to \
+             0x000003ff, and /devices shows the devices"
+        );
+        assert!(hint(rv32_i::Error::AccessViolation(1022)).contains("runs past the end"));
+        assert!(hint(rv32_i::Error::IllegalCsr(csr::CYCLE)).starts_with("cycle is read-only"));
+        assert!(hint(rv32_i::Error::IllegalCsr(0x7ff)).contains("no CSR at 0x7ff"));
+        assert!(
+            hint(rv32_i::Error::IllegalInstruction(DecodeError::Compressed(
+                0x4515
+            )))
+            .contains("compressed code")
+        );
+        assert!(hint(rv32_i::Error::NotImplemented(Instruction::ECALL(
+            Default::default()
+        )))
+        .starts_with("system call 0 (in a7) isn't supported"));
+        assert_eq!(
+            hint(rv32_i::Error::NotImplemented(Instruction::ADDW(
+                Default::default()
+            ))),
+            "ADDW is only in RV64I; brubeck --rv64 starts a 64-bit CPU"
+        );
+
+        let lw = brubeck::interpreter::parse_listing("LW a0, sp, 2").unwrap()[0].1;
+        assert_eq!(
+            hint(rv32_i::Error::MisalignedAccess(lw, 0x1006)),
+            "lw a0, sp, 2 loads a word at 0x00001006, which isn't a multiple of 4; \
+             this board doesn't allow misaligned accesses (misaligned = \"emulate\" \
+             in a board file does)"
+        );
+
+        assert_eq!(super::hint(&Error::Cancelled, &cpu), None);
+        let statement = Error::Statement {
+            index: 1,
+            error: Box::new(Error::Cpu(rv32_i::Error::MisalignedJump(6))),
+        };
+        assert!(super::hint(&statement, &cpu).is_some());
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut i = brubeck::Interpreter::new();
+        let mut hint = |input| hint(&i.interpret(input).unwrap_err(), &CPU::default());
+        assert_eq!(
+            hint("ADDD x1, x2, x3").unwrap(),
+            "did you mean ADD, ADDI, or ADDW?"
+        );
+        assert_eq!(
+            hint("NOP; SUBB x1, x2, x3").unwrap(),
+            "did you mean SUB or SUBW?"
+        );
+        assert_eq!(hint("ADDI x1, zero, x99"), None);
+    }
+
+    #[test]
+    fn carets() {
+        use crate::formatting::sink::Buffer;
+        let mut i = brubeck::Interpreter::new();
+
+        let input = "NOP; ADDI x1, x99, 3";
+        let error = i.interpret(input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Statement 2: second operand `x99`: invalid register"
+        );
+        let mut buffer = Buffer::default();
+        caret(&mut buffer, input, &error).unwrap();
+        assert_eq!(
+            buffer.text,
+            "   NOP; ADDI x1, x99, 3\n\
+             \x20                ^^^\n"
+        );
+
+        // nothing to point at
+        let mut buffer = Buffer::default();
+        caret(&mut buffer, "EBREAK", &i.interpret("EBREAK").unwrap_err()).unwrap();
+        assert_eq!(buffer.text, "");
+    }
+}
@@ -0,0 +1,147 @@
+//! Syntax highlighting for assembly: mnemonics, registers, CSRs, immediates,
+//! and comments each get their own color, in listings and as it's typed.
+
+use std::io;
+
+use brubeck::interpreter;
+use brubeck::metadata;
+use brubeck::rv32_i::{csr, Register};
+
+use super::{Color, Sink};
+
+const MNEMONIC: Color = Color::Cyan;
+const REGISTER: Color = Color::Green;
+const CSR: Color = Color::Magenta;
+const IMMEDIATE: Color = Color::Yellow;
+const COMMENT: Color = Color::Dim;
+
+/// Splits a line of assembly into pieces with their colors; together, the
+/// pieces are the whole line. Commands (lines starting with `/`) aren't
+/// highlighted.
+pub fn spans(line: &str) -> Vec<(&str, Color)> {
+    if line.trim_start().starts_with('/') {
+        return vec![(line, Color::Default)];
+    }
+
+    let (code, comment) = match line.find('#') {
+        Some(i) => line.split_at(i),
+        None => (line, ""),
+    };
+
+    let mut spans = vec![];
+    // whether the next word is where a mnemonic goes
+    let mut first = true;
+    let mut rest = code;
+    while !rest.is_empty() {
+        let end = match rest.find(separator) {
+            Some(0) => rest.find(|c| !separator(c)).unwrap_or(rest.len()),
+            Some(end) => end,
+            None => rest.len(),
+        };
+        let (piece, remaining) = rest.split_at(end);
+        rest = remaining;
+
+        if piece.starts_with(separator) {
+            if piece.contains(';') {
+                first = true;
+            }
+            spans.push((piece, Color::Default));
+            continue;
+        }
+
+        let color = match first {
+            // a label, before the mnemonic
+            _ if piece.ends_with(':') => Color::Default,
+            true if is_mnemonic(piece) => MNEMONIC,
+            true => Color::Default,
+            false => operand(piece),
+        };
+        first = first && piece.ends_with(':');
+        spans.push((piece, color));
+    }
+
+    if !comment.is_empty() {
+        spans.push((comment, COMMENT));
+    }
+    spans
+}
+
+/// Writes a line of assembly, highlighted (without ending the line)
+pub fn line(out: &mut dyn Sink, line: &str) -> io::Result<()> {
+    for (text, color) in spans(line) {
+        out.write(text, color)?;
+    }
+    Ok(())
+}
+
+/// A line of assembly with ANSI colors, for writing straight to a terminal
+pub fn ansi(line: &str) -> String {
+    spans(line)
+        .into_iter()
+        .map(|(text, color)| color.paint(text))
+        .collect()
+}
+
+fn separator(c: char) -> bool {
+    c.is_whitespace() || ",;()[]+-*".contains(c)
+}
+
+fn is_mnemonic(word: &str) -> bool {
+    let upper = word.to_uppercase();
+    metadata::lookup(&upper).is_some() || interpreter::pseudo_instructions().any(|p| p == upper)
+}
+
+fn operand(word: &str) -> Color {
+    let upper = word.to_uppercase();
+    if Register::from_name(&upper).is_some() {
+        REGISTER
+    } else if csr::STANDARD
+        .iter()
+        .any(|def| def.name.eq_ignore_ascii_case(word))
+    {
+        CSR
+    } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '\'') {
+        IMMEDIATE
+    } else {
+        Color::Default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pieces_and_colors() {
+        let colored: Vec<(&str, Color)> = spans("loop: addi a0, a0, -1 # count down")
+            .into_iter()
+            .filter(|(text, _)| !text.trim().is_empty())
+            .collect();
+        assert_eq!(
+            colored,
+            [
+                ("loop:", Color::Default),
+                ("addi", MNEMONIC),
+                ("a0", REGISTER),
+                (", ", Color::Default),
+                ("a0", REGISTER),
+                (", -", Color::Default),
+                ("1", IMMEDIATE),
+                ("# count down", COMMENT),
+            ]
+        );
+
+        // every piece, so nothing's lost
+        let line = "CSRRS t0, mstatus, x0; sw sp, a0, 0x10";
+        let pieces: String = spans(line).iter().map(|(text, _)| *text).collect();
+        assert_eq!(pieces, line);
+        let colors: Vec<Color> = spans(line).iter().map(|(_, color)| *color).collect();
+        assert!(colors.contains(&CSR));
+        assert_eq!(colors.iter().filter(|c| **c == MNEMONIC).count(), 2);
+
+        // typos and commands are left alone
+        assert_eq!(spans("adx a0")[0], ("adx", Color::Default));
+        assert_eq!(spans("/regs hex"), [("/regs hex", Color::Default)]);
+        assert_eq!(ansi("nop"), "\x1b[36mnop\x1b[0m");
+    }
+}
@@ -0,0 +1,165 @@
+//! The undo history, as a timeline of what ran, and the net change between
+//! two points in it.
+
+use std::io;
+
+use brubeck::history::{StateDelta, StateHistory, Step};
+
+use super::{highlight, Color, Sink};
+
+/// Lists up to `limit` steps around the current one, which is marked with
+/// `=>`; the steps after it can be redone
+pub fn history(out: &mut dyn Sink, history: &StateHistory, limit: usize) -> io::Result<()> {
+    if history.is_empty() {
+        out.write("Nothing executed yet", Color::Default)?;
+        return out.end_line();
+    }
+
+    let (epoch, len) = (history.epoch(), history.len());
+    out.write(&format!("Step {epoch} of {len}"), Color::Cyan)?;
+    out.end_line()?;
+
+    let end = len.min(epoch.saturating_sub(limit / 2 + 1) + limit);
+    let start = end.saturating_sub(limit);
+    if start > 0 {
+        out.write(&format!("   ... {start} earlier"), Color::Default)?;
+        out.end_line()?;
+    }
+
+    for (index, (pc, step)) in history.timeline().enumerate().take(end).skip(start) {
+        let number = index + 1;
+        let marker = if number == epoch { "=> " } else { "   " };
+        out.write(marker, Color::Green)?;
+        out.write(&format!("{number:>6}  "), Color::Default)?;
+        out.write(&format!("0x{pc:08x}:  "), Color::Cyan)?;
+        match step {
+            Step::Executed(instruction) => highlight::line(out, &instruction.to_string())?,
+            Step::Interrupt(cause) => out.write(&format!("({cause})"), Color::Yellow)?,
+            Step::Set => out.write("(set by hand)", Color::Yellow)?,
+        }
+        out.end_line()?;
+    }
+
+    if end < len {
+        out.write(&format!("   ... {} later", len - end), Color::Default)?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+/// Most bytes of a write to show; the rest are counted
+const BYTES_SHOWN: usize = 8;
+
+/// Shows what changed overall from step `a` to step `b`: each register and
+/// run of bytes once, with its value before and after
+pub fn diff(out: &mut dyn Sink, a: usize, b: usize, delta: &StateDelta) -> io::Result<()> {
+    let bytes: usize = delta.memory.iter().map(|w| w.new.len()).sum();
+    let heading = format!(
+        "Steps {} to {}: {} registers and {bytes} bytes changed in {} instructions",
+        a.min(b),
+        a.max(b),
+        delta.registers.len(),
+        delta.counted.instret
+    );
+    out.write(&heading, Color::Cyan)?;
+    out.end_line()?;
+
+    for change in delta.registers.iter() {
+        out.write(&format!("  {:>4}: ", change.register), Color::Default)?;
+        out.write(&format!("0x{:08x}", change.old), Color::Red)?;
+        out.write(" -> ", Color::Default)?;
+        out.write(&format!("0x{:08x}", change.new), Color::Green)?;
+        out.end_line()?;
+    }
+
+    for change in delta.csrs.iter() {
+        out.write(&format!("  {}: ", change.name()), Color::Default)?;
+        out.write(&format!("0x{:08x}", change.old), Color::Red)?;
+        out.write(" -> ", Color::Default)?;
+        out.write(&format!("0x{:08x}", change.new), Color::Green)?;
+        out.end_line()?;
+    }
+
+    if let Some((old, new)) = delta.privilege {
+        out.write("  mode: ", Color::Default)?;
+        out.write(&old.to_string(), Color::Red)?;
+        out.write(" -> ", Color::Default)?;
+        out.write(&new.to_string(), Color::Green)?;
+        out.end_line()?;
+    }
+
+    for write in delta.memory.iter() {
+        out.write(&format!("  0x{:08x}: ", write.address), Color::Cyan)?;
+        out.write(&hex_bytes(&write.old), Color::Red)?;
+        out.write(" -> ", Color::Default)?;
+        out.write(&hex_bytes(&write.new), Color::Green)?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    let shown: Vec<String> = bytes
+        .iter()
+        .take(BYTES_SHOWN)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    match bytes.len() > BYTES_SHOWN {
+        true => format!("{} (+{} more)", shown.join(" "), bytes.len() - BYTES_SHOWN),
+        false => shown.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+    use brubeck::Interpreter;
+
+    #[test]
+    fn timeline() {
+        let mut i = Interpreter::new();
+        let mut buffer = Buffer::default();
+        history(&mut buffer, i.history(), 3).unwrap();
+        assert_eq!(buffer.text, "Nothing executed yet\n");
+
+        for _ in 0..5 {
+            i.interpret("ADDI a0, a0, 1").unwrap();
+        }
+        i.seek(3).unwrap();
+
+        let mut buffer = Buffer::default();
+        history(&mut buffer, i.history(), 3).unwrap();
+        assert_eq!(
+            buffer.text,
+            "Step 3 of 5\n   \
+             ... 1 earlier\n   \
+             \x20    2  0x00000004:  addi a0, a0, 1\n\
+             =>      3  0x00000008:  addi a0, a0, 1\n   \
+             \x20    4  0x0000000c:  addi a0, a0, 1\n   \
+             ... 1 later\n"
+        );
+    }
+
+    #[test]
+    fn net_change() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI a0, zero, 0x41; ADDI sp, zero, 0x100")
+            .unwrap();
+        i.interpret("SW sp, a0, 0; ADDI a0, a0, 1; ADDI a0, a0, 0xfff")
+            .unwrap();
+        i.interpret("SB sp, a0, 1; ADDI t0, zero, 12").unwrap();
+
+        let mut buffer = Buffer::default();
+        let delta = i.history().diff(7, 2).unwrap();
+        diff(&mut buffer, 7, 2, &delta).unwrap();
+        assert_eq!(
+            buffer.text,
+            "Steps 2 to 7: 2 registers and 2 bytes changed in 5 instructions\n\
+             \x20   x5: 0x00000000 -> 0x0000000c\n\
+             \x20   pc: 0x00000008 -> 0x0000001c\n\
+             \x20 0x00000100: 00 00 -> 41 41\n"
+        );
+        assert_eq!(hex_bytes(&[0; 10]), "00 00 00 00 00 00 00 00 (+2 more)");
+    }
+}
@@ -0,0 +1,210 @@
+//! Output as JSON, one object per line, for programs that drive the REPL
+//! (`brubeck --json`) rather than people. Every object has a `type`:
+//!
+//! - `result`: a line of input was interpreted, with `ok`, the `output` or
+//!   the error's `code` and `message` (and a `hint`, and the `span` of input
+//!   it's about as `[start, end]` byte offsets, if there are), and the
+//!   `registers` and `memory` it changed
+//! - `registers` and `memory`: from `/regs` and `/mem`, as numbers
+//! - `output`: what the guest printed, from the `console` or the `uart`
+//! - `command`: any other command, with `ok` and the `lines` it showed
+//! - `devices`: devices that were drawn again because they changed
+//! - `exit`: the guest exited, with its `code`
+
+use std::io;
+
+use brubeck::history::StateDelta;
+use brubeck::interpreter::Error;
+use brubeck::json::{self, Object};
+use brubeck::rv32_i::{Register, CPU};
+
+use super::{Color, Sink, FAILURE};
+
+/// How the REPL shows what happens
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum OutputFormat {
+    /// For people: text, in color on a terminal
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Shows the result of interpreting `input`; `delta` is what it changed
+pub fn result(
+    out: &mut dyn Sink,
+    input: &str,
+    result: &Result<String, Error>,
+    delta: Option<&StateDelta>,
+    hint: Option<String>,
+) -> io::Result<()> {
+    let object = Object::new()
+        .string("type", "result")
+        .string("input", input)
+        .bool("ok", result.is_ok());
+    let object = match result {
+        Ok(output) => object.string("output", output),
+        Err(e) => {
+            let object = object
+                .string("code", e.kind().code())
+                .string("message", &e.to_string());
+            let object = match hint {
+                Some(hint) => object.string("hint", &hint),
+                None => object,
+            };
+            match e.span() {
+                Some(span) => object.raw("span", format!("[{},{}]", span.start, span.end)),
+                None => object,
+            }
+        }
+    };
+    let object = match delta {
+        Some(delta) => object.delta(delta),
+        None => object.delta(&StateDelta::default()),
+    };
+    emit(out, &object)
+}
+
+/// Shows the PC, then every register with its ABI name and value
+pub fn registers(out: &mut dyn Sink, cpu: &CPU) -> io::Result<()> {
+    let registers = Register::ALL[..32].iter().map(|r| {
+        let abi = r.abi().map_or("", |abi| abi.name());
+        Object::new()
+            .string("register", &r.to_string())
+            .string("abi", &abi.to_lowercase())
+            .raw("value", cpu.get_x(*r))
+            .to_string()
+    });
+    let object = Object::new()
+        .string("type", "registers")
+        .number("pc", cpu.pc)
+        .raw("registers", json::array(registers));
+    emit(out, &object)
+}
+
+/// Shows `count` elements of `width` bytes from `address`, as unsigned
+/// numbers; any that can't be read are `null`
+pub fn memory(
+    out: &mut dyn Sink,
+    cpu: &CPU,
+    address: u32,
+    count: usize,
+    width: usize,
+) -> io::Result<()> {
+    let values = (0..count).map(|i| {
+        let address = address.wrapping_add((i * width) as u32);
+        let value = match width {
+            1 => cpu.read_mem_u8(address).map(u32::from),
+            2 => cpu.read_mem_u16(address).map(u32::from),
+            _ => cpu.read_mem_u32(address),
+        };
+        value.map_or("null".to_owned(), |v| v.to_string())
+    });
+    let object = Object::new()
+        .string("type", "memory")
+        .number("address", address)
+        .number("width", width as i64)
+        .raw("values", json::array(values));
+    emit(out, &object)
+}
+
+/// Shows what the guest program printed; `source` is `console` or `uart`
+pub fn output(out: &mut dyn Sink, source: &str, bytes: &[u8]) -> io::Result<()> {
+    let object = Object::new()
+        .string("type", "output")
+        .string("source", source)
+        .string("text", &String::from_utf8_lossy(bytes));
+    emit(out, &object)
+}
+
+/// Shows text that was formatted for people (eg: a command's response) as
+/// its lines, without the marks that say whether it worked (`ok` says that);
+/// `kind` is the object's type
+pub fn text(out: &mut dyn Sink, kind: &str, input: &str, ok: bool, text: &str) -> io::Result<()> {
+    let lines = text.lines().map(|line| {
+        let line = line.strip_prefix("=> ").unwrap_or(line);
+        let line = line.strip_prefix("✅ ").unwrap_or(line);
+        json::string(line.strip_prefix(&format!("{FAILURE} ")).unwrap_or(line))
+    });
+    let object = Object::new()
+        .string("type", kind)
+        .string("input", input)
+        .bool("ok", ok)
+        .raw("lines", json::array(lines));
+    emit(out, &object)
+}
+
+/// Shows that the guest program exited
+pub fn exit(out: &mut dyn Sink, code: u32) -> io::Result<()> {
+    let object = Object::new().string("type", "exit").number("code", code);
+    emit(out, &object)
+}
+
+fn emit(out: &mut dyn Sink, object: &Object) -> io::Result<()> {
+    out.write(&object.to_string(), Color::Default)?;
+    out.end_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+    use brubeck::Interpreter;
+
+    #[test]
+    fn objects() {
+        let mut i = Interpreter::new();
+        let mut buffer = Buffer::default();
+        let output = i.interpret("ADDI a0, zero, 5");
+        result(
+            &mut buffer,
+            "ADDI a0, zero, 5",
+            &output,
+            i.last_delta(),
+            None,
+        )
+        .unwrap();
+        let output = i.interpret("ADDI a0, zero");
+        result(
+            &mut buffer,
+            "ADDI a0, zero",
+            &output,
+            None,
+            Some("try this".into()),
+        )
+        .unwrap();
+        i.cpu.write_memory(0x100, &[1, 2, 3, 4]).unwrap();
+        memory(&mut buffer, &i.cpu, 0x100, 2, 2).unwrap();
+        memory(&mut buffer, &i.cpu, u32::MAX, 1, 4).unwrap();
+        text(&mut buffer, "command", "/undo", false, "❌ one\n\"two\"\n").unwrap();
+        exit(&mut buffer, 3).unwrap();
+
+        let lines: Vec<&str> = buffer.text.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"type":"result","input":"ADDI a0, zero, 5","ok":true,"output":"addi a0, zero, 5","registers":[{"register":"x10","old":0,"new":5}],"memory":[]}"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"type":"result","input":"ADDI a0, zero","ok":false,"code":"E102","message":"ADDI takes rd, rs1, imm","hint":"try this","span":[5,13],"registers":[],"memory":[]}"#
+        );
+        assert_eq!(
+            lines[2..],
+            [
+                r#"{"type":"memory","address":256,"width":2,"values":[513,1027]}"#,
+                r#"{"type":"memory","address":4294967295,"width":4,"values":[null]}"#,
+                r#"{"type":"command","input":"/undo","ok":false,"lines":["one","\"two\""]}"#,
+                r#"{"type":"exit","code":3}"#,
+            ]
+        );
+
+        let mut buffer = Buffer::default();
+        registers(&mut buffer, &i.cpu).unwrap();
+        assert!(buffer.text.starts_with(
+            r#"{"type":"registers","pc":4,"registers":[{"register":"x0","abi":"zero","value":0},"#
+        ));
+        assert!(buffer
+            .text
+            .contains(r#"{"register":"x10","abi":"a0","value":5}"#));
+    }
+}
@@ -0,0 +1,343 @@
+//! Memory dumps, in the style of gdb's `x` command: a number of elements of
+//! one width (bytes, halfwords, or words), all shown in one format.
+//!
+//! Memory is read with the CPU's typed helpers, which bypass devices (so a
+//! dump never has side effects). Elements that can't be read are shown as
+//! `?`s rather than ending the dump, so a range can straddle the end of
+//! memory.
+//!
+//! Hex dumps end each line with the same bytes as characters, and elements
+//! holding a byte that the last instruction changed are highlighted.
+
+use std::collections::HashSet;
+use std::io;
+
+use brubeck::rv32_i::{MemoryWrite, CPU};
+
+use super::{Color, Sink};
+
+/// How many bytes are shown on each line
+const ROW_BYTES: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Hex,
+    /// Signed decimal
+    Dec,
+    /// Printable characters, with `.` for anything else
+    Ascii,
+    /// IEEE 754 single precision
+    Float,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hex" | "x" => Some(Self::Hex),
+            "dec" | "d" => Some(Self::Dec),
+            "ascii" | "c" => Some(Self::Ascii),
+            "float" | "f" => Some(Self::Float),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    /// Bytes per element: 1, 2, or 4
+    width: usize,
+    format: Format,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            format: Format::Hex,
+        }
+    }
+}
+
+impl View {
+    /// A view of `width` byte elements. Without a width, floats are words and
+    /// everything else is bytes.
+    pub fn new(width: Option<usize>, format: Format) -> Result<Self, String> {
+        let width = match (width, format) {
+            (None, Format::Float) => 4,
+            (None, _) => 1,
+            (Some(width @ (1 | 2 | 4)), _) => width,
+            (Some(width), _) => return Err(format!("Width must be 1, 2, or 4, not {width}")),
+        };
+
+        match (width, format) {
+            (1, Format::Ascii) | (4, Format::Float) | (_, Format::Hex | Format::Dec) => {
+                Ok(Self { width, format })
+            }
+            (_, Format::Ascii) => Err("Characters are 1 byte wide".to_owned()),
+            (_, Format::Float) => Err("Floats are 4 bytes wide".to_owned()),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// How many characters an element takes
+    fn column(&self) -> usize {
+        match (self.format, self.width) {
+            (Format::Hex, width) => width * 2,
+            (Format::Dec, 1) => 4,
+            (Format::Dec, 2) => 6,
+            (Format::Dec, _) => 11,
+            (Format::Ascii, _) => 1,
+            (Format::Float, _) => 14,
+        }
+    }
+
+    fn element(&self, cpu: &CPU, address: u32) -> Option<String> {
+        let value = match self.width {
+            1 => cpu.read_mem_u8(address).ok()? as u32,
+            2 => cpu.read_mem_u16(address).ok()? as u32,
+            _ => cpu.read_mem_u32(address).ok()?,
+        };
+
+        let column = self.column();
+        Some(match (self.format, self.width) {
+            (Format::Hex, _) => format!("{value:0column$x}"),
+            (Format::Dec, 1) => format!("{:>column$}", value as u8 as i8),
+            (Format::Dec, 2) => format!("{:>column$}", value as u16 as i16),
+            (Format::Dec, _) => format!("{:>column$}", value as i32),
+            (Format::Ascii, _) => match value as u8 {
+                c @ (b' '..=b'~') => (c as char).to_string(),
+                _ => ".".to_owned(),
+            },
+            (Format::Float, _) => format!("{:>column$}", f32::from_bits(value)),
+        })
+    }
+}
+
+/// One line of a dump
+struct Row {
+    address: u32,
+    /// Each element's address and text
+    elements: Vec<(u32, String)>,
+    /// The row's bytes as characters, for hex dumps
+    sidebar: Option<String>,
+}
+
+fn rows(cpu: &CPU, address: u32, count: usize, view: &View) -> Vec<Row> {
+    let characters = View {
+        width: 1,
+        format: Format::Ascii,
+    };
+
+    (0..count)
+        .collect::<Vec<_>>()
+        .chunks(ROW_BYTES / view.width)
+        .map(|row| {
+            let elements: Vec<(u32, String)> = row
+                .iter()
+                .map(|i| {
+                    let address = address.wrapping_add((i * view.width) as u32);
+                    let text = view
+                        .element(cpu, address)
+                        .unwrap_or_else(|| "?".repeat(view.column()));
+                    (address, text)
+                })
+                .collect();
+
+            let start = elements[0].0;
+            let sidebar = (view.format == Format::Hex).then(|| {
+                (0..row.len() * view.width)
+                    .map(|i| {
+                        characters
+                            .element(cpu, start.wrapping_add(i as u32))
+                            .unwrap_or_else(|| "?".to_owned())
+                    })
+                    .collect()
+            });
+
+            Row {
+                address: start,
+                elements,
+                sidebar,
+            }
+        })
+        .collect()
+}
+
+impl View {
+    fn separator(&self) -> &'static str {
+        match self.format {
+            Format::Ascii => "",
+            _ => " ",
+        }
+    }
+
+    /// Spaces to line up the sidebar of a row of `elements`, when there are
+    /// `count` elements in all
+    fn padding(&self, elements: usize, count: usize) -> String {
+        let full = count.min(ROW_BYTES / self.width);
+        " ".repeat(full.saturating_sub(elements) * (self.column() + 1))
+    }
+}
+
+/// The addresses of the bytes that `writes` changed
+pub fn changed(writes: &[MemoryWrite]) -> HashSet<u32> {
+    writes
+        .iter()
+        .flat_map(|write| {
+            (write.address..=u32::MAX)
+                .zip(write.old.iter().zip(&write.new))
+                .filter(|(_, (old, new))| old != new)
+                .map(|(address, _)| address)
+        })
+        .collect()
+}
+
+/// Writes the dump to `out`, with addresses highlighted, and elements that
+/// hold any of the `changed` bytes highlighted differently
+pub fn memory(
+    out: &mut dyn Sink,
+    cpu: &CPU,
+    address: u32,
+    count: usize,
+    view: &View,
+    changed: &HashSet<u32>,
+) -> io::Result<()> {
+    for row in rows(cpu, address, count, view) {
+        out.write(&format!("0x{:08x}:", row.address), Color::Cyan)?;
+        out.write(" ", Color::Default)?;
+
+        for (i, (address, text)) in row.elements.iter().enumerate() {
+            if i > 0 {
+                out.write(view.separator(), Color::Default)?;
+            }
+            let mut bytes = (0..view.width as u32).map(|b| address.wrapping_add(b));
+            let color = if bytes.any(|b| changed.contains(&b)) {
+                Color::Yellow
+            } else {
+                Color::Default
+            };
+            out.write(text, color)?;
+        }
+
+        if let Some(sidebar) = row.sidebar {
+            let padding = view.padding(row.elements.len(), count);
+            out.write(&format!("{padding}  |{sidebar}|"), Color::Default)?;
+        }
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+
+    fn lines(cpu: &CPU, address: u32, count: usize, view: &View) -> Vec<String> {
+        let mut buffer = Buffer::default();
+        memory(&mut buffer, cpu, address, count, view, &HashSet::new()).unwrap();
+        buffer.text.lines().map(str::to_owned).collect()
+    }
+
+    /// Collects the highlighted text
+    #[derive(Default)]
+    struct Highlights(Vec<String>);
+
+    impl Sink for Highlights {
+        fn write(&mut self, text: &str, color: Color) -> io::Result<()> {
+            if color == Color::Yellow {
+                self.0.push(text.to_owned());
+            }
+            Ok(())
+        }
+
+        fn end_line(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let mut cpu = CPU::new(24);
+        cpu.write_memory(0, b"Hi!\n").unwrap();
+        cpu.write_memory(4, &(-2i32).to_le_bytes()).unwrap();
+        cpu.write_memory(8, &1.5f32.to_bits().to_le_bytes())
+            .unwrap();
+
+        let view = |width, format| View::new(width, format).unwrap();
+
+        assert_eq!(
+            lines(&cpu, 0, 20, &View::default()),
+            vec![
+                "0x00000000: 48 69 21 0a fe ff ff ff 00 00 c0 3f 00 00 00 00  |Hi!........?....|",
+                "0x00000010: 00 00 00 00                                      |....|",
+            ]
+        );
+        assert_eq!(
+            lines(&cpu, 0, 4, &view(None, Format::Ascii)),
+            vec!["0x00000000: Hi!."]
+        );
+        assert_eq!(
+            lines(&cpu, 4, 2, &view(Some(2), Format::Dec)),
+            vec!["0x00000004:     -2     -1"]
+        );
+        assert_eq!(
+            lines(&cpu, 0, 2, &view(Some(4), Format::Hex)),
+            vec!["0x00000000: 0a216948 fffffffe  |Hi!.....|"]
+        );
+        assert_eq!(
+            lines(&cpu, 8, 1, &view(None, Format::Float)),
+            vec!["0x00000008:            1.5"]
+        );
+
+        // reading past the end of memory
+        assert_eq!(
+            lines(&cpu, 20, 2, &view(Some(4), Format::Dec)),
+            vec!["0x00000014:           0 ???????????"]
+        );
+
+        assert!(View::new(Some(3), Format::Hex).is_err());
+        assert!(View::new(Some(2), Format::Float).is_err());
+        assert!(View::new(Some(4), Format::Ascii).is_err());
+    }
+
+    #[test]
+    fn highlights_changes() {
+        let mut cpu = CPU::new(16);
+        cpu.write_memory(4, &[1, 2]).unwrap();
+        let changed = changed(&[
+            MemoryWrite {
+                address: 4,
+                old: vec![0, 0, 0, 0],
+                new: vec![1, 2, 0, 0],
+            },
+            // writing the same value isn't a change
+            MemoryWrite {
+                address: 12,
+                old: vec![0],
+                new: vec![0],
+            },
+        ]);
+        assert_eq!(changed, HashSet::from([4, 5]));
+
+        // right up to the top of the address space
+        let top = super::changed(&[MemoryWrite {
+            address: 0xffff_fffe,
+            old: vec![0, 0],
+            new: vec![1, 2],
+        }]);
+        assert_eq!(top, HashSet::from([0xffff_fffe, 0xffff_ffff]));
+
+        let mut out = Highlights::default();
+        memory(&mut out, &cpu, 0, 16, &View::default(), &changed).unwrap();
+        assert_eq!(out.0, vec!["01", "02"]);
+
+        let mut out = Highlights::default();
+        let words = View::new(Some(4), Format::Hex).unwrap();
+        memory(&mut out, &cpu, 0, 4, &words, &changed).unwrap();
+        assert_eq!(out.0, vec!["00000201"]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Turns interpreter results into output.
+//!
+//! Formatters write to a [Sink] rather than returning strings, so the
+//! terminal, the transcript, and tests all receive identical content; only
+//! the sink decides whether colors are shown.
+
+pub mod bits;
+pub mod csr;
+pub mod disassembly;
+pub mod errors;
+pub mod highlight;
+pub mod history;
+pub mod json;
+pub mod memory;
+pub mod registers;
+pub mod sink;
+pub mod stats;
+
+pub use sink::{Color, Sink, Style, FAILURE};
+
+use brubeck::expression;
+use brubeck::interpreter::{Error, JumpPreview};
+use brubeck::TwosComplement;
+use std::io;
+
+/// Shows the result of interpreting a line of input
+pub fn result(out: &mut dyn Sink, result: &Result<String, Error>) -> io::Result<()> {
+    out.write("=> ", Color::Default)?;
+    match result {
+        Ok(s) => out.write(&format!("✅ {s}"), Color::Green)?,
+        Err(e) => out.write(&format!("{FAILURE} {e}"), Color::Red)?,
+    }
+    out.end_line()
+}
+
+/// Shows the instructions a line of input ran, in highlighted assembly
+pub fn executed(out: &mut dyn Sink, assembly: &str) -> io::Result<()> {
+    out.write("=> ", Color::Default)?;
+    out.write("✅ ", Color::Green)?;
+    highlight::line(out, assembly)?;
+    out.end_line()
+}
+
+/// Shows a number in hex, decimal (signed too, if negative), and binary,
+/// with `_` between digit groups if `readable`
+pub fn value(out: &mut dyn Sink, value: u32, readable: bool) -> io::Result<()> {
+    let group = |digits: String, size| match readable {
+        true => expression::separated(&digits, size),
+        false => digits,
+    };
+    let decimal = if (value as i32) < 0 {
+        format!("{} ({})", group(value.to_string(), 3), value as i32)
+    } else {
+        group(value.to_string(), 3)
+    };
+    let hex = group(format!("{value:08x}"), 4);
+    let binary = group(format!("{value:b}"), 4);
+    result(out, &Ok(format!("0x{hex} = {decimal} = 0b{binary}")))
+}
+
+/// Shows how an immediate is encoded in two's complement: a heading, then
+/// the breakdown
+pub fn explanation(out: &mut dyn Sink, explanation: &TwosComplement) -> io::Result<()> {
+    let text = explanation.to_string();
+    let mut lines = text.lines();
+    if let Some(heading) = lines.next() {
+        out.write(heading, Color::Cyan)?;
+        out.end_line()?;
+    }
+    for line in lines {
+        out.write(line, Color::Default)?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+/// Shows where a branch or jump is about to go, warning if it's outside
+/// memory
+pub fn jump_preview(out: &mut dyn Sink, preview: &JumpPreview) -> io::Result<()> {
+    let sign = if preview.offset < 0 { '-' } else { '+' };
+    out.write(
+        &format!(
+            "-> target 0x{:08x} (PC {sign} {})",
+            preview.target,
+            preview.offset.unsigned_abs()
+        ),
+        Color::Default,
+    )?;
+    out.end_line()?;
+
+    if !preview.mapped {
+        warning(
+            out,
+            &format!("0x{:08x} is outside mapped memory", preview.target),
+        )?;
+    }
+    Ok(())
+}
+
+/// Shows a device's drawing (see `MmioDevice::render`), labelled with its
+/// name; drawings of more than one line go under the name
+pub fn drawing(out: &mut dyn Sink, name: &str, drawing: &str) -> io::Result<()> {
+    if drawing.contains('\n') {
+        out.write(&format!("{name}:"), Color::Cyan)?;
+        out.end_line()?;
+        for line in drawing.lines() {
+            out.write(line, Color::Default)?;
+            out.end_line()?;
+        }
+        Ok(())
+    } else {
+        out.write(&format!("{name}: "), Color::Cyan)?;
+        out.write(drawing, Color::Default)?;
+        out.end_line()
+    }
+}
+
+/// Shows an error that didn't come from the interpreter (eg: a bad command)
+pub fn error(out: &mut dyn Sink, message: &str) -> io::Result<()> {
+    out.write(&format!("{FAILURE} {message}"), Color::Red)?;
+    out.end_line()
+}
+
+/// Shows something suspicious that isn't an error
+pub fn warning(out: &mut dyn Sink, message: &str) -> io::Result<()> {
+    out.write(&format!("⚠️  {message}"), Color::Yellow)?;
+    out.end_line()
+}
+
+/// Shows console output produced by the guest program
+pub fn guest_output(out: &mut dyn Sink, output: &[u8]) -> io::Result<()> {
+    out.write(&String::from_utf8_lossy(output), Color::Cyan)?;
+    out.end_line()
+}
+
+/// Shows what the guest program sent to the UART, each line marked so it
+/// stands apart from console output
+pub fn uart_output(out: &mut dyn Sink, output: &[u8]) -> io::Result<()> {
+    let text = String::from_utf8_lossy(output);
+    for line in text.strip_suffix('\n').unwrap_or(&text).split('\n') {
+        out.write(&format!("uart| {line}"), Color::Magenta)?;
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+/// Shows the notice that the guest program has exited
+pub fn exit(out: &mut dyn Sink, code: u32) -> io::Result<()> {
+    out.write(&format!("Program exited with code {code}"), Color::Default)?;
+    out.end_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sink::Buffer;
+    use super::*;
+
+    #[test]
+    fn formats_to_a_buffer() {
+        let mut buffer = Buffer::default();
+
+        result(&mut buffer, &Ok("ADDI".to_owned())).unwrap();
+        executed(&mut buffer, "addi ra, zero, 5").unwrap();
+        guest_output(&mut buffer, b"hello").unwrap();
+        uart_output(&mut buffer, b"one\ntwo\n").unwrap();
+        exit(&mut buffer, 3).unwrap();
+        value(&mut buffer, 18, false).unwrap();
+        value(&mut buffer, u32::MAX, false).unwrap();
+        value(&mut buffer, 1_000_000, true).unwrap();
+
+        assert_eq!(
+            buffer.text,
+            "=> ✅ ADDI\n=> ✅ addi ra, zero, 5\nhello\nuart| one\nuart| two\nProgram exited with code 3\n\
+             => ✅ 0x00000012 = 18 = 0b10010\n\
+             => ✅ 0xffffffff = 4294967295 (-1) = 0b11111111111111111111111111111111\n\
+             => ✅ 0x000f_4240 = 1_000_000 = 0b1111_0100_0010_0100_0000\n"
+        );
+    }
+
+    #[test]
+    fn jump_previews() {
+        let mut buffer = Buffer::default();
+        let preview = JumpPreview {
+            target: 0xfffffff8,
+            offset: -8,
+            mapped: false,
+        };
+        jump_preview(&mut buffer, &preview).unwrap();
+
+        assert_eq!(
+            buffer.text,
+            "-> target 0xfffffff8 (PC - 8)\n⚠️  0xfffffff8 is outside mapped memory\n"
+        );
+    }
+}
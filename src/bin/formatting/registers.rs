@@ -0,0 +1,277 @@
+//! Register dumps, in a choice of layouts.
+//!
+//! [lines] does the layout and returns lines of cells, each colored by its
+//! register's ABI role, so anything that wants a block of registers (the
+//! REPL, a pane in a larger display) gets the same result; [registers]
+//! writes it to a [Sink].
+
+use std::io;
+
+use brubeck::interpreter::RegisterFormat;
+use brubeck::rv32_i::{Register, ABI, CPU};
+
+use super::{Color, Sink};
+
+/// How registers are ordered and labelled
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Grouping {
+    /// `x0` to `x31`, in order
+    Numeric,
+    /// By ABI role (arguments, saved, temporaries), ABI name first
+    Abi,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Layout {
+    pub columns: usize,
+    pub grouping: Grouping,
+    /// Leave out registers that hold zero
+    pub hide_zero: bool,
+    pub format: RegisterFormat,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            grouping: Grouping::Numeric,
+            hide_zero: false,
+            format: RegisterFormat::Hex,
+        }
+    }
+}
+
+const ROLES: [(&str, Color, &[ABI]); 4] = [
+    (
+        "special",
+        Color::Default,
+        &[ABI::Zero, ABI::RA, ABI::SP, ABI::GP, ABI::TP],
+    ),
+    (
+        "arguments",
+        Color::Green,
+        &[
+            ABI::A0,
+            ABI::A1,
+            ABI::A2,
+            ABI::A3,
+            ABI::A4,
+            ABI::A5,
+            ABI::A6,
+            ABI::A7,
+        ],
+    ),
+    (
+        "saved",
+        Color::Cyan,
+        &[
+            ABI::S0,
+            ABI::S1,
+            ABI::S2,
+            ABI::S3,
+            ABI::S4,
+            ABI::S5,
+            ABI::S6,
+            ABI::S7,
+            ABI::S8,
+            ABI::S9,
+            ABI::S10,
+            ABI::S11,
+        ],
+    ),
+    (
+        "temporaries",
+        Color::Yellow,
+        &[
+            ABI::T0,
+            ABI::T1,
+            ABI::T2,
+            ABI::T3,
+            ABI::T4,
+            ABI::T5,
+            ABI::T6,
+        ],
+    ),
+];
+
+/// The widest label, eg: `x27/s11`
+const LABEL_WIDTH: usize = 7;
+
+/// A line of cells, each with the color of the register's role
+pub type Line = Vec<(String, Color)>;
+
+/// Writes the registers to `out`, colored by ABI role; in numeric order a
+/// key to the colors follows
+pub fn registers(out: &mut dyn Sink, cpu: &CPU, layout: &Layout) -> io::Result<()> {
+    for row in lines(cpu, layout) {
+        for (i, (cell, color)) in row.iter().enumerate() {
+            if i > 0 {
+                out.write("  ", Color::Default)?;
+            }
+            out.write(cell, *color)?;
+        }
+        out.end_line()?;
+    }
+
+    if layout.grouping == Grouping::Numeric {
+        for (role, color, _) in &ROLES[1..] {
+            out.write(&format!("  {role}"), *color)?;
+        }
+        out.end_line()?;
+    }
+    Ok(())
+}
+
+/// Lays out the registers, starting with the PC
+pub fn lines(cpu: &CPU, layout: &Layout) -> Vec<Line> {
+    let pc = format!("{:>LABEL_WIDTH$}: 0x{:08x}", "pc", cpu.pc);
+    let mut rows = vec![vec![(pc, Color::Default)]];
+
+    match layout.grouping {
+        Grouping::Numeric => {
+            let registers = Register::ALL[..32].iter().map(|r| {
+                let abi = r.abi().expect("x0 to x31 have ABI names");
+                (format!("{r}/{}", abi.name().to_lowercase()), *r)
+            });
+            rows.extend(grid(cpu, layout, registers));
+        }
+        Grouping::Abi => {
+            for (role, color, abis) in ROLES {
+                let registers = abis.iter().map(|abi| {
+                    let r = abi.to_register();
+                    (format!("{}/{r}", abi.name().to_lowercase()), r)
+                });
+                let grid = grid(cpu, layout, registers);
+                if !grid.is_empty() {
+                    rows.push(vec![(format!("{role}:"), color)]);
+                    rows.extend(grid);
+                }
+            }
+        }
+    }
+
+    rows
+}
+
+fn grid(
+    cpu: &CPU,
+    layout: &Layout,
+    registers: impl Iterator<Item = (String, Register)>,
+) -> Vec<Line> {
+    let xlen = cpu.config.xlen;
+    let format = |r: Register| layout.format.format(cpu.get_x(r), xlen);
+    // line the values up across the whole dump, not just this grid
+    let width = Register::ALL[..32]
+        .iter()
+        .map(|r| format(*r).len())
+        .max()
+        .unwrap_or_default();
+
+    let cells: Vec<(String, Color)> = registers
+        .filter(|(_, r)| !(layout.hide_zero && cpu.get_x(*r) == 0))
+        .map(|(label, r)| {
+            let cell = format!("{label:>LABEL_WIDTH$}: {:>width$}", format(r));
+            (cell, role_color(r))
+        })
+        .collect();
+
+    cells
+        .chunks(layout.columns.max(1))
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+fn role_color(register: Register) -> Color {
+    ROLES
+        .iter()
+        .find(|(_, _, abis)| abis.iter().any(|abi| abi.to_register() == register))
+        .map(|(_, color, _)| *color)
+        .unwrap_or(Color::Default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+
+    fn lines(cpu: &CPU, layout: &Layout) -> Vec<String> {
+        super::lines(cpu, layout)
+            .into_iter()
+            .map(|line| {
+                let cells: Vec<String> = line.into_iter().map(|(cell, _)| cell).collect();
+                cells.join("  ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layouts() {
+        let mut cpu = CPU::default();
+        cpu.pc = 8;
+        cpu.x2 = 0x1000;
+        cpu.x10 = 5;
+
+        let numeric = lines(&cpu, &Layout::default());
+        assert_eq!(numeric.len(), 9);
+        assert_eq!(numeric[0], "     pc: 0x00000008");
+        assert_eq!(
+            numeric[1],
+            "x0/zero: 0x00000000    x1/ra: 0x00000000    x2/sp: 0x00001000    x3/gp: 0x00000000"
+        );
+
+        let eight = Layout {
+            columns: 8,
+            ..Layout::default()
+        };
+        assert_eq!(lines(&cpu, &eight).len(), 5);
+
+        let abi = Layout {
+            grouping: Grouping::Abi,
+            hide_zero: true,
+            ..Layout::default()
+        };
+        assert_eq!(
+            lines(&cpu, &abi),
+            vec![
+                "     pc: 0x00000008",
+                "special:",
+                "  sp/x2: 0x00001000",
+                "arguments:",
+                " a0/x10: 0x00000005",
+            ]
+        );
+
+        // values are padded to the widest, so columns line up
+        cpu.x11 = -300i32 as u32;
+        let signed = Layout {
+            format: RegisterFormat::Decimal,
+            ..abi
+        };
+        assert_eq!(lines(&cpu, &signed)[4], " a0/x10:    5   a1/x11: -300");
+
+        let mut cpu = CPU::new_rv64();
+        cpu.set_x(Register::X10, u64::MAX);
+        assert_eq!(
+            lines(&cpu, &abi),
+            vec![
+                "     pc: 0x00000000",
+                "arguments:",
+                " a0/x10: 0xffffffffffffffff"
+            ]
+        );
+    }
+
+    #[test]
+    fn role_colors() {
+        let mut buffer = Buffer::default();
+        registers(&mut buffer, &CPU::default(), &Layout::default()).unwrap();
+        assert_eq!(
+            buffer.text.lines().last(),
+            Some("  arguments  saved  temporaries")
+        );
+        assert_eq!(role_color(Register::X10), Color::Green);
+        assert_eq!(role_color(Register::X8), Color::Cyan);
+        assert_eq!(role_color(Register::X31), Color::Yellow);
+        assert_eq!(role_color(Register::X2), Color::Default);
+    }
+}
@@ -0,0 +1,163 @@
+//! Destinations for formatted output.
+//!
+//! Formatters describe *what* to show (text, plus a color hint) and a [Sink]
+//! decides *how*: the terminal paints it, a transcript records it as plain
+//! text, and a [Buffer] keeps it around (for tests, or to pass on as JSON). A [Tee] sends the same
+//! content to several sinks at once, so they can never disagree.
+
+use std::io::{self, Write};
+
+/// A color hint; sinks that can't (or shouldn't) show colors ignore it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Color {
+    Default,
+    Green,
+    Red,
+    Yellow,
+    Cyan,
+    Magenta,
+    /// Fainter than the default (eg: for comments)
+    Dim,
+}
+
+impl Color {
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Color::Default => None,
+            Color::Green => Some("\x1b[32m"),
+            Color::Red => Some("\x1b[31m"),
+            Color::Yellow => Some("\x1b[33m"),
+            Color::Cyan => Some("\x1b[36m"),
+            Color::Magenta => Some("\x1b[35m"),
+            Color::Dim => Some("\x1b[2m"),
+        }
+    }
+
+    /// The text with the ANSI codes to show it in this color
+    pub fn paint(self, text: &str) -> String {
+        match self.ansi() {
+            Some(code) => format!("{code}{text}{RESET}"),
+            None => text.to_owned(),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// What errors and failed results start with
+pub const FAILURE: &str = "❌";
+
+pub trait Sink {
+    /// Writes part of a line
+    fn write(&mut self, text: &str, color: Color) -> io::Result<()>;
+
+    /// Finishes the current line
+    fn end_line(&mut self) -> io::Result<()>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Style {
+    Color,
+    Plain,
+}
+
+/// Writes to standard output, with or without ANSI colors
+pub struct Terminal {
+    style: Style,
+}
+
+impl Terminal {
+    pub fn new(style: Style) -> Self {
+        Self { style }
+    }
+}
+
+impl Sink for Terminal {
+    fn write(&mut self, text: &str, color: Color) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        match self.style {
+            Style::Color => write!(stdout, "{}", color.paint(text)),
+            Style::Plain => write!(stdout, "{text}"),
+        }
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout)?;
+        stdout.flush()
+    }
+}
+
+/// Collects plain text in memory
+#[derive(Debug, Default)]
+pub struct Buffer {
+    pub text: String,
+}
+
+impl Sink for Buffer {
+    fn write(&mut self, text: &str, _color: Color) -> io::Result<()> {
+        self.text.push_str(text);
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        self.text.push('\n');
+        Ok(())
+    }
+}
+
+/// Passes everything on to another sink, noting whether any of it was an
+/// error (see [FAILURE]), or a JSON object saying something didn't work
+pub struct Watch<'a> {
+    sink: &'a mut dyn Sink,
+    pub failed: bool,
+}
+
+impl<'a> Watch<'a> {
+    pub fn new(sink: &'a mut dyn Sink) -> Self {
+        Self {
+            sink,
+            failed: false,
+        }
+    }
+}
+
+impl Sink for Watch<'_> {
+    fn write(&mut self, text: &str, color: Color) -> io::Result<()> {
+        self.failed |=
+            text.starts_with(FAILURE) || (text.starts_with('{') && text.contains(r#""ok":false"#));
+        self.sink.write(text, color)
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        self.sink.end_line()
+    }
+}
+
+/// Sends everything to each of its sinks, in order
+#[derive(Default)]
+pub struct Tee {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Tee {
+    pub fn push(&mut self, sink: impl Sink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+}
+
+impl Sink for Tee {
+    fn write(&mut self, text: &str, color: Color) -> io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.write(text, color)?;
+        }
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.end_line()?;
+        }
+        Ok(())
+    }
+}
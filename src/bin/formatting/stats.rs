@@ -0,0 +1,101 @@
+//! Execution statistics, as a short profiling report.
+
+use std::io;
+
+use brubeck::stats::{BranchCount, ExecutionStats};
+
+use super::{Color, Sink};
+
+/// How many instructions, branches, and addresses each section lists
+const TOP: usize = 10;
+
+/// Writes the report: the most executed instructions, how the branches
+/// went, the hottest addresses, and the memory accesses
+pub fn stats(out: &mut dyn Sink, stats: &ExecutionStats) -> io::Result<()> {
+    let total = stats.total();
+    if total == 0 {
+        return line(out, "No instructions executed yet");
+    }
+
+    heading(out, &format!("Instructions: {total}"))?;
+    for (mnemonic, count) in stats.by_instruction().into_iter().take(TOP) {
+        let share = 100.0 * count as f64 / total as f64;
+        line(out, &format!("  {mnemonic:<8}{count:>8}  {share:5.1}%"))?;
+    }
+
+    let all = stats.all_branches();
+    if all.total() > 0 {
+        heading(out, &format!("Branches: {}", branch(&all)))?;
+        let mut branches: Vec<_> = stats.branches.iter().collect();
+        branches.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(b.0)));
+        for (pc, count) in branches.into_iter().take(TOP) {
+            line(out, &format!("  0x{pc:08x}  {}", branch(count)))?;
+        }
+    }
+
+    heading(out, "Hot addresses:")?;
+    for (pc, count) in stats.hottest(TOP) {
+        line(out, &format!("  0x{pc:08x}  {count:>8}"))?;
+    }
+
+    heading(
+        out,
+        &format!("Memory: {} loads, {} stores", stats.loads, stats.stores),
+    )
+}
+
+fn branch(count: &BranchCount) -> String {
+    format!(
+        "{} taken, {} not taken ({:.1}% taken)",
+        count.taken,
+        count.not_taken,
+        100.0 * count.taken_ratio()
+    )
+}
+
+fn heading(out: &mut dyn Sink, text: &str) -> io::Result<()> {
+    out.write(text, Color::Cyan)?;
+    out.end_line()
+}
+
+fn line(out: &mut dyn Sink, text: &str) -> io::Result<()> {
+    out.write(text, Color::Default)?;
+    out.end_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+    use brubeck::rv32_i::{BType, IType, Instruction};
+
+    #[test]
+    fn report() {
+        let mut stats = ExecutionStats::default();
+        let mut buffer = Buffer::default();
+        super::stats(&mut buffer, &stats).unwrap();
+        assert_eq!(buffer.text, "No instructions executed yet\n");
+
+        for _ in 0..3 {
+            stats.record(0, Instruction::LW(IType::default()), 4);
+            stats.record(4, Instruction::BNE(BType::default()), 0);
+        }
+        stats.record(0, Instruction::LW(IType::default()), 4);
+        stats.record(4, Instruction::BNE(BType::default()), 8);
+
+        let mut buffer = Buffer::default();
+        super::stats(&mut buffer, &stats).unwrap();
+        assert_eq!(
+            buffer.text,
+            "Instructions: 8\n\
+             \x20 BNE            4   50.0%\n\
+             \x20 LW             4   50.0%\n\
+             Branches: 3 taken, 1 not taken (75.0% taken)\n\
+             \x20 0x00000004  3 taken, 1 not taken (75.0% taken)\n\
+             Hot addresses:\n\
+             \x20 0x00000000         4\n\
+             \x20 0x00000004         4\n\
+             Memory: 4 loads, 0 stores\n"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! The command line. Every option is a flag, some followed by a value (eg:
+//! `--memory 4M`); see [USAGE]. Values given here override the ones in the
+//! [config](super::config) file.
+
+use brubeck::board;
+use brubeck::expression;
+use brubeck::interpreter::RegisterFormat;
+use brubeck::rv32_i::{Extensions, StackPointer};
+
+pub const USAGE: &str = "\
+Usage: brubeck [options]
+
+  --board <file>            memory and devices from a board file
+  --memory <size>           memory size in bytes, or with a K or M suffix
+  --memory-base <address>   where memory starts (eg: 0x80000000)
+  --reset-vector <address>  where execution starts; the start of memory if not given
+  --sp <address>            initial stack pointer, or top for the end of memory
+  --gp <address>            initial global pointer
+  --isa <isa>               rv32i with any of m, a, and c (eg: rv32imc), or rv64i
+  --rv64                    the same as --isa rv64i
+  --load-elf <file>         load an ELF executable before starting
+  --script <file>           run a file of REPL input, then exit
+  --eval <line>             run one line, show the result, then exit
+  --max-steps <n>           how many instructions /run executes unless told
+  --json                    answer with JSON instead of text
+  --color, --no-color       whether to color output on a terminal
+  --format <format>         show registers in hex, dec, unsigned, bin, or float
+  --readable-numbers        group the digits of long numbers
+  --history-limit <n>       lines of input to remember between sessions
+  --config <file>           defaults from this file instead of the usual one
+  --workspace <dir>         keep sessions and transcripts in a directory
+  --transcript <file>       record everything shown in a file
+  --semihosting-root <dir>  let semihosting open files in a directory
+  --double-check            check each instruction against a second model
+  --riscv-tests <dir>       run the rv32ui-p tests built by riscv-tests, then exit
+  --help                    show this help";
+
+/// What the command line asked for
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args {
+    pub board: Option<String>,
+    /// Memory size in bytes
+    pub memory: Option<usize>,
+    pub memory_base: Option<u32>,
+    pub reset_vector: Option<u32>,
+    pub stack_pointer: Option<StackPointer>,
+    pub global_pointer: Option<u32>,
+    /// Extensions from `--isa`; the board's if not given
+    pub extensions: Option<Extensions>,
+    pub rv64: bool,
+    pub load_elf: Option<String>,
+    pub script: Option<String>,
+    pub eval: Option<String>,
+    pub max_steps: Option<usize>,
+    pub json: bool,
+    /// `--color` or `--no-color`, whichever came last
+    pub color: Option<bool>,
+    pub format: Option<RegisterFormat>,
+    pub readable_numbers: bool,
+    pub history_limit: Option<usize>,
+    pub config: Option<String>,
+    pub workspace: Option<String>,
+    pub transcript: Option<String>,
+    pub semihosting_root: Option<String>,
+    pub double_check: bool,
+    /// Directory of riscv-tests executables to run
+    pub riscv_tests: Option<String>,
+    pub help: bool,
+}
+
+impl Args {
+    /// Reads the arguments (without the program name), or says what's wrong
+    /// with them
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next().ok_or_else(|| {
+                    format!("{flag} needs a value; brubeck --help lists the options")
+                })
+            };
+
+            match flag.as_str() {
+                "--board" => parsed.board = Some(value()?),
+                "--memory" => {
+                    let size = value()?;
+                    let bytes = board::parse_size(&size)
+                        .ok_or_else(|| format!("Invalid memory size: {size}"))?;
+                    parsed.memory = Some(bytes);
+                }
+                "--memory-base" => parsed.memory_base = Some(address(&value()?)?),
+                "--reset-vector" => parsed.reset_vector = Some(address(&value()?)?),
+                "--sp" => {
+                    let sp = value()?;
+                    let sp = StackPointer::parse(&sp)
+                        .ok_or_else(|| format!("Invalid stack pointer: {sp}"))?;
+                    parsed.stack_pointer = Some(sp);
+                }
+                "--gp" => parsed.global_pointer = Some(address(&value()?)?),
+                "--isa" => {
+                    let isa = value()?;
+                    if isa.eq_ignore_ascii_case("rv64i") {
+                        parsed.rv64 = true;
+                    } else {
+                        let extensions = Extensions::from_isa(&isa)
+                            .ok_or_else(|| format!("Unsupported ISA: {isa}"))?;
+                        parsed.extensions = Some(extensions);
+                    }
+                }
+                "--rv64" => parsed.rv64 = true,
+                "--load-elf" => parsed.load_elf = Some(value()?),
+                "--script" => parsed.script = Some(value()?),
+                "--eval" => parsed.eval = Some(value()?),
+                "--max-steps" => {
+                    let steps = value()?;
+                    let steps = steps
+                        .parse()
+                        .map_err(|_| format!("Invalid number of steps: {steps}"))?;
+                    parsed.max_steps = Some(steps);
+                }
+                "--json" => parsed.json = true,
+                "--color" => parsed.color = Some(true),
+                "--no-color" => parsed.color = Some(false),
+                "--format" => {
+                    let name = value()?;
+                    let format = RegisterFormat::parse(&name)
+                        .ok_or_else(|| format!("Invalid number format: {name}"))?;
+                    parsed.format = Some(format);
+                }
+                "--readable-numbers" => parsed.readable_numbers = true,
+                "--history-limit" => {
+                    let limit = value()?;
+                    let limit = limit
+                        .parse()
+                        .map_err(|_| format!("Invalid history limit: {limit}"))?;
+                    parsed.history_limit = Some(limit);
+                }
+                "--config" => parsed.config = Some(value()?),
+                "--workspace" => parsed.workspace = Some(value()?),
+                "--transcript" => parsed.transcript = Some(value()?),
+                "--semihosting-root" => parsed.semihosting_root = Some(value()?),
+                "--double-check" => parsed.double_check = true,
+                "--riscv-tests" => parsed.riscv_tests = Some(value()?),
+                "--help" | "-h" => parsed.help = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown option: {flag}; brubeck --help lists the options"
+                    ))
+                }
+            }
+        }
+
+        if parsed.script.is_some() && parsed.eval.is_some() {
+            return Err("--script and --eval can't be used together".to_owned());
+        }
+        Ok(parsed)
+    }
+}
+
+/// An address like `0x8000_0000`, in any base the REPL reads numbers in
+fn address(text: &str) -> Result<u32, String> {
+    expression::parse_number(text).ok_or_else(|| format!("Invalid address: {text}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn flags_and_values() {
+        let args = parse("--memory 4M --isa RV32IMC --eval nop --max-steps 10 --no-color").unwrap();
+        assert_eq!(args.memory, Some(4 * 1024 * 1024));
+        assert_eq!(args.extensions, Extensions::from_isa("rv32imc"));
+        assert_eq!(args.eval.as_deref(), Some("nop"));
+        assert_eq!(args.max_steps, Some(10));
+        assert_eq!(args.color, Some(false));
+        assert!(!args.rv64 && !args.json);
+
+        let args = parse("--isa rv64i --load-elf a.out --json --format bin --color").unwrap();
+        assert!(args.rv64 && args.json);
+        assert_eq!(args.extensions, None);
+        assert_eq!(args.load_elf.as_deref(), Some("a.out"));
+        assert_eq!(args.format, Some(RegisterFormat::Binary));
+        assert_eq!(args.color, Some(true));
+        assert_eq!(
+            parse("--riscv-tests isa").unwrap().riscv_tests.as_deref(),
+            Some("isa")
+        );
+
+        let args = parse("--memory-base 0x8000_0000 --reset-vector 0x80000100 --sp top --gp 2048")
+            .unwrap();
+        assert_eq!(args.memory_base, Some(0x8000_0000));
+        assert_eq!(args.reset_vector, Some(0x8000_0100));
+        assert_eq!(args.stack_pointer, Some(StackPointer::Top));
+        assert_eq!(args.global_pointer, Some(2048));
+
+        assert_eq!(parse("").unwrap(), Args::default());
+        assert!(parse("-h").unwrap().help);
+        assert_eq!(
+            parse("--script").unwrap_err(),
+            "--script needs a value; brubeck --help lists the options"
+        );
+        assert_eq!(
+            parse("--isa rv32if").unwrap_err(),
+            "Unsupported ISA: rv32if"
+        );
+        assert_eq!(
+            parse("--memory lots").unwrap_err(),
+            "Invalid memory size: lots"
+        );
+        assert_eq!(
+            parse("--reset-vector start").unwrap_err(),
+            "Invalid address: start"
+        );
+        assert!(parse("--sp bottom").is_err());
+        assert!(parse("--max-steps -1").is_err());
+        assert!(parse("--script a.bru --eval nop").is_err());
+        assert!(parse("--verbose")
+            .unwrap_err()
+            .starts_with("Unknown option: --verbose"));
+    }
+}
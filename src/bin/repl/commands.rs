@@ -0,0 +1,1526 @@
+//! Slash commands (eg: `/help`), which control the REPL rather than the CPU.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use brubeck::analysis;
+use brubeck::audit::{self, Check};
+use brubeck::bits::BitRange;
+use brubeck::devices::{Keyboard, Switches, Uart};
+use brubeck::disassembler;
+use brubeck::elf;
+use brubeck::expression;
+use brubeck::fuzzy;
+use brubeck::golden;
+use brubeck::ihex;
+use brubeck::interpreter::{parse_listing, Interpreter, RegisterFormat, Stop};
+use brubeck::metadata;
+use brubeck::programs;
+use brubeck::rv32_i::{csr, Counters, FillPattern, Instruction, Register};
+use brubeck::script::{Assertion, MAX_STEPS};
+use brubeck::search::{self, Pattern};
+use brubeck::trace::{JsonTracer, TextTracer};
+use brubeck::Immediate;
+
+use super::help;
+use super::workspace::Workspace;
+use crate::formatting::errors;
+use crate::formatting::json::OutputFormat;
+use crate::formatting::memory::{Format, View};
+use crate::formatting::registers::{Grouping, Layout};
+use crate::formatting::sink::{Buffer, Watch};
+use crate::formatting::{self, Color, Sink};
+
+/// Usage and description of every command, for help text
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("/help", "show this overview"),
+    ("/help --categories", "count instructions by category"),
+    (
+        "/help <instruction>",
+        "explain an instruction: operands, encoding, and an example",
+    ),
+    ("/cheatsheet", "operands and operation of every instruction"),
+    (
+        "/cheatsheet --markdown <file>",
+        "write the same reference as a Markdown table",
+    ),
+    (
+        "/regs [hex|dec|unsigned|bin|float] [--columns N] [--abi] [--nonzero]",
+        "show all registers, colored by ABI role",
+    ),
+    (
+        "/set format hex|dec|unsigned|bin|float",
+        "choose how /regs shows values from now on",
+    ),
+    (
+        "/set <register> <value>",
+        "change a register or the PC by hand, as a step /undo can take back",
+    ),
+    (
+        "/set mem <address> <byte>...",
+        "change bytes of memory by hand",
+    ),
+    ("/set csr <name> <value>", "change a CSR by hand"),
+    (
+        "/csrs",
+        "list the CSRs, with their addresses and values, and the privilege level",
+    ),
+    (
+        "/csr <name>",
+        "show a CSR (by name or address) broken into its fields",
+    ),
+    (
+        "/eval <expression>",
+        "evaluate eg: x5 + x6 * 2, [sp + 4], or mepc & ~3",
+    ),
+    (
+        "/lint <file>",
+        "check an assembly listing for unreachable code and bad jumps",
+    ),
+    (
+        "/load <file> [address]",
+        "reset, then load a listing, an ELF executable, or a .bin or .hex image at an address",
+    ),
+    (
+        "/reload [--keep-state]",
+        "load the last file again, optionally keeping registers and memory",
+    ),
+    (
+        "/edit [--keep-state]",
+        "edit the program (or a scratch file) in $EDITOR, then reload it",
+    ),
+    (
+        "/session save|load <name>",
+        "keep or restore the machine state, program, and breakpoints in the workspace",
+    ),
+    ("/session list", "show the sessions saved in the workspace"),
+    (
+        "/save <file>",
+        "write the machine state and undo history to a file",
+    ),
+    (
+        "/load-session <file>",
+        "go back to the state and history saved in a file",
+    ),
+    (
+        "/bits <register> [hi:lo]",
+        "show a register (or any /eval expression) bit by bit",
+    ),
+    (
+        "/mem <address> [count] [-w 1|2|4] [-f hex|dec|ascii|float]",
+        "examine memory (also /memory or /m), eg: /mem sp 8 -w 4 -f dec",
+    ),
+    (
+        "/disasm [address] [count]",
+        "decode instructions from memory (default: 8 from the PC)",
+    ),
+    (
+        "/find <value>|\"text\" [-w 1|2|4] [-a align]",
+        "list the addresses where a value or text is in memory",
+    ),
+    (
+        "/explain-imm <value> <bits>",
+        "show how a value is encoded as an immediate, eg: -42 12",
+    ),
+    (
+        "/assert <a> <op> <b>",
+        "check the machine state, eg: /assert x1 == 42 or /assert [sp] != 0",
+    ),
+    (
+        "/dryrun <instruction>",
+        "show what an instruction would change, without running it",
+    ),
+    (
+        "/devices",
+        "draw the toy devices (LEDs, switches, displays)",
+    ),
+    ("/switch <n> on|off", "flip one of the toy switches"),
+    (
+        "/input <text>",
+        "queue keys for the keyboard, the UART, and stdin; \\n is Enter",
+    ),
+    (
+        "/audit",
+        "check every instruction is parsed, encoded, described, and run",
+    ),
+    ("/example list", "show the built-in example programs"),
+    (
+        "/example load <name>",
+        "reset, then load a built-in example program",
+    ),
+    (
+        "/step [n]",
+        "execute the next n (default 1) instructions from memory",
+    ),
+    (
+        "/run [max]",
+        "execute from memory until the program exits (Ctrl-C stops it)",
+    ),
+    (
+        "/trace on|off",
+        "print every instruction executed, with what it changed",
+    ),
+    (
+        "/trace text|json <file>",
+        "write the trace to a file instead, as text or JSON lines",
+    ),
+    (
+        "/compare <log>",
+        "run alongside a Spike commit log, stopping where they first differ",
+    ),
+    (
+        "/stepback [n]",
+        "undo the last n (default 1) instructions, memory and all",
+    ),
+    (
+        "/reverse-continue",
+        "run backwards to the last breakpoint or write to a watchpoint",
+    ),
+    (
+        "/break [address]",
+        "stop /run at an address, or list the breakpoints",
+    ),
+    ("/unbreak <address>", "remove a breakpoint"),
+    (
+        "/watch [address] [bytes]",
+        "stop /run after a write to memory (default 4 bytes), or list them",
+    ),
+    ("/unwatch <address>", "remove a watchpoint"),
+    (
+        "/counters",
+        "show how many instructions have run, and the cycles they took",
+    ),
+    (
+        "/stats [--clear]",
+        "profile what has run, undone or not: instruction counts, branches, hot addresses",
+    ),
+    ("/undo", "revert the last instruction"),
+    ("/redo", "re-apply the last undone instruction"),
+    (
+        "/checkpoint [name]",
+        "save the machine state under a name, or list the checkpoints",
+    ),
+    ("/restore <name>", "go back to a checkpoint"),
+    (
+        "/history [n]",
+        "list the last n (default 20) instructions in the undo history",
+    ),
+    (
+        "/goto <step>",
+        "undo or redo to the state after that step in the history",
+    ),
+    (
+        "/diff <n> <m>",
+        "show what changed overall between two steps in the history",
+    ),
+    (
+        "/reset",
+        "clear registers and memory, and go to the reset vector",
+    ),
+    (
+        "/reset --pattern <fill>",
+        "reset, filling memory with eg: 0xCC or random:42",
+    ),
+];
+
+/// What the REPL remembers between commands
+#[derive(Debug, Default)]
+pub struct Session {
+    /// The most recently loaded program file, for `/reload`
+    pub program: Option<String>,
+    /// Where `/load` was told to put the program, if anywhere
+    pub load_address: Option<u32>,
+    /// Where `/session` saves sessions, if a workspace was given
+    pub workspace: Option<Workspace>,
+    /// Device drawings as last shown, so only changes are shown again
+    pub drawings: Vec<(String, String)>,
+    /// How many `/assert`s held, and how many didn't
+    pub assertions_passed: usize,
+    pub assertions_failed: usize,
+    /// How many lines of input failed, for the exit code when it's piped in
+    pub lines_failed: usize,
+    /// Whether to show everything as text or as JSON
+    pub output: OutputFormat,
+    /// How many instructions `/run` executes unless told; [MAX_STEPS] if
+    /// not given
+    pub max_steps: Option<usize>,
+    /// The command `/edit` runs, with arguments (eg: `code --wait`);
+    /// `$EDITOR`, or `vi`, if not given
+    pub editor: Option<String>,
+}
+
+/// Lines starting with `/` are commands
+pub fn is_command(line: &str) -> bool {
+    line.trim_start().starts_with('/')
+}
+
+/// Runs a command line, writing the response to `out` as JSON: registers
+/// and memory as numbers, and anything else as the lines it would have shown
+pub fn run_json(
+    line: &str,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let line = line.trim();
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+
+    match (command, args.as_slice()) {
+        ("/regs", _) => return formatting::json::registers(out, &interpreter.cpu),
+        ("/mem" | "/memory" | "/m", args) if !args.is_empty() => {
+            let address = memory_request(args).and_then(|(expression, count, view)| {
+                let address = interpreter
+                    .evaluate(expression)
+                    .map_err(|e| e.to_string())?;
+                Ok((address, count, view))
+            });
+            if let Ok((address, count, view)) = address {
+                let cpu = &interpreter.cpu;
+                return formatting::json::memory(out, cpu, address, count, view.width());
+            }
+        }
+        _ => {}
+    }
+
+    let mut buffer = Buffer::default();
+    let mut watch = Watch::new(&mut buffer);
+    run(line, interpreter, session, &mut watch)?;
+    let ok = !watch.failed;
+    formatting::json::text(out, "command", line, ok, &buffer.text)
+}
+
+/// Runs a command line, writing any response to `out`
+pub fn run(
+    line: &str,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+
+    match (command, args.as_slice()) {
+        ("/help", []) => help::overview(out),
+        ("/help", ["--categories"]) => help::categories(out),
+        ("/help", [name]) => match metadata::lookup(name) {
+            Some(info) => help::instruction(out, info),
+            None => {
+                let names = metadata::INSTRUCTIONS.iter().map(|i| i.name);
+                let close: Vec<String> = fuzzy::suggest(name, names)
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect();
+                let message = match close.is_empty() {
+                    true => format!("Unknown instruction: {name}"),
+                    false => format!(
+                        "Unknown instruction: {name} (did you mean {}?)",
+                        errors::alternatives(&close)
+                    ),
+                };
+                formatting::error(out, &message)
+            }
+        },
+        ("/help", _) => formatting::error(out, "Usage: /help [--categories | <instruction>]"),
+        ("/cheatsheet", []) => help::cheatsheet(out),
+        ("/cheatsheet", ["--markdown", path]) => {
+            match std::fs::write(path, metadata::markdown_reference()) {
+                Ok(()) => formatting::result(out, &Ok(format!("Wrote {path}"))),
+                Err(e) => formatting::error(out, &format!("{path}: {e}")),
+            }
+        }
+        ("/cheatsheet", _) => formatting::error(out, "Usage: /cheatsheet [--markdown <file>]"),
+        ("/regs", args) => match register_layout(args, interpreter.register_format()) {
+            Some(layout) => formatting::registers::registers(out, &interpreter.cpu, &layout),
+            None => formatting::error(
+                out,
+                "Usage: /regs [hex|dec|unsigned|bin|float] [--columns N] [--abi] [--nonzero]",
+            ),
+        },
+        ("/set", ["format", name]) => match RegisterFormat::parse(name) {
+            Some(format) => {
+                interpreter.set_register_format(format);
+                formatting::result(out, &Ok(format!("Registers are shown as {format}")))
+            }
+            None => formatting::error(out, "Usage: /set format hex|dec|unsigned|bin|float"),
+        },
+        ("/set", args) => set(args, interpreter, out),
+        ("/csrs", []) => formatting::csr::csrs(out, &interpreter.cpu),
+        ("/csrs", _) => formatting::error(out, "Usage: /csrs"),
+        ("/csr", [name]) => {
+            let cpu = &interpreter.cpu;
+            let def = match expression::parse_number(name) {
+                Some(address) => cpu.csrs.get(address),
+                None => cpu.csrs.find(name),
+            };
+            match def {
+                Some(def) => formatting::csr::csr(out, cpu, def),
+                None => formatting::error(out, &format!("No CSR named {name} (try /csrs)")),
+            }
+        }
+        ("/csr", _) => formatting::error(out, "Usage: /csr <name>"),
+        ("/eval", []) => formatting::error(out, "Usage: /eval <expression>"),
+        ("/eval", _) => {
+            let expression = line.trim_start().trim_start_matches("/eval");
+            match interpreter.evaluate(expression) {
+                Ok(value) => formatting::value(out, value, interpreter.readable_numbers()),
+                Err(e) => formatting::error(out, &e.to_string()),
+            }
+        }
+        ("/lint", [path]) => lint(path, out),
+        ("/lint", _) => formatting::error(out, "Usage: /lint <file>"),
+        ("/load", [path]) => {
+            session.program = Some(path.to_string());
+            session.load_address = None;
+            load(path, None, true, interpreter, out)
+        }
+        ("/load", [path, address]) => match interpreter.evaluate(address) {
+            Ok(address) => {
+                session.program = Some(path.to_string());
+                session.load_address = Some(address);
+                load(path, Some(address), true, interpreter, out)
+            }
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/load", _) => formatting::error(out, "Usage: /load <file> [address]"),
+        ("/reload", args @ ([] | ["--keep-state"])) => match session.program.clone() {
+            Some(path) => load(
+                &path,
+                session.load_address,
+                args.is_empty(),
+                interpreter,
+                out,
+            ),
+            None => formatting::error(out, "Nothing to reload; /load a file first"),
+        },
+        ("/reload", _) => formatting::error(out, "Usage: /reload [--keep-state]"),
+        ("/edit", args @ ([] | ["--keep-state"])) => {
+            let path = match &session.program {
+                Some(path) => path.clone(),
+                None => match scratch_file() {
+                    Ok(path) => session.program.insert(path).clone(),
+                    Err(e) => return formatting::error(out, &format!("Couldn't make a file: {e}")),
+                },
+            };
+            let editor = session
+                .editor
+                .clone()
+                .or_else(|| std::env::var("EDITOR").ok());
+            match edit(&path, editor.as_deref().unwrap_or("vi")) {
+                Ok(()) => load(
+                    &path,
+                    session.load_address,
+                    args.is_empty(),
+                    interpreter,
+                    out,
+                ),
+                Err(e) => formatting::error(out, &format!("Couldn't run the editor: {e}")),
+            }
+        }
+        ("/edit", _) => formatting::error(out, "Usage: /edit [--keep-state]"),
+        ("/save", [path]) => match std::fs::write(path, interpreter.to_bytes()) {
+            Ok(()) => formatting::result(out, &Ok(format!("Saved the session to {path}"))),
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+        ("/save", _) => formatting::error(out, "Usage: /save <file>"),
+        ("/load-session", [path]) => {
+            let restored = std::fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| interpreter.restore_bytes(&bytes).map_err(|e| e.to_string()));
+            match restored {
+                Ok(()) => {
+                    let message = format!(
+                        "Loaded {path}: step {} of {}, PC = 0x{:08x}",
+                        interpreter.history().epoch(),
+                        interpreter.history().len(),
+                        interpreter.cpu.pc
+                    );
+                    formatting::result(out, &Ok(message))
+                }
+                Err(e) => formatting::error(out, &format!("{path}: {e}")),
+            }
+        }
+        ("/load-session", _) => formatting::error(out, "Usage: /load-session <file>"),
+        ("/session", args) => match (&session.workspace, args) {
+            (None, _) => formatting::error(out, "No workspace; start with --workspace <dir>"),
+            (Some(workspace), ["save", name]) => {
+                match workspace.save(name, interpreter, session.program.as_deref()) {
+                    Ok(()) => formatting::result(out, &Ok(format!("Saved session {name}"))),
+                    Err(e) => formatting::error(out, &e.to_string()),
+                }
+            }
+            (Some(workspace), ["load", name]) => match workspace.load(name, interpreter) {
+                Ok(program) => {
+                    session.program = program;
+                    formatting::result(out, &Ok(format!("Loaded session {name}")))
+                }
+                Err(e) => formatting::error(out, &e.to_string()),
+            },
+            (Some(workspace), ["list"]) => match workspace.sessions() {
+                Ok(names) if names.is_empty() => {
+                    formatting::result(out, &Ok("No sessions".to_owned()))
+                }
+                Ok(names) => formatting::result(out, &Ok(names.join(", "))),
+                Err(e) => formatting::error(out, &e.to_string()),
+            },
+            _ => formatting::error(out, "Usage: /session save|load <name>, or /session list"),
+        },
+        ("/bits", []) => formatting::error(out, "Usage: /bits <register> [hi:lo]"),
+        ("/bits", args) => {
+            // a trailing hi:lo picks out a field
+            let (range, expression) = match args.split_last() {
+                Some((last, rest)) if last.contains(':') && !rest.is_empty() => {
+                    match BitRange::parse(last) {
+                        Some(range) => (Some(range), rest.join(" ")),
+                        None => {
+                            return formatting::error(out, &format!("Invalid bit range {last}"))
+                        }
+                    }
+                }
+                _ => (None, args.join(" ")),
+            };
+            match interpreter.evaluate(&expression) {
+                Ok(value) => formatting::bits::bits(out, &expression, value, range),
+                Err(e) => formatting::error(out, &e.to_string()),
+            }
+        }
+        ("/mem" | "/memory" | "/m", []) => formatting::error(
+            out,
+            "Usage: /mem <address> [count] [-w 1|2|4] [-f hex|dec|ascii|float]",
+        ),
+        ("/mem" | "/memory" | "/m", args) => match memory_request(args) {
+            Ok((expression, count, view)) => match interpreter.evaluate(expression) {
+                Ok(address) => {
+                    let writes = interpreter.last_delta().map(|d| &d.memory[..]);
+                    let changed = formatting::memory::changed(writes.unwrap_or_default());
+                    formatting::memory::memory(
+                        out,
+                        &interpreter.cpu,
+                        address,
+                        count,
+                        &view,
+                        &changed,
+                    )
+                }
+                Err(e) => formatting::error(out, &e.to_string()),
+            },
+            Err(e) => formatting::error(out, &e),
+        },
+        ("/disasm", []) => {
+            let pc = interpreter.cpu.pc;
+            disasm(pc, DISASM_LINES, interpreter, out)
+        }
+        ("/disasm", [address]) => match interpreter.evaluate(address) {
+            Ok(address) => disasm(address, DISASM_LINES, interpreter, out),
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/disasm", [address, count]) => match (
+            interpreter.evaluate(address),
+            expression::parse_number(count),
+        ) {
+            (Ok(address), Some(count)) if count as usize <= disassembler::MAX_LINES => {
+                disasm(address, count as usize, interpreter, out)
+            }
+            (Err(e), _) => formatting::error(out, &e.to_string()),
+            (_, Some(_)) => formatting::error(
+                out,
+                &format!(
+                    "/disasm shows at most {} instructions at once",
+                    disassembler::MAX_LINES
+                ),
+            ),
+            (_, None) => formatting::error(out, &format!("Invalid count {count}")),
+        },
+        ("/disasm", _) => formatting::error(out, "Usage: /disasm [address] [count]"),
+        ("/find", []) => {
+            formatting::error(out, "Usage: /find <value>|\"text\" [-w 1|2|4] [-a align]")
+        }
+        ("/find", _) => {
+            let query = line.trim_start().trim_start_matches("/find").trim();
+            match search_pattern(query, interpreter) {
+                Ok((pattern, alignment)) => {
+                    let found = search::find(&interpreter.cpu, &pattern, alignment);
+                    matches(&found, out)
+                }
+                Err(e) => formatting::error(out, &e),
+            }
+        }
+        ("/explain-imm", [value, bits]) => match immediate(value, bits) {
+            Ok(imm) => formatting::explanation(out, &imm.explain()),
+            Err(e) => formatting::error(out, &e),
+        },
+        ("/explain-imm", _) => formatting::error(out, "Usage: /explain-imm <value> <bits>"),
+        ("/dryrun", []) => formatting::error(out, "Usage: /dryrun <instruction>"),
+        ("/dryrun", _) => {
+            let instruction = line.trim_start().trim_start_matches("/dryrun");
+            match interpreter.dry_run(instruction) {
+                Ok(delta) => formatting::result(out, &Ok(format!("Would change {delta}"))),
+                Err(e) => formatting::error(out, &e.to_string()),
+            }
+        }
+        ("/devices", []) => {
+            session.drawings = interpreter.cpu.devices.renders();
+            for (name, drawing) in &session.drawings {
+                formatting::drawing(out, name, drawing)?;
+            }
+            Ok(())
+        }
+        ("/switch", [index, state @ ("on" | "off")]) => {
+            let switches = interpreter.cpu.devices.get_mut::<Switches>();
+            match (switches, index.parse()) {
+                (Some(switches), Ok(index)) if index < Switches::COUNT => {
+                    switches.set(index, *state == "on");
+                    show_device_changes(interpreter, session, out)
+                }
+                (None, _) => formatting::error(out, "This board has no switches"),
+                _ => formatting::error(
+                    out,
+                    &format!("Switches are numbered 0 to {}", Switches::COUNT - 1),
+                ),
+            }
+        }
+        ("/input", []) => formatting::error(out, "Usage: /input <text>"),
+        ("/input", _) => {
+            let text = line.trim_start().trim_start_matches("/input").trim();
+            let keys = text.replace("\\n", "\n");
+            interpreter.cpu.push_syscall_input(keys.as_bytes());
+            if let Some(uart) = interpreter.cpu.devices.get_mut::<Uart>() {
+                uart.push_input(keys.as_bytes());
+            }
+            if let Some(keyboard) = interpreter.cpu.devices.get_mut::<Keyboard>() {
+                keyboard.push_keys(keys.as_bytes());
+            }
+            let message = format!("Queued {} keys", keys.len());
+            formatting::result(out, &Ok(message))
+        }
+        ("/switch", _) => formatting::error(out, "Usage: /switch <n> on|off"),
+        ("/audit", []) => audit(out),
+        ("/example", ["list"]) => {
+            for program in programs::PROGRAMS {
+                let line = format!("{:<12} {}", program.name, program.description);
+                formatting::result(out, &Ok(line))?;
+            }
+            Ok(())
+        }
+        ("/example", ["load", name]) => match programs::find(name) {
+            Some(program) => load_listing(program.name, program.source, true, interpreter, out),
+            None => formatting::error(out, &format!("No example named {name} (try /example list)")),
+        },
+        ("/example", _) => formatting::error(out, "Usage: /example list, or /example load <name>"),
+        ("/step", []) => step(1, interpreter, session, out),
+        ("/step", [count]) => match expression::parse_number(count) {
+            Some(count) if count as usize > MAX_STEPS => formatting::error(
+                out,
+                &format!("Can't step more than {MAX_STEPS} instructions at once"),
+            ),
+            Some(count) => step(count as usize, interpreter, session, out),
+            None => formatting::error(out, &format!("Invalid count {count}")),
+        },
+        ("/step", _) => formatting::error(out, "Usage: /step [n]"),
+        ("/run", []) => {
+            let max_steps = session.max_steps.unwrap_or(MAX_STEPS);
+            run_loaded(max_steps, interpreter, session, out)
+        }
+        ("/run", [max]) => match expression::parse_number(max) {
+            Some(max) => run_loaded(max as usize, interpreter, session, out),
+            None => formatting::error(out, &format!("Invalid step limit {max}")),
+        },
+        ("/run", _) => formatting::error(out, "Usage: /run [max]"),
+        ("/trace", ["on"]) => {
+            interpreter.set_tracer(Box::new(TextTracer::new(io::stdout())));
+            formatting::result(out, &Ok("Tracing every instruction".to_owned()))
+        }
+        ("/trace", ["off"]) => match interpreter.take_tracer() {
+            Some(_) => formatting::result(out, &Ok("Stopped tracing".to_owned())),
+            None => formatting::error(out, "Not tracing"),
+        },
+        ("/trace", [format @ ("text" | "json"), path]) => match std::fs::File::create(path) {
+            Ok(file) => {
+                let file = io::BufWriter::new(file);
+                match *format {
+                    "json" => interpreter.set_tracer(Box::new(JsonTracer::new(file))),
+                    _ => interpreter.set_tracer(Box::new(TextTracer::new(file))),
+                }
+                formatting::result(out, &Ok(format!("Tracing to {path}")))
+            }
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+        ("/trace", _) => formatting::error(out, "Usage: /trace on|off, or /trace text|json <file>"),
+        ("/compare", [path]) => {
+            let log = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|log| golden::parse_log(&log).map_err(|e| e.to_string()));
+            match log.map(|log| golden::compare(interpreter, &log)) {
+                Ok(Ok(matched)) => {
+                    let message = format!("Matched {path} for {matched} instructions");
+                    formatting::result(out, &Ok(message))
+                }
+                Ok(Err(divergence)) => formatting::error(out, &divergence.to_string()),
+                Err(e) => formatting::error(out, &format!("{path}: {e}")),
+            }
+        }
+        ("/compare", _) => formatting::error(out, "Usage: /compare <log>"),
+        ("/stepback", []) => step_back(1, interpreter, out),
+        ("/stepback", [count]) => match expression::parse_number(count) {
+            Some(count) => step_back(count as usize, interpreter, out),
+            None => formatting::error(out, &format!("Invalid count {count}")),
+        },
+        ("/stepback", _) => formatting::error(out, "Usage: /stepback [n]"),
+        ("/reverse-continue", []) => match interpreter.reverse_continue() {
+            Ok(run) => {
+                let message = format!("{run}, PC = 0x{:08x}", interpreter.cpu.pc);
+                formatting::result(out, &Ok(message))
+            }
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/reverse-continue", _) => formatting::error(out, "Usage: /reverse-continue"),
+        ("/break", []) => {
+            let breakpoints: Vec<String> = interpreter
+                .breakpoints()
+                .map(|address| format!("0x{address:08x}"))
+                .collect();
+            match breakpoints.is_empty() {
+                true => formatting::result(out, &Ok("No breakpoints".to_owned())),
+                false => formatting::result(out, &Ok(breakpoints.join(", "))),
+            }
+        }
+        ("/break", [address]) => match interpreter.evaluate(address) {
+            Ok(address) => {
+                interpreter.add_breakpoint(address);
+                formatting::result(out, &Ok(format!("Breakpoint at 0x{address:08x}")))
+            }
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/break", _) => formatting::error(out, "Usage: /break [address]"),
+        ("/unbreak", [address]) => match interpreter.evaluate(address) {
+            Ok(address) if interpreter.remove_breakpoint(address) => formatting::result(
+                out,
+                &Ok(format!("Removed the breakpoint at 0x{address:08x}")),
+            ),
+            Ok(address) => formatting::error(out, &format!("No breakpoint at 0x{address:08x}")),
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/unbreak", _) => formatting::error(out, "Usage: /unbreak <address>"),
+        ("/watch", []) => {
+            let watchpoints: Vec<String> = interpreter
+                .watchpoints()
+                .map(|(address, len)| format!("{len} bytes at 0x{address:08x}"))
+                .collect();
+            match watchpoints.is_empty() {
+                true => formatting::result(out, &Ok("No watchpoints".to_owned())),
+                false => formatting::result(out, &Ok(watchpoints.join(", "))),
+            }
+        }
+        ("/watch", [address]) => watch(address, "4", interpreter, out),
+        ("/watch", [address, len]) => watch(address, len, interpreter, out),
+        ("/watch", _) => formatting::error(out, "Usage: /watch [address] [bytes]"),
+        ("/unwatch", [address]) => match interpreter.evaluate(address) {
+            Ok(address) if interpreter.unwatch(address) => formatting::result(
+                out,
+                &Ok(format!("Removed the watchpoint at 0x{address:08x}")),
+            ),
+            Ok(address) => formatting::error(out, &format!("No watchpoint at 0x{address:08x}")),
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/unwatch", _) => formatting::error(out, "Usage: /unwatch <address>"),
+        ("/counters", []) => counters(interpreter, out),
+        ("/assert", args) => {
+            let result = Assertion::parse(&args.join(" ")).and_then(|assertion| {
+                assertion.check(interpreter)?;
+                Ok(format!("Passed: {assertion}"))
+            });
+            match result {
+                Ok(_) => session.assertions_passed += 1,
+                Err(_) => session.assertions_failed += 1,
+            }
+            formatting::result(out, &result)
+        }
+        ("/stats", []) => formatting::stats::stats(out, interpreter.stats()),
+        ("/stats", ["--clear"]) => {
+            interpreter.clear_stats();
+            formatting::result(out, &Ok("Cleared the execution statistics".to_owned()))
+        }
+        ("/stats", _) => formatting::error(out, "Usage: /stats [--clear]"),
+        ("/undo", []) => match interpreter.undo() {
+            Ok(delta) => formatting::result(out, &Ok(format!("Undid {delta}"))),
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/redo", []) => match interpreter.redo() {
+            Ok(delta) => formatting::result(out, &Ok(format!("Redid {delta}"))),
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/checkpoint", []) => {
+            if interpreter.checkpoints().iter().next().is_none() {
+                return formatting::result(out, &Ok("No checkpoints yet".to_owned()));
+            }
+            for checkpoint in interpreter.checkpoints().iter() {
+                let line = format!("{:<16} PC = 0x{:08x}", checkpoint.name, checkpoint.pc());
+                formatting::result(out, &Ok(line))?;
+            }
+            Ok(())
+        }
+        ("/checkpoint", [name]) => {
+            interpreter.checkpoint(name);
+            let message = format!("Saved checkpoint {name} (/restore {name} goes back to it)");
+            formatting::result(out, &Ok(message))
+        }
+        ("/checkpoint", _) => formatting::error(out, "Usage: /checkpoint [name]"),
+        ("/restore", [name]) => match interpreter.restore_checkpoint(name) {
+            Ok(()) => {
+                let message = format!("Restored {name}, PC = 0x{:08x}", interpreter.cpu.pc);
+                formatting::result(out, &Ok(message))
+            }
+            Err(e) => formatting::error(out, &e.to_string()),
+        },
+        ("/restore", _) => formatting::error(out, "Usage: /restore <name>"),
+        ("/history", []) => formatting::history::history(out, interpreter.history(), 20),
+        ("/history", [count]) => match expression::parse_number(count) {
+            Some(count) => formatting::history::history(out, interpreter.history(), count as usize),
+            None => formatting::error(out, &format!("Invalid count {count}")),
+        },
+        ("/history", _) => formatting::error(out, "Usage: /history [n]"),
+        ("/goto", [step]) => match expression::parse_number(step) {
+            Some(step) => match interpreter.seek(step as usize) {
+                Ok(()) => formatting::result(
+                    out,
+                    &Ok(format!(
+                        "At step {step} of {}, PC = 0x{:08x}",
+                        interpreter.history().len(),
+                        interpreter.cpu.pc
+                    )),
+                ),
+                Err(e) => formatting::error(out, &e.to_string()),
+            },
+            None => formatting::error(out, &format!("Invalid step {step}")),
+        },
+        ("/goto", _) => formatting::error(out, "Usage: /goto <step>"),
+        ("/diff", [a, b]) => {
+            let (a, b) = match (expression::parse_number(a), expression::parse_number(b)) {
+                (Some(a), Some(b)) => (a as usize, b as usize),
+                _ => return formatting::error(out, "Usage: /diff <n> <m>"),
+            };
+            match interpreter.history().diff(a, b) {
+                Ok(delta) => formatting::history::diff(out, a, b, &delta),
+                Err(e) => formatting::error(out, &e.to_string()),
+            }
+        }
+        ("/diff", _) => formatting::error(out, "Usage: /diff <n> <m>"),
+        ("/reset", []) => reset(interpreter, out),
+        ("/reset", ["--pattern", pattern]) => match FillPattern::parse(pattern) {
+            Some(fill) => {
+                interpreter.cpu.config.fill = fill;
+                reset(interpreter, out)
+            }
+            None => formatting::error(out, &format!("Unknown fill pattern: {pattern}")),
+        },
+        ("/reset", _) => formatting::error(out, "Usage: /reset [--pattern <fill>]"),
+        _ => formatting::error(out, &format!("Unknown command: {command} (try /help)")),
+    }
+}
+
+/// Draws any devices that look different from when they were last shown
+pub fn show_device_changes(
+    interpreter: &Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let drawings = interpreter.cpu.devices.renders();
+    for (name, drawing) in &drawings {
+        let shown = session.drawings.iter().find(|(n, _)| n == name);
+        if shown.map(|(_, d)| d) != Some(drawing) {
+            formatting::drawing(out, name, drawing)?;
+        }
+    }
+    session.drawings = drawings;
+    Ok(())
+}
+
+/// How many instructions `/disasm` shows, unless told
+const DISASM_LINES: usize = 8;
+
+/// The most bytes `/mem` shows at once
+const MAX_MEMORY_SHOWN: usize = 64 * 1024;
+
+/// Executes `count` instructions from memory, showing each one
+fn step(
+    count: usize,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    for _ in 0..count {
+        let pc = interpreter.cpu.pc;
+        let result = interpreter
+            .fetch()
+            .and_then(|instruction| {
+                interpreter.step()?;
+                Ok(instruction)
+            })
+            .map(|instruction| format!("0x{pc:08x}: {instruction}"));
+        let failed = result.is_err();
+
+        show_guest_output(interpreter, out)?;
+        formatting::result(out, &result)?;
+        if let Err(e) = &result {
+            formatting::errors::hints(out, e, &interpreter.cpu)?;
+        }
+        show_device_changes(interpreter, session, out)?;
+
+        if let Some(code) = interpreter.exit_code() {
+            return formatting::exit(out, code);
+        }
+        if failed {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes `count` instructions, then shows where that left the PC
+fn step_back(count: usize, interpreter: &mut Interpreter, out: &mut dyn Sink) -> io::Result<()> {
+    let undone = match interpreter.step_back(count) {
+        Ok(undone) => undone,
+        Err(e) => return formatting::error(out, &e.to_string()),
+    };
+    if undone == 0 {
+        return formatting::error(out, "Nothing to step back over");
+    }
+
+    let pc = interpreter.cpu.pc;
+    let message = match interpreter.fetch() {
+        Ok(instruction) => format!("Stepped back {undone} to 0x{pc:08x}: {instruction}"),
+        Err(_) => format!("Stepped back {undone} to 0x{pc:08x}"),
+    };
+    formatting::result(out, &Ok(message))
+}
+
+/// `/set` for anything but the format: changes a register, memory, or a CSR,
+/// recorded in the history
+fn set(args: &[&str], interpreter: &mut Interpreter, out: &mut dyn Sink) -> io::Result<()> {
+    let evaluate = |interpreter: &Interpreter, text: &str| {
+        interpreter.evaluate(text).map_err(|e| e.to_string())
+    };
+
+    let result = match args {
+        ["mem", address, bytes @ ..] if !bytes.is_empty() => evaluate(interpreter, address)
+            .and_then(|address| {
+                let bytes = bytes
+                    .iter()
+                    .map(|text| {
+                        let value = evaluate(interpreter, text)?;
+                        u8::try_from(value).map_err(|_| format!("{text} isn't a byte"))
+                    })
+                    .collect::<Result<Vec<u8>, String>>()?;
+                interpreter
+                    .set_memory(address, &bytes)
+                    .map_err(|e| e.to_string())
+            }),
+        ["csr", name, value] => {
+            let def = match expression::parse_number(name) {
+                Some(address) => interpreter.cpu.csrs.get(address),
+                None => interpreter.cpu.csrs.find(name),
+            };
+            match def.map(|def| def.address) {
+                Some(address) if csr::is_read_only(address) => Err(format!("{name} is read-only")),
+                Some(address) => evaluate(interpreter, value).and_then(|value| {
+                    interpreter
+                        .set_csr(address, value)
+                        .map_err(|e| e.to_string())
+                }),
+                None => Err(format!("No CSR named {name} (try /csrs)")),
+            }
+        }
+        [name, value] if !matches!(*name, "mem" | "csr" | "format") => {
+            match Register::from_name(&name.to_uppercase()) {
+                Some(register) => evaluate(interpreter, value)
+                    .map(|value| interpreter.set_register(register, value)),
+                None => Err(format!("Unknown register {name}")),
+            }
+        }
+        _ => Err(
+            "Usage: /set format hex|dec|unsigned|bin|float, /set <register> <value>, \
+                  /set mem <address> <byte>..., or /set csr <name> <value>"
+                .to_owned(),
+        ),
+    };
+
+    match result {
+        Ok(delta) if delta.is_empty() => formatting::result(out, &Ok("Nothing changed".to_owned())),
+        Ok(delta) => formatting::result(out, &Ok(format!("Set {delta}"))),
+        Err(e) => formatting::error(out, &e),
+    }
+}
+
+fn watch(
+    address: &str,
+    len: &str,
+    interpreter: &mut Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let address = match interpreter.evaluate(address) {
+        Ok(address) => address,
+        Err(e) => return formatting::error(out, &e.to_string()),
+    };
+    match expression::parse_number(len).filter(|len| *len > 0) {
+        Some(len) => {
+            interpreter.watch(address, len);
+            let message = format!("Watching {len} bytes at 0x{address:08x}");
+            formatting::result(out, &Ok(message))
+        }
+        None => formatting::error(out, &format!("Invalid length {len}")),
+    }
+}
+
+/// Executes from memory until the program stops, showing how it ended
+fn run_loaded(
+    max_steps: usize,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let run = interpreter.run(max_steps);
+
+    show_guest_output(interpreter, out)?;
+    match run.stop {
+        Stop::Exited(_) | Stop::Breakpoint(_) | Stop::Watchpoint(_) | Stop::HistoryStart => {
+            formatting::result(out, &Ok(run.to_string()))?
+        }
+        // didn't finish, which fails a script
+        Stop::StepLimit | Stop::Cancelled => formatting::error(out, &run.to_string())?,
+        Stop::Failed(ref e) => {
+            formatting::error(out, &run.to_string())?;
+            formatting::errors::hints(out, e, &interpreter.cpu)?
+        }
+    }
+    show_device_changes(interpreter, session, out)
+}
+
+fn counters(interpreter: &Interpreter, out: &mut dyn Sink) -> io::Result<()> {
+    let Counters { cycles, instret } = interpreter.cpu.counters;
+    let mut message = format!("{instret} instructions executed in {cycles} cycles");
+    if instret > 0 {
+        message += &format!(
+            " ({:.2} cycles per instruction)",
+            cycles as f64 / instret as f64
+        );
+    }
+    formatting::result(out, &Ok(message))
+}
+
+fn show_guest_output(interpreter: &mut Interpreter, out: &mut dyn Sink) -> io::Result<()> {
+    let output = interpreter.take_output();
+    if !output.is_empty() {
+        formatting::guest_output(out, &output)?;
+    }
+    let uart = interpreter.take_uart_output();
+    if !uart.is_empty() {
+        formatting::uart_output(out, &uart)?;
+    }
+    Ok(())
+}
+
+fn reset(interpreter: &mut Interpreter, out: &mut dyn Sink) -> io::Result<()> {
+    interpreter.reset();
+    let message = format!(
+        "Reset; PC is 0x{:08x}, memory filled with {}",
+        interpreter.cpu.pc, interpreter.cpu.config.fill
+    );
+    formatting::result(out, &Ok(message))
+}
+
+/// Loads a program file (a listing, or an ELF, .bin, or .hex image),
+/// resetting the CPU first unless keeping its state
+pub fn load(
+    path: &str,
+    address: Option<u32>,
+    reset: bool,
+    interpreter: &mut Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+
+    match (extension.as_deref(), address) {
+        _ if is_elf(path) => match std::fs::read(path) {
+            Ok(bytes) => match elf::parse(&bytes) {
+                Ok(image) => load_image(path, &image, address, reset, interpreter, out),
+                Err(e) => formatting::error(out, &format!("{path}: {e}")),
+            },
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+        (Some("bin"), _) => match std::fs::read(path) {
+            Ok(image) => load_binary(path, &image, address, reset, interpreter, out),
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+        (Some("hex" | "ihex"), _) => match std::fs::read_to_string(path) {
+            Ok(text) => match ihex::parse(&text) {
+                Ok(image) => load_image(path, &image, address, reset, interpreter, out),
+                Err(e) => formatting::error(out, &format!("{path}: {e}")),
+            },
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+        (_, Some(_)) => formatting::error(
+            out,
+            "Listings load at the reset vector; only images take an address",
+        ),
+        (_, None) => match std::fs::read_to_string(path) {
+            Ok(listing) => load_listing(path, &listing, reset, interpreter, out),
+            Err(e) => formatting::error(out, &format!("{path}: {e}")),
+        },
+    }
+}
+
+/// Loads a flat binary at `address` (the reset vector by default) and
+/// starts there
+fn load_binary(
+    name: &str,
+    image: &[u8],
+    address: Option<u32>,
+    reset: bool,
+    interpreter: &mut Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    if reset {
+        interpreter.reset();
+    }
+
+    let address = address.unwrap_or(interpreter.cpu.config.reset_vector);
+    let result = interpreter.cpu.load_binary(address, image);
+    interpreter.clear_history();
+
+    match result {
+        Ok(()) => {
+            let message = format!("Loaded {name}: {} bytes at 0x{address:08x}", image.len());
+            formatting::result(out, &Ok(message))
+        }
+        Err(e) => formatting::error(out, &format!("{name} doesn't fit in memory: {e:?}")),
+    }
+}
+
+/// Whether the file at `path` is an ELF executable (whatever it's called)
+pub fn is_elf(path: &str) -> bool {
+    let mut magic = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| io::Read::read_exact(&mut file, &mut magic))
+        .is_ok_and(|()| magic == elf::MAGIC)
+}
+
+/// Loads an Intel HEX or ELF image, `offset` bytes on from where it says,
+/// and starts at its entry point (or its first byte)
+fn load_image(
+    name: &str,
+    image: &ihex::Image,
+    offset: Option<u32>,
+    reset: bool,
+    interpreter: &mut Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    if reset {
+        interpreter.reset();
+    }
+
+    let offset = offset.unwrap_or(0);
+    let result = image.load(&mut interpreter.cpu, offset);
+    interpreter.clear_history();
+
+    if let Err(e) = result {
+        return formatting::error(out, &format!("{name} doesn't fit in memory: {e:?}"));
+    }
+
+    let start = image.entry.or(image.chunks.first().map(|(a, _)| *a));
+    if let Some(start) = start {
+        interpreter.cpu.pc = start.wrapping_add(offset);
+    }
+
+    let message = format!(
+        "Loaded {name}: {} bytes in {} chunk(s), starting at 0x{:08x}",
+        image.len(),
+        image.chunks.len(),
+        interpreter.cpu.pc
+    );
+    formatting::result(out, &Ok(message))
+}
+
+/// Loads a listing; `name` is shown in messages
+fn load_listing(
+    name: &str,
+    listing: &str,
+    reset: bool,
+    interpreter: &mut Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    if reset {
+        interpreter.reset();
+    }
+
+    match interpreter.load_program(listing) {
+        Ok(size) => {
+            let message = format!(
+                "Loaded {name}: {} instructions at 0x{:08x}",
+                size / Instruction::LENGTH as usize,
+                interpreter.cpu.pc
+            );
+            formatting::result(out, &Ok(message))
+        }
+        Err(errors) => {
+            for (line, e) in errors {
+                formatting::error(out, &format!("{name}:{line}: {e}"))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A new file to edit when no program has been loaded. It's only ever
+/// created, never opened if it's there already (eg: as a symlink someone else
+/// left in the temporary directory).
+fn scratch_file() -> io::Result<String> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    for attempt in 0..100 {
+        let name = format!(
+            "brubeck-scratch-{}-{:08x}.s",
+            std::process::id(),
+            nanos.wrapping_add(attempt)
+        );
+        let path = std::env::temp_dir().join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(b"# write a program here, one instruction per line\n")?;
+                return Ok(path.to_string_lossy().into_owned());
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::other("every name tried was taken"))
+}
+
+/// Opens a file in `editor`, waiting for it to close. The editor can include
+/// arguments, eg: `code --wait`.
+fn edit(path: &str, editor: &str) -> io::Result<()> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let status = Command::new(program).args(words).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{program} exited with {status}")))
+    }
+}
+
+fn lint(path: &str, out: &mut dyn Sink) -> io::Result<()> {
+    let listing = match std::fs::read_to_string(path) {
+        Ok(listing) => listing,
+        Err(e) => return formatting::error(out, &format!("{path}: {e}")),
+    };
+
+    let program = match parse_listing(&listing) {
+        Ok(program) => program,
+        Err(errors) => {
+            for (line, e) in errors {
+                formatting::error(out, &format!("{path}:{line}: {e}"))?;
+            }
+            return Ok(());
+        }
+    };
+
+    let instructions: Vec<_> = program.iter().map(|(_, i)| *i).collect();
+    let warnings = analysis::lint(&instructions);
+
+    for warning in &warnings {
+        let line = program[warning.index].0;
+        formatting::warning(out, &format!("{path}:{line}: {warning}"))?;
+    }
+
+    let message = format!(
+        "{} instructions, {} warnings",
+        instructions.len(),
+        warnings.len()
+    );
+    formatting::result(out, &Ok(message))
+}
+
+/// Decodes and shows `count` instructions from `address`
+fn disasm(
+    address: u32,
+    count: usize,
+    interpreter: &Interpreter,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let lines = disassembler::disassemble(&interpreter.cpu, address, count);
+    formatting::disassembly::disassembly(out, &lines, interpreter.cpu.pc)
+}
+
+/// The address expression, element count, and view for `/mem`. The count
+/// defaults to one line's worth, and can't be more than [MAX_MEMORY_SHOWN]
+/// bytes.
+fn memory_request<'a>(args: &[&'a str]) -> Result<(&'a str, usize, View), String> {
+    let mut positional = vec![];
+    let mut width = None;
+    let mut format = Format::Hex;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-w" => {
+                let value = args.next().ok_or("-w needs a width")?;
+                width = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid width {value}"))?,
+                );
+            }
+            "-f" => {
+                let value = args.next().ok_or("-f needs a format")?;
+                format = Format::parse(value).ok_or_else(|| format!("Unknown format {value}"))?;
+            }
+            _ => positional.push(*arg),
+        }
+    }
+
+    let view = View::new(width, format)?;
+    match positional[..] {
+        [address] => Ok((address, 16 / view.width(), view)),
+        [address, count] => match expression::parse_number(count) {
+            Some(count) if count as usize * view.width() <= MAX_MEMORY_SHOWN => {
+                Ok((address, count as usize, view))
+            }
+            Some(_) => Err(format!(
+                "/mem shows at most {MAX_MEMORY_SHOWN} bytes at once"
+            )),
+            None => Err(format!("Invalid count {count}")),
+        },
+        _ => Err("Expected an address and an optional count".to_owned()),
+    }
+}
+
+/// The pattern and alignment for `/find`: text in double quotes, or an
+/// expression for a value (a word, unless `-w` says otherwise)
+fn search_pattern(query: &str, interpreter: &Interpreter) -> Result<(Pattern, u32), String> {
+    let (text, options) = match query.strip_prefix('"') {
+        Some(quoted) => match quoted.split_once('"') {
+            Some((text, options)) => (Some(text), options),
+            None => return Err("Missing the closing \"".to_owned()),
+        },
+        None => (None, query),
+    };
+
+    let mut expression = None;
+    let mut width = 4;
+    let mut alignment = 1;
+    let mut options = options.split_whitespace();
+    while let Some(option) = options.next() {
+        match option {
+            "-w" | "-a" => {
+                let value = options.next().ok_or(format!("{option} needs a value"))?;
+                let value = expression::parse_number(value)
+                    .ok_or_else(|| format!("Invalid number {value}"))?;
+                match option {
+                    "-w" => width = value as usize,
+                    _ => alignment = value,
+                }
+            }
+            _ if text.is_none() && expression.is_none() => expression = Some(option),
+            _ => return Err(format!("Unexpected {option}")),
+        }
+    }
+
+    let pattern = match (text, expression) {
+        (Some(text), _) => Pattern::text(text),
+        (None, Some(expression)) => {
+            let value = interpreter
+                .evaluate(expression)
+                .map_err(|e| e.to_string())?;
+            Pattern::value(value, width).map_err(|e| e.to_string())?
+        }
+        (None, None) => return Err("Expected a value or \"text\" to find".to_owned()),
+    };
+    Ok((pattern, alignment))
+}
+
+/// Lists the addresses found by `/find`, four to a line, up to a limit
+fn matches(found: &[u32], out: &mut dyn Sink) -> io::Result<()> {
+    const SHOWN: usize = 64;
+
+    for row in found[..found.len().min(SHOWN)].chunks(4) {
+        let addresses: Vec<String> = row.iter().map(|a| format!("0x{a:08x}")).collect();
+        out.write(&addresses.join("  "), Color::Default)?;
+        out.end_line()?;
+    }
+    if found.len() > SHOWN {
+        out.write(
+            &format!("... and {} more", found.len() - SHOWN),
+            Color::Default,
+        )?;
+        out.end_line()?;
+    }
+
+    let message = match found.len() {
+        0 => "No matches".to_owned(),
+        1 => "1 match".to_owned(),
+        n => format!("{n} matches"),
+    };
+    formatting::result(out, &Ok(message))
+}
+
+/// An immediate of `bits` bits holding `value`: negative values are signed,
+/// others (which may be hex or binary) unsigned
+fn immediate(value: &str, bits: &str) -> Result<Immediate, String> {
+    let bits = bits
+        .parse()
+        .ok()
+        .filter(|b| (1..=31).contains(b))
+        .ok_or_else(|| format!("Invalid width {bits} (expected 1 to 31 bits)"))?;
+    let mut imm = Immediate::new(bits);
+
+    let set = match value.strip_prefix('-') {
+        Some(magnitude) => expression::parse_number(magnitude)
+            .and_then(|m| 0i32.checked_sub_unsigned(m))
+            .map(|v| imm.set_signed(v)),
+        None => expression::parse_number(value).map(|v| imm.set_unsigned(v)),
+    };
+    match set {
+        Some(Ok(())) => Ok(imm),
+        Some(Err(e)) => Err(format!("{e:?}")),
+        None => Err(format!("Invalid number {value}")),
+    }
+}
+
+fn audit(out: &mut dyn Sink) -> io::Result<()> {
+    let report = audit::audit();
+
+    // gaps everywhere are shown once, rather than for every instruction
+    let everywhere: Vec<Check> = Check::ALL
+        .into_iter()
+        .filter(|check| report.iter().all(|f| f.missing.contains(check)))
+        .collect();
+    if !everywhere.is_empty() {
+        let names: Vec<String> = everywhere.iter().map(Check::to_string).collect();
+        formatting::warning(out, &format!("No instruction has: {}", names.join(", ")))?;
+    }
+
+    let mut gaps = 0;
+    for finding in &report {
+        let missing: Vec<String> = finding
+            .missing
+            .iter()
+            .filter(|check| !everywhere.contains(check))
+            .map(Check::to_string)
+            .collect();
+        if !missing.is_empty() {
+            gaps += 1;
+            let message = format!(
+                "{}: missing {} (tried `{}`)",
+                finding.mnemonic,
+                missing.join(", "),
+                finding.example
+            );
+            formatting::warning(out, &message)?;
+        }
+    }
+
+    let message = format!(
+        "{} instructions, {} with gaps of their own",
+        report.len(),
+        gaps
+    );
+    formatting::result(out, &Ok(message))
+}
+
+fn register_layout(args: &[&str], format: RegisterFormat) -> Option<Layout> {
+    let mut layout = Layout {
+        format,
+        ..Layout::default()
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--columns" => layout.columns = args.next()?.parse().ok().filter(|c| *c > 0)?,
+            "--abi" => layout.grouping = Grouping::Abi,
+            "--nonzero" => layout.hide_zero = true,
+            name => layout.format = RegisterFormat::parse(name)?,
+        }
+    }
+
+    Some(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_requests() {
+        let (address, count, view) = memory_request(&["sp", "8", "-w", "4"]).unwrap();
+        assert_eq!((address, count, view.width()), ("sp", 8, 4));
+        assert!(memory_request(&["0", "0x4000", "-w", "4"]).is_ok());
+
+        // a count that would read gigabytes is refused up front
+        assert_eq!(
+            memory_request(&["0", "0xffffffff"]).err().unwrap(),
+            "/mem shows at most 65536 bytes at once"
+        );
+        assert!(memory_request(&["0", "0x4001", "-w", "4"]).is_err());
+    }
+
+    #[test]
+    fn edit_then_reload() {
+        let directory = std::env::temp_dir().join(format!(
+            "brubeck-edit-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir(&directory).unwrap();
+        let edited = directory.join("edited.s");
+        std::fs::write(&edited, "ADDI x1, zero, 5\nADDI x1, x1, 1\n").unwrap();
+
+        // the "editor" copies the edited program over the file it's given
+        let mut session = Session {
+            editor: Some(format!("cp {}", edited.display())),
+            ..Session::default()
+        };
+        let mut interpreter = Interpreter::new();
+        let mut out = Buffer::default();
+        run("/edit", &mut interpreter, &mut session, &mut out).unwrap();
+        assert!(out.text.contains("Loaded"), "{}", out.text);
+        let scratch = session.program.clone().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&scratch).unwrap(),
+            std::fs::read_to_string(&edited).unwrap()
+        );
+        interpreter.run(10);
+        assert_eq!(interpreter.cpu.x1, 6);
+
+        // the same file is edited again
+        std::fs::write(&edited, "ADDI x1, zero, 7\n").unwrap();
+        run("/edit", &mut interpreter, &mut session, &mut out).unwrap();
+        assert_eq!(session.program.as_ref(), Some(&scratch));
+        interpreter.run(10);
+        assert_eq!(interpreter.cpu.x1, 7);
+
+        std::fs::remove_file(scratch).unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn scratch_files_are_new() {
+        let first = scratch_file().unwrap();
+        let second = scratch_file().unwrap();
+        assert_ne!(first, second);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+//! Defaults for the REPL, from `~/.config/brubeck/config.toml` (or under
+//! `$XDG_CONFIG_HOME`), in the same small subset of TOML as
+//! [board](brubeck::board) files:
+//!
+//! ```toml
+//! memory_size = "4M"      # bytes, or with a K or M suffix
+//! history_limit = 1000    # lines of input to remember; 0 for none
+//! number_format = "hex"   # or dec, unsigned, bin, or float, as for /regs
+//! color = false           # never color, even on a terminal
+//! ```
+//!
+//! Every key is optional, and command line flags override them. Input
+//! history is kept in `~/.local/share/brubeck/history` (or under
+//! `$XDG_DATA_HOME`).
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use brubeck::board;
+use brubeck::interpreter::RegisterFormat;
+
+/// How many lines of input are remembered, unless the config says otherwise
+pub const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Memory size in bytes; the default board's if not given
+    pub memory_size: Option<usize>,
+    pub history_limit: usize,
+    pub number_format: Option<RegisterFormat>,
+    /// Whether to color output on a terminal (it never is otherwise)
+    pub color: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            memory_size: None,
+            history_limit: HISTORY_LIMIT,
+            number_format: None,
+            color: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        for (index, line) in input.lines().enumerate() {
+            let error = |message: &str| format!("Config file line {}: {message}", index + 1);
+
+            // a config never needs a '#' inside a string
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("expected key = value"));
+            };
+            let key = key.trim();
+            let value = value.trim();
+            let text = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+
+            match key {
+                "memory_size" => {
+                    let size = board::parse_size(text)
+                        .ok_or_else(|| error("expected a size like 1024 or \"64K\""))?;
+                    config.memory_size = Some(size);
+                }
+                "history_limit" => {
+                    config.history_limit = text
+                        .parse()
+                        .map_err(|_| error("expected a number of lines"))?;
+                }
+                "number_format" => {
+                    let format = RegisterFormat::parse(text)
+                        .ok_or_else(|| error("expected hex, dec, unsigned, bin, or float"))?;
+                    config.number_format = Some(format);
+                }
+                "color" => {
+                    config.color = match text {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(error("expected true or false")),
+                    };
+                }
+                _ => return Err(error(&format!("unknown key {key}"))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the config file at `path`; there being no file is the same as
+    /// an empty one
+    pub fn load(path: &PathBuf) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+}
+
+/// Where the config file is, if there's a home directory to look in
+pub fn config_path() -> Option<PathBuf> {
+    base_directory("XDG_CONFIG_HOME", ".config").map(|d| d.join("brubeck").join("config.toml"))
+}
+
+/// Where input history is kept, if there's a home directory to keep it in
+pub fn history_path() -> Option<PathBuf> {
+    base_directory("XDG_DATA_HOME", ".local/share").map(|d| d.join("brubeck").join("history"))
+}
+
+/// `$variable`, or `fallback` in the home directory
+fn base_directory(variable: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(variable) {
+        Some(directory) if !directory.is_empty() => Some(directory.into()),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        let config = Config::parse(
+            "# defaults\n\
+             memory_size = \"64K\"\n\
+             history_limit = 50\n\
+             number_format = \"bin\"  # for bit twiddling\n\
+             color = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                memory_size: Some(65536),
+                history_limit: 50,
+                number_format: Some(RegisterFormat::Binary),
+                color: false,
+            }
+        );
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(
+            Config::parse("\ncolour = true").unwrap_err(),
+            "Config file line 2: unknown key colour"
+        );
+        assert_eq!(
+            Config::parse("color = yes").unwrap_err(),
+            "Config file line 1: expected true or false"
+        );
+        assert!(Config::parse("memory_size").is_err());
+    }
+}
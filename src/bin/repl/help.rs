@@ -0,0 +1,331 @@
+//! Help text, generated from the library's [metadata](brubeck::metadata) so
+//! it always describes what's actually implemented.
+
+use std::io;
+
+use brubeck::interpreter::{self, EvalOutcome};
+use brubeck::json;
+use brubeck::metadata::{self, Category, InstructionInfo};
+use brubeck::rv32_i::{csr, Register, TrapCause};
+use brubeck::Interpreter;
+
+use super::commands::COMMANDS;
+use crate::formatting::{Color, Sink};
+
+/// What can be typed, and every instruction grouped by category
+pub fn overview(out: &mut dyn Sink) -> io::Result<()> {
+    line(
+        out,
+        "Type an instruction to execute it (eg: addi x1, zero, 5), or a",
+    )?;
+    line(
+        out,
+        "register to inspect it (eg: x1, or sp by its ABI name).",
+    )?;
+    line(out, "")?;
+
+    line(out, "Instructions:")?;
+    for category in Category::ALL {
+        let names: Vec<&str> = metadata::in_category(category).map(|i| i.name).collect();
+        if !names.is_empty() {
+            line(out, &format!("  {category:<14}{}", names.join(" ")))?;
+        }
+    }
+    line(out, "")?;
+
+    line(out, "Commands:")?;
+    let width = COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+    for (usage, description) in COMMANDS {
+        line(out, &format!("  {usage:<width$}  {description}"))?;
+    }
+
+    Ok(())
+}
+
+/// How many instructions there are in each category
+pub fn categories(out: &mut dyn Sink) -> io::Result<()> {
+    out.write(
+        &format!("{:<14}{:>5}  Description", "Category", "Count"),
+        Color::Cyan,
+    )?;
+    out.end_line()?;
+
+    let mut total = 0;
+    for category in Category::ALL {
+        let count = metadata::in_category(category).count();
+        if count > 0 {
+            line(
+                out,
+                &format!("{category:<14}{count:>5}  {}", category.blurb()),
+            )?;
+            total += count;
+        }
+    }
+
+    line(out, &format!("{:<14}{total:>5}", "Total"))
+}
+
+/// A compact reference: every instruction's operands, operation, and
+/// immediate range, grouped by category
+pub fn cheatsheet(out: &mut dyn Sink) -> io::Result<()> {
+    for category in Category::ALL {
+        let mut instructions = metadata::in_category(category).peekable();
+        if instructions.peek().is_none() {
+            continue;
+        }
+
+        out.write(&category.to_string(), Color::Cyan)?;
+        out.end_line()?;
+
+        for info in instructions {
+            let range = info
+                .immediate_range()
+                .map(|r| format!("  [{}..={}]", r.start(), r.end()))
+                .unwrap_or_default();
+            line(
+                out,
+                &format!(
+                    "  {:<7}{:<16}{}{range}",
+                    info.name,
+                    info.syntax(),
+                    info.description
+                ),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Everything about one instruction: its operation, format, what its
+/// operands are for, how it's encoded, and a worked example (run on a
+/// scratch CPU, so it doesn't touch the session's)
+pub fn instruction(out: &mut dyn Sink, info: &InstructionInfo) -> io::Result<()> {
+    out.write(&format!("{}: {}", info.name, info.description), Color::Cyan)?;
+    out.end_line()?;
+    line(
+        out,
+        &format!("  Format    {:?}-type ({})", info.format, info.category),
+    )?;
+    line(
+        out,
+        format!("  Syntax    {} {}", info.name, info.syntax()).trim_end(),
+    )?;
+
+    let operands = info.operands();
+    let width = operands.iter().map(|o| o.name.len()).max().unwrap_or(0);
+    for (i, operand) in operands.iter().enumerate() {
+        let label = if i == 0 { "Operands" } else { "" };
+        let range = match operand.name {
+            "imm" | "uimm" | "shamt" => info
+                .immediate_range()
+                .map(|r| format!(", {}..={}", r.start(), r.end()))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        line(
+            out,
+            &format!(
+                "  {label:<8}  {:<width$}  {}{range}",
+                operand.name, operand.meaning
+            ),
+        )?;
+    }
+
+    let example = info.example();
+    let word = interpreter::parse_listing(&example)
+        .ok()
+        .and_then(|instructions| instructions.first().map(|(_, i)| i.encode()));
+
+    // each field's name over its bits, then the example's bits
+    let fields = info.format.fields();
+    let widths: Vec<usize> = fields
+        .iter()
+        .map(|f| f.name.len().max(f.width() as usize))
+        .collect();
+    let row = |cell: &dyn Fn(usize) -> String| {
+        let cells: Vec<String> = (0..fields.len())
+            .map(|i| format!("{:<width$}", cell(i), width = widths[i]))
+            .collect();
+        cells.join(" ").trim_end().to_owned()
+    };
+    line(
+        out,
+        &format!("  Encoding  {}", row(&|i| fields[i].name.to_owned())),
+    )?;
+    line(
+        out,
+        &format!(
+            "            {}",
+            row(&|i| match fields[i].high == fields[i].low {
+                true => fields[i].high.to_string(),
+                false => format!("{}:{}", fields[i].high, fields[i].low),
+            })
+        ),
+    )?;
+
+    let setup = match example.contains("a1") {
+        true => "   (with a1 = 12, a2 = 5)",
+        false => "",
+    };
+    line(out, &format!("  Example   {example}{setup}"))?;
+    if let Some(word) = word {
+        let bits = row(&|i| {
+            let field = fields[i];
+            format!(
+                "{:0width$b}",
+                field.extract(word),
+                width = field.width() as usize
+            )
+        });
+        line(out, &format!("            {bits}   = 0x{word:08x}"))?;
+    }
+    line(out, &format!("            {}", example_effect(&example)))
+}
+
+/// What running the example does to a fresh CPU, with `a1` and `a2` set.
+/// Deltas don't cover CSRs, so the one examples use is compared by hand.
+fn example_effect(example: &str) -> String {
+    let mut i = Interpreter::new();
+    i.cpu.set_x(Register::X11, 12);
+    i.cpu.set_x(Register::X12, 5);
+    let mscratch = i.cpu.read_csr(csr::MSCRATCH);
+
+    let (delta, trap) = match i.eval(example) {
+        Ok(EvalOutcome::InstructionExecuted { delta, trap, .. }) => (delta, trap),
+        Ok(outcome) => return i.describe(&outcome),
+        Err(e) => return format!("can't run here: {e}"),
+    };
+
+    let mut changes: Vec<String> = delta
+        .registers
+        .iter()
+        .filter(|c| c.register != Register::PC || c.new != c.old + 4)
+        .map(|c| {
+            let name = match c.register {
+                Register::PC => "pc",
+                r => r.abi().map_or("?", |abi| abi.name()),
+            };
+            format!("{}: 0x{:x} -> 0x{:x}", name.to_lowercase(), c.old, c.new)
+        })
+        .collect();
+    changes.extend(delta.memory.iter().map(|w| {
+        format!(
+            "[0x{:08x}]: {} -> {}",
+            w.address,
+            json::hex(&w.old),
+            json::hex(&w.new)
+        )
+    }));
+    let now = i.cpu.read_csr(csr::MSCRATCH);
+    if let (Ok(old), Ok(new)) = (mscratch, now) {
+        if old != new {
+            changes.push(format!("mscratch: 0x{old:x} -> 0x{new:x}"));
+        }
+    }
+    if let Some(cause) = trap {
+        changes.push(format!("trapped: {}", TrapCause::describe(cause)));
+    }
+
+    match changes.is_empty() {
+        true => "changes nothing".to_owned(),
+        false => changes.join(", "),
+    }
+}
+
+fn line(out: &mut dyn Sink, text: &str) -> io::Result<()> {
+    out.write(text, Color::Default)?;
+    out.end_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+
+    #[test]
+    fn categories_table() {
+        let mut buffer = Buffer::default();
+        categories(&mut buffer).unwrap();
+
+        let lines: Vec<&str> = buffer.text.lines().collect();
+        assert!(lines.contains(&"Branches          6  conditionally jump to a PC-relative target"));
+        assert_eq!(
+            lines.last(),
+            Some(&format!("Total            {}", metadata::INSTRUCTIONS.len()).as_str())
+        );
+
+        assert!(lines.contains(&"CSR               6  read and write control and status registers"));
+    }
+
+    #[test]
+    fn cheatsheet_lines() {
+        let mut buffer = Buffer::default();
+        cheatsheet(&mut buffer).unwrap();
+
+        let lines: Vec<&str> = buffer.text.lines().collect();
+        assert!(lines.contains(&"  ADDI   rd, rs1, imm    rd = rs1 + imm  [-2048..=2047]"));
+        assert!(lines.contains(&"  ECALL                  call the execution environment"));
+        assert_eq!(
+            lines.len(),
+            metadata::INSTRUCTIONS.len() + Category::ALL.len()
+        );
+    }
+
+    #[test]
+    fn instruction_pages() {
+        let mut buffer = Buffer::default();
+        instruction(&mut buffer, metadata::lookup("addi").unwrap()).unwrap();
+        assert_eq!(
+            buffer.text,
+            "ADDI: rd = rs1 + imm\n\
+             \x20 Format    I-type (Arithmetic)\n\
+             \x20 Syntax    ADDI rd, rs1, imm\n\
+             \x20 Operands  rd   destination register\n\
+             \x20           rs1  source register\n\
+             \x20           imm  signed immediate, -2048..=2047\n\
+             \x20 Encoding  imm[11:0]    rs1   funct3 rd    opcode\n\
+             \x20           31:20        19:15 14:12  11:7  6:0\n\
+             \x20 Example   ADDI a0, a1, 5   (with a1 = 12, a2 = 5)\n\
+             \x20           000000000101 01011 000    01010 0010011   = 0x00558513\n\
+             \x20           a0: 0x0 -> 0x11\n"
+        );
+
+        // every example runs (or says why it can't), without the session
+        for info in metadata::INSTRUCTIONS {
+            let mut buffer = Buffer::default();
+            instruction(&mut buffer, info).unwrap();
+            assert!(buffer.text.contains(&info.example()));
+        }
+        assert_eq!(
+            example_effect("SW a1, a2, 8"),
+            "[0x00000014]: 00000000 -> 05000000"
+        );
+        assert_eq!(
+            example_effect("JAL a0, 8"),
+            "a0: 0x0 -> 0x4, pc: 0x0 -> 0x10"
+        );
+        assert_eq!(
+            example_effect("CSRRW a0, mscratch, a1"),
+            "mscratch: 0x0 -> 0xc"
+        );
+        assert_eq!(example_effect("BEQ a1, a2, 8"), "changes nothing");
+    }
+
+    #[test]
+    fn overview_lists_everything() {
+        let mut buffer = Buffer::default();
+        overview(&mut buffer).unwrap();
+
+        for info in metadata::INSTRUCTIONS {
+            assert!(buffer.text.contains(info.name));
+        }
+        for (usage, _) in COMMANDS {
+            assert!(buffer.text.contains(usage));
+        }
+    }
+}
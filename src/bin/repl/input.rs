@@ -0,0 +1,358 @@
+//! Reading lines from a terminal, with Tab to complete (see
+//! [completion](brubeck::completion)), the up and down arrows to go back
+//! through what's been entered (in this run, and earlier ones if the history
+//! is kept in a file), and (optionally) syntax highlighting as it's typed.
+//!
+//! The terminal is put in raw mode with `stty` while a line is read, so it's
+//! only done on Unix, and only when `stty` works; anywhere else, lines are
+//! read as they come.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use brubeck::completion;
+
+use super::commands::COMMANDS;
+use crate::formatting::highlight;
+
+/// What the user entered
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// A line, ending with a newline
+    Line(String),
+    /// Ctrl-C
+    Interrupted,
+    /// Ctrl-D on an empty line, or the end of input
+    End,
+}
+
+/// Reads lines, remembering them for the arrow keys
+#[derive(Debug, Default)]
+pub struct Editor {
+    history: Vec<String>,
+    /// Most lines to remember; no limit if not given
+    limit: Option<usize>,
+    /// Where the history is kept between runs, if anywhere
+    file: Option<PathBuf>,
+    /// Whether to color the line as it's typed
+    highlight: bool,
+}
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+impl Editor {
+    pub fn new(highlight: bool) -> Self {
+        Self {
+            highlight,
+            ..Self::default()
+        }
+    }
+
+    /// Keeps the history in a file between runs: what's there already is
+    /// loaded, and each line is added as it's entered. Only the last `limit`
+    /// lines are kept. The history is a convenience, so errors reading or
+    /// writing it are ignored.
+    pub fn keep_history(&mut self, path: PathBuf, limit: usize) {
+        self.limit = Some(limit);
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let lines: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+            let skip = lines.len().saturating_sub(limit);
+            self.history = lines[skip..].iter().map(|l| l.to_string()).collect();
+            if skip > 0 {
+                let mut kept = self.history.join("\n");
+                kept.push('\n');
+                let _ = fs::write(&path, kept);
+            }
+        }
+        self.file = Some(path);
+    }
+
+    /// Reads a line from the terminal, or from stdin as it comes if it can't
+    /// be put in raw mode
+    pub fn read_line(&mut self) -> io::Result<Entry> {
+        let Some(_raw) = RawMode::enter() else {
+            let mut buffer = String::new();
+            return match io::stdin().lock().read_line(&mut buffer)? {
+                0 => Ok(Entry::End),
+                _ => Ok(Entry::Line(buffer)),
+            };
+        };
+        self.edit(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// Reads key presses from `input` until a line is entered, echoing
+    /// what's typed to `out`
+    pub fn edit(&mut self, mut input: impl Read, mut out: impl Write) -> io::Result<Entry> {
+        let mut line = String::new();
+        // where in the history the line came from; past the end if it's new
+        let mut browsing = self.history.len();
+
+        loop {
+            out.flush()?;
+            let Some(byte) = next(&mut input)? else {
+                return Ok(Entry::End);
+            };
+
+            match byte {
+                b'\r' | b'\n' => {
+                    write!(out, "\r\n")?;
+                    self.remember(&line);
+                    line.push('\n');
+                    return Ok(Entry::Line(line));
+                }
+                CTRL_C => {
+                    write!(out, "^C\r\n")?;
+                    return Ok(Entry::Interrupted);
+                }
+                CTRL_D if line.is_empty() => return Ok(Entry::End),
+                BACKSPACE | DELETE if line.pop().is_some() => match self.highlight {
+                    true => self.redraw(&line, &mut out)?,
+                    false => write!(out, "\x08 \x08")?,
+                },
+                TAB => self.complete(&mut line, &mut out)?,
+                ESCAPE => {
+                    let recalled = match escape(&mut input)? {
+                        Some(b'A') if browsing > 0 => {
+                            browsing -= 1;
+                            self.history[browsing].clone()
+                        }
+                        Some(b'B') if browsing < self.history.len() => {
+                            browsing += 1;
+                            self.history.get(browsing).cloned().unwrap_or_default()
+                        }
+                        _ => continue,
+                    };
+                    line = recalled;
+                    self.redraw(&line, &mut out)?;
+                }
+                byte if byte >= 0x80 => {
+                    // the rest of a UTF-8 character
+                    let mut bytes = vec![byte];
+                    let length = byte.leading_ones() as usize;
+                    for _ in 1..length.min(4) {
+                        bytes.extend(next(&mut input)?);
+                    }
+                    line.push_str(&String::from_utf8_lossy(&bytes));
+                    self.echo(&line, &mut out)?;
+                }
+                byte if byte >= 0x20 => {
+                    line.push(byte as char);
+                    self.echo(&line, &mut out)?;
+                }
+                // other control keys do nothing
+                _ => {}
+            }
+        }
+    }
+
+    /// Adds a line to the history (unless it's empty, or the same as the last
+    /// one), and to the file it's kept in
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty()
+            || self.limit == Some(0)
+            || self.history.last().is_some_and(|last| last == line)
+        {
+            return;
+        }
+
+        self.history.push(line.to_owned());
+        if let Some(limit) = self.limit {
+            let excess = self.history.len().saturating_sub(limit);
+            self.history.drain(..excess);
+        }
+
+        if let Some(path) = &self.file {
+            if let Some(directory) = path.parent() {
+                let _ = fs::create_dir_all(directory);
+            }
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{line}");
+            }
+        }
+    }
+
+    /// Completes the word being typed as far as it can be. If there's more
+    /// than one way to go on from there, they're listed.
+    fn complete(&self, line: &mut String, out: &mut impl Write) -> io::Result<()> {
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .filter_map(|(usage, _)| usage.split_whitespace().next())
+            .collect();
+        names.dedup();
+
+        let completion = completion::complete(line, &names);
+        let addition = match completion.candidates.as_slice() {
+            [] => return write!(out, "\x07"),
+            [only] => {
+                let space = if completion.first { " " } else { "" };
+                format!("{}{space}", &only[completion.word.len()..])
+            }
+            _ => completion.common_prefix()[completion.word.len()..].to_owned(),
+        };
+
+        if !addition.is_empty() {
+            line.push_str(&addition);
+            return self.redraw(line, out);
+        }
+        write!(out, "\r\n{}\r\n", completion.candidates.join("  "))?;
+        self.redraw(line, out)
+    }
+
+    /// Shows the last character typed; the whole line is drawn again if it's
+    /// highlighted, since one character can change the color of a word
+    fn echo(&self, line: &str, out: &mut impl Write) -> io::Result<()> {
+        match (self.highlight, line.chars().last()) {
+            (false, Some(c)) => write!(out, "{c}"),
+            _ => self.redraw(line, out),
+        }
+    }
+
+    /// Shows the line again, in place of what was there
+    fn redraw(&self, line: &str, out: &mut impl Write) -> io::Result<()> {
+        match self.highlight {
+            true => write!(out, "\r\x1b[K{}", highlight::ansi(line)),
+            false => write!(out, "\r\x1b[K{line}"),
+        }
+    }
+}
+
+/// The next byte, if there is one
+fn next(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Reads the rest of an escape sequence (eg: `[A` for the up arrow),
+/// returning its last byte
+fn escape(input: &mut impl Read) -> io::Result<Option<u8>> {
+    if next(input)? != Some(b'[') {
+        return Ok(None);
+    }
+    // parameters, then a letter or `~`
+    while let Some(byte) = next(input)? {
+        if byte.is_ascii_alphabetic() || byte == b'~' {
+            return Ok(Some(byte));
+        }
+    }
+    Ok(None)
+}
+
+/// The terminal in raw mode (keys are read as they're pressed, and not
+/// echoed), until this is dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> Option<Self> {
+        let saved = stty(&["-g"])?;
+        // -isig, so Ctrl-C is read as a key rather than quitting with the
+        // terminal still in raw mode
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(Self {
+            saved: saved.trim().to_owned(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the terminal, returning what it printed if it worked
+fn stty(args: &[&str]) -> Option<String> {
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => String::from_utf8(output.stdout).ok(),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(editor: &mut Editor, keys: &str) -> (Entry, String) {
+        let mut out = vec![];
+        let entry = editor.edit(keys.as_bytes(), &mut out).unwrap();
+        (entry, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn completion_and_history() {
+        let mut editor = Editor::default();
+
+        // one way to finish the mnemonic, so it's followed by a space
+        let (entry, _) = edit(&mut editor, "lu\ta0, 1\r");
+        assert_eq!(entry, Entry::Line("lui a0, 1\n".into()));
+
+        // more than one, so they're listed; then the register
+        let (entry, out) = edit(&mut editor, "xor\ti a0, ze\t, 1\r");
+        assert_eq!(entry, Entry::Line("xori a0, zero, 1\n".into()));
+        assert!(out.contains("\r\nxor  xori\r\n"));
+
+        // as far as it goes, then the choices
+        let (entry, out) = edit(&mut editor, "/ch\t\t\x7f\x7f\x7f\x7f/help\r");
+        assert_eq!(entry, Entry::Line("/help\n".into()));
+        assert!(out.contains("\r\x1b[K/che"));
+        assert!(out.contains("\r\n/cheatsheet  /checkpoint\r\n"));
+
+        // up goes back through what was entered, and down comes forward
+        let (entry, _) = edit(&mut editor, "\x1b[A\x1b[A\x1b[B\r");
+        assert_eq!(entry, Entry::Line("/help\n".into()));
+        assert_eq!(editor.history, ["lui a0, 1", "xori a0, zero, 1", "/help"]);
+
+        assert_eq!(edit(&mut editor, "nop\x03").0, Entry::Interrupted);
+
+        // colored as it's typed
+        let mut colored = Editor::new(true);
+        let (entry, out) = edit(&mut colored, "nop\r");
+        assert_eq!(entry, Entry::Line("nop\n".into()));
+        assert!(out.contains("\r\x1b[Kno\r\x1b[K\x1b[36mnop\x1b[0m"));
+        assert_eq!(edit(&mut editor, "\x04").0, Entry::End);
+        assert_eq!(edit(&mut editor, "").0, Entry::End);
+        let (_, out) = edit(&mut editor, "qqq\t\r");
+        assert!(out.contains('\x07'));
+    }
+
+    #[test]
+    fn history_file() {
+        let path = std::env::temp_dir().join(format!("brubeck-history-{}", std::process::id()));
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        // only the last two are kept, in memory and in the file
+        let mut editor = Editor::default();
+        editor.keep_history(path.clone(), 2);
+        assert_eq!(editor.history, ["two", "three"]);
+        edit(&mut editor, "four\r");
+        assert_eq!(editor.history, ["three", "four"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two\nthree\nfour\n");
+
+        // and are there for the next run
+        let mut editor = Editor::default();
+        editor.keep_history(path.clone(), 2);
+        assert_eq!(
+            edit(&mut editor, "\x1b[A\r").0,
+            Entry::Line("four\n".into())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Ctrl-C while the guest is running stops the guest, not the REPL.
+//!
+//! The handler only sets the interpreter's [CancelToken]; the run loop
+//! notices between instructions and returns what it finished. At the prompt,
+//! with nothing running, Ctrl-C quits as usual.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use brubeck::cancel::CancelToken;
+
+static TOKEN: OnceLock<CancelToken> = OnceLock::new();
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Routes Ctrl-C to `token` while a [Running] guard is held. Only does
+/// anything on Unix; elsewhere Ctrl-C keeps its default behavior.
+pub fn install(token: CancelToken) {
+    if TOKEN.set(token).is_ok() {
+        platform::install();
+    }
+}
+
+/// Marks input as being run, so Ctrl-C cancels it rather than quitting.
+/// Any cancellation left over from before is forgotten.
+pub struct Running;
+
+impl Running {
+    pub fn start() -> Self {
+        if let Some(token) = TOKEN.get() {
+            token.reset();
+        }
+        RUNNING.store(true, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// What Ctrl-C does: cancels the run and returns true if one is going,
+/// otherwise returns false so the process can exit. Only touches atomics
+/// (`OnceLock::get` is an atomic load once the token is set), so it's safe
+/// to call from a signal handler.
+#[cfg(any(unix, test))]
+fn interrupt() -> bool {
+    match (RUNNING.load(Ordering::SeqCst), TOKEN.get()) {
+        (true, Some(token)) => {
+            token.cancel();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Declares the two libc functions it needs rather than depending on the
+/// `libc` crate. Their signatures are the same on every Unix: `signal` takes
+/// an `int` and a handler pointer and returns the old handler (a pointer, so
+/// `usize` holds it), and `SIGINT` is 2 everywhere POSIX applies.
+#[cfg(unix)]
+mod platform {
+    const SIGINT: i32 = 2;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+        fn _exit(status: i32) -> !;
+    }
+
+    /// Does only what's async-signal-safe: [interrupt](super::interrupt)
+    /// touches atomics, and `_exit` (unlike `exit`) runs no destructors or
+    /// `atexit` handlers.
+    extern "C" fn handle(_signum: i32) {
+        if !super::interrupt() {
+            // SAFETY: `_exit` is async-signal-safe, and never returns.
+            // 130 is the conventional status for a process ended by SIGINT.
+            unsafe { _exit(130) }
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: `handle` is an `extern "C" fn(i32)`, the type `signal`
+        // expects, and lives for the whole program. If installing fails,
+        // Ctrl-C just keeps its default behavior.
+        unsafe {
+            signal(SIGINT, handle as extern "C" fn(i32) as usize);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    pub fn install() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancels_only_while_running() {
+        let token = CancelToken::default();
+        TOKEN.set(token.clone()).unwrap();
+
+        // at the prompt, Ctrl-C quits
+        assert!(!interrupt());
+        assert!(!token.is_cancelled());
+
+        let running = Running::start();
+        assert!(interrupt());
+        assert!(token.is_cancelled());
+        drop(running);
+        assert!(!interrupt());
+
+        // a new run forgets the last one was cancelled
+        let _running = Running::start();
+        assert!(!token.is_cancelled());
+    }
+}
@@ -0,0 +1,113 @@
+//! Pieces of the interactive REPL that aren't about formatting.
+
+pub mod args;
+pub mod commands;
+pub mod config;
+pub mod help;
+pub mod input;
+pub mod interrupt;
+pub mod script;
+pub mod transcript;
+pub mod workspace;
+
+use std::io;
+
+use brubeck::interpreter::{EvalOutcome, Interpreter};
+
+use crate::formatting::json::{self, OutputFormat};
+use crate::formatting::sink::Buffer;
+use crate::formatting::{self, Sink};
+use commands::Session;
+
+/// Handles a line of input: a command, or something for the interpreter
+/// (an instruction, or a register to show)
+pub fn handle(
+    line: &str,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    if session.output == OutputFormat::Json {
+        return handle_json(line, interpreter, session, out);
+    }
+    if commands::is_command(line) {
+        return commands::run(line, interpreter, session, out);
+    }
+
+    if let Some(preview) = interpreter.preview(line) {
+        formatting::jump_preview(out, &preview)?;
+    }
+
+    let (result, ran) = match interpreter.eval(line) {
+        Ok(outcome) => (Ok(interpreter.describe(&outcome)), executed(&outcome)),
+        Err(e) => (Err(e), false),
+    };
+
+    let guest_output = interpreter.take_output();
+    if !guest_output.is_empty() {
+        formatting::guest_output(out, &guest_output)?;
+    }
+
+    match &result {
+        Ok(assembly) if ran => formatting::executed(out, assembly)?,
+        _ => formatting::result(out, &result)?,
+    }
+    if let Err(e) = &result {
+        formatting::errors::caret(out, line, e)?;
+        formatting::errors::hints(out, e, &interpreter.cpu)?;
+    }
+    commands::show_device_changes(interpreter, session, out)
+}
+
+/// Whether the outcome is only instructions that ran, so it's shown as
+/// assembly
+fn executed(outcome: &EvalOutcome) -> bool {
+    match outcome {
+        EvalOutcome::InstructionExecuted { .. } => true,
+        EvalOutcome::Several(outcomes) => !outcomes.is_empty() && outcomes.iter().all(executed),
+        _ => false,
+    }
+}
+
+/// [handle], with everything shown as JSON; see [formatting::json]
+fn handle_json(
+    line: &str,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<()> {
+    let line = line.trim();
+    if commands::is_command(line) {
+        return commands::run_json(line, interpreter, session, out);
+    }
+
+    let epoch = interpreter.history().epoch();
+    let result = interpreter.interpret(line);
+    // everything the line did, if it was several instructions
+    let delta = interpreter
+        .history()
+        .diff(epoch, interpreter.history().epoch())
+        .ok();
+
+    let console = interpreter.take_output();
+    if !console.is_empty() {
+        json::output(out, "console", &console)?;
+    }
+    let uart = interpreter.take_uart_output();
+    if !uart.is_empty() {
+        json::output(out, "uart", &uart)?;
+    }
+
+    let hint = match &result {
+        Ok(_) => None,
+        Err(e) => formatting::errors::hint(e, &interpreter.cpu),
+    };
+    json::result(out, line, &result, delta.as_ref(), hint)?;
+
+    let mut drawings = Buffer::default();
+    commands::show_device_changes(interpreter, session, &mut drawings)?;
+    if !drawings.text.is_empty() {
+        json::text(out, "devices", line, true, &drawings.text)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! `brubeck --script exercise.bru` runs a file of REPL input without a
+//! prompt: instructions, register names, and commands (eg: `/load`, `/run`,
+//! and `/assert`), one per line. Lines are read as [brubeck::script::lines]
+//! reads them, so anything after a `#` is a comment.
+//!
+//! The script stops at the first line that fails, naming it, so a course can
+//! grade an exercise by whether the script succeeds. As in a library script,
+//! a `/run` that gets to its step limit fails.
+
+use std::io;
+
+use brubeck::interpreter::Interpreter;
+use brubeck::script;
+
+use super::commands::Session;
+use crate::formatting::sink::Watch;
+use crate::formatting::{self, Sink};
+
+/// Runs the script at `path`, returning whether every line succeeded
+pub fn run(
+    path: &str,
+    interpreter: &mut Interpreter,
+    session: &mut Session,
+    out: &mut dyn Sink,
+) -> io::Result<bool> {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            formatting::error(out, &format!("{path}: {e}"))?;
+            return Ok(false);
+        }
+    };
+
+    for (line, code) in script::lines(&script) {
+        let mut watch = Watch::new(out);
+        super::handle(code, interpreter, session, &mut watch)?;
+        if watch.failed {
+            formatting::error(out, &format!("{path}:{line}: {code}"))?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatting::sink::Buffer;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A new directory of its own under the temporary directory
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let directory =
+            std::env::temp_dir().join(format!("brubeck-{name}-{}-{nanos}", std::process::id()));
+        std::fs::create_dir(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn stops_at_the_first_failure() {
+        let directory = temp_dir("script");
+        let path = directory.join("exercise.bru");
+        std::fs::write(
+            &path,
+            "# setup\n\
+             ADDI x1, zero, 41  # the answer, nearly\n\
+             /assert x1 == 41\n\
+             \n\
+             ADDI x1, x1, 1\n\
+             /assert x1 == 41\n\
+             ADDI x1, x1, 1\n",
+        )
+        .unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let mut interpreter = Interpreter::new();
+        let mut buffer = Buffer::default();
+        let passed = run(
+            &path,
+            &mut interpreter,
+            &mut Session::default(),
+            &mut buffer,
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(!passed);
+        assert_eq!(interpreter.cpu.x1, 42);
+        assert!(buffer.text.contains("=> ✅ Passed: x1 == 41\n"));
+        assert!(buffer.text.ends_with(&format!(
+            "Assertion failed: x1 == 41, but x1 is 42 (0x0000002a)\n❌ {path}:6: /assert x1 == 41\n"
+        )));
+    }
+
+    #[test]
+    fn running_out_of_steps_fails() {
+        let directory = temp_dir("steps");
+        let path = directory.join("forever.bru");
+        std::fs::write(&path, "/run 10\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.load_program("JAL zero, 0").unwrap();
+        let mut buffer = Buffer::default();
+        let passed = run(
+            &path,
+            &mut interpreter,
+            &mut Session::default(),
+            &mut buffer,
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(!passed);
+        assert!(buffer
+            .text
+            .contains("Stopped at the limit of 10 instructions"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Records a REPL session to a file: every line of input, and the plain text
+//! rendering of every response. Handy for reviewing a session later, or for
+//! handing in with an assignment.
+//!
+//! A [Transcript] can be cloned and shared between threads; every clone
+//! writes to the same file, one whole line at a time. It's also a [Sink], so
+//! formatters can write to it directly.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::formatting::{Color, Sink};
+
+#[derive(Clone)]
+pub struct Transcript {
+    writer: Arc<Mutex<BufWriter<File>>>,
+    /// the line being built up by [Sink::write]
+    line: String,
+}
+
+impl Transcript {
+    /// Creates (or truncates) the transcript file at `path`
+    pub fn create(path: &str) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+            line: String::new(),
+        })
+    }
+
+    /// Records a line the user typed
+    pub fn input(&self, line: &str) -> io::Result<()> {
+        self.write(&format!("> {}", line.trim_end()))
+    }
+
+    /// Records rendered output; any stray ANSI escape codes are removed
+    pub fn output(&self, text: &str) -> io::Result<()> {
+        self.write(&strip_ansi(text))
+    }
+
+    fn write(&self, text: &str) -> io::Result<()> {
+        // a poisoned lock only means another thread panicked mid-write; the
+        // file itself is still usable
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{text}")?;
+        writer.flush()
+    }
+}
+
+impl Sink for Transcript {
+    fn write(&mut self, text: &str, _color: Color) -> io::Result<()> {
+        self.line.push_str(text);
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        self.output(&line)
+    }
+}
+
+/// Removes ANSI escape sequences (eg: colors) from text
+pub fn strip_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            // skip the parameters up to and including the final letter
+            chars.next();
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(strip_ansi("\x1b[32m✅ ok\x1b[0m"), "✅ ok");
+        assert_eq!(strip_ansi("plain"), "plain");
+        assert_eq!(strip_ansi("\x1b[1;31mbold red\x1b[0m!"), "bold red!");
+    }
+
+    #[test]
+    fn records_session() {
+        let path = std::env::temp_dir().join(format!("brubeck-transcript-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut transcript = Transcript::create(path).unwrap();
+        let other_thread = transcript.clone();
+
+        transcript.input("ADDI x1, zero, 5\n").unwrap();
+        std::thread::spawn(move || other_thread.output("\x1b[32m=> ✅ done\x1b[0m").unwrap())
+            .join()
+            .unwrap();
+        crate::formatting::exit(&mut transcript, 0).unwrap();
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert_eq!(
+            contents,
+            "> ADDI x1, zero, 5\n=> ✅ done\nProgram exited with code 0\n"
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! A workspace is a directory that keeps REPL sessions between runs.
+//!
+//! Each named session is a subdirectory holding the machine state and undo
+//! history (see [Interpreter::to_bytes]), a copy of the loaded program, and
+//! the options, breakpoints, and watchpoints in effect:
+//!
+//! ```text
+//! workspace/
+//!   transcripts/1700000000.txt
+//!   lab1/
+//!     state.bin
+//!     program.s
+//!     session.txt
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use brubeck::expression::parse_number;
+use brubeck::interpreter::Interpreter;
+use brubeck::rv32_i::FillPattern;
+
+const STATE: &str = "state.bin";
+const PROGRAM: &str = "program.s";
+const OPTIONS: &str = "session.txt";
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    /// Opens a workspace directory, creating it if needed
+    pub fn open(root: &str) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(Self { root: root.into() })
+    }
+
+    /// Where to record this run's transcript
+    pub fn transcript_path(&self) -> io::Result<PathBuf> {
+        let directory = self.root.join("transcripts");
+        fs::create_dir_all(&directory)?;
+
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Ok(directory.join(format!("{seconds}.txt")))
+    }
+
+    /// Saves the machine state, options, breakpoints, watchpoints, and a copy
+    /// of `program` (if one is loaded) as the session `name`, replacing any
+    /// session with that name.
+    pub fn save(
+        &self,
+        name: &str,
+        interpreter: &Interpreter,
+        program: Option<&str>,
+    ) -> io::Result<()> {
+        let directory = self.session(name)?;
+        fs::create_dir_all(&directory)?;
+
+        fs::write(directory.join(STATE), interpreter.to_bytes())?;
+
+        let copy = directory.join(PROGRAM);
+        match program {
+            // the program may already be the session's own copy
+            Some(path) if Path::new(path) != copy => {
+                fs::copy(path, &copy)?;
+            }
+            Some(_) => (),
+            None => {
+                if copy.exists() {
+                    fs::remove_file(&copy)?;
+                }
+            }
+        }
+
+        let mut options = format!("fill = {}\n", interpreter.cpu.config.fill);
+        for address in interpreter.breakpoints() {
+            options += &format!("break = 0x{address:08x}\n");
+        }
+        for (address, len) in interpreter.watchpoints() {
+            options += &format!("watch = 0x{address:08x} {len}\n");
+        }
+        fs::write(directory.join(OPTIONS), options)
+    }
+
+    /// Restores the session `name`, returning the path of its program, if it
+    /// has one. Its breakpoints and watchpoints replace any already set.
+    pub fn load(&self, name: &str, interpreter: &mut Interpreter) -> io::Result<Option<String>> {
+        let directory = self.session(name)?;
+
+        let bytes = fs::read(directory.join(STATE))
+            .map_err(|e| io::Error::new(e.kind(), format!("no session named {name}")))?;
+        interpreter
+            .restore_bytes(&bytes)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        let breakpoints: Vec<u32> = interpreter.breakpoints().collect();
+        for address in breakpoints {
+            interpreter.remove_breakpoint(address);
+        }
+        let watchpoints: Vec<(u32, u32)> = interpreter.watchpoints().collect();
+        for (address, _) in watchpoints {
+            interpreter.unwatch(address);
+        }
+
+        let options = fs::read_to_string(directory.join(OPTIONS)).unwrap_or_default();
+        for line in options.lines() {
+            let Some((key, value)) = line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) else {
+                continue;
+            };
+            match key {
+                "fill" => {
+                    if let Some(fill) = FillPattern::parse(value) {
+                        interpreter.cpu.config.fill = fill;
+                    }
+                }
+                "break" => {
+                    if let Some(address) = parse_number(value) {
+                        interpreter.add_breakpoint(address);
+                    }
+                }
+                "watch" => {
+                    let parsed = value.split_once(' ').and_then(|(address, len)| {
+                        Some((parse_number(address)?, parse_number(len)?))
+                    });
+                    if let Some((address, len)) = parsed {
+                        interpreter.watch(address, len);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let program = directory.join(PROGRAM);
+        Ok(program
+            .exists()
+            .then(|| program.to_string_lossy().into_owned()))
+    }
+
+    /// Names of the saved sessions, sorted
+    pub fn sessions(&self) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.join(STATE).exists() {
+                names.extend(path.file_name().map(|n| n.to_string_lossy().into_owned()));
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The directory for a session; names can't escape the workspace
+    fn session(&self, name: &str) -> io::Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if valid {
+            Ok(self.root.join(name))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid session name {name:?} (use letters, digits, - and _)"),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_list() {
+        let root = std::env::temp_dir().join(format!("brubeck-workspace-{}", std::process::id()));
+        let workspace = Workspace::open(root.to_str().unwrap()).unwrap();
+
+        let program = root.join("original.s");
+        fs::write(&program, "ADDI x1, zero, 4\n").unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret("ADDI x2, zero, 7").unwrap();
+        interpreter.cpu.config.fill = FillPattern::Byte(0xcc);
+        interpreter.add_breakpoint(0x40);
+        interpreter.watch(0x100, 4);
+        workspace
+            .save("lab1", &interpreter, program.to_str())
+            .unwrap();
+        workspace.save("empty", &Interpreter::new(), None).unwrap();
+
+        let mut restored = Interpreter::new();
+        restored.add_breakpoint(0x80);
+        let copy = workspace.load("lab1", &mut restored).unwrap().unwrap();
+        assert_eq!(restored.cpu.x2, 7);
+        assert_eq!(restored.cpu.config.fill, FillPattern::Byte(0xcc));
+        assert_eq!(restored.breakpoints().collect::<Vec<_>>(), [0x40]);
+        assert_eq!(restored.watchpoints().collect::<Vec<_>>(), [(0x100, 4)]);
+        assert_eq!(fs::read_to_string(copy).unwrap(), "ADDI x1, zero, 4\n");
+
+        assert_eq!(workspace.load("empty", &mut restored).unwrap(), None);
+        assert_eq!(restored.breakpoints().count(), 0);
+        assert_eq!(restored.watchpoints().count(), 0);
+        assert!(workspace.load("missing", &mut restored).is_err());
+        assert!(workspace.save("../escape", &interpreter, None).is_err());
+        assert_eq!(workspace.sessions().unwrap(), vec!["empty", "lab1"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
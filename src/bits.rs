@@ -0,0 +1,104 @@
+//! Helpers for picking bit fields out of 32-bit values, as instruction
+//! encodings and CSRs are full of them.
+//!
+//! ```
+//! use brubeck::bits::{field, BitRange};
+//!
+//! assert_eq!(field(0xabcd, 15, 8), 0xab);
+//!
+//! let range = BitRange::parse("7:4").unwrap();
+//! assert_eq!(range.extract(0xabcd), 0xc);
+//! assert_eq!(range.mask(), 0xf0);
+//! ```
+
+use std::fmt::Display;
+
+/// Bits `high` down to `low` (inclusive) of `value`, shifted down to bit 0
+pub fn field(value: u32, high: u32, low: u32) -> u32 {
+    debug_assert!(low <= high && high < 32, "invalid bit range {high}:{low}");
+    let width = high - low + 1;
+    let mask = if width == 32 {
+        u32::MAX
+    } else {
+        (1 << width) - 1
+    };
+    (value >> low) & mask
+}
+
+/// Whether bit `index` of `value` is set
+pub fn bit(value: u32, index: u32) -> bool {
+    field(value, index, index) == 1
+}
+
+/// An inclusive range of bit positions, written `high:low` (eg: `11:7`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BitRange {
+    high: u32,
+    low: u32,
+}
+
+impl BitRange {
+    /// A range within a 32-bit value, or None if it's backwards or too wide
+    pub fn new(high: u32, low: u32) -> Option<Self> {
+        (low <= high && high < 32).then_some(Self { high, low })
+    }
+
+    /// Parses `high:low`
+    pub fn parse(input: &str) -> Option<Self> {
+        let (high, low) = input.split_once(':')?;
+        Self::new(high.trim().parse().ok()?, low.trim().parse().ok()?)
+    }
+
+    pub fn high(&self) -> u32 {
+        self.high
+    }
+
+    pub fn low(&self) -> u32 {
+        self.low
+    }
+
+    /// Number of bits in the range
+    pub fn width(&self) -> u32 {
+        self.high - self.low + 1
+    }
+
+    /// The bits of the range, in place
+    pub fn mask(&self) -> u32 {
+        field(u32::MAX, self.high, self.low) << self.low
+    }
+
+    /// The field from `value`, shifted down to bit 0
+    pub fn extract(&self, value: u32) -> u32 {
+        field(value, self.high, self.low)
+    }
+}
+
+impl Display for BitRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.high, self.low)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_and_ranges() {
+        assert_eq!(field(u32::MAX, 31, 0), u32::MAX);
+        assert_eq!(field(0x8000_0000, 31, 31), 1);
+        assert!(bit(0b100, 2));
+        assert!(!bit(0b100, 1));
+
+        let range = BitRange::parse("31:28").unwrap();
+        assert_eq!(range.width(), 4);
+        assert_eq!(range.mask(), 0xf000_0000);
+        assert_eq!(range.extract(0xdead_beef), 0xd);
+        assert_eq!(range.to_string(), "31:28");
+
+        assert_eq!(BitRange::parse("3:7"), None);
+        assert_eq!(BitRange::parse("32:0"), None);
+        assert_eq!(BitRange::parse("7"), None);
+        assert_eq!(BitRange::new(0, 0).unwrap().mask(), 1);
+    }
+}
@@ -0,0 +1,738 @@
+//! Board descriptions: how much memory a machine has, and which
+//! [devices](crate::devices) are mapped where.
+//!
+//! Boards are written in a small subset of TOML so a course setup can be
+//! shared as a single file:
+//!
+//! ```toml
+//! name = "classroom"
+//!
+//! [memory]
+//! size = "256K"
+//! base = 0x8000_0000
+//! fill = 0xCC           # or "random:42"; zero if not given
+//!
+//! [cpu]
+//! reset_vector = 0x8000_0000
+//! sp = "top"            # or an address; zero if not given
+//! gp = 0x8000_0800      # zero if not given
+//! ecall = "trap"        # or "host" (the default), or "error"
+//! misaligned = "trap"   # or "emulate" (the default), or "error"
+//! csr = "trap"          # or "error" (the default)
+//! isa = "rv32imac"      # rv32ima if not given
+//!
+//! [[device]]
+//! kind = "clint"
+//! base = 0x0200_0000
+//!
+//! [[device]]
+//! kind = "uart"
+//! base = 0x1000_0000
+//! irq = 10
+//! ```
+//!
+//! Memory starts at address zero unless a `base` is given, and the reset
+//! vector (the initial PC) defaults to the start of memory. `sp` and `gp`
+//! are the [StackPointer] and global pointer registers start with (and
+//! return to on a reset); `"top"` puts the stack at the end of memory, which
+//! code that doesn't set up its own stack needs. `ecall` picks the
+//! [EcallPolicy]: trap to the guest's handler, let the host handle system
+//! calls, or stop with an error. `misaligned` picks the [MisalignedPolicy]
+//! for loads and stores to addresses that aren't a multiple of their size.
+//! `csr` picks the [CsrPolicy] for CSRs that don't exist.
+//! `isa` picks the [Extensions]; C (compressed instructions) is off unless
+//! it's named, and is needed to run code built by gcc's default `rv32imac`
+//! (or `rv32imc`) target.
+//!
+//! Supported device kinds are `clint`, `plic`, `uart`, `button`, `keyboard`,
+//! `tohost`, and the toys `leds`, `switches`, `seven_segment`, and
+//! `framebuffer`. The `base` and `irq` keys are optional and default to each
+//! device's conventional values.
+//!
+//! ```
+//! use brubeck::board::Board;
+//!
+//! let board = Board::parse("[memory]\nsize = 1024\n[[device]]\nkind = \"clint\"").unwrap();
+//! let cpu = board.build().unwrap();
+//! assert_eq!(cpu.memory.len(), 1024);
+//! assert_eq!(cpu.devices.mappings().len(), 1);
+//! ```
+
+use std::fmt::Display;
+
+use crate::devices::{
+    self, Button, Clint, Framebuffer, Keyboard, Leds, Plic, SevenSegment, Switches, ToHost, Uart,
+};
+use crate::rv32_i::{
+    CpuConfig, CsrPolicy, EcallPolicy, Extensions, FillPattern, MisalignedPolicy, StackPointer, CPU,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Board {
+    pub name: String,
+    /// Memory size in bytes
+    pub memory_size: usize,
+    /// Address of the first byte of memory
+    pub memory_base: u32,
+    /// What memory holds before anything is written
+    pub memory_fill: FillPattern,
+    /// Initial PC; the start of memory if not given
+    pub reset_vector: Option<u32>,
+    /// Initial `sp`
+    pub stack_pointer: StackPointer,
+    /// Initial `gp`; zero if not given
+    pub global_pointer: Option<u32>,
+    /// What `ECALL` does
+    pub ecall: EcallPolicy,
+    /// What misaligned loads and stores do
+    pub misaligned: MisalignedPolicy,
+    /// What accessing a CSR that doesn't exist does
+    pub csr: CsrPolicy,
+    /// Enabled ISA extensions
+    pub extensions: Extensions,
+    pub devices: Vec<DeviceSpec>,
+}
+
+/// A device on a [Board]: what it is, where it's mapped, and which interrupt
+/// source it drives (if any).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSpec {
+    pub kind: DeviceKind,
+    pub base: u32,
+    pub irq: Option<u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DeviceKind {
+    Clint,
+    Plic,
+    Uart,
+    Button,
+    Keyboard,
+    ToHost,
+    Leds,
+    Switches,
+    SevenSegment,
+    Framebuffer,
+}
+
+impl DeviceKind {
+    /// Parses a device kind by name (eg: `uart`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "clint" => Some(Self::Clint),
+            "plic" => Some(Self::Plic),
+            "uart" => Some(Self::Uart),
+            "button" => Some(Self::Button),
+            "keyboard" => Some(Self::Keyboard),
+            "tohost" => Some(Self::ToHost),
+            "leds" => Some(Self::Leds),
+            "switches" => Some(Self::Switches),
+            "seven_segment" => Some(Self::SevenSegment),
+            "framebuffer" => Some(Self::Framebuffer),
+            _ => None,
+        }
+    }
+
+    /// Conventional base address for the device
+    pub fn default_base(&self) -> u32 {
+        match self {
+            Self::Clint => Clint::BASE,
+            Self::Plic => Plic::BASE,
+            Self::Uart => Uart::BASE,
+            Self::Button => Button::BASE,
+            Self::Keyboard => Keyboard::BASE,
+            Self::ToHost => ToHost::BASE,
+            Self::Leds => Leds::BASE,
+            Self::Switches => Switches::BASE,
+            Self::SevenSegment => SevenSegment::BASE,
+            Self::Framebuffer => Framebuffer::BASE,
+        }
+    }
+
+    /// Conventional interrupt source for the device, if it raises interrupts
+    /// through the PLIC
+    pub fn default_irq(&self) -> Option<u32> {
+        match self {
+            Self::Uart => Some(Uart::IRQ),
+            Self::Button => Some(Button::IRQ),
+            Self::Keyboard => Some(Keyboard::IRQ),
+            _ => None,
+        }
+    }
+}
+
+impl DeviceSpec {
+    /// A device at its conventional address and interrupt source
+    pub fn new(kind: DeviceKind) -> Self {
+        Self {
+            kind,
+            base: kind.default_base(),
+            irq: kind.default_irq(),
+        }
+    }
+}
+
+impl Default for Board {
+    /// One mebibyte of memory plus the standard devices, laid out like QEMU's
+    /// `virt` board.
+    fn default() -> Self {
+        Self {
+            name: "default".to_owned(),
+            memory_size: 2usize.pow(20),
+            memory_base: 0,
+            memory_fill: FillPattern::default(),
+            reset_vector: None,
+            stack_pointer: StackPointer::default(),
+            global_pointer: None,
+            ecall: EcallPolicy::default(),
+            misaligned: MisalignedPolicy::default(),
+            csr: CsrPolicy::default(),
+            extensions: Extensions::default(),
+            devices: vec![
+                DeviceSpec::new(DeviceKind::Clint),
+                DeviceSpec::new(DeviceKind::Plic),
+                DeviceSpec::new(DeviceKind::Uart),
+                DeviceSpec::new(DeviceKind::Button),
+                DeviceSpec::new(DeviceKind::Keyboard),
+                DeviceSpec::new(DeviceKind::ToHost),
+                DeviceSpec::new(DeviceKind::Leds),
+                DeviceSpec::new(DeviceKind::Switches),
+                DeviceSpec::new(DeviceKind::SevenSegment),
+                DeviceSpec::new(DeviceKind::Framebuffer),
+            ],
+        }
+    }
+}
+
+impl Board {
+    /// Parses a board description
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let mut board = Self::default();
+
+        // kind, base, and irq of each [[device]], filled in as keys are read
+        let mut devices: Vec<(Option<DeviceKind>, Option<u32>, Option<u32>)> = vec![];
+
+        let mut section = Section::Root;
+
+        // the last line that set up memory, for reporting errors about it
+        let mut memory_line = 0;
+
+        for (index, raw_line) in input.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(raw_line).trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line == "[[device]]" {
+                section = Section::Device(devices.len());
+                devices.push((None, None, None));
+                continue;
+            }
+
+            if line == "[memory]" {
+                section = Section::Memory;
+                continue;
+            }
+
+            if line == "[cpu]" {
+                section = Section::Cpu;
+                continue;
+            }
+
+            if line.starts_with('[') {
+                return Err(Error::Syntax(line_number, format!("unknown table {line}")));
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(Error::Syntax(
+                    line_number,
+                    "expected key = value".to_owned(),
+                ));
+            };
+            let key = key.trim();
+            let value = Value::parse(value.trim())
+                .ok_or_else(|| Error::Syntax(line_number, format!("invalid value for {key}")))?;
+
+            match (section, key) {
+                (Section::Root, "name") => board.name = value.string(line_number)?,
+                (Section::Memory, "size") => {
+                    board.memory_size = value.size(line_number)?;
+                    memory_line = line_number;
+                }
+                (Section::Memory, "base") => {
+                    board.memory_base = value.address(line_number)?;
+                    memory_line = line_number;
+                }
+                (Section::Memory, "fill") => board.memory_fill = value.fill(line_number)?,
+                (Section::Cpu, "reset_vector") => {
+                    board.reset_vector = Some(value.address(line_number)?)
+                }
+                (Section::Cpu, "sp") => board.stack_pointer = value.stack_pointer(line_number)?,
+                (Section::Cpu, "gp") => board.global_pointer = Some(value.address(line_number)?),
+                (Section::Cpu, "ecall") => {
+                    let name = value.string(line_number)?;
+                    board.ecall = EcallPolicy::from_name(&name).ok_or_else(|| {
+                        Error::Syntax(line_number, format!("unknown ecall policy {name}"))
+                    })?;
+                }
+                (Section::Cpu, "misaligned") => {
+                    let name = value.string(line_number)?;
+                    board.misaligned = MisalignedPolicy::from_name(&name).ok_or_else(|| {
+                        Error::Syntax(line_number, format!("unknown misaligned policy {name}"))
+                    })?;
+                }
+                (Section::Cpu, "csr") => {
+                    let name = value.string(line_number)?;
+                    board.csr = CsrPolicy::from_name(&name).ok_or_else(|| {
+                        Error::Syntax(line_number, format!("unknown csr policy {name}"))
+                    })?;
+                }
+                (Section::Cpu, "isa") => {
+                    let name = value.string(line_number)?;
+                    board.extensions = Extensions::from_isa(&name).ok_or_else(|| {
+                        Error::Syntax(line_number, format!("unsupported isa {name}"))
+                    })?;
+                }
+                (Section::Device(i), "kind") => {
+                    let name = value.string(line_number)?;
+                    let kind = DeviceKind::from_name(&name)
+                        .ok_or(Error::UnknownDevice(line_number, name))?;
+                    devices[i].0 = Some(kind);
+                }
+                (Section::Device(i), "base") => devices[i].1 = Some(value.address(line_number)?),
+                (Section::Device(i), "irq") => devices[i].2 = Some(value.address(line_number)?),
+                _ => {
+                    return Err(Error::Syntax(line_number, format!("unknown key {key}")));
+                }
+            }
+        }
+
+        if board.memory_base as u64 + board.memory_size as u64 > 1 << 32 {
+            return Err(Error::Unsupported(
+                memory_line,
+                "memory must end below 4 GiB",
+            ));
+        }
+
+        board.devices = devices
+            .into_iter()
+            .map(|(kind, base, irq)| {
+                let kind = kind.ok_or(Error::MissingKind)?;
+                Ok(DeviceSpec {
+                    kind,
+                    base: base.unwrap_or(kind.default_base()),
+                    irq: irq.or(kind.default_irq()),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(board)
+    }
+
+    /// The memory layout and initial state for the board's CPU
+    pub fn cpu_config(&self) -> CpuConfig {
+        CpuConfig {
+            memory_size: self.memory_size,
+            memory_base: self.memory_base,
+            reset_vector: self.reset_vector.unwrap_or(self.memory_base),
+            stack_pointer: self.stack_pointer,
+            global_pointer: self.global_pointer,
+            fill: self.memory_fill,
+            ecall: self.ecall,
+            misaligned: self.misaligned,
+            csr: self.csr,
+            extensions: self.extensions,
+            ..CpuConfig::default()
+        }
+    }
+
+    /// Reads and parses a board file
+    #[cfg(feature = "host")]
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| Error::Io(format!("{path}: {e}")))?;
+        Self::parse(&contents)
+    }
+
+    /// Builds a [CPU] with the board's memory and devices attached
+    pub fn build(&self) -> Result<CPU, Error> {
+        self.build_with(self.cpu_config())
+    }
+
+    /// Builds a [CPU] set up by `config` (in place of the board's own memory
+    /// and CPU settings), with the board's devices attached
+    pub fn build_with(&self, config: CpuConfig) -> Result<CPU, Error> {
+        if config.memory_base as u64 + config.memory_size as u64 > 1 << 32 {
+            return Err(Error::MemoryPastEnd);
+        }
+        let mut cpu = CPU::with_config(config);
+
+        for spec in &self.devices {
+            let bus = &mut cpu.devices;
+            let result = match (spec.kind, spec.irq) {
+                (DeviceKind::Clint, _) => bus.attach(spec.base, Clint::default()),
+                (DeviceKind::Plic, _) => bus.attach(spec.base, Plic::default()),
+                (DeviceKind::Uart, Some(irq)) => {
+                    bus.attach_with_irq(spec.base, irq, Uart::default())
+                }
+                (DeviceKind::Uart, None) => bus.attach(spec.base, Uart::default()),
+                (DeviceKind::Button, Some(irq)) => {
+                    bus.attach_with_irq(spec.base, irq, Button::default())
+                }
+                (DeviceKind::Button, None) => bus.attach(spec.base, Button::default()),
+                (DeviceKind::Keyboard, Some(irq)) => {
+                    bus.attach_with_irq(spec.base, irq, Keyboard::default())
+                }
+                (DeviceKind::Keyboard, None) => bus.attach(spec.base, Keyboard::default()),
+                (DeviceKind::ToHost, _) => bus.attach(spec.base, ToHost::default()),
+                (DeviceKind::Leds, _) => bus.attach(spec.base, Leds::default()),
+                (DeviceKind::Switches, _) => bus.attach(spec.base, Switches::default()),
+                (DeviceKind::SevenSegment, _) => bus.attach(spec.base, SevenSegment::default()),
+                (DeviceKind::Framebuffer, _) => bus.attach(spec.base, Framebuffer::default()),
+            };
+
+            result.map_err(|e| Error::Device(spec.kind, e))?;
+        }
+
+        Ok(cpu)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Section {
+    Root,
+    Memory,
+    Cpu,
+    Device(usize),
+}
+
+/// The handful of TOML value types a board file uses
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(u64),
+    String(String),
+}
+
+impl Value {
+    fn parse(input: &str) -> Option<Self> {
+        if let Some(quoted) = input.strip_prefix('"') {
+            return quoted
+                .strip_suffix('"')
+                .map(|s| Value::String(s.to_owned()));
+        }
+
+        let digits = input.replace('_', "");
+        let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16)
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            u64::from_str_radix(bin, 2)
+        } else {
+            digits.parse::<u64>()
+        };
+
+        parsed.ok().map(Value::Integer)
+    }
+
+    fn string(self, line: usize) -> Result<String, Error> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(Error::Syntax(line, "expected a string".to_owned())),
+        }
+    }
+
+    fn integer(self, line: usize) -> Result<u64, Error> {
+        match self {
+            Value::Integer(i) => Ok(i),
+            _ => Err(Error::Syntax(line, "expected an integer".to_owned())),
+        }
+    }
+
+    fn address(self, line: usize) -> Result<u32, Error> {
+        let value = self.integer(line)?;
+        u32::try_from(value).map_err(|_| Error::Syntax(line, format!("{value} is too large")))
+    }
+
+    /// A [FillPattern], either a byte or a string like `"random:42"`
+    fn fill(self, line: usize) -> Result<FillPattern, Error> {
+        let pattern = match self {
+            Value::Integer(i) => u8::try_from(i).ok().map(FillPattern::Byte),
+            Value::String(s) => FillPattern::parse(&s),
+        };
+        pattern.ok_or_else(|| {
+            Error::Syntax(
+                line,
+                "expected a byte, \"zero\", or \"random:<seed>\"".to_owned(),
+            )
+        })
+    }
+
+    /// A [StackPointer], either an address or `"top"` or `"zero"`
+    fn stack_pointer(self, line: usize) -> Result<StackPointer, Error> {
+        match self {
+            Value::Integer(_) => self.address(line).map(StackPointer::At),
+            Value::String(s) => StackPointer::parse(&s).ok_or_else(|| {
+                Error::Syntax(line, "expected an address, \"top\", or \"zero\"".to_owned())
+            }),
+        }
+    }
+
+    /// A byte count, either as an integer or a string with a `K` or `M` suffix
+    fn size(self, line: usize) -> Result<usize, Error> {
+        let invalid = || Error::Syntax(line, "expected a size like 1024 or \"64K\"".to_owned());
+
+        match self {
+            Value::Integer(i) => usize::try_from(i).map_err(|_| invalid()),
+            Value::String(s) => parse_size(&s).ok_or_else(invalid),
+        }
+    }
+}
+
+/// A byte count like `1024`, `"64K"`, or `"1M"`, as memory sizes are written
+///
+/// ```
+/// use brubeck::board::parse_size;
+///
+/// assert_eq!(parse_size("64K"), Some(65536));
+/// assert_eq!(parse_size("1m"), Some(1 << 20));
+/// assert_eq!(parse_size("lots"), None);
+/// ```
+pub fn parse_size(text: &str) -> Option<usize> {
+    let text = text.trim().to_uppercase();
+    let (number, multiplier) = if let Some(n) = text.strip_suffix('K') {
+        (n, 1024)
+    } else if let Some(n) = text.strip_suffix('M') {
+        (n, 1024 * 1024)
+    } else {
+        (text.as_str(), 1)
+    };
+    let bytes = number.trim().replace('_', "").parse::<u64>().ok()?;
+    usize::try_from(bytes.checked_mul(multiplier)?).ok()
+}
+
+fn strip_comment(line: &str) -> &str {
+    // board files never need a '#' inside a string, so this can be simple
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Io(String),
+    Syntax(usize, String),
+    Unsupported(usize, &'static str),
+    UnknownDevice(usize, String),
+    MissingKind,
+    /// Memory set up after parsing (eg: from the command line) runs past
+    /// 4 GiB
+    MemoryPastEnd,
+    Device(DeviceKind, devices::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(s) => write!(f, "Unable to read board file: {s}"),
+            Self::Syntax(line, s) => write!(f, "Board file line {line}: {s}"),
+            Self::Unsupported(line, s) => write!(f, "Board file line {line}: {s}"),
+            Self::UnknownDevice(line, s) => {
+                write!(f, "Board file line {line}: unknown device kind '{s}'")
+            }
+            Self::MissingKind => write!(f, "Every [[device]] needs a kind"),
+            Self::MemoryPastEnd => write!(f, "Memory must end below 4 GiB"),
+            Self::Device(kind, e) => write!(f, "Unable to attach {kind:?}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::Register;
+
+    const CLASSROOM: &str = r#"
+        # a small board for the intro course
+        name = "classroom"
+
+        [memory]
+        size = "64K"
+
+        [[device]]
+        kind = "clint"
+
+        [[device]]
+        kind = "uart"
+        base = 0x2000_0000 # moved out of the way
+        irq = 3
+    "#;
+
+    #[test]
+    fn parse_board() {
+        let board = Board::parse(CLASSROOM).unwrap();
+
+        assert_eq!(board.name, "classroom");
+        assert_eq!(board.memory_size, 64 * 1024);
+        assert_eq!(
+            board.devices,
+            vec![
+                DeviceSpec::new(DeviceKind::Clint),
+                DeviceSpec {
+                    kind: DeviceKind::Uart,
+                    base: 0x2000_0000,
+                    irq: Some(3),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn build_board() {
+        let cpu = Board::parse(CLASSROOM).unwrap().build().unwrap();
+        assert_eq!(cpu.memory.len(), 64 * 1024);
+        assert!(cpu.devices.contains(Clint::BASE));
+        assert!(cpu.devices.contains(0x2000_0000));
+        assert!(!cpu.devices.contains(Uart::BASE));
+    }
+
+    #[test]
+    fn memory_base_and_reset_vector() {
+        let board = Board::parse("[memory]\nsize = 256\nbase = 0x8000_0000").unwrap();
+        let cpu = board.build().unwrap();
+        assert_eq!(cpu.pc, 0x8000_0000);
+        assert_eq!(cpu.address_map.translate(0x8000_0010, 4), Some(16..20));
+
+        let board = Board::parse("[memory]\nbase = 0x8000_0000\n[cpu]\nreset_vector = 0x8000_0100");
+        assert_eq!(board.unwrap().build().unwrap().pc, 0x8000_0100);
+
+        let board = Board::parse("[memory]\nsize = 16\nfill = 0xcc").unwrap();
+        assert_eq!(board.build().unwrap().memory.to_vec(), vec![0xcc; 16]);
+        let board = Board::parse("[memory]\nfill = \"random:3\"").unwrap();
+        assert_eq!(board.memory_fill, FillPattern::Random { seed: 3 });
+    }
+
+    #[test]
+    fn stack_and_global_pointers() {
+        let board =
+            "[memory]\nsize = \"64K\"\nbase = 0x8000_0000\n[cpu]\nsp = \"top\"\ngp = 0x8000_0800";
+        let mut cpu = Board::parse(board).unwrap().build().unwrap();
+        assert_eq!(cpu.get_register(Register::X2), 0x8001_0000);
+        assert_eq!(cpu.get_register(Register::X3), 0x8000_0800);
+
+        // they come back on a reset
+        cpu.set_register(Register::X2, 4);
+        cpu.reset();
+        assert_eq!(cpu.get_register(Register::X2), 0x8001_0000);
+
+        let board = Board::parse("[cpu]\nsp = 0x1000").unwrap();
+        assert_eq!(board.stack_pointer, StackPointer::At(0x1000));
+        assert_eq!(
+            Board::default().build().unwrap().get_register(Register::X2),
+            0
+        );
+        assert!(matches!(
+            Board::parse("[cpu]\nsp = \"bottom\""),
+            Err(Error::Syntax(2, _))
+        ));
+    }
+
+    #[test]
+    fn ecall_policy() {
+        let board = Board::parse("[cpu]\necall = \"trap\"").unwrap();
+        assert_eq!(board.ecall, EcallPolicy::TrapIntoGuestHandler);
+        assert_eq!(board.build().unwrap().config.ecall, board.ecall);
+
+        assert_eq!(Board::default().ecall, EcallPolicy::HostHandle);
+        assert!(matches!(
+            Board::parse("[cpu]\necall = \"ignore\""),
+            Err(Error::Syntax(2, _))
+        ));
+    }
+
+    #[test]
+    fn misaligned_policy() {
+        let board = Board::parse("[cpu]\nmisaligned = \"trap\"").unwrap();
+        assert_eq!(
+            board.build().unwrap().config.misaligned,
+            MisalignedPolicy::Trap
+        );
+
+        assert_eq!(Board::default().misaligned, MisalignedPolicy::Emulate);
+        assert!(matches!(
+            Board::parse("[cpu]\nmisaligned = \"ignore\""),
+            Err(Error::Syntax(2, _))
+        ));
+
+        let board = Board::parse("[cpu]\ncsr = \"trap\"").unwrap();
+        assert_eq!(board.build().unwrap().config.csr, CsrPolicy::Trap);
+        assert_eq!(Board::default().csr, CsrPolicy::Error);
+    }
+
+    #[test]
+    fn isa() {
+        let board = Board::parse("[cpu]\nisa = \"rv32imac\"").unwrap();
+        assert!(board.build().unwrap().config.extensions.c);
+
+        assert!(!Board::default().extensions.c);
+        assert!(matches!(
+            Board::parse("[cpu]\nisa = \"rv64gc\""),
+            Err(Error::Syntax(2, _))
+        ));
+    }
+
+    #[test]
+    fn default_board() {
+        let cpu = Board::default().build().unwrap();
+        assert_eq!(cpu.memory.len(), 2usize.pow(20));
+        assert_eq!(cpu.devices.mappings().len(), 10);
+
+        // tohost is above memory, even when there's more of it
+        let board = Board::parse("[memory]\nsize = \"64M\"\n[[device]]\nkind = \"tohost\"");
+        let cpu = board.unwrap().build().unwrap();
+        assert!((0..64 << 20)
+            .step_by(4096)
+            .all(|a| !cpu.devices.contains(a)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Board::parse("[[device]]\nkind = \"gpu\""),
+            Err(Error::UnknownDevice(2, "gpu".to_owned()))
+        );
+        assert_eq!(
+            Board::parse("[[device]]\nbase = 0"),
+            Err(Error::MissingKind)
+        );
+        assert!(matches!(
+            Board::parse("[memory]\nsize = lots"),
+            Err(Error::Syntax(2, _))
+        ));
+        // too big to count in bytes
+        assert!(matches!(
+            Board::parse("[memory]\nsize = \"99999999999999999M\""),
+            Err(Error::Syntax(2, _))
+        ));
+        assert!(matches!(
+            Board::parse("[memory]\nbase = 0xffff_0000\nsize = \"1M\""),
+            Err(Error::Unsupported(3, _))
+        ));
+        let board = Board {
+            memory_base: 0xffff_0000,
+            ..Board::default()
+        };
+        assert!(matches!(board.build(), Err(Error::MemoryPastEnd)));
+
+        let overlapping = "[[device]]\nkind = \"clint\"\n[[device]]\nkind = \"clint\"";
+        let board = Board::parse(overlapping).unwrap();
+        assert!(matches!(
+            board.build(),
+            Err(Error::Device(DeviceKind::Clint, _))
+        ));
+    }
+}
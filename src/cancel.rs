@@ -0,0 +1,47 @@
+//! Stopping a long run from outside, eg: when Ctrl-C is pressed.
+//!
+//! A [CancelToken] is a shared flag. Loops that can run for a while (like
+//! [Interpreter::execute_all](crate::Interpreter::execute_all)) check it
+//! between instructions and stop cleanly, returning whatever they finished,
+//! so the machine is left in a consistent state. Clones share the flag, so
+//! one can be handed to a signal handler or another thread.
+//!
+//! ```
+//! use brubeck::cancel::CancelToken;
+//!
+//! let token = CancelToken::default();
+//! let handle = token.clone();
+//!
+//! handle.cancel();
+//! assert!(token.take());
+//! // taking the cancellation clears it
+//! assert!(!token.take());
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Asks whatever is running to stop. Only sets a flag, so it's safe to
+    /// call from a signal handler.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Whether a cancellation was asked for, clearing it
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+
+    /// Forgets any cancellation, eg: one that arrived between runs
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,228 @@
+//! Named bookmarks of the machine state.
+//!
+//! [Interpreter::checkpoint](crate::Interpreter::checkpoint) saves the
+//! registers (including the PC), CSRs, counters, exit code, and memory under
+//! a name, and
+//! [Interpreter::restore_checkpoint](crate::Interpreter::restore_checkpoint)
+//! goes back to it later, however much has run in between. Unlike the undo
+//! history, checkpoints don't depend on the CPU only being changed by
+//! instructions. Device state isn't included.
+//!
+//! Memory is kept in pages of [PAGE] bytes. A page that hasn't been written
+//! since the last copy of memory is shared with that copy rather than copied
+//! again (see [pages](crate::rv32_i::pages)), so saving a checkpoint costs the
+//! pages touched since, not the size of memory, and a series of them of a
+//! program that only touches a little memory costs little more than the
+//! first. Restoring one copies back only the pages that have changed.
+//!
+//! ```
+//! use brubeck::Interpreter;
+//!
+//! let mut i = Interpreter::new();
+//! i.interpret("ADDI x1, zero, 5").unwrap();
+//! i.checkpoint("five");
+//!
+//! i.interpret("SW zero, x1, 0").unwrap();
+//! i.interpret("ADDI x1, zero, 6").unwrap();
+//!
+//! i.restore_checkpoint("five").unwrap();
+//! assert_eq!(i.cpu.x1, 5);
+//! assert_eq!(i.cpu.memory[0], 0);
+//! ```
+
+use std::fmt::Display;
+
+use crate::history::{self, Registers};
+use crate::rv32_i::{pages, Counters, Pages, Privilege, CPU};
+
+/// Bytes in each page of a checkpoint's memory
+pub const PAGE: usize = pages::PAGE;
+
+/// The machine state saved under a name
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub name: String,
+    registers: Registers,
+    /// Every CSR with a nonzero value, by address
+    csrs: Vec<(u16, u32)>,
+    privilege: Privilege,
+    counters: Counters,
+    exit_code: Option<u32>,
+    pages: Pages,
+}
+
+impl Checkpoint {
+    /// Copies the state of a CPU
+    fn capture(name: &str, cpu: &CPU) -> Self {
+        let csrs = cpu
+            .csrs
+            .iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(def, value)| (def.address as u16, value))
+            .collect();
+
+        Self {
+            name: name.to_owned(),
+            registers: history::registers(cpu),
+            csrs,
+            privilege: cpu.privilege,
+            counters: cpu.counters,
+            exit_code: cpu.exit_code,
+            pages: cpu.memory.pages(),
+        }
+    }
+
+    /// The PC when the checkpoint was saved
+    pub fn pc(&self) -> u32 {
+        self.registers[32] as u32
+    }
+
+    /// Bytes of memory saved
+    pub fn memory_size(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Puts the CPU back in this state. Its memory must be the same size.
+    fn restore(&self, cpu: &mut CPU) -> Result<(), CheckpointError> {
+        if cpu.memory.len() != self.memory_size() {
+            return Err(CheckpointError::MemorySize {
+                expected: cpu.memory.len(),
+                found: self.memory_size(),
+            });
+        }
+
+        history::set_registers(cpu, &self.registers);
+        // CSRs the CPU doesn't have are left out
+        cpu.csrs.clear();
+        for (address, value) in &self.csrs {
+            cpu.csrs.set(u32::from(*address), *value);
+        }
+        cpu.memory.restore(&self.pages);
+        cpu.privilege = self.privilege;
+        cpu.counters = self.counters;
+        cpu.exit_code = self.exit_code;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointError {
+    /// There's no checkpoint with the name
+    NotFound(String),
+    /// Memory is a different size than when the checkpoint was saved
+    MemorySize { expected: usize, found: usize },
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "No checkpoint named {name}"),
+            Self::MemorySize { expected, found } => write!(
+                f,
+                "Checkpoint has {found} bytes of memory, but the CPU has {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// Checkpoints by name, in the order they were saved
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    saved: Vec<Checkpoint>,
+}
+
+impl Checkpoints {
+    /// Saves the CPU's state under a name, replacing any checkpoint already
+    /// saved under it
+    pub fn save(&mut self, name: &str, cpu: &CPU) {
+        let checkpoint = Checkpoint::capture(name, cpu);
+        self.remove(name);
+        self.saved.push(checkpoint);
+    }
+
+    /// Puts the CPU back in the state saved under a name
+    pub fn restore(&self, name: &str, cpu: &mut CPU) -> Result<(), CheckpointError> {
+        match self.get(name) {
+            Some(checkpoint) => checkpoint.restore(cpu),
+            None => Err(CheckpointError::NotFound(name.to_owned())),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Checkpoint> {
+        self.saved.iter().find(|c| c.name == name)
+    }
+
+    /// Forgets a checkpoint, returning whether there was one
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.saved.len();
+        self.saved.retain(|c| c.name != name);
+        self.saved.len() != count
+    }
+
+    /// Every checkpoint, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.saved.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::csr;
+
+    #[test]
+    fn save_and_restore() {
+        let mut cpu = CPU::new(3 * PAGE + 100);
+        let mut checkpoints = Checkpoints::default();
+        cpu.x1 = 1;
+        cpu.pc = 8;
+        cpu.write_memory(PAGE as u32 + 1, &[1]).unwrap();
+        cpu.write_csr(csr::MSCRATCH, 7).unwrap();
+        checkpoints.save("first", &cpu);
+
+        cpu.x1 = 2;
+        cpu.write_memory(3 * PAGE as u32 + 99, &[2]).unwrap();
+        checkpoints.save("second", &cpu);
+
+        // unchanged pages are shared
+        let first = checkpoints.get("first").unwrap();
+        let second = checkpoints.get("second").unwrap();
+        assert_eq!(second.pages.shared_with(&first.pages), 3);
+        assert_eq!(second.memory_size(), 3 * PAGE + 100);
+
+        cpu.reset();
+        checkpoints.restore("first", &mut cpu).unwrap();
+        assert_eq!((cpu.x1, cpu.pc), (1, 8));
+        assert_eq!(cpu.memory[PAGE + 1], 1);
+        assert_eq!(cpu.memory[3 * PAGE + 99], 0);
+        assert_eq!(cpu.read_csr(csr::MSCRATCH).unwrap(), 7);
+
+        checkpoints.restore("second", &mut cpu).unwrap();
+        assert_eq!(cpu.x1, 2);
+        assert_eq!(cpu.memory[3 * PAGE + 99], 2);
+
+        // saving under a name again replaces it
+        cpu.x1 = 3;
+        checkpoints.save("first", &cpu);
+        let names: Vec<_> = checkpoints.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["second", "first"]);
+        assert_eq!(checkpoints.get("first").unwrap().registers[1], 3);
+
+        assert_eq!(
+            checkpoints.restore("third", &mut cpu),
+            Err(CheckpointError::NotFound("third".to_owned()))
+        );
+        assert_eq!(
+            checkpoints.restore("first", &mut CPU::new(16)),
+            Err(CheckpointError::MemorySize {
+                expected: 16,
+                found: 3 * PAGE + 100
+            })
+        );
+        assert!(checkpoints.remove("first"));
+        assert!(!checkpoints.remove("first"));
+    }
+}
@@ -0,0 +1,180 @@
+//! Completing a partly typed word, for line editors: instruction mnemonics
+//! where an instruction goes, then registers or CSRs for its operands (going
+//! by the [metadata](crate::metadata) for what each operand can be).
+//!
+//! Lines starting with `/` are commands, which the caller knows about; their
+//! names are passed in.
+//!
+//! ```
+//! use brubeck::completion;
+//!
+//! let completion = completion::complete("addi a0, z", &[]);
+//! assert_eq!(completion.start, 9);
+//! assert_eq!(completion.candidates, ["zero"]);
+//!
+//! let completion = completion::complete("/hel", &["/help", "/hex"]);
+//! assert_eq!(completion.candidates, ["/help"]);
+//! ```
+
+use crate::interpreter;
+use crate::metadata::{self, OperandKind};
+use crate::rv32_i::{csr, Register};
+
+/// What the word being typed could be
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Completion {
+    /// Where the word starts in the line, in bytes
+    pub start: usize,
+    /// The word so far
+    pub word: String,
+    /// Everything that starts with it, in the same case as it's typed
+    pub candidates: Vec<String>,
+    /// Whether the word is the first of a statement or command, which is
+    /// followed by a space once it's complete
+    pub first: bool,
+}
+
+impl Completion {
+    /// The longest text that every candidate starts with (at least the word
+    /// itself, if there are any candidates)
+    pub fn common_prefix(&self) -> &str {
+        let Some(first) = self.candidates.first() else {
+            return "";
+        };
+        let mut length = first.len();
+        for candidate in &self.candidates[1..] {
+            length = first
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a.eq_ignore_ascii_case(b))
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8())
+                .min(length);
+        }
+        &first[..length]
+    }
+}
+
+/// Completes the word at the end of `line`, given the names of the commands
+/// (eg: `/help`)
+pub fn complete(line: &str, commands: &[&str]) -> Completion {
+    let start = line
+        .rfind(|c: char| c.is_whitespace() || ",;[]()+*".contains(c))
+        .map_or(0, |i| i + 1);
+    let word = &line[start..];
+
+    // the words before this one, in this statement
+    let statement = match line.starts_with('/') {
+        true => &line[..start],
+        false => line[..start].rsplit(';').next().unwrap_or_default(),
+    };
+    let before: Vec<&str> = statement
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let names: Vec<&str> = match (before.first(), line.starts_with('/')) {
+        (None, true) => commands.to_vec(),
+        (None, false) => instructions().collect(),
+        (Some(&"/help"), true) => instructions().collect(),
+        (Some(&"/csr"), true) => csrs().collect(),
+        (Some(_), true) => registers().chain(csrs()).collect(),
+        (Some(mnemonic), false) => {
+            let kind = metadata::lookup(mnemonic)
+                .and_then(|info| info.operands().get(before.len() - 1).map(|o| o.kind));
+            match kind {
+                Some(OperandKind::Register) => registers().collect(),
+                Some(OperandKind::Csr) => csrs().collect(),
+                Some(OperandKind::Immediate) => vec![],
+                // a pseudo-instruction, or too many operands
+                None => registers().chain(csrs()).collect(),
+            }
+        }
+    };
+
+    // in upper case only if that's how it's being typed
+    let upper = word.chars().any(|c| c.is_ascii_uppercase());
+    let mut candidates: Vec<String> = vec![];
+    for name in names {
+        let matches = name.len() >= word.len()
+            && name.is_char_boundary(word.len())
+            && name[..word.len()].eq_ignore_ascii_case(word);
+        let name = match (name.starts_with('/'), upper) {
+            (true, _) => name.to_owned(),
+            (false, true) => name.to_uppercase(),
+            (false, false) => name.to_lowercase(),
+        };
+        if matches && !candidates.contains(&name) {
+            candidates.push(name);
+        }
+    }
+
+    Completion {
+        start,
+        word: word.to_owned(),
+        candidates,
+        first: before.is_empty(),
+    }
+}
+
+/// Every mnemonic, real or pseudo
+fn instructions() -> impl Iterator<Item = &'static str> {
+    metadata::INSTRUCTIONS
+        .iter()
+        .map(|info| info.name)
+        .chain(interpreter::pseudo_instructions())
+}
+
+/// `x0` to `x31`, then their ABI names
+fn registers() -> impl Iterator<Item = &'static str> {
+    let general = &Register::ALL[..32];
+    general
+        .iter()
+        .map(|r| r.name())
+        .chain(general.iter().filter_map(|r| r.abi().map(|abi| abi.name())))
+}
+
+fn csrs() -> impl Iterator<Item = &'static str> {
+    csr::STANDARD.iter().map(|def| def.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(line: &str) -> Vec<String> {
+        complete(line, &["/help", "/hex", "/regs"]).candidates
+    }
+
+    #[test]
+    fn by_position() {
+        // mnemonics first, in the case they're typed
+        assert_eq!(candidates("sltiu"), ["sltiu"]);
+        assert_eq!(candidates("SLTI"), ["SLTI", "SLTIU"]);
+        assert_eq!(candidates("rdcycle"), ["rdcycle", "rdcycleh"]);
+        assert_eq!(
+            candidates("ADDI a0, a0, 1; sra"),
+            ["sra", "srai", "sraiw", "sraw"]
+        );
+
+        // then what each operand can be
+        assert_eq!(candidates("add x1, x3"), ["x3", "x30", "x31"]);
+        assert_eq!(candidates("add x1 s1"), ["s1", "s10", "s11"]);
+        assert_eq!(candidates("csrrw a0, mscr"), ["mscratch"]);
+        assert!(candidates("addi a0, a0, 1").is_empty());
+        assert_eq!(candidates("csrr a0, mep"), ["mepc"]);
+
+        // commands, and their arguments
+        assert_eq!(candidates("/h"), ["/help", "/hex"]);
+        assert_eq!(candidates("/help jal"), ["jal", "jalr"]);
+        assert_eq!(candidates("/csr mstat"), ["mstatus"]);
+
+        let completion = complete("sw sp, a", &[]);
+        assert_eq!((completion.start, completion.first), (7, false));
+        assert_eq!(completion.word, "a");
+        assert_eq!(completion.common_prefix(), "a");
+        assert!(complete("ad", &[]).first);
+        assert_eq!(complete("ad", &[]).common_prefix(), "add");
+        assert_eq!(complete("zzz", &[]).common_prefix(), "");
+    }
+}
@@ -0,0 +1,68 @@
+//! A push button, the simplest possible interrupt source.
+//!
+//! | Offset | Register | Description                                        |
+//! |--------|----------|----------------------------------------------------|
+//! | `0x0`  | `state`  | bit 0: button is held down                         |
+//! | `0x4`  | `event`  | bit 0: button was pressed; write anything to clear |
+//!
+//! The interrupt line is raised while an `event` is waiting to be cleared.
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Button {
+    pub pressed: bool,
+    pub event: bool,
+}
+
+impl Button {
+    /// Conventional base address of the button
+    pub const BASE: u32 = 0x1000_1000;
+
+    /// Conventional interrupt source number of the button
+    pub const IRQ: u32 = 1;
+
+    pub const STATE: u32 = 0x0;
+    pub const EVENT: u32 = 0x4;
+
+    /// Pushes the button down, latching an event
+    pub fn press(&mut self) {
+        self.pressed = true;
+        self.event = true;
+    }
+
+    /// Lets the button back up
+    pub fn release(&mut self) {
+        self.pressed = false;
+    }
+}
+
+impl MmioDevice for Button {
+    fn name(&self) -> &str {
+        "button"
+    }
+
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        let value = match offset & !0b11 {
+            Self::STATE => self.pressed as u32,
+            Self::EVENT => self.event as u32,
+            _ => 0,
+        };
+
+        read_bytes(value as u64, offset & 0b11, width)
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, _value: u32) {
+        if offset & !0b11 == Self::EVENT {
+            self.event = false;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.event
+    }
+}
@@ -0,0 +1,147 @@
+//! Core-local interruptor ("CLINT"), the standard machine timer and software
+//! interrupt device. Register offsets match the SiFive CLINT used by QEMU's
+//! `virt` board, so timer examples written for real hardware work unchanged.
+//!
+//! | Offset   | Register   | Width   |
+//! |----------|------------|---------|
+//! | `0x0000` | `msip`     | 32 bits |
+//! | `0x4000` | `mtimecmp` | 64 bits |
+//! | `0xbff8` | `mtime`    | 64 bits |
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clint {
+    /// Software interrupt pending; only bit 0 is writable
+    pub msip: u32,
+    /// Timer compare value; the timer interrupt is pending while `mtime >= mtimecmp`
+    pub mtimecmp: u64,
+    /// Free running timer, incremented once per tick
+    pub mtime: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self {
+            msip: 0,
+            // keeps the timer interrupt quiet until software sets a compare value
+            mtimecmp: u64::MAX,
+            mtime: 0,
+        }
+    }
+}
+
+impl Clint {
+    /// Conventional base address of the CLINT
+    pub const BASE: u32 = 0x0200_0000;
+
+    /// Size of the CLINT address range
+    pub const SIZE: u32 = 0x0001_0000;
+
+    pub const MSIP: u32 = 0x0000;
+    pub const MTIMECMP: u32 = 0x4000;
+    pub const MTIME: u32 = 0xbff8;
+}
+
+impl MmioDevice for Clint {
+    fn name(&self) -> &str {
+        "clint"
+    }
+
+    fn size(&self) -> u32 {
+        Self::SIZE
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        match offset {
+            Self::MSIP..=0x0003 => read_bytes(self.msip as u64, offset - Self::MSIP, width),
+            Self::MTIMECMP..=0x4007 => read_bytes(self.mtimecmp, offset - Self::MTIMECMP, width),
+            Self::MTIME..=0xbfff => read_bytes(self.mtime, offset - Self::MTIME, width),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        match offset {
+            Self::MSIP..=0x0003 => {
+                let msip = write_bytes(self.msip as u64, offset - Self::MSIP, width, value);
+                self.msip = msip as u32 & 1;
+            }
+            Self::MTIMECMP..=0x4007 => {
+                self.mtimecmp = write_bytes(self.mtimecmp, offset - Self::MTIMECMP, width, value)
+            }
+            Self::MTIME..=0xbfff => {
+                self.mtime = write_bytes(self.mtime, offset - Self::MTIME, width, value)
+            }
+            _ => (),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    fn interrupts(&self) -> u32 {
+        let mut pending = 0;
+
+        if self.msip & 1 == 1 {
+            pending |= MSIP;
+        }
+
+        if self.mtime >= self.mtimecmp {
+            pending |= MTIP;
+        }
+
+        pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn software_interrupt() {
+        let mut clint = Clint::default();
+        assert_eq!(clint.interrupts(), 0);
+
+        // only bit 0 sticks
+        clint.write(Clint::MSIP, 4, 0xffff_ffff);
+        assert_eq!(clint.read(Clint::MSIP, 4), 1);
+        assert_eq!(clint.interrupts(), MSIP);
+
+        clint.write(Clint::MSIP, 4, 0);
+        assert_eq!(clint.interrupts(), 0);
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let mut clint = Clint::default();
+
+        // 64-bit compare value written as two halves, like RV32 software does
+        clint.write(Clint::MTIMECMP, 4, 3);
+        clint.write(Clint::MTIMECMP + 4, 4, 0);
+        assert_eq!(clint.mtimecmp, 3);
+
+        clint.tick();
+        clint.tick();
+        assert_eq!(clint.read(Clint::MTIME, 4), 2);
+        assert_eq!(clint.interrupts(), 0);
+
+        clint.tick();
+        assert_eq!(clint.interrupts(), MTIP);
+
+        // pushing the compare value forward clears the interrupt
+        clint.write(Clint::MTIMECMP, 4, 100);
+        assert_eq!(clint.interrupts(), 0);
+    }
+
+    #[test]
+    fn mtime_rolls_into_high_word() {
+        let mut clint = Clint::default();
+        clint.write(Clint::MTIME, 4, u32::MAX);
+        clint.tick();
+        assert_eq!(clint.read(Clint::MTIME, 4), 0);
+        assert_eq!(clint.read(Clint::MTIME + 4, 4), 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! A tiny monochrome display: 64 by 32 pixels, one bit each.
+//!
+//! The 256 bytes of pixels are plain memory to the program. Each row takes 8
+//! bytes (two words), top row first; within a row, pixel `x` is bit `x % 8`
+//! of byte `x / 8`, so the leftmost pixel is bit 0 of the row's first byte.
+//!
+//! | Offset        | Description                   |
+//! |---------------|-------------------------------|
+//! | `0x00..0x100` | pixels; a set bit is lit      |
+//!
+//! Rendered with block characters, two rows of pixels per line of text.
+
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    pub pixels: [u8; Framebuffer::SIZE as usize],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            pixels: [0; Self::SIZE as usize],
+        }
+    }
+}
+
+impl Framebuffer {
+    /// Conventional base address of the framebuffer
+    pub const BASE: u32 = 0x1000_6000;
+
+    pub const WIDTH: u32 = 64;
+    pub const HEIGHT: u32 = 32;
+
+    /// Bytes of pixel memory
+    pub const SIZE: u32 = Self::WIDTH * Self::HEIGHT / 8;
+
+    /// Whether the pixel at `x`, `y` is lit; pixels off the display aren't
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        if x >= Self::WIDTH || y >= Self::HEIGHT {
+            return false;
+        }
+        let index = y * Self::WIDTH + x;
+        self.pixels[(index / 8) as usize] >> (index % 8) & 1 == 1
+    }
+}
+
+impl MmioDevice for Framebuffer {
+    fn name(&self) -> &str {
+        "framebuffer"
+    }
+
+    fn size(&self) -> u32 {
+        Self::SIZE
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        let mut bytes = [0; 4];
+        for (i, byte) in bytes.iter_mut().take(width as usize).enumerate() {
+            *byte = self.pixels.get(offset as usize + i).copied().unwrap_or(0);
+        }
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        let bytes = value.to_le_bytes();
+        for (i, byte) in bytes.iter().take(width as usize).enumerate() {
+            if let Some(pixel) = self.pixels.get_mut(offset as usize + i) {
+                *pixel = *byte;
+            }
+        }
+    }
+
+    fn render(&self) -> Option<String> {
+        let lines: Vec<String> = (0..Self::HEIGHT)
+            .step_by(2)
+            .map(|y| {
+                (0..Self::WIDTH)
+                    .map(|x| match (self.pixel(x, y), self.pixel(x, y + 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // a border shows the edges of the display
+        let edge = "-".repeat(Self::WIDTH as usize);
+        let mut drawing = format!("+{edge}+");
+        for line in lines {
+            drawing.push_str(&format!("\n|{line}|"));
+        }
+        drawing.push_str(&format!("\n+{edge}+"));
+        Some(drawing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_and_render() {
+        let mut framebuffer = Framebuffer::default();
+
+        // top left, the pixel below it, and the last pixel of the third row
+        framebuffer.write(0, 1, 1);
+        framebuffer.write(8, 4, 1);
+        framebuffer.write(23, 1, 0x80);
+
+        assert!(framebuffer.pixel(0, 0));
+        assert!(framebuffer.pixel(0, 1));
+        assert!(framebuffer.pixel(63, 2));
+        assert!(!framebuffer.pixel(64, 0));
+        assert_eq!(framebuffer.read(22, 4), 0x80 << 8);
+
+        let drawing = framebuffer.render().unwrap();
+        let lines: Vec<&str> = drawing.lines().collect();
+        assert_eq!(lines.len(), 18);
+        assert!(lines[1].starts_with("|█ "));
+        assert!(lines[2].ends_with("▀|"));
+    }
+}
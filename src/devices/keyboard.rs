@@ -0,0 +1,95 @@
+//! A keyboard that programs poll for keys, one byte at a time.
+//!
+//! | Offset | Register  | Description                                        |
+//! |--------|-----------|----------------------------------------------------|
+//! | `0x0`  | `status`  | bit 0: a key is waiting                            |
+//! | `0x4`  | `data`    | read: the next key (removing it), or 0 if none     |
+//! | `0x8`  | `control` | bit 0: raise the interrupt line while keys wait    |
+//!
+//! Reads never wait: a program checks `status` (or takes the interrupt) and
+//! then reads `data`. Keys are queued by the host (eg: the REPL's `/input`)
+//! with [Keyboard::push_keys].
+
+use std::collections::VecDeque;
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keyboard {
+    /// Keys sent by the host, oldest first
+    pub keys: VecDeque<u8>,
+    pub interrupt_enabled: bool,
+}
+
+impl Keyboard {
+    /// Conventional base address of the keyboard
+    pub const BASE: u32 = 0x1000_5000;
+
+    /// Conventional interrupt source number of the keyboard
+    pub const IRQ: u32 = 2;
+
+    pub const STATUS: u32 = 0x0;
+    pub const DATA: u32 = 0x4;
+    pub const CONTROL: u32 = 0x8;
+
+    /// Queues keys for the guest to read
+    pub fn push_keys(&mut self, keys: &[u8]) {
+        self.keys.extend(keys);
+    }
+}
+
+impl MmioDevice for Keyboard {
+    fn name(&self) -> &str {
+        "keyboard"
+    }
+
+    fn size(&self) -> u32 {
+        12
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        let byte = offset & 0b11;
+        let value = match offset & !0b11 {
+            Self::STATUS => !self.keys.is_empty() as u32,
+            // only a read of the whole register (or its low byte) takes a key
+            Self::DATA if byte == 0 => self.keys.pop_front().unwrap_or(0) as u32,
+            Self::CONTROL => self.interrupt_enabled as u32,
+            _ => 0,
+        };
+
+        read_bytes(value as u64, byte, width)
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        if offset == Self::CONTROL {
+            self.interrupt_enabled = value & 1 == 1;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.interrupt_enabled && !self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polling() {
+        let mut keyboard = Keyboard::default();
+        assert_eq!(keyboard.read(Keyboard::STATUS, 4), 0);
+        assert_eq!(keyboard.read(Keyboard::DATA, 4), 0);
+
+        keyboard.push_keys(b"hi");
+        keyboard.write(Keyboard::CONTROL, 4, 1);
+        assert!(keyboard.irq());
+        assert_eq!(keyboard.read(Keyboard::STATUS, 4), 1);
+        assert_eq!(keyboard.read(Keyboard::DATA + 1, 1), 0);
+        assert_eq!(keyboard.read(Keyboard::DATA, 4), b'h' as u32);
+        assert_eq!(keyboard.read(Keyboard::DATA, 1), b'i' as u32);
+
+        assert_eq!(keyboard.read(Keyboard::STATUS, 4), 0);
+        assert!(!keyboard.irq());
+    }
+}
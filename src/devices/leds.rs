@@ -0,0 +1,62 @@
+//! A bank of eight LEDs, for programs to show a byte at a glance.
+//!
+//! | Offset | Register | Description                          |
+//! |--------|----------|--------------------------------------|
+//! | `0x0`  | `state`  | bits 7..0: LED on (1) or off (0)     |
+//!
+//! Rendered with the most significant LED on the left: `[*..*....]`.
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Leds {
+    pub state: u8,
+}
+
+impl Leds {
+    /// Conventional base address of the LEDs
+    pub const BASE: u32 = 0x1000_2000;
+}
+
+impl MmioDevice for Leds {
+    fn name(&self) -> &str {
+        "leds"
+    }
+
+    fn size(&self) -> u32 {
+        4
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        read_bytes(self.state as u64, offset, width)
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        self.state = write_bytes(self.state as u64, offset, width, value) as u8;
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(format!("[{}]", row(self.state, '*', '.')))
+    }
+}
+
+/// Bits 7..0 of `value` as characters
+pub(crate) fn row(value: u8, on: char, off: char) -> String {
+    (0..8)
+        .rev()
+        .map(|bit| if value >> bit & 1 == 1 { on } else { off })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut leds = Leds::default();
+        leds.write(0, 4, 0x1_90);
+        assert_eq!(leds.read(0, 4), 0x90);
+        assert_eq!(leds.render().unwrap(), "[*..*....]");
+    }
+}
@@ -0,0 +1,415 @@
+//! Memory-mapped I/O ("MMIO") devices.
+//!
+//! A device claims a range of the address space. Loads and stores that land
+//! inside that range are routed to the device instead of the
+//! [CPU](crate::rv32_i::CPU) memory, which is how real RISC-V systems talk to
+//! timers, consoles, and everything else that isn't RAM.
+//!
+//! Devices are attached to a [Bus] at a base address, and implement the
+//! [MmioDevice] trait. Offsets passed to a device are relative to its base.
+//!
+//! ```
+//! use brubeck::devices::{Bus, Clint};
+//!
+//! let mut bus = Bus::default();
+//! assert!(bus.attach(Clint::BASE, Clint::default()).is_ok());
+//!
+//! // mtime counts up with each tick (one per executed instruction)
+//! bus.tick();
+//! assert_eq!(bus.read(Clint::BASE + 0xbff8, 4), Some(1));
+//! ```
+
+mod button;
+mod clint;
+mod framebuffer;
+mod keyboard;
+mod leds;
+mod plic;
+mod seven_segment;
+mod switches;
+mod tohost;
+mod uart;
+
+pub use button::Button;
+pub use clint::Clint;
+pub use framebuffer::Framebuffer;
+pub use keyboard::Keyboard;
+pub use leds::Leds;
+pub use plic::Plic;
+pub use seven_segment::SevenSegment;
+pub use switches::Switches;
+pub use tohost::ToHost;
+pub use uart::Uart;
+
+use std::any::Any;
+use std::fmt::Debug;
+
+/// Machine software interrupt pending bit, as it appears in `mip`
+pub const MSIP: u32 = 1 << 3;
+
+/// Machine timer interrupt pending bit, as it appears in `mip`
+pub const MTIP: u32 = 1 << 7;
+
+/// Machine external interrupt pending bit, as it appears in `mip`
+pub const MEIP: u32 = 1 << 11;
+
+/// A device that responds to loads and stores within its address range.
+pub trait MmioDevice: Debug + DeviceClone {
+    /// Short name for display (eg: `clint`)
+    fn name(&self) -> &str;
+
+    /// Number of bytes of address space claimed by the device
+    fn size(&self) -> u32;
+
+    /// Reads `width` bytes (1, 2, or 4) at `offset` from the device base.
+    /// Takes `&mut self` because reads can have side effects on real hardware.
+    fn read(&mut self, offset: u32, width: u32) -> u32;
+
+    /// Writes the low `width` bytes (1, 2, or 4) of `value` at `offset` from
+    /// the device base.
+    fn write(&mut self, offset: u32, width: u32, value: u32);
+
+    /// Advances the device by one step; called once per executed instruction.
+    fn tick(&mut self) {}
+
+    /// Interrupt lines currently asserted by the device, as `mip` bits (eg:
+    /// [MTIP]).
+    fn interrupts(&self) -> u32 {
+        0
+    }
+
+    /// External interrupt request line. Devices attached with an interrupt
+    /// source number (see [Bus::attach_with_irq]) have this routed through the
+    /// [Plic].
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Exit code, once the device has been asked to end the guest program
+    /// (see [ToHost]).
+    fn exit_code(&self) -> Option<u32> {
+        None
+    }
+
+    /// The device's state drawn as text (possibly several lines), for
+    /// devices meant to be looked at like [Leds].
+    fn render(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Cloning and downcasting support for boxed [MmioDevice]s. This is
+/// implemented automatically for any device that is `Clone`.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn MmioDevice>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: MmioDevice + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn MmioDevice> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn MmioDevice> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A device attached to the [Bus] at a base address.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub base: u32,
+    /// Interrupt source number on the [Plic], if the device raises interrupts
+    pub irq: Option<u32>,
+    pub device: Box<dyn MmioDevice>,
+}
+
+impl Mapping {
+    /// True if `address` falls within the device's range
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.base && address - self.base < self.device.size()
+    }
+}
+
+/// Routes addresses to attached devices.
+#[derive(Debug, Clone, Default)]
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    /// Attaches a device at `base`. Fails if the device's range would
+    /// overlap a device that's already attached.
+    pub fn attach<D: MmioDevice + 'static>(&mut self, base: u32, device: D) -> Result<(), Error> {
+        self.attach_mapping(base, None, Box::new(device))
+    }
+
+    /// Attaches a device at `base` whose [irq](MmioDevice::irq) line is wired
+    /// to interrupt source `irq` on the [Plic].
+    pub fn attach_with_irq<D: MmioDevice + 'static>(
+        &mut self,
+        base: u32,
+        irq: u32,
+        device: D,
+    ) -> Result<(), Error> {
+        if irq == 0 || irq >= Plic::SOURCES {
+            return Err(Error::InvalidIrq(irq));
+        }
+
+        self.attach_mapping(base, Some(irq), Box::new(device))
+    }
+
+    fn attach_mapping(
+        &mut self,
+        base: u32,
+        irq: Option<u32>,
+        device: Box<dyn MmioDevice>,
+    ) -> Result<(), Error> {
+        let end = base as u64 + device.size() as u64;
+
+        for mapping in &self.mappings {
+            let other_end = mapping.base as u64 + mapping.device.size() as u64;
+            if (base as u64) < other_end && (mapping.base as u64) < end {
+                return Err(Error::Overlap(mapping.base));
+            }
+        }
+
+        self.mappings.push(Mapping { base, irq, device });
+
+        Ok(())
+    }
+
+    /// All attached devices, in the order they were attached
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// True if `address` is claimed by a device
+    pub fn contains(&self, address: u32) -> bool {
+        self.mappings.iter().any(|m| m.contains(address))
+    }
+
+    /// Reads from the device mapped at `address`, or `None` if there isn't one
+    pub fn read(&mut self, address: u32, width: u32) -> Option<u32> {
+        let mapping = self.mappings.iter_mut().find(|m| m.contains(address))?;
+        let value = mapping.device.read(address - mapping.base, width);
+
+        // reads can change interrupt lines (eg: draining a receive buffer)
+        self.route_irqs();
+
+        Some(value)
+    }
+
+    /// Writes to the device mapped at `address`; returns false if there isn't one
+    pub fn write(&mut self, address: u32, width: u32, value: u32) -> bool {
+        match self.mappings.iter_mut().find(|m| m.contains(address)) {
+            Some(mapping) => {
+                mapping.device.write(address - mapping.base, width, value);
+                self.route_irqs();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ticks every attached device, then routes device interrupt lines to
+    /// the [Plic] (if there is one).
+    pub fn tick(&mut self) {
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.tick();
+        }
+
+        self.route_irqs();
+    }
+
+    /// Copies the level of each device's [irq](MmioDevice::irq) line to its
+    /// source on the [Plic].
+    pub fn route_irqs(&mut self) {
+        let levels: Vec<(u32, bool)> = self
+            .mappings
+            .iter()
+            .filter_map(|m| m.irq.map(|irq| (irq, m.device.irq())))
+            .collect();
+
+        if let Some(plic) = self.get_mut::<Plic>() {
+            for (irq, level) in levels {
+                plic.set_level(irq, level);
+            }
+        }
+    }
+
+    /// Combined interrupt lines of every attached device, as `mip` bits
+    pub fn interrupts(&self) -> u32 {
+        self.mappings
+            .iter()
+            .fold(0, |acc, m| acc | m.device.interrupts())
+    }
+
+    /// Exit code requested through any attached device
+    pub fn exit_code(&self) -> Option<u32> {
+        self.mappings.iter().find_map(|m| m.device.exit_code())
+    }
+
+    /// Every device that can be drawn, by name, with its current drawing;
+    /// see [MmioDevice::render]
+    pub fn renders(&self) -> Vec<(String, String)> {
+        self.mappings
+            .iter()
+            .filter_map(|m| Some((m.device.name().to_owned(), m.device.render()?)))
+            .collect()
+    }
+
+    /// Finds the first attached device of type `T`
+    ///
+    /// ```
+    /// use brubeck::devices::{Bus, Clint};
+    ///
+    /// let mut bus = Bus::default();
+    /// bus.attach(Clint::BASE, Clint::default()).unwrap();
+    /// assert_eq!(bus.get::<Clint>().unwrap().mtime, 0);
+    /// ```
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.mappings
+            .iter()
+            .find_map(|m| m.device.as_any().downcast_ref::<T>())
+    }
+
+    /// Finds the first attached device of type `T`, mutably
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.mappings
+            .iter_mut()
+            .find_map(|m| m.device.as_any_mut().downcast_mut::<T>())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The new device overlaps the device attached at this base address
+    Overlap(u32),
+    /// Interrupt source numbers run from 1 to [Plic::SOURCES] - 1
+    InvalidIrq(u32),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overlap(base) => write!(f, "overlaps the device at 0x{base:08x}"),
+            Self::InvalidIrq(irq) => write!(
+                f,
+                "interrupt {irq} isn't a source (they run from 1 to {})",
+                Plic::SOURCES - 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reads `width` bytes starting `byte` bytes into a register value. Handy for
+/// devices with registers wider than the access (eg: 64-bit timers).
+pub(crate) fn read_bytes(register: u64, byte: u32, width: u32) -> u32 {
+    let shifted = register >> (byte * 8);
+    match width {
+        1 => shifted as u32 & 0xff,
+        2 => shifted as u32 & 0xffff,
+        _ => shifted as u32,
+    }
+}
+
+/// Writes the low `width` bytes of `value` starting `byte` bytes into a
+/// register value, leaving the other bytes untouched.
+pub(crate) fn write_bytes(register: u64, byte: u32, width: u32, value: u32) -> u64 {
+    let mask: u64 = match width {
+        1 => 0xff,
+        2 => 0xffff,
+        _ => 0xffff_ffff,
+    };
+    let shift = byte * 8;
+    (register & !(mask << shift)) | ((value as u64 & mask) << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attach_and_route() {
+        let mut bus = Bus::default();
+        assert!(bus.attach(Clint::BASE, Clint::default()).is_ok());
+
+        assert!(bus.contains(Clint::BASE));
+        assert!(bus.contains(Clint::BASE + Clint::SIZE - 1));
+        assert!(!bus.contains(Clint::BASE + Clint::SIZE));
+        assert!(!bus.contains(0));
+
+        assert_eq!(bus.read(0, 4), None);
+        assert!(!bus.write(0, 4, 1));
+    }
+
+    #[test]
+    fn overlapping_devices() {
+        let mut bus = Bus::default();
+        assert!(bus.attach(Clint::BASE, Clint::default()).is_ok());
+        assert_eq!(
+            bus.attach(Clint::BASE + 4, Clint::default()),
+            Err(Error::Overlap(Clint::BASE))
+        );
+        assert!(bus
+            .attach(Clint::BASE + Clint::SIZE, Clint::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn irq_routing() {
+        let mut bus = Bus::default();
+        bus.attach(Plic::BASE, Plic::default()).unwrap();
+        bus.attach_with_irq(Button::BASE, Button::IRQ, Button::default())
+            .unwrap();
+
+        assert_eq!(
+            bus.attach_with_irq(0, 0, Button::default()),
+            Err(Error::InvalidIrq(0))
+        );
+
+        // enable the button source with a non-zero priority
+        bus.write(Plic::BASE + 4 * Button::IRQ, 4, 1);
+        bus.write(Plic::BASE + Plic::ENABLE, 4, 1 << Button::IRQ);
+
+        bus.tick();
+        assert_eq!(bus.interrupts(), 0);
+
+        bus.get_mut::<Button>().unwrap().press();
+        bus.tick();
+        assert_eq!(bus.interrupts(), MEIP);
+
+        // claim, acknowledge the button, then complete
+        assert_eq!(bus.read(Plic::BASE + Plic::CLAIM, 4), Some(Button::IRQ));
+        bus.write(Button::BASE + Button::EVENT, 4, 0);
+        bus.write(Plic::BASE + Plic::CLAIM, 4, Button::IRQ);
+        bus.tick();
+        assert_eq!(bus.interrupts(), 0);
+    }
+
+    #[test]
+    fn partial_register_access() {
+        let register = 0x1122_3344_5566_7788;
+        assert_eq!(read_bytes(register, 0, 4), 0x5566_7788);
+        assert_eq!(read_bytes(register, 4, 4), 0x1122_3344);
+        assert_eq!(read_bytes(register, 1, 1), 0x77);
+        assert_eq!(read_bytes(register, 6, 2), 0x1122);
+
+        assert_eq!(write_bytes(register, 4, 4, 0), 0x5566_7788);
+        assert_eq!(write_bytes(register, 0, 1, 0xff), 0x1122_3344_5566_77ff);
+    }
+}
@@ -0,0 +1,241 @@
+//! Platform-level interrupt controller ("PLIC"), simplified to a single hart
+//! context and 31 interrupt sources. Register offsets follow the standard
+//! PLIC layout used by QEMU's `virt` board.
+//!
+//! | Offset     | Register                      |
+//! |------------|-------------------------------|
+//! | `0x000004` | priority of source 1 (and so on, 4 bytes per source) |
+//! | `0x001000` | pending bits (read only)      |
+//! | `0x002000` | enable bits                   |
+//! | `0x200000` | priority threshold            |
+//! | `0x200004` | claim (read) / complete (write) |
+//!
+//! A source interrupts the hart when it is pending, enabled, and its priority
+//! is greater than the threshold. Claiming returns the highest priority
+//! source (ties go to the lowest source number) and holds it until software
+//! writes the same number back to complete it.
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plic {
+    /// Priority of each source, 0 (never interrupts) to 7
+    pub priority: [u32; Plic::SOURCES as usize],
+    /// Pending bit per source
+    pub pending: u32,
+    /// Enable bit per source
+    pub enable: u32,
+    /// Sources with a priority at or below the threshold are masked
+    pub threshold: u32,
+    /// Sources that have been claimed but not yet completed
+    pub claimed: u32,
+    /// Current level of each source's interrupt line
+    levels: u32,
+}
+
+impl Plic {
+    /// Conventional base address of the PLIC
+    pub const BASE: u32 = 0x0c00_0000;
+
+    /// Size of the PLIC address range
+    pub const SIZE: u32 = 0x0400_0000;
+
+    /// Number of sources, including the reserved source 0
+    pub const SOURCES: u32 = 32;
+
+    /// Highest priority a source can have
+    pub const MAX_PRIORITY: u32 = 7;
+
+    pub const PENDING: u32 = 0x0000_1000;
+    pub const ENABLE: u32 = 0x0000_2000;
+    pub const THRESHOLD: u32 = 0x0020_0000;
+    pub const CLAIM: u32 = 0x0020_0004;
+
+    /// Sets the level of a source's interrupt line. A high line makes the
+    /// source pending unless it is currently claimed.
+    pub fn set_level(&mut self, source: u32, level: bool) {
+        if source == 0 || source >= Self::SOURCES {
+            return;
+        }
+
+        if level {
+            self.levels |= 1 << source;
+        } else {
+            self.levels &= !(1 << source);
+        }
+
+        self.update();
+    }
+
+    /// Highest priority source that is pending, enabled, and above the
+    /// threshold.
+    pub fn highest_pending(&self) -> Option<u32> {
+        let mut best: Option<u32> = None;
+
+        for source in 1..Self::SOURCES {
+            let bit = 1 << source;
+            let priority = self.priority[source as usize];
+
+            if self.pending & self.enable & bit == 0 || priority <= self.threshold {
+                continue;
+            }
+
+            match best {
+                Some(b) if self.priority[b as usize] >= priority => (),
+                _ => best = Some(source),
+            }
+        }
+
+        best
+    }
+
+    /// Claims the highest priority pending source, returning 0 if there isn't one.
+    pub fn claim(&mut self) -> u32 {
+        match self.highest_pending() {
+            Some(source) => {
+                self.pending &= !(1 << source);
+                self.claimed |= 1 << source;
+                source
+            }
+            None => 0,
+        }
+    }
+
+    /// Signals that the handler for `source` is done.
+    pub fn complete(&mut self, source: u32) {
+        if source < Self::SOURCES {
+            self.claimed &= !(1 << source);
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        self.pending |= self.levels & !self.claimed;
+    }
+}
+
+impl MmioDevice for Plic {
+    fn name(&self) -> &str {
+        "plic"
+    }
+
+    fn size(&self) -> u32 {
+        Self::SIZE
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        let register = offset & !0b11;
+        let byte = offset & 0b11;
+
+        let value = match register {
+            0x0000_0000..=0x0000_007c => self.priority[register as usize / 4],
+            Self::PENDING => self.pending,
+            Self::ENABLE => self.enable,
+            Self::THRESHOLD => self.threshold,
+            Self::CLAIM => self.claim(),
+            _ => 0,
+        };
+
+        read_bytes(value as u64, byte, width)
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        let register = offset & !0b11;
+        let byte = offset & 0b11;
+
+        match register {
+            // source 0 doesn't exist, so it has no priority
+            0x0000_0004..=0x0000_007c => {
+                let index = register as usize / 4;
+                let priority = write_bytes(self.priority[index] as u64, byte, width, value);
+                self.priority[index] = (priority as u32).min(Self::MAX_PRIORITY);
+            }
+            Self::ENABLE => {
+                let enable = write_bytes(self.enable as u64, byte, width, value);
+                self.enable = enable as u32 & !1;
+            }
+            Self::THRESHOLD => {
+                let threshold = write_bytes(self.threshold as u64, byte, width, value);
+                self.threshold = (threshold as u32).min(Self::MAX_PRIORITY);
+            }
+            Self::CLAIM => self.complete(value),
+            _ => (),
+        }
+    }
+
+    fn interrupts(&self) -> u32 {
+        match self.highest_pending() {
+            Some(_) => MEIP,
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plic_with_sources() -> Plic {
+        let mut plic = Plic::default();
+        plic.write(4, 4, 1); // source 1, priority 1
+        plic.write(8, 4, 3); // source 2, priority 3
+        plic.write(12, 4, 3); // source 3, priority 3
+        plic.write(Plic::ENABLE, 4, 0b1110);
+        plic
+    }
+
+    #[test]
+    fn priority_ordering() {
+        let mut plic = plic_with_sources();
+
+        plic.set_level(1, true);
+        plic.set_level(2, true);
+        plic.set_level(3, true);
+        assert_eq!(plic.read(Plic::PENDING, 4), 0b1110);
+        assert_eq!(plic.interrupts(), MEIP);
+
+        // highest priority first, lowest source number breaks ties
+        assert_eq!(plic.read(Plic::CLAIM, 4), 2);
+        assert_eq!(plic.read(Plic::CLAIM, 4), 3);
+        assert_eq!(plic.read(Plic::CLAIM, 4), 1);
+        assert_eq!(plic.read(Plic::CLAIM, 4), 0);
+        assert_eq!(plic.interrupts(), 0);
+    }
+
+    #[test]
+    fn threshold_and_enable() {
+        let mut plic = plic_with_sources();
+        plic.set_level(1, true);
+
+        plic.write(Plic::THRESHOLD, 4, 1);
+        assert_eq!(plic.interrupts(), 0);
+
+        plic.write(Plic::THRESHOLD, 4, 0);
+        assert_eq!(plic.interrupts(), MEIP);
+
+        plic.write(Plic::ENABLE, 4, 0);
+        assert_eq!(plic.interrupts(), 0);
+    }
+
+    #[test]
+    fn claim_complete() {
+        let mut plic = plic_with_sources();
+        plic.set_level(2, true);
+
+        assert_eq!(plic.read(Plic::CLAIM, 4), 2);
+
+        // the line is still high, but the source is in service
+        plic.set_level(2, true);
+        assert_eq!(plic.interrupts(), 0);
+
+        // completing with the line still high makes it pending again
+        plic.write(Plic::CLAIM, 4, 2);
+        assert_eq!(plic.interrupts(), MEIP);
+
+        // dropping the line and servicing it again leaves nothing pending
+        plic.set_level(2, false);
+        assert_eq!(plic.read(Plic::CLAIM, 4), 2);
+        plic.write(Plic::CLAIM, 4, 2);
+        assert_eq!(plic.interrupts(), 0);
+    }
+}
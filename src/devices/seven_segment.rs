@@ -0,0 +1,111 @@
+//! A four digit seven-segment display.
+//!
+//! | Offset | Register | Description                              |
+//! |--------|----------|------------------------------------------|
+//! | `0x0`  | `digits` | one byte per digit; byte 0 is rightmost  |
+//!
+//! Each byte lights segments by bit, in the usual `a` to `g` order, with the
+//! decimal point in bit 7:
+//!
+//! ```text
+//!  _      a
+//! |_|   f g b
+//! |_|.  e d c  dp
+//! ```
+//!
+//! So `0x3f` shows `0`, and `0x06` shows `1`. Nothing is decoded for the
+//! program; it has to pick the segments itself.
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SevenSegment {
+    /// Segments of each digit, rightmost first
+    pub digits: [u8; 4],
+}
+
+impl SevenSegment {
+    /// Conventional base address of the display
+    pub const BASE: u32 = 0x1000_4000;
+
+    /// Segments for the hex digits `0` to `f`
+    pub const HEX: [u8; 16] = [
+        0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79,
+        0x71,
+    ];
+}
+
+impl MmioDevice for SevenSegment {
+    fn name(&self) -> &str {
+        "seven segment"
+    }
+
+    fn size(&self) -> u32 {
+        4
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        read_bytes(u32::from_le_bytes(self.digits) as u64, offset, width)
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        let current = u32::from_le_bytes(self.digits) as u64;
+        self.digits = (write_bytes(current, offset, width, value) as u32).to_le_bytes();
+    }
+
+    fn render(&self) -> Option<String> {
+        let lit = |segments: u8, bit: u32, c: char| {
+            if segments >> bit & 1 == 1 {
+                c
+            } else {
+                ' '
+            }
+        };
+
+        let mut rows = [String::new(), String::new(), String::new()];
+        for segments in self.digits.iter().rev().copied() {
+            rows[0].extend([' ', lit(segments, 0, '_'), ' ', ' ']);
+            rows[1].extend([
+                lit(segments, 5, '|'),
+                lit(segments, 6, '_'),
+                lit(segments, 1, '|'),
+                ' ',
+            ]);
+            rows[2].extend([
+                lit(segments, 4, '|'),
+                lit(segments, 3, '_'),
+                lit(segments, 2, '|'),
+                lit(segments, 7, '.'),
+            ]);
+        }
+
+        Some(
+            rows.iter()
+                .map(|row| row.trim_end())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut display = SevenSegment::default();
+        let hex = SevenSegment::HEX;
+        let digits = [hex[2], hex[0xb] | 0x80, hex[1], hex[0]];
+        display.write(0, 4, u32::from_le_bytes(digits));
+        display.write(3, 1, hex[8] as u32);
+
+        assert_eq!(display.read(0, 1), hex[2] as u32);
+        assert_eq!(
+            display.render().unwrap(),
+            " _           _\n\
+             |_|   | |_   _|\n\
+             |_|   | |_|.|_"
+        );
+    }
+}
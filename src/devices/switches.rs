@@ -0,0 +1,69 @@
+//! Eight toggle switches, flipped from the host (eg: the REPL's `/switch`)
+//! and read by the program.
+//!
+//! | Offset | Register | Description                      |
+//! |--------|----------|----------------------------------|
+//! | `0x0`  | `state`  | bits 7..0: switch up (1) or down |
+//!
+//! Writes are ignored. Rendered like the [Leds](super::Leds): `[1..1....]`.
+
+use super::leds::row;
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Switches {
+    pub state: u8,
+}
+
+impl Switches {
+    /// Conventional base address of the switches
+    pub const BASE: u32 = 0x1000_3000;
+
+    /// Number of switches
+    pub const COUNT: u32 = 8;
+
+    /// Flips switch `index` (0 is the least significant bit) up or down
+    pub fn set(&mut self, index: u32, up: bool) {
+        if index < Self::COUNT {
+            self.state = (self.state & !(1 << index)) | ((up as u8) << index);
+        }
+    }
+}
+
+impl MmioDevice for Switches {
+    fn name(&self) -> &str {
+        "switches"
+    }
+
+    fn size(&self) -> u32 {
+        4
+    }
+
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        read_bytes(self.state as u64, offset, width)
+    }
+
+    fn write(&mut self, _offset: u32, _width: u32, _value: u32) {}
+
+    fn render(&self) -> Option<String> {
+        Some(format!("[{}]", row(self.state, '1', '.')))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip() {
+        let mut switches = Switches::default();
+        switches.set(0, true);
+        switches.set(7, true);
+        switches.set(8, true);
+        switches.write(0, 4, 0);
+        assert_eq!(switches.read(0, 4), 0x81);
+
+        switches.set(0, false);
+        assert_eq!(switches.render().unwrap(), "[1.......]");
+    }
+}
@@ -0,0 +1,63 @@
+//! The `tohost` register from the HTIF ("host-target interface") used by
+//! Spike and the `riscv-tests` suite to report results.
+//!
+//! Writing a value with the low bit set ends the program with exit code
+//! `value >> 1`; so `1` means success and anything else is a failure code.
+//! Other writes are ignored.
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToHost {
+    pub exit_code: Option<u32>,
+}
+
+impl ToHost {
+    /// Where a board puts `tohost` unless it says otherwise: with the other
+    /// devices, so it doesn't cover memory on a board with more than 1 MiB.
+    /// The riscv-tests put it at their `tohost` symbol instead.
+    pub const BASE: u32 = 0x1000_7000;
+}
+
+impl MmioDevice for ToHost {
+    fn name(&self) -> &str {
+        "tohost"
+    }
+
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn read(&mut self, _offset: u32, _width: u32) -> u32 {
+        0
+    }
+
+    fn write(&mut self, offset: u32, _width: u32, value: u32) {
+        if offset == 0 && value & 1 == 1 {
+            self.exit_code = Some(value >> 1);
+        }
+    }
+
+    fn exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let mut tohost = ToHost::default();
+
+        tohost.write(0, 4, 2); // not an exit command
+        assert_eq!(tohost.exit_code(), None);
+
+        tohost.write(0, 4, 1);
+        assert_eq!(tohost.exit_code(), Some(0));
+
+        tohost.write(0, 4, (3 << 1) | 1);
+        assert_eq!(tohost.exit_code(), Some(3));
+    }
+}
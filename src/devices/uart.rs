@@ -0,0 +1,171 @@
+//! A minimal NS16550A-style UART, the console device on QEMU's `virt` board.
+//!
+//! Only the registers that simple polling and interrupt-driven console code
+//! touch are implemented; baud rate, FIFO control, and modem lines are
+//! accepted and ignored.
+//!
+//! | Offset | Register | Description                                      |
+//! |--------|----------|--------------------------------------------------|
+//! | `0x0`  | RBR/THR  | read: next received byte; write: transmit a byte |
+//! | `0x1`  | IER      | bit 0: interrupt when received data is available |
+//! | `0x5`  | LSR      | bit 0: data ready; bit 5: transmitter empty      |
+//!
+//! Transmitted bytes are collected in a buffer rather than printed, so the
+//! host decides how to display them (see [Uart::take_output]).
+
+use std::collections::VecDeque;
+
+use super::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Uart {
+    /// Bytes written by the guest, waiting for the host to display them
+    pub output: Vec<u8>,
+    /// Bytes sent by the host, waiting for the guest to read them
+    pub input: VecDeque<u8>,
+    /// Interrupt enable register
+    pub ier: u8,
+    /// Line control register; stored but otherwise ignored
+    pub lcr: u8,
+    /// Scratch register
+    pub scr: u8,
+}
+
+impl Uart {
+    /// Conventional base address of the UART
+    pub const BASE: u32 = 0x1000_0000;
+
+    /// Conventional interrupt source number of the UART
+    pub const IRQ: u32 = 10;
+
+    pub const RBR: u32 = 0x0;
+    pub const THR: u32 = 0x0;
+    pub const IER: u32 = 0x1;
+    pub const IIR: u32 = 0x2;
+    pub const LCR: u32 = 0x3;
+    pub const LSR: u32 = 0x5;
+    pub const SCR: u32 = 0x7;
+
+    /// Received data ready
+    pub const LSR_DR: u8 = 1;
+    /// Transmit holding register empty
+    pub const LSR_THRE: u8 = 1 << 5;
+    /// Transmitter empty
+    pub const LSR_TEMT: u8 = 1 << 6;
+
+    /// Received data available interrupt enable
+    pub const IER_RDA: u8 = 1;
+
+    /// Queues bytes for the guest to receive
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Takes everything the guest has transmitted since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn read_register(&mut self, register: u32) -> u8 {
+        match register {
+            Self::RBR => self.input.pop_front().unwrap_or(0),
+            Self::IER => self.ier,
+            Self::IIR => {
+                if self.irq() {
+                    0b0100 // received data available
+                } else {
+                    0b0001 // no interrupt pending
+                }
+            }
+            Self::LCR => self.lcr,
+            Self::LSR => {
+                let ready = if self.input.is_empty() {
+                    0
+                } else {
+                    Self::LSR_DR
+                };
+                ready | Self::LSR_THRE | Self::LSR_TEMT
+            }
+            Self::SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, register: u32, value: u8) {
+        match register {
+            Self::THR => self.output.push(value),
+            Self::IER => self.ier = value & 0x0f,
+            Self::LCR => self.lcr = value,
+            Self::SCR => self.scr = value,
+            _ => (),
+        }
+    }
+}
+
+impl MmioDevice for Uart {
+    fn name(&self) -> &str {
+        "uart"
+    }
+
+    fn size(&self) -> u32 {
+        0x100
+    }
+
+    /// Registers are a byte wide; wider accesses touch consecutive registers.
+    fn read(&mut self, offset: u32, width: u32) -> u32 {
+        (0..width).fold(0, |acc, byte| {
+            acc | (self.read_register(offset + byte) as u32) << (byte * 8)
+        })
+    }
+
+    fn write(&mut self, offset: u32, width: u32, value: u32) {
+        for byte in 0..width {
+            self.write_register(offset + byte, (value >> (byte * 8)) as u8);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.ier & Self::IER_RDA != 0 && !self.input.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmit() {
+        let mut uart = Uart::default();
+        for b in b"hi!" {
+            uart.write(Uart::THR, 1, *b as u32);
+        }
+        assert_eq!(uart.take_output(), b"hi!");
+        assert!(uart.take_output().is_empty());
+    }
+
+    #[test]
+    fn receive() {
+        let mut uart = Uart::default();
+        assert_eq!(uart.read(Uart::LSR, 1) as u8 & Uart::LSR_DR, 0);
+
+        uart.push_input(b"ok");
+        assert_eq!(uart.read(Uart::LSR, 1) as u8 & Uart::LSR_DR, Uart::LSR_DR);
+        assert_eq!(uart.read(Uart::RBR, 1), b'o' as u32);
+        assert_eq!(uart.read(Uart::RBR, 1), b'k' as u32);
+        assert_eq!(uart.read(Uart::LSR, 1) as u8 & Uart::LSR_DR, 0);
+    }
+
+    #[test]
+    fn receive_interrupt() {
+        let mut uart = Uart::default();
+        uart.push_input(b"x");
+        assert!(!uart.irq());
+
+        uart.write(Uart::IER, 1, Uart::IER_RDA as u32);
+        assert!(uart.irq());
+        assert_eq!(uart.read(Uart::IIR, 1), 0b0100);
+
+        uart.read(Uart::RBR, 1);
+        assert!(!uart.irq());
+    }
+}
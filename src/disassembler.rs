@@ -0,0 +1,205 @@
+//! Reading instructions back out of memory.
+//!
+//! [disassemble] decodes a run of instruction words, so you can check what
+//! a listing actually assembled to. Each [Line] has the address, the raw
+//! word, and the instruction in the same syntax the parser reads (see
+//! [Instruction]'s `Display`), plus a comment for what that syntax doesn't make
+//! obvious: where a branch or jump goes, and what a negative immediate is.
+//!
+//! ```
+//! use brubeck::disassembler::disassemble;
+//! use brubeck::rv32_i::CPU;
+//!
+//! let mut cpu = CPU::new(64);
+//! // addi a0, zero, 5; beq a0, zero, -4
+//! cpu.write_memory(0, &0x0050_0513_u32.to_le_bytes()).unwrap();
+//! cpu.write_memory(4, &0xfe05_0ee3_u32.to_le_bytes()).unwrap();
+//!
+//! let lines = disassemble(&cpu, 0, 3);
+//! assert_eq!(lines[0].to_string(), "0x00000000: 00500513  addi a0, zero, 5");
+//! assert_eq!(
+//!     lines[1].to_string(),
+//!     "0x00000004: fe050ee3  beq a0, zero, 4094  # 0x00000000"
+//! );
+//! assert_eq!(lines[2].to_string(), "0x00000008: 00000000  (not an instruction)");
+//! ```
+//!
+//! With the C extension enabled, compressed instructions are read as
+//! halfwords and shown as the instruction they expand to.
+
+use std::fmt::Display;
+
+/// The most lines [disassemble] decodes at once
+pub const MAX_LINES: usize = 4096;
+
+use crate::rv32_i::{Instruction, Operands, CPU};
+
+/// One decoded instruction word
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: u32,
+    /// Bytes the instruction takes up: 4, or 2 for a compressed one
+    pub length: u32,
+    /// None if the address isn't in memory; only the low halfword for a
+    /// compressed instruction
+    pub word: Option<u32>,
+    /// None if the word isn't an instruction
+    pub instruction: Option<Instruction>,
+}
+
+impl Line {
+    /// The word in hex, right aligned so that a compressed halfword lines
+    /// up with full words
+    pub fn raw(&self) -> String {
+        match (self.word, self.length) {
+            (None, _) => "????????".to_owned(),
+            (Some(half), Instruction::COMPRESSED_LENGTH) => format!("    {half:04x}"),
+            (Some(word), _) => format!("{word:08x}"),
+        }
+    }
+
+    /// The instruction, or why there isn't one
+    pub fn text(&self) -> String {
+        match (self.word, self.instruction) {
+            (_, Some(instruction)) => instruction.to_string(),
+            (Some(_), None) => "(not an instruction)".to_owned(),
+            (None, None) => "(not in memory)".to_owned(),
+        }
+    }
+
+    /// The target of a branch or jump, or the value of a negative immediate
+    pub fn comment(&self) -> Option<String> {
+        match self.instruction?.operands() {
+            Operands::BType(b) => Some(format!("0x{:08x}", b.target(self.address))),
+            Operands::JType(j) => Some(format!("0x{:08x}", j.target(self.address))),
+            Operands::IType(i) if self.is_signed() && i.imm.as_i32() < 0 => {
+                Some(i.imm.as_i32().to_string())
+            }
+            Operands::SType(s) if s.imm.as_i32() < 0 => Some(s.imm.as_i32().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Whether an I-type immediate is a signed number (rather than a CSR
+    /// address or a shift amount), going by the instruction's syntax
+    fn is_signed(&self) -> bool {
+        self.instruction
+            .is_some_and(|i| i.info().operands().iter().any(|o| o.name == "imm"))
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:08x}: {}  {}", self.address, self.raw(), self.text())?;
+        if let Some(comment) = self.comment() {
+            write!(f, "  # {comment}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes `count` instructions from memory (up to [MAX_LINES]), starting
+/// at `address`. The addresses wrap around at the top of the address space.
+pub fn disassemble(cpu: &CPU, address: u32, count: usize) -> Vec<Line> {
+    let count = count.min(MAX_LINES);
+    let mut address = address;
+    let mut lines = Vec::with_capacity(count);
+    for _ in 0..count {
+        let half = cpu.read_mem_u16(address).ok();
+        let line = match half {
+            Some(half) if cpu.config.extensions.c && Instruction::is_compressed(half as u32) => {
+                Line {
+                    address,
+                    length: Instruction::COMPRESSED_LENGTH,
+                    word: Some(half as u32),
+                    instruction: Instruction::decode_compressed(half).ok(),
+                }
+            }
+            _ => {
+                let word = cpu.read_mem_u32(address).ok();
+                Line {
+                    address,
+                    length: Instruction::LENGTH,
+                    word,
+                    instruction: word.and_then(|word| Instruction::decode(word).ok()),
+                }
+            }
+        };
+        address = address.wrapping_add(line.length);
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_and_comments() {
+        let mut cpu = CPU::new(32);
+        for (i, word) in [
+            0xffc1_2083_u32, // lw ra, sp, -4
+            0x4032_d293,     // srai t0, t0, 3
+            0x3401_10f3,     // csrrw ra, mscratch, sp
+            0x0080_006f,     // jal zero, 8
+            0x0010_0073,     // ebreak
+            0x0000_0013,     // nop
+        ]
+        .iter()
+        .enumerate()
+        {
+            cpu.write_memory(i as u32 * 4, &word.to_le_bytes()).unwrap();
+        }
+
+        let lines: Vec<String> = disassemble(&cpu, 0, 9)
+            .iter()
+            .map(Line::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0x00000000: ffc12083  lw ra, sp, 4092  # -4",
+                "0x00000004: 4032d293  srai t0, t0, 3",
+                "0x00000008: 340110f3  csrrw ra, mscratch, sp",
+                "0x0000000c: 0080006f  jal zero, 4  # 0x00000014",
+                "0x00000010: 00100073  ebreak",
+                "0x00000014: 00000013  nop",
+                "0x00000018: 00000000  (not an instruction)",
+                "0x0000001c: 00000000  (not an instruction)",
+                "0x00000020: ????????  (not in memory)",
+            ]
+        );
+    }
+
+    #[test]
+    fn compressed() {
+        let mut cpu = CPU::new(16);
+        cpu.config.extensions.c = true;
+        // c.li a0, 5; addi a0, zero, 5; c.bnez a0, -6
+        cpu.write_memory(0, &[0x15, 0x45, 0x13, 0x05, 0x50, 0x00, 0x6d, 0xfd])
+            .unwrap();
+
+        let lines: Vec<String> = disassemble(&cpu, 0, 4)
+            .iter()
+            .map(Line::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0x00000000:     4515  addi a0, zero, 5",
+                "0x00000002: 00500513  addi a0, zero, 5",
+                "0x00000006:     fd6d  bne a0, zero, 4093  # 0x00000000",
+                "0x00000008:     0000  (not an instruction)",
+            ]
+        );
+    }
+
+    #[test]
+    fn wraps_and_caps() {
+        let cpu = CPU::new(16);
+        let lines = disassemble(&cpu, 0xffff_fffc, 2);
+        assert_eq!(lines[1].address, 0);
+        assert_eq!(disassemble(&cpu, 0, usize::MAX).len(), MAX_LINES);
+    }
+}
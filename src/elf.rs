@@ -0,0 +1,273 @@
+//! ELF executables, as a compiler or linker writes them for bare metal.
+//!
+//! Only what's needed to run a program is read: the file header (which must
+//! be a 32-bit little-endian RISC-V executable) for the entry point, and the
+//! loadable segments of the program header table for what goes where. Each
+//! segment is placed at its physical address, as a bare-metal loader does,
+//! and the part of it that's in memory but not in the file (eg: `.bss`) is
+//! zeroed. Sections and relocations are ignored, but [symbols] can be looked
+//! up (eg: to find `tohost`).
+//!
+//! The result is the same [Image] as for Intel HEX files, so it's loaded the
+//! same way.
+
+use std::fmt::Display;
+
+use crate::ihex::Image;
+
+/// `\x7fELF`, which every ELF file starts with
+pub const MAGIC: &[u8] = b"\x7fELF";
+
+const CLASS_32: u8 = 1;
+const LITTLE_ENDIAN: u8 = 1;
+const EXECUTABLE: u16 = 2;
+const RISCV: u16 = 0xf3;
+const LOAD: u32 = 1;
+const SYMBOL_TABLE: u32 = 2;
+
+/// Size of a 32-bit program header
+const PROGRAM_HEADER: usize = 32;
+/// Size of a 32-bit section header
+const SECTION_HEADER: usize = 40;
+/// Size of a 32-bit symbol table entry
+const SYMBOL: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file doesn't start with the ELF magic number
+    NotElf,
+    /// An ELF file, but not one this can run; what it is instead
+    Unsupported(&'static str),
+    /// A header or segment goes past the end of the file
+    Truncated,
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotElf => write!(f, "not an ELF file"),
+            Self::Unsupported(what) => write!(f, "can't run a {what}"),
+            Self::Truncated => write!(f, "the file ends in the middle of a header or segment"),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+/// Reads the loadable segments and entry point of an executable
+pub fn parse(bytes: &[u8]) -> Result<Image, ElfError> {
+    check(bytes)?;
+
+    let entry = word(bytes, 24)?;
+    let table = word(bytes, 28)? as usize;
+    let size = (half(bytes, 42)? as usize).max(PROGRAM_HEADER);
+    let count = half(bytes, 44)? as usize;
+
+    let mut image = Image {
+        chunks: vec![],
+        entry: Some(entry),
+    };
+    for index in 0..count {
+        let header = table + index * size;
+        if word(bytes, header)? != LOAD {
+            continue;
+        }
+        let offset = word(bytes, header + 4)? as usize;
+        let address = word(bytes, header + 12)?;
+        let file_size = word(bytes, header + 16)? as usize;
+        let memory_size = word(bytes, header + 20)? as usize;
+
+        let mut data = bytes
+            .get(offset..offset + file_size)
+            .ok_or(ElfError::Truncated)?
+            .to_vec();
+        data.resize(memory_size.max(file_size), 0);
+        if !data.is_empty() {
+            image.chunks.push((address, data));
+        }
+    }
+    Ok(image)
+}
+
+/// The named symbols in an executable's symbol tables (eg: `_start`), with
+/// their values; none if it's been stripped
+pub fn symbols(bytes: &[u8]) -> Result<Vec<(String, u32)>, ElfError> {
+    check(bytes)?;
+
+    let table = word(bytes, 32)? as usize;
+    let size = (half(bytes, 46)? as usize).max(SECTION_HEADER);
+    let count = half(bytes, 48)? as usize;
+    let section = |index: usize| table + index * size;
+
+    let mut symbols = vec![];
+    for index in 0..count {
+        let header = section(index);
+        if word(bytes, header + 4)? != SYMBOL_TABLE {
+            continue;
+        }
+        let offset = word(bytes, header + 16)? as usize;
+        let length = word(bytes, header + 20)? as usize;
+        // the names are in the string table section it links to
+        let strings = word(bytes, section(word(bytes, header + 24)? as usize) + 16)? as usize;
+
+        for entry in (offset..offset + length).step_by(SYMBOL) {
+            let name = word(bytes, entry)? as usize;
+            if name == 0 {
+                continue;
+            }
+            let text = bytes.get(strings + name..).ok_or(ElfError::Truncated)?;
+            let end = text
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or(ElfError::Truncated)?;
+            let name = String::from_utf8_lossy(&text[..end]).into_owned();
+            symbols.push((name, word(bytes, entry + 4)?));
+        }
+    }
+    Ok(symbols)
+}
+
+/// Checks the file header is for a 32-bit little-endian RISC-V executable
+fn check(bytes: &[u8]) -> Result<(), ElfError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(ElfError::NotElf);
+    }
+    if bytes.get(4) != Some(&CLASS_32) {
+        return Err(ElfError::Unsupported("64-bit ELF file"));
+    }
+    if bytes.get(5) != Some(&LITTLE_ENDIAN) {
+        return Err(ElfError::Unsupported("big-endian ELF file"));
+    }
+    if half(bytes, 18)? != RISCV {
+        return Err(ElfError::Unsupported("program for another architecture"));
+    }
+    if half(bytes, 16)? != EXECUTABLE {
+        return Err(ElfError::Unsupported("relocatable or shared object"));
+    }
+    Ok(())
+}
+
+/// The 16-bit field at `offset`
+fn half(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    match bytes.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(ElfError::Truncated),
+    }
+}
+
+/// The 32-bit field at `offset`
+fn word(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(ElfError::Truncated),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An executable with the given segments (each loaded at its address)
+    /// and symbols
+    pub(crate) fn executable(
+        entry: u32,
+        segments: &[(u32, &[u8])],
+        symbols: &[(&str, u32)],
+    ) -> Vec<u8> {
+        let put = |elf: &mut Vec<u8>, at: usize, value: u32| {
+            elf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        let mut elf = vec![0; 52 + segments.len() * PROGRAM_HEADER];
+        elf[..4].copy_from_slice(MAGIC);
+        elf[4] = CLASS_32;
+        elf[5] = LITTLE_ENDIAN;
+        elf[16..18].copy_from_slice(&EXECUTABLE.to_le_bytes());
+        elf[18..20].copy_from_slice(&RISCV.to_le_bytes());
+        put(&mut elf, 24, entry);
+        put(&mut elf, 28, 52);
+        elf[42..44].copy_from_slice(&(PROGRAM_HEADER as u16).to_le_bytes());
+        elf[44..46].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+        for (index, (address, data)) in segments.iter().enumerate() {
+            let header = 52 + index * PROGRAM_HEADER;
+            let offset = elf.len() as u32;
+            // type, offset, virtual and physical address, sizes in the file
+            // and in memory (a segment with no data is all .bss)
+            let (file, memory) = match data.iter().all(|b| *b == 0) {
+                true => (0, data.len() as u32),
+                false => (data.len() as u32, data.len() as u32),
+            };
+            for (field, value) in [LOAD, offset, *address, *address, file, memory]
+                .iter()
+                .enumerate()
+            {
+                put(&mut elf, header + field * 4, *value);
+            }
+            elf.extend(&data[..file as usize]);
+        }
+
+        // a symbol table (after an empty entry), then the names
+        let mut names = vec![0];
+        let table = elf.len();
+        elf.extend([0; SYMBOL]);
+        for (name, value) in symbols {
+            let entry = elf.len();
+            elf.extend([0; SYMBOL]);
+            put(&mut elf, entry, names.len() as u32);
+            put(&mut elf, entry + 4, *value);
+            names.extend(name.bytes().chain([0]));
+        }
+        let strings = elf.len();
+        elf.extend(&names);
+
+        // sections: the empty one, the symbol table, then the names
+        let sections = elf.len();
+        put(&mut elf, 32, sections as u32);
+        elf[46..48].copy_from_slice(&(SECTION_HEADER as u16).to_le_bytes());
+        elf[48..50].copy_from_slice(&3u16.to_le_bytes());
+        elf.extend([0; 3 * SECTION_HEADER]);
+        let symbol_table = sections + SECTION_HEADER;
+        put(&mut elf, symbol_table + 4, SYMBOL_TABLE);
+        put(&mut elf, symbol_table + 16, table as u32);
+        put(&mut elf, symbol_table + 20, (strings - table) as u32);
+        put(&mut elf, symbol_table + 24, 2);
+        let string_table = sections + 2 * SECTION_HEADER;
+        put(&mut elf, string_table + 4, 3);
+        put(&mut elf, string_table + 16, strings as u32);
+        put(&mut elf, string_table + 20, names.len() as u32);
+        elf
+    }
+
+    #[test]
+    fn segments_and_symbols() {
+        // NOP; ADDI a0, a0, 1
+        let code = [0x13, 0, 0, 0, 0x13, 0x05, 0x15, 0];
+        let elf = executable(
+            0x104,
+            &[(0x100, &code), (0x200, &[0; 4])],
+            &[("_start", 0x104), ("tohost", 0x200)],
+        );
+
+        let image = parse(&elf).unwrap();
+        assert_eq!(image.entry, Some(0x104));
+        assert_eq!(
+            image.chunks,
+            vec![(0x100, code.to_vec()), (0x200, vec![0; 4])]
+        );
+        assert_eq!(
+            symbols(&elf).unwrap(),
+            [("_start".to_owned(), 0x104), ("tohost".to_owned(), 0x200)]
+        );
+
+        let mut elf64 = elf.clone();
+        elf64[4] = 2;
+        assert_eq!(parse(&elf64), Err(ElfError::Unsupported("64-bit ELF file")));
+        let mut x86 = elf.clone();
+        x86[18] = 0x3e;
+        assert!(matches!(parse(&x86), Err(ElfError::Unsupported(_))));
+        assert_eq!(parse(&elf[..100]), Err(ElfError::Truncated));
+        assert_eq!(symbols(&elf[..100]), Err(ElfError::Truncated));
+        assert_eq!(parse(b"#!/bin/sh"), Err(ElfError::NotElf));
+    }
+}
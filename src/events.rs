@@ -0,0 +1,146 @@
+//! Events emitted by the [Interpreter](crate::Interpreter) as it runs.
+//!
+//! Anything that wants to follow along (a tracer, a grader, a UI) registers a
+//! [Subscriber] and receives every [Event] as it happens, instead of needing
+//! its own hooks into the interpreter.
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! use brubeck::events::Event;
+//! use brubeck::Interpreter;
+//!
+//! let retired = Rc::new(RefCell::new(0));
+//! let counter = retired.clone();
+//!
+//! let mut i = Interpreter::new();
+//! i.subscribe(move |event: &Event| {
+//!     if let Event::InstructionRetired { .. } = event {
+//!         *counter.borrow_mut() += 1;
+//!     }
+//! });
+//!
+//! i.interpret("ADDI x1, zero, 1").unwrap();
+//! i.interpret("NOP").unwrap();
+//! assert_eq!(*retired.borrow(), 2);
+//! ```
+
+use std::fmt::Display;
+
+use crate::rv32_i::{Instruction, TrapCause};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// An instruction finished executing; `pc` is where it was executed from
+    InstructionRetired { pc: u32, instruction: Instruction },
+    /// An `EBREAK` (that wasn't a semihosting call) was reached
+    BreakpointHit { pc: u32 },
+    /// Execution was diverted to a trap handler
+    TrapTaken { pc: u32, cause: u32 },
+    /// Bytes were stored to memory
+    MemoryWritten { address: u32, bytes: Vec<u8> },
+    /// A program was loaded into memory, starting at `entry`
+    ProgramLoaded { entry: u32, size: usize },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InstructionRetired { pc, instruction } => {
+                write!(f, "0x{pc:08x}: retired {instruction:?}")
+            }
+            Self::BreakpointHit { pc } => write!(f, "0x{pc:08x}: breakpoint"),
+            Self::TrapTaken { pc, cause } => {
+                write!(f, "0x{pc:08x}: trap, {}", TrapCause::describe(*cause))
+            }
+            Self::MemoryWritten { address, bytes } => {
+                write!(f, "wrote {} bytes at 0x{address:08x}", bytes.len())
+            }
+            Self::ProgramLoaded { entry, size } => {
+                write!(f, "loaded {size} bytes at 0x{entry:08x}")
+            }
+        }
+    }
+}
+
+/// Receives [Event]s. Implemented for any `FnMut(&Event)` closure.
+pub trait Subscriber {
+    fn notify(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> Subscriber for F {
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Identifies a subscription so it can be cancelled later
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SubscriptionId(usize);
+
+/// Delivers events to subscribers, in the order they subscribed.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<(SubscriptionId, Box<dyn Subscriber>)>,
+    next_id: usize,
+}
+
+impl EventBus {
+    pub fn subscribe<S: Subscriber + 'static>(&mut self, subscriber: S) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.push((id, Box::new(subscriber)));
+        id
+    }
+
+    /// Removes a subscriber; returns false if it wasn't subscribed
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|(i, _)| *i != id);
+        self.subscribers.len() != before
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        for (_, subscriber) in self.subscribers.iter_mut() {
+            subscriber.notify(&event);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn subscribe_and_unsubscribe() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut bus = EventBus::default();
+
+        let log = seen.clone();
+        let id = bus.subscribe(move |e: &Event| log.borrow_mut().push(e.clone()));
+
+        bus.emit(Event::BreakpointHit { pc: 4 });
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.emit(Event::BreakpointHit { pc: 8 });
+
+        assert_eq!(*seen.borrow(), vec![Event::BreakpointHit { pc: 4 }]);
+        assert!(bus.is_empty());
+    }
+
+    #[test]
+    fn display() {
+        let trap = Event::TrapTaken { pc: 8, cause: 11 };
+        assert_eq!(
+            trap.to_string(),
+            "0x00000008: trap, environment call from M-mode (11)"
+        );
+    }
+}
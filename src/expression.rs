@@ -0,0 +1,508 @@
+//! A small expression evaluator shared by everything that accepts arithmetic
+//! in place of a plain number.
+//!
+//! Expressions use C-like operators and precedence over 32-bit values, with
+//! wrapping arithmetic:
+//!
+//! | precedence | operators                                        |
+//! |------------|--------------------------------------------------|
+//! | highest    | unary `-`, `~`, `( )`, `[ ]`, `%hi( )`, `%lo( )` |
+//! |            | `*`, `/`, `%`                                    |
+//! |            | `+`, `-`                                         |
+//! |            | `<<`, `>>`                                       |
+//! |            | `&`                                              |
+//! |            | `^`                                              |
+//! | lowest     | `\|`                                             |
+//!
+//! Numbers can be decimal, `0x` hex, or `0b` binary, with `_` separators
+//! anywhere after the first digit (eg: `0x1000_0000`, `1_000_000`). Character
+//! literals (eg: `'A'`, `'\n'`, `'\x41'`) are their ASCII value. Names (eg: `x5`, `sp`,
+//! `mepc`) and memory reads (`[sp + 4]` or `mem[sp + 4]`, one little endian
+//! word) are looked up in an [Environment]; [Constants] has neither, for expressions that must be
+//! known up front.
+//!
+//! `%hi(value)` and `%lo(value)` are the assembler's relocation operators:
+//! the upper 20 and lower 12 bits of a value, for a `LUI` (or `AUIPC`) and
+//! the `ADDI` after it (see [hi_lo]).
+//!
+//! ```
+//! use brubeck::expression::{evaluate, Constants};
+//!
+//! assert_eq!(evaluate("(1 << 20) - 4", &Constants), Ok(0xffffc));
+//! assert_eq!(evaluate("-1", &Constants), Ok(u32::MAX));
+//! assert_eq!(evaluate("%hi(0x12345fff)", &Constants), Ok(0x12346));
+//! ```
+
+use std::fmt::Display;
+
+/// Supplies the values of names and memory while evaluating
+pub trait Environment {
+    /// The value of a name such as a register, or None if it isn't known
+    fn symbol(&self, name: &str) -> Option<u32>;
+
+    /// The word at `address`, or None if it can't be read
+    fn word(&self, address: u32) -> Option<u32>;
+}
+
+/// An [Environment] with no names or memory
+pub struct Constants;
+
+impl Environment for Constants {
+    fn symbol(&self, _name: &str) -> Option<u32> {
+        None
+    }
+
+    fn word(&self, _address: u32) -> Option<u32> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpressionError {
+    /// Nothing to evaluate
+    Empty,
+    /// A character that can't start a token, and its position
+    UnexpectedCharacter(char, usize),
+    /// A token that doesn't fit where it was found
+    UnexpectedToken(String),
+    /// A number that doesn't fit in 32 bits, or isn't a number
+    InvalidNumber(String),
+    UnknownName(String),
+    /// Memory at this address can't be read
+    UnreadableMemory(u32),
+    DivisionByZero,
+    /// Parentheses, brackets, or unary operators nested more than
+    /// [MAX_DEPTH] deep
+    TooDeep,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty expression"),
+            Self::UnexpectedCharacter(c, at) => {
+                write!(f, "unexpected character '{c}' at position {at}")
+            }
+            Self::UnexpectedToken(token) => write!(f, "unexpected '{token}'"),
+            Self::InvalidNumber(number) => write!(f, "invalid number {number}"),
+            Self::UnknownName(name) => write!(f, "unknown name {name}"),
+            Self::UnreadableMemory(address) => {
+                write!(f, "can't read memory at 0x{address:08x}")
+            }
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::TooDeep => write!(f, "nested more than {MAX_DEPTH} deep"),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+/// How deeply parentheses, brackets, and unary operators can nest, so a
+/// long enough expression can't overflow the stack
+pub const MAX_DEPTH: usize = 256;
+
+/// Evaluates an expression, looking up names and memory in `environment`
+pub fn evaluate(input: &str, environment: &dyn Environment) -> Result<u32, ExpressionError> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err(ExpressionError::Empty);
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        depth: 0,
+        environment,
+    };
+    let value = parser.expression(0)?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(ExpressionError::UnexpectedToken(token.to_string())),
+    }
+}
+
+/// Parses a number literal: decimal, `0x` hex, or `0b` binary, ignoring `_`
+/// separators
+pub fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase().replace('_', "");
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Parses a character literal at the start of `text`: a single ASCII
+/// character in quotes, or one of the escapes `\n`, `\t`, `\r`, `\0`, `\\`,
+/// `\'`, and `\xNN`. Returns its value and how many bytes it took up.
+///
+/// ```
+/// use brubeck::expression::parse_char;
+///
+/// assert_eq!(parse_char("'A'"), Some((65, 3)));
+/// assert_eq!(parse_char("'\\n', x1"), Some((10, 4)));
+/// assert_eq!(parse_char("'\\x41'"), Some((0x41, 6)));
+/// assert_eq!(parse_char("'AB'"), None);
+/// ```
+pub fn parse_char(text: &str) -> Option<(u32, usize)> {
+    let rest = text.strip_prefix('\'')?;
+    let (value, len) = match rest.strip_prefix('\\') {
+        Some(escape) => match escape.chars().next()? {
+            'n' => (b'\n', 2),
+            't' => (b'\t', 2),
+            'r' => (b'\r', 2),
+            '0' => (0, 2),
+            '\\' => (b'\\', 2),
+            '\'' => (b'\'', 2),
+            'x' => (u8::from_str_radix(escape.get(1..3)?, 16).ok()?, 4),
+            _ => return None,
+        },
+        None => match rest.chars().next()? {
+            c if c.is_ascii() && c != '\'' => (c as u8, 1),
+            _ => return None,
+        },
+    };
+
+    // quote, character, quote
+    rest[len..]
+        .starts_with('\'')
+        .then_some((value as u32, len + 2))
+}
+
+/// Splits a value into the upper 20 bits for a `LUI` or `AUIPC`, and the
+/// lower 12 bits for the `ADDI` (or load, store, or `JALR`) after it. The
+/// lower part is sign-extended when it's used, so the upper part is rounded
+/// up when bit 11 is set; `(hi << 12) + sign_extend(lo)` is the value.
+pub fn hi_lo(value: u32) -> (u32, u32) {
+    (value.wrapping_add(0x800) >> 12, value & 0xfff)
+}
+
+/// Splits digits into groups of `size` with `_`, counting from the right
+/// (eg: `1000000` becomes `1_000_000` in groups of 3)
+pub fn separated(digits: &str, size: usize) -> String {
+    let mut output = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(size) {
+            output.push('_');
+        }
+        output.push(c);
+    }
+    output
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u32),
+    Name(String),
+    /// An operator or bracket
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 15] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "[", "]",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut rest = input;
+
+    while let Some(c) = rest.chars().next() {
+        let position = input.len() - rest.len();
+
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '\'' {
+            let (value, len) =
+                parse_char(rest).ok_or_else(|| ExpressionError::InvalidNumber(rest.to_owned()))?;
+            tokens.push(Token::Number(value));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            rest = &rest[end..];
+
+            if c.is_ascii_digit() {
+                let number = parse_number(word)
+                    .ok_or_else(|| ExpressionError::InvalidNumber(word.to_owned()))?;
+                tokens.push(Token::Number(number));
+            } else {
+                tokens.push(Token::Name(word.to_owned()));
+            }
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            return Err(ExpressionError::UnexpectedCharacter(c, position));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Binding strength of each binary operator; higher binds tighter
+fn precedence(symbol: &str) -> Option<u8> {
+    match symbol {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-" => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// How many operands are being parsed inside each other
+    depth: usize,
+    environment: &'a dyn Environment,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, ExpressionError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or(ExpressionError::UnexpectedToken(
+                "end of expression".to_owned(),
+            ))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ExpressionError> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => Err(ExpressionError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    /// Precedence climbing: parses operators binding tighter than `minimum`
+    fn expression(&mut self, minimum: u8) -> Result<u32, ExpressionError> {
+        let mut left = self.unary()?;
+
+        while let Some(Token::Symbol(symbol)) = self.peek() {
+            let symbol = *symbol;
+            let Some(strength) = precedence(symbol).filter(|s| *s > minimum) else {
+                break;
+            };
+            self.position += 1;
+            let right = self.expression(strength)?;
+            left = apply(symbol, left, right)?;
+        }
+
+        Ok(left)
+    }
+
+    /// An operand, counting how deeply they're nested
+    fn unary(&mut self) -> Result<u32, ExpressionError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ExpressionError::TooDeep);
+        }
+        self.depth += 1;
+        let value = self.operand();
+        self.depth -= 1;
+        value
+    }
+
+    fn operand(&mut self) -> Result<u32, ExpressionError> {
+        match self.next()? {
+            Token::Number(n) => Ok(n),
+            Token::Name(name) if name == "mem" && self.peek() == Some(&Token::Symbol("[")) => {
+                self.position += 1;
+                self.memory()
+            }
+            Token::Name(name) => self
+                .environment
+                .symbol(&name)
+                .ok_or(ExpressionError::UnknownName(name)),
+            Token::Symbol("-") => Ok(self.unary()?.wrapping_neg()),
+            Token::Symbol("~") => Ok(!self.unary()?),
+            Token::Symbol("(") => {
+                let value = self.expression(0)?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Token::Symbol("[") => self.memory(),
+            Token::Symbol("%") => {
+                let part = match self.next()? {
+                    Token::Name(name) if name.eq_ignore_ascii_case("hi") => 0,
+                    Token::Name(name) if name.eq_ignore_ascii_case("lo") => 1,
+                    token => return Err(ExpressionError::UnexpectedToken(token.to_string())),
+                };
+                self.expect("(")?;
+                let (hi, lo) = hi_lo(self.expression(0)?);
+                self.expect(")")?;
+                Ok([hi, lo][part])
+            }
+            token => Err(ExpressionError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    /// The word at the address inside `[ ]`, once past the `[`
+    fn memory(&mut self) -> Result<u32, ExpressionError> {
+        let address = self.expression(0)?;
+        self.expect("]")?;
+        self.environment
+            .word(address)
+            .ok_or(ExpressionError::UnreadableMemory(address))
+    }
+}
+
+fn apply(symbol: &str, left: u32, right: u32) -> Result<u32, ExpressionError> {
+    Ok(match symbol {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" => left
+            .checked_div(right)
+            .ok_or(ExpressionError::DivisionByZero)?,
+        "%" => left
+            .checked_rem(right)
+            .ok_or(ExpressionError::DivisionByZero)?,
+        "<<" => left.wrapping_shl(right),
+        ">>" => left.wrapping_shr(right),
+        "&" => left & right,
+        "^" => left ^ right,
+        "|" => left | right,
+        _ => unreachable!("{symbol} has a precedence but no meaning"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Machine;
+
+    impl Environment for Machine {
+        fn symbol(&self, name: &str) -> Option<u32> {
+            match name {
+                "x5" => Some(3),
+                "sp" => Some(0x100),
+                _ => None,
+            }
+        }
+
+        fn word(&self, address: u32) -> Option<u32> {
+            (address == 0x104).then_some(42)
+        }
+    }
+
+    #[test]
+    fn precedence_and_literals() {
+        let eval = |input| evaluate(input, &Constants);
+
+        assert_eq!(eval("10 + 5"), Ok(15));
+        assert_eq!(eval("2 + 3 * 4"), Ok(14));
+        assert_eq!(eval("(2 + 3) * 4"), Ok(20));
+        assert_eq!(eval("0x1000 >> 12"), Ok(1));
+        assert_eq!(eval("1 << 4 | 1"), Ok(17));
+        assert_eq!(eval("0b1010 & ~0b10"), Ok(8));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("-(3)"), Ok(-3i32 as u32));
+        assert_eq!(eval("7 % 4 ^ 1"), Ok(2));
+        assert_eq!(eval("0x1000_0000 + 1_000_000"), Ok(0x100f_4240));
+        assert_eq!(eval("0b1010_1010"), Ok(0xaa));
+        assert_eq!(eval("'a' - 'A'"), Ok(32));
+        assert_eq!(eval("'\\0' + '\\''"), Ok(39));
+        assert_eq!(eval("%hi(0x12345678)"), Ok(0x12345));
+        assert_eq!(eval("%lo(0x12345678)"), Ok(0x678));
+        assert_eq!(eval("%HI(0x800) << 12 | %lo(0x7ff)"), Ok(0x17ff));
+        assert_eq!(eval("%hi(-1)"), Ok(0));
+        assert_eq!(eval("7 % 4"), Ok(3));
+        assert_eq!(
+            eval("%mid(1)"),
+            Err(ExpressionError::UnexpectedToken("mid".to_owned()))
+        );
+        assert_eq!(
+            eval("'ab'"),
+            Err(ExpressionError::InvalidNumber("'ab'".to_owned()))
+        );
+        assert_eq!(
+            eval("0x_"),
+            Err(ExpressionError::InvalidNumber("0x_".to_owned()))
+        );
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(separated("1000000", 3), "1_000_000");
+        assert_eq!(separated("100", 3), "100");
+        assert_eq!(separated("1000f4240", 4), "1_000f_4240");
+        assert_eq!(separated("", 4), "");
+    }
+
+    #[test]
+    fn names_and_memory() {
+        assert_eq!(evaluate("x5 + x5 * 2", &Machine), Ok(9));
+        assert_eq!(evaluate("[sp + 4] + 1", &Machine), Ok(43));
+        assert_eq!(evaluate("mem[sp + 4]", &Machine), Ok(42));
+        assert_eq!(
+            evaluate("[sp]", &Machine),
+            Err(ExpressionError::UnreadableMemory(0x100))
+        );
+        assert_eq!(
+            evaluate("x5 + t0", &Machine),
+            Err(ExpressionError::UnknownName("t0".to_owned()))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let eval = |input| evaluate(input, &Constants);
+
+        assert_eq!(eval(""), Err(ExpressionError::Empty));
+        assert_eq!(eval("1 / 0"), Err(ExpressionError::DivisionByZero));
+        assert_eq!(
+            eval("(1 + 2"),
+            Err(ExpressionError::UnexpectedToken(
+                "end of expression".to_owned()
+            ))
+        );
+        assert_eq!(
+            eval("1 2"),
+            Err(ExpressionError::UnexpectedToken("2".to_owned()))
+        );
+        assert_eq!(
+            eval("1 $ 2"),
+            Err(ExpressionError::UnexpectedCharacter('$', 2))
+        );
+        assert_eq!(
+            eval("0x1_0000_0000"),
+            Err(ExpressionError::InvalidNumber("0x1_0000_0000".to_owned()))
+        );
+
+        let nested = |depth| "(".repeat(depth) + "1" + &")".repeat(depth);
+        assert_eq!(evaluate(&nested(MAX_DEPTH - 1), &Constants), Ok(1));
+        assert_eq!(
+            evaluate(&nested(MAX_DEPTH), &Constants),
+            Err(ExpressionError::TooDeep)
+        );
+        assert_eq!(
+            evaluate(&"-".repeat(100_000), &Constants),
+            Err(ExpressionError::TooDeep)
+        );
+    }
+}
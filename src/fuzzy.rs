@@ -0,0 +1,92 @@
+//! Finding the names closest to a misspelled one, for "did you mean"
+//! suggestions (eg: `ADD` for `ADDD`, or `mstatus` for `MSTATS`).
+//!
+//! ```
+//! use brubeck::fuzzy;
+//!
+//! assert_eq!(fuzzy::distance("addd", "ADD"), 1);
+//! let names = ["ADD", "ADDI", "AND", "SUB"];
+//! assert_eq!(fuzzy::suggest("ADDD", names), ["ADD", "ADDI"]);
+//! ```
+
+use std::collections::BTreeSet;
+
+/// At most this many suggestions are made for a word
+pub const LIMIT: usize = 3;
+
+/// The edit (Levenshtein) distance: how many characters have to be
+/// inserted, deleted, or replaced to turn one string into the other,
+/// ignoring case
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().map(|c| c.to_ascii_uppercase()).collect();
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_uppercase()).collect();
+
+    // distances from the first i characters of `a` to each prefix of `b`
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = Vec::with_capacity(b.len() + 1);
+        current.push(i + 1);
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != cb);
+            let delete = previous[j + 1] + 1;
+            let insert = current[j] + 1;
+            current.push(replace.min(delete).min(insert));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How far off a candidate can be and still be suggested: one edit for
+/// short words, where two would match almost anything, and two otherwise
+fn tolerance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 1,
+        _ => 2,
+    }
+}
+
+/// Up to [LIMIT] candidates close enough to `word` to be what was meant,
+/// in the order given. Only the nearest are suggested (`ADDD` is one edit
+/// from `ADD`, so `AND`, two edits away, isn't suggested with it). The word
+/// itself isn't suggested, and a candidate given twice is suggested once.
+pub fn suggest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = BTreeSet::new();
+    let close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| seen.insert(candidate.to_ascii_uppercase()))
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|(distance, _)| (1..=tolerance(word)).contains(distance))
+        .collect();
+    let nearest = close.iter().map(|(distance, _)| *distance).min();
+    close
+        .into_iter()
+        .filter(|(distance, _)| Some(*distance) == nearest)
+        .take(LIMIT)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_and_suggestions() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("MSTATS", "mstatus"), 1);
+        assert_eq!(distance("add", "ADD"), 0);
+
+        let names = ["SUB", "ADDW", "ADD", "ADDI", "add", "AND", "LUI"];
+        // the nearest, each once, and no more than the limit
+        assert_eq!(suggest("ADDD", names), ["ADDW", "ADD", "ADDI"]);
+        assert_eq!(suggest("ANDD", names), ["ADD", "AND"]);
+        assert_eq!(suggest("SUBBB", names), ["SUB"]);
+        // short words only get one edit
+        assert_eq!(suggest("X", ["X1", "X10", "SP"]), ["X1"]);
+        // not the word itself, or anything too far off
+        assert!(suggest("ADD", ["ADD"]).is_empty());
+        assert!(suggest("BOGUS", names).is_empty());
+    }
+}
@@ -0,0 +1,493 @@
+//! Running a program alongside a reference emulator's record of the same
+//! run, and finding the first instruction where they differ.
+//!
+//! The reference is [Spike](https://github.com/riscv-software-src/riscv-isa-sim)'s
+//! commit log (`spike --isa=rv32imac --log-commits prog 2> prog.log`), which
+//! has a line for each instruction it completes: the privilege level, the PC,
+//! the machine word, then the registers it wrote and the memory it stored to:
+//!
+//! ```text
+//! core   0: 3 0x80000000 (0x00500293) x5  0x00000005
+//! core   0: 3 0x80000004 (0x0052a023) mem 0x80001000 0x00000005
+//! core   0: 3 0x80000008 (0x4501) x10 0x00000000
+//! ```
+//!
+//! [compare] steps the [Interpreter] once for each line, checking that it
+//! ran the same instruction from the same place, wrote the same values to the
+//! same registers, and stored the same bytes, and stops at the first
+//! [Divergence] with the machine left just after the instruction (so it can
+//! be inspected, or undone). Anything else in the log (eg: Spike's notes on
+//! exceptions) is skipped, as are the CSRs and floating point registers.
+//!
+//! A few things differ by design rather than by mistake:
+//!
+//! - Spike starts in a boot ROM that jumps to the program, so the log is
+//!   followed from its first line at the current PC
+//! - an instruction that traps isn't in the log, so one that traps here is
+//!   run without being compared, as long as the log carries on from where
+//!   the trap went
+//! - stores to a device (eg: `tohost`) aren't checked, since devices don't
+//!   keep what's written to them like memory does
+//! - the comparison ends when the program exits, as Spike goes on running
+//!   until its host notices
+//!
+//! For comparing each instruction with Brubeck's own second model instead,
+//! see [set_double_check](Interpreter::set_double_check).
+
+use std::fmt::Display;
+
+use crate::history::StateDelta;
+use crate::interpreter::Interpreter;
+use crate::rv32_i::{Instruction, Register};
+
+/// An instruction the reference emulator completed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Commit {
+    pub pc: u32,
+    /// The machine word, or the 16-bit one for a compressed instruction
+    pub word: u32,
+    /// Registers written, in order, with their new values
+    pub registers: Vec<(Register, u32)>,
+    /// Memory stored to: the address, and the bytes stored there
+    pub stores: Vec<(u32, Vec<u8>)>,
+}
+
+/// A line of a commit log that can't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Counting from 1
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reads the commit lines of a Spike log, skipping anything else
+pub fn parse_log(log: &str) -> Result<Vec<Commit>, ParseError> {
+    let mut commits = vec![];
+
+    for (index, line) in log.lines().enumerate() {
+        let error = |reason| ParseError {
+            line: index + 1,
+            reason,
+        };
+
+        // core, hart, privilege level, then the instruction; other lines
+        // from the same core have words where the privilege level would be
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("core") || tokens.next().is_none() {
+            continue;
+        }
+        if !tokens
+            .next()
+            .is_some_and(|p| p.len() == 1 && p.as_bytes()[0].is_ascii_digit())
+        {
+            continue;
+        }
+
+        let pc = tokens
+            .next()
+            .and_then(hex)
+            .ok_or(error("expected the PC"))?;
+        let word = tokens
+            .next()
+            .and_then(|w| w.strip_prefix('(')?.strip_suffix(')'))
+            .and_then(hex)
+            .ok_or(error("expected the instruction, eg: (0x00000013)"))?;
+        let mut commit = Commit {
+            pc: pc as u32,
+            word: word as u32,
+            ..Commit::default()
+        };
+
+        let mut tokens = tokens.peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                "mem" => {
+                    let address = tokens
+                        .next()
+                        .and_then(hex)
+                        .ok_or(error("expected an address after mem"))?;
+                    // a load has only the address
+                    if !tokens.peek().is_some_and(|t| t.starts_with("0x")) {
+                        continue;
+                    }
+                    let value = tokens.next().unwrap_or_default();
+                    // as many bytes as the value has digits for
+                    let length = (value.len() - 2) / 2;
+                    let bytes = hex(value)
+                        .ok_or(error("expected the value stored"))?
+                        .to_le_bytes();
+                    commit
+                        .stores
+                        .push((address as u32, bytes[..length.min(8)].to_vec()));
+                }
+                _ => {
+                    let value = tokens
+                        .next()
+                        .and_then(hex)
+                        .ok_or(error("expected a register's value"))?;
+                    // CSRs (eg: c768_mstatus) and floating point registers
+                    // aren't compared
+                    let number = token.strip_prefix('x').and_then(|n| n.parse().ok());
+                    if let Some(register) = number.and_then(|n: usize| Register::ALL.get(n)) {
+                        commit.registers.push((*register, value as u32));
+                    }
+                }
+            }
+        }
+        commits.push(commit);
+    }
+
+    Ok(commits)
+}
+
+/// Where the run first differed from the reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Which commit in the log, counting from 0
+    pub step: usize,
+    /// Where the instruction was, here
+    pub pc: u32,
+    pub difference: Difference,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// The reference ran the instruction at another address
+    Pc { expected: u32 },
+    /// The reference ran another instruction from this address
+    Word { expected: u32, actual: u32 },
+    /// A register has another value, or one was written that the reference
+    /// didn't write (`expected` is `None`)
+    Register {
+        register: Register,
+        expected: Option<u32>,
+        actual: u32,
+    },
+    /// Memory holds other bytes, or was stored to where the reference didn't
+    /// store (`expected` is `None`)
+    Memory {
+        address: u32,
+        expected: Option<Vec<u8>>,
+        actual: Vec<u8>,
+    },
+    /// The instruction couldn't be run here
+    Failed(String),
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Step {} at 0x{:08x}: ", self.step, self.pc)?;
+        match &self.difference {
+            Difference::Pc { expected } => {
+                write!(f, "the reference ran the instruction at 0x{expected:08x}")
+            }
+            Difference::Word { expected, actual } => write!(
+                f,
+                "the instruction is 0x{actual:08x}, but the reference ran 0x{expected:08x}"
+            ),
+            Difference::Register {
+                register,
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "{} is 0x{actual:08x}, but the reference has 0x{expected:08x}",
+                name(*register)
+            ),
+            Difference::Register {
+                register,
+                expected: None,
+                actual,
+            } => write!(
+                f,
+                "{} was set to 0x{actual:08x}, but the reference didn't write it",
+                name(*register)
+            ),
+            Difference::Memory {
+                address,
+                expected: Some(expected),
+                actual,
+            } => write!(
+                f,
+                "memory at 0x{address:08x} is {}, but the reference stored {}",
+                bytes(actual),
+                bytes(expected)
+            ),
+            Difference::Memory {
+                address,
+                expected: None,
+                actual,
+            } => write!(
+                f,
+                "{} was stored at 0x{address:08x}, but the reference didn't store there",
+                bytes(actual)
+            ),
+            Difference::Failed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+/// Runs an instruction for each commit in the log (from the first at the
+/// current PC), returning how many matched, or where the run first differed
+pub fn compare(interpreter: &mut Interpreter, log: &[Commit]) -> Result<usize, Divergence> {
+    let start = log
+        .iter()
+        .position(|commit| commit.pc == interpreter.cpu.pc)
+        .unwrap_or_default();
+
+    let mut matched = 0;
+    for (step, commit) in log.iter().enumerate().skip(start) {
+        if interpreter.exit_code().is_some() {
+            break;
+        }
+        let before = interpreter.cpu.pc;
+        skip_traps(interpreter, commit.pc).map_err(|difference| Divergence {
+            step,
+            pc: before,
+            difference,
+        })?;
+        let pc = interpreter.cpu.pc;
+        let diverged = |difference| Divergence {
+            step,
+            pc,
+            difference,
+        };
+
+        let word = word(interpreter);
+        let delta = interpreter
+            .step()
+            .map_err(|e| diverged(Difference::Failed(e.to_string())))?;
+        if word != Some(commit.word) {
+            return Err(diverged(Difference::Word {
+                expected: commit.word,
+                actual: word.unwrap_or_default(),
+            }));
+        }
+        check(interpreter, commit, &delta).map_err(diverged)?;
+        matched += 1;
+    }
+
+    Ok(matched)
+}
+
+/// Runs instructions that trap until the PC is where the reference's next
+/// instruction is. An instruction that doesn't trap is undone again.
+fn skip_traps(interpreter: &mut Interpreter, expected: u32) -> Result<(), Difference> {
+    while interpreter.cpu.pc != expected {
+        interpreter
+            .step()
+            .map_err(|e| Difference::Failed(e.to_string()))?;
+        if interpreter.cpu.last_trap().is_none() {
+            let _ = interpreter.step_back(1);
+            return Err(Difference::Pc { expected });
+        }
+    }
+    Ok(())
+}
+
+/// The registers and memory an instruction changed, against the reference's
+fn check(interpreter: &Interpreter, commit: &Commit, delta: &StateDelta) -> Result<(), Difference> {
+    let cpu = &interpreter.cpu;
+
+    for &(register, expected) in &commit.registers {
+        let actual = cpu.get_register(register);
+        if actual != expected {
+            return Err(Difference::Register {
+                register,
+                expected: Some(expected),
+                actual,
+            });
+        }
+    }
+    for change in &delta.registers {
+        let written = commit.registers.iter().any(|(r, _)| *r == change.register);
+        if change.register != Register::PC && !written {
+            return Err(Difference::Register {
+                register: change.register,
+                expected: None,
+                actual: change.new as u32,
+            });
+        }
+    }
+
+    for (address, expected) in &commit.stores {
+        if cpu.devices.contains(*address) {
+            continue;
+        }
+        let actual: Vec<u8> = (0..expected.len() as u32)
+            .map(|i| cpu.read_mem_u8(address.wrapping_add(i)).unwrap_or_default())
+            .collect();
+        if actual != *expected {
+            return Err(Difference::Memory {
+                address: *address,
+                expected: Some(expected.clone()),
+                actual,
+            });
+        }
+    }
+    for write in &delta.memory {
+        if !commit
+            .stores
+            .iter()
+            .any(|(address, _)| *address == write.address)
+        {
+            return Err(Difference::Memory {
+                address: write.address,
+                expected: None,
+                actual: write.new.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The instruction word at the PC, as the reference shows it: 16 bits for
+/// a compressed instruction
+fn word(interpreter: &Interpreter) -> Option<u32> {
+    let cpu = &interpreter.cpu;
+    let half = cpu.read_mem_u16(cpu.pc).ok()? as u32;
+    match cpu.config.extensions.c && Instruction::is_compressed(half) {
+        true => Some(half),
+        false => cpu.read_mem_u32(cpu.pc).ok(),
+    }
+}
+
+/// A register's ABI name (eg: `t1`), as the trace shows it
+fn name(register: Register) -> String {
+    register
+        .abi()
+        .map_or("?".to_owned(), |abi| abi.name().to_lowercase())
+}
+
+/// A number from the log, which is always in hex
+fn hex(text: &str) -> Option<u64> {
+    u64::from_str_radix(text.strip_prefix("0x")?, 16).ok()
+}
+
+/// Bytes as they're shown in a divergence, eg: `05 00 00 00`
+fn bytes(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    hex.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{csr, EcallPolicy};
+
+    const LOG: &str = "\
+core   0: 0x00001000 (0x00000297) auipc   t0, 0x0
+core   0: 3 0x00001000 (0x00000297) x5  0x00001000
+core   0: 3 0x00000000 (0x00500293) x5  0x00000005
+core   0: 3 0x00000004 (0x10502023) mem 0x00000100 0x00000005
+core   0: 3 0x00000008 (0x10001303) x6  0x00000005 mem 0x00000100
+core   0: exception trap_machine_ecall, epc 0x0000000c
+core   0: 3 0x00000040 (0x00000013)
+";
+
+    /// ADDI t0, zero, 5; SW t0, 256(zero); LH t1, 256(zero); ECALL, which
+    /// traps to a NOP at 0x40
+    fn program() -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        let words: [u32; 4] = [0x0050_0293, 0x1050_2023, 0x1000_1303, 0x0000_0073];
+        for (index, word) in words.iter().enumerate() {
+            let address = index as u32 * 4;
+            interpreter
+                .cpu
+                .write_memory(address, &word.to_le_bytes())
+                .unwrap();
+        }
+        let nop = Instruction::NOP.encode().to_le_bytes();
+        interpreter.cpu.write_memory(0x40, &nop).unwrap();
+        interpreter.cpu.write_csr(csr::MTVEC, 0x40).unwrap();
+        interpreter.cpu.config.ecall = EcallPolicy::TrapIntoGuestHandler;
+        interpreter
+    }
+
+    #[test]
+    fn parse() {
+        let log = parse_log(LOG).unwrap();
+        // the boot ROM's instruction, not the disassembly or exception
+        assert_eq!(log.len(), 5);
+        assert_eq!(
+            log[1],
+            Commit {
+                pc: 0,
+                word: 0x0050_0293,
+                registers: vec![(Register::X5, 5)],
+                stores: vec![],
+            }
+        );
+        assert_eq!(log[2].stores, [(0x100, vec![5, 0, 0, 0])]);
+        // a load's address isn't a store
+        assert_eq!(log[3].registers, [(Register::X6, 5)]);
+        assert!(log[3].stores.is_empty());
+
+        // stores are as wide as their value, and CSR writes are left out
+        let log = parse_log("core 0: 1 0x8 (0x4501) c768_mstatus 0x80 mem 0x10 0xff").unwrap();
+        assert_eq!(log[0].word, 0x4501);
+        assert_eq!(log[0].stores, [(0x10, vec![0xff])]);
+        assert!(log[0].registers.is_empty());
+
+        assert_eq!(
+            parse_log("core 0: 3 pc").unwrap_err().to_string(),
+            "line 1: expected the PC"
+        );
+        assert!(parse_log("core 0: 3 0x0 (0x13) x1").is_err());
+    }
+
+    #[test]
+    fn divergences() {
+        let log = parse_log(LOG).unwrap();
+
+        // the same, from the instruction at the PC, and through the trap
+        let mut interpreter = program();
+        assert_eq!(compare(&mut interpreter, &log), Ok(4));
+        assert_eq!(interpreter.cpu.pc, 0x44);
+
+        // the reference loaded a sign-extended halfword
+        let mut changed = log.clone();
+        changed[3].registers = vec![(Register::X6, 0xffff_8005)];
+        let mut interpreter = program();
+        let divergence = compare(&mut interpreter, &changed).unwrap_err();
+        assert_eq!(
+            divergence.to_string(),
+            "Step 3 at 0x00000008: t1 is 0x00000005, but the reference has 0xffff8005"
+        );
+
+        // stored somewhere else
+        changed = log.clone();
+        changed[2].stores = vec![(0x104, vec![5, 0, 0, 0])];
+        let mut interpreter = program();
+        assert_eq!(
+            compare(&mut interpreter, &changed).unwrap_err().difference,
+            Difference::Memory {
+                address: 0x104,
+                expected: Some(vec![5, 0, 0, 0]),
+                actual: vec![0; 4],
+            }
+        );
+
+        // went somewhere else; the machine is left before the instruction
+        changed = log.clone();
+        changed[2].pc = 0x20;
+        let mut interpreter = program();
+        let divergence = compare(&mut interpreter, &changed).unwrap_err();
+        assert_eq!(divergence.difference, Difference::Pc { expected: 0x20 });
+        assert_eq!(interpreter.cpu.pc, 4);
+
+        // the program exits before the log ends
+        let mut interpreter = program();
+        interpreter.cpu.exit_code = Some(0);
+        assert_eq!(compare(&mut interpreter, &log), Ok(0));
+    }
+}
@@ -0,0 +1,1017 @@
+//! Undo and redo for executed instructions.
+//!
+//! Every instruction the [Interpreter](crate::Interpreter) executes is
+//! recorded as a [StateDelta]: the registers it changed and the memory it
+//! wrote, with both the old and new values. [StateHistory] keeps those deltas
+//! in a timeline that can be walked backwards and forwards.
+//!
+//! Deltas are cheap to make: memory is never copied or compared to find what
+//! changed. Stores journal their own old and new bytes as they happen (see
+//! [CPU::last_writes]), so a delta costs a copy of the registers plus the
+//! bytes actually written, whatever the size of memory.
+//!
+//! The CPU can also be changed without going through the history (eg:
+//! setting `interpreter.cpu.x1` directly). Applying a delta on top of a state
+//! it wasn't recorded against would quietly corrupt that state, so every
+//! entry carries a checksum of the registers before and after it ran, and the
+//! memory it touched is compared byte by byte. If anything doesn't match, undo
+//! and redo refuse with a [HistoryError] instead.
+//!
+//! Deltas include the CSRs that changed (journaled by the CPU like stores,
+//! see [CPU::last_csr_writes]) and the privilege mode, so undoing a trap or
+//! an `MRET` puts back `mepc`, `mstatus`, and the mode it ran in. Changes
+//! made by hand (eg: with `/set`) are recorded as a [Step::Set], so they're
+//! undone and redone in turn with the instructions around them.
+//!
+//! [StateHistory::seek] jumps to any point in the timeline. Walking there one
+//! delta at a time would be slow for long runs, so every [SPAN] deltas the
+//! history also keeps the block's net change: the registers and bytes it
+//! changed, with their values before the first delta and after the last.
+//! Seeking applies whole blocks where it can, and single deltas for the rest.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::rv32_i::{
+    Counters, CsrChange, Instruction, MemoryWrite, Privilege, Register, TrapCause, CPU,
+};
+use crate::snapshot::{Reader, SnapshotError};
+
+/// How many deltas each block of the timeline holds; see the
+/// [module](self) docs
+pub const SPAN: usize = 256;
+
+/// The values of every register, in [Register::ALL] order. They're 64 bits
+/// wide so [RV64](crate::rv32_i::rv64) registers are kept whole; in RV32 the
+/// top halves are always zero.
+pub type Registers = [u64; 33];
+
+/// Reads every register from the CPU
+pub fn registers(cpu: &CPU) -> Registers {
+    Register::ALL.map(|r| cpu.get_x(r))
+}
+
+/// Puts every register back
+pub fn set_registers(cpu: &mut CPU, registers: &Registers) {
+    for (register, value) in Register::ALL.iter().zip(registers) {
+        cpu.set_x(*register, *value);
+    }
+}
+
+/// A register that changed: from `old` to `new`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub old: u64,
+    pub new: u64,
+}
+
+/// Everything an instruction changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDelta {
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<MemoryWrite>,
+    /// What it added to the cycle and instruction counters
+    pub counted: Counters,
+    /// CSRs it changed, including those set by trapping
+    pub csrs: Vec<CsrChange>,
+    /// The mode before and after, if it changed
+    pub privilege: Option<(Privilege, Privilege)>,
+}
+
+impl StateDelta {
+    /// Compares the registers from before an instruction with the CPU after
+    /// it, and collects the memory and CSR writes it made, the mode it
+    /// switched to, and the cycles it took. Memory itself isn't read; the
+    /// writes come from the CPU's journal.
+    pub fn between(before: &Registers, cpu: &CPU) -> Self {
+        let registers = Register::ALL
+            .iter()
+            .zip(before)
+            .filter_map(|(&register, &old)| {
+                let new = cpu.get_x(register);
+                (old != new).then_some(RegisterChange { register, old, new })
+            })
+            .collect();
+
+        Self {
+            registers,
+            memory: cpu.last_writes().to_vec(),
+            counted: cpu.last_counted(),
+            csrs: cpu.last_csr_writes().to_vec(),
+            privilege: cpu.last_privilege_change(),
+        }
+    }
+
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.memory.is_empty()
+            && self.counted == Counters::default()
+            && self.csrs.is_empty()
+            && self.privilege.is_none()
+    }
+
+    /// One delta with the effect of several, applied in order
+    pub(crate) fn merge<'a>(deltas: impl IntoIterator<Item = &'a StateDelta>) -> Self {
+        let mut registers: Vec<RegisterChange> = vec![];
+        let mut bytes: BTreeMap<u32, (u8, u8)> = BTreeMap::new();
+        let mut counted = Counters::default();
+        let mut csrs: Vec<CsrChange> = vec![];
+        let mut privilege: Option<(Privilege, Privilege)> = None;
+
+        for delta in deltas {
+            if let Some((old, new)) = delta.privilege {
+                privilege = Some((privilege.map_or(old, |(first, _)| first), new));
+            }
+            for change in delta.registers.iter() {
+                match registers.iter_mut().find(|c| c.register == change.register) {
+                    Some(merged) => merged.new = change.new,
+                    None => registers.push(*change),
+                }
+            }
+            for change in delta.csrs.iter() {
+                match csrs.iter_mut().find(|c| c.address == change.address) {
+                    Some(merged) => merged.new = change.new,
+                    None => csrs.push(*change),
+                }
+            }
+            for write in delta.memory.iter() {
+                for ((address, &old), &new) in
+                    (write.address..=u32::MAX).zip(&write.old).zip(&write.new)
+                {
+                    bytes.entry(address).or_insert((old, new)).1 = new;
+                }
+            }
+            counted += delta.counted;
+        }
+        registers.retain(|c| c.old != c.new);
+        csrs.retain(|c| c.old != c.new);
+        privilege = privilege.filter(|(old, new)| old != new);
+        bytes.retain(|_, (old, new)| old != new);
+
+        // contiguous bytes become one write
+        let mut memory: Vec<MemoryWrite> = vec![];
+        for (address, (old, new)) in bytes {
+            match memory.last_mut() {
+                Some(w) if w.address.wrapping_add(w.old.len() as u32) == address => {
+                    w.old.push(old);
+                    w.new.push(new);
+                }
+                _ => memory.push(MemoryWrite {
+                    address,
+                    old: vec![old],
+                    new: vec![new],
+                }),
+            }
+        }
+
+        Self {
+            registers,
+            memory,
+            counted,
+            csrs,
+            privilege,
+        }
+    }
+
+    /// Restores the old values
+    fn revert(&self, cpu: &mut CPU, epoch: usize) -> Result<(), HistoryError> {
+        for change in self.registers.iter() {
+            cpu.set_x(change.register, change.old);
+        }
+        for change in self.csrs.iter() {
+            cpu.csrs.set(change.address, change.old);
+        }
+        if let Some((old, _)) = self.privilege {
+            cpu.privilege = old;
+        }
+        // in reverse, in case an instruction wrote the same place twice
+        for write in self.memory.iter().rev() {
+            copy(cpu, write.address, &write.old, epoch)?;
+        }
+        cpu.counters -= self.counted;
+        Ok(())
+    }
+
+    /// Applies the new values
+    fn apply(&self, cpu: &mut CPU, epoch: usize) -> Result<(), HistoryError> {
+        for change in self.registers.iter() {
+            cpu.set_x(change.register, change.new);
+        }
+        for change in self.csrs.iter() {
+            cpu.csrs.set(change.address, change.new);
+        }
+        if let Some((_, new)) = self.privilege {
+            cpu.privilege = new;
+        }
+        for write in self.memory.iter() {
+            copy(cpu, write.address, &write.new, epoch)?;
+        }
+        cpu.counters += self.counted;
+        Ok(())
+    }
+}
+
+impl Display for StateDelta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut changes: Vec<String> = self
+            .registers
+            .iter()
+            .map(|c| format!("{:?}: 0x{:x} -> 0x{:x}", c.register, c.old, c.new))
+            .collect();
+        changes.extend(
+            self.csrs
+                .iter()
+                .map(|c| format!("{}: 0x{:x} -> 0x{:x}", c.name(), c.old, c.new)),
+        );
+        changes.extend(
+            self.privilege
+                .map(|(old, new)| format!("mode: {old} -> {new}")),
+        );
+        changes.extend(
+            self.memory
+                .iter()
+                .map(|w| format!("{} bytes at 0x{:08x}", w.new.len(), w.address)),
+        );
+
+        if changes.is_empty() {
+            write!(f, "no changes")
+        } else {
+            write!(f, "{}", changes.join(", "))
+        }
+    }
+}
+
+fn copy(cpu: &mut CPU, address: u32, bytes: &[u8], epoch: usize) -> Result<(), HistoryError> {
+    let range = cpu
+        .address_map
+        .translate(address, bytes.len())
+        .and_then(|range| cpu.memory.get_mut(range))
+        .ok_or(HistoryError::MemoryUnmapped { epoch, address })?;
+    range.copy_from_slice(bytes);
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryError {
+    NothingToUndo,
+    NothingToRedo,
+    /// The registers aren't what the history expects; `epoch` is the position
+    /// in the timeline where it noticed
+    RegistersChanged {
+        epoch: usize,
+    },
+    /// Memory isn't what the history expects
+    MemoryChanged {
+        epoch: usize,
+        address: u32,
+        expected: u8,
+        found: u8,
+    },
+    /// Memory the history wrote isn't there anymore, eg: the memory map
+    /// changed
+    MemoryUnmapped {
+        epoch: usize,
+        address: u32,
+    },
+    /// [seek](StateHistory::seek) was asked for a step past the end
+    NoSuchStep {
+        step: usize,
+        last: usize,
+    },
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NothingToUndo => write!(f, "Nothing to undo"),
+            Self::NothingToRedo => write!(f, "Nothing to redo"),
+            Self::RegistersChanged { epoch } => write!(
+                f,
+                "Registers were changed outside of the history (at step {epoch}); \
+                 clear the history to continue"
+            ),
+            Self::MemoryChanged {
+                epoch,
+                address,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Memory at 0x{address:08x} was changed outside of the history (at step {epoch}): \
+                 expected 0x{expected:02x}, found 0x{found:02x}; clear the history to continue"
+            ),
+            Self::MemoryUnmapped { epoch, address } => write!(
+                f,
+                "Memory at 0x{address:08x} isn't mapped anymore (at step {epoch}); \
+                 clear the history to continue"
+            ),
+            Self::NoSuchStep { step, last } => {
+                write!(f, "There's no step {step}; the history ends at step {last}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+/// What made an entry in the timeline
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Step {
+    /// An instruction was executed
+    Executed(Instruction),
+    /// An interrupt was taken, between instructions
+    Interrupt(TrapCause),
+    /// Registers, memory, or CSRs were set by hand (eg: with `/set`)
+    Set,
+}
+
+/// A delta, with checksums of the registers before and after it
+#[derive(Debug, Clone)]
+struct Checked {
+    delta: StateDelta,
+    before: u64,
+    after: u64,
+}
+
+impl Checked {
+    fn revert(&self, cpu: &mut CPU, epoch: usize) -> Result<(), HistoryError> {
+        verify(cpu, self.after, &self.delta.memory, |w| &w.new, epoch)?;
+        verify_csrs(cpu, &self.delta, |c| c.new, |(_, new)| new, epoch)?;
+        self.delta.revert(cpu, epoch)
+    }
+
+    fn apply(&self, cpu: &mut CPU, epoch: usize) -> Result<(), HistoryError> {
+        verify(cpu, self.before, &self.delta.memory, |w| &w.old, epoch)?;
+        verify_csrs(cpu, &self.delta, |c| c.old, |(old, _)| old, epoch)?;
+        self.delta.apply(cpu, epoch)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// the PC before the step
+    pc: u32,
+    step: Step,
+    checked: Checked,
+}
+
+/// A timeline of [StateDelta]s that can be undone and redone
+#[derive(Debug, Clone, Default)]
+pub struct StateHistory {
+    entries: Vec<Entry>,
+    /// how many entries are currently applied; the rest can be redone
+    epoch: usize,
+    /// the net change of each complete block of [SPAN] entries
+    spans: Vec<Checked>,
+}
+
+impl StateHistory {
+    /// Records a delta at the current point, discarding anything that could
+    /// have been redone. `before` is the registers from before it ran.
+    pub fn record(&mut self, step: Step, before: &Registers, delta: StateDelta, cpu: &CPU) {
+        self.forget_redo();
+        self.entries.push(Entry {
+            pc: before[32] as u32,
+            step,
+            checked: Checked {
+                delta,
+                before: checksum(before),
+                after: checksum(&registers(cpu)),
+            },
+        });
+        self.epoch += 1;
+
+        if self.epoch.is_multiple_of(SPAN) {
+            self.spans.push(self.span(self.spans.len()));
+        }
+    }
+
+    /// The net change of the `index`th block of [SPAN] entries
+    fn span(&self, index: usize) -> Checked {
+        let block = &self.entries[index * SPAN..(index + 1) * SPAN];
+        Checked {
+            delta: StateDelta::merge(block.iter().map(|e| &e.checked.delta)),
+            before: block[0].checked.before,
+            after: block[SPAN - 1].checked.after,
+        }
+    }
+
+    /// Appends the serialized history to `bytes`; the blocks aren't
+    /// included, since they can be worked out again
+    pub(crate) fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend((self.entries.len() as u32).to_le_bytes());
+        bytes.extend((self.epoch as u32).to_le_bytes());
+
+        for entry in &self.entries {
+            bytes.extend(entry.pc.to_le_bytes());
+            let (kind, word) = match entry.step {
+                Step::Executed(instruction) => (0, instruction.encode()),
+                Step::Interrupt(cause) => (1, cause.mcause()),
+                Step::Set => (2, 0),
+            };
+            bytes.push(kind);
+            bytes.extend(word.to_le_bytes());
+            bytes.extend(entry.checked.before.to_le_bytes());
+            bytes.extend(entry.checked.after.to_le_bytes());
+
+            let delta = &entry.checked.delta;
+            bytes.push(delta.registers.len() as u8);
+            for change in &delta.registers {
+                bytes.push(
+                    Register::ALL
+                        .iter()
+                        .position(|r| *r == change.register)
+                        .unwrap() as u8,
+                );
+                bytes.extend(change.old.to_le_bytes());
+                bytes.extend(change.new.to_le_bytes());
+            }
+            bytes.extend((delta.memory.len() as u32).to_le_bytes());
+            for write in &delta.memory {
+                bytes.extend(write.address.to_le_bytes());
+                bytes.extend((write.old.len() as u32).to_le_bytes());
+                bytes.extend(&write.old);
+                bytes.extend(&write.new);
+            }
+            bytes.extend(delta.counted.cycles.to_le_bytes());
+            bytes.extend(delta.counted.instret.to_le_bytes());
+
+            bytes.extend((delta.csrs.len() as u32).to_le_bytes());
+            for change in &delta.csrs {
+                bytes.extend(change.address.to_le_bytes());
+                bytes.extend(change.old.to_le_bytes());
+                bytes.extend(change.new.to_le_bytes());
+            }
+            match delta.privilege {
+                Some((old, new)) => bytes.extend([1, old.bits() as u8, new.bits() as u8]),
+                None => bytes.push(0),
+            }
+        }
+    }
+
+    /// Whether every write in the history is to the CPU's memory, so it can
+    /// be undone and redone there
+    pub(crate) fn fits(&self, cpu: &CPU) -> bool {
+        self.entries
+            .iter()
+            .flat_map(|e| &e.checked.delta.memory)
+            .all(|w| {
+                cpu.address_map
+                    .translate(w.address, w.old.len())
+                    .is_some_and(|r| r.end <= cpu.memory.len())
+            })
+    }
+
+    /// Reads a history written by [write](Self::write), after a snapshot of
+    /// `version`. Before version 4, register values were 32 bits, and only
+    /// changes made by hand had CSRs (and none had a mode).
+    pub(crate) fn read(reader: &mut Reader, version: u16) -> Result<Self, SnapshotError> {
+        let value = |reader: &mut Reader| match version {
+            ..=3 => reader.u32().map(u64::from),
+            _ => reader.u64(),
+        };
+
+        let count = reader.u32()? as usize;
+        let epoch = reader.u32()? as usize;
+        if epoch > count {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        let mut history = Self {
+            epoch,
+            ..Self::default()
+        };
+        for _ in 0..count {
+            let pc = reader.u32()?;
+            let step = match (reader.u8()?, reader.u32()?) {
+                (0, word) => {
+                    Step::Executed(Instruction::decode(word).map_err(|_| SnapshotError::Corrupt)?)
+                }
+                (1, mcause) => {
+                    Step::Interrupt(TrapCause::from_mcause(mcause).ok_or(SnapshotError::Corrupt)?)
+                }
+                (2, _) => Step::Set,
+                _ => return Err(SnapshotError::Corrupt),
+            };
+            let before = reader.u64()?;
+            let after = reader.u64()?;
+
+            let mut delta = StateDelta::default();
+            for _ in 0..reader.u8()? {
+                delta.registers.push(RegisterChange {
+                    register: *Register::ALL
+                        .get(reader.u8()? as usize)
+                        .ok_or(SnapshotError::Corrupt)?,
+                    old: value(reader)?,
+                    new: value(reader)?,
+                });
+            }
+            for _ in 0..reader.u32()? {
+                let address = reader.u32()?;
+                let len = reader.u32()? as usize;
+                delta.memory.push(MemoryWrite {
+                    address,
+                    old: reader.take(len)?.to_vec(),
+                    new: reader.take(len)?.to_vec(),
+                });
+            }
+            delta.counted = Counters {
+                cycles: reader.u64()?,
+                instret: reader.u64()?,
+            };
+            if version >= 4 || step == Step::Set {
+                for _ in 0..reader.u32()? {
+                    delta.csrs.push(CsrChange {
+                        address: reader.u32()?,
+                        old: reader.u32()?,
+                        new: reader.u32()?,
+                    });
+                }
+            }
+            if version >= 4 && reader.u8()? != 0 {
+                let mode = |reader: &mut Reader| {
+                    Privilege::from_bits(reader.u8()?.into()).ok_or(SnapshotError::Corrupt)
+                };
+                delta.privilege = Some((mode(reader)?, mode(reader)?));
+            }
+
+            history.entries.push(Entry {
+                pc,
+                step,
+                checked: Checked {
+                    delta,
+                    before,
+                    after,
+                },
+            });
+        }
+
+        history.spans = (0..count / SPAN).map(|i| history.span(i)).collect();
+        Ok(history)
+    }
+
+    /// Reverts the most recent delta
+    pub fn undo(&mut self, cpu: &mut CPU) -> Result<&StateDelta, HistoryError> {
+        let entry = match self.epoch.checked_sub(1) {
+            Some(i) => &self.entries[i],
+            None => return Err(HistoryError::NothingToUndo),
+        };
+
+        entry.checked.revert(cpu, self.epoch)?;
+        self.epoch -= 1;
+
+        Ok(&entry.checked.delta)
+    }
+
+    /// Re-applies the most recently undone delta
+    pub fn redo(&mut self, cpu: &mut CPU) -> Result<&StateDelta, HistoryError> {
+        let entry = match self.entries.get(self.epoch) {
+            Some(entry) => entry,
+            None => return Err(HistoryError::NothingToRedo),
+        };
+
+        entry.checked.apply(cpu, self.epoch)?;
+        self.epoch += 1;
+
+        Ok(&entry.checked.delta)
+    }
+
+    /// Undoes or redoes until `epoch` deltas are applied, a block of [SPAN]
+    /// at a time where it can. If the CPU doesn't match the history partway,
+    /// it stops there with the error.
+    pub fn seek(&mut self, epoch: usize, cpu: &mut CPU) -> Result<(), HistoryError> {
+        if epoch > self.entries.len() {
+            return Err(HistoryError::NoSuchStep {
+                step: epoch,
+                last: self.entries.len(),
+            });
+        }
+
+        while self.epoch > epoch {
+            let block = (self.epoch.is_multiple_of(SPAN) && self.epoch - epoch >= SPAN)
+                .then(|| &self.spans[self.epoch / SPAN - 1]);
+            match block {
+                Some(block) => {
+                    block.revert(cpu, self.epoch)?;
+                    self.epoch -= SPAN;
+                }
+                None => {
+                    self.undo(cpu)?;
+                }
+            }
+        }
+
+        while self.epoch < epoch {
+            let block = (self.epoch.is_multiple_of(SPAN) && epoch - self.epoch >= SPAN)
+                .then(|| &self.spans[self.epoch / SPAN]);
+            match block {
+                Some(block) => {
+                    block.apply(cpu, self.epoch)?;
+                    self.epoch += SPAN;
+                }
+                None => {
+                    self.redo(cpu)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The net change between the states after steps `a` and `b` (in either
+    /// order, from the earlier to the later), as one delta: a register or
+    /// byte changed more than once shows only its first and last value, and
+    /// one that ended up where it started is left out. Registers are in
+    /// [Register::ALL] order. Whole blocks of [SPAN] are merged where they
+    /// fit, so long ranges are cheap. Works across steps that have been
+    /// undone too.
+    pub fn diff(&self, a: usize, b: usize) -> Result<StateDelta, HistoryError> {
+        let (from, to) = (a.min(b), a.max(b));
+        if to > self.entries.len() {
+            return Err(HistoryError::NoSuchStep {
+                step: to,
+                last: self.entries.len(),
+            });
+        }
+
+        let mut deltas = vec![];
+        let mut i = from;
+        while i < to {
+            match self.spans.get(i / SPAN) {
+                Some(block) if i.is_multiple_of(SPAN) && to - i >= SPAN => {
+                    deltas.push(&block.delta);
+                    i += SPAN;
+                }
+                _ => {
+                    deltas.push(&self.entries[i].checked.delta);
+                    i += 1;
+                }
+            }
+        }
+        let mut delta = StateDelta::merge(deltas);
+        delta.registers.sort_by_key(|c| c.register as usize);
+        Ok(delta)
+    }
+
+    /// Forgets the deltas that could be redone
+    pub fn forget_redo(&mut self) {
+        self.entries.truncate(self.epoch);
+        self.spans.truncate(self.epoch / SPAN);
+    }
+
+    /// Forgets everything, eg: after the CPU was changed directly
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.spans.clear();
+        self.epoch = 0;
+    }
+
+    /// Every step recorded, oldest first, with the PC it started from. The
+    /// first [epoch](Self::epoch) are applied; the rest can be redone.
+    pub fn timeline(&self) -> impl Iterator<Item = (u32, Step)> + '_ {
+        self.entries.iter().map(|e| (e.pc, e.step))
+    }
+
+    /// The `index`th step recorded (from 0), with the PC it started from
+    pub fn get(&self, index: usize) -> Option<(u32, Step)> {
+        self.entries.get(index).map(|e| (e.pc, e.step))
+    }
+
+    /// Number of deltas recorded, applied or not
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The most recently applied delta
+    pub fn last(&self) -> Option<&StateDelta> {
+        let i = self.epoch.checked_sub(1)?;
+        Some(&self.entries[i].checked.delta)
+    }
+
+    /// Number of deltas currently applied
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// Number of deltas that can be redone
+    pub fn redoable(&self) -> usize {
+        self.entries.len() - self.epoch
+    }
+}
+
+/// Checks each CSR an entry changed holds its `expected` side, and so does
+/// the mode if it changed
+fn verify_csrs(
+    cpu: &CPU,
+    delta: &StateDelta,
+    expected: impl Fn(&CsrChange) -> u32,
+    mode: impl Fn((Privilege, Privilege)) -> Privilege,
+    epoch: usize,
+) -> Result<(), HistoryError> {
+    let csrs = delta
+        .csrs
+        .iter()
+        .all(|c| cpu.csrs.read(c.address) == Some(expected(c)));
+    match csrs
+        && delta
+            .privilege
+            .is_none_or(|change| cpu.privilege == mode(change))
+    {
+        true => Ok(()),
+        false => Err(HistoryError::RegistersChanged { epoch }),
+    }
+}
+
+/// Checks the CPU is in the state an entry expects: the registers match the
+/// checksum, and the memory it touched holds the `expected` side of each write.
+fn verify(
+    cpu: &CPU,
+    checksum_expected: u64,
+    writes: &[MemoryWrite],
+    expected: impl Fn(&MemoryWrite) -> &Vec<u8>,
+    epoch: usize,
+) -> Result<(), HistoryError> {
+    if checksum(&registers(cpu)) != checksum_expected {
+        return Err(HistoryError::RegistersChanged { epoch });
+    }
+
+    for write in writes {
+        let expected = expected(write);
+        let found = cpu
+            .address_map
+            .translate(write.address, expected.len())
+            .and_then(|range| cpu.memory.get(range))
+            .ok_or(HistoryError::MemoryUnmapped {
+                epoch,
+                address: write.address,
+            })?;
+        let mismatch = (0u32..)
+            .zip(expected.iter().zip(found))
+            .find(|(_, (e, f))| e != f);
+        if let Some((offset, (&expected, &found))) = mismatch {
+            return Err(HistoryError::MemoryChanged {
+                epoch,
+                address: write.address.wrapping_add(offset),
+                expected,
+                found,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// FNV-1a; it only needs to notice changes, not resist tampering. The low
+/// halves come first and top halves are only counted when they're nonzero,
+/// so RV32 checksums are the same as when registers were 32 bits.
+fn checksum(registers: &Registers) -> u64 {
+    let low = registers.iter().flat_map(|r| (*r as u32).to_le_bytes());
+    let high = registers
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| *r >> 32 != 0)
+        .flat_map(|(i, r)| [i as u32, (*r >> 32) as u32])
+        .flat_map(u32::to_le_bytes);
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in low.chain(high) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{AddressMap, IType, Instruction, SType};
+
+    fn step(cpu: &mut CPU, history: &mut StateHistory, instruction: Instruction) {
+        let before = registers(cpu);
+        cpu.execute(instruction).unwrap();
+        let delta = StateDelta::between(&before, cpu);
+        history.record(Step::Executed(instruction), &before, delta, cpu);
+    }
+
+    fn store_x1() -> Instruction {
+        Instruction::SW(SType {
+            rs1: Register::X2,
+            rs2: Register::X1,
+            ..SType::default()
+        })
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut cpu = CPU::default();
+        let mut history = StateHistory::default();
+        cpu.x1 = 0xdead_beef;
+        cpu.x2 = 16;
+
+        step(&mut cpu, &mut history, store_x1());
+        assert_eq!(cpu.memory[16..20], [0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(cpu.pc, 4);
+
+        let delta = history.undo(&mut cpu).unwrap();
+        assert_eq!(delta.registers.len(), 1);
+        assert_eq!(cpu.memory[16..20], [0; 4]);
+        assert_eq!(cpu.pc, 0);
+        assert_eq!(history.undo(&mut cpu), Err(HistoryError::NothingToUndo));
+        assert_eq!(history.last(), None);
+
+        history.redo(&mut cpu).unwrap();
+        assert_eq!(cpu.memory[16..20], [0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(cpu.pc, 4);
+        assert_eq!(history.last().unwrap().memory[0].address, 16);
+        assert_eq!(history.redo(&mut cpu), Err(HistoryError::NothingToRedo));
+    }
+
+    #[test]
+    fn detects_outside_changes() {
+        let mut cpu = CPU::default();
+        let mut history = StateHistory::default();
+        cpu.x1 = 0xdead_beef;
+        cpu.x2 = 16;
+
+        step(&mut cpu, &mut history, store_x1());
+
+        // memory touched by the delta
+        cpu.memory[17] = 0;
+        assert_eq!(
+            history.undo(&mut cpu),
+            Err(HistoryError::MemoryChanged {
+                epoch: 1,
+                address: 17,
+                expected: 0xbe,
+                found: 0
+            })
+        );
+        cpu.memory[17] = 0xbe;
+
+        // registers, between undo and redo
+        history.undo(&mut cpu).unwrap();
+        cpu.x5 = 1;
+        assert_eq!(
+            history.redo(&mut cpu),
+            Err(HistoryError::RegistersChanged { epoch: 0 })
+        );
+
+        // nothing was applied
+        assert_eq!(cpu.pc, 0);
+        assert_eq!(history.redoable(), 1);
+
+        history.clear();
+        assert_eq!(history.redo(&mut cpu), Err(HistoryError::NothingToRedo));
+
+        // memory that was unmapped
+        step(&mut cpu, &mut history, store_x1());
+        cpu.address_map = AddressMap::new(0x1000, cpu.memory.len());
+        assert_eq!(
+            history.undo(&mut cpu),
+            Err(HistoryError::MemoryUnmapped {
+                epoch: 1,
+                address: 16
+            })
+        );
+    }
+
+    #[test]
+    fn deltas_hold_only_what_was_written() {
+        let mut cpu = CPU::new(16 * 1024 * 1024);
+        let mut history = StateHistory::default();
+        cpu.x1 = 0xdead_beef;
+        cpu.x2 = 0x00ff_0000;
+
+        step(&mut cpu, &mut history, Instruction::NOP);
+        assert!(history.last().unwrap().memory.is_empty());
+
+        step(&mut cpu, &mut history, store_x1());
+        let delta = history.last().unwrap();
+        assert_eq!(delta.memory.len(), 1);
+        assert_eq!(delta.memory[0].address, 0x00ff_0000);
+        assert_eq!(delta.memory[0].old, [0; 4]);
+        assert_eq!(delta.memory[0].new, [0xef, 0xbe, 0xad, 0xde]);
+    }
+
+    #[test]
+    fn seeking() {
+        let mut cpu = CPU::default();
+        let mut history = StateHistory::default();
+        let program = crate::interpreter::parse_listing(
+            "ADDI x1, x1, 1\n\
+             SB x1, x1, 64",
+        )
+        .unwrap();
+
+        let mut states = vec![(registers(&cpu), cpu.memory[..512].to_vec())];
+        for i in 0..2 * SPAN + 10 {
+            step(&mut cpu, &mut history, program[i % 2].1);
+            states.push((registers(&cpu), cpu.memory[..512].to_vec()));
+        }
+        assert_eq!(history.spans.len(), 2);
+        let state = |cpu: &CPU| (registers(cpu), cpu.memory[..512].to_vec());
+
+        for epoch in [3, 0, SPAN, 2 * SPAN + 10, SPAN + 1, 2 * SPAN, 7] {
+            history.seek(epoch, &mut cpu).unwrap();
+            assert_eq!(history.epoch(), epoch);
+            assert_eq!(state(&cpu), states[epoch]);
+            assert_eq!(cpu.counters.instret, epoch as u64);
+        }
+
+        assert_eq!(
+            history.seek(2 * SPAN + 11, &mut cpu),
+            Err(HistoryError::NoSuchStep {
+                step: 2 * SPAN + 11,
+                last: 2 * SPAN + 10
+            })
+        );
+        assert_eq!(
+            history.timeline().nth(1),
+            Some((4, Step::Executed(program[1].1)))
+        );
+
+        // recording after seeking back drops the blocks that were ahead
+        history.seek(SPAN + 1, &mut cpu).unwrap();
+        step(&mut cpu, &mut history, Instruction::NOP);
+        assert_eq!((history.len(), history.spans.len()), (SPAN + 2, 1));
+
+        // a block is checked like a single delta
+        cpu.memory[100] ^= 1;
+        assert!(matches!(
+            history.seek(0, &mut cpu),
+            Err(HistoryError::MemoryChanged { epoch, .. }) if epoch == SPAN
+        ));
+        assert_eq!(history.epoch(), SPAN);
+    }
+
+    #[test]
+    fn net_difference() {
+        let mut cpu = CPU::default();
+        let mut history = StateHistory::default();
+        let addi = |rd, imm| {
+            let mut itype = IType {
+                rd,
+                rs1: rd,
+                ..IType::default()
+            };
+            itype.imm.set_signed(imm).unwrap();
+            Instruction::ADDI(itype)
+        };
+
+        step(&mut cpu, &mut history, addi(Register::X1, 5));
+        step(&mut cpu, &mut history, store_x1());
+        step(&mut cpu, &mut history, addi(Register::X2, 1));
+        step(&mut cpu, &mut history, addi(Register::X2, -1));
+        step(&mut cpu, &mut history, addi(Register::X1, 1));
+
+        let delta = history.diff(1, 5).unwrap();
+        let changed: Vec<Register> = delta.registers.iter().map(|c| c.register).collect();
+        // x2 went back to where it was
+        assert_eq!(changed, vec![Register::X1, Register::PC]);
+        assert_eq!(delta.registers[0].old, 5);
+        assert_eq!(delta.registers[0].new, 6);
+        assert_eq!(delta.memory.len(), 1);
+        assert_eq!(delta.memory[0].new, vec![5]);
+        assert_eq!(delta.counted.instret, 4);
+
+        assert_eq!(history.diff(5, 1), Ok(delta));
+        assert_eq!(history.diff(2, 2), Ok(StateDelta::default()));
+        assert_eq!(
+            history.diff(0, 6),
+            Err(HistoryError::NoSuchStep { step: 6, last: 5 })
+        );
+
+        // the same answer with blocks as without
+        for _ in 0..SPAN * 2 {
+            step(&mut cpu, &mut history, addi(Register::X3, 1));
+        }
+        let delta = history.diff(3, 5 + SPAN * 2).unwrap();
+        assert_eq!(delta.registers[2].register, Register::X3);
+        assert_eq!(delta.registers[2].new, SPAN as u64 * 2);
+        assert_eq!(delta.counted.instret, SPAN as u64 * 2 + 2);
+    }
+
+    #[test]
+    fn changes_between_steps() {
+        let mut cpu = CPU::default();
+        let mut history = StateHistory::default();
+
+        step(&mut cpu, &mut history, Instruction::NOP);
+        cpu.x1 = 5;
+        step(&mut cpu, &mut history, Instruction::NOP);
+
+        // the second step can be undone, but the first was recorded against
+        // a state that no longer exists
+        history.undo(&mut cpu).unwrap();
+        assert_eq!(
+            history.undo(&mut cpu),
+            Err(HistoryError::RegistersChanged { epoch: 1 })
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Intel HEX, a text format for memory images.
+//!
+//! Each line is a record: `:`, then hex pairs for the byte count, a 16-bit
+//! address, the record type, the data, and a checksum that makes all the
+//! bytes sum to zero. Data records are placed relative to a base address set
+//! by the extended segment (type 2, base = value * 16) and extended linear
+//! (type 4, base = value << 16) records, and the file ends with an end of
+//! file record (type 1). The start address records (types 3 and 5) give the
+//! entry point.
+//!
+//! ```
+//! use brubeck::ihex;
+//!
+//! let image = ihex::parse(
+//!     ":0400100013050500CF\n\
+//!      :00000001FF\n",
+//! )
+//! .unwrap();
+//!
+//! assert_eq!(image.chunks, vec![(0x10, vec![0x13, 0x05, 0x05, 0x00])]);
+//! assert_eq!(image.entry, None);
+//! ```
+
+use std::fmt::Display;
+
+use crate::rv32_i::{self, CPU};
+
+/// The data in a file, in the order it appeared
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Image {
+    /// Each data record's address and bytes; consecutive records are joined
+    pub chunks: Vec<(u32, Vec<u8>)>,
+    /// The start address, if the file gave one
+    pub entry: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// A line doesn't start with `:`, or isn't all hex pairs
+    Malformed(usize),
+    /// The byte count doesn't match the length of the line
+    WrongLength(usize),
+    /// The bytes don't sum to zero; the line and the checksum that was expected
+    Checksum(usize, u8),
+    /// A record type this format doesn't have
+    UnknownRecord(usize, u8),
+    /// A record with the wrong amount of data for its type (eg: an extended
+    /// linear address record without exactly 2 bytes); the line and the type
+    RecordLength(usize, u8),
+    /// There's no end of file record
+    MissingEnd,
+}
+
+impl Display for HexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(line) => write!(f, "line {line}: not a record (:, then hex digits)"),
+            Self::WrongLength(line) => {
+                write!(f, "line {line}: the byte count doesn't match the record")
+            }
+            Self::Checksum(line, expected) => {
+                write!(f, "line {line}: bad checksum, expected {expected:02X}")
+            }
+            Self::UnknownRecord(line, kind) => {
+                write!(f, "line {line}: unknown record type {kind:02X}")
+            }
+            Self::RecordLength(line, kind) => {
+                write!(
+                    f,
+                    "line {line}: wrong number of bytes for a type {kind:02X} record"
+                )
+            }
+            Self::MissingEnd => write!(f, "no end of file record (:00000001FF)"),
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+/// Reads the records in `text`. Blank lines are skipped, and anything after
+/// the end of file record is ignored.
+pub fn parse(text: &str) -> Result<Image, HexError> {
+    let mut image = Image::default();
+    let mut base = 0u32;
+
+    for (number, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if line.is_empty() {
+            continue;
+        }
+
+        let bytes = record(line).ok_or(HexError::Malformed(number))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(HexError::WrongLength(number));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        let sum = body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        if sum.wrapping_add(checksum[0]) != 0 {
+            return Err(HexError::Checksum(number, sum.wrapping_neg()));
+        }
+
+        let offset = u16::from_be_bytes([body[1], body[2]]) as u32;
+        let data = &body[4..];
+        let value = data.iter().fold(0u32, |v, b| v << 8 | *b as u32);
+        let size = match body[3] {
+            1 => Some(0),
+            2 | 4 => Some(2),
+            3 | 5 => Some(4),
+            _ => None,
+        };
+        if size.is_some_and(|size| size != data.len()) {
+            return Err(HexError::RecordLength(number, body[3]));
+        }
+
+        match body[3] {
+            0 => image.add(base.wrapping_add(offset), data),
+            1 => return Ok(image),
+            2 => base = value << 4,
+            // CS:IP
+            3 => image.entry = Some((value >> 16 << 4) + (value & 0xffff)),
+            4 => base = value << 16,
+            5 => image.entry = Some(value),
+            kind => return Err(HexError::UnknownRecord(number, kind)),
+        }
+    }
+
+    Err(HexError::MissingEnd)
+}
+
+/// The bytes of a record, without the leading `:`
+fn record(line: &str) -> Option<Vec<u8>> {
+    let digits = line.strip_prefix(':')?;
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl Image {
+    fn add(&mut self, address: u32, data: &[u8]) {
+        match self.chunks.last_mut() {
+            Some((start, bytes)) if start.wrapping_add(bytes.len() as u32) == address => {
+                bytes.extend_from_slice(data)
+            }
+            _ => self.chunks.push((address, data.to_vec())),
+        }
+    }
+
+    /// Writes every chunk into memory, `offset` bytes on from its address.
+    /// Stops at the first chunk that doesn't fit.
+    pub fn load(&self, cpu: &mut CPU, offset: u32) -> Result<(), rv32_i::Error> {
+        for (address, bytes) in &self.chunks {
+            cpu.write_memory(address.wrapping_add(offset), bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Number of data bytes
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let image = parse(
+            ":020000040800F2\n\
+             :0400000001020304F2\n\
+             :020004000506EF\n\
+             \n\
+             :020000021000EC\n\
+             :01000000AA55\n\
+             :0400000508000010DF\n\
+             :00000001FF\n\
+             ignored",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image.chunks,
+            vec![
+                (0x0800_0000, vec![1, 2, 3, 4, 5, 6]),
+                (0x0001_0000, vec![0xaa]),
+            ]
+        );
+        assert_eq!(image.entry, Some(0x0800_0010));
+        assert_eq!(image.len(), 7);
+
+        assert_eq!(parse(":00000001FE"), Err(HexError::Checksum(1, 0xff)));
+        assert_eq!(parse(":0200000001FF"), Err(HexError::WrongLength(1)));
+        assert_eq!(parse("00000001FF"), Err(HexError::Malformed(1)));
+        assert_eq!(parse(":00000006FA"), Err(HexError::UnknownRecord(1, 6)));
+        assert_eq!(parse(":0100000001FE"), Err(HexError::MissingEnd));
+        assert_eq!(parse(":0100000100FE"), Err(HexError::RecordLength(1, 1)));
+        assert_eq!(
+            parse(":03000004000102F6"),
+            Err(HexError::RecordLength(1, 4))
+        );
+        assert_eq!(parse(":020000050001F8"), Err(HexError::RecordLength(1, 5)));
+
+        let mut cpu = CPU::new(32);
+        let image = parse(":0400100013050500CF\n:00000001FF").unwrap();
+        image.load(&mut cpu, 4).unwrap();
+        assert_eq!(cpu.read_mem_u32(0x14).unwrap(), 0x0005_0513);
+        assert!(image.load(&mut cpu, 16).is_err());
+    }
+}
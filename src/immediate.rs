@@ -71,6 +71,123 @@ impl Immediate {
     pub fn signed_min(&self) -> i32 {
         0 - 2i32.pow(self.bits as u32 - 1)
     }
+
+    /// Width of the immediate in bits
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    /// The value as it's stored in the instruction: just the low `bits` bits
+    pub fn encoded(&self) -> u32 {
+        self.value & self.unsigned_max()
+    }
+
+    /// Breaks the value down for teaching two's complement: see
+    /// [TwosComplement]
+    pub fn explain(&self) -> TwosComplement {
+        TwosComplement {
+            bits: self.bits,
+            encoded: self.encoded(),
+            extended: self.value,
+        }
+    }
+}
+
+/// How an [Immediate] is encoded in two's complement and sign-extended to 32
+/// bits. Displays as a multi-line explanation:
+///
+/// ```
+/// use brubeck::Immediate;
+///
+/// let mut imm = Immediate::new(12);
+/// imm.set_signed(-42).unwrap();
+///
+/// let explanation = imm.explain();
+/// assert!(explanation.negative());
+/// assert_eq!(explanation.magnitude(), 42);
+/// assert_eq!(explanation.to_string().lines().next(), Some("-42 as a 12-bit immediate"));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TwosComplement {
+    pub bits: u8,
+    /// The low `bits` bits, as stored in an instruction
+    pub encoded: u32,
+    /// After sign extension to 32 bits
+    pub extended: u32,
+}
+
+impl TwosComplement {
+    /// Whether the sign (top) bit is set
+    pub fn negative(&self) -> bool {
+        self.encoded >> (self.bits - 1) & 1 == 1
+    }
+
+    /// The value the bits represent
+    pub fn value(&self) -> i32 {
+        self.extended as i32
+    }
+
+    /// The absolute value
+    pub fn magnitude(&self) -> u32 {
+        self.value().unsigned_abs()
+    }
+}
+
+impl std::fmt::Display for TwosComplement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = self.bits as usize;
+
+        writeln!(f, "{} as a {bits}-bit immediate", self.value())?;
+        writeln!(
+            f,
+            "  bits:        {} (0x{:x})",
+            grouped(self.encoded, bits),
+            self.encoded
+        )?;
+
+        if self.negative() {
+            writeln!(
+                f,
+                "  sign bit:    1, so negative; magnitude {}",
+                self.magnitude()
+            )?;
+            writeln!(
+                f,
+                "  negating:    invert {} and add 1",
+                grouped(self.magnitude(), bits)
+            )?;
+            writeln!(
+                f,
+                "  extended:    the sign bit is copied into the upper {} bits",
+                32 - bits
+            )?;
+        } else {
+            writeln!(f, "  sign bit:    0, so positive")?;
+            writeln!(f, "  extended:    the upper {} bits are zero", 32 - bits)?;
+        }
+
+        write!(
+            f,
+            "  as 32 bits:  {} (0x{:08x})",
+            grouped(self.extended, 32),
+            self.extended
+        )
+    }
+}
+
+/// The low `bits` bits of `value` in binary, in groups of 4 from the right
+fn grouped(value: u32, bits: usize) -> String {
+    let binary = format!("{value:0bits$b}");
+    let binary = &binary[binary.len() - bits..];
+
+    let mut output = String::new();
+    for (i, c) in binary.chars().enumerate() {
+        if i > 0 && (bits - i).is_multiple_of(4) {
+            output.push(' ');
+        }
+        output.push(c);
+    }
+    output
 }
 
 #[derive(Debug, Clone)]
@@ -78,6 +195,16 @@ pub enum Error {
     OutOfRange(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfRange(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[cfg(test)]
 mod immediate_tests {
     use super::*;
@@ -134,6 +261,31 @@ mod immediate_tests {
         assert_eq!(imm.as_u32(), u32::MAX);
     }
 
+    #[test]
+    fn explain() {
+        let mut imm = Immediate::new(12);
+        imm.set_signed(-42).unwrap();
+        assert_eq!(imm.encoded(), 0xfd6);
+        assert_eq!(
+            imm.explain().to_string(),
+            "-42 as a 12-bit immediate
+  bits:        1111 1101 0110 (0xfd6)
+  sign bit:    1, so negative; magnitude 42
+  negating:    invert 0000 0010 1010 and add 1
+  extended:    the sign bit is copied into the upper 20 bits
+  as 32 bits:  1111 1111 1111 1111 1111 1111 1101 0110 (0xffffffd6)"
+        );
+
+        let mut imm = Immediate::new(5);
+        imm.set_unsigned(5).unwrap();
+        let explanation = imm.explain();
+        assert!(!explanation.negative());
+        assert_eq!(explanation.value(), 5);
+        assert!(explanation
+            .to_string()
+            .contains("  bits:        0 0101 (0x5)"));
+    }
+
     #[test]
     fn get_unsigned() {
         let mut imm = Immediate::new(8);
@@ -1,10 +1,9 @@
 //! The interpreter takes input, parses it, and executes it in the [CPU](crate::rv32_i::CPU)
 //!
-//! Right now it demonstrates a basic proof of concept; next steps will be making the results and
-//! errors much more useful instead of being Strings.
-//!
-//! Future work will be extending the interpreter input language to more closely match standard
-//! RISC-V assembly; at the moment it's extremely bare bones.
+//! Input is RISC-V assembly (instructions and pseudo-instructions, with labels and constant
+//! expressions), a register or CSR to inspect, or a word of memory (eg: `[sp + 4]`). What happened
+//! comes back as text to show ([Interpreter::interpret]) or as data ([Interpreter::eval]), and
+//! anything that went wrong as an [Error].
 //!
 //! ## Examples
 //!
@@ -13,484 +12,3721 @@
 //!
 //! let mut i = Interpreter::new();
 //!
-//! // output will be a Result<String, Error>, in this case a Debug representation of an ADDI
-//! // instruction with `rd` set to `x1`, `rs1` set to `zero`, and `imm` set to `3`. It won't
-//! // generate the opcode or other parts of the instruction yet.
+//! // output will be a Result<String, Error>, in this case the instruction that ran, in
+//! // assembly syntax with ABI register names
 //! let output = i.interpret("ADDI x1, zero, 3");
-//! assert!(output.is_ok());
+//! assert_eq!(output.unwrap(), "addi ra, zero, 3");
 //!
 //! // the output will be a String containing the value of the `PC` register, which increments
 //! // by 4 bytes with each instruction executed. `PC` and other registers don't change when
 //! // inspected directly.
 //! let output = i.interpret("PC");
 //! assert!(output.is_ok());
+//!
+//! // or what happened, as data
+//! use brubeck::interpreter::EvalOutcome;
+//! let outcome = i.eval("x1").unwrap();
+//! assert!(matches!(outcome, EvalOutcome::RegisterShown { value: 3, .. }));
 //! ```
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
 
-use crate::rv32_i::{BType, IType, Instruction, JType, RType, Register, SType, UType, ABI, CPU};
+use crate::board::Board;
+use crate::cancel::CancelToken;
+use crate::checkpoint::{CheckpointError, Checkpoints};
+use crate::devices::{Bus, Uart};
+use crate::events::{Event, EventBus, Subscriber, SubscriptionId};
+use crate::expression::{self, Constants, ExpressionError};
+use crate::fuzzy;
+use crate::history::{self, HistoryError, StateDelta, StateHistory, Step};
+use crate::immediate;
+use crate::metadata::{self, OperandKind};
+use crate::rv32_i::{
+    csr, semantics, BType, Counters, CpuConfig, CsrChange, CsrFile, IType, Instruction, JType,
+    MemoryWrite, Operands, Privilege, RType, Register, SType, TrapCause, UType, Xlen, CPU,
+};
+use crate::sandbox::Sandbox;
+use crate::script::{self, ScriptError};
+use crate::semihosting::Semihosting;
+use crate::snapshot::{Reader, Snapshot, SnapshotError};
+use crate::stats::ExecutionStats;
+use crate::trace::{TraceRecord, Tracer};
+
+/// How register values are shown in register dumps
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RegisterFormat {
+    #[default]
+    Hex,
+    /// Signed decimal
+    Decimal,
+    Unsigned,
+    Binary,
+    /// The bits read as an IEEE 754 float (single precision in RV32, double
+    /// in RV64)
+    Float,
+}
+
+impl RegisterFormat {
+    pub const ALL: [Self; 5] = [
+        Self::Hex,
+        Self::Decimal,
+        Self::Unsigned,
+        Self::Binary,
+        Self::Float,
+    ];
+
+    /// Finds a format by name: `hex`, `dec` (or `signed`), `unsigned`, `bin`,
+    /// or `float`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "hex" => Some(Self::Hex),
+            "dec" | "signed" => Some(Self::Decimal),
+            "unsigned" => Some(Self::Unsigned),
+            "bin" => Some(Self::Binary),
+            "float" => Some(Self::Float),
+            _ => None,
+        }
+    }
+
+    /// Formats a register's value; hex and binary are padded to the full
+    /// register width
+    pub fn format(self, value: u64, xlen: Xlen) -> String {
+        let bits = xlen.bits() as usize;
+        match (self, xlen) {
+            (Self::Hex, _) => format!("0x{value:0width$x}", width = bits / 4),
+            (Self::Decimal, Xlen::Rv32) => (value as u32 as i32).to_string(),
+            (Self::Decimal, Xlen::Rv64) => (value as i64).to_string(),
+            (Self::Unsigned, _) => value.to_string(),
+            (Self::Binary, _) => format!("0b{value:0bits$b}"),
+            (Self::Float, Xlen::Rv32) => format!("{:?}", f32::from_bits(value as u32)),
+            (Self::Float, Xlen::Rv64) => format!("{:?}", f64::from_bits(value)),
+        }
+    }
+}
+
+impl Display for RegisterFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Hex => "hex",
+            Self::Decimal => "dec",
+            Self::Unsigned => "unsigned",
+            Self::Binary => "bin",
+            Self::Float => "float",
+        };
+        write!(f, "{name}")
+    }
+}
 
-#[derive(Default)]
 pub struct Interpreter {
-    cpu: CPU,
+    /// The CPU can be changed directly, but doing so breaks the undo
+    /// history; see [clear_history](Self::clear_history)
+    pub cpu: CPU,
+    events: EventBus,
+    history: StateHistory,
+    checkpoints: Checkpoints,
+    stats: ExecutionStats,
+    /// Whether to check each result against [semantics]
+    double_check: bool,
+    /// Whether to separate digit groups in values shown by inspections
+    readable_numbers: bool,
+    /// How register dumps show values
+    register_format: RegisterFormat,
+    /// Addresses where [run](Self::run) and
+    /// [reverse_continue](Self::reverse_continue) stop
+    breakpoints: BTreeSet<u32>,
+    /// Watched memory, as start address and length in bytes
+    watchpoints: BTreeMap<u32, u32>,
+    /// Handed a record of every executed instruction
+    tracer: Option<Box<dyn Tracer>>,
+    /// Checked between instructions by anything that runs more than one
+    cancel: CancelToken,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
-    /// Creates a new Interpreter with 1 mebibyte of memory.
+    /// Creates a new Interpreter with 1 mebibyte of memory and the standard
+    /// [devices](crate::devices) at their conventional addresses: a CLINT
+    /// timer, a PLIC, a UART (interrupt source 10), a button (interrupt
+    /// source 1), a keyboard (interrupt source 2), and the toy LEDs,
+    /// switches, seven-segment display, and framebuffer. See
+    /// [Board::default].
     pub fn new() -> Self {
+        Self::with_board(&Board::default()).expect("the default board is valid")
+    }
+
+    /// Creates a new Interpreter with the memory and devices described by a
+    /// [Board].
+    pub fn with_board(board: &Board) -> Result<Self, crate::board::Error> {
+        Ok(Self::with_cpu(board.build()?))
+    }
+
+    /// Creates a new Interpreter whose CPU is set up by `config` (eg: to
+    /// start at `0x8000_0000` with the stack at the top of memory), with the
+    /// standard devices as for [new](Self::new). Fails if memory overlaps
+    /// one of them.
+    ///
+    /// ```
+    /// use brubeck::interpreter::Interpreter;
+    /// use brubeck::rv32_i::{CpuConfig, Register, StackPointer};
+    ///
+    /// let interpreter = Interpreter::with_config(CpuConfig {
+    ///     memory_size: 64 * 1024,
+    ///     memory_base: 0x8000_0000,
+    ///     reset_vector: 0x8000_0000,
+    ///     stack_pointer: StackPointer::Top,
+    ///     ..CpuConfig::default()
+    /// })
+    /// .unwrap();
+    /// assert_eq!(interpreter.cpu.pc, 0x8000_0000);
+    /// assert_eq!(interpreter.cpu.get_register(Register::X2), 0x8001_0000);
+    /// ```
+    pub fn with_config(config: CpuConfig) -> Result<Self, crate::board::Error> {
+        Ok(Self::with_cpu(Board::default().build_with(config)?))
+    }
+
+    /// An Interpreter for `cpu`, with semihosting and nothing else set up
+    fn with_cpu(mut cpu: CPU) -> Self {
+        cpu.semihosting = Some(Semihosting::new());
         Self {
-            cpu: CPU::default(), // initializes with 1 mebibyte of memory
+            cpu,
+            events: EventBus::default(),
+            history: StateHistory::default(),
+            checkpoints: Checkpoints::default(),
+            stats: ExecutionStats::default(),
+            double_check: false,
+            readable_numbers: false,
+            register_format: RegisterFormat::default(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            tracer: None,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Creates a new Interpreter from a board file; see [board](crate::board)
+    /// for the format.
+    #[cfg(feature = "host")]
+    pub fn load_board(path: &str) -> Result<Self, crate::board::Error> {
+        Self::with_board(&Board::load(path)?)
+    }
+
+    /// Allows semihosting file operations within `root`
+    pub fn set_semihosting_root(&mut self, root: &str) {
+        if let Some(sh) = self.cpu.semihosting.as_mut() {
+            sh.root = Some(root.into());
         }
     }
 
+    /// Takes any console output the guest program has produced since the
+    /// last call, from semihosting and then system calls.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let mut output = match self.cpu.semihosting.as_mut() {
+            Some(sh) => sh.take_console(),
+            None => vec![],
+        };
+        output.extend(self.cpu.take_syscall_output());
+        output
+    }
+
+    /// Takes the bytes the guest program has transmitted on the UART since
+    /// the last call (from the first UART, if the board has more than one)
+    pub fn take_uart_output(&mut self) -> Vec<u8> {
+        match self.cpu.devices.get_mut::<Uart>() {
+            Some(uart) => uart.take_output(),
+            None => vec![],
+        }
+    }
+
+    /// Registers a [Subscriber] (such as a closure) to receive every [Event]
+    pub fn subscribe<S: Subscriber + 'static>(&mut self, subscriber: S) -> SubscriptionId {
+        self.events.subscribe(subscriber)
+    }
+
+    /// Stops sending events to a subscriber
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    /// Exit code of the guest program, once it has exited
+    pub fn exit_code(&self) -> Option<u32> {
+        self.cpu.exit_code
+    }
+
     /// Interprets a single command, which could be an instruction (eg: `ADDI x1, zero, 3`) or an
-    /// inspection for registers or memory (eg: `PC` or `X1`). Returns a String or an Error that's
-    /// also just a String. This needs some work.
+    /// inspection for registers or memory (eg: `PC`, `X1`, or `[sp + 4]`). Returns a String or an
+    /// Error that's also just a String; [eval](Self::eval) returns what happened as data instead.
+    ///
+    /// Several commands can be given at once, separated by `;` or newlines
+    /// (eg: `ADDI x1, zero, 3; SLLI x1, x1, 2; x1`). They're all parsed
+    /// before any of them runs, then run in order; the results are joined
+    /// with `; `, and the first failure is an [Error::Statement].
     pub fn interpret(&mut self, input: &str) -> Result<String, Error> {
-        let command = parse(input)?;
-        self.run_command(command)
+        let outcome = self.eval(input)?;
+        Ok(self.describe(&outcome))
+    }
+
+    /// Like [interpret](Self::interpret), but returns an [EvalOutcome]
+    /// saying what happened (eg: the instruction that ran and what it
+    /// changed) for the caller to show however it likes. Several statements
+    /// give [EvalOutcome::Several], one outcome for each (and none, for
+    /// input that's only comments).
+    ///
+    /// A word of memory is inspected with an expression in brackets (eg:
+    /// `[sp + 4]`), on its own rather than as one of several statements.
+    pub fn eval(&mut self, input: &str) -> Result<EvalOutcome, Error> {
+        // custom CSRs aren't known to the parser
+        if let Some(def) = self.cpu.csrs.find(input.trim()) {
+            return self.eval_command(Command::InspectCsr(def.address));
+        }
+        if let Some(expression) = memory_operand(input) {
+            let address = self
+                .evaluate(expression)
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            let value = self.cpu.read_mem_u32(address).map_err(Error::Cpu)?;
+            return Ok(EvalOutcome::MemoryShown { address, value });
+        }
+
+        let statements = statements(input);
+        match statements[..] {
+            // nothing but comments and blank space
+            [] => return Ok(EvalOutcome::Several(vec![])),
+            [statement] => {
+                let command = parse_at(statement, self.cpu.pc, self.cpu.config.xlen)
+                    .map_err(|error| error.offset(offset_in(input, statement)))?;
+                return self.eval_command(command);
+            }
+            _ => {}
+        }
+
+        let commands = parse_statements(input, &statements, self.cpu.pc, self.cpu.config.xlen)?;
+        let mut outcomes = Vec::with_capacity(commands.len());
+        for (index, command) in commands.into_iter().enumerate() {
+            let outcome = self
+                .eval_command(command)
+                .map_err(|error| Error::Statement {
+                    index,
+                    error: Box::new(error),
+                })?;
+            outcomes.push(outcome);
+        }
+        Ok(EvalOutcome::Several(outcomes))
+    }
+
+    /// An outcome as [interpret](Self::interpret) shows it, with numbers
+    /// grouped if [readable_numbers](Self::set_readable_numbers) is on
+    pub fn describe(&self, outcome: &EvalOutcome) -> String {
+        match outcome {
+            EvalOutcome::InstructionExecuted {
+                instruction,
+                trap: None,
+                ..
+            } => instruction.to_string(),
+            EvalOutcome::InstructionExecuted {
+                instruction,
+                trap: Some(cause),
+                ..
+            } => format!("{instruction}, trapped: {}", TrapCause::describe(*cause)),
+            EvalOutcome::RegisterShown { register, value } => format!(
+                "{:?}: {} ({})",
+                register,
+                self.decimal(*value),
+                self.hex(*value)
+            ),
+            EvalOutcome::CsrShown {
+                address: csr::MCAUSE,
+                value,
+            } => format!("mcause: {}", TrapCause::describe(*value)),
+            EvalOutcome::CsrShown { address, value } => {
+                let name = self.cpu.csrs.get(*address).map_or("csr", |def| def.name);
+                match csr::describe_fields(*address, *value) {
+                    Some(fields) => format!("{name}: {} ({fields})", self.hex(*value)),
+                    None => format!("{name}: {} ({})", self.decimal(*value), self.hex(*value)),
+                }
+            }
+            EvalOutcome::MemoryShown { address, value } => format!(
+                "[0x{address:08x}]: {} ({})",
+                self.decimal(*value),
+                self.hex(*value)
+            ),
+            EvalOutcome::Several(outcomes) => {
+                let results: Vec<String> = outcomes.iter().map(|o| self.describe(o)).collect();
+                results.join("; ")
+            }
+        }
+    }
+
+    /// Runs instructions separated by `;` or newlines, like
+    /// [interpret](Self::interpret), returning everything they changed as
+    /// one [StateDelta]. Nothing runs unless all of them parse; otherwise
+    /// it's [execute_all](Self::execute_all), so the [BatchError] says which
+    /// instruction failed.
+    pub fn interpret_all(&mut self, input: &str) -> Result<StateDelta, BatchError> {
+        let batch_error = |index, error| BatchError {
+            index,
+            error,
+            deltas: vec![],
+            rolled_back: false,
+        };
+
+        let mut instructions = vec![];
+        for (index, statement) in statements(input).into_iter().enumerate() {
+            let pc = self
+                .cpu
+                .pc
+                .wrapping_add(instructions.len() as u32 * Instruction::LENGTH);
+            match parse_at(statement, pc, self.cpu.config.xlen) {
+                Ok(Command::Exec(instruction)) => instructions.push(instruction),
+                Ok(Command::Sequence(sequence)) => instructions.extend(sequence),
+                Ok(_) => {
+                    return Err(batch_error(
+                        index,
+                        Error::NotAnInstruction(statement.to_owned()),
+                    ))
+                }
+                Err(error) => return Err(batch_error(index, error)),
+            }
+        }
+
+        let deltas = self.execute_all(&instructions)?;
+        Ok(StateDelta::merge(&deltas))
     }
 
     /// Executes an [Instruction] directly, skipping the parsing steps.
     pub fn execute(&mut self, instruction: Instruction) -> Result<String, Error> {
-        match self.cpu.execute(instruction) {
-            Ok(()) => Ok(format!("{:?}", instruction)),
-            e => Err(Error::Generic(format!("{:?}", e))),
+        let outcome = self.eval_instruction(instruction)?;
+        Ok(self.describe(&outcome))
+    }
+
+    fn eval_instruction(&mut self, instruction: Instruction) -> Result<EvalOutcome, Error> {
+        let delta = self.perform(instruction, Instruction::LENGTH)?;
+        Ok(EvalOutcome::InstructionExecuted {
+            instruction,
+            delta,
+            trap: self.cpu.last_trap(),
+        })
+    }
+
+    /// Executes instructions in order, returning what each one changed.
+    /// Stops at the first one that fails, or when [cancelled](Self::cancel_token);
+    /// the instructions before it stay executed, and are listed in the
+    /// [BatchError].
+    pub fn execute_all(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Vec<StateDelta>, BatchError> {
+        let mut deltas = Vec::with_capacity(instructions.len());
+        for (index, instruction) in instructions.iter().enumerate() {
+            let result = match self.cancel.take() {
+                true => Err(Error::Cancelled),
+                false => self.perform(*instruction, Instruction::LENGTH),
+            };
+            match result {
+                Ok(delta) => deltas.push(delta),
+                Err(error) => {
+                    return Err(BatchError {
+                        index,
+                        error,
+                        deltas,
+                        rolled_back: false,
+                    })
+                }
+            }
         }
+        Ok(deltas)
     }
 
-    /// Executes a [Command], which can be an instruction or an inspection
-    pub fn run_command(&mut self, input: Command) -> Result<String, Error> {
-        match input {
-            Command::Exec(instruction) => self.execute(instruction),
-            Command::Inspect(r) => Ok(format!(
-                "{:?}: {:?} (0x{:x})",
-                r,
-                self.cpu.get_register(r),
-                self.cpu.get_register(r)
-            )),
+    /// Like [execute_all](Self::execute_all), but all or nothing: if any
+    /// instruction fails, the machine (registers, memory, CSRs, and devices)
+    /// and the history go back to how they were before the first one. If
+    /// the history can't undo them, the [BatchError] holds its
+    /// [Error::History] instead, and isn't `rolled_back`.
+    pub fn execute_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Vec<StateDelta>, BatchError> {
+        let saved = self.save_point();
+        self.execute_all(instructions).map_err(|mut e| {
+            match self.roll_back(saved) {
+                Ok(()) => e.rolled_back = true,
+                Err(error) => e.error = Error::History(error),
+            }
+            e
+        })
+    }
+
+    /// What [roll_back](Self::roll_back) needs to put the machine back as
+    /// it is now
+    fn save_point(&self) -> SavePoint {
+        SavePoint {
+            epoch: self.history.epoch(),
+            csrs: self.cpu.csrs.clone(),
+            privilege: self.cpu.privilege,
+            devices: self.cpu.devices.clone(),
+            exit_code: self.cpu.exit_code,
         }
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum Command {
-    Inspect(Register),
-    Exec(Instruction),
-}
+    /// Undoes everything since the [save_point](Self::save_point), and
+    /// forgets it was ever done
+    fn roll_back(&mut self, saved: SavePoint) -> Result<(), HistoryError> {
+        while self.history.epoch() > saved.epoch {
+            self.history.undo(&mut self.cpu)?;
+        }
+        self.history.forget_redo();
+        self.cpu.csrs = saved.csrs;
+        self.cpu.privilege = saved.privilege;
+        self.cpu.devices = saved.devices;
+        self.cpu.exit_code = saved.exit_code;
+        Ok(())
+    }
 
-#[derive(Debug, PartialEq)]
-pub enum Token {
-    Register(Register),
-    Instruction(Instruction),
-    Value32(u32),
-}
+    /// Executes an instruction `length` bytes long (see
+    /// [CPU::execute_with_length]), recording it in the history and emitting
+    /// events
+    fn perform(&mut self, instruction: Instruction, length: u32) -> Result<StateDelta, Error> {
+        let pc = self.cpu.pc;
+        let before = history::registers(&self.cpu);
+        // read before it runs, in case it overwrites itself
+        let word = self.tracer.is_some().then(|| match length {
+            Instruction::COMPRESSED_LENGTH => self.cpu.read_mem_u16(pc).map_or(0, u32::from),
+            _ => instruction.encode(),
+        });
+        let result = self.cpu.execute_with_length(instruction, length);
 
-#[derive(Debug)]
-pub enum Error {
-    Generic(String),
-    UnrecognizedToken(String),
-}
+        let delta = result
+            .is_ok()
+            .then(|| StateDelta::between(&before, &self.cpu));
+        if let Some(delta) = &delta {
+            self.history.record(
+                Step::Executed(instruction),
+                &before,
+                delta.clone(),
+                &self.cpu,
+            );
+            self.stats.record(pc, instruction, self.cpu.pc);
+        }
+        if let (Some(tracer), Some(delta), Some(word)) = (&mut self.tracer, &delta, word) {
+            tracer.trace(&TraceRecord {
+                pc,
+                word,
+                length,
+                instruction,
+                delta: delta.clone(),
+            });
+        }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let err_string = match self {
-            Self::Generic(s) => s.to_owned(),
-            Self::UnrecognizedToken(s) => format!("Unrecognized token: '{}'", s),
-        };
+        if !self.events.is_empty() {
+            self.emit_events(pc, instruction, &result);
+        }
 
-        write!(f, "{err_string}")
-    }
-}
+        // the semantics are of RV32I
+        if result.is_ok() && self.double_check && self.cpu.config.xlen == Xlen::Rv32 {
+            self.check_semantics(instruction, length, &before)?;
+        }
 
-fn parse(input: &str) -> Result<Command, Error> {
-    // clean up whitespace, punctuation, capitalization, etc ...
-    let normalized = normalize(input);
+        match (result, delta) {
+            (Ok(()), Some(delta)) => Ok(delta),
+            (Err(e), _) => Err(Error::Cpu(e)),
+            (Ok(()), None) => unreachable!("a delta is made for every success"),
+        }
+    }
 
-    // convert the normalized input into recognized tokens
-    let mut tokens = tokenize(normalized)?;
+    /// A handle for stopping a run from elsewhere (eg: a Ctrl-C handler).
+    /// Runs check it between instructions, so the machine is never left
+    /// part way through one.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
 
-    // build a command from those tokens
-    build_command(&mut tokens)
-}
+    /// When on, the result of every instruction is recomputed by the
+    /// independent [semantics] model, and any difference is reported as an
+    /// error (after the instruction has run, so it can be inspected or
+    /// undone). Useful while developing new instructions.
+    pub fn set_double_check(&mut self, on: bool) {
+        self.double_check = on;
+    }
 
-fn build_command(tokens: &mut Vec<Token>) -> Result<Command, Error> {
-    if tokens.is_empty() {
-        return Err(Error::Generic("Empty tokens in build!".to_owned()));
+    /// When on, inspected values are shown with `_` between groups of
+    /// digits, eg: `x1: 1_000_000 (0xf_4240)`
+    pub fn set_readable_numbers(&mut self, on: bool) {
+        self.readable_numbers = on;
     }
 
-    let first_token = tokens.remove(0);
+    pub fn readable_numbers(&self) -> bool {
+        self.readable_numbers
+    }
 
-    match first_token {
-        Token::Register(register) => Ok(Command::Inspect(register)),
-        Token::Value32(value) => Err(Error::Generic(format!("Value: {}", value))),
-        Token::Instruction(mut i) => Ok(Command::Exec(build_instruction(&mut i, tokens)?)),
-    }
-}
-
-fn build_instruction(instruction: &mut Instruction, args: &[Token]) -> Result<Instruction, Error> {
-    let output = match instruction {
-        // build instructions
-        Instruction::ADD(mut rtype) => Instruction::ADD(build_rtype(&mut rtype, args)?),
-        Instruction::ADDI(mut itype) => Instruction::ADDI(build_itype(&mut itype, args)?),
-        Instruction::AND(mut rtype) => Instruction::AND(build_rtype(&mut rtype, args)?),
-        Instruction::ANDI(mut itype) => Instruction::ANDI(build_itype(&mut itype, args)?),
-        Instruction::AUIPC(mut utype) => Instruction::AUIPC(build_utype(&mut utype, args)?),
-        Instruction::BEQ(mut btype) => Instruction::BEQ(build_btype(&mut btype, args)?),
-        Instruction::BGE(mut btype) => Instruction::BGE(build_btype(&mut btype, args)?),
-        Instruction::BGEU(mut btype) => Instruction::BGEU(build_btype(&mut btype, args)?),
-        Instruction::BLT(mut btype) => Instruction::BLT(build_btype(&mut btype, args)?),
-        Instruction::BLTU(mut btype) => Instruction::BLTU(build_btype(&mut btype, args)?),
-        Instruction::BNE(mut btype) => Instruction::BNE(build_btype(&mut btype, args)?),
-        Instruction::EBREAK(mut itype) => Instruction::EBREAK(build_itype(&mut itype, args)?),
-        Instruction::ECALL(mut itype) => Instruction::ECALL(build_itype(&mut itype, args)?),
-        Instruction::FENCE(mut itype) => Instruction::FENCE(build_itype(&mut itype, args)?),
-        Instruction::JAL(mut jtype) => Instruction::JAL(build_jtype(&mut jtype, args)?),
-        Instruction::JALR(mut itype) => Instruction::JALR(build_itype(&mut itype, args)?),
-        Instruction::LB(mut itype) => Instruction::LB(build_itype(&mut itype, args)?),
-        Instruction::LBU(mut itype) => Instruction::LBU(build_itype(&mut itype, args)?),
-        Instruction::LH(mut itype) => Instruction::LH(build_itype(&mut itype, args)?),
-        Instruction::LHU(mut itype) => Instruction::LHU(build_itype(&mut itype, args)?),
-        Instruction::LUI(mut utype) => Instruction::LUI(build_utype(&mut utype, args)?),
-        Instruction::LW(mut itype) => Instruction::LW(build_itype(&mut itype, args)?),
-        Instruction::NOP => Instruction::NOP,
-        Instruction::OR(mut rtype) => Instruction::OR(build_rtype(&mut rtype, args)?),
-        Instruction::ORI(mut itype) => Instruction::ORI(build_itype(&mut itype, args)?),
-        Instruction::SB(mut stype) => Instruction::SB(build_stype(&mut stype, args)?),
-        Instruction::SH(mut stype) => Instruction::SH(build_stype(&mut stype, args)?),
-        Instruction::SLL(mut rtype) => Instruction::SLL(build_rtype(&mut rtype, args)?),
-        Instruction::SLLI(mut itype) => Instruction::SLLI(build_itype(&mut itype, args)?),
-        Instruction::SLT(mut rtype) => Instruction::SLT(build_rtype(&mut rtype, args)?),
-        Instruction::SLTI(mut itype) => Instruction::SLTI(build_itype(&mut itype, args)?),
-        Instruction::SLTIU(mut itype) => Instruction::SLTIU(build_itype(&mut itype, args)?),
-        Instruction::SLTU(mut rtype) => Instruction::SLTU(build_rtype(&mut rtype, args)?),
-        Instruction::SRA(mut rtype) => Instruction::SRA(build_rtype(&mut rtype, args)?),
-        Instruction::SRAI(mut itype) => Instruction::SRAI(build_itype(&mut itype, args)?),
-        Instruction::SRL(mut rtype) => Instruction::SRL(build_rtype(&mut rtype, args)?),
-        Instruction::SRLI(mut itype) => Instruction::SRLI(build_itype(&mut itype, args)?),
-        Instruction::SUB(mut rtype) => Instruction::SUB(build_rtype(&mut rtype, args)?),
-        Instruction::SW(mut stype) => Instruction::SW(build_stype(&mut stype, args)?),
-        Instruction::XOR(mut rtype) => Instruction::XOR(build_rtype(&mut rtype, args)?),
-        Instruction::XORI(mut itype) => Instruction::XORI(build_itype(&mut itype, args)?),
-    };
+    /// Hands every instruction executed from now on to `tracer`, replacing
+    /// any tracer already set; see [trace](crate::trace)
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
 
-    Ok(output)
-}
+    /// Stops tracing, returning the tracer that was set
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
 
-fn build_utype(utype: &mut UType, args: &[Token]) -> Result<UType, Error> {
-    if let [Token::Register(rd), Token::Value32(imm)] = args {
-        utype.rd = *rd;
-        utype
-            .imm
-            .set_unsigned(*imm)
-            .map_err(|e| Error::Generic(format!("{:?}", e)))?;
-        Ok(*utype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid UType arguments: {:?}",
-            args
-        )))
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
     }
-}
 
-fn build_jtype(jtype: &mut JType, args: &[Token]) -> Result<JType, Error> {
-    if let [Token::Register(rd), Token::Value32(imm)] = args {
-        jtype.rd = *rd;
-        jtype
-            .imm
-            .set_unsigned(*imm)
-            .map_err(|e| Error::Generic(format!("{:?}", e)))?;
-        Ok(*jtype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid JType arguments: {:?}",
-            args
-        )))
+    /// Sets how register dumps show values, until it's changed again
+    pub fn set_register_format(&mut self, format: RegisterFormat) {
+        self.register_format = format;
     }
-}
 
-fn build_btype(btype: &mut BType, args: &[Token]) -> Result<BType, Error> {
-    if let [Token::Register(rs1), Token::Register(rs2), Token::Value32(imm)] = args {
-        btype.rs1 = *rs1;
-        btype.rs2 = *rs2;
-        btype
-            .imm
-            .set_unsigned(*imm)
-            .map_err(|e| Error::Generic(format!("{:?}", e)))?;
-        Ok(*btype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid BType arguments: {:?}",
-            args
-        )))
+    pub fn register_format(&self) -> RegisterFormat {
+        self.register_format
     }
-}
 
-fn build_stype(stype: &mut SType, args: &[Token]) -> Result<SType, Error> {
-    if let [Token::Register(rs1), Token::Register(rs2), Token::Value32(imm)] = args {
-        stype.rs1 = *rs1;
-        stype.rs2 = *rs2;
-        stype
-            .imm
-            .set_unsigned(*imm)
-            .map_err(|e| Error::Generic(format!("{:?}", e)))?;
-        Ok(*stype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid SType arguments: {:?}",
-            args
-        )))
+    fn decimal(&self, value: impl Into<u64>) -> String {
+        let value = value.into();
+        match self.readable_numbers {
+            true => expression::separated(&value.to_string(), 3),
+            false => value.to_string(),
+        }
     }
-}
 
-fn build_itype(itype: &mut IType, args: &[Token]) -> Result<IType, Error> {
-    if let [Token::Register(rd), Token::Register(rs1), Token::Value32(imm)] = args {
-        itype.rd = *rd;
-        itype.rs1 = *rs1;
-        itype
-            .imm
-            .set_unsigned(*imm)
-            .map_err(|e| Error::Generic(format!("{:?}", e)))?;
-        Ok(*itype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid IType arguments: {:?}",
-            args
-        )))
+    fn hex(&self, value: impl Into<u64>) -> String {
+        let value = value.into();
+        match self.readable_numbers {
+            true => format!("0x{}", expression::separated(&format!("{value:x}"), 4)),
+            false => format!("0x{value:x}"),
+        }
     }
-}
 
-fn build_rtype(rtype: &mut RType, args: &[Token]) -> Result<RType, Error> {
-    if let [Token::Register(rd), Token::Register(rs1), Token::Register(rs2)] = args {
-        rtype.rd = *rd;
-        rtype.rs1 = *rs1;
-        rtype.rs2 = *rs2;
-        Ok(*rtype)
-    } else {
-        Err(Error::Generic(format!(
-            "Invalid RType arguments: {:?}",
-            args
-        )))
+    fn check_semantics(
+        &self,
+        instruction: Instruction,
+        length: u32,
+        before: &history::Registers,
+    ) -> Result<(), Error> {
+        // the semantics are of RV32I, so only the low halves are compared
+        let before = before.map(|value| value as u32);
+        let Some(expected) = semantics::outcome(instruction, length, &before) else {
+            return Ok(());
+        };
+        let actual = Register::ALL.map(|r| self.cpu.get_register(r));
+
+        let differences: Vec<String> = Register::ALL
+            .iter()
+            .filter(|r| expected[**r as usize] != actual[**r as usize])
+            .map(|r| {
+                format!(
+                    "{r} is 0x{:x}, expected 0x{:x}",
+                    actual[*r as usize], expected[*r as usize]
+                )
+            })
+            .collect();
+
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Generic(format!(
+                "Semantics check failed for {:?}: {}",
+                instruction,
+                differences.join(", ")
+            )))
+        }
     }
-}
 
-fn tokenize(input: Vec<String>) -> Result<Vec<Token>, Error> {
-    input.into_iter().map(tokenize_one).collect()
-}
+    /// Assembles a listing (see [parse_listing]) and writes it into memory
+    /// at the reset vector, moving the PC there. Registers and other memory
+    /// are left alone, so [reset](Self::reset) first for a clean start.
+    /// Returns the program's size in bytes.
+    pub fn load_program(&mut self, listing: &str) -> Result<usize, Vec<Numbered<Error>>> {
+        let entry = self.cpu.config.reset_vector;
+        let program = parse_listing_for(listing, entry, self.cpu.config.xlen)?;
 
-fn tokenize_one(input: String) -> Result<Token, Error> {
-    let token = match input.as_str() {
-        // registers
-        "PC" => Token::Register(Register::PC),
-        "X0" => Token::Register(Register::X0),
-        "X1" => Token::Register(Register::X1),
-        "X2" => Token::Register(Register::X2),
-        "X3" => Token::Register(Register::X3),
-        "X4" => Token::Register(Register::X4),
-        "X5" => Token::Register(Register::X5),
-        "X6" => Token::Register(Register::X6),
-        "X7" => Token::Register(Register::X7),
-        "X8" => Token::Register(Register::X8),
-        "X9" => Token::Register(Register::X9),
-        "X10" => Token::Register(Register::X10),
-        "X11" => Token::Register(Register::X11),
-        "X12" => Token::Register(Register::X12),
-        "X13" => Token::Register(Register::X13),
-        "X14" => Token::Register(Register::X14),
-        "X15" => Token::Register(Register::X15),
-        "X16" => Token::Register(Register::X16),
-        "X17" => Token::Register(Register::X17),
-        "X18" => Token::Register(Register::X18),
-        "X19" => Token::Register(Register::X19),
-        "X20" => Token::Register(Register::X20),
-        "X21" => Token::Register(Register::X21),
-        "X22" => Token::Register(Register::X22),
-        "X23" => Token::Register(Register::X23),
-        "X24" => Token::Register(Register::X24),
-        "X25" => Token::Register(Register::X25),
-        "X26" => Token::Register(Register::X26),
-        "X27" => Token::Register(Register::X27),
-        "X28" => Token::Register(Register::X28),
-        "X29" => Token::Register(Register::X29),
-        "X30" => Token::Register(Register::X30),
-        "X31" => Token::Register(Register::X31),
-
-        // ABI-named registers
-        "ZERO" => Token::Register(ABI::Zero.to_register()),
-        "RA" => Token::Register(ABI::RA.to_register()),
-        "SP" => Token::Register(ABI::SP.to_register()),
-        "GP" => Token::Register(ABI::GP.to_register()),
-        "TP" => Token::Register(ABI::TP.to_register()),
-        "T0" => Token::Register(ABI::T0.to_register()),
-        "T1" => Token::Register(ABI::T1.to_register()),
-        "T2" => Token::Register(ABI::T2.to_register()),
-        "S0" => Token::Register(ABI::S0.to_register()),
-        "FP" => Token::Register(ABI::FP.to_register()),
-        "S1" => Token::Register(ABI::S1.to_register()),
-        "A0" => Token::Register(ABI::A0.to_register()),
-        "A1" => Token::Register(ABI::A1.to_register()),
-        "A2" => Token::Register(ABI::A2.to_register()),
-        "A3" => Token::Register(ABI::A3.to_register()),
-        "A4" => Token::Register(ABI::A4.to_register()),
-        "A5" => Token::Register(ABI::A5.to_register()),
-        "A6" => Token::Register(ABI::A6.to_register()),
-        "A7" => Token::Register(ABI::A7.to_register()),
-        "S2" => Token::Register(ABI::S2.to_register()),
-        "S3" => Token::Register(ABI::S3.to_register()),
-        "S4" => Token::Register(ABI::S4.to_register()),
-        "S5" => Token::Register(ABI::S5.to_register()),
-        "S6" => Token::Register(ABI::S6.to_register()),
-        "S7" => Token::Register(ABI::S7.to_register()),
-        "S8" => Token::Register(ABI::S8.to_register()),
-        "S9" => Token::Register(ABI::S9.to_register()),
-        "S10" => Token::Register(ABI::S10.to_register()),
-        "S11" => Token::Register(ABI::S11.to_register()),
-        "T3" => Token::Register(ABI::T3.to_register()),
-        "T4" => Token::Register(ABI::T4.to_register()),
-        "T5" => Token::Register(ABI::T5.to_register()),
-        "T6" => Token::Register(ABI::T6.to_register()),
-
-        // instructions
-        "ADD" => Token::Instruction(Instruction::ADD(RType::default())),
-        "ADDI" => Token::Instruction(Instruction::ADDI(IType::default())),
-        "AND" => Token::Instruction(Instruction::AND(RType::default())),
-        "ANDI" => Token::Instruction(Instruction::ANDI(IType::default())),
-        "AUIPC" => Token::Instruction(Instruction::AUIPC(UType::default())),
-        "BEQ" => Token::Instruction(Instruction::BEQ(BType::default())),
-        "BGE" => Token::Instruction(Instruction::BGE(BType::default())),
-        "BGEU" => Token::Instruction(Instruction::BGEU(BType::default())),
-        "BLT" => Token::Instruction(Instruction::BLT(BType::default())),
-        "BLTU" => Token::Instruction(Instruction::BLTU(BType::default())),
-        "BNE" => Token::Instruction(Instruction::BNE(BType::default())),
-        "EBREAK" => Token::Instruction(Instruction::EBREAK(IType::default())),
-        "ECALL" => Token::Instruction(Instruction::ECALL(IType::default())),
-        "FENCE" => Token::Instruction(Instruction::FENCE(IType::default())),
-        "JAL" => Token::Instruction(Instruction::JAL(JType::default())),
-        "JALR" => Token::Instruction(Instruction::JALR(IType::default())),
-        "LB" => Token::Instruction(Instruction::LB(IType::default())),
-        "LBU" => Token::Instruction(Instruction::LBU(IType::default())),
-        "LH" => Token::Instruction(Instruction::LH(IType::default())),
-        "LHU" => Token::Instruction(Instruction::LHU(IType::default())),
-        "LUI" => Token::Instruction(Instruction::LUI(UType::default())),
-        "LW" => Token::Instruction(Instruction::LW(IType::default())),
-        "NOP" => Token::Instruction(Instruction::NOP),
-        "OR" => Token::Instruction(Instruction::OR(RType::default())),
-        "ORI" => Token::Instruction(Instruction::ORI(IType::default())),
-        "SB" => Token::Instruction(Instruction::SB(SType::default())),
-        "SH" => Token::Instruction(Instruction::SH(SType::default())),
-        "SLL" => Token::Instruction(Instruction::SLL(RType::default())),
-        "SLLI" => Token::Instruction(Instruction::SLLI(IType::default())),
-        "SLT" => Token::Instruction(Instruction::SLT(RType::default())),
-        "SLTI" => Token::Instruction(Instruction::SLTI(IType::default())),
-        "SLTIU" => Token::Instruction(Instruction::SLTIU(IType::default())),
-        "SLTU" => Token::Instruction(Instruction::SLTU(RType::default())),
-        "SRA" => Token::Instruction(Instruction::SRA(RType::default())),
-        "SRAI" => Token::Instruction(Instruction::SRAI(IType::default())),
-        "SRL" => Token::Instruction(Instruction::SRL(RType::default())),
-        "SRLI" => Token::Instruction(Instruction::SRLI(IType::default())),
-        "SUB" => Token::Instruction(Instruction::SUB(RType::default())),
-        "SW" => Token::Instruction(Instruction::SW(SType::default())),
-        "XOR" => Token::Instruction(Instruction::XOR(RType::default())),
-        "XORI" => Token::Instruction(Instruction::XORI(IType::default())),
-
-        // everything else could be a value
-        _ => parse_value(input)?,
-    };
+        let code: Vec<u8> = program
+            .iter()
+            .flat_map(|(_, instruction)| instruction.encode().to_le_bytes())
+            .collect();
 
-    Ok(token)
-}
+        if let Err(e) = self.cpu.write_memory(entry, &code) {
+            // find the first instruction that doesn't fit
+            let memory = self.cpu.memory.len();
+            let fits = self
+                .cpu
+                .address_map
+                .available(entry)
+                .map_or(0, |r| r.end.min(memory).saturating_sub(r.start));
+            let index = fits / Instruction::LENGTH as usize;
+            let line = program.get(index).or(program.last()).map_or(0, |(l, _)| *l);
+            return Err(vec![(
+                line,
+                Error::Generic(format!("Program doesn't fit in memory: {:?}", e)),
+            )]);
+        }
 
-fn parse_value(input: String) -> Result<Token, Error> {
-    // it's gotta be a number; we might build something more NASM-complete later
-    match input.parse::<i32>() {
-        Ok(value) => Ok(Token::Value32(value as u32)),
-        Err(_) => Err(Error::UnrecognizedToken(input)),
+        self.cpu.pc = entry;
+        // memory changed outside of the history
+        self.history.clear();
+
+        self.events.emit(Event::ProgramLoaded {
+            entry,
+            size: code.len(),
+        });
+
+        Ok(code.len())
     }
-}
 
-fn normalize(input: &str) -> Vec<String> {
-    let mut output = vec![];
+    /// Reads and decodes the instruction at the PC; see [CPU::fetch]
+    pub fn fetch(&self) -> Result<Instruction, Error> {
+        self.cpu.fetch().map_err(Error::Cpu)
+    }
 
-    // split on whitespace and commas, uppercase
-    for ws in input.to_uppercase().split_whitespace() {
-        for t in ws.split(',') {
-            // ignore empty tokens
-            if t.is_empty() {
-                continue;
+    /// Fetches the instruction at the PC and executes it, like typing it in.
+    /// A pending interrupt is taken first, so the instruction is the first
+    /// of its handler.
+    pub fn step(&mut self) -> Result<StateDelta, Error> {
+        self.interrupt();
+        let (instruction, length) = self.cpu.fetch_with_length().map_err(Error::Cpu)?;
+        self.perform(instruction, length)
+    }
+
+    /// Takes a pending interrupt (see [CPU::take_interrupt]), recording the
+    /// jump to the handler in the history on its own, so it can be undone
+    /// like an instruction
+    fn interrupt(&mut self) {
+        let pc = self.cpu.pc;
+        let before = history::registers(&self.cpu);
+        if let Some(cause) = self.cpu.take_interrupt() {
+            let delta = StateDelta::between(&before, &self.cpu);
+            self.history
+                .record(Step::Interrupt(cause), &before, delta, &self.cpu);
+            self.events.emit(Event::TrapTaken {
+                pc,
+                cause: cause.mcause(),
+            });
+        }
+    }
+
+    /// Steps until the program exits, an instruction fails, the run is
+    /// [cancelled](Self::cancel_token), or `max_steps` have run. It also
+    /// stops before the instruction at a [breakpoint](Self::add_breakpoint)
+    /// (other than the one it starts on), and after an instruction that
+    /// writes to [watched](Self::watch) memory. A program that has already
+    /// exited doesn't run again until it's reloaded or [reset](Self::reset).
+    pub fn run(&mut self, max_steps: usize) -> Run {
+        let mut steps = 0;
+        let stop = loop {
+            if let Some(code) = self.cpu.exit_code {
+                break Stop::Exited(code);
+            }
+            if steps > 0 && self.breakpoints.contains(&self.cpu.pc) {
+                break Stop::Breakpoint(self.cpu.pc);
+            }
+            if steps == max_steps {
+                break Stop::StepLimit;
+            }
+            if self.cancel.take() {
+                break Stop::Cancelled;
             }
-            output.push(t.to_owned());
+            match self.step() {
+                Ok(delta) => {
+                    steps += 1;
+                    if let Some(address) = first_watched(&self.watchpoints, &delta) {
+                        break Stop::Watchpoint(address);
+                    }
+                }
+                Err(e) => break Stop::Failed(e),
+            }
+        };
+
+        Run { steps, stop }
+    }
+
+    /// Undoes the last `count` instructions in the history, along with any
+    /// interrupts taken between them, returning how many it undid (fewer if
+    /// the history runs out first). A program that had exited can run again
+    /// from where it's taken back to.
+    pub fn step_back(&mut self, count: usize) -> Result<usize, HistoryError> {
+        let mut undone = 0;
+        while undone < count && self.history.epoch() > 0 {
+            if let Some((_, Step::Executed(_))) = self.history.get(self.history.epoch() - 1) {
+                undone += 1;
+            }
+            self.history.undo(&mut self.cpu)?;
+            self.cpu.exit_code = None;
         }
+        Ok(undone)
     }
 
-    output
-}
+    /// [run](Self::run) backwards through the history: undoes instructions
+    /// until the PC is at a [breakpoint](Self::add_breakpoint) or the
+    /// instruction just undone wrote to [watched](Self::watch) memory (so
+    /// the state is from before the write), or until the start of the
+    /// history. `steps` in the result counts the instructions undone.
+    pub fn reverse_continue(&mut self) -> Result<Run, HistoryError> {
+        let mut steps = 0;
+        let stop = loop {
+            let Some(index) = self.history.epoch().checked_sub(1) else {
+                break Stop::HistoryStart;
+            };
+            if self.cancel.take() {
+                break Stop::Cancelled;
+            }
+            if let Some((_, Step::Executed(_))) = self.history.get(index) {
+                steps += 1;
+            }
+            let delta = self.history.undo(&mut self.cpu)?;
+            self.cpu.exit_code = None;
+            if let Some(address) = first_watched(&self.watchpoints, delta) {
+                break Stop::Watchpoint(address);
+            }
+            if self.breakpoints.contains(&self.cpu.pc) {
+                break Stop::Breakpoint(self.cpu.pc);
+            }
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        Ok(Run { steps, stop })
+    }
 
-    #[test]
-    fn normalize_input() {
-        let a = "whitespace is   weird \t and can be dumb";
-        let b = "commas ,are, ok\t,too";
+    /// Makes [run](Self::run) and [reverse_continue](Self::reverse_continue)
+    /// stop when the PC gets to `address`
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.breakpoints.insert(address);
+    }
 
-        assert_eq!(
-            normalize(a),
-            vec!["WHITESPACE", "IS", "WEIRD", "AND", "CAN", "BE", "DUMB"]
-        );
-        assert_eq!(normalize(b), vec!["COMMAS", "ARE", "OK", "TOO"]);
+    /// Returns whether there was a breakpoint at `address`
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        self.breakpoints.remove(&address)
     }
 
-    #[test]
-    fn tokenize_input() {
-        let a = "ADD x1, x2, x3";
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.breakpoints.iter().copied()
+    }
 
-        let normalized = normalize(a);
-        let result = tokenize(normalized);
+    /// Makes [run](Self::run) and [reverse_continue](Self::reverse_continue)
+    /// stop at an instruction that writes to any of the `len` bytes from
+    /// `address`. Watching an address again replaces its length.
+    pub fn watch(&mut self, address: u32, len: u32) {
+        self.watchpoints.insert(address, len.max(1));
+    }
 
-        assert!(result.is_ok());
+    /// Returns whether `address` was watched
+    pub fn unwatch(&mut self, address: u32) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
 
-        let tokens = result.unwrap();
+    /// Every watchpoint, as its address and length
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.watchpoints
+            .iter()
+            .map(|(&address, &len)| (address, len))
+    }
 
-        assert_eq!(
-            tokens,
-            vec![
-                Token::Instruction(Instruction::ADD(RType::default())),
-                Token::Register(Register::X1),
-                Token::Register(Register::X2),
-                Token::Register(Register::X3)
-            ]
-        );
+    /// Evaluates an expression over the machine state, eg: `x5 + x6 * 2` or
+    /// `[sp + 4]`; see [expression](crate::expression)
+    pub fn evaluate(&self, input: &str) -> Result<u32, ExpressionError> {
+        expression::evaluate(input, &self.cpu)
     }
 
-    #[test]
-    fn parse_command() {
-        let a = "ADD x1, x2, x3";
-        let result = parse(a);
+    /// Works out what an instruction would change without changing anything:
+    /// it runs in a [Sandbox], which throws away its changes along with any
+    /// device side effects or output. Nothing is added to the history.
+    pub fn dry_run(&mut self, input: &str) -> Result<StateDelta, Error> {
+        self.speculate(|sandbox| sandbox.interpret(input))
+    }
 
-        assert!(result.is_ok());
+    /// Runs `f` with a [Sandbox] that starts from the current state. Anything
+    /// done in the sandbox is undone when `f` returns; see
+    /// [sandbox](crate::sandbox).
+    pub fn speculate<R>(&mut self, f: impl FnOnce(&mut Sandbox) -> R) -> R {
+        f(&mut Sandbox::new(&mut self.cpu))
+    }
 
-        let rtype = RType {
-            rd: Register::X1,
-            rs1: Register::X2,
-            rs2: Register::X3,
-            ..Default::default()
+    /// For input that's a branch or jump, where it would go if taken from the
+    /// current PC; lets the target be shown before the instruction runs.
+    /// Returns None for anything else, including input that doesn't parse.
+    pub fn preview(&self, input: &str) -> Option<JumpPreview> {
+        let Ok(Command::Exec(instruction)) = parse(input) else {
+            return None;
         };
 
-        assert_eq!(result.unwrap(), Command::Exec(Instruction::ADD(rtype)));
+        let pc = self.cpu.pc;
+        let target = match instruction.operands() {
+            Operands::BType(b) => b.target(pc),
+            Operands::JType(j) => j.target(pc),
+            Operands::IType(i) if matches!(instruction, Instruction::JALR(_)) => {
+                self.cpu.get_register(i.rs1).wrapping_add(i.imm.as_u32()) & !1
+            }
+            _ => return None,
+        };
+
+        Some(JumpPreview {
+            target,
+            offset: target.wrapping_sub(pc) as i32,
+            mapped: self
+                .cpu
+                .address_map
+                .translate(target, Instruction::LENGTH as usize)
+                .is_some(),
+        })
     }
 
-    #[test]
-    fn trivial_add() {
-        let mut i = Interpreter::default();
-        i.cpu.x2 = 3;
-        i.cpu.x3 = 5;
+    /// Reverts the most recently executed instruction, returning what it
+    /// changed. Fails if the CPU was changed outside of the history.
+    pub fn undo(&mut self) -> Result<StateDelta, HistoryError> {
+        self.history.undo(&mut self.cpu).cloned()
+    }
 
-        assert_eq!(i.cpu.x1, 0);
+    /// Re-applies the most recently undone instruction
+    pub fn redo(&mut self) -> Result<StateDelta, HistoryError> {
+        self.history.redo(&mut self.cpu).cloned()
+    }
 
-        let input = "ADD x1, x2, x3";
-        assert!(i.interpret(input).is_ok());
+    /// Sets a register (or the PC) by hand, recording it in the history so
+    /// it can be undone like an instruction. Returns what changed, which is
+    /// nothing for `x0`.
+    ///
+    /// ```
+    /// use brubeck::interpreter::Interpreter;
+    /// use brubeck::rv32_i::Register;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.set_register(Register::X5, 0xdead_beef);
+    /// assert_eq!(interpreter.cpu.get_register(Register::X5), 0xdead_beef);
+    ///
+    /// interpreter.undo().unwrap();
+    /// assert_eq!(interpreter.cpu.get_register(Register::X5), 0);
+    /// ```
+    pub fn set_register(&mut self, register: Register, value: u32) -> StateDelta {
+        let before = history::registers(&self.cpu);
+        self.cpu.set_register(register, value);
+        self.record_set(&before, vec![], vec![])
+    }
 
-        assert_eq!(i.cpu.x1, 8);
+    /// Writes bytes to memory by hand, recording it in the history like
+    /// [set_register](Self::set_register). They must all be in memory, not
+    /// a device.
+    pub fn set_memory(&mut self, address: u32, bytes: &[u8]) -> Result<StateDelta, Error> {
+        let before = history::registers(&self.cpu);
+        let old = self
+            .cpu
+            .address_map
+            .translate(address, bytes.len())
+            .and_then(|range| self.cpu.memory.get(range))
+            .ok_or(Error::Cpu(crate::rv32_i::Error::AccessViolation(address)))?
+            .to_vec();
+        self.cpu.write_memory(address, bytes).map_err(Error::Cpu)?;
+
+        let write = MemoryWrite {
+            address,
+            old,
+            new: bytes.to_vec(),
+        };
+        let memory = match write.old == write.new {
+            true => vec![],
+            false => vec![write],
+        };
+        Ok(self.record_set(&before, memory, vec![]))
+    }
+
+    /// Writes a CSR by hand, recording it in the history like
+    /// [set_register](Self::set_register). As with an instruction's write,
+    /// only the bits the CSR lets software change are set, and read-only
+    /// CSRs can't be.
+    pub fn set_csr(&mut self, address: u32, value: u32) -> Result<StateDelta, Error> {
+        let before = history::registers(&self.cpu);
+        let old = self.cpu.csrs.read(address);
+        self.cpu.write_csr(address, value).map_err(Error::Cpu)?;
+
+        let csrs = match (old, self.cpu.csrs.read(address)) {
+            (Some(old), Some(new)) if old != new => vec![CsrChange { address, old, new }],
+            _ => vec![],
+        };
+        Ok(self.record_set(&before, vec![], csrs))
+    }
+
+    /// Records a change made by hand as a [Step::Set], unless nothing
+    /// changed. `before` is the registers from before it was made.
+    fn record_set(
+        &mut self,
+        before: &history::Registers,
+        memory: Vec<MemoryWrite>,
+        csrs: Vec<CsrChange>,
+    ) -> StateDelta {
+        let delta = StateDelta {
+            memory,
+            csrs,
+            counted: Counters::default(),
+            privilege: None,
+            // the CPU's journals are from the last instruction
+            ..StateDelta::between(before, &self.cpu)
+        };
+        if !delta.is_empty() {
+            self.history
+                .record(Step::Set, before, delta.clone(), &self.cpu);
+        }
+        delta
+    }
+
+    /// What the most recent instruction in the history changed
+    pub fn last_delta(&self) -> Option<&StateDelta> {
+        self.history.last()
+    }
+
+    /// Goes to the state after the first `step` instructions in the
+    /// history (0 is before any of them), undoing or redoing as many as it
+    /// takes. Fails if the CPU was changed outside of the history.
+    pub fn seek(&mut self, step: usize) -> Result<(), HistoryError> {
+        self.history.seek(step, &mut self.cpu)
+    }
+
+    /// The undo history: every instruction executed since it was last
+    /// cleared
+    pub fn history(&self) -> &StateHistory {
+        &self.history
+    }
+
+    /// Resets the CPU (see [CPU::reset]), and forgets the undo history and
+    /// the execution statistics
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.history.clear();
+        self.stats.clear();
+    }
+
+    /// Tallies of the instructions executed since the last reset; see
+    /// [stats](crate::stats)
+    pub fn stats(&self) -> &ExecutionStats {
+        &self.stats
+    }
+
+    /// Starts the execution statistics again from zero
+    pub fn clear_stats(&mut self) {
+        self.stats.clear();
+    }
+
+    /// Copies the machine state; see [Snapshot]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(&self.cpu)
+    }
+
+    /// Returns to a saved machine state, forgetting the undo history
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        snapshot.restore(&mut self.cpu)?;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Saves the machine state under a name, replacing any checkpoint
+    /// already saved under it; see [checkpoint](crate::checkpoint)
+    pub fn checkpoint(&mut self, name: &str) {
+        self.checkpoints.save(name, &self.cpu);
+    }
+
+    /// Returns to the machine state saved under a name, forgetting the undo
+    /// history. The checkpoint is kept, so it can be restored again.
+    pub fn restore_checkpoint(&mut self, name: &str) -> Result<(), CheckpointError> {
+        self.checkpoints.restore(name, &mut self.cpu)?;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// The checkpoints saved so far; they're kept across resets
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// The machine state and the undo history as bytes, eg: to save a
+    /// session to a file. The machine state is a [Snapshot], so device state
+    /// isn't included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.snapshot().write(&mut bytes);
+        self.history.write(&mut bytes);
+        bytes
+    }
+
+    /// Returns to a machine state and undo history saved by
+    /// [to_bytes](Self::to_bytes). A plain [Snapshot] can be restored too,
+    /// with an empty history. Nothing changes if the bytes can't be read.
+    pub fn restore_bytes(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader { bytes };
+        let (snapshot, version) = Snapshot::read_versioned(&mut reader)?;
+        let history = match reader.bytes.is_empty() {
+            true => StateHistory::default(),
+            false => StateHistory::read(&mut reader, version)?,
+        };
+        if !history.fits(&self.cpu) {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        snapshot.restore(&mut self.cpu)?;
+        self.history = history;
+        Ok(())
+    }
+
+    /// Creates an Interpreter on the default [Board] from the bytes of
+    /// [to_bytes](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut interpreter = Self::new();
+        interpreter.restore_bytes(bytes)?;
+        Ok(interpreter)
+    }
+
+    /// Runs a script of instructions and commands line by line, stopping at
+    /// the first that fails; see [script](crate::script). Returns the number
+    /// of assertions checked.
+    pub fn run_script(&mut self, script: &str) -> Result<usize, ScriptError> {
+        script::run(self, script)
+    }
+
+    /// Forgets the undo history, eg: after changing the CPU directly
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    fn emit_events(
+        &mut self,
+        pc: u32,
+        instruction: Instruction,
+        result: &Result<(), crate::rv32_i::Error>,
+    ) {
+        match result {
+            Ok(()) => {
+                for write in self.cpu.last_writes() {
+                    self.events.emit(Event::MemoryWritten {
+                        address: write.address,
+                        bytes: write.new.clone(),
+                    });
+                }
+                if let Some(cause) = self.cpu.last_trap() {
+                    self.events.emit(Event::TrapTaken { pc, cause });
+                }
+                self.events
+                    .emit(Event::InstructionRetired { pc, instruction });
+            }
+            Err(crate::rv32_i::Error::Breakpoint(pc)) => {
+                self.events.emit(Event::BreakpointHit { pc: *pc })
+            }
+            Err(_) => (),
+        }
+    }
+
+    /// Executes a [Command], which can be an instruction or an inspection
+    pub fn run_command(&mut self, input: Command) -> Result<String, Error> {
+        let outcome = self.eval_command(input)?;
+        Ok(self.describe(&outcome))
+    }
+
+    /// Runs a parsed [Command], returning what happened as an [EvalOutcome]
+    pub fn eval_command(&mut self, input: Command) -> Result<EvalOutcome, Error> {
+        match input {
+            Command::Exec(instruction) => self.eval_instruction(instruction),
+            // the instructions a pseudo-instruction stands for run as one:
+            // if any fails, none of them happened
+            Command::Sequence(instructions) => {
+                let saved = self.save_point();
+                let outcomes = instructions
+                    .into_iter()
+                    .map(|instruction| self.eval_instruction(instruction))
+                    .collect::<Result<_, _>>();
+                match outcomes {
+                    Ok(outcomes) => Ok(EvalOutcome::Several(outcomes)),
+                    Err(error) => match self.roll_back(saved) {
+                        Ok(()) => Err(error),
+                        Err(e) => Err(Error::History(e)),
+                    },
+                }
+            }
+            Command::Inspect(register) => Ok(EvalOutcome::RegisterShown {
+                register,
+                value: self.cpu.get_x(register),
+            }),
+            Command::InspectCsr(address) => match self.cpu.read_csr(address) {
+                Ok(value) => Ok(EvalOutcome::CsrShown { address, value }),
+                Err(e) => Err(Error::Cpu(e)),
+            },
+        }
+    }
+}
+
+/// What [Interpreter::eval] did, for the caller to show however it likes
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalOutcome {
+    /// An instruction ran, changing `delta`; `trap` is its cause (as it
+    /// would be in `mcause`) if it trapped
+    InstructionExecuted {
+        instruction: Instruction,
+        delta: StateDelta,
+        trap: Option<u32>,
+    },
+    /// A register was inspected (eg: `x1` or `PC`)
+    RegisterShown { register: Register, value: u64 },
+    /// A CSR was inspected (eg: `mstatus`)
+    CsrShown { address: u32, value: u32 },
+    /// A word of memory was inspected (eg: `[sp + 4]`)
+    MemoryShown { address: u32, value: u32 },
+    /// Several statements, or a pseudo-instruction that stands for several
+    /// instructions, in the order they ran
+    Several(Vec<EvalOutcome>),
+}
+
+/// The expression in `[expression]`, if that's all the input is
+fn memory_operand(input: &str) -> Option<&str> {
+    let input = input.trim();
+    input.strip_prefix('[')?.strip_suffix(']')
+}
+
+/// Where a branch or jump would go; see [Interpreter::preview]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct JumpPreview {
+    /// Absolute address of the target
+    pub target: u32,
+    /// Bytes from the current PC to the target
+    pub offset: i32,
+    /// Whether the target is in memory; jumping anywhere else will fault
+    pub mapped: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Inspect(Register),
+    InspectCsr(u32),
+    Exec(Instruction),
+    /// A pseudo-instruction that stands for several instructions (eg:
+    /// `CALL` is `AUIPC` then `JALR`)
+    Sequence(Vec<Instruction>),
+}
+
+impl Command {
+    /// Bytes of instructions
+    fn size(&self) -> u32 {
+        match self {
+            Self::Exec(_) => Instruction::LENGTH,
+            Self::Sequence(instructions) => instructions.len() as u32 * Instruction::LENGTH,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Token {
+    Register(Register),
+    Instruction(Instruction),
+    /// A CSR, by address
+    Csr(u32),
+    Value32(u32),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Generic(String),
+    UnrecognizedToken(String),
+    /// An instruction was given the wrong operands; `expected` is what it
+    /// takes (eg: `rd, rs1, imm`)
+    Operands {
+        mnemonic: String,
+        expected: String,
+    },
+    /// An immediate doesn't fit in its instruction
+    OutOfRange(immediate::Error),
+    /// A constant expression in an operand couldn't be worked out
+    Expression {
+        input: String,
+        error: ExpressionError,
+    },
+    /// One of an instruction's operands is wrong (eg: `ADDI x1, x99, 3`);
+    /// `index` counts from 0 after the mnemonic, and `operand` is as it was
+    /// typed
+    Operand {
+        index: usize,
+        operand: String,
+        problem: OperandProblem,
+    },
+    /// Something other than an instruction (eg: `x1`) where only
+    /// instructions go, like a program listing
+    NotAnInstruction(String),
+    /// The CPU couldn't execute an instruction (or read a CSR)
+    Cpu(crate::rv32_i::Error),
+    /// The run was stopped through the [CancelToken]
+    Cancelled,
+    /// A script's [Assertion](crate::script::Assertion) didn't hold
+    AssertionFailed(String),
+    /// Instructions that failed part way couldn't be undone, eg: in
+    /// [execute_transaction](Interpreter::execute_transaction)
+    History(HistoryError),
+    /// One of several statements given to [Interpreter::interpret] failed;
+    /// `index` counts from 0, and the statements before it stay executed
+    Statement {
+        index: usize,
+        error: Box<Error>,
+    },
+    /// An error about the part of the input at `span`
+    At {
+        span: Span,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Which kind of error this is, under any [Statement](Self::Statement)
+    /// or [At](Self::At)
+    pub fn kind(&self) -> ErrorKind {
+        use crate::rv32_i::Error as Cpu;
+        match self {
+            Self::Generic(_) => ErrorKind::Other,
+            Self::UnrecognizedToken(_) => ErrorKind::UnrecognizedToken,
+            Self::Operands { .. } => ErrorKind::Operands,
+            Self::Operand { .. } => ErrorKind::Operand,
+            Self::OutOfRange(_) => ErrorKind::OutOfRange,
+            Self::Expression { .. } => ErrorKind::Expression,
+            Self::NotAnInstruction(_) => ErrorKind::NotAnInstruction,
+            Self::Cpu(Cpu::NotImplemented(_)) => ErrorKind::NotImplemented,
+            Self::Cpu(Cpu::Breakpoint(_)) => ErrorKind::Breakpoint,
+            Self::Cpu(Cpu::MisalignedJump(_)) => ErrorKind::MisalignedJump,
+            Self::Cpu(Cpu::AccessViolation(_)) => ErrorKind::AccessViolation,
+            Self::Cpu(Cpu::IllegalInstruction(_)) => ErrorKind::IllegalInstruction,
+            Self::Cpu(Cpu::IllegalCsr(_)) => ErrorKind::IllegalCsr,
+            Self::Cpu(Cpu::EnvironmentCall(_)) => ErrorKind::EnvironmentCall,
+            Self::Cpu(Cpu::MisalignedAccess(..)) => ErrorKind::MisalignedAccess,
+            Self::Cancelled => ErrorKind::Cancelled,
+            Self::AssertionFailed(_) => ErrorKind::AssertionFailed,
+            Self::History(_) => ErrorKind::Other,
+            Self::Statement { error, .. } | Self::At { error, .. } => error.kind(),
+        }
+    }
+
+    /// Where in the input the error is, in bytes, if it's about one part of
+    /// it. For several statements, it's counted from the start of all of
+    /// them, and for a program listing, from the start of the line.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::At { span, .. } => Some(*span),
+            Self::Statement { error, .. } => error.span(),
+            _ => None,
+        }
+    }
+
+    /// Names close to a word that wasn't recognized (eg: `ADD` for `ADDD`),
+    /// of the kind that could go where it was: a register for a register
+    /// operand, a CSR for a CSR operand, and an instruction,
+    /// pseudo-instruction, register, or CSR for anything else. Instructions
+    /// are in upper case, and registers and CSRs in lower case.
+    pub fn suggestions(&self) -> Vec<String> {
+        let registers = || {
+            Register::ALL
+                .iter()
+                .flat_map(|r| [Some(r.name()), r.abi().map(|abi| abi.name())])
+                .flatten()
+                .map(str::to_lowercase)
+        };
+        let csrs = || csr::STANDARD.iter().map(|def| def.name.to_owned());
+
+        let (word, names): (&str, Vec<String>) = match self {
+            Self::Statement { error, .. } | Self::At { error, .. } => return error.suggestions(),
+            Self::UnrecognizedToken(word) => {
+                let instructions = metadata::INSTRUCTIONS
+                    .iter()
+                    .map(|info| info.name)
+                    .chain(pseudo_instructions())
+                    .map(str::to_owned);
+                (
+                    word,
+                    instructions.chain(registers()).chain(csrs()).collect(),
+                )
+            }
+            Self::Operand {
+                operand,
+                problem: OperandProblem::InvalidRegister,
+                ..
+            } => (operand, registers().collect()),
+            Self::Operand {
+                operand,
+                problem: OperandProblem::InvalidCsr,
+                ..
+            } => (operand, csrs().collect()),
+            _ => return vec![],
+        };
+        fuzzy::suggest(word, names.iter().map(String::as_str))
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The same error, with its span moved `by` bytes later
+    fn offset(self, by: usize) -> Self {
+        match self {
+            Self::At { span, error } => Self::At {
+                span: Span {
+                    start: span.start + by,
+                    end: span.end + by,
+                },
+                error,
+            },
+            error => error,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let err_string = match self {
+            Self::Generic(s) => s.to_owned(),
+            Self::UnrecognizedToken(s) => format!("Unrecognized token: '{}'", s),
+            Self::Operands { mnemonic, expected } => format!("{mnemonic} takes {expected}"),
+            Self::Operand {
+                index,
+                operand,
+                problem,
+            } => {
+                let ordinal = ["first", "second", "third", "fourth"]
+                    .get(*index)
+                    .map_or_else(
+                        || format!("operand {}", index + 1),
+                        |o| format!("{o} operand"),
+                    );
+                format!("{ordinal} `{operand}`: {problem}")
+            }
+            Self::OutOfRange(e) => e.to_string(),
+            Self::Expression { input, error } => format!("Invalid expression {input}: {error}"),
+            Self::NotAnInstruction(s) => format!("Not an instruction: {s}"),
+            Self::Cpu(e) => e.to_string(),
+            Self::Cancelled => "Stopped before finishing".to_owned(),
+            Self::AssertionFailed(s) => format!("Assertion failed: {s}"),
+            Self::History(e) => format!("Couldn't roll back: {e}"),
+            Self::Statement { index, error } => format!("Statement {}: {error}", index + 1),
+            Self::At { error, .. } => error.to_string(),
+        };
+
+        write!(f, "{err_string}")
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::OutOfRange(e) => Some(e),
+            Self::Expression { error, .. } => Some(error),
+            Self::Cpu(e) => Some(e),
+            Self::History(e) => Some(e),
+            Self::Statement { error, .. } | Self::At { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// What went wrong, for programs that handle errors (eg: an editor
+/// underlining the input). Each kind has a [code](Self::code) that stays the
+/// same from one version to the next, even if the message changes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Anything without a kind of its own
+    Other,
+    // the input couldn't be parsed
+    UnrecognizedToken,
+    Operands,
+    OutOfRange,
+    Expression,
+    NotAnInstruction,
+    Operand,
+    // the CPU couldn't execute it
+    NotImplemented,
+    Breakpoint,
+    MisalignedJump,
+    AccessViolation,
+    IllegalInstruction,
+    IllegalCsr,
+    EnvironmentCall,
+    MisalignedAccess,
+    // it was stopped, or a check failed
+    Cancelled,
+    AssertionFailed,
+}
+
+impl ErrorKind {
+    /// The kind's code: `E1xx` for parsing, `E2xx` for execution, and `E3xx`
+    /// for anything else
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Other => "E300",
+            Self::UnrecognizedToken => "E101",
+            Self::Operands => "E102",
+            Self::OutOfRange => "E103",
+            Self::Expression => "E104",
+            Self::NotAnInstruction => "E105",
+            Self::Operand => "E106",
+            Self::NotImplemented => "E201",
+            Self::Breakpoint => "E202",
+            Self::MisalignedJump => "E203",
+            Self::AccessViolation => "E204",
+            Self::IllegalInstruction => "E205",
+            Self::IllegalCsr => "E206",
+            Self::EnvironmentCall => "E207",
+            Self::MisalignedAccess => "E208",
+            Self::Cancelled => "E301",
+            Self::AssertionFailed => "E302",
+        }
+    }
+
+    /// Whether the input couldn't be parsed, so nothing ran
+    pub fn is_parse(self) -> bool {
+        self.code().starts_with("E1")
+    }
+}
+
+/// What's wrong with an operand; see [Error::Operand]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OperandProblem {
+    /// A register goes here, but this isn't the name of one
+    InvalidRegister,
+    /// A register goes here, but this is something else (eg: a number)
+    ExpectedRegister,
+    InvalidCsr,
+    ExpectedCsr,
+    InvalidNumber,
+    ExpectedNumber,
+}
+
+impl Display for OperandProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let problem = match self {
+            Self::InvalidRegister => "invalid register",
+            Self::ExpectedRegister => "expected a register",
+            Self::InvalidCsr => "invalid CSR",
+            Self::ExpectedCsr => "expected a CSR",
+            Self::InvalidNumber => "invalid number",
+            Self::ExpectedNumber => "expected a number",
+        };
+        write!(f, "{problem}")
+    }
+}
+
+/// A range of bytes in the input, from `start` up to (not including) `end`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// How far [Interpreter::run] got
+#[derive(Debug)]
+pub struct Run {
+    /// Instructions executed
+    pub steps: usize,
+    pub stop: Stop,
+}
+
+/// Why a [Run] stopped
+#[derive(Debug)]
+pub enum Stop {
+    /// The program exited with this code
+    Exited(u32),
+    /// The step limit was reached first
+    StepLimit,
+    Cancelled,
+    /// An instruction couldn't be fetched or executed; the PC is left on it
+    Failed(Error),
+    /// The PC got to a breakpoint at this address
+    Breakpoint(u32),
+    /// An instruction wrote to watched memory, at this address
+    Watchpoint(u32),
+    /// Running backwards got to the start of the history
+    HistoryStart,
+}
+
+impl Display for Run {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let steps = self.steps;
+        match &self.stop {
+            Stop::Exited(code) => write!(f, "Exited with code {code} after {steps} instructions"),
+            Stop::StepLimit => write!(f, "Stopped at the limit of {steps} instructions"),
+            Stop::Cancelled => write!(f, "Stopped after {steps} instructions"),
+            Stop::Failed(e) => write!(f, "Failed after {steps} instructions: {e}"),
+            Stop::Breakpoint(pc) => write!(
+                f,
+                "Stopped at the breakpoint at 0x{pc:08x} after {steps} instructions"
+            ),
+            Stop::Watchpoint(address) => write!(
+                f,
+                "Stopped at a write to 0x{address:08x} after {steps} instructions"
+            ),
+            Stop::HistoryStart => write!(
+                f,
+                "Reached the start of the history after {steps} instructions"
+            ),
+        }
+    }
+}
+
+/// The first address a delta wrote that's in one of the watchpoints
+fn first_watched(watchpoints: &BTreeMap<u32, u32>, delta: &StateDelta) -> Option<u32> {
+    delta.memory.iter().find_map(|write| {
+        (write.address..=u32::MAX)
+            .take(write.new.len())
+            .find(|&address| {
+                watchpoints
+                    .range(..=address)
+                    .any(|(&start, &len)| address - start < len)
+            })
+    })
+}
+
+/// The state a transaction goes back to if it fails; see
+/// [Interpreter::execute_transaction]
+struct SavePoint {
+    epoch: usize,
+    csrs: CsrFile,
+    privilege: Privilege,
+    devices: Bus,
+    exit_code: Option<u32>,
+}
+
+/// An instruction in a batch failed; see [Interpreter::execute_all]
+#[derive(Debug)]
+pub struct BatchError {
+    /// Position of the failed instruction in the batch
+    pub index: usize,
+    pub error: Error,
+    /// What the instructions before it changed
+    pub deltas: Vec<StateDelta>,
+    /// Whether those changes were undone
+    pub rolled_back: bool,
+}
+
+impl Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instruction {} failed: {}", self.index, self.error)?;
+        if self.rolled_back {
+            write!(f, " (all {} before it were rolled back)", self.deltas.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Something from a listing, with the line number it came from
+pub type Numbered<T> = (usize, T);
+
+/// Parses an assembly listing with one instruction per line; blank lines and
+/// `#` comments are skipped. Returns each instruction with its (1-based) line
+/// number, or every error found, with line numbers.
+///
+/// The listing is taken to start at address 0, which only matters to
+/// PC-relative pseudo-instructions like `LA`; see [parse_listing_at].
+pub fn parse_listing(listing: &str) -> Result<Vec<Numbered<Instruction>>, Vec<Numbered<Error>>> {
+    parse_listing_at(listing, 0)
+}
+
+/// Like [parse_listing], for a listing that will be loaded at `address`.
+/// Without a CPU to say otherwise, shifts can be as big as RV64 allows.
+pub fn parse_listing_at(
+    listing: &str,
+    address: u32,
+) -> Result<Vec<Numbered<Instruction>>, Vec<Numbered<Error>>> {
+    parse_listing_for(listing, address, Xlen::Rv64)
+}
+
+/// Like [parse_listing_at], for a CPU that's `xlen` wide; see [parse_at]
+fn parse_listing_for(
+    listing: &str,
+    address: u32,
+    xlen: Xlen,
+) -> Result<Vec<Numbered<Instruction>>, Vec<Numbered<Error>>> {
+    let mut instructions = vec![];
+    let mut errors = vec![];
+
+    for (index, line) in listing.lines().enumerate() {
+        let code = strip_comment(line).trim();
+        if code.is_empty() {
+            continue;
+        }
+
+        let pc = address.wrapping_add(instructions.len() as u32 * Instruction::LENGTH);
+        match parse_at(code, pc, xlen) {
+            Ok(Command::Exec(instruction)) => instructions.push((index + 1, instruction)),
+            Ok(Command::Sequence(sequence)) => {
+                instructions.extend(sequence.into_iter().map(|i| (index + 1, i)))
+            }
+            Ok(_) => errors.push((index + 1, Error::NotAnInstruction(code.to_owned()))),
+            Err(e) => errors.push((index + 1, e.offset(offset_in(line, code)))),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
+
+/// A line without its `#` comment, if it has one; a `'#'` character literal
+/// isn't a comment
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut position = 0;
+    while let Some(offset) = line[position..].find(['#', '\'']) {
+        position += offset;
+        if line[position..].starts_with('#') {
+            return &line[..position];
+        }
+        position += expression::parse_char(&line[position..]).map_or(1, |(_, len)| len);
+    }
+    line
+}
+
+/// The statements in `input`, which are separated by `;` or newlines;
+/// comments and empty statements are left out
+fn statements(input: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    for line in input.lines() {
+        let line = strip_comment(line);
+        let mut start = 0;
+        let mut position = 0;
+        while let Some(offset) = line[position..].find([';', '\'']) {
+            position += offset;
+            if line[position..].starts_with(';') {
+                statements.push(&line[start..position]);
+                position += 1;
+                start = position;
+            } else {
+                position += expression::parse_char(&line[position..]).map_or(1, |(_, len)| len);
+            }
+        }
+        statements.push(&line[start..]);
+    }
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Parses every statement, or fails on the first that doesn't parse. The
+/// first is at `pc`, and the instructions are taken to follow each other.
+fn parse_statements(
+    input: &str,
+    statements: &[&str],
+    mut pc: u32,
+    xlen: Xlen,
+) -> Result<Vec<Command>, Error> {
+    let mut commands = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        let command = parse_at(statement, pc, xlen).map_err(|error| Error::Statement {
+            index,
+            error: Box::new(error.offset(offset_in(input, statement))),
+        })?;
+        pc = pc.wrapping_add(command.size());
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+/// Parses input whose PC doesn't matter, allowing anything RV64 does; see
+/// [parse_at]
+pub(crate) fn parse(input: &str) -> Result<Command, Error> {
+    parse_at(input, 0, Xlen::Rv64)
+}
+
+/// Parses input as if it were at `pc`, which PC-relative pseudo-instructions
+/// (eg: `LA`) need, for a CPU that's `xlen` wide, which limits how far a
+/// shift immediate can shift. Errors about a word in the input are
+/// [Error::At] it.
+pub(crate) fn parse_at(input: &str, pc: u32, xlen: Xlen) -> Result<Command, Error> {
+    // split into words, remembering where each one is
+    let mut expanded = expand_pseudo(words(input), pc)?;
+
+    if expanded.len() == 1 {
+        // recognize each word, then build a command from them
+        return build_command(tokenize(expanded.remove(0)), xlen);
+    }
+
+    // a pseudo-instruction that stands for several instructions
+    let mut instructions = vec![];
+    for words in expanded {
+        match build_command(tokenize(words), xlen)? {
+            Command::Exec(instruction) => instructions.push(instruction),
+            command => unreachable!("pseudo-instructions expand to instructions: {command:?}"),
+        }
+    }
+    Ok(Command::Sequence(instructions))
+}
+
+fn build_command(mut lexemes: Vec<Lexeme>, xlen: Xlen) -> Result<Command, Error> {
+    if lexemes.is_empty() {
+        return Err(Error::Generic("Empty tokens in build!".to_owned()));
+    }
+
+    let (word, first_token) = lexemes.remove(0);
+
+    match first_token.map_err(|e| word.error(e))? {
+        Token::Register(register) => Ok(Command::Inspect(register)),
+        Token::Csr(address) => Ok(Command::InspectCsr(address)),
+        Token::Value32(value) => Err(Error::Generic(format!("Value: {}", value))),
+        Token::Instruction(mut i) => Ok(Command::Exec(build_instruction(
+            &word, &mut i, lexemes, xlen,
+        )?)),
+    }
+}
+
+/// Checks the operands against what the instruction takes, then builds it.
+/// An immediate that doesn't fit is pointed at the last operand, which is
+/// where immediates go.
+fn build_instruction(
+    word: &Word,
+    instruction: &mut Instruction,
+    lexemes: Vec<Lexeme>,
+    xlen: Xlen,
+) -> Result<Instruction, Error> {
+    let last = lexemes.last().map(|(word, _)| word.clone());
+    let args = check_operands(word, instruction.mnemonic(), lexemes)?;
+    build_operands(instruction, &args, xlen).map_err(|error| match (error, last) {
+        (error @ Error::OutOfRange(_), Some(last)) => last.error(error),
+        (error, _) => error,
+    })
+}
+
+/// Checks the operands one by one against the instruction's syntax (eg:
+/// `rd, rs1, imm`), returning their tokens. The first that doesn't fit is an
+/// [Error::Operand] pointing at it; the wrong number of them is an
+/// [Error::Operands] pointing at all of them (or at the mnemonic, if there
+/// are none).
+fn check_operands(word: &Word, mnemonic: &str, lexemes: Vec<Lexeme>) -> Result<Vec<Token>, Error> {
+    let info = metadata::lookup(mnemonic);
+    let syntax = info.map_or("", |info| info.syntax());
+    let kinds: Vec<OperandKind> = info
+        .map(|info| info.operands().iter().map(|o| o.kind).collect())
+        .unwrap_or_default();
+    if kinds.len() != lexemes.len() {
+        let expected = match syntax {
+            "" => "no operands",
+            syntax => syntax,
+        };
+        let words: Vec<&Word> = lexemes.iter().map(|(word, _)| word).collect();
+        return Err(operands_error(
+            word,
+            &words,
+            wrong_operands(mnemonic, expected),
+        ));
+    }
+
+    let mut tokens = Vec::with_capacity(lexemes.len());
+    for (index, (kind, (word, token))) in kinds.into_iter().zip(lexemes).enumerate() {
+        let problem = match (kind, token) {
+            (OperandKind::Register, Ok(token @ Token::Register(_)))
+            | (OperandKind::Csr, Ok(token @ (Token::Csr(_) | Token::Value32(_))))
+            | (OperandKind::Immediate, Ok(token @ Token::Value32(_))) => {
+                tokens.push(token);
+                continue;
+            }
+            (OperandKind::Register, Err(_)) => OperandProblem::InvalidRegister,
+            (OperandKind::Register, Ok(_)) => OperandProblem::ExpectedRegister,
+            (OperandKind::Csr, Err(_)) => OperandProblem::InvalidCsr,
+            (OperandKind::Csr, Ok(_)) => OperandProblem::ExpectedCsr,
+            (OperandKind::Immediate, Err(Error::UnrecognizedToken(_))) => {
+                OperandProblem::InvalidNumber
+            }
+            // eg: an expression that can't be worked out says why
+            (OperandKind::Immediate, Err(error)) => return Err(word.error(error)),
+            (OperandKind::Immediate, Ok(_)) => OperandProblem::ExpectedNumber,
+        };
+        return Err(word.error(Error::Operand {
+            index: word.operand.unwrap_or(index),
+            operand: word.source.clone(),
+            problem,
+        }));
+    }
+    Ok(tokens)
+}
+
+/// An error about all of an instruction's operands, pointing at them, or at
+/// its mnemonic if there are none
+fn operands_error(mnemonic: &Word, operands: &[&Word], error: Error) -> Error {
+    let spans = (
+        operands.first().and_then(|w| w.span),
+        operands.last().and_then(|w| w.span),
+    );
+    match spans {
+        (Some(first), Some(last)) => Error::At {
+            span: Span {
+                start: first.start,
+                end: last.end,
+            },
+            error: Box::new(error),
+        },
+        _ if operands.is_empty() => mnemonic.error(error),
+        _ => error,
+    }
+}
+
+fn build_operands(
+    instruction: &mut Instruction,
+    args: &[Token],
+    xlen: Xlen,
+) -> Result<Instruction, Error> {
+    let mnemonic = instruction.mnemonic();
+    let operands = match (*instruction, instruction.operands()) {
+        // system instructions take no arguments; the immediate picks which one
+        (Instruction::EBREAK(mut itype), _) => {
+            Operands::IType(build_system(mnemonic, &mut itype, 1, args)?)
+        }
+        (Instruction::ECALL(mut itype), _) => {
+            Operands::IType(build_system(mnemonic, &mut itype, 0, args)?)
+        }
+        (Instruction::CSRRW(mut itype), _)
+        | (Instruction::CSRRS(mut itype), _)
+        | (Instruction::CSRRC(mut itype), _) => {
+            Operands::IType(build_csr(mnemonic, &mut itype, args)?)
+        }
+        // LR.W has no rs2
+        (Instruction::LR_W(mut rtype), _) => match args {
+            [Token::Register(rd), Token::Register(rs1)] => {
+                rtype.rd = *rd;
+                rtype.rs1 = *rs1;
+                Operands::RType(rtype)
+            }
+            _ => return Err(wrong_operands(mnemonic, "rd, rs1")),
+        },
+        (Instruction::CSRRWI(mut itype), _)
+        | (Instruction::CSRRSI(mut itype), _)
+        | (Instruction::CSRRCI(mut itype), _) => {
+            Operands::IType(build_csr_immediate(mnemonic, &mut itype, args)?)
+        }
+
+        (_, Operands::None) => Operands::None,
+        (_, Operands::RType(mut rtype)) => {
+            Operands::RType(build_rtype(mnemonic, &mut rtype, args)?)
+        }
+        (shift, Operands::IType(mut itype)) if shift.is_shift() => {
+            Operands::IType(build_shift(shift, &mut itype, args, xlen)?)
+        }
+        (_, Operands::IType(mut itype)) => {
+            Operands::IType(build_itype(mnemonic, &mut itype, args)?)
+        }
+        (_, Operands::SType(mut stype)) => {
+            Operands::SType(build_stype(mnemonic, &mut stype, args)?)
+        }
+        (_, Operands::BType(mut btype)) => {
+            Operands::BType(build_btype(mnemonic, &mut btype, args)?)
+        }
+        (_, Operands::UType(mut utype)) => {
+            Operands::UType(build_utype(mnemonic, &mut utype, args)?)
+        }
+        (_, Operands::JType(mut jtype)) => {
+            Operands::JType(build_jtype(mnemonic, &mut jtype, args)?)
+        }
+    };
+
+    Ok(instruction
+        .with_operands(operands)
+        .expect("operands are built in the instruction's own format"))
+}
+
+fn build_utype(mnemonic: &str, utype: &mut UType, args: &[Token]) -> Result<UType, Error> {
+    if let [Token::Register(rd), Token::Value32(imm)] = args {
+        utype.rd = *rd;
+        set_immediate(&mut utype.imm, *imm)?;
+        Ok(*utype)
+    } else {
+        Err(wrong_operands(mnemonic, "rd, imm"))
+    }
+}
+
+fn build_jtype(mnemonic: &str, jtype: &mut JType, args: &[Token]) -> Result<JType, Error> {
+    if let [Token::Register(rd), Token::Value32(imm)] = args {
+        jtype.rd = *rd;
+        set_immediate(&mut jtype.imm, *imm)?;
+        Ok(*jtype)
+    } else {
+        Err(wrong_operands(mnemonic, "rd, imm"))
+    }
+}
+
+fn build_btype(mnemonic: &str, btype: &mut BType, args: &[Token]) -> Result<BType, Error> {
+    if let [Token::Register(rs1), Token::Register(rs2), Token::Value32(imm)] = args {
+        btype.rs1 = *rs1;
+        btype.rs2 = *rs2;
+        set_immediate(&mut btype.imm, *imm)?;
+        Ok(*btype)
+    } else {
+        Err(wrong_operands(mnemonic, "rs1, rs2, imm"))
+    }
+}
+
+fn build_stype(mnemonic: &str, stype: &mut SType, args: &[Token]) -> Result<SType, Error> {
+    if let [Token::Register(rs1), Token::Register(rs2), Token::Value32(imm)] = args {
+        stype.rs1 = *rs1;
+        stype.rs2 = *rs2;
+        set_immediate(&mut stype.imm, *imm)?;
+        Ok(*stype)
+    } else {
+        Err(wrong_operands(mnemonic, "rs1, rs2, imm"))
+    }
+}
+
+fn build_itype(mnemonic: &str, itype: &mut IType, args: &[Token]) -> Result<IType, Error> {
+    if let [Token::Register(rd), Token::Register(rs1), Token::Value32(imm)] = args {
+        itype.rd = *rd;
+        itype.rs1 = *rs1;
+        set_immediate(&mut itype.imm, *imm)?;
+        Ok(*itype)
+    } else {
+        Err(wrong_operands(mnemonic, "rd, rs1, imm"))
+    }
+}
+
+/// Constant expressions evaluate to a u32, so one with the top bit set is a
+/// negative number (eg: `-1`, or `10 - 20`) and has to fit the field signed;
+/// anything else can use the field's full unsigned range (eg: `4095`).
+fn set_immediate(imm: &mut immediate::Immediate, value: u32) -> Result<(), Error> {
+    match value as i32 {
+        signed if signed < 0 => imm.set_signed(signed),
+        _ => imm.set_unsigned(value),
+    }
+    .map_err(Error::OutOfRange)
+}
+
+/// Shift amounts share the immediate with `funct7`, so they're limited to 6
+/// bits (63) in RV64, or 5 bits (31) in RV32 and for the RV64 W forms;
+/// anything bigger wouldn't encode as the same instruction (or, in RV32, as
+/// one that runs at all)
+fn build_shift(
+    shift: Instruction,
+    itype: &mut IType,
+    args: &[Token],
+    xlen: Xlen,
+) -> Result<IType, Error> {
+    let itype = build_itype(shift.mnemonic(), itype, args)?;
+    let max = match (xlen, shift) {
+        (Xlen::Rv32, _)
+        | (_, Instruction::SLLIW(_) | Instruction::SRLIW(_) | Instruction::SRAIW(_)) => 31,
+        (Xlen::Rv64, _) => 63,
+    };
+    let amount = itype.imm.as_u32();
+    if amount > max {
+        return Err(Error::OutOfRange(immediate::Error::OutOfRange(format!(
+            "Shift amount {amount} is too big; {} shifts by at most {max}.",
+            shift.mnemonic()
+        ))));
+    }
+    Ok(itype)
+}
+
+/// The error for operands that don't fit the instruction, which takes `expected`
+fn wrong_operands(mnemonic: &str, expected: &str) -> Error {
+    Error::Operands {
+        mnemonic: mnemonic.to_owned(),
+        expected: expected.to_owned(),
+    }
+}
+
+/// ECALL and EBREAK take no arguments; they're told apart by the immediate
+fn build_system(
+    mnemonic: &str,
+    itype: &mut IType,
+    imm: u32,
+    args: &[Token],
+) -> Result<IType, Error> {
+    if args.is_empty() {
+        itype.imm.set_unsigned(imm).map_err(Error::OutOfRange)?;
+        Ok(*itype)
+    } else {
+        Err(wrong_operands(mnemonic, "no operands"))
+    }
+}
+
+/// CSR instructions are written `rd, csr, rs1`; the CSR address goes in the
+/// immediate
+fn build_csr(mnemonic: &str, itype: &mut IType, args: &[Token]) -> Result<IType, Error> {
+    const EXPECTED: &str = "rd, csr, rs1";
+    if let [Token::Register(rd), csr, Token::Register(rs1)] = args {
+        itype.rd = *rd;
+        itype.rs1 = *rs1;
+        set_csr_address(itype, csr).ok_or_else(|| wrong_operands(mnemonic, EXPECTED))?;
+        Ok(*itype)
+    } else {
+        Err(wrong_operands(mnemonic, EXPECTED))
+    }
+}
+
+/// The immediate forms are written `rd, csr, uimm`; the 5-bit uimm goes where
+/// rs1 would be
+fn build_csr_immediate(mnemonic: &str, itype: &mut IType, args: &[Token]) -> Result<IType, Error> {
+    const EXPECTED: &str = "rd, csr, uimm";
+    if let [Token::Register(rd), csr, Token::Value32(uimm)] = args {
+        let Some(rs1) = Register::ALL.get(*uimm as usize).filter(|_| *uimm < 32) else {
+            return Err(Error::OutOfRange(immediate::Error::OutOfRange(format!(
+                "CSR immediate {} doesn't fit in 5 bits",
+                *uimm as i32
+            ))));
+        };
+        itype.rd = *rd;
+        itype.rs1 = *rs1;
+        set_csr_address(itype, csr).ok_or_else(|| wrong_operands(mnemonic, EXPECTED))?;
+        Ok(*itype)
+    } else {
+        Err(wrong_operands(mnemonic, EXPECTED))
+    }
+}
+
+/// A CSR can be given by name or by address; `None` if it's neither
+fn set_csr_address(itype: &mut IType, csr: &Token) -> Option<()> {
+    let address = match csr {
+        Token::Csr(address) | Token::Value32(address) if *address < 4096 => *address,
+        _ => return None,
+    };
+
+    itype.imm.set_unsigned(address).ok()
+}
+
+fn build_rtype(mnemonic: &str, rtype: &mut RType, args: &[Token]) -> Result<RType, Error> {
+    if let [Token::Register(rd), Token::Register(rs1), Token::Register(rs2)] = args {
+        rtype.rd = *rd;
+        rtype.rs1 = *rs1;
+        rtype.rs2 = *rs2;
+        Ok(*rtype)
+    } else {
+        Err(wrong_operands(mnemonic, "rd, rs1, rs2"))
+    }
+}
+
+/// The mnemonics of every pseudo-instruction (eg: `MV`, `LI`), in upper
+/// case; some (`JAL`, `JALR`) are also the names of real instructions
+pub fn pseudo_instructions() -> impl Iterator<Item = &'static str> {
+    PSEUDO.iter().map(|(name, ..)| *name)
+}
+
+/// Pseudo-instructions, with how many operands they take and the
+/// instructions they stand for. `{0}`, `{1}`, ... are the operands, and `{hi}`
+/// and `{lo}` are the parts of the last one (see [expression::hi_lo]). Branch and `J`
+/// offsets count 2-byte steps, like the instructions they become; `CALL` and
+/// `TAIL` offsets are in bytes from the `AUIPC`, and `LA` takes an address.
+/// (`LA` is always an `AUIPC` and an `ADDI`, so a program's size doesn't
+/// depend on where it is.)
+const PSEUDO: &[(&str, usize, &[&str])] = &[
+    ("MV", 2, &["ADDI {0} {1} 0"]),
+    ("NOT", 2, &["XORI {0} {1} 4095"]),
+    ("NEG", 2, &["SUB {0} ZERO {1}"]),
+    ("LI", 2, &["LUI {0} {hi}", "ADDI {0} {0} {lo}"]),
+    ("SEQZ", 2, &["SLTIU {0} {1} 1"]),
+    ("SNEZ", 2, &["SLTU {0} ZERO {1}"]),
+    ("SLTZ", 2, &["SLT {0} {1} ZERO"]),
+    ("SGTZ", 2, &["SLT {0} ZERO {1}"]),
+    ("BEQZ", 2, &["BEQ {0} ZERO {1}"]),
+    ("BNEZ", 2, &["BNE {0} ZERO {1}"]),
+    ("BLEZ", 2, &["BGE ZERO {0} {1}"]),
+    ("BGEZ", 2, &["BGE {0} ZERO {1}"]),
+    ("BLTZ", 2, &["BLT {0} ZERO {1}"]),
+    ("BGTZ", 2, &["BLT ZERO {0} {1}"]),
+    ("BGT", 3, &["BLT {1} {0} {2}"]),
+    ("BLE", 3, &["BGE {1} {0} {2}"]),
+    ("BGTU", 3, &["BLTU {1} {0} {2}"]),
+    ("BLEU", 3, &["BGEU {1} {0} {2}"]),
+    ("J", 1, &["JAL ZERO {0}"]),
+    ("JAL", 1, &["JAL RA {0}"]),
+    ("JR", 1, &["JALR ZERO {0} 0"]),
+    ("JALR", 1, &["JALR RA {0} 0"]),
+    ("RET", 0, &["JALR ZERO RA 0"]),
+    ("LA", 2, &["AUIPC {0} {hi}", "ADDI {0} {0} {lo}"]),
+    ("CALL", 1, &["AUIPC RA {hi}", "JALR RA RA {lo}"]),
+    ("TAIL", 1, &["AUIPC T1 {hi}", "JALR ZERO T1 {lo}"]),
+    ("CSRR", 2, &["CSRRS {0} {1} ZERO"]),
+    ("CSRW", 2, &["CSRRW ZERO {0} {1}"]),
+    ("CSRS", 2, &["CSRRS ZERO {0} {1}"]),
+    ("CSRC", 2, &["CSRRC ZERO {0} {1}"]),
+    ("CSRWI", 2, &["CSRRWI ZERO {0} {1}"]),
+    ("CSRSI", 2, &["CSRRSI ZERO {0} {1}"]),
+    ("CSRCI", 2, &["CSRRCI ZERO {0} {1}"]),
+    ("RDCYCLE", 1, &["CSRRS {0} CYCLE ZERO"]),
+    ("RDCYCLEH", 1, &["CSRRS {0} CYCLEH ZERO"]),
+    ("RDTIME", 1, &["CSRRS {0} TIME ZERO"]),
+    ("RDTIMEH", 1, &["CSRRS {0} TIMEH ZERO"]),
+    ("RDINSTRET", 1, &["CSRRS {0} INSTRET ZERO"]),
+    ("RDINSTRETH", 1, &["CSRRS {0} INSTRETH ZERO"]),
+];
+
+/// Rewrites a pseudo-instruction (eg: `BEQZ a0, 8`) as the instructions it
+/// stands for, as words; anything else is left as it is. `LI`
+/// leaves out the `LUI` for a value that fits in 12 bits, and the `ADDI` for
+/// one whose low 12 bits are zero. `pc` is where the first instruction will
+/// be, for `LA`. The operands keep where they are in the input, so errors
+/// about them still point there; `{hi}` and `{lo}` point at the last one.
+fn expand_pseudo(input: Vec<Word>, pc: u32) -> Result<Vec<Vec<Word>>, Error> {
+    let Some((mnemonic, operands)) = input.split_first() else {
+        return Ok(vec![input]);
+    };
+    let name = mnemonic.text.as_str();
+    let pseudo = PSEUDO
+        .iter()
+        .find(|(pseudo, count, _)| *pseudo == name && *count == operands.len());
+    let Some((_, _, expansion)) = pseudo else {
+        return match PSEUDO.iter().find(|(pseudo, ..)| *pseudo == name) {
+            Some((_, count, _)) if Instruction::from_mnemonic(name).is_none() => {
+                let expected = match count {
+                    0 => "no operands".to_owned(),
+                    1 => "1 operand".to_owned(),
+                    _ => format!("{count} operands"),
+                };
+                let operands: Vec<&Word> = operands.iter().collect();
+                let error = wrong_operands(name, &expected);
+                Err(operands_error(mnemonic, &operands, error))
+            }
+            _ => Ok(vec![input]),
+        };
+    };
+
+    let (hi, lo) = match operands.last() {
+        Some(last) if expansion.iter().any(|i| i.contains("{hi}")) => {
+            match parse_value(last.text.clone()).map_err(|e| last.error(e))? {
+                Token::Value32(value) if name == "LA" => expression::hi_lo(value.wrapping_sub(pc)),
+                Token::Value32(value) => expression::hi_lo(value),
+                _ => unreachable!("values are the only tokens parse_value makes"),
+            }
+        }
+        _ => (0, 0),
+    };
+    let expansion: &[&str] = match (name, hi, lo) {
+        ("LI", 0, _) => &["ADDI {0} ZERO {lo}"],
+        ("LI", _, 0) => &["LUI {0} {hi}"],
+        _ => expansion,
+    };
+
+    let part = |value: u32| Word {
+        text: value.to_string(),
+        ..operands[operands.len() - 1].clone()
+    };
+    let word = |word: &str| match word {
+        "{hi}" => part(hi),
+        "{lo}" => part(lo),
+        _ => match word.strip_prefix('{').and_then(|w| w.strip_suffix('}')) {
+            Some(index) => operands[index.parse::<usize>().expect("operand number")].clone(),
+            None => Word::added(word),
+        },
+    };
+    Ok(expansion
+        .iter()
+        .map(|instruction| instruction.split_whitespace().map(word).collect())
+        .collect())
+}
+
+/// A word, and what it's recognized as. Words that aren't recognized are
+/// kept, so the builder can say which operand is wrong and why.
+type Lexeme = (Word, Result<Token, Error>);
+
+fn tokenize(words: Vec<Word>) -> Vec<Lexeme> {
+    words
+        .into_iter()
+        .map(|word| {
+            let token = tokenize_one(word.text.clone());
+            (word, token)
+        })
+        .collect()
+}
+
+fn tokenize_one(input: String) -> Result<Token, Error> {
+    if let Some(register) = Register::from_name(&input) {
+        return Ok(Token::Register(register));
+    }
+
+    if let Some(instruction) = Instruction::from_mnemonic(&input) {
+        return Ok(Token::Instruction(instruction));
+    }
+
+    if let Some(address) = csr::address(&input) {
+        return Ok(Token::Csr(address));
+    }
+
+    // everything else could be a value
+    parse_value(input)
+}
+
+fn parse_value(input: String) -> Result<Token, Error> {
+    // a number, or a constant expression (eg: `(1<<20)-4`) worked out now,
+    // before the instruction checks that it fits
+    match input.parse::<i32>() {
+        Ok(value) => Ok(Token::Value32(value as u32)),
+        Err(_) if input.starts_with(|c: char| c.is_ascii_digit()) && !is_expression(&input) => {
+            match expression::parse_number(&input) {
+                Some(value) => Ok(Token::Value32(value)),
+                None => Err(Error::UnrecognizedToken(input)),
+            }
+        }
+        Err(_) if is_expression(&input) => match expression::evaluate(&input, &Constants) {
+            Ok(value) => Ok(Token::Value32(value)),
+            Err(error) => Err(Error::Expression { input, error }),
+        },
+        Err(_) => Err(Error::UnrecognizedToken(input)),
+    }
+}
+
+/// Characters that only appear in expressions
+const OPERATORS: &str = "+-*/%&|^~<>()";
+
+fn is_expression(input: &str) -> bool {
+    input.contains(|c| OPERATORS.contains(c))
+}
+
+/// Replaces character literals (eg: `'a'` or `' '`) with their values,
+/// before case and punctuation are lost
+fn char_literals(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(quote) = rest.find('\'') {
+        output.push_str(&rest[..quote]);
+        rest = &rest[quote..];
+        match expression::parse_char(rest) {
+            Some((value, len)) => {
+                output.push_str(&value.to_string());
+                rest = &rest[len..];
+            }
+            None => {
+                output.push('\'');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Whether `next` continues the expression `previous` (they're separated by
+/// whitespace). A leading `-` or `~` is a sign, not a continuation, so `x0 -5`
+/// stays two words.
+fn joins(previous: &str, next: &str) -> bool {
+    let operator = |c: char| OPERATORS.contains(c);
+    previous.ends_with(|c| operator(c) && c != ')')
+        || next.starts_with(|c| operator(c) && c != '-' && c != '~' && c != '(')
+        || next.chars().all(operator)
+}
+
+/// Where `inner`, a slice of `outer`, starts in it
+fn offset_in(outer: &str, inner: &str) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
+
+/// A word of input and where it came from
+#[derive(Debug, Clone, PartialEq)]
+struct Word {
+    /// In upper case, with character literals replaced by their values
+    text: String,
+    /// As it was typed
+    source: String,
+    /// Where it is in the input; `None` for words a pseudo-instruction adds
+    span: Option<Span>,
+    /// Which operand it is, counting from 0 after the mnemonic
+    operand: Option<usize>,
+}
+
+impl Word {
+    /// A word that isn't in the input, from a pseudo-instruction's expansion
+    fn added(text: &str) -> Self {
+        Self {
+            text: text.to_owned(),
+            source: text.to_lowercase(),
+            span: None,
+            operand: None,
+        }
+    }
+
+    /// An error about this word, [at](Error::At) it if it's in the input
+    fn error(&self, error: Error) -> Error {
+        match self.span {
+            Some(span) => Error::At {
+                span,
+                error: Box::new(error),
+            },
+            None => error,
+        }
+    }
+}
+
+/// Splits input into words: the mnemonic, then the operands. They're
+/// separated by commas or whitespace, but spaces around an operator are
+/// inside an expression, so the pieces go back together (eg: `1 << 4` is one
+/// word). Character literals (eg: `','` or `' '`) are never split.
+fn words(input: &str) -> Vec<Word> {
+    let mut words: Vec<Word> = vec![];
+    for operand in split(input, |c| c == ',') {
+        let first = words.len();
+        for piece in split(operand, char::is_whitespace) {
+            let start = offset_in(input, piece);
+            let end = start + piece.len();
+            let text = char_literals(piece).to_uppercase();
+            let in_operand = words.len() > first;
+            match words.last_mut() {
+                Some(last) if in_operand && joins(&last.text, &text) => {
+                    last.text.push_str(&text);
+                    if let Some(span) = &mut last.span {
+                        span.end = end;
+                        last.source = input[span.start..end].to_owned();
+                    }
+                }
+                _ => words.push(Word {
+                    text,
+                    source: piece.to_owned(),
+                    span: Some(Span { start, end }),
+                    operand: None,
+                }),
+            }
+        }
+    }
+    for (index, word) in words.iter_mut().enumerate().skip(1) {
+        word.operand = Some(index - 1);
+    }
+    words
+}
+
+/// The pieces of `text` between separators, leaving out empty ones, and
+/// without looking inside character literals
+fn split(text: &str, separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    let mut position = 0;
+    while let Some((offset, c)) = text[position..]
+        .char_indices()
+        .find(|&(_, c)| c == '\'' || separator(c))
+    {
+        position += offset;
+        if c == '\'' {
+            position += expression::parse_char(&text[position..]).map_or(1, |(_, len)| len);
+        } else {
+            pieces.push(&text[start..position]);
+            position += c.len_utf8();
+            start = position;
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces.into_iter().filter(|p| !p.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(input: &str) -> Vec<String> {
+        words(input).into_iter().map(|word| word.text).collect()
+    }
+
+    #[test]
+    fn normalize_input() {
+        let a = "whitespace is   weird \t and can be dumb";
+        let b = "commas ,are, ok\t,too";
+
+        assert_eq!(
+            normalize(a),
+            vec!["WHITESPACE", "IS", "WEIRD", "AND", "CAN", "BE", "DUMB"]
+        );
+        assert_eq!(normalize(b), vec!["COMMAS", "ARE", "OK", "TOO"]);
+
+        // where each word is, and which operand
+        let words = words("addi x1,  x0, 1 << 4");
+        let spans: Vec<_> = words
+            .iter()
+            .map(|w| {
+                (
+                    w.source.as_str(),
+                    w.span.map(|s| (s.start, s.end)),
+                    w.operand,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("addi", Some((0, 4)), None),
+                ("x1", Some((5, 7)), Some(0)),
+                ("x0", Some((10, 12)), Some(1)),
+                ("1 << 4", Some((14, 20)), Some(2)),
+            ]
+        );
+        assert_eq!(normalize("ADDI x1, x0, ' '"), ["ADDI", "X1", "X0", "32"]);
+    }
+
+    #[test]
+    fn tokenize_input() {
+        let a = "ADD x1, x2, x3";
+
+        let tokens: Result<Vec<Token>, Error> = tokenize(words(a))
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect();
+        let tokens = tokens.unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Instruction(Instruction::ADD(RType::default())),
+                Token::Register(Register::X1),
+                Token::Register(Register::X2),
+                Token::Register(Register::X3)
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_matches_parser() {
+        for info in crate::metadata::INSTRUCTIONS {
+            let token = tokenize_one(info.name.to_owned());
+            assert!(
+                matches!(token, Ok(Token::Instruction(_))),
+                "{} isn't parsed as an instruction",
+                info.name
+            );
+
+            let example = info.example();
+            assert!(parse(&example).is_ok(), "{example} doesn't parse");
+        }
+    }
+
+    #[test]
+    fn parse_command() {
+        let a = "ADD x1, x2, x3";
+        let result = parse(a);
+
+        assert!(result.is_ok());
+
+        let rtype = RType {
+            rd: Register::X1,
+            rs1: Register::X2,
+            rs2: Register::X3,
+            ..Default::default()
+        };
+
+        assert_eq!(result.unwrap(), Command::Exec(Instruction::ADD(rtype)));
+    }
+
+    #[test]
+    fn expression_immediates() {
+        let mut i = Interpreter::default();
+
+        i.interpret("ADDI x1, zero, 10 + 5").unwrap();
+        i.interpret("ADDI x2, zero, 0x1000 >> 12").unwrap();
+        i.interpret("LUI x3, (1 << 20) - 4").unwrap();
+        i.interpret("ADDI x4, zero, (2+3) * 4").unwrap();
+        i.interpret("ADDI x5, zero, 7 - 2 - 1").unwrap();
+        assert_eq!(
+            [i.cpu.x1, i.cpu.x2, i.cpu.x3, i.cpu.x4, i.cpu.x5],
+            [15, 1, 0xffff_c000, 20, 4]
+        );
+
+        // negative results are signed immediates
+        i.interpret("ADDI x1, zero, 10 - 20").unwrap();
+        i.interpret("ADDI x2, zero, -1").unwrap();
+        i.interpret("LUI x3, -(1 << 4)").unwrap();
+        assert_eq!(
+            [i.cpu.x1, i.cpu.x2, i.cpu.x3],
+            [-10i32 as u32, u32::MAX, 0xffff_0000]
+        );
+        i.interpret("ADDI x4, zero, 0x104").unwrap();
+        i.interpret("SW x4, x2, -(2 * 2)").unwrap();
+        assert_eq!(i.cpu.memory[0x100..0x104], [0xff; 4]);
+        let pc = i.cpu.pc;
+        i.interpret("BEQ x0, x0, -4").unwrap(); // in halfwords
+        assert_eq!(i.cpu.pc, pc - 8);
+        assert_eq!(
+            i.interpret("ADDI x1, zero, -2048 - 1")
+                .unwrap_err()
+                .to_string(),
+            "Signed value -2049 is too small for 12 bits."
+        );
+
+        // worked out before the range is checked
+        assert!(i.interpret("ADDI x1, zero, 4000 + 96").is_err());
+        assert_eq!(
+            i.interpret("ADDI x1, zero, 1 / 0").unwrap_err().to_string(),
+            "Invalid expression 1/0: division by zero"
+        );
+        assert_eq!(normalize("addi x1, x0 -5"), ["ADDI", "X1", "X0", "-5"]);
+
+        // %hi and %lo build a 32-bit constant, whatever bit 11 is
+        for value in ["0x12345678", "0xdeadbeef", "0x7ff", "-2048"] {
+            i.interpret(&format!("LUI x6, %hi({value})")).unwrap();
+            i.interpret(&format!("ADDI x6, x6, %lo({value})")).unwrap();
+            assert_eq!(i.cpu.x6, expression::evaluate(value, &Constants).unwrap());
+        }
+    }
+
+    #[test]
+    fn character_literals() {
+        let mut i = Interpreter::default();
+
+        i.interpret("ADDI x1, x0, 'A'").unwrap();
+        i.interpret("ADDI x2, x0, 'a'").unwrap();
+        i.interpret("ADDI x3, x0, '\\n'").unwrap();
+        i.interpret("ADDI x4, x0, ','").unwrap();
+        i.interpret("ADDI x5, x0, ' '").unwrap();
+        i.interpret("ADDI x6, x0, '\\x41' + 1").unwrap();
+        i.interpret("ADDI x7, x0, '\\0'").unwrap();
+        assert_eq!(
+            [i.cpu.x1, i.cpu.x2, i.cpu.x3, i.cpu.x4, i.cpu.x5, i.cpu.x6, i.cpu.x7],
+            [65, 97, 10, 44, 32, 66, 0]
+        );
+
+        assert!(i.interpret("ADDI x1, x0, 'ab'").is_err());
+        assert_eq!(
+            strip_comment("ADDI x1, x0, '#' # hash"),
+            "ADDI x1, x0, '#' "
+        );
+        assert_eq!(strip_comment("NOP 'a # b"), "NOP 'a ");
+        assert!(i.interpret("ADDI x1, x0, '").is_err());
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        let same = |pseudo, real| assert_eq!(parse(pseudo).unwrap(), parse(real).unwrap());
+        same("MV a0, a1", "ADDI a0, a1, 0");
+        same("NOT a0, a1", "XORI a0, a1, 4095");
+        same("NEG a0, a1", "SUB a0, zero, a1");
+        same("SEQZ a0, a1", "SLTIU a0, a1, 1");
+        same("SNEZ a0, a1", "SLTU a0, zero, a1");
+        same("SLTZ a0, a1", "SLT a0, a1, zero");
+        same("SGTZ a0, a1", "SLT a0, zero, a1");
+        same("BEQZ a0, 8", "BEQ a0, zero, 8");
+        same("BNEZ a0, 8", "BNE a0, zero, 8");
+        same("BLEZ a0, 8", "BGE zero, a0, 8");
+        same("BGEZ a0, 8", "BGE a0, zero, 8");
+        same("BLTZ a0, 8", "BLT a0, zero, 8");
+        same("BGTZ a0, 8", "BLT zero, a0, 8");
+        same("BGT a0, a1, 8", "BLT a1, a0, 8");
+        same("BLE a0, a1, 8", "BGE a1, a0, 8");
+        same("BGTU a0, a1, 8", "BLTU a1, a0, 8");
+        same("BLEU a0, a1, 8", "BGEU a1, a0, 8");
+        same("J 8", "JAL zero, 8");
+        same("JAL 8", "JAL ra, 8");
+        same("JR a0", "JALR zero, a0, 0");
+        same("JALR a0", "JALR ra, a0, 0");
+        same("RET", "JALR zero, ra, 0");
+        same("LI a0, -5", "ADDI a0, zero, 4091");
+        same("LI a0, 0x12345000", "LUI a0, 0x12345");
+        assert_eq!(
+            parse("LI a0, 0x12345fff").unwrap(),
+            Command::Sequence(vec![
+                parse_listing("LUI a0, 0x12346").unwrap()[0].1,
+                parse_listing("ADDI a0, a0, 0xfff").unwrap()[0].1,
+            ])
+        );
+        assert!(parse("BEQZ a0").is_err());
+        assert!(parse("RET ra").is_err());
+
+        // CALL and TAIL reach 32 bits from the AUIPC, with a sign-extended
+        // low part
+        let mut i = Interpreter::new();
+        i.interpret("LI a0, 0xdeadbeef").unwrap();
+        assert_eq!(i.cpu.x10, 0xdeadbeef);
+        i.interpret("CALL 0x800").unwrap();
+        assert_eq!((i.cpu.pc, i.cpu.x1), (0x808, 0x10));
+        i.interpret("TAIL -0x808").unwrap();
+        assert_eq!((i.cpu.pc, i.cpu.x1, i.cpu.x6), (0, 0x10, 0xfffff808));
+        assert_eq!(parse_listing("CALL 16\nNOP").unwrap().len(), 3);
+
+        // LA is relative to where it runs, with the low part sign-extended
+        i.interpret("LA a1, 0x12345ffc").unwrap();
+        assert_eq!((i.cpu.x11, i.cpu.pc), (0x12345ffc, 8));
+        i.interpret("LA a2, 0").unwrap();
+        assert_eq!(i.cpu.x12, 0);
+        let listing = parse_listing_at("NOP\nLA a0, 0x100", 0x80).unwrap();
+        assert_eq!(
+            listing[1..]
+                .iter()
+                .map(|(_, i)| i.to_string())
+                .collect::<Vec<_>>(),
+            ["auipc a0, 0", "addi a0, a0, 124"]
+        );
+        let mut i = Interpreter::new();
+        i.interpret("NOP; LA a0, 0x800; NOP").unwrap();
+        assert_eq!(i.cpu.x10, 0x800);
+
+        // backwards, and past the top of the address space, the AUIPC wraps
+        i.cpu.pc = 0x1000;
+        i.interpret("LA a0, 0x10").unwrap();
+        assert_eq!(i.cpu.x10, 0x10);
+        i.cpu.pc = 0xfffff000;
+        i.interpret("LA a0, 2").unwrap();
+        assert_eq!((i.cpu.x10, i.cpu.pc), (2, 0xfffff008));
+    }
+
+    #[test]
+    fn trivial_add() {
+        let mut i = Interpreter::default();
+        i.cpu.x2 = 3;
+        i.cpu.x3 = 5;
+
+        assert_eq!(i.cpu.x1, 0);
+
+        let input = "ADD x1, x2, x3";
+        assert!(i.interpret(input).is_ok());
+
+        assert_eq!(i.cpu.x1, 8);
+    }
+
+    #[test]
+    fn system_instructions() {
+        let mut ebreak = IType::default();
+        ebreak.imm.set_unsigned(1).unwrap();
+
+        assert_eq!(
+            parse("EBREAK").unwrap(),
+            Command::Exec(Instruction::EBREAK(ebreak))
+        );
+        assert_eq!(
+            parse("ECALL").unwrap(),
+            Command::Exec(Instruction::ECALL(IType::default()))
+        );
+        assert!(parse("ECALL x1").is_err());
+
+        // the A extension's operands are in field order, the address in rs1
+        let mut i = Interpreter::new();
+        i.interpret("ADDI a0, zero, 64; ADDI a1, zero, 5; SW a0, a1, 0")
+            .unwrap();
+        i.interpret("lr.w t0, a0; sc.w t1, a0, a1; amoadd.w t2, a0, a1")
+            .unwrap();
+        assert_eq!((i.cpu.x5, i.cpu.x6, i.cpu.x7), (5, 0, 5));
+        assert_eq!(i.evaluate("[64]").unwrap(), 10);
+        assert_eq!(
+            Instruction::LR_W(RType::default()).to_string(),
+            "lr.w zero, zero"
+        );
+        assert!(i.interpret("LR.W t0, a0, a1").is_err());
+
+        // FENCE.I has nothing to synchronize, but runs
+        assert_eq!(
+            parse("fence.i").unwrap(),
+            Command::Exec(Instruction::FENCE_I)
+        );
+        assert_eq!(Instruction::FENCE_I.to_string(), "fence.i");
+        let mut i = Interpreter::new();
+        i.interpret("FENCE.I").unwrap();
+        assert_eq!(i.cpu.pc, 4);
+    }
+
+    #[test]
+    fn semihosting_console() {
+        use crate::semihosting::{ENTRY, EXIT, SYS_WRITE0};
+
+        let mut i = Interpreter::default();
+
+        // the magic sequence around the EBREAK, which sits at address 4
+        i.cpu.memory[0..4].copy_from_slice(&ENTRY.to_le_bytes());
+        i.cpu.memory[8..12].copy_from_slice(&EXIT.to_le_bytes());
+        i.cpu.memory[256..259].copy_from_slice(b"hi\0");
+        i.cpu.pc = 4;
+        i.cpu.x10 = SYS_WRITE0;
+        i.cpu.x11 = 256;
+
+        assert!(i.interpret("EBREAK").is_ok());
+        assert_eq!(i.take_output(), b"hi");
+        assert_eq!(i.cpu.pc, 8);
+
+        // without the sequence it's just a breakpoint
+        assert!(i.interpret("EBREAK").is_err());
+    }
+
+    #[test]
+    fn semihosting_exit() {
+        use crate::semihosting::{ADP_STOPPED_APPLICATION_EXIT, SYS_EXIT, SYS_WRITEC};
+
+        let mut i = Interpreter::default();
+        i.load_program(
+            "ADDI a0, zero, 0x18\n\
+             LUI a1, 0x20\n\
+             ADDI a1, a1, 0x26\n\
+             SLLI zero, zero, 0x1f\n\
+             EBREAK\n\
+             SRAI zero, zero, 7\n\
+             JAL zero, 0",
+        )
+        .unwrap();
+        assert_eq!(SYS_EXIT, 0x18);
+        assert_eq!(ADP_STOPPED_APPLICATION_EXIT, 0x20 << 12 | 0x26);
+
+        let run = i.run(100);
+        assert!(matches!(run.stop, Stop::Exited(0)));
+        assert_eq!(run.steps, 5);
+
+        // undone, another call doesn't exit
+        i.step_back(1).unwrap();
+        assert_eq!(i.exit_code(), None);
+        i.set_register(Register::X10, SYS_WRITEC);
+        i.step().unwrap();
+        assert_eq!(i.exit_code(), None);
+        assert_eq!(i.take_output(), [0]);
+    }
+
+    #[test]
+    fn events() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = seen.clone();
+
+        let mut i = Interpreter::default();
+        i.subscribe(move |e: &Event| log.borrow_mut().push(e.clone()));
+
+        i.cpu.x1 = 64;
+        assert!(i.interpret("ADDI x2, zero, 7").is_ok());
+        assert!(i.interpret("SB x1, x2, 1").is_ok());
+        assert!(i.interpret("EBREAK").is_err());
+
+        let seen = seen.borrow();
+        assert_eq!(seen.len(), 4);
+        assert!(matches!(seen[0], Event::InstructionRetired { pc: 0, .. }));
+        assert_eq!(
+            seen[1],
+            Event::MemoryWritten {
+                address: 65,
+                bytes: vec![7]
+            }
+        );
+        assert!(matches!(seen[2], Event::InstructionRetired { pc: 4, .. }));
+        assert_eq!(seen[3], Event::BreakpointHit { pc: 8 });
+    }
+
+    #[test]
+    fn csr_instructions() {
+        let mut i = Interpreter::new();
+
+        i.interpret("ADDI x1, zero, 8").unwrap();
+        i.interpret("CSRRW zero, mscratch, x1").unwrap();
+        i.interpret("CSRRSI x2, mscratch, 3").unwrap();
+        assert_eq!(i.cpu.x2, 8);
+        assert_eq!(i.cpu.read_csr(csr::MSCRATCH).unwrap(), 11);
+
+        // by address, too
+        i.interpret("CSRRCI x3, 832, 1").unwrap();
+        assert_eq!(i.cpu.x3, 11);
+        assert_eq!(i.interpret("MSCRATCH").unwrap(), "mscratch: 10 (0xa)");
+
+        // misa reflects the machine; read-only CSRs can be read but not written
+        i.interpret("CSRRS x4, misa, zero").unwrap();
+        assert_eq!(i.cpu.x4, 0x4000_1101);
+        assert!(i.interpret("CSRRS x5, mhartid, zero").is_ok());
+        assert!(i.interpret("CSRRW x5, mhartid, x1").is_err());
+        assert!(i.interpret("CSRRWI x5, mscratch, 32").is_err());
+
+        // and the pseudo-instructions for when only one side matters
+        i.interpret("CSRW mscratch, x1; CSRS mscratch, x4; CSRCI mscratch, 8")
+            .unwrap();
+        i.interpret("CSRR x6, mscratch").unwrap();
+        assert_eq!(i.cpu.x6, 0x4000_1101);
+        i.interpret("CSRWI mscratch, 5; CSRSI mscratch, 2; CSRC mscratch, x1")
+            .unwrap();
+        assert_eq!(i.cpu.read_csr(csr::MSCRATCH).unwrap(), 7);
+        assert_eq!(
+            parse("CSRR a0, mepc").unwrap(),
+            parse("CSRRS a0, mepc, zero").unwrap()
+        );
+        assert!(i.interpret("CSRW mhartid, x1").is_err());
+
+        // a custom CSR, written by address and shown by name
+        let def = csr::CsrDef::new("mleds", 0x7c0).with_write(csr::Warl::Mask(0xff));
+        i.cpu.csrs.define(def).unwrap();
+        i.interpret("CSRRW zero, 1984, x4").unwrap();
+        assert_eq!(i.interpret("mleds").unwrap(), "mleds: 1 (0x1)");
+
+        // counters only exist with Zicntr
+        assert!(i.interpret("CSRRS x5, cycle, zero").is_ok());
+        i.cpu.config.extensions.zicntr = false;
+        assert!(i.interpret("CSRRS x5, cycle, zero").is_err());
+
+        // traps and mcause are shown by name
+        i.cpu.config.ecall = crate::rv32_i::EcallPolicy::TrapIntoGuestHandler;
+        assert!(i
+            .interpret("ECALL")
+            .unwrap()
+            .ends_with("trapped: environment call from M-mode (11)"));
+        assert_eq!(
+            i.interpret("MCAUSE").unwrap(),
+            "mcause: environment call from M-mode (11)"
+        );
+        assert_eq!(
+            i.interpret("MSTATUS").unwrap(),
+            "mstatus: 0x1800 (MPP=Machine, MPIE=0, MIE=0)"
+        );
+    }
+
+    #[test]
+    fn counters() {
+        let mut i = Interpreter::new();
+        i.cpu.config.cycles.taken = 2;
+
+        i.interpret("NOP").unwrap();
+        i.interpret("JAL zero, 4").unwrap();
+        i.interpret("RDINSTRET a0").unwrap();
+        i.interpret("RDCYCLE a1").unwrap();
+        i.interpret("rdcycleh a2").unwrap();
+        assert_eq!((i.cpu.x10, i.cpu.x11, i.cpu.x12), (2, 5, 0));
+        assert_eq!(i.cpu.counters.instret, 5);
+        assert_eq!(i.last_delta().unwrap().counted.cycles, 1);
+
+        // time is the CLINT's mtime, which ticks once per instruction
+        i.interpret("RDTIME a3").unwrap();
+        assert_eq!(i.cpu.x13, 5);
+        assert!(i.interpret("RDTIME a3, a4").is_err());
+
+        // undoing an instruction takes it off the counters
+        i.undo().unwrap();
+        i.undo().unwrap();
+        assert_eq!(i.cpu.counters.instret, 4);
+        assert_eq!(i.cpu.counters.cycles, 6);
+        i.redo().unwrap();
+        assert_eq!(i.cpu.counters.instret, 5);
+
+        // and so does seeking, or rolling back; the stats and mtime don't
+        // go back
+        i.seek(1).unwrap();
+        assert_eq!(i.cpu.counters.instret, 1);
+        assert_eq!(i.stats().count("NOP"), 1);
+        assert_eq!(i.stats().instructions.values().sum::<u64>(), 6);
+        assert_eq!(i.cpu.time(), 6);
+        i.seek(6).unwrap();
+        let before = i.cpu.counters;
+        let failing = [Instruction::NOP, Instruction::ECALL(IType::default())];
+        i.cpu.syscalls = None;
+        assert!(i.execute_transaction(&failing).is_err());
+        assert_eq!(i.cpu.counters, before);
+
+        i.reset();
+        assert_eq!(i.cpu.counters, Default::default());
+    }
+
+    #[test]
+    fn readable_literals() {
+        let mut i = Interpreter::new();
+
+        i.interpret("LUI x1, 0x1_0000").unwrap();
+        i.interpret("ADDI x1, x1, 0b1010_1010").unwrap();
+        i.interpret("ADDI x2, zero, 1_000").unwrap();
+        assert_eq!(i.cpu.x1, 0x1000_00aa);
+        assert_eq!(i.cpu.x2, 1000);
+        assert!(i.interpret("ADDI x2, zero, 0x").is_err());
+
+        assert_eq!(i.interpret("x1").unwrap(), "X1: 268435626 (0x100000aa)");
+        i.set_readable_numbers(true);
+        assert_eq!(i.interpret("x1").unwrap(), "X1: 268_435_626 (0x1000_00aa)");
+        assert_eq!(i.interpret("x2").unwrap(), "X2: 1_000 (0x3e8)");
+    }
+
+    #[test]
+    fn register_formats() {
+        let minus_one = u32::MAX as u64;
+        let format = |f: &str, value, xlen| RegisterFormat::parse(f).unwrap().format(value, xlen);
+
+        assert_eq!(format("hex", 255, Xlen::Rv32), "0x000000ff");
+        assert_eq!(format("dec", minus_one, Xlen::Rv32), "-1");
+        assert_eq!(format("signed", minus_one, Xlen::Rv64), "4294967295");
+        assert_eq!(format("unsigned", minus_one, Xlen::Rv32), "4294967295");
+        assert_eq!(
+            format("bin", 5, Xlen::Rv32),
+            format!("0b{}101", "0".repeat(29))
+        );
+        assert_eq!(format("float", 0x3fc0_0000, Xlen::Rv32), "1.5");
+        assert_eq!(format("float", 0x3ff8 << 48, Xlen::Rv64), "1.5");
+        assert_eq!(RegisterFormat::parse("octal"), None);
+
+        for f in RegisterFormat::ALL {
+            assert_eq!(RegisterFormat::parse(&f.to_string()), Some(f));
+        }
+    }
+
+    #[test]
+    fn evaluate_expressions() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x5, zero, 12").unwrap();
+        i.interpret("ADDI x6, zero, 3").unwrap();
+        i.interpret("SW zero, x5, 16").unwrap();
+
+        assert_eq!(i.evaluate("x5 + x6 * 2"), Ok(18));
+        assert_eq!(i.evaluate("T0 - t1"), Ok(9));
+        assert_eq!(i.evaluate("[4 * 4] | mscratch"), Ok(12));
+        assert_eq!(i.evaluate("pc"), Ok(12));
+        assert!(i.evaluate("x32").is_err());
+    }
+
+    #[test]
+    fn listings() {
+        let listing = "# count to 3\nADDI x1, zero, 3  # limit\n\nNOP\n";
+        let program = parse_listing(listing).unwrap();
+        assert_eq!(program.len(), 2);
+        assert_eq!(program[1], (4, Instruction::NOP));
+
+        let errors = parse_listing("NOP\nADDI x1\nPC\nBOGUS").unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn load_programs() {
+        let mut i = Interpreter::new();
+        i.cpu.pc = 0x40;
+
+        let size = i.load_program("ADDI x1, zero, 5\n# done\nNOP").unwrap();
+        assert_eq!(size, 8);
+        assert_eq!(i.cpu.pc, 0);
+        assert_eq!(i.cpu.memory[..8], [0x93, 0x00, 0x50, 0x00, 0x13, 0, 0, 0]);
+
+        let errors = i.load_program("NOP\nADDI x1").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 2);
+
+        // the third instruction is the first that doesn't fit
+        let mut i = Interpreter::with_board(&Board::parse("[memory]\nsize = 8").unwrap()).unwrap();
+        let errors = i.load_program("NOP\nNOP\n\nNOP").unwrap_err();
+        assert_eq!(errors[0].0, 4);
+    }
+
+    #[test]
+    fn step_and_run() {
+        let mut i = Interpreter::new();
+        let listing = "ADDI a0, zero, 3\n\
+                       ADDI a1, a1, 2\n\
+                       ADDI a0, a0, 4095\n\
+                       BNE a0, zero, 4092\n\
+                       ADDI a0, a1, 0\n\
+                       ADDI a7, zero, 93\n\
+                       ECALL";
+        i.load_program(listing).unwrap();
+
+        assert!(matches!(i.fetch(), Ok(Instruction::ADDI(_))));
+        let delta = i.step().unwrap();
+        assert_eq!(delta.registers.len(), 2);
+        assert_eq!(i.cpu.x10, 3);
+
+        // around the loop backwards, then out
+        let run = i.run(1000);
+        assert!(matches!(run.stop, Stop::Exited(6)));
+        assert_eq!(run.steps, 12);
+        assert_eq!(run.to_string(), "Exited with code 6 after 12 instructions");
+        assert_eq!(i.run(1000).steps, 0);
+
+        // stepping is undoable like anything else
+        i.undo().unwrap();
+        assert_eq!(i.cpu.pc, 24);
+
+        let mut i = Interpreter::new();
+        i.load_program("JAL zero, 0").unwrap();
+        let run = i.run(50);
+        assert!(matches!(run.stop, Stop::StepLimit));
+        assert_eq!(run.steps, 50);
+
+        i.cancel_token().cancel();
+        assert!(matches!(i.run(50).stop, Stop::Cancelled));
+
+        // cleared memory isn't an instruction
+        i.cpu.pc = 4;
+        assert!(matches!(i.run(50).stop, Stop::Failed(_)));
+        assert_eq!(
+            i.step().unwrap_err().to_string(),
+            "Illegal instruction: 0x00000000: all zeros is never an instruction"
+        );
+
+        // instructions can be written to memory directly
+        i.cpu
+            .write_memory(4, &0x0050_0513u32.to_le_bytes())
+            .unwrap();
+        i.step().unwrap();
+        assert_eq!(i.cpu.x10, 5);
+    }
+
+    #[test]
+    fn reverse_debugging() {
+        let mut i = Interpreter::new();
+        let listing = "ADDI a0, zero, 3\n\
+                       ADDI sp, zero, 0x100\n\
+                       SW sp, a0, 0\n\
+                       ADDI a0, a0, 4095\n\
+                       BNE a0, zero, 4092\n\
+                       ADDI a7, zero, 93\n\
+                       ECALL";
+        i.load_program(listing).unwrap();
+        let stored = |i: &Interpreter| i.cpu.memory[0x100];
+
+        // forwards: a breakpoint stops before the instruction, every time
+        // round the loop, and a watchpoint after the write
+        i.add_breakpoint(12);
+        let run = i.run(100);
+        assert!(matches!(run.stop, Stop::Breakpoint(12)));
+        assert_eq!(run.steps, 3);
+        assert!(matches!(i.run(100).stop, Stop::Breakpoint(12)));
+        assert!(i.remove_breakpoint(12));
+        i.watch(0x102, 1);
+        i.watch(0x100, 4);
+        assert_eq!(
+            i.watchpoints().collect::<Vec<_>>(),
+            [(0x100, 4), (0x102, 1)]
+        );
+        let run = i.run(100);
+        assert!(matches!(run.stop, Stop::Watchpoint(0x100)));
+        assert_eq!(
+            run.to_string(),
+            "Stopped at a write to 0x00000100 after 3 instructions"
+        );
+        assert_eq!((i.history().epoch(), i.cpu.pc, stored(&i)), (9, 12, 1));
+
+        // back over the write and the branch
+        assert_eq!(i.step_back(2), Ok(2));
+        assert_eq!((i.cpu.pc, stored(&i)), (16, 2));
+
+        // backwards: stops with the write to watched memory undone
+        let run = i.reverse_continue().unwrap();
+        assert!(matches!(run.stop, Stop::Watchpoint(0x100)));
+        assert_eq!((run.steps, i.cpu.pc, stored(&i)), (2, 8, 3));
+
+        assert!(i.unwatch(0x100) && i.unwatch(0x102));
+        i.add_breakpoint(4);
+        let run = i.reverse_continue().unwrap();
+        assert!(matches!(run.stop, Stop::Breakpoint(4)));
+        assert_eq!((run.steps, i.cpu.pc, i.cpu.x10), (4, 4, 3));
+
+        let run = i.reverse_continue().unwrap();
+        assert!(matches!(run.stop, Stop::HistoryStart));
+        assert_eq!((run.steps, i.cpu.pc, i.cpu.x10), (1, 0, 0));
+        assert_eq!(i.step_back(1), Ok(0));
+
+        // and forwards again, from the start
+        assert_eq!(i.breakpoints().collect::<Vec<_>>(), [4]);
+        assert!(matches!(i.run(100).stop, Stop::Breakpoint(4)));
+
+        // stepping back over the exit lets the program run on again
+        assert!(matches!(i.run(100).stop, Stop::Exited(0)));
+        assert_eq!(i.step_back(1), Ok(1));
+        assert_eq!(i.exit_code(), None);
+        assert!(matches!(i.run(100).stop, Stop::Exited(0)));
+    }
+
+    #[test]
+    fn interrupts() {
+        use crate::devices::{self, Clint};
+
+        let mut i = Interpreter::new();
+        i.load_program("ADDI a0, a0, 1\nJAL zero, 1048574").unwrap();
+        i.cpu.write_csr(csr::MTVEC, 0x40).unwrap();
+        i.cpu.write_csr(csr::MIE, devices::MTIP).unwrap();
+        i.cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MIE).unwrap();
+        i.cpu.devices.get_mut::<Clint>().unwrap().mtimecmp = 5;
+
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let log = seen.clone();
+        i.subscribe(move |e: &Event| log.borrow_mut().push(e.clone()));
+
+        // the loop runs until the timer goes off, between instructions
+        let run = i.run(10);
+        assert!(matches!(run.stop, Stop::Failed(_)));
+        assert_eq!(run.steps, 5);
+        assert_eq!(i.cpu.pc, 0x40);
+        assert_eq!(i.cpu.x10, 3);
+        assert!(seen.borrow().contains(&Event::TrapTaken {
+            pc: 4,
+            cause: TrapCause::MachineTimerInterrupt.mcause()
+        }));
+
+        // taking the interrupt is undone on its own
+        i.undo().unwrap();
+        assert_eq!(i.cpu.pc, 4);
+    }
+
+    #[test]
+    fn uart_output() {
+        let mut i = Interpreter::new();
+        i.load_program(crate::programs::find("uart_hello").unwrap().source)
+            .unwrap();
+        i.run(100);
+
+        assert_eq!(i.take_uart_output(), b"hello\n");
+        assert_eq!(i.take_uart_output(), b"");
+        // kept apart from the console output of system calls
+        assert_eq!(i.take_output(), b"");
+    }
+
+    #[test]
+    fn double_check() {
+        let mut i = Interpreter::new();
+        i.set_double_check(true);
+
+        for line in [
+            "ADDI x1, zero, 4095",
+            "SRAI x2, x1, 4",
+            "SLTIU x3, x1, 4095",
+            "SUB x4, zero, x1",
+            "SLT x5, x4, x1",
+            "BGE x4, x1, 4",
+            "BLTU x4, x1, 4",
+            "JAL ra, 2",
+            "JALR x6, ra, 4",
+            "LUI x7, 1048575",
+            "AUIPC x8, 1",
+            "SW zero, x1, 0",
+        ] {
+            assert!(i.interpret(line).is_ok(), "{line}");
+        }
+
+        // the CPU is changed behind the model's back mid-instruction; the
+        // model can't see that, so it reports the difference
+        let before = history::registers(&i.cpu);
+        let addi = IType {
+            rd: Register::X9,
+            ..Default::default()
+        };
+        i.cpu.execute(Instruction::ADDI(addi)).unwrap();
+        i.cpu.x9 = 1;
+        let error = i.check_semantics(Instruction::ADDI(addi), Instruction::LENGTH, &before);
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            format!(
+                "Semantics check failed for {:?}: x9 is 0x1, expected 0x0",
+                Instruction::ADDI(addi)
+            )
+        );
+    }
+
+    #[test]
+    fn batches() {
+        let program: Vec<Instruction> = [
+            "ADDI x1, zero, 5",
+            "CSRRW zero, mscratch, x1",
+            "SW zero, x1, 8",
+        ]
+        .iter()
+        .map(|line| match parse(line).unwrap() {
+            Command::Exec(instruction) => instruction,
+            _ => unreachable!(),
+        })
+        .collect();
+        let failure = match parse("LW x2, zero, 2048").unwrap() {
+            Command::Exec(instruction) => instruction,
+            _ => unreachable!(),
+        };
+
+        let mut i = Interpreter::new();
+        let deltas = i.execute_all(&program).unwrap();
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[2].memory[0].address, 8);
+
+        // stops at the failure, keeping what ran
+        let mut i = Interpreter::with_board(&Board::parse("[memory]\nsize = 64").unwrap()).unwrap();
+        let batch = [program.clone(), vec![failure, Instruction::NOP]].concat();
+        let error = i.execute_all(&batch).unwrap_err();
+        assert_eq!((error.index, error.deltas.len()), (3, 3));
+        assert_eq!(i.cpu.x1, 5);
+
+        // or rolls everything back
+        let mut i = Interpreter::with_board(&Board::parse("[memory]\nsize = 64").unwrap()).unwrap();
+        i.interpret("ADDI x3, zero, 1").unwrap();
+        let error = i.execute_transaction(&batch).unwrap_err();
+        assert!(error.rolled_back);
+        assert_eq!(i.cpu.x1, 0);
+        assert_eq!(i.cpu.pc, 4);
+        assert_eq!(i.cpu.memory[8], 0);
+        assert_eq!(i.cpu.read_csr(csr::MSCRATCH).unwrap(), 0);
+        assert!(i.redo().is_err());
+        assert!(i.undo().is_ok());
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Instruction 3 failed: {} (all 3 before it were rolled back)",
+                error.error
+            )
+        );
+
+        // cancelling part way stops before the next instruction
+        let mut i = Interpreter::new();
+        let token = i.cancel_token();
+        let mut seen = 0;
+        i.subscribe(move |event: &Event| {
+            if let Event::InstructionRetired { .. } = event {
+                seen += 1;
+                if seen == 2 {
+                    token.cancel();
+                }
+            }
+        });
+        let error = i.execute_all(&program).unwrap_err();
+        assert!(matches!(error.error, Error::Cancelled));
+        assert_eq!((error.index, error.deltas.len()), (2, 2));
+        assert!(!i.cancel_token().is_cancelled());
+    }
+
+    #[test]
+    fn several_statements() {
+        assert_eq!(
+            statements("ADDI x1, zero, ';' ; x1 # a; comment\n\n  ;PC;"),
+            vec!["ADDI x1, zero, ';'", "x1", "PC"]
+        );
+
+        let mut i = Interpreter::new();
+        let result = i.interpret("ADDI x1, zero, 3; SLLI x1, x1, 2\nx1").unwrap();
+        assert_eq!(result.split("; ").nth(2), Some("X1: 12 (0xc)"));
+        assert_eq!(i.cpu.pc, 8);
+
+        // nothing runs if a statement doesn't parse
+        let error = i.interpret("ADDI x1, zero, 1; ADDI x1, x1").unwrap_err();
+        assert!(matches!(error, Error::Statement { index: 1, .. }));
+        assert!(error.to_string().starts_with("Statement 2: "));
+        assert_eq!(i.cpu.x1, 12);
+
+        // a failure stops the rest, keeping what ran
+        let error = i
+            .interpret("ADDI x1, zero, 1; EBREAK; ADDI x1, zero, 2")
+            .unwrap_err();
+        let Error::Statement { index: 1, error } = error else {
+            panic!("{error}");
+        };
+        assert!(matches!(*error, Error::Cpu(_)));
+        assert_eq!(i.cpu.x1, 1);
+
+        // or as one delta
+        let mut i = Interpreter::new();
+        let delta = i
+            .interpret_all("ADDI x1, zero, 3; SB zero, x1, 16; ADDI x1, x1, 1")
+            .unwrap();
+        assert_eq!(delta.registers.len(), 2);
+        assert_eq!(
+            (delta.memory[0].address, &delta.memory[0].new),
+            (16, &vec![3])
+        );
+        assert_eq!(i.cpu.x1, 4);
+        let error = i.interpret_all("ADDI x1, zero, 1; x1").unwrap_err();
+        assert_eq!((error.index, error.deltas.len()), (1, 0));
+        assert_eq!(i.cpu.x1, 4);
+    }
+
+    #[test]
+    fn pseudo_instructions_are_all_or_nothing() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI ra, zero, 8").unwrap();
+        // the AUIPC runs, then the JALR can't jump to 2
+        assert!(i.interpret("CALL 2").is_err());
+        assert_eq!((i.cpu.pc, i.cpu.x1), (4, 8));
+        assert_eq!(i.history().len(), 1);
+    }
+
+    #[test]
+    fn one_statement() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 1;").unwrap();
+        assert_eq!(i.cpu.x1, 1);
+        i.interpret("; ADDI x1, zero, 2").unwrap();
+        assert_eq!(i.cpu.x1, 2);
+        i.interpret("ADDI x1, zero, 3 # a comment").unwrap();
+        assert_eq!(i.cpu.x1, 3);
+
+        // nothing to do
+        assert_eq!(i.interpret("").unwrap(), "");
+        assert_eq!(i.interpret("  # just a comment").unwrap(), "");
+        assert_eq!(i.cpu.pc, 12);
+
+        // spans still count from the start of the input
+        let e = i.interpret("  ; ADDI x1, zero, 0x1000").unwrap_err();
+        assert_eq!(e.span().map(|s| (s.start, s.end)), Some((19, 25)));
+    }
+
+    #[test]
+    fn error_kinds_and_spans() {
+        let mut i = Interpreter::new();
+        let error = |i: &mut Interpreter, input| i.interpret(input).unwrap_err();
+        let span = |e: &Error| e.span().map(|s| (s.start, s.end));
+
+        let e = error(&mut i, "ADDI x1, zero, 0x1000");
+        assert_eq!((e.kind(), e.kind().code()), (ErrorKind::OutOfRange, "E103"));
+        assert_eq!(span(&e), Some((15, 21)));
+        assert_eq!(e.to_string(), "Unsigned value 4096 is too big for 12 bits.");
+        assert!(std::error::Error::source(&e).is_some());
+
+        let e = error(&mut i, "bogus x1");
+        assert_eq!(
+            (e.kind(), span(&e)),
+            (ErrorKind::UnrecognizedToken, Some((0, 5)))
+        );
+
+        // operands are checked one by one
+        let e = error(&mut i, "add  x1, bogus, x2");
+        assert_eq!((e.kind(), e.kind().code()), (ErrorKind::Operand, "E106"));
+        assert_eq!(e.to_string(), "second operand `bogus`: invalid register");
+        assert_eq!(span(&e), Some((9, 14)));
+        let e = error(&mut i, "ADD x1, x2, 5");
+        assert_eq!(e.to_string(), "third operand `5`: expected a register");
+        assert_eq!(span(&e), Some((12, 13)));
+        let e = error(&mut i, "ADD x1, x2");
+        assert_eq!(e.to_string(), "ADD takes rd, rs1, rs2");
+        assert_eq!(span(&e), Some((4, 10)));
+        let e = error(&mut i, "ADDI x1, x2, x3");
+        assert_eq!(e.to_string(), "third operand `x3`: expected a number");
+        let e = error(&mut i, "CSRRW x1, x2, x3");
+        assert_eq!(e.to_string(), "second operand `x2`: expected a CSR");
+        let e = error(&mut i, "NOP x1");
+        assert_eq!(
+            (e.to_string().as_str(), span(&e)),
+            ("NOP takes no operands", Some((4, 6)))
+        );
+
+        // a pseudo-instruction's operands are still where they were typed
+        let e = error(&mut i, "BEQZ a9, 8");
+        assert_eq!(e.to_string(), "first operand `a9`: invalid register");
+        assert_eq!(span(&e), Some((5, 7)));
+        let e = error(&mut i, "BEQZ a0");
+        assert_eq!(
+            (e.to_string().as_str(), span(&e)),
+            ("BEQZ takes 2 operands", Some((5, 7)))
+        );
+        let e = error(&mut i, "ADDI x1, zero, 2 * (3");
+        assert_eq!(e.kind(), ErrorKind::Expression);
+        assert_eq!(span(&e), Some((15, 21)));
+
+        // counted from the start of all the statements, or of the line
+        let e = error(&mut i, "NOP; ADDI x1, x1, 0x1000");
+        assert_eq!(
+            (e.kind(), span(&e)),
+            (ErrorKind::OutOfRange, Some((18, 24)))
+        );
+        let errors = parse_listing("NOP\n  LUI a0, bogus").unwrap_err();
+        assert_eq!(span(&errors[0].1), Some((10, 15)));
+
+        // nothing to point at once it's running
+        let e = error(&mut i, "JALR zero, zero, 2");
+        assert_eq!(
+            (e.kind(), e.kind().is_parse(), span(&e)),
+            (ErrorKind::MisalignedJump, false, None)
+        );
+        assert!(ErrorKind::NotAnInstruction.is_parse());
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut i = Interpreter::new();
+        let suggest = |i: &mut Interpreter, input| i.interpret(input).unwrap_err().suggestions();
+
+        assert_eq!(suggest(&mut i, "ADDD x1, x2, x3"), ["ADD", "ADDI", "ADDW"]);
+        assert_eq!(suggest(&mut i, "MSTATS"), ["mstatus"]);
+        assert_eq!(suggest(&mut i, "BEQZZ a0, 8"), ["BEQZ"]);
+        // only registers where a register goes, and only CSRs for a CSR
+        assert_eq!(suggest(&mut i, "ADD x1, x2, sq"), ["sp", "s0", "s1"]);
+        assert_eq!(suggest(&mut i, "CSRR a0, mepcc"), ["mepc"]);
+        assert!(suggest(&mut i, "ADDI x1, x2, 0x").is_empty());
+        assert!(suggest(&mut i, "NOP; XYZZY").is_empty());
+    }
+
+    #[test]
+    fn outcomes() {
+        let mut i = Interpreter::new();
+        let outcome = i.eval("ADDI a0, zero, 5").unwrap();
+        let EvalOutcome::InstructionExecuted {
+            instruction: Instruction::ADDI(_),
+            delta,
+            trap: None,
+        } = outcome
+        else {
+            panic!("{outcome:?}");
+        };
+        assert_eq!(delta.registers[0].register, Register::X10);
+        assert_eq!(delta.registers[0].new, 5);
+
+        assert_eq!(
+            i.eval("a0; mscratch").unwrap(),
+            EvalOutcome::Several(vec![
+                EvalOutcome::RegisterShown {
+                    register: Register::X10,
+                    value: 5
+                },
+                EvalOutcome::CsrShown {
+                    address: csr::MSCRATCH,
+                    value: 0
+                },
+            ])
+        );
+
+        i.interpret("SW zero, a0, 0x100").unwrap();
+        assert_eq!(
+            i.eval("[a0 * 0x40 - 0x40]").unwrap(),
+            EvalOutcome::MemoryShown {
+                address: 0x100,
+                value: 5
+            }
+        );
+        assert_eq!(i.interpret("[0x100]").unwrap(), "[0x00000100]: 5 (0x5)");
+        assert!(matches!(i.eval("[0xffffffff]"), Err(Error::Cpu(_))));
+
+        // a pseudo-instruction is each of its instructions
+        let EvalOutcome::Several(outcomes) = i.eval("LI a1, 0x12345").unwrap() else {
+            panic!();
+        };
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(i.cpu.x11, 0x12345);
+    }
+
+    #[test]
+    fn dry_runs() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 5").unwrap();
+
+        let delta = i.dry_run("SW zero, x1, 8").unwrap();
+        assert_eq!(delta.memory[0].address, 8);
+        assert_eq!(delta.memory[0].new, vec![5, 0, 0, 0]);
+        assert_eq!(delta.to_string(), "PC: 0x4 -> 0x8, 4 bytes at 0x00000008");
+
+        // nothing actually happened
+        assert_eq!(i.cpu.pc, 4);
+        assert_eq!(i.cpu.memory[8], 0);
+        assert!(i.undo().is_ok());
+        assert!(i.undo().is_err());
+
+        assert!(i.dry_run("x1").is_err());
+        assert!(i.dry_run("JALR zero, zero, 2").is_err());
+    }
+
+    #[test]
+    fn jump_previews() {
+        let mut i = Interpreter::new();
+        i.cpu.pc = 0x100;
+        i.cpu.x5 = 0x203;
+
+        let preview = |i: &Interpreter, input| i.preview(input).unwrap();
+        assert_eq!(
+            preview(&i, "BEQ x1, x2, 8"),
+            JumpPreview {
+                target: 0x110,
+                offset: 16,
+                mapped: true
+            }
+        );
+        assert_eq!(preview(&i, "JAL ra, 2").target, 0x104);
+        assert_eq!(preview(&i, "JALR zero, x5, 1").target, 0x204);
+
+        // 1 MiB of memory starts at 0
+        assert!(!preview(&i, "JAL ra, 524287").mapped);
+
+        assert_eq!(i.preview("ADDI x1, zero, 8"), None);
+        assert_eq!(i.preview("BEQ x1"), None);
+        assert_eq!(i.preview("PC"), None);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut i = Interpreter::new();
+
+        i.interpret("ADDI x1, zero, 5").unwrap();
+        i.interpret("ADDI x1, x1, 1").unwrap();
+        assert_eq!(i.cpu.x1, 6);
+
+        i.undo().unwrap();
+        assert_eq!(i.cpu.x1, 5);
+        i.redo().unwrap();
+        assert_eq!(i.cpu.x1, 6);
+
+        i.seek(0).unwrap();
+        assert_eq!(i.cpu.x1, 0);
+        i.seek(2).unwrap();
+        assert_eq!(i.cpu.x1, 6);
+        assert_eq!(i.history().len(), 2);
+
+        // changing the CPU directly is noticed, rather than overwritten
+        i.undo().unwrap();
+        i.cpu.x1 = 100;
+        assert_eq!(i.redo(), Err(HistoryError::RegistersChanged { epoch: 1 }));
+        assert_eq!(i.cpu.x1, 100);
+
+        i.clear_history();
+        assert_eq!(i.undo(), Err(HistoryError::NothingToUndo));
+    }
+
+    #[test]
+    fn set_by_hand() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 5").unwrap();
+        i.set_register(Register::PC, 0x100);
+        i.set_memory(0x200, &[0xaa, 0xbb]).unwrap();
+        i.set_csr(csr::MSCRATCH, 1).unwrap();
+        assert_eq!(i.history().get(3), Some((0x100, Step::Set)));
+        assert_eq!(i.last_delta().unwrap().to_string(), "mscratch: 0x0 -> 0x1");
+
+        // nothing changed, so nothing's recorded
+        assert!(i.set_register(Register::X0, 7).is_empty());
+        assert_eq!(i.history().len(), 4);
+
+        // a saved session keeps them, CSRs included
+        let mut restored = Interpreter::from_bytes(&i.to_bytes()).unwrap();
+        restored.seek(1).unwrap();
+        assert_eq!(restored.cpu.pc, 4);
+        assert_eq!(restored.cpu.memory[0x200], 0);
+        assert_eq!(restored.cpu.csrs.read(csr::MSCRATCH), Some(0));
+        assert_eq!(restored.cpu.x1, 5);
+        restored.seek(4).unwrap();
+        assert_eq!(restored.cpu.csrs.read(csr::MSCRATCH), Some(1));
+
+        // a CSR changed since is noticed
+        i.cpu.csrs.set(csr::MSCRATCH, 9);
+        assert_eq!(i.undo(), Err(HistoryError::RegistersChanged { epoch: 4 }));
+
+        assert!(i.set_memory(0xffff_fff0, &[1]).is_err());
+        assert!(i.set_csr(csr::CYCLE, 1).is_err());
+    }
+
+    #[test]
+    fn undo_across_modes() {
+        let mut i = Interpreter::new();
+        // MPP is zero, so MRET goes to user mode
+        i.set_csr(csr::MSTATUS, 0).unwrap();
+        i.set_csr(csr::MEPC, 0x100).unwrap();
+        i.set_csr(csr::MTVEC, 0x200).unwrap();
+        i.interpret("MRET").unwrap();
+        assert_eq!((i.cpu.privilege, i.cpu.pc), (Privilege::User, 0x100));
+        assert_eq!(
+            i.last_delta().unwrap().privilege,
+            Some((Privilege::Machine, Privilege::User))
+        );
+
+        // machine CSRs trap in user mode, back to machine mode
+        i.interpret("CSRRW zero, mscratch, x1").unwrap();
+        assert_eq!((i.cpu.privilege, i.cpu.pc), (Privilege::Machine, 0x200));
+        assert_eq!(i.cpu.csrs.read(csr::MEPC), Some(0x100));
+        assert_eq!(i.cpu.csrs.read(csr::MCAUSE), Some(2));
+
+        i.undo().unwrap();
+        assert_eq!((i.cpu.privilege, i.cpu.pc), (Privilege::User, 0x100));
+        assert_eq!(i.cpu.csrs.read(csr::MCAUSE), Some(0));
+        i.undo().unwrap();
+        assert_eq!((i.cpu.privilege, i.cpu.pc), (Privilege::Machine, 0));
+        assert_eq!(i.cpu.csrs.read(csr::MSTATUS), Some(0));
+
+        // and so does a saved session
+        i.redo().unwrap();
+        i.redo().unwrap();
+        let mut restored = Interpreter::from_bytes(&i.to_bytes()).unwrap();
+        restored.seek(2).unwrap();
+        assert_eq!(restored.cpu.privilege, Privilege::Machine);
+        restored.seek(3).unwrap();
+        assert_eq!(restored.cpu.privilege, Privilege::User);
+
+        // an instruction's own CSR writes are undone too
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 7").unwrap();
+        i.interpret("CSRRW zero, mscratch, x1").unwrap();
+        i.undo().unwrap();
+        assert_eq!(i.cpu.csrs.read(csr::MSCRATCH), Some(0));
+
+        // a mode changed since is noticed
+        i.redo().unwrap();
+        i.cpu.privilege = Privilege::User;
+        i.interpret("MRET").unwrap();
+        i.cpu.privilege = Privilege::User;
+        assert_eq!(i.undo(), Err(HistoryError::RegistersChanged { epoch: 3 }));
+    }
+
+    #[test]
+    fn saved_sessions() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 5").unwrap();
+        i.interpret("SW zero, x1, 16").unwrap();
+        i.interpret("ADDI x1, x1, 1").unwrap();
+        i.undo().unwrap();
+        let bytes = i.to_bytes();
+
+        let mut restored = Interpreter::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.snapshot(), i.snapshot());
+        assert_eq!(restored.history().epoch(), 2);
+        restored.redo().unwrap();
+        assert_eq!(restored.cpu.x1, 6);
+        restored.seek(0).unwrap();
+        assert_eq!(restored.cpu.memory[16], 0);
+        assert_eq!(restored.cpu.counters.instret, 0);
+
+        // a snapshot on its own has no history
+        restored.restore_bytes(&i.snapshot().to_bytes()).unwrap();
+        assert_eq!(restored.cpu.x1, 5);
+        assert!(restored.history().is_empty());
+
+        assert_eq!(
+            Interpreter::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(SnapshotError::Truncated)
+        );
+    }
+
+    #[test]
+    fn saved_shifts() {
+        let mut i = Interpreter::new();
+        i.cpu.config.xlen = Xlen::Rv64;
+        i.interpret("ADDI x1, zero, 1").unwrap();
+        i.interpret("SLLI x1, x1, 63").unwrap();
+        i.interpret("SRAIW x2, x1, 31").unwrap();
+        let mut restored = Interpreter::new();
+        restored.cpu.config.xlen = Xlen::Rv64;
+        restored.restore_bytes(&i.to_bytes()).unwrap();
+        assert_eq!(restored.history().len(), 3);
+        restored.undo().unwrap();
+        restored.undo().unwrap();
+        assert_eq!(restored.cpu.get_x(Register::X1), 1);
+
+        // too big to encode, so they'd be read back as something else
+        let e = i.interpret("SLLI x1, x1, 100").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+        assert_eq!(
+            e.to_string(),
+            "Shift amount 100 is too big; SLLI shifts by at most 63."
+        );
+        let e = i.interpret("SRLIW x1, x1, 32").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+
+        // RV32 has no shamt[5], so it's caught before anything runs
+        let mut i = Interpreter::new();
+        i.interpret("SLLI x1, x1, 31").unwrap();
+        let e = i.interpret("SLLI x1, x1, 32").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+        assert_eq!(e.span(), Some(Span { start: 13, end: 15 }));
+        assert_eq!(
+            e.to_string(),
+            "Shift amount 32 is too big; SLLI shifts by at most 31."
+        );
+        assert!(i.interpret("NOP; SRAI x1, x1, 40").is_err());
+        assert_eq!(i.cpu.pc, 4);
+        assert!(i.load_program("SRLI a0, a0, 63").is_err());
+    }
+
+    #[test]
+    fn exit_conventions() {
+        // ECALL with a7 = exit
+        let mut i = Interpreter::default();
+        assert!(i.interpret("ADDI a7, zero, 93").is_ok());
+        assert!(i.interpret("ADDI a0, zero, 3").is_ok());
+        assert_eq!(i.exit_code(), None);
+        assert!(i.interpret("ECALL").is_ok());
+        assert_eq!(i.exit_code(), Some(3));
+
+        // a store to tohost
+        let mut i = Interpreter::default();
+        i.cpu.x1 = crate::devices::ToHost::BASE;
+        assert!(i.interpret("ADDI x2, zero, 5").is_ok());
+        assert!(i.interpret("SW x1, x2, 0").is_ok());
+        assert_eq!(i.exit_code(), Some(2));
+    }
+
+    #[test]
+    fn writes_at_the_top_of_memory() {
+        let delta = StateDelta {
+            memory: vec![MemoryWrite {
+                address: 0xffff_fffe,
+                old: vec![0, 0],
+                new: vec![1, 2],
+            }],
+            ..StateDelta::default()
+        };
+        let watchpoints = BTreeMap::from([(0xffff_ffff, 1)]);
+        assert_eq!(first_watched(&watchpoints, &delta), Some(0xffff_ffff));
+        assert_eq!(StateDelta::merge([&delta, &delta]), delta);
+    }
+
+    #[test]
+    fn undoing_a_read() {
+        let mut i = Interpreter::default();
+        i.cpu.push_syscall_input(b"hey");
+        i.interpret_all("ADDI a7, zero, 63; ADDI a1, zero, 64; ADDI a2, zero, 2; ECALL")
+            .unwrap();
+        assert_eq!(i.cpu.x10, 2);
+        assert_eq!(&i.cpu.memory[64..66], b"he");
+
+        // memory goes back, but what was read stays read
+        i.undo().unwrap();
+        assert_eq!(&i.cpu.memory[64..66], [0, 0]);
+        i.redo().unwrap();
+        i.interpret_all("ADDI a0, zero, 0; ECALL").unwrap();
+        assert_eq!(i.cpu.x10, 1);
+        assert_eq!(i.cpu.memory[64], b'y');
     }
 }
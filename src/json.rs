@@ -0,0 +1,186 @@
+//! Just enough JSON to write it: strings, arrays, and objects built up field
+//! by field. Used for [trace](crate::trace) records and anything else that
+//! hands results to other programs.
+//!
+//! ```
+//! use brubeck::json::{self, Object};
+//!
+//! let object = Object::new()
+//!     .string("name", "say \"hi\"")
+//!     .number("pc", 4)
+//!     .bool("ok", true)
+//!     .raw("values", json::array([1, 2].map(|n: u32| n.to_string())));
+//! assert_eq!(
+//!     object.to_string(),
+//!     r#"{"name":"say \"hi\"","pc":4,"ok":true,"values":[1,2]}"#
+//! );
+//! ```
+
+use std::fmt::Display;
+
+use crate::history::StateDelta;
+use crate::rv32_i::Register;
+
+/// A JSON object, written in the order its fields were added
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object {
+    fields: Vec<(String, String)>,
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, string(value))
+    }
+
+    pub fn number(self, key: &str, value: impl Into<i64>) -> Self {
+        self.raw(key, value.into().to_string())
+    }
+
+    pub fn bool(self, key: &str, value: bool) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    /// A field whose value is already JSON (eg: an [array], or another
+    /// object)
+    pub fn raw(mut self, key: &str, json: impl Display) -> Self {
+        self.fields.push((key.to_owned(), json.to_string()));
+        self
+    }
+
+    /// Adds what a delta changed: `registers`, each `{"register":"x10",
+    /// "old":0,"new":5}` (the PC is left out), and `memory`, each
+    /// `{"address":256,"old":"00000000","new":"05000000"}` with the bytes in
+    /// hex in address order. CSRs it changed are added as `csrs`, each
+    /// `{"csr":"mscratch","address":832,"old":0,"new":1}`, if there are any,
+    /// and a change of mode as `mode`, eg: `{"old":"user","new":"machine"}`.
+    pub fn delta(self, delta: &StateDelta) -> Self {
+        let registers = delta
+            .registers
+            .iter()
+            .filter(|c| c.register != Register::PC)
+            .map(|c| {
+                Object::new()
+                    .string("register", &c.register.to_string())
+                    .raw("old", c.old.to_string())
+                    .raw("new", c.new.to_string())
+                    .to_string()
+            });
+        let memory = delta.memory.iter().map(|w| {
+            Object::new()
+                .number("address", w.address)
+                .string("old", &hex(&w.old))
+                .string("new", &hex(&w.new))
+                .to_string()
+        });
+
+        let mut object = self
+            .raw("registers", array(registers))
+            .raw("memory", array(memory));
+        if !delta.csrs.is_empty() {
+            let csrs = delta.csrs.iter().map(|c| {
+                Object::new()
+                    .string("csr", &c.name())
+                    .number("address", c.address)
+                    .number("old", c.old)
+                    .number("new", c.new)
+                    .to_string()
+            });
+            object = object.raw("csrs", array(csrs));
+        }
+        match delta.privilege {
+            Some((old, new)) => object.raw(
+                "mode",
+                Object::new()
+                    .string("old", &old.to_string())
+                    .string("new", &new.to_string())
+                    .to_string(),
+            ),
+            None => object,
+        }
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{value}", string(key))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// A JSON array of values that are already JSON
+pub fn array(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("[{}]", items.join(","))
+}
+
+/// A quoted JSON string, with quotes, backslashes, and control characters
+/// escaped
+pub fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Bytes as pairs of hex digits, eg: `0500`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RegisterChange;
+    use crate::rv32_i::MemoryWrite;
+
+    #[test]
+    fn strings_and_deltas() {
+        assert_eq!(string("a\\b\n\t"), r#""a\\b\n\u0009""#);
+        assert_eq!(Object::new().to_string(), "{}");
+        assert_eq!(array([]), "[]");
+
+        let delta = StateDelta {
+            registers: vec![
+                RegisterChange {
+                    register: Register::X1,
+                    old: 0,
+                    new: 8,
+                },
+                RegisterChange {
+                    register: Register::PC,
+                    old: 0,
+                    new: 4,
+                },
+            ],
+            memory: vec![MemoryWrite {
+                address: 16,
+                old: vec![0, 0],
+                new: vec![1, 0xff],
+            }],
+            ..StateDelta::default()
+        };
+        assert_eq!(
+            Object::new().delta(&delta).to_string(),
+            r#"{"registers":[{"register":"x1","old":0,"new":8}],"memory":[{"address":16,"old":"0000","new":"01ff"}]}"#
+        );
+    }
+}
@@ -20,11 +20,18 @@
 //! ## Running
 //!
 //! `brubeck` (or `cargo run`) opens a REPL for a minimal RV32I RISC-V emulated
-//! processor (single hardware thread, one mebibyte of memory).
+//! processor (single hardware thread, one mebibyte of memory). Standard
+//! [devices](crate::devices) are mapped at the same addresses as QEMU's `virt`
+//! board: a CLINT timer at `0x0200_0000`, a PLIC at `0x0c00_0000`, and a UART
+//! at `0x1000_0000`.
 //!
 //! To see the contents of a register, just type in it's name (eg: `x2` or
 //! `sp` if you prefer the [ABI](crate::rv32_i::ABI) name). To examine a
-//! region in memory ... well, that's TBD.
+//! region in memory, use `/mem` (eg: `/mem sp 8 -w 4`), and to look for a
+//! value or some text, `/find` (see [search]). `/disasm` shows the
+//! instructions in memory (see [disassembler]), and `/stats` reports what
+//! has run (see [stats]). `/checkpoint <name>` bookmarks the machine state
+//! and `/restore <name>` goes back to it (see [checkpoint]).
 //!
 //! To execute an instruction, type in its name and arguments (eg: `nop` or `addi x2, x0, 5`).
 //!
@@ -117,8 +124,38 @@
 /// Provides immediate value checks, conversions, etc.
 mod immediate;
 
+pub mod analysis;
+pub mod audit;
+pub mod bits;
+pub mod board;
+pub mod cancel;
+pub mod checkpoint;
+pub mod completion;
+pub mod devices;
+pub mod disassembler;
+pub mod elf;
+pub mod events;
+pub mod expression;
+pub mod fuzzy;
+pub mod golden;
+pub mod history;
+pub mod ihex;
 pub mod interpreter;
+pub mod json;
+pub mod metadata;
+pub mod programs;
+pub mod riscv_tests;
 pub mod rv32_i;
+pub mod sandbox;
+pub mod script;
+pub mod search;
+pub mod semihosting;
+pub mod snapshot;
+pub mod stats;
+pub mod syscalls;
+pub mod trace;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use immediate::Immediate;
+pub use immediate::{Immediate, TwosComplement};
 pub use interpreter::Interpreter;
@@ -0,0 +1,535 @@
+//! Facts about each supported instruction, in one table.
+//!
+//! Help text, summaries, and anything else that describes the instruction set
+//! should be generated from [INSTRUCTIONS] rather than written by hand, so it
+//! can't drift from what's actually implemented. The table itself is generated
+//! from the same definition as [Instruction](crate::rv32_i::Instruction), and
+//! the encoder, the parser's operand checks, and the disassembler all read
+//! their opcodes and operands from it too.
+//!
+//! ```
+//! use brubeck::metadata::{self, Category, OperandKind};
+//!
+//! let addi = metadata::lookup("addi").unwrap();
+//! assert_eq!(addi.category, Category::Arithmetic);
+//!
+//! let branches = metadata::in_category(Category::Branch).count();
+//! assert_eq!(branches, 6);
+//!
+//! // enough to explain (or encode) an instruction from scratch
+//! assert_eq!(addi.opcode, 0b001_0011);
+//! assert_eq!(addi.operands()[1].kind, OperandKind::Register);
+//! assert_eq!(addi.operands()[1].meaning, "source register");
+//! assert_eq!(addi.format.fields()[0].name, "imm[11:0]");
+//! assert_eq!(addi.example(), "ADDI a0, a1, 5");
+//! ```
+
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+use crate::rv32_i::{BType, IType, JType, SType, UType};
+
+/// Instruction encoding format; see [formats](crate::rv32_i::formats)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    R,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+impl Format {
+    /// The fields of a machine word in this format, most significant first.
+    /// Branch and jump immediates are scattered, so one immediate can take up
+    /// several fields (eg: `imm[12]` and `imm[10:5]`).
+    pub fn fields(&self) -> &'static [Field] {
+        const fn field(name: &'static str, high: u32, low: u32) -> Field {
+            Field { name, high, low }
+        }
+
+        const R: &[Field] = &[
+            field("funct7", 31, 25),
+            field("rs2", 24, 20),
+            field("rs1", 19, 15),
+            field("funct3", 14, 12),
+            field("rd", 11, 7),
+            field("opcode", 6, 0),
+        ];
+        const I: &[Field] = &[
+            field("imm[11:0]", 31, 20),
+            field("rs1", 19, 15),
+            field("funct3", 14, 12),
+            field("rd", 11, 7),
+            field("opcode", 6, 0),
+        ];
+        const S: &[Field] = &[
+            field("imm[11:5]", 31, 25),
+            field("rs2", 24, 20),
+            field("rs1", 19, 15),
+            field("funct3", 14, 12),
+            field("imm[4:0]", 11, 7),
+            field("opcode", 6, 0),
+        ];
+        const B: &[Field] = &[
+            field("imm[12]", 31, 31),
+            field("imm[10:5]", 30, 25),
+            field("rs2", 24, 20),
+            field("rs1", 19, 15),
+            field("funct3", 14, 12),
+            field("imm[4:1]", 11, 8),
+            field("imm[11]", 7, 7),
+            field("opcode", 6, 0),
+        ];
+        const U: &[Field] = &[
+            field("imm[31:12]", 31, 12),
+            field("rd", 11, 7),
+            field("opcode", 6, 0),
+        ];
+        const J: &[Field] = &[
+            field("imm[20]", 31, 31),
+            field("imm[10:1]", 30, 21),
+            field("imm[11]", 20, 20),
+            field("imm[19:12]", 19, 12),
+            field("rd", 11, 7),
+            field("opcode", 6, 0),
+        ];
+
+        match self {
+            Format::R => R,
+            Format::I => I,
+            Format::S => S,
+            Format::B => B,
+            Format::U => U,
+            Format::J => J,
+        }
+    }
+}
+
+/// A field of a machine word: bits `high` down to `low`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub high: u32,
+    pub low: u32,
+}
+
+impl Field {
+    /// This field's bits of `word`, shifted down to bit 0
+    pub fn extract(&self, word: u32) -> u32 {
+        crate::bits::field(word, self.high, self.low)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.high - self.low + 1
+    }
+}
+
+/// An operand, as named in an instruction's [syntax](InstructionInfo::syntax)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Operand {
+    /// eg: `rd`
+    pub name: &'static str,
+    pub kind: OperandKind,
+    /// What it's for, eg: `destination register`
+    pub meaning: &'static str,
+}
+
+/// What an operand can be
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    /// A CSR, by name or address
+    Csr,
+    /// A number (`imm`, `uimm`, or `shamt`); see
+    /// [immediate_range](InstructionInfo::immediate_range)
+    Immediate,
+}
+
+/// Broad grouping of instructions by what they do
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Category {
+    Arithmetic,
+    Logical,
+    Branch,
+    Jump,
+    LoadStore,
+    System,
+    Csr,
+    /// The M extension
+    Multiply,
+    /// The A extension
+    Atomic,
+    /// Only in RV64I
+    Rv64,
+    Pseudo,
+}
+
+impl Category {
+    pub const ALL: [Category; 11] = [
+        Category::Arithmetic,
+        Category::Logical,
+        Category::Branch,
+        Category::Jump,
+        Category::LoadStore,
+        Category::System,
+        Category::Csr,
+        Category::Multiply,
+        Category::Atomic,
+        Category::Rv64,
+        Category::Pseudo,
+    ];
+
+    /// One-line summary of the category
+    pub fn blurb(&self) -> &'static str {
+        match self {
+            Category::Arithmetic => "add, subtract, compare, and build constants",
+            Category::Logical => "bitwise operations and shifts",
+            Category::Branch => "conditionally jump to a PC-relative target",
+            Category::Jump => "unconditionally jump, saving the return address",
+            Category::LoadStore => "move bytes, halfwords, and words to and from memory",
+            Category::System => "environment calls, breakpoints, and memory ordering",
+            Category::Csr => "read and write control and status registers",
+            Category::Multiply => "multiply, divide, and remainder (the M extension)",
+            Category::Atomic => "read-modify-write memory in one step (the A extension)",
+            Category::Rv64 => "32-bit arithmetic and doubleword loads and stores (RV64I only)",
+            Category::Pseudo => "shorthand for other instructions",
+        }
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Category::Arithmetic => "Arithmetic",
+            Category::Logical => "Logical",
+            Category::Branch => "Branches",
+            Category::Jump => "Jumps",
+            Category::LoadStore => "Loads/Stores",
+            Category::System => "System",
+            Category::Csr => "CSR",
+            Category::Multiply => "Multiply",
+            Category::Atomic => "Atomic",
+            Category::Rv64 => "RV64",
+            Category::Pseudo => "Pseudo",
+        };
+
+        // pad, so categories can be lined up in tables
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionInfo {
+    /// Mnemonic, in upper case
+    pub name: &'static str,
+    pub format: Format,
+    pub category: Category,
+    /// The major opcode, the bottom 7 bits of the machine word (eg:
+    /// [OP_IMM](crate::rv32_i::encoding::OP_IMM))
+    pub opcode: u32,
+    /// Tells apart instructions with the same opcode; 0 for the U and J
+    /// formats, which don't have it
+    pub funct3: u32,
+    /// Tells apart R-type instructions (and shifts by an immediate) with the
+    /// same opcode and `funct3`; 0 for formats that don't have it
+    pub funct7: u32,
+    /// One line on what it does
+    pub description: &'static str,
+}
+
+impl InstructionInfo {
+    /// Finds an instruction by mnemonic, ignoring case
+    pub fn lookup(name: &str) -> Option<&'static InstructionInfo> {
+        INSTRUCTIONS
+            .iter()
+            .find(|i| i.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Operands, in the order the interpreter expects them (eg: `rd, rs1,
+    /// imm`); empty if there are none
+    pub fn syntax(&self) -> &'static str {
+        match (self.category, self.format, self.name) {
+            (Category::Csr, _, name) if name.ends_with('I') => "rd, csr, uimm",
+            (Category::Csr, _, _) => "rd, csr, rs1",
+            (_, _, "ECALL" | "EBREAK" | "FENCE.I" | "MRET" | "NOP" | "WFI") => "",
+            (_, _, "LR.W") => "rd, rs1",
+            (_, _, "SLLI" | "SRLI" | "SRAI" | "SLLIW" | "SRLIW" | "SRAIW") => "rd, rs1, shamt",
+            (_, Format::R, _) => "rd, rs1, rs2",
+            (_, Format::I, _) => "rd, rs1, imm",
+            (_, Format::S | Format::B, _) => "rs1, rs2, imm",
+            (_, Format::U | Format::J, _) => "rd, imm",
+        }
+    }
+
+    /// Values the immediate operand (`imm`, `uimm`, or `shamt`) can take, if
+    /// there is one. Branch and jump immediates count 2 byte steps.
+    pub fn immediate_range(&self) -> Option<RangeInclusive<i32>> {
+        let syntax = self.syntax();
+        if syntax.ends_with("uimm") || syntax.ends_with("shamt") {
+            return Some(0..=31);
+        }
+        if !syntax.ends_with("imm") {
+            return None;
+        }
+
+        let imm = match self.format {
+            Format::R => return None,
+            Format::I => IType::default().imm,
+            Format::S => SType::default().imm,
+            Format::B => BType::default().imm,
+            Format::U => UType::default().imm,
+            Format::J => JType::default().imm,
+        };
+        Some(imm.signed_min()..=imm.signed_max())
+    }
+
+    /// What each operand in the [syntax](Self::syntax) is for, in order
+    pub fn operands(&self) -> Vec<Operand> {
+        let memory = matches!(self.category, Category::LoadStore | Category::Atomic)
+            || matches!(self.name, "LD" | "LWU" | "SD");
+        self.syntax()
+            .split(", ")
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let meaning = match (name, self.format) {
+                    ("rd", _) => "destination register",
+                    ("rs1", _) if memory || self.name == "JALR" => "base address register",
+                    ("rs1", Format::R | Format::S | Format::B) => "first source register",
+                    ("rs1", _) => "source register",
+                    ("rs2", Format::S) => "register to store",
+                    ("rs2", _) if memory => "value register",
+                    ("rs2", _) => "second source register",
+                    ("csr", _) => "CSR, by name (eg: mscratch) or address",
+                    ("uimm", _) => "unsigned 5 bit immediate",
+                    ("shamt", _) => "how many bits to shift by",
+                    ("imm", Format::B | Format::J) => "offset from the PC, in 2 byte steps",
+                    ("imm", Format::U) => "upper 20 bits",
+                    ("imm", _) if memory || self.name == "JALR" => "offset from the base address",
+                    _ => "signed immediate",
+                };
+                let kind = match name {
+                    "rd" | "rs1" | "rs2" => OperandKind::Register,
+                    "csr" => OperandKind::Csr,
+                    _ => OperandKind::Immediate,
+                };
+                Operand {
+                    name,
+                    kind,
+                    meaning,
+                }
+            })
+            .collect()
+    }
+
+    /// A line to try the instruction with, eg: `ADDI a0, a1, 5`: the
+    /// destination is `a0`, the sources `a1` and `a2`, and immediates are
+    /// small enough to be valid for any of them (memory accesses stay aligned
+    /// if `a1` is)
+    pub fn example(&self) -> String {
+        let operands: Vec<&str> = self
+            .operands()
+            .iter()
+            .map(|operand| match (operand.name, self.format) {
+                ("rd", _) => "a0",
+                ("rs1", _) => "a1",
+                ("rs2", _) => "a2",
+                ("csr", _) => "mscratch",
+                ("shamt", _) => "2",
+                ("imm", Format::B | Format::J) => "8",
+                ("imm", Format::U) => "1",
+                ("imm", _) if self.name == "JALR" => "8",
+                ("imm", _) if matches!(self.category, Category::LoadStore | Category::Rv64) => "8",
+                _ => "5",
+            })
+            .collect();
+        match operands.is_empty() {
+            true => self.name.to_owned(),
+            false => format!("{} {}", self.name, operands.join(", ")),
+        }
+    }
+}
+
+/// Finds an instruction by mnemonic, ignoring case
+pub fn lookup(name: &str) -> Option<&'static InstructionInfo> {
+    InstructionInfo::lookup(name)
+}
+
+/// All instructions in a [Category]
+pub fn in_category(category: Category) -> impl Iterator<Item = &'static InstructionInfo> {
+    INSTRUCTIONS.iter().filter(move |i| i.category == category)
+}
+
+/// A quick reference for every instruction, as a Markdown table
+pub fn markdown_reference() -> String {
+    let mut markdown =
+        String::from("| Instruction | Operands | Operation | Immediate |\n|---|---|---|---|\n");
+
+    for category in Category::ALL {
+        for info in in_category(category) {
+            let range = info
+                .immediate_range()
+                .map(|r| format!("{}..={}", r.start(), r.end()))
+                .unwrap_or_default();
+            markdown.push_str(&format!(
+                "| `{}` | `{}` | {} | {} |\n",
+                info.name,
+                info.syntax(),
+                info.description.replace('|', "\\|"),
+                range
+            ));
+        }
+    }
+
+    markdown
+}
+
+macro_rules! define_metadata {
+    ($($name:ident $(($_operands:ident))? $(as $mnemonic:literal)? :
+        $format:ident, $category:ident, $opcode:ident $funct3:literal $funct7:literal, $description:literal;)*) => {
+        /// Every instruction the interpreter understands, in alphabetical order
+        pub const INSTRUCTIONS: &[InstructionInfo] = &[
+            $(InstructionInfo {
+                name: define_metadata!(@name $name $($mnemonic)?),
+                format: Format::$format,
+                category: Category::$category,
+                opcode: crate::rv32_i::encoding::$opcode,
+                funct3: $funct3,
+                funct7: $funct7,
+                description: $description,
+            },)*
+        ];
+    };
+
+    (@name $name:ident) => { stringify!($name) };
+    (@name $name:ident $mnemonic:literal) => { $mnemonic };
+}
+
+crate::rv32_i::rv32i_instructions!(define_metadata);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted_and_unique() {
+        for pair in INSTRUCTIONS.windows(2) {
+            assert!(
+                pair[0].name < pair[1].name,
+                "{} >= {}",
+                pair[0].name,
+                pair[1].name
+            );
+        }
+    }
+
+    #[test]
+    fn every_instruction_is_categorized() {
+        let total: usize = Category::ALL.iter().map(|c| in_category(*c).count()).sum();
+        assert_eq!(total, INSTRUCTIONS.len());
+        assert_eq!(lookup("Sltiu").unwrap().format, Format::I);
+        assert_eq!(lookup("mul").unwrap().category, Category::Multiply);
+        assert!(lookup("FMUL").is_none());
+    }
+
+    #[test]
+    fn syntax_and_ranges() {
+        let syntax = |name| lookup(name).unwrap().syntax();
+        assert_eq!(syntax("ADD"), "rd, rs1, rs2");
+        assert_eq!(syntax("SW"), "rs1, rs2, imm");
+        assert_eq!(syntax("CSRRSI"), "rd, csr, uimm");
+        assert_eq!(syntax("ECALL"), "");
+
+        let range = |name| lookup(name).unwrap().immediate_range();
+        assert_eq!(range("ADDI"), Some(-2048..=2047));
+        assert_eq!(range("SRAI"), Some(0..=31));
+        assert_eq!(range("JAL"), Some(-524288..=524287));
+        assert_eq!(range("CSRRW"), None);
+        assert_eq!(range("OR"), None);
+
+        let markdown = markdown_reference();
+        assert_eq!(markdown.lines().count(), INSTRUCTIONS.len() + 2);
+        assert!(markdown.contains("| `OR` | `rd, rs1, rs2` | rd = rs1 \\| rs2 |  |"));
+    }
+
+    #[test]
+    fn encodings_match_the_encoder() {
+        use crate::bits::field;
+        use crate::rv32_i::{encoding, Instruction};
+
+        for instruction in Instruction::all() {
+            let info = instruction.info();
+            let word = instruction.encode();
+            assert_eq!(info.category, instruction.category());
+            assert_eq!(info.opcode, field(word, 6, 0), "{}", info.name);
+            if !matches!(info.format, Format::U | Format::J) {
+                assert_eq!(info.funct3, field(word, 14, 12), "{}", info.name);
+            }
+            if info.format == Format::R {
+                assert_eq!(info.funct7, field(word, 31, 25), "{}", info.name);
+            }
+        }
+
+        let srai = lookup("SRAI").unwrap();
+        assert_eq!(
+            (srai.opcode, srai.funct3, srai.funct7),
+            (encoding::OP_IMM, 0b101, 0b010_0000)
+        );
+    }
+
+    #[test]
+    fn operands_fields_and_examples() {
+        let kinds = |name| -> Vec<OperandKind> {
+            let info = lookup(name).unwrap();
+            info.operands().iter().map(|o| o.kind).collect()
+        };
+        assert_eq!(
+            kinds("CSRRWI"),
+            [
+                OperandKind::Register,
+                OperandKind::Csr,
+                OperandKind::Immediate
+            ]
+        );
+
+        let meanings = |name| -> Vec<&str> {
+            let info = lookup(name).unwrap();
+            info.operands().iter().map(|o| o.meaning).collect()
+        };
+        assert_eq!(
+            meanings("SW"),
+            [
+                "base address register",
+                "register to store",
+                "offset from the base address"
+            ]
+        );
+        assert_eq!(meanings("AUIPC"), ["destination register", "upper 20 bits"]);
+        assert!(meanings("ECALL").is_empty());
+
+        // every format's fields cover the word exactly once
+        for format in [
+            Format::R,
+            Format::I,
+            Format::S,
+            Format::B,
+            Format::U,
+            Format::J,
+        ] {
+            let fields = format.fields();
+            assert_eq!(fields.iter().map(Field::width).sum::<u32>(), 32);
+            for pair in fields.windows(2) {
+                assert_eq!(pair[0].low, pair[1].high + 1);
+            }
+        }
+        assert_eq!(Format::B.fields()[1].extract(0x7e00_0000), 0x3f);
+
+        let example = |name| lookup(name).unwrap().example();
+        assert_eq!(example("SW"), "SW a1, a2, 8");
+        assert_eq!(example("CSRRSI"), "CSRRSI a0, mscratch, 5");
+        assert_eq!(example("BEQ"), "BEQ a1, a2, 8");
+        assert_eq!(example("SLLI"), "SLLI a0, a1, 2");
+        assert_eq!(example("NOP"), "NOP");
+    }
+}
@@ -0,0 +1,87 @@
+//! Example programs built into the crate, so there's something to run
+//! straight away (eg: the REPL's `/example load fibonacci`) and realistic
+//! workloads for tests.
+//!
+//! Each is an assembly listing in the format [load_program] takes, and ends
+//! by exiting with its result in `a0`.
+//!
+//! ```
+//! use brubeck::programs;
+//! use brubeck::Interpreter;
+//!
+//! let fibonacci = programs::find("fibonacci").unwrap();
+//! let mut i = Interpreter::new();
+//! assert!(i.load_program(fibonacci.source).is_ok());
+//! ```
+//!
+//! [load_program]: crate::Interpreter::load_program
+
+/// A built-in example program
+#[derive(Debug)]
+pub struct Program {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// What the program exits with
+    pub result: u32,
+    pub source: &'static str,
+}
+
+pub const PROGRAMS: &[Program] = &[
+    Program {
+        name: "fibonacci",
+        description: "the 10th Fibonacci number, iteratively",
+        result: 55,
+        source: include_str!("programs/fibonacci.s"),
+    },
+    Program {
+        name: "bubble_sort",
+        description: "sorts eight words in memory",
+        result: 1,
+        source: include_str!("programs/bubble_sort.s"),
+    },
+    Program {
+        name: "memcpy",
+        description: "copies 16 bytes, one at a time",
+        result: 45,
+        source: include_str!("programs/memcpy.s"),
+    },
+    Program {
+        name: "crc32",
+        description: "the CRC-32 of \"123456789\", a bit at a time",
+        result: 0xcbf4_3926,
+        source: include_str!("programs/crc32.s"),
+    },
+    Program {
+        name: "uart_hello",
+        description: "prints \"hello\" with stores to the UART",
+        result: 6,
+        source: include_str!("programs/uart_hello.s"),
+    },
+];
+
+/// An example program by name
+pub fn find(name: &str) -> Option<&'static Program> {
+    PROGRAMS.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    #[test]
+    fn programs_produce_their_results() {
+        for program in PROGRAMS {
+            let mut i = Interpreter::new();
+            i.load_program(program.source).unwrap();
+            let run = i.run(10_000);
+
+            assert_eq!(
+                i.exit_code(),
+                Some(program.result),
+                "{}: {run}",
+                program.name
+            );
+        }
+    }
+}
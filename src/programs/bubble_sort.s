@@ -0,0 +1,49 @@
+# Bubble sort: sorts eight words at 0x400, then exits with the smallest (1)
+#
+# Branch and jump offsets count 2-byte steps from the branch itself, and
+# negative numbers are written as their two's complement (eg: 4092 is -4).
+
+ADDI s0, zero, 1024     # the array
+ADDI t0, zero, 5
+SW s0, t0, 0
+ADDI t0, zero, 3
+SW s0, t0, 4
+ADDI t0, zero, 8
+SW s0, t0, 8
+ADDI t0, zero, 1
+SW s0, t0, 12
+ADDI t0, zero, 9
+SW s0, t0, 16
+ADDI t0, zero, 2
+SW s0, t0, 20
+ADDI t0, zero, 7
+SW s0, t0, 24
+ADDI t0, zero, 4
+SW s0, t0, 28
+ADDI s1, zero, 28       # offset of the last element still unsorted
+
+# pass: 0x48
+BEQ s1, zero, 26        # done when nothing is left (to 0x7c)
+ADDI t3, zero, 0        # offset of the pair being compared
+
+# compare: 0x50
+BGE t3, s1, 18          # end of the pass (to 0x74)
+ADD t4, s0, t3
+LW t5, t4, 0
+LW t6, t4, 4
+BGE t6, t5, 6           # already in order (to 0x6c)
+SW t4, t6, 0            # swap
+SW t4, t5, 4
+
+# next: 0x6c
+ADDI t3, t3, 4
+JAL zero, 1048560       # back to compare (-32 bytes)
+
+# end of the pass: 0x74
+ADDI s1, s1, 4092       # the largest has bubbled to the end
+JAL zero, 1048552       # back to pass (-48 bytes)
+
+# done: 0x7c
+LW a0, s0, 0
+ADDI a7, zero, 93       # exit with a0
+ECALL
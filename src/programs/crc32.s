@@ -0,0 +1,51 @@
+# CRC-32: checksums the nine bytes "123456789", then exits with the result,
+# which should be the standard check value 0xcbf43926
+#
+# Branch and jump offsets count 2-byte steps from the branch itself, and
+# negative numbers are written as their two's complement (eg: 4095 is -1).
+
+ADDI s0, zero, 1024     # the message
+ADDI s1, zero, 9        # its length
+ADDI t0, zero, 0        # index
+ADDI t1, zero, 49       # '1'
+
+# fill: 0x10
+BGE t0, s1, 12          # filled (to 0x28)
+ADD t2, s0, t0
+SB t2, t1, 0
+ADDI t0, t0, 1
+ADDI t1, t1, 1
+JAL zero, 1048566       # back to fill (-20 bytes)
+
+# 0x28
+LUI t6, 973704          # the reflected polynomial, 0xedb88320
+ADDI t6, t6, 800
+ADDI a0, zero, 4095     # crc = 0xffffffff
+ADDI t0, zero, 0
+
+# byte: 0x38
+BGE t0, s1, 28          # all bytes done (to 0x70)
+ADD t2, s0, t0
+LBU t3, t2, 0
+XOR a0, a0, t3
+ADDI t4, zero, 8        # bits left
+
+# bit: 0x4c
+BEQ t4, zero, 14        # byte done (to 0x68)
+ANDI t5, a0, 1
+SRLI a0, a0, 1
+BEQ t5, zero, 4         # low bit was clear (to 0x60)
+XOR a0, a0, t6
+
+# 0x60
+ADDI t4, t4, 4095
+JAL zero, 1048564       # back to bit (-24 bytes)
+
+# byte done: 0x68
+ADDI t0, t0, 1
+JAL zero, 1048550       # back to byte (-52 bytes)
+
+# done: 0x70
+XORI a0, a0, 4095       # crc = !crc
+ADDI a7, zero, 93       # exit with a0
+ECALL
@@ -0,0 +1,20 @@
+# Fibonacci: exits with fib(10) = 55
+#
+# Branch and jump offsets count 2-byte steps from the branch itself, and
+# negative numbers are written as their two's complement (eg: 4095 is -1).
+
+ADDI t0, zero, 10       # n
+ADDI a0, zero, 0        # fib(i)
+ADDI t1, zero, 1        # fib(i + 1)
+
+# loop: 0x0c
+BEQ t0, zero, 12        # done when n is 0 (to 0x24)
+ADD t2, a0, t1
+ADDI a0, t1, 0
+ADDI t1, t2, 0
+ADDI t0, t0, 4095       # n -= 1
+JAL zero, 1048566       # back to loop (-20 bytes)
+
+# done: 0x24
+ADDI a7, zero, 93       # exit with a0
+ECALL
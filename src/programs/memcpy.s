@@ -0,0 +1,38 @@
+# memcpy: copies 16 bytes from 0x400 to 0x500, then exits with the last
+# byte copied (45)
+#
+# Branch and jump offsets count 2-byte steps from the branch itself, and
+# negative numbers are written as their two's complement.
+
+ADDI a0, zero, 1024     # source
+ADDI a1, zero, 1280     # destination
+ADDI a2, zero, 16       # length
+
+# fill the source with 0, 3, 6, ...
+ADDI t0, zero, 0        # index
+ADDI t1, zero, 0        # value
+
+# fill: 0x14
+BGE t0, a2, 12          # filled (to 0x2c)
+ADD t2, a0, t0
+SB t2, t1, 0
+ADDI t0, t0, 1
+ADDI t1, t1, 3
+JAL zero, 1048566       # back to fill (-20 bytes)
+
+# copy a byte at a time: 0x2c
+ADDI t0, zero, 0
+
+# copy: 0x30
+BGE t0, a2, 14          # copied (to 0x4c)
+ADD t2, a0, t0
+LBU t3, t2, 0
+ADD t2, a1, t0
+SB t2, t3, 0
+ADDI t0, t0, 1
+JAL zero, 1048564       # back to copy (-24 bytes)
+
+# done: 0x4c
+LBU a0, a1, 15
+ADDI a7, zero, 93       # exit with a0
+ECALL
@@ -0,0 +1,24 @@
+# uart_hello: prints "hello" on the UART, then exits with the number of bytes
+# sent (6)
+#
+# Bare-metal programs talk to the UART with plain stores: each byte stored to
+# its transmit register (THR, at 0x1000_0000) is sent. There's no ECALL and
+# no operating system involved.
+
+LUI t0, 65536           # the UART (0x1000_0000)
+
+ADDI t1, zero, 104      # h
+SB t0, t1, 0
+ADDI t1, zero, 101      # e
+SB t0, t1, 0
+ADDI t1, zero, 108      # l
+SB t0, t1, 0
+SB t0, t1, 0
+ADDI t1, zero, 111      # o
+SB t0, t1, 0
+ADDI t1, zero, 10       # newline
+SB t0, t1, 0
+
+ADDI a0, zero, 6
+ADDI a7, zero, 93       # exit with a0
+ECALL
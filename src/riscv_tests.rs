@@ -0,0 +1,255 @@
+//! Runs the ISA tests from [riscv-tests](https://github.com/riscv-software-src/riscv-tests)
+//! (eg: `rv32ui-p-addi`, as built in its `isa` directory) and reports which
+//! pass.
+//!
+//! Each test is an ELF executable that starts in machine mode, sets up its
+//! own trap handler, and reports through the HTIF `tohost` word: `1` when
+//! every case passed, or the number of the case that failed shifted left
+//! with the low bit set. So each one gets a machine of its own with:
+//!
+//! - memory covering the executable's segments, at their addresses
+//! - a [ToHost](crate::devices::tohost::ToHost) device at the `tohost`
+//!   symbol, which ends the run with the case number (`0` for a pass)
+//! - `ECALL` and unknown CSRs trapping to the test's handler, as the tests
+//!   expect (see [EcallPolicy] and [CsrPolicy])
+//! - every instruction [double-checked](Interpreter::set_double_check)
+//!   against the independent semantics model, so a result the test can't
+//!   see still fails the run
+//!
+//! A test that doesn't pass keeps a [Snapshot] of the machine as it stopped,
+//! which can be written out and loaded in the REPL with `/load-session` for
+//! a closer look.
+//!
+//! `brubeck --riscv-tests <dir>` runs every test in a directory. The same
+//! suite runs with `cargo test` when `RISCV_TESTS` names the directory.
+
+use std::fmt::Display;
+#[cfg(feature = "host")]
+use std::{fs, io, path::Path};
+
+use crate::board::{Board, DeviceKind, DeviceSpec};
+use crate::elf;
+use crate::interpreter::{Interpreter, Stop};
+use crate::rv32_i::{CsrPolicy, EcallPolicy, Extensions};
+use crate::snapshot::Snapshot;
+
+/// Instructions a test can run before it's given up on; the longest of the
+/// `rv32ui` tests takes a few thousand
+pub const MAX_STEPS: usize = 100_000;
+
+/// Memory is given in multiples of this many bytes
+const MEMORY_ALIGNMENT: u32 = 0x1_0000;
+
+/// How a test ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The test reported this case as failing
+    Failed(u32),
+    /// The test couldn't be loaded, or an instruction couldn't be run (or
+    /// the two models disagreed about it)
+    Error(String),
+    /// [MAX_STEPS] ran without the test reporting
+    Timeout,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passed => write!(f, "passed"),
+            Self::Failed(case) => write!(f, "failed test case {case}"),
+            Self::Error(message) => write!(f, "error: {message}"),
+            Self::Timeout => write!(f, "didn't finish in {MAX_STEPS} instructions"),
+        }
+    }
+}
+
+/// What happened when a test ran
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The test's file name (eg: `rv32ui-p-addi`)
+    pub name: String,
+    pub outcome: Outcome,
+    /// Instructions executed
+    pub steps: usize,
+    /// The machine as it stopped, unless the test passed (or never started)
+    pub snapshot: Option<Snapshot>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+/// Runs the test in the ELF executable `bytes`
+pub fn run(name: &str, bytes: &[u8]) -> Report {
+    let error = |message: String| Report {
+        name: name.to_owned(),
+        outcome: Outcome::Error(message),
+        steps: 0,
+        snapshot: None,
+    };
+
+    let mut interpreter = match machine(bytes) {
+        Ok(interpreter) => interpreter,
+        Err(message) => return error(message),
+    };
+    let run = interpreter.run(MAX_STEPS);
+    let outcome = match run.stop {
+        Stop::Exited(0) => Outcome::Passed,
+        Stop::Exited(case) => Outcome::Failed(case),
+        Stop::StepLimit => Outcome::Timeout,
+        Stop::Failed(e) => Outcome::Error(e.to_string()),
+        stop => Outcome::Error(format!("stopped unexpectedly: {stop:?}")),
+    };
+    let snapshot = match outcome {
+        Outcome::Passed => None,
+        _ => Some(interpreter.snapshot()),
+    };
+
+    Report {
+        name: name.to_owned(),
+        outcome,
+        steps: run.steps,
+        snapshot,
+    }
+}
+
+/// Runs every test in `directory` whose name starts with `prefix` (eg:
+/// `rv32ui-p-`), in order of name. The disassembly (`.dump`) files the
+/// suite builds alongside them are skipped.
+#[cfg(feature = "host")]
+pub fn run_directory(directory: &Path, prefix: &str) -> io::Result<Vec<Report>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with(prefix) && path.extension().is_none() && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            Ok(run(&name, &fs::read(path)?))
+        })
+        .collect()
+}
+
+/// A machine with the executable loaded, as described in the module docs
+fn machine(bytes: &[u8]) -> Result<Interpreter, String> {
+    let image = elf::parse(bytes).map_err(|e| e.to_string())?;
+    let symbols = elf::symbols(bytes).map_err(|e| e.to_string())?;
+    let tohost = symbols
+        .iter()
+        .find(|(name, _)| name == "tohost")
+        .map(|(_, address)| *address)
+        .ok_or("there's no tohost symbol")?;
+
+    let start = image
+        .chunks
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .ok_or("there's nothing to load")?;
+    let end = image
+        .chunks
+        .iter()
+        .map(|(address, data)| *address as u64 + data.len() as u64)
+        .max()
+        .unwrap_or_default();
+    let base = start & !(MEMORY_ALIGNMENT - 1);
+    let size = (end - base as u64).next_multiple_of(MEMORY_ALIGNMENT as u64);
+
+    let board = Board {
+        name: "riscv-tests".to_owned(),
+        memory_size: size as usize,
+        memory_base: base,
+        reset_vector: image.entry,
+        ecall: EcallPolicy::TrapIntoGuestHandler,
+        csr: CsrPolicy::Trap,
+        extensions: Extensions::from_isa("rv32imac").unwrap_or_default(),
+        devices: vec![DeviceSpec {
+            kind: DeviceKind::ToHost,
+            base: tohost,
+            irq: None,
+        }],
+        ..Board::default()
+    };
+    let mut interpreter = Interpreter::with_board(&board).map_err(|e| e.to_string())?;
+    image
+        .load(&mut interpreter.cpu, 0)
+        .map_err(|e| e.to_string())?;
+    interpreter.set_double_check(true);
+    Ok(interpreter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::tests::executable;
+
+    /// A test that reports `result` to `tohost` at 0x8000_1000
+    fn test(result: u32) -> Vec<u8> {
+        let code: Vec<u8> = [
+            0x8000_12b7,                // LUI t0, 0x80001
+            0x0000_0313 | result << 20, // ADDI t1, zero, result
+            0x0062_a023,                // SW t1, 0(t0)
+            0x0000_006f,                // J .
+        ]
+        .iter()
+        .flat_map(|word: &u32| word.to_le_bytes())
+        .collect();
+        executable(
+            0x8000_0000,
+            &[(0x8000_0000, &code), (0x8000_1000, &[0; 8])],
+            &[("_start", 0x8000_0000), ("tohost", 0x8000_1000)],
+        )
+    }
+
+    #[test]
+    fn outcomes() {
+        let report = run("pass", &test(1));
+        assert_eq!(report.outcome, Outcome::Passed);
+        assert_eq!(report.steps, 3);
+        assert!(report.snapshot.is_none());
+
+        // case 3 failed
+        let report = run("fail", &test(7));
+        assert_eq!(report.outcome, Outcome::Failed(3));
+        assert_eq!(report.outcome.to_string(), "failed test case 3");
+        assert_eq!(report.snapshot.unwrap().registers[6], 7);
+
+        // never reports, so it's stopped
+        let report = run("spin", &test(0));
+        assert_eq!(report.outcome, Outcome::Timeout);
+        assert_eq!(report.steps, MAX_STEPS);
+
+        let untagged = executable(0x8000_0000, &[(0x8000_0000, &[0x6f, 0, 0, 0])], &[]);
+        assert_eq!(
+            run("untagged", &untagged).outcome,
+            Outcome::Error("there's no tohost symbol".to_owned())
+        );
+        assert!(matches!(run("text", b"hello").outcome, Outcome::Error(_)));
+    }
+
+    /// The real suite, if `RISCV_TESTS` says where it's been built
+    #[cfg(feature = "host")]
+    #[test]
+    fn suite() {
+        let Some(directory) = std::env::var_os("RISCV_TESTS") else {
+            return;
+        };
+        let reports = run_directory(Path::new(&directory), "rv32ui-p-").unwrap();
+        let failed: Vec<String> = reports
+            .iter()
+            .filter(|r| !r.passed())
+            .map(|r| format!("{}: {}", r.name, r.outcome))
+            .collect();
+        assert!(failed.is_empty(), "{failed:#?}");
+    }
+}
@@ -0,0 +1,296 @@
+//! The C extension: 16-bit encodings of the most common instructions.
+//!
+//! A compressed instruction is told apart by its low two bits, which are
+//! `0b11` for every 32-bit instruction. Each one stands for a full-size
+//! instruction (`c.addi a0, 1` is `addi a0, a0, 1`), so rather than adding
+//! instructions, [Instruction::decode_compressed] expands the halfword to
+//! the 32-bit instruction it means. The CPU executes that as usual; the only
+//! difference is that the PC moves on by 2 bytes rather than 4.
+//!
+//! ```
+//! use brubeck::rv32_i::*;
+//!
+//! // c.li a0, 5
+//! let li = Instruction::decode_compressed(0x4515).unwrap();
+//! assert_eq!(li.to_string(), "addi a0, zero, 5");
+//!
+//! assert!(Instruction::is_compressed(0x4515));
+//! assert!(!Instruction::is_compressed(0x0050_0513));
+//! ```
+//!
+//! Many compressed registers are 3 bits wide and name `x8` to `x15` (`s0`,
+//! `s1`, and `a0` to `a5`), which are the registers compilers use most. The
+//! floating point loads and stores decode as errors, since there's no F or D
+//! extension, as do RV64's `c.addw` and `c.subw`.
+
+use super::*;
+use crate::bits::field as bits;
+
+impl Instruction {
+    /// Length of a compressed instruction in bytes
+    pub const COMPRESSED_LENGTH: u32 = 2;
+
+    /// Whether the instruction starting with these bits is a 16-bit
+    /// compressed one (only the low 2 bits matter)
+    pub fn is_compressed(bits: u32) -> bool {
+        bits & 0b11 != 0b11
+    }
+
+    /// The 32-bit instruction a compressed one stands for
+    pub fn decode_compressed(half: u16) -> Result<Self, DecodeError> {
+        let half = half as u32;
+        let illegal = Err(DecodeError::IllegalCompressed(half as u16));
+        let funct3 = bits(half, 15, 13);
+
+        // full-size registers, and the 3-bit ones that name x8 to x15
+        let rd = register(bits(half, 11, 7));
+        let rs2 = register(bits(half, 6, 2));
+        let rd_prime = register(bits(half, 4, 2) + 8);
+        let rs1_prime = register(bits(half, 9, 7) + 8);
+
+        // the 6-bit immediate most quadrant 1 instructions share
+        let imm6 = signed(bits(half, 12, 12) << 5 | bits(half, 6, 2), 6);
+        // loads and stores of words, relative to a register
+        let word_offset = bits(half, 12, 10) << 3 | bits(half, 6, 6) << 2 | bits(half, 5, 5) << 6;
+
+        let instruction = match (bits(half, 1, 0), funct3) {
+            // C.ADDI4SPN
+            (0b00, 0b000) => {
+                let imm = bits(half, 12, 11) << 4
+                    | bits(half, 10, 7) << 6
+                    | bits(half, 6, 6) << 2
+                    | bits(half, 5, 5) << 3;
+                if imm == 0 {
+                    return illegal;
+                }
+                Self::ADDI(i(rd_prime, Register::X2, imm as i32))
+            }
+            // C.LW and C.SW
+            (0b00, 0b010) => Self::LW(i(rd_prime, rs1_prime, word_offset as i32)),
+            (0b00, 0b110) => Self::SW(s(rs1_prime, rd_prime, word_offset as i32)),
+
+            // C.NOP and C.ADDI
+            (0b01, 0b000) if rd == Register::X0 && imm6 == 0 => Self::NOP,
+            (0b01, 0b000) => Self::ADDI(i(rd, rd, imm6)),
+            // C.JAL, C.J
+            (0b01, 0b001) => Self::JAL(j(Register::X1, jump_offset(half))),
+            (0b01, 0b101) => Self::JAL(j(Register::X0, jump_offset(half))),
+            // C.LI
+            (0b01, 0b010) => Self::ADDI(i(rd, Register::X0, imm6)),
+            // C.ADDI16SP
+            (0b01, 0b011) if rd == Register::X2 => {
+                let imm = bits(half, 12, 12) << 9
+                    | bits(half, 6, 6) << 4
+                    | bits(half, 5, 5) << 6
+                    | bits(half, 4, 3) << 7
+                    | bits(half, 2, 2) << 5;
+                if imm == 0 {
+                    return illegal;
+                }
+                Self::ADDI(i(Register::X2, Register::X2, signed(imm, 10)))
+            }
+            // C.LUI
+            (0b01, 0b011) if imm6 == 0 => return illegal,
+            (0b01, 0b011) => {
+                let mut u = UType {
+                    rd,
+                    ..UType::default()
+                };
+                u.imm
+                    .set_signed(imm6)
+                    .expect("6 bits fit in the U-immediate");
+                Self::LUI(u)
+            }
+            // the arithmetic on 3-bit registers
+            (0b01, 0b100) => {
+                let shamt = bits(half, 6, 2) as i32;
+                match bits(half, 11, 10) {
+                    // shifts of 32 or more are RV64's
+                    0b00 | 0b01 if bits(half, 12, 12) != 0 => return illegal,
+                    0b00 => Self::SRLI(i(rs1_prime, rs1_prime, shamt)),
+                    0b01 => Self::SRAI(i(rs1_prime, rs1_prime, shamt)),
+                    0b10 => Self::ANDI(i(rs1_prime, rs1_prime, imm6)),
+                    _ => {
+                        let r = r(rs1_prime, rs1_prime, rd_prime);
+                        match (bits(half, 12, 12), bits(half, 6, 5)) {
+                            (0, 0b00) => Self::SUB(r),
+                            (0, 0b01) => Self::XOR(r),
+                            (0, 0b10) => Self::OR(r),
+                            (0, _) => Self::AND(r),
+                            // C.SUBW and C.ADDW
+                            _ => return illegal,
+                        }
+                    }
+                }
+            }
+            // C.BEQZ and C.BNEZ
+            (0b01, 0b110) => Self::BEQ(b(rs1_prime, branch_offset(half))),
+            (0b01, 0b111) => Self::BNE(b(rs1_prime, branch_offset(half))),
+
+            // C.SLLI
+            (0b10, 0b000) if bits(half, 12, 12) != 0 => return illegal,
+            (0b10, 0b000) => Self::SLLI(i(rd, rd, bits(half, 6, 2) as i32)),
+            // C.LWSP, which can't load into zero
+            (0b10, 0b010) if rd == Register::X0 => return illegal,
+            (0b10, 0b010) => {
+                let offset =
+                    bits(half, 12, 12) << 5 | bits(half, 6, 4) << 2 | bits(half, 3, 2) << 6;
+                Self::LW(i(rd, Register::X2, offset as i32))
+            }
+            // C.SWSP
+            (0b10, 0b110) => {
+                let offset = bits(half, 12, 9) << 2 | bits(half, 8, 7) << 6;
+                Self::SW(s(Register::X2, rs2, offset as i32))
+            }
+            // C.JR, C.MV, C.EBREAK, C.JALR, and C.ADD
+            (0b10, 0b100) => match (bits(half, 12, 12), rd, rs2) {
+                (0, Register::X0, Register::X0) => return illegal,
+                (0, _, Register::X0) => Self::JALR(i(Register::X0, rd, 0)),
+                (0, _, _) => Self::ADD(r(rd, Register::X0, rs2)),
+                (_, Register::X0, Register::X0) => Self::EBREAK(i(Register::X0, Register::X0, 1)),
+                (_, _, Register::X0) => Self::JALR(i(Register::X1, rd, 0)),
+                _ => Self::ADD(r(rd, rd, rs2)),
+            },
+
+            // the floating point loads and stores, reserved encodings, and
+            // 32-bit instructions
+            _ => return illegal,
+        };
+
+        Ok(instruction)
+    }
+}
+
+/// The offset of C.J and C.JAL, in bytes
+fn jump_offset(half: u32) -> i32 {
+    let offset = bits(half, 12, 12) << 11
+        | bits(half, 11, 11) << 4
+        | bits(half, 10, 9) << 8
+        | bits(half, 8, 8) << 10
+        | bits(half, 7, 7) << 6
+        | bits(half, 6, 6) << 7
+        | bits(half, 5, 3) << 1
+        | bits(half, 2, 2) << 5;
+    signed(offset, 12)
+}
+
+/// The offset of C.BEQZ and C.BNEZ, in bytes
+fn branch_offset(half: u32) -> i32 {
+    let offset = bits(half, 12, 12) << 8
+        | bits(half, 11, 10) << 3
+        | bits(half, 6, 5) << 6
+        | bits(half, 4, 3) << 1
+        | bits(half, 2, 2) << 5;
+    signed(offset, 9)
+}
+
+/// Sign-extends the low `width` bits of a value
+fn signed(value: u32, width: u32) -> i32 {
+    let shift = 32 - width;
+    ((value << shift) as i32) >> shift
+}
+
+fn register(number: u32) -> Register {
+    Register::ALL[number as usize]
+}
+
+// Builders for the operands of the expanded instructions; the compressed
+// immediates are all narrower than the full-size ones
+
+fn r(rd: Register, rs1: Register, rs2: Register) -> RType {
+    RType {
+        rd,
+        rs1,
+        rs2,
+        ..RType::default()
+    }
+}
+
+fn i(rd: Register, rs1: Register, imm: i32) -> IType {
+    let mut i = IType::default();
+    (i.rd, i.rs1) = (rd, rs1);
+    i.imm.set_signed(imm).expect("fits in the I-immediate");
+    i
+}
+
+fn s(rs1: Register, rs2: Register, imm: i32) -> SType {
+    let mut s = SType::default();
+    (s.rs1, s.rs2) = (rs1, rs2);
+    s.imm.set_signed(imm).expect("fits in the S-immediate");
+    s
+}
+
+/// A branch comparing rs1 with zero
+fn b(rs1: Register, offset: i32) -> BType {
+    let mut b = BType {
+        rs1,
+        ..BType::default()
+    };
+    b.imm
+        .set_signed(offset >> 1)
+        .expect("fits in the B-immediate");
+    b
+}
+
+fn j(rd: Register, offset: i32) -> JType {
+    let mut j = JType {
+        rd,
+        ..JType::default()
+    };
+    j.imm
+        .set_signed(offset >> 1)
+        .expect("fits in the J-immediate");
+    j
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansions() {
+        // halfwords from the LLVM assembler, and what they expand to
+        for (half, expanded) in [
+            (0x0048, "addi a0, sp, 4"),     // c.addi4spn a0, sp, 4
+            (0x40c8, "lw a0, s1, 4"),       // c.lw a0, 4(s1)
+            (0xc0c8, "sw s1, a0, 4"),       // c.sw a0, 4(s1)
+            (0x0001, "nop"),                // c.nop
+            (0x157d, "addi a0, a0, 4095"),  // c.addi a0, -1
+            (0x2011, "jal ra, 2"),          // c.jal 4
+            (0xbff5, "jal zero, 1048574"),  // c.j -4
+            (0x4515, "addi a0, zero, 5"),   // c.li a0, 5
+            (0x7179, "addi sp, sp, 4048"),  // c.addi16sp sp, -48
+            (0x6505, "lui a0, 1"),          // c.lui a0, 1
+            (0x757d, "lui a0, 1048575"),    // c.lui a0, 0xfffff
+            (0x8105, "srli a0, a0, 1"),     // c.srli a0, 1
+            (0x8505, "srai a0, a0, 1"),     // c.srai a0, 1
+            (0x897d, "andi a0, a0, 31"),    // c.andi a0, 31
+            (0x8d0d, "sub a0, a0, a1"),     // c.sub a0, a1
+            (0x8d2d, "xor a0, a0, a1"),     // c.xor a0, a1
+            (0x8d4d, "or a0, a0, a1"),      // c.or a0, a1
+            (0x8d6d, "and a0, a0, a1"),     // c.and a0, a1
+            (0xdd75, "beq a0, zero, 4094"), // c.beqz a0, -4
+            (0xe111, "bne a0, zero, 2"),    // c.bnez a0, 4
+            (0x050a, "slli a0, a0, 2"),     // c.slli a0, 2
+            (0x4532, "lw a0, sp, 12"),      // c.lwsp a0, 12(sp)
+            (0xc62a, "sw sp, a0, 12"),      // c.swsp a0, 12(sp)
+            (0x8082, "jalr zero, ra, 0"),   // c.jr ra (ret)
+            (0x852e, "add a0, zero, a1"),   // c.mv a0, a1
+            (0x9002, "ebreak"),             // c.ebreak
+            (0x9502, "jalr ra, a0, 0"),     // c.jalr a0
+            (0x952e, "add a0, a0, a1"),     // c.add a0, a1
+        ] {
+            let instruction = Instruction::decode_compressed(half).unwrap();
+            assert_eq!(instruction.to_string(), expanded, "0x{half:04x}");
+        }
+
+        // all zeros, C.FLW, C.LWSP into zero, C.JR zero, and C.SUBW
+        for half in [0x0000, 0x60c8, 0x4002, 0x8002, 0x9d0d] {
+            assert_eq!(
+                Instruction::decode_compressed(half),
+                Err(DecodeError::IllegalCompressed(half)),
+                "0x{half:04x}"
+            );
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! The performance counters behind the `cycle`, `time`, and `instret` CSRs.
+//!
+//! Every instruction that completes ("retires") adds one to `instret`, and
+//! the [CycleModel] says how many cycles it took. The default model is one
+//! cycle for everything, so `cycle` and `instret` agree; a model with extra
+//! cycles for memory, multiplies, or taken branches shows why some programs
+//! are slower than their instruction count suggests.
+//!
+//! Each entry in the [history](crate::history) keeps what its instruction
+//! added, so undoing it (or seeking past it, or rolling back a transaction)
+//! takes the counts back too. The `time` CSR reads the CLINT's `mtime` when
+//! there is one, and that keeps counting like a wall clock: undo doesn't turn
+//! it back.
+//!
+//! ```
+//! use brubeck::rv32_i::{CpuConfig, CycleModel, Instruction, CPU};
+//!
+//! let mut cpu = CPU::with_config(CpuConfig {
+//!     cycles: CycleModel {
+//!         memory: 2,
+//!         ..CycleModel::default()
+//!     },
+//!     ..CpuConfig::default()
+//! });
+//!
+//! cpu.execute(Instruction::NOP).unwrap();
+//! cpu.execute(Instruction::LW(Default::default())).unwrap();
+//!
+//! assert_eq!(cpu.counters.instret, 2);
+//! assert_eq!(cpu.counters.cycles, 4);
+//! ```
+
+use std::ops::{AddAssign, SubAssign};
+
+use super::Instruction;
+
+/// Counts of cycles and retired instructions
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub cycles: u64,
+    pub instret: u64,
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other: Self) {
+        self.cycles = self.cycles.wrapping_add(other.cycles);
+        self.instret = self.instret.wrapping_add(other.instret);
+    }
+}
+
+impl SubAssign for Counters {
+    fn sub_assign(&mut self, other: Self) {
+        self.cycles = self.cycles.wrapping_sub(other.cycles);
+        self.instret = self.instret.wrapping_sub(other.instret);
+    }
+}
+
+/// How many cycles each instruction takes: `base`, plus extra cycles for
+/// the kinds of instruction that are slower on a simple pipeline
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CycleModel {
+    /// Cycles for every instruction
+    pub base: u64,
+    /// Extra cycles for loads, stores, and atomic memory operations
+    pub memory: u64,
+    /// Extra cycles for `MUL`, `MULH`, `MULHSU`, and `MULHU`
+    pub multiply: u64,
+    /// Extra cycles for `DIV`, `DIVU`, `REM`, and `REMU`
+    pub divide: u64,
+    /// Extra cycles for jumps, taken branches, and traps, which flush the
+    /// pipeline
+    pub taken: u64,
+}
+
+impl Default for CycleModel {
+    /// One cycle per instruction
+    fn default() -> Self {
+        Self {
+            base: 1,
+            memory: 0,
+            multiply: 0,
+            divide: 0,
+            taken: 0,
+        }
+    }
+}
+
+impl CycleModel {
+    /// Cycles for an instruction; `taken` is whether it changed the flow of
+    /// control, rather than going on to the next instruction
+    pub fn cycles(&self, instruction: Instruction, taken: bool) -> u64 {
+        use Instruction::*;
+
+        let extra = match instruction {
+            LB(_) | LBU(_) | LH(_) | LHU(_) | LW(_) | SB(_) | SH(_) | SW(_) => self.memory,
+            LD(_) | LWU(_) | SD(_) => self.memory,
+            _ if instruction.is_atomic() => self.memory,
+            MUL(_) | MULH(_) | MULHSU(_) | MULHU(_) => self.multiply,
+            DIV(_) | DIVU(_) | REM(_) | REMU(_) => self.divide,
+            _ if taken => self.taken,
+            _ => 0,
+        };
+        self.base + extra
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{BType, RType};
+
+    #[test]
+    fn cycle_model() {
+        let model = CycleModel {
+            base: 1,
+            memory: 1,
+            multiply: 2,
+            divide: 30,
+            taken: 2,
+        };
+        let beq = Instruction::BEQ(BType::default());
+
+        assert_eq!(model.cycles(Instruction::NOP, false), 1);
+        assert_eq!(model.cycles(Instruction::SW(Default::default()), false), 2);
+        assert_eq!(model.cycles(Instruction::MULHU(RType::default()), false), 3);
+        assert_eq!(model.cycles(Instruction::REM(RType::default()), false), 31);
+        assert_eq!(model.cycles(beq, false), 1);
+        assert_eq!(model.cycles(beq, true), 3);
+
+        let mut counters = Counters::default();
+        counters += Counters {
+            cycles: 3,
+            instret: 1,
+        };
+        counters -= Counters {
+            cycles: 1,
+            instret: 1,
+        };
+        assert_eq!(
+            counters,
+            Counters {
+                cycles: 2,
+                instret: 0
+            }
+        );
+    }
+}
@@ -7,10 +7,52 @@
 //! stored in memory as `[0xab, 0x12]`.
 
 use super::*;
+use crate::devices::{self, Bus, Clint};
+use crate::expression::Environment;
+use crate::semihosting::{self, Semihosting};
+use crate::syscalls::{self, SyscallHandler, Syscalls};
 
 #[derive(Debug, Clone)]
 pub struct CPU {
-    pub memory: Vec<u8>,
+    pub config: CpuConfig,
+    pub memory: Memory,
+    /// Where guest addresses are found in `memory`
+    pub address_map: AddressMap,
+    /// Memory-mapped devices; these take priority over `memory`
+    pub devices: Bus,
+    /// Handles semihosting calls (`EBREAK` in the magic sequence) when set
+    pub semihosting: Option<Semihosting>,
+    /// Performs system calls (`ECALL` with [EcallPolicy::HostHandle]) when
+    /// set; see [syscalls]
+    pub syscalls: Option<Box<dyn SyscallHandler>>,
+    /// Set when the guest program exits, whether by `ECALL`, semihosting, or
+    /// a write to a device like [ToHost](crate::devices::ToHost)
+    pub exit_code: Option<u32>,
+    /// The mode the CPU is running in; see [privilege]
+    pub privilege: Privilege,
+    /// Cycles and retired instructions, as read by the `cycle` and `instret`
+    /// CSRs; see [counters]
+    pub counters: Counters,
+    /// Memory stores made by the most recently executed instruction
+    writes: Vec<MemoryWrite>,
+    /// CSRs changed by the most recently executed instruction
+    csr_writes: Vec<CsrChange>,
+    /// The mode the most recently executed instruction started in
+    started_in: Privilege,
+    /// What the most recently executed instruction added to the counters
+    counted: Counters,
+    /// The CSRs, and the values of those that hold one; see
+    /// [CPU::read_csr]
+    pub csrs: CsrFile,
+    /// Cause of the trap taken by the most recently executed instruction
+    last_trap: Option<u32>,
+    /// The address reserved by the last `LR.W`, until an `SC.W` or a trap
+    reservation: Option<u32>,
+    /// Length of the instruction being executed: 4, or 2 if it was
+    /// compressed
+    length: u32,
+    /// The top halves of the registers, in [RV64](super::rv64) mode
+    upper: [u32; 32],
     pub x0: u32,
     pub x1: u32,
     pub x2: u32,
@@ -49,21 +91,57 @@ pub struct CPU {
 impl Default for CPU {
     /// Initializes the [CPU] with 1 mebibyte (2^20) of memory
     fn default() -> Self {
-        Self::new(2usize.pow(20)) // default 1 mebibyte
+        Self::with_config(CpuConfig::default())
     }
 }
 
 impl CPU {
+    /// The `exit` system call number (in `a7`) for `ECALL`, following the
+    /// Linux convention used by newlib and most teaching simulators.
+    pub const SYSCALL_EXIT: u32 = syscalls::EXIT;
+
     /// Creates a single hardware thread ("hart") CPU implementing the RV32I
     /// instruction set. Memory size is counted in bytes; `default()` will
     /// initialize with 1 mebibyte.
     pub fn new(memory_size: usize) -> Self {
+        Self::with_config(CpuConfig {
+            memory_size,
+            ..CpuConfig::default()
+        })
+    }
+
+    /// Creates a CPU with memory placed at `config.memory_base` and the PC
+    /// at `config.reset_vector`.
+    pub fn with_config(config: CpuConfig) -> Self {
+        let mut memory = vec![0; config.memory_size];
+        config.fill.fill(&mut memory, 0);
+        let sp = config.initial_sp();
+        let gp = config.global_pointer.unwrap_or(0);
+
         Self {
-            memory: vec![0; memory_size],
+            memory: Memory::from(memory),
+            address_map: AddressMap::new(config.memory_base, config.memory_size),
+            pc: config.reset_vector,
+            config,
+            devices: Bus::default(),
+            semihosting: None,
+            syscalls: Some(Box::new(Syscalls::default())),
+            exit_code: None,
+            privilege: Privilege::Machine,
+            counters: Counters::default(),
+            writes: vec![],
+            csr_writes: vec![],
+            started_in: Privilege::Machine,
+            counted: Counters::default(),
+            csrs: CsrFile::default(),
+            last_trap: None,
+            reservation: None,
+            length: Instruction::LENGTH,
+            upper: [0; 32],
             x0: 0,
             x1: 0,
-            x2: 0,
-            x3: 0,
+            x2: sp,
+            x3: gp,
             x4: 0,
             x5: 0,
             x6: 0,
@@ -92,10 +170,42 @@ impl CPU {
             x29: 0,
             x30: 0,
             x31: 0,
-            pc: 0,
         }
     }
 
+    /// Adds `size` bytes of memory at guest address `base`, eg: for a program
+    /// segment linked far from the rest of memory. See [AddressMap].
+    pub fn map_segment(&mut self, base: u32, size: usize) -> Result<(), MapError> {
+        let offset = self.memory.len();
+        self.address_map.map(base, size, offset)?;
+        self.memory.resize(offset + size, 0);
+        self.config.fill.fill(&mut self.memory[offset..], offset);
+        Ok(())
+    }
+
+    /// Returns to the initial state: registers and counters are zeroed
+    /// (except `sp` and `gp`, which get their configured values), memory is
+    /// filled with the configured [FillPattern], and the PC is set to the
+    /// reset vector. Devices keep their state.
+    pub fn reset(&mut self) {
+        for register in Register::ALL {
+            self.set_register(register, 0);
+        }
+        self.set_register(Register::X2, self.config.initial_sp());
+        self.set_register(Register::X3, self.config.global_pointer.unwrap_or(0));
+        self.pc = self.config.reset_vector;
+        self.upper = [0; 32];
+        self.config.fill.fill(&mut self.memory, 0);
+        self.exit_code = None;
+        self.privilege = Privilege::Machine;
+        self.counters = Counters::default();
+        self.writes.clear();
+        self.counted = Counters::default();
+        self.csrs.reset();
+        self.last_trap = None;
+        self.reservation = None;
+    }
+
     /// Gets the value for a given register.
     ///
     /// `Register::X0` will always remain zero
@@ -137,10 +247,14 @@ impl CPU {
         }
     }
 
-    /// Sets a given register to the provided value.
+    /// Sets a given register to the provided value. In RV64 mode, the top
+    /// half of the register is cleared.
     ///
     /// `Register::X0` will always remain zero
     pub fn set_register(&mut self, r: Register, v: u32) {
+        if r != Register::PC {
+            self.upper[r as usize] = 0;
+        }
         match r {
             Register::X0 => self.x0 = 0,
             Register::X1 => self.x1 = v,
@@ -178,6 +292,26 @@ impl CPU {
         }
     }
 
+    /// Gets the whole of a register: the same as
+    /// [get_register](Self::get_register) in RV32, and 64 bits wide in
+    /// [RV64](super::rv64)
+    pub fn get_x(&self, r: Register) -> u64 {
+        let low = u64::from(self.get_register(r));
+        match r {
+            Register::PC => low,
+            _ => u64::from(self.upper[r as usize]) << 32 | low,
+        }
+    }
+
+    /// Sets the whole of a register; in RV32 (and for the PC), only the low
+    /// 32 bits are kept
+    pub fn set_x(&mut self, r: Register, v: u64) {
+        self.set_register(r, v as u32);
+        if r != Register::X0 && r != Register::PC && self.config.xlen == Xlen::Rv64 {
+            self.upper[r as usize] = (v >> 32) as u32;
+        }
+    }
+
     /// Gets the content of a register by it's ABI name
     pub fn get_abi(&self, abi: ABI) -> u32 {
         self.get_register(abi.to_register())
@@ -204,9 +338,97 @@ impl CPU {
     /// assert_eq!(cpu.pc, Instruction::LENGTH);
     /// ```
     pub fn execute(&mut self, instruction: Instruction) -> Result<(), Error> {
+        self.execute_with_length(instruction, Instruction::LENGTH)
+    }
+
+    /// Executes an instruction that takes up `length` bytes: 2 for one that
+    /// was [compressed](Instruction::decode_compressed), so the PC moves on
+    /// (and JAL and JALR link) to 2 bytes after it
+    pub fn execute_with_length(
+        &mut self,
+        instruction: Instruction,
+        length: u32,
+    ) -> Result<(), Error> {
+        self.length = length;
+        self.writes.clear();
+        self.csr_writes.clear();
+        self.started_in = self.privilege;
+        self.counted = Counters::default();
+        self.last_trap = None;
+        let pc = self.pc;
+
+        if instruction.is_multiply() && !self.config.extensions.m
+            || instruction.is_atomic() && !self.config.extensions.a
+        {
+            return Err(Error::NotImplemented(instruction));
+        }
+        self.check_shift_amount(instruction)?;
+
+        match self.config.xlen {
+            Xlen::Rv32 => self.execute_rv32(instruction),
+            Xlen::Rv64 => self.execute_rv64(instruction),
+        }?;
+
+        self.counted = Counters {
+            cycles: self
+                .config
+                .cycles
+                .cycles(instruction, self.pc != pc.wrapping_add(length)),
+            // an instruction that traps doesn't retire
+            instret: u64::from(self.last_trap.is_none()),
+        };
+        self.counters += self.counted;
+        self.devices.tick();
+
+        if self.exit_code.is_none() {
+            self.exit_code = self.devices.exit_code();
+        }
+
+        Ok(())
+    }
+
+    /// Shift immediates have a sixth bit, `shamt[5]`, that only RV64 uses: a
+    /// shift by more than 31 is an illegal instruction in RV32, as it is for
+    /// the W forms, and by more than 63 in RV64
+    fn check_shift_amount(&self, instruction: Instruction) -> Result<(), Error> {
+        let limit = match (self.config.xlen, instruction) {
+            (Xlen::Rv32, Instruction::SLLI(_) | Instruction::SRLI(_) | Instruction::SRAI(_))
+            | (_, Instruction::SLLIW(_) | Instruction::SRLIW(_) | Instruction::SRAIW(_)) => 31,
+            (Xlen::Rv64, Instruction::SLLI(_) | Instruction::SRLI(_) | Instruction::SRAI(_)) => 63,
+            _ => return Ok(()),
+        };
+        match instruction.operands() {
+            Operands::IType(i) if i.imm.as_u32() > limit => {
+                let word = instruction.encode();
+                Err(Error::IllegalInstruction(DecodeError::UnknownFunction {
+                    word,
+                    funct3: (word >> 12) & 0b111,
+                    funct7: word >> 25,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Executes an instruction with 32-bit registers; the RV64 instructions
+    /// aren't implemented
+    pub(super) fn execute_rv32(&mut self, instruction: Instruction) -> Result<(), Error> {
         match instruction {
             Instruction::ADD(i) => self.rv32i_add(i),
             Instruction::ADDI(i) => self.rv32i_addi(i),
+            Instruction::AMOADD_W(i) => self.rv32a_amo(Instruction::AMOADD_W(i), u32::wrapping_add),
+            Instruction::AMOAND_W(i) => self.rv32a_amo(Instruction::AMOAND_W(i), |a, b| a & b),
+            Instruction::AMOMAX_W(i) => self.rv32a_amo(Instruction::AMOMAX_W(i), |a, b| {
+                (a as i32).max(b as i32) as u32
+            }),
+            Instruction::AMOMAXU_W(i) => self.rv32a_amo(Instruction::AMOMAXU_W(i), u32::max),
+            Instruction::AMOMIN_W(i) => self.rv32a_amo(Instruction::AMOMIN_W(i), |a, b| {
+                (a as i32).min(b as i32) as u32
+            }),
+            Instruction::AMOMINU_W(i) => self.rv32a_amo(Instruction::AMOMINU_W(i), u32::min),
+            Instruction::AMOOR_W(i) => self.rv32a_amo(Instruction::AMOOR_W(i), |a, b| a | b),
+            Instruction::AMOSWAP_W(i) => self.rv32a_amo(Instruction::AMOSWAP_W(i), |_, b| b),
+            Instruction::AMOXOR_W(i) => self.rv32a_amo(Instruction::AMOXOR_W(i), |a, b| a ^ b),
             Instruction::AND(i) => self.rv32i_and(i),
             Instruction::ANDI(i) => self.rv32i_andi(i),
             Instruction::AUIPC(i) => self.rv32i_auipc(i),
@@ -216,18 +438,38 @@ impl CPU {
             Instruction::BLT(i) => self.rv32i_blt(i),
             Instruction::BLTU(i) => self.rv32i_bltu(i),
             Instruction::BNE(i) => self.rv32i_bne(i),
+            Instruction::CSRRC(i) => self.zicsr_csrrc(i),
+            Instruction::CSRRCI(i) => self.zicsr_csrrci(i),
+            Instruction::CSRRS(i) => self.zicsr_csrrs(i),
+            Instruction::CSRRSI(i) => self.zicsr_csrrsi(i),
+            Instruction::CSRRW(i) => self.zicsr_csrrw(i),
+            Instruction::CSRRWI(i) => self.zicsr_csrrwi(i),
+            Instruction::DIV(i) => self.rv32m_div(i),
+            Instruction::DIVU(i) => self.rv32m_divu(i),
+            Instruction::EBREAK(i) => self.rv32i_ebreak(i),
+            Instruction::ECALL(i) => self.rv32i_ecall(i),
+            Instruction::FENCE_I => self.zifencei_fence_i(),
             Instruction::JAL(i) => self.rv32i_jal(i),
             Instruction::JALR(i) => self.rv32i_jalr(i),
             Instruction::LB(i) => self.rv32i_lb(i),
             Instruction::LBU(i) => self.rv32i_lbu(i),
             Instruction::LH(i) => self.rv32i_lh(i),
             Instruction::LHU(i) => self.rv32i_lhu(i),
+            Instruction::LR_W(i) => self.rv32a_lr_w(i),
             Instruction::LUI(i) => self.rv32i_lui(i),
             Instruction::LW(i) => self.rv32i_lw(i),
+            Instruction::MRET => self.privileged_mret(),
+            Instruction::MUL(i) => self.rv32m_mul(i),
+            Instruction::MULH(i) => self.rv32m_mulh(i),
+            Instruction::MULHSU(i) => self.rv32m_mulhsu(i),
+            Instruction::MULHU(i) => self.rv32m_mulhu(i),
             Instruction::NOP => self.rv32i_nop(),
             Instruction::OR(i) => self.rv32i_or(i),
             Instruction::ORI(i) => self.rv32i_ori(i),
+            Instruction::REM(i) => self.rv32m_rem(i),
+            Instruction::REMU(i) => self.rv32m_remu(i),
             Instruction::SB(i) => self.rv32i_sb(i),
+            Instruction::SC_W(i) => self.rv32a_sc_w(i),
             Instruction::SH(i) => self.rv32i_sh(i),
             Instruction::SLL(i) => self.rv32i_sll(i),
             Instruction::SLLI(i) => self.rv32i_slli(i),
@@ -241,22 +483,242 @@ impl CPU {
             Instruction::SRLI(i) => self.rv32i_srli(i),
             Instruction::SUB(i) => self.rv32i_sub(i),
             Instruction::SW(i) => self.rv32i_sw(i),
+            Instruction::WFI => self.privileged_wfi(),
             Instruction::XOR(i) => self.rv32i_xor(i),
             Instruction::XORI(i) => self.rv32i_xori(i),
             e => Err(Error::NotImplemented(e)),
-        }?;
+        }
+    }
 
+    /// Reads and decodes the instruction at the PC, from memory (devices
+    /// don't hold code). A compressed instruction comes back expanded; see
+    /// [fetch_with_length](Self::fetch_with_length).
+    pub fn fetch(&self) -> Result<Instruction, Error> {
+        self.fetch_with_length().map(|(instruction, _)| instruction)
+    }
+
+    /// Like [fetch](Self::fetch), along with how many bytes the instruction
+    /// took up: 2 for a compressed instruction when the C extension is
+    /// enabled, and 4 otherwise
+    pub fn fetch_with_length(&self) -> Result<(Instruction, u32), Error> {
+        if !self
+            .pc
+            .is_multiple_of(self.config.extensions.instruction_alignment())
+        {
+            return Err(Error::MisalignedJump(self.pc));
+        }
+
+        // a compressed instruction may be the last halfword of memory
+        let half = self.read_mem_u16(self.pc)?;
+        // there's no RV64C here
+        let c = self.config.extensions.c && self.config.xlen == Xlen::Rv32;
+        if c && Instruction::is_compressed(half as u32) {
+            return Instruction::decode_compressed(half)
+                .map(|instruction| (instruction, Instruction::COMPRESSED_LENGTH))
+                .map_err(Error::IllegalInstruction);
+        }
+
+        let word = self.read_mem_u32(self.pc)?;
+        Instruction::decode(word)
+            .map(|instruction| (instruction, Instruction::LENGTH))
+            .map_err(Error::IllegalInstruction)
+    }
+
+    /// The interrupt that would be taken before the next instruction, if
+    /// any: one that's pending in `mip`, enabled in `mie`, with interrupts
+    /// enabled globally by `mstatus.MIE` (or running in user mode). External interrupts come first,
+    /// then software, then timer, as in the privileged spec.
+    pub fn pending_interrupt(&self) -> Option<TrapCause> {
+        // machine interrupts are always enabled in user mode
+        let enabled = self.csrs[csr::MSTATUS] & csr::MSTATUS_MIE != 0;
+        if self.privilege == Privilege::Machine && !enabled {
+            return None;
+        }
+
+        let pending = self.devices.interrupts() & self.csrs[csr::MIE];
+        [
+            (devices::MEIP, TrapCause::MachineExternalInterrupt),
+            (devices::MSIP, TrapCause::MachineSoftwareInterrupt),
+            (devices::MTIP, TrapCause::MachineTimerInterrupt),
+        ]
+        .into_iter()
+        .find_map(|(bit, cause)| (pending & bit != 0).then_some(cause))
+    }
+
+    /// Takes the [pending interrupt](Self::pending_interrupt), if there is
+    /// one, so the next instruction is the first of its handler. Returns
+    /// the cause.
+    pub fn take_interrupt(&mut self) -> Option<TrapCause> {
+        let cause = self.pending_interrupt()?;
+        self.writes.clear();
+        self.csr_writes.clear();
+        self.started_in = self.privilege;
+        self.counted = Counters::default();
+        self.trap(cause, 0);
+        Some(cause)
+    }
+
+    /// Takes any pending interrupt, then fetches the instruction at the PC
+    /// and executes it, returning the instruction
+    ///
+    /// ```
+    /// use brubeck::rv32_i::*;
+    ///
+    /// let mut cpu = CPU::default();
+    /// // ADDI a0, zero, 5
+    /// cpu.write_memory(0, &0x0050_0513u32.to_le_bytes()).unwrap();
+    ///
+    /// assert!(matches!(cpu.step(), Ok(Instruction::ADDI(_))));
+    /// assert_eq!(cpu.x10, 5);
+    /// assert_eq!(cpu.pc, 4);
+    /// ```
+    pub fn step(&mut self) -> Result<Instruction, Error> {
+        self.take_interrupt();
+        let (instruction, length) = self.fetch_with_length()?;
+        self.execute_with_length(instruction, length)?;
+        Ok(instruction)
+    }
+
+    /// Steps until the program exits or `max_steps` instructions have run
+    /// (the "fuel", so a program that never exits still returns), and
+    /// returns how many ran. Check [exit_code](Self::exit_code) to tell the
+    /// two apart. Stops at the first instruction that fails, leaving the PC
+    /// on it.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize, Error> {
+        let mut steps = 0;
+        while steps < max_steps && self.exit_code.is_none() {
+            self.step()?;
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Reads a CSR. Fails for CSRs that aren't in the [CsrFile], or that
+    /// belong to an extension that isn't enabled.
+    pub fn read_csr(&self, address: u32) -> Result<u32, Error> {
+        if self.csrs.get(address).is_none()
+            || (csr::is_counter(address) && !self.config.extensions.zicntr)
+        {
+            return Err(Error::IllegalCsr(address));
+        }
+
+        let value = match address {
+            csr::MISA => self.config.extensions.misa(),
+            // pending interrupts come straight from the devices
+            csr::MIP => self.devices.interrupts(),
+            csr::CYCLE => self.counters.cycles as u32,
+            csr::CYCLEH => (self.counters.cycles >> 32) as u32,
+            csr::TIME => self.time() as u32,
+            csr::TIMEH => (self.time() >> 32) as u32,
+            csr::INSTRET => self.counters.instret as u32,
+            csr::INSTRETH => (self.counters.instret >> 32) as u32,
+            // a single hart, with no vendor, architecture, or implementation
+            csr::MVENDORID | csr::MARCHID | csr::MIMPID | csr::MHARTID => 0,
+            _ => self.csrs[address],
+        };
+
+        Ok(value)
+    }
+
+    /// Writes a CSR. Fields that can't be changed (like the extensions in
+    /// `misa`) keep their values, as the spec allows (see [csr::Warl]);
+    /// writing a read-only CSR fails.
+    pub fn write_csr(&mut self, address: u32, value: u32) -> Result<(), Error> {
+        // checks the CSR exists
+        self.read_csr(address)?;
+
+        if csr::is_read_only(address) {
+            return Err(Error::IllegalCsr(address));
+        }
+
+        let old = self.csrs[address];
+        self.csrs.write(address, value);
+        self.journal_csr(address, old);
         Ok(())
     }
 
+    /// Sets a CSR's stored value as part of the current instruction, so
+    /// it's recorded in [last_csr_writes](Self::last_csr_writes)
+    fn store_csr(&mut self, address: u32, value: u32) {
+        let old = self.csrs[address];
+        self.csrs[address] = value;
+        self.journal_csr(address, old);
+    }
+
+    /// Records a CSR that held `old`, if it's changed; one that's written
+    /// twice is recorded once
+    fn journal_csr(&mut self, address: u32, old: u32) {
+        let new = self.csrs[address];
+        match self.csr_writes.iter_mut().find(|c| c.address == address) {
+            Some(change) => change.new = new,
+            None if old != new => self.csr_writes.push(CsrChange { address, old, new }),
+            None => {}
+        }
+    }
+
+    /// The wall clock behind the `time` CSR: the CLINT's `mtime` if there is
+    /// one, or else the cycle count
+    pub fn time(&self) -> u64 {
+        match self.devices.get::<Clint>() {
+            Some(clint) => clint.mtime,
+            None => self.counters.cycles,
+        }
+    }
+
+    /// What the most recently executed instruction added to the
+    /// [counters](Self::counters): nothing if it failed, or if an interrupt
+    /// was taken since
+    pub fn last_counted(&self) -> Counters {
+        self.counted
+    }
+
+    /// Memory stores made by the most recently executed instruction. Stores
+    /// to devices aren't included.
+    pub fn last_writes(&self) -> &[MemoryWrite] {
+        &self.writes
+    }
+
+    /// CSRs the most recently executed instruction (or interrupt) changed,
+    /// including those a trap or `MRET` saves and restores
+    pub fn last_csr_writes(&self) -> &[CsrChange] {
+        &self.csr_writes
+    }
+
+    /// The mode before and after the most recently executed instruction (or
+    /// interrupt), if it changed it
+    pub fn last_privilege_change(&self) -> Option<(Privilege, Privilege)> {
+        (self.started_in != self.privilege).then_some((self.started_in, self.privilege))
+    }
+
+    /// Takes the output guest programs have written with system calls
+    pub fn take_syscall_output(&mut self) -> Vec<u8> {
+        match self.syscalls.as_mut() {
+            Some(handler) => handler.take_output(),
+            None => vec![],
+        }
+    }
+
+    /// Queues input for guest programs to read with system calls
+    pub fn push_syscall_input(&mut self, input: &[u8]) {
+        if let Some(handler) = self.syscalls.as_mut() {
+            handler.push_input(input);
+        }
+    }
+
+    /// The `mcause` of the trap taken by the most recently executed
+    /// instruction, if it trapped
+    pub fn last_trap(&self) -> Option<u32> {
+        self.last_trap
+    }
+
     /*
      *  All functions below are either instructions or helper functions for execution.
      *
      *  Naming follows the convention isa_instruction (eg: rv32i_nop)
      */
 
-    fn increment_pc(&mut self) -> Result<(), Error> {
-        self.pc += Instruction::LENGTH;
+    pub(super) fn increment_pc(&mut self) -> Result<(), Error> {
+        self.pc = self.pc.wrapping_add(self.length);
         Ok(())
     }
 
@@ -264,6 +726,45 @@ impl CPU {
         self.increment_pc()
     }
 
+    /// FENCE.I makes stores to memory visible to the instruction fetches
+    /// after it, for code that writes code. Instructions are always fetched
+    /// straight from memory here, so there's nothing to synchronize.
+    fn zifencei_fence_i(&mut self) -> Result<(), Error> {
+        self.increment_pc()
+    }
+
+    /// The EBREAK instruction is used to return control to a debugging
+    /// environment. When [semihosting] is enabled and the EBREAK sits in the
+    /// magic semihosting sequence, the host performs the operation in `a0`
+    /// and places the result back in `a0`.
+    fn rv32i_ebreak(&mut self, _instruction: IType) -> Result<(), Error> {
+        let entry = self
+            .read_mem_u32(self.pc.wrapping_sub(Instruction::LENGTH))
+            .ok();
+        let exit = self
+            .read_mem_u32(self.pc.wrapping_add(Instruction::LENGTH))
+            .ok();
+        let magic = entry == Some(semihosting::ENTRY) && exit == Some(semihosting::EXIT);
+
+        match self.semihosting.take() {
+            Some(mut sh) if magic => {
+                // taken out while it runs, since it needs the rest of the CPU
+                let (op, arg) = (self.x10, self.x11);
+                self.x10 = sh.call(self, op, arg);
+                // taken, so it doesn't exit again after an undo
+                if let Some(code) = sh.exit_code.take() {
+                    self.exit_code = Some(code);
+                }
+                self.semihosting = Some(sh);
+                self.increment_pc()
+            }
+            sh => {
+                self.semihosting = sh;
+                Err(Error::Breakpoint(self.pc))
+            }
+        }
+    }
+
     /// ADD and SUB perform addition and subtraction respectively. Overflows
     /// are ignored and the low XLEN bits of results are written to the
     /// destination.
@@ -382,8 +883,7 @@ impl CPU {
     fn rv32i_auipc(&mut self, instruction: UType) -> Result<(), Error> {
         let mut imm = instruction.imm.as_u32();
         imm <<= 12;
-        let pc = self.pc;
-        let value = imm + pc;
+        let value = self.pc.wrapping_add(imm);
         self.set_register(instruction.rd, value);
 
         self.increment_pc()
@@ -564,21 +1064,16 @@ impl CPU {
     /// Plain unconditional jumps (assembler pseudo-op J) are encoded as a JAL
     /// with rd=x0.
     fn rv32i_jal(&mut self, instruction: JType) -> Result<(), Error> {
-        let mut offset = instruction.imm.as_u32();
-
-        // shift left one bit; multiply by 2
-        offset <<= 1;
-
-        // create the offset address
-        let offset_address = self.pc.wrapping_add(offset);
+        let offset_address = instruction.target(self.pc);
 
-        // validate the offset address is 32-bit aligned
-        if offset_address % 4 != 0 {
+        // validate the offset address is 32-bit aligned (16-bit with
+        // compressed instructions)
+        if !offset_address.is_multiple_of(self.config.extensions.instruction_alignment()) {
             return Err(Error::MisalignedJump(offset_address));
         }
 
         // set the return address
-        let return_address = self.pc.wrapping_add(Instruction::LENGTH);
+        let return_address = self.pc.wrapping_add(self.length);
 
         self.set_register(Register::PC, offset_address);
         self.set_register(instruction.rd, return_address);
@@ -603,12 +1098,13 @@ impl CPU {
         offset_address >>= 1;
         offset_address <<= 1;
 
-        // validate the offset address is 32-bit aligned
-        if offset_address % 4 != 0 {
+        // validate the offset address is 32-bit aligned (16-bit with
+        // compressed instructions)
+        if !offset_address.is_multiple_of(self.config.extensions.instruction_alignment()) {
             return Err(Error::MisalignedJump(offset_address));
         }
 
-        let return_address = self.pc.wrapping_add(Instruction::LENGTH);
+        let return_address = self.pc.wrapping_add(self.length);
 
         self.set_register(Register::PC, offset_address);
         self.set_register(instruction.rd, return_address);
@@ -628,14 +1124,10 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2);
 
         if rs1 == rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
-
-        Ok(())
     }
 
     fn rv32i_bne(&mut self, instruction: BType) -> Result<(), Error> {
@@ -643,13 +1135,22 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2);
 
         if rs1 != rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
+    }
 
+    /// Moves the PC to the branch target, which must be word aligned (or
+    /// halfword aligned with compressed instructions)
+    pub(super) fn branch(&mut self, instruction: BType) -> Result<(), Error> {
+        let target = instruction.target(self.pc);
+
+        if !target.is_multiple_of(self.config.extensions.instruction_alignment()) {
+            return Err(Error::MisalignedJump(target));
+        }
+
+        self.pc = target;
         Ok(())
     }
 
@@ -660,14 +1161,10 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2) as i32;
 
         if rs1 < rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
-
-        Ok(())
     }
 
     fn rv32i_bltu(&mut self, instruction: BType) -> Result<(), Error> {
@@ -675,14 +1172,10 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2);
 
         if rs1 < rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
-
-        Ok(())
     }
 
     ///  BGE and BGEU take the branch if rs1 is greater than or equal to rs2,
@@ -692,14 +1185,10 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2) as i32;
 
         if rs1 >= rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
-
-        Ok(())
     }
 
     fn rv32i_bgeu(&mut self, instruction: BType) -> Result<(), Error> {
@@ -707,14 +1196,10 @@ impl CPU {
         let rs2 = self.get_register(instruction.rs2);
 
         if rs1 >= rs2 {
-            let mut offset = instruction.imm.as_u32();
-            offset <<= 1; // multiple of 2
-            self.pc = self.pc.wrapping_add(offset);
+            self.branch(instruction)
         } else {
-            self.pc += Instruction::LENGTH;
+            self.increment_pc()
         }
-
-        Ok(())
     }
 
     /// Load and store instructions transfer a value between the registers and
@@ -724,21 +1209,15 @@ impl CPU {
     /// rd. Stores copy the value in register rs2 to memory
     ///
     /// The LW instruction loads a 32-bit value from memory into rd.
-
     fn rv32i_lw(&mut self, instruction: IType) -> Result<(), Error> {
         let rs1 = self.get_register(instruction.rs1);
         let imm = instruction.imm.as_u32();
 
-        let offset = rs1.wrapping_add(imm);
-        let index = offset as usize;
-
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(rs1));
+        let address = rs1.wrapping_add(imm);
+        if self.misaligned(Instruction::LW(instruction), address, 4)? {
+            return Ok(());
         }
-
-        let mut value_buf = [0u8; 4];
-        value_buf.clone_from_slice(&self.memory[index..index + 4]);
-        let value = u32::from_le_bytes(value_buf);
+        let value = self.load(address, 4)?;
 
         self.set_register(instruction.rd, value);
         self.increment_pc()
@@ -750,17 +1229,11 @@ impl CPU {
         let rs1 = self.get_register(instruction.rs1);
         let imm = instruction.imm.as_u32();
 
-        let offset = rs1.wrapping_add(imm);
-        let index = offset as usize;
-
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(rs1));
+        let address = rs1.wrapping_add(imm);
+        if self.misaligned(Instruction::LH(instruction), address, 2)? {
+            return Ok(());
         }
-
-        let mut value_buf = [0u8; 2];
-        value_buf.clone_from_slice(&self.memory[index..index + 2]);
-        let u16_value = u16::from_le_bytes(value_buf);
-        let value = u16_value as u32;
+        let value = self.load(address, 2)? as u16 as i16 as i32 as u32;
 
         self.set_register(instruction.rd, value);
         self.increment_pc()
@@ -772,18 +1245,12 @@ impl CPU {
         let rs1 = self.get_register(instruction.rs1);
         let imm = instruction.imm.as_u32();
 
-        let offset = rs1.wrapping_add(imm);
-        let index = offset as usize;
-
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(rs1));
+        let address = rs1.wrapping_add(imm);
+        if self.misaligned(Instruction::LHU(instruction), address, 2)? {
+            return Ok(());
         }
-
-        let mut value_buf = [0u8; 2];
-        value_buf.clone_from_slice(&self.memory[index..index + 2]);
-        let u16_value = u16::from_le_bytes(value_buf);
-
-        let value = 0b0000_0000_0000_0000_1111_1111_1111_1111 & u16_value as u32;
+        let u16_value = self.load(address, 2)?;
+        let value = 0b0000_0000_0000_0000_1111_1111_1111_1111 & u16_value;
 
         self.set_register(instruction.rd, value);
         self.increment_pc()
@@ -795,15 +1262,8 @@ impl CPU {
         let rs1 = self.get_register(instruction.rs1);
         let imm = instruction.imm.as_u32();
 
-        let offset = rs1.wrapping_add(imm);
-        let index = offset as usize;
-
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(rs1));
-        }
-
-        let i8_value = self.memory[index];
-        let value = i8_value as u32;
+        let address = rs1.wrapping_add(imm);
+        let value = self.load(address, 1)? as u8 as i8 as i32 as u32;
 
         self.set_register(instruction.rd, value);
         self.increment_pc()
@@ -815,15 +1275,9 @@ impl CPU {
         let rs1 = self.get_register(instruction.rs1);
         let imm = instruction.imm.as_u32();
 
-        let offset = rs1.wrapping_add(imm);
-        let index = offset as usize;
-
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(rs1));
-        }
-
-        let u8_value = self.memory[index];
-        let value = 0b0000_0000_0000_0000_0000_0000_1111_1111 & u8_value as u32;
+        let address = rs1.wrapping_add(imm);
+        let u8_value = self.load(address, 1)?;
+        let value = 0b0000_0000_0000_0000_0000_0000_1111_1111 & u8_value;
 
         self.set_register(instruction.rd, value);
         self.increment_pc()
@@ -832,46 +1286,660 @@ impl CPU {
     /// The SW, SH, and SB instructions store 32-bit, 16-bit, and 8-bit values
     /// from the low bits of register rs2 to memory
     fn rv32i_sw(&mut self, instruction: SType) -> Result<(), Error> {
-        self.store(instruction, 4)?;
+        if self.store(Instruction::SW(instruction), instruction, 4)? {
+            return Ok(());
+        }
         self.increment_pc()
     }
 
     fn rv32i_sh(&mut self, instruction: SType) -> Result<(), Error> {
-        self.store(instruction, 2)?;
+        if self.store(Instruction::SH(instruction), instruction, 2)? {
+            return Ok(());
+        }
         self.increment_pc()
     }
 
     fn rv32i_sb(&mut self, instruction: SType) -> Result<(), Error> {
-        self.store(instruction, 1)?;
+        self.store(Instruction::SB(instruction), instruction, 1)?;
         self.increment_pc()
     }
 
-    fn store(&mut self, instruction: SType, bytes: usize) -> Result<(), Error> {
+    /// Stores the low `bytes` of rs2. Returns whether the store trapped
+    /// instead (see [misaligned](Self::misaligned)).
+    fn store(
+        &mut self,
+        store: Instruction,
+        instruction: SType,
+        bytes: usize,
+    ) -> Result<bool, Error> {
         let base = self.get_register(instruction.rs1);
         let src = self.get_register(instruction.rs2);
         let imm = instruction.imm.as_u32();
 
         let address = base.wrapping_add(imm);
-        let mut index = address as usize;
+        if self.misaligned(store, address, bytes as u32)? {
+            return Ok(true);
+        }
 
-        if index >= self.memory.len() {
-            return Err(Error::AccessViolation(address));
+        if self.devices.write(address, bytes as u32, src) {
+            return Ok(false);
+        }
+
+        match bytes {
+            1 => self.write_mem_u8(address, src as u8),
+            2 => self.write_mem_u16(address, src as u16),
+            _ => self.write_mem_u32(address, src),
+        }?;
+
+        Ok(false)
+    }
+
+    /// Applies the [MisalignedPolicy] to a load or store of `bytes` at
+    /// `address`. Returns whether it trapped, in which case the access
+    /// mustn't be made.
+    pub(super) fn misaligned(
+        &mut self,
+        instruction: Instruction,
+        address: u32,
+        bytes: u32,
+    ) -> Result<bool, Error> {
+        if address.is_multiple_of(bytes) {
+            return Ok(false);
         }
 
-        for (byte_index, byte) in src.to_le_bytes().into_iter().enumerate() {
-            if byte_index < bytes {
-                self.memory[index] = byte;
-                index += 1;
+        match self.config.misaligned {
+            MisalignedPolicy::Emulate => Ok(false),
+            MisalignedPolicy::Trap => {
+                let cause = match instruction.operands() {
+                    Operands::SType(_) => TrapCause::StoreAddressMisaligned,
+                    _ => TrapCause::LoadAddressMisaligned,
+                };
+                self.trap(cause, address);
+                Ok(true)
             }
+            MisalignedPolicy::Error => Err(Error::MisalignedAccess(instruction, address)),
         }
+    }
 
+    /// The M extension multiplies and divides registers. MUL gives the low
+    /// 32 bits of the 64-bit product, and MULH, MULHSU, and MULHU the high 32
+    /// bits, treating the operands as signed, signed * unsigned, or unsigned.
+    ///
+    /// Division never traps. Dividing by zero gives all ones for the quotient
+    /// and the dividend for the remainder; the one signed overflow,
+    /// `i32::MIN / -1`, gives the dividend and a remainder of zero.
+    fn rv32m_mul(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| a.wrapping_mul(b))
+    }
+
+    fn rv32m_mulh(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| {
+            ((a as i32 as i64 * b as i32 as i64) >> 32) as u32
+        })
+    }
+
+    fn rv32m_mulhsu(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| {
+            ((a as i32 as i64 * b as i64) >> 32) as u32
+        })
+    }
+
+    fn rv32m_mulhu(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| ((a as u64 * b as u64) >> 32) as u32)
+    }
+
+    fn rv32m_div(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| match b {
+            0 => u32::MAX,
+            _ => (a as i32).wrapping_div(b as i32) as u32,
+        })
+    }
+
+    fn rv32m_divu(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| a.checked_div(b).unwrap_or(u32::MAX))
+    }
+
+    fn rv32m_rem(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| match b {
+            0 => a,
+            _ => (a as i32).wrapping_rem(b as i32) as u32,
+        })
+    }
+
+    fn rv32m_remu(&mut self, instruction: RType) -> Result<(), Error> {
+        self.multiply_divide(instruction, |a, b| a.checked_rem(b).unwrap_or(a))
+    }
+
+    /// Writes `op(rs1, rs2)` to rd
+    fn multiply_divide(
+        &mut self,
+        instruction: RType,
+        op: impl FnOnce(u32, u32) -> u32,
+    ) -> Result<(), Error> {
+        let a = self.get_register(instruction.rs1);
+        let b = self.get_register(instruction.rs2);
+        self.set_register(instruction.rd, op(a, b));
+        self.increment_pc()
+    }
+
+    /// The A extension's instructions read and write a word of memory at the
+    /// address in rs1 as one step, which matters once there's more than one
+    /// hart. LR.W loads the word and reserves its address; SC.W stores rs2
+    /// there only if the reservation still holds, writing 0 to rd if it did
+    /// and 1 if it didn't. Either way the reservation is gone afterwards, as
+    /// it is after a trap.
+    ///
+    /// The address must be a multiple of 4, whatever the [MisalignedPolicy]
+    /// says: a misaligned address traps, or stops with
+    /// [Error::MisalignedAccess] under [MisalignedPolicy::Error].
+    fn rv32a_lr_w(&mut self, instruction: RType) -> Result<(), Error> {
+        let address = self.get_register(instruction.rs1);
+        if self.atomic_misaligned(Instruction::LR_W(instruction), address)? {
+            return Ok(());
+        }
+
+        let value = self.load(address, 4)?;
+        self.reservation = Some(address);
+        self.set_register(instruction.rd, value);
+        self.increment_pc()
+    }
+
+    fn rv32a_sc_w(&mut self, instruction: RType) -> Result<(), Error> {
+        let address = self.get_register(instruction.rs1);
+        if self.atomic_misaligned(Instruction::SC_W(instruction), address)? {
+            return Ok(());
+        }
+
+        let reserved = self.reservation.take() == Some(address);
+        if reserved {
+            let value = self.get_register(instruction.rs2);
+            self.store_word(address, value)?;
+        }
+        self.set_register(instruction.rd, u32::from(!reserved));
+        self.increment_pc()
+    }
+
+    /// The AMOs (atomic memory operations) load the word at rs1 into rd, and
+    /// store `op(word, rs2)` back in its place.
+    fn rv32a_amo(
+        &mut self,
+        amo: Instruction,
+        op: impl FnOnce(u32, u32) -> u32,
+    ) -> Result<(), Error> {
+        let Operands::RType(instruction) = amo.operands() else {
+            unreachable!("AMOs are R-type");
+        };
+        let address = self.get_register(instruction.rs1);
+        if self.atomic_misaligned(amo, address)? {
+            return Ok(());
+        }
+
+        let value = self.load(address, 4)?;
+        let operand = self.get_register(instruction.rs2);
+        self.store_word(address, op(value, operand))?;
+        self.set_register(instruction.rd, value);
+        self.increment_pc()
+    }
+
+    /// Like [misaligned](Self::misaligned) for the atomic instructions,
+    /// which are never emulated. Returns whether it trapped.
+    fn atomic_misaligned(&mut self, instruction: Instruction, address: u32) -> Result<bool, Error> {
+        if address.is_multiple_of(4) {
+            return Ok(false);
+        }
+
+        match self.config.misaligned {
+            MisalignedPolicy::Error => Err(Error::MisalignedAccess(instruction, address)),
+            _ => {
+                let cause = match instruction {
+                    Instruction::LR_W(_) => TrapCause::LoadAddressMisaligned,
+                    _ => TrapCause::StoreAddressMisaligned,
+                };
+                self.trap(cause, address);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Stores a word to a device, or to memory if no device claims the
+    /// address
+    fn store_word(&mut self, address: u32, value: u32) -> Result<(), Error> {
+        if self.devices.write(address, 4, value) {
+            return Ok(());
+        }
+        self.write_mem_u32(address, value)
+    }
+
+    /// The CSR instructions atomically read-modify-write a single CSR, whose
+    /// address is in the immediate. CSRRW swaps the values in the CSR and
+    /// rs1. CSRRS and CSRRC set or clear the bits that are set in rs1; they
+    /// don't write the CSR at all when rs1 is x0, so they can read read-only
+    /// CSRs. In every case the old value is written to rd.
+    ///
+    /// The immediate forms use a 5-bit unsigned value ("uimm") in place of
+    /// rs1; it's carried in the rs1 field, just as it's encoded.
+    fn zicsr_csrrw(&mut self, instruction: IType) -> Result<(), Error> {
+        let value = self.get_register(instruction.rs1);
+        self.csr_modify(instruction, |_| Some(value))
+    }
+
+    fn zicsr_csrrs(&mut self, instruction: IType) -> Result<(), Error> {
+        let bits = self.get_register(instruction.rs1);
+        let write = instruction.rs1 != Register::X0;
+        self.csr_modify(instruction, |old| write.then_some(old | bits))
+    }
+
+    fn zicsr_csrrc(&mut self, instruction: IType) -> Result<(), Error> {
+        let bits = self.get_register(instruction.rs1);
+        let write = instruction.rs1 != Register::X0;
+        self.csr_modify(instruction, |old| write.then_some(old & !bits))
+    }
+
+    fn zicsr_csrrwi(&mut self, instruction: IType) -> Result<(), Error> {
+        let uimm = instruction.rs1 as u32;
+        self.csr_modify(instruction, |_| Some(uimm))
+    }
+
+    fn zicsr_csrrsi(&mut self, instruction: IType) -> Result<(), Error> {
+        let uimm = instruction.rs1 as u32;
+        self.csr_modify(instruction, |old| (uimm != 0).then_some(old | uimm))
+    }
+
+    fn zicsr_csrrci(&mut self, instruction: IType) -> Result<(), Error> {
+        let uimm = instruction.rs1 as u32;
+        self.csr_modify(instruction, |old| (uimm != 0).then_some(old & !uimm))
+    }
+
+    /// Reads the CSR into rd, and writes the new value if there is one. A
+    /// CSR above the current [privilege] level traps as an illegal
+    /// instruction (with an `mtval` of zero), as does one that doesn't exist
+    /// if the [CsrPolicy] says so.
+    fn csr_modify(
+        &mut self,
+        instruction: IType,
+        new: impl FnOnce(u32) -> Option<u32>,
+    ) -> Result<(), Error> {
+        // the immediate is a 12-bit address, not a signed number
+        let address = instruction.imm.as_u32() & 0xfff;
+        if !self.privilege.can_access(address) {
+            self.trap(TrapCause::IllegalInstruction, 0);
+            return Ok(());
+        }
+
+        let result = self.read_csr(address).and_then(|old| {
+            if let Some(value) = new(old) {
+                self.write_csr(address, value)?;
+            }
+            Ok(old)
+        });
+        let old = match result {
+            Err(Error::IllegalCsr(_)) if self.config.csr == CsrPolicy::Trap => {
+                self.trap(TrapCause::IllegalInstruction, 0);
+                return Ok(());
+            }
+            result => result?,
+        };
+
+        self.set_register(instruction.rd, old);
+        self.increment_pc()
+    }
+
+    /// The ECALL instruction is used to make a service request to the
+    /// execution environment. What happens depends on the configured
+    /// [EcallPolicy].
+    fn rv32i_ecall(&mut self, instruction: IType) -> Result<(), Error> {
+        match self.config.ecall {
+            EcallPolicy::HostHandle => self.host_ecall(instruction),
+            EcallPolicy::TrapIntoGuestHandler => {
+                let cause = match self.privilege {
+                    Privilege::User => TrapCause::UserEcall,
+                    Privilege::Machine => TrapCause::MachineEcall,
+                };
+                self.trap(cause, 0);
+                Ok(())
+            }
+            EcallPolicy::Error => Err(Error::EnvironmentCall(self.pc)),
+        }
+    }
+
+    /// The host handles the request itself, with the [SyscallHandler]. The
+    /// handler is taken out of the CPU while it runs, so it can change the
+    /// rest of the CPU.
+    fn host_ecall(&mut self, instruction: IType) -> Result<(), Error> {
+        let unsupported = Err(Error::NotImplemented(Instruction::ECALL(instruction)));
+        let Some(mut handler) = self.syscalls.take() else {
+            return unsupported;
+        };
+
+        let result = handler.call(self.x17, self);
+        self.syscalls = Some(handler);
+
+        match result {
+            Some(value) => {
+                self.x10 = value;
+                self.increment_pc()
+            }
+            None => unsupported,
+        }
+    }
+
+    /// MRET returns from a machine mode trap handler: the PC goes back to
+    /// `mepc`, the mode to the one saved in `mstatus.MPP`, and `MIE` to
+    /// what it was before the trap (saved in `MPIE`). `MPP` is left as user
+    /// mode, and `MPIE` set. Only machine mode can execute it.
+    fn privileged_mret(&mut self) -> Result<(), Error> {
+        if self.privilege != Privilege::Machine {
+            self.trap(TrapCause::IllegalInstruction, Instruction::MRET.encode());
+            return Ok(());
+        }
+
+        let status = self.csrs[csr::MSTATUS];
+        let previous = (status & csr::MSTATUS_MPP) >> csr::MSTATUS_MPP.trailing_zeros();
+        let enable = match status & csr::MSTATUS_MPIE {
+            0 => 0,
+            _ => csr::MSTATUS_MIE,
+        };
+        self.store_csr(
+            csr::MSTATUS,
+            (status & !(csr::MSTATUS_MIE | csr::MSTATUS_MPP)) | enable | csr::MSTATUS_MPIE,
+        );
+
+        self.privilege = Privilege::from_bits(previous).unwrap_or_default();
+        self.pc = self.csrs[csr::MEPC];
+        self.reservation = None;
+        Ok(())
+    }
+
+    /// WFI says there's nothing to do until an interrupt. It's allowed to
+    /// do nothing, so it does: the next instruction runs straight away
+    /// (and an interrupt that's pending is taken before it).
+    fn privileged_wfi(&mut self) -> Result<(), Error> {
+        self.increment_pc()
+    }
+
+    /// Takes a trap into the machine mode handler at `mtvec`: the PC is saved
+    /// in `mepc`, the mode in `mstatus.MPP`, and interrupts are disabled
+    /// until the handler returns. For an interrupt, the PC is that of the
+    /// instruction that hasn't run yet.
+    fn trap(&mut self, cause: TrapCause, value: u32) {
+        let status = self.csrs[csr::MSTATUS];
+        let previous_enable = if status & csr::MSTATUS_MIE != 0 {
+            csr::MSTATUS_MPIE
+        } else {
+            0
+        };
+        self.store_csr(
+            csr::MSTATUS,
+            (status & !(csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP))
+                | previous_enable
+                | self.privilege.bits() << csr::MSTATUS_MPP.trailing_zeros(),
+        );
+        self.privilege = Privilege::Machine;
+
+        self.store_csr(csr::MEPC, self.pc);
+        self.store_csr(csr::MCAUSE, cause.mcause());
+        self.store_csr(csr::MTVAL, value);
+
+        // exceptions go to the base address even when mtvec is vectored;
+        // interrupts go to base + 4 * code
+        let mtvec = self.csrs[csr::MTVEC];
+        self.pc = mtvec & !0b11;
+        if cause.is_interrupt() && mtvec & 0b11 == 1 {
+            self.pc = self
+                .pc
+                .wrapping_add(4 * (cause.mcause() & !trap::INTERRUPT));
+        }
+        self.last_trap = Some(cause.mcause());
+        self.reservation = None;
+    }
+
+    /// Copies bytes straight into memory (bypassing devices), eg: to load a
+    /// program. Nothing is written unless all of them fit.
+    pub fn write_memory(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        let range = self.address_map.translate(address, bytes.len());
+        match range.and_then(|r| self.memory.get_mut(r)) {
+            Some(destination) => {
+                destination.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(Error::AccessViolation(address)),
+        }
+    }
+
+    /// Copies a raw image (eg: firmware, or hand-assembled machine code) into
+    /// memory at `address`, and moves the PC there to run it
+    pub fn load_binary(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.write_memory(address, bytes)?;
+        self.pc = address;
+        Ok(())
+    }
+
+    /// Copies bytes into memory (bypassing devices) as part of the current
+    /// instruction, so they're recorded in [last_writes](Self::last_writes)
+    /// like a store. Nothing is written unless all of them fit.
+    pub fn store_bytes(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        let range = self.address_map.translate(address, bytes.len());
+        let Some(target) = range.and_then(|r| self.memory.get_mut(r)) else {
+            return Err(Error::AccessViolation(address));
+        };
+
+        self.writes.push(MemoryWrite {
+            address,
+            old: target.to_vec(),
+            new: bytes.to_vec(),
+        });
+        target.copy_from_slice(bytes);
         Ok(())
     }
+
+    /// Reads a byte straight from memory, bypassing devices
+    pub fn read_mem_u8(&self, address: u32) -> Result<u8, Error> {
+        Ok(self.read_mem::<1>(address)?[0])
+    }
+
+    /// Reads a little endian halfword straight from memory, bypassing devices
+    pub fn read_mem_u16(&self, address: u32) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_mem(address)?))
+    }
+
+    /// Reads a little endian word straight from memory, bypassing devices
+    pub fn read_mem_u32(&self, address: u32) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_mem(address)?))
+    }
+
+    /// Writes a byte straight to memory, bypassing devices. Like
+    /// [store_bytes](Self::store_bytes), it's recorded as part of the current
+    /// instruction.
+    pub fn write_mem_u8(&mut self, address: u32, value: u8) -> Result<(), Error> {
+        self.store_bytes(address, &[value])
+    }
+
+    /// Writes a little endian halfword straight to memory, bypassing devices
+    pub fn write_mem_u16(&mut self, address: u32, value: u16) -> Result<(), Error> {
+        self.store_bytes(address, &value.to_le_bytes())
+    }
+
+    /// Writes a little endian word straight to memory, bypassing devices
+    pub fn write_mem_u32(&mut self, address: u32, value: u32) -> Result<(), Error> {
+        self.store_bytes(address, &value.to_le_bytes())
+    }
+
+    fn read_mem<const N: usize>(&self, address: u32) -> Result<[u8; N], Error> {
+        let range = self.address_map.translate(address, N);
+        match range.and_then(|r| self.memory.get(r)) {
+            Some(bytes) => Ok(bytes.try_into().expect("the range is N bytes long")),
+            None => Err(Error::AccessViolation(address)),
+        }
+    }
+
+    /// Reads a little endian value of `bytes` length from memory, or from a
+    /// memory-mapped device if one claims the address.
+    pub(super) fn load(&mut self, address: u32, bytes: usize) -> Result<u32, Error> {
+        if let Some(value) = self.devices.read(address, bytes as u32) {
+            return Ok(value);
+        }
+
+        match bytes {
+            1 => self.read_mem_u8(address).map(u32::from),
+            2 => self.read_mem_u16(address).map(u32::from),
+            _ => self.read_mem_u32(address),
+        }
+    }
+}
+
+/// Registers and CSRs by name (in any case), and memory by address. Devices
+/// aren't read, since reading them can have side effects.
+impl Environment for CPU {
+    fn symbol(&self, name: &str) -> Option<u32> {
+        if let Some(register) = Register::from_name(&name.to_uppercase()) {
+            return Some(self.get_register(register));
+        }
+
+        let def = self.csrs.find(name)?;
+        self.read_csr(def.address).ok()
+    }
+
+    fn word(&self, address: u32) -> Option<u32> {
+        self.read_mem_u32(address).ok()
+    }
+}
+
+/// A store to memory, as recorded while executing an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: u32,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// A CSR that changed: its stored value went from `old` to `new`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CsrChange {
+    pub address: u32,
+    pub old: u32,
+    pub new: u32,
+}
+
+impl CsrChange {
+    /// The CSR's name, or its address for one that isn't standard
+    pub fn name(&self) -> String {
+        csr::name(self.address).map_or(format!("csr 0x{:03x}", self.address), str::to_owned)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Error {
     NotImplemented(Instruction),
+    /// An `EBREAK` was executed at this address
+    Breakpoint(u32),
     MisalignedJump(u32),
     AccessViolation(u32),
+    /// The word at the PC isn't an instruction
+    IllegalInstruction(DecodeError),
+    /// The CSR at this address doesn't exist, or can't be written
+    IllegalCsr(u32),
+    /// An `ECALL` was executed at this address with [EcallPolicy::Error]
+    EnvironmentCall(u32),
+    /// A load or store to this address isn't aligned to its size, with
+    /// [MisalignedPolicy::Error]
+    MisalignedAccess(Instruction, u32),
+}
+
+/// The decoding and alignment errors are spelled out; the rest are terse
+/// (eg: `MisalignedJump(6)`), and the REPL adds a hint
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IllegalInstruction(e) => write!(f, "Illegal instruction: {e}"),
+            Self::MisalignedAccess(instruction, address) => {
+                write!(f, "Misaligned access: {instruction} at 0x{address:08x}")
+            }
+            e => write!(f, "{e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IllegalInstruction(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// What `ECALL` does. The same program can be run against its own trap
+/// handler (eg: for an exercise on writing one), or with the host standing in
+/// for an operating system.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EcallPolicy {
+    /// Trap to the handler at `mtvec`, like real hardware
+    TrapIntoGuestHandler,
+    /// The host emulates the system call; see [syscalls]
+    #[default]
+    HostHandle,
+    /// Stop with [Error::EnvironmentCall]
+    Error,
+}
+
+impl EcallPolicy {
+    /// Parses the names used in board files: `trap`, `host`, or `error`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trap" => Some(Self::TrapIntoGuestHandler),
+            "host" => Some(Self::HostHandle),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// What a load or store does when its address isn't a multiple of its size
+/// (eg: `LW` from `0x1002`). The spec lets hardware either perform the
+/// access or raise an address-misaligned exception.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MisalignedPolicy {
+    /// Perform the access anyway, a byte at a time
+    #[default]
+    Emulate,
+    /// Trap to the handler at `mtvec`, with the address in `mtval`
+    Trap,
+    /// Stop with [Error::MisalignedAccess]
+    Error,
+}
+
+impl MisalignedPolicy {
+    /// Parses the names used in board files: `emulate`, `trap`, or `error`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "emulate" => Some(Self::Emulate),
+            "trap" => Some(Self::Trap),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// What a CSR instruction does when the CSR doesn't exist, or is read-only
+/// and would be written. Hardware raises an illegal instruction exception,
+/// which startup code sometimes relies on to probe for optional CSRs (eg:
+/// `satp`); stopping instead points straight at the mistake.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CsrPolicy {
+    /// Trap to the handler at `mtvec`
+    Trap,
+    /// Stop with [Error::IllegalCsr]
+    #[default]
+    Error,
+}
+
+impl CsrPolicy {
+    /// Parses the names used in board files: `trap` or `error`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trap" => Some(Self::Trap),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,618 @@
+//! Control and status registers ("CSRs"), from the Zicsr extension.
+//!
+//! CSRs have their own 12-bit address space and are accessed with the
+//! `CSRRW`, `CSRRS`, and `CSRRC` instructions (and their immediate forms).
+//! Only a handful are implemented; accessing any other address is an illegal
+//! instruction. The top two address bits say whether a CSR is read-only
+//! (`0b11`) or read/write.
+//!
+//! Some CSRs describe the machine rather than storing anything: `misa` lists
+//! the enabled [Extensions], and the counters (`cycle`, `time`, `instret`)
+//! only exist when the Zicntr extension is enabled.
+//!
+//! The CSRs a CPU has are in its [CsrFile]: a [CsrDef] for each (its name,
+//! address, value at reset, and which bits can be written), and their
+//! values. Most CSRs are "WARL" (write any, read legal): a write can't put
+//! a value in that the hardware doesn't support, so some bits keep their
+//! value or are worked out from what was written. Custom CSRs can be added
+//! with [CsrFile::define].
+//!
+//! ```
+//! use brubeck::rv32_i::csr::{CsrDef, Warl};
+//! use brubeck::rv32_i::CPU;
+//!
+//! let mut cpu = CPU::default();
+//! // a read/write machine CSR in the custom range, with only 8 bits
+//! let def = CsrDef::new("mleds", 0x7c0).with_write(Warl::Mask(0xff));
+//! cpu.csrs.define(def).unwrap();
+//!
+//! cpu.write_csr(0x7c0, 0x1234).unwrap();
+//! assert_eq!(cpu.read_csr(0x7c0).unwrap(), 0x34);
+//! assert_eq!(cpu.csrs.find("MLEDS").unwrap().address, 0x7c0);
+//! ```
+
+use std::fmt::Display;
+use std::ops::{Index, IndexMut};
+
+use super::{Instruction, Privilege};
+use crate::bits;
+use crate::devices::{MEIP, MSIP, MTIP};
+
+/// Supervisor-independent machine status: interrupt enables, previous mode
+pub const MSTATUS: u32 = 0x300;
+/// ISA and extensions
+pub const MISA: u32 = 0x301;
+/// Machine interrupt enables
+pub const MIE: u32 = 0x304;
+/// Machine trap handler base address
+pub const MTVEC: u32 = 0x305;
+/// Scratch register for machine trap handlers
+pub const MSCRATCH: u32 = 0x340;
+/// Machine exception program counter
+pub const MEPC: u32 = 0x341;
+/// Machine trap cause
+pub const MCAUSE: u32 = 0x342;
+/// Machine bad address or instruction
+pub const MTVAL: u32 = 0x343;
+/// Machine interrupts pending
+pub const MIP: u32 = 0x344;
+/// Cycle counter (Zicntr)
+pub const CYCLE: u32 = 0xc00;
+/// Timer (Zicntr)
+pub const TIME: u32 = 0xc01;
+/// Instructions retired counter (Zicntr)
+pub const INSTRET: u32 = 0xc02;
+/// Upper 32 bits of `cycle` (Zicntr)
+pub const CYCLEH: u32 = 0xc80;
+/// Upper 32 bits of `time` (Zicntr)
+pub const TIMEH: u32 = 0xc81;
+/// Upper 32 bits of `instret` (Zicntr)
+pub const INSTRETH: u32 = 0xc82;
+/// Vendor ID
+pub const MVENDORID: u32 = 0xf11;
+/// Architecture ID
+pub const MARCHID: u32 = 0xf12;
+/// Implementation ID
+pub const MIMPID: u32 = 0xf13;
+/// Hardware thread ID
+pub const MHARTID: u32 = 0xf14;
+
+/// `mstatus` bits that can be written; the rest are hardwired to zero
+pub const MSTATUS_MASK: u32 = MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP;
+pub const MSTATUS_MIE: u32 = 1 << 3;
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+pub const MSTATUS_MPP: u32 = 0b11 << 11;
+
+/// A named bit field within a CSR, for display
+#[derive(Debug)]
+pub struct Field {
+    pub name: &'static str,
+    pub high: u32,
+    pub low: u32,
+    /// Shown in hex without shifting down, eg: an aligned address
+    pub in_place: bool,
+    /// Names for the field's values; fields without any are shown as numbers
+    pub values: &'static [(u32, &'static str)],
+}
+
+impl Field {
+    const fn flag(name: &'static str, bit: u32) -> Self {
+        Self {
+            name,
+            high: bit,
+            low: bit,
+            in_place: false,
+            values: &[],
+        }
+    }
+
+    /// The field's value, named if possible (eg: `MPP=Machine`)
+    pub fn describe(&self, value: u32) -> String {
+        let raw = bits::field(value, self.high, self.low);
+        let shown = match self.values.iter().find(|(v, _)| *v == raw) {
+            Some((_, name)) => name.to_string(),
+            None if self.in_place => format!("0x{:x}", raw << self.low),
+            None => raw.to_string(),
+        };
+        format!("{}={shown}", self.name)
+    }
+}
+
+const PRIVILEGE: &[(u32, &str)] = &[(0, "User"), (1, "Supervisor"), (3, "Machine")];
+
+/// The fields of CSRs that have them, most significant first
+pub const FIELDS: &[(u32, &[Field])] = &[
+    (
+        MSTATUS,
+        &[
+            Field {
+                name: "MPP",
+                high: 12,
+                low: 11,
+                in_place: false,
+                values: PRIVILEGE,
+            },
+            Field::flag("MPIE", 7),
+            Field::flag("MIE", 3),
+        ],
+    ),
+    (
+        MTVEC,
+        &[
+            Field {
+                name: "BASE",
+                high: 31,
+                low: 2,
+                in_place: true,
+                values: &[],
+            },
+            Field {
+                name: "MODE",
+                high: 1,
+                low: 0,
+                in_place: false,
+                values: &[(0, "Direct"), (1, "Vectored")],
+            },
+        ],
+    ),
+    (
+        MIE,
+        &[
+            Field::flag("MEIE", 11),
+            Field::flag("MTIE", 7),
+            Field::flag("MSIE", 3),
+        ],
+    ),
+    (
+        MIP,
+        &[
+            Field::flag("MEIP", 11),
+            Field::flag("MTIP", 7),
+            Field::flag("MSIP", 3),
+        ],
+    ),
+];
+
+/// The fields of the CSR at `address`; empty if it isn't broken into fields
+pub fn fields(address: u32) -> &'static [Field] {
+    FIELDS
+        .iter()
+        .find(|(a, _)| *a == address)
+        .map_or(&[], |(_, fields)| fields)
+}
+
+/// A CSR value broken into its fields, eg: `MPP=Machine, MPIE=0, MIE=1`, or
+/// None for CSRs without fields
+pub fn describe_fields(address: u32, value: u32) -> Option<String> {
+    let fields = fields(address);
+    (!fields.is_empty()).then(|| {
+        fields
+            .iter()
+            .map(|f| f.describe(value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+/// Which bits of a CSR a write changes
+#[derive(Debug, Copy, Clone)]
+pub enum Warl {
+    /// The bits set in the mask are written; the rest keep their value
+    Mask(u32),
+    /// Works out the new value from the old value and the one written
+    Function(fn(old: u32, written: u32) -> u32),
+}
+
+impl Warl {
+    /// The value after `written` is written over `old`
+    pub fn apply(&self, old: u32, written: u32) -> u32 {
+        match self {
+            Self::Mask(mask) => (old & !mask) | (written & mask),
+            Self::Function(f) => f(old, written),
+        }
+    }
+}
+
+/// A CSR: its name, address, value at reset, and how it takes writes.
+/// Whether it's read-only, and the privilege it needs, come from its
+/// address.
+#[derive(Debug, Copy, Clone)]
+pub struct CsrDef {
+    pub name: &'static str,
+    pub address: u32,
+    pub reset: u32,
+    pub write: Warl,
+}
+
+impl CsrDef {
+    /// A CSR that resets to zero and can be written freely
+    pub const fn new(name: &'static str, address: u32) -> Self {
+        Self {
+            name,
+            address,
+            reset: 0,
+            write: Warl::Mask(u32::MAX),
+        }
+    }
+
+    pub const fn with_reset(self, reset: u32) -> Self {
+        Self { reset, ..self }
+    }
+
+    pub const fn with_write(self, write: Warl) -> Self {
+        Self { write, ..self }
+    }
+}
+
+/// `MPP` only holds modes that are implemented; writing another keeps the
+/// old one. The other bits not in [MSTATUS_MASK] are hardwired to zero.
+fn mstatus(old: u32, written: u32) -> u32 {
+    let mpp = match Privilege::from_bits(bits::field(written, 12, 11)) {
+        Some(_) => written,
+        None => old,
+    };
+    (written & (MSTATUS_MIE | MSTATUS_MPIE)) | (mpp & MSTATUS_MPP)
+}
+
+/// The CSRs every CPU has. `misa`, `mip`, the counters, and the IDs are
+/// worked out when they're read, so nothing is stored for them.
+pub const STANDARD: &[CsrDef] = &[
+    CsrDef::new("mstatus", MSTATUS).with_write(Warl::Function(mstatus)),
+    CsrDef::new("misa", MISA).with_write(Warl::Mask(0)),
+    // only the machine level interrupts can be enabled
+    CsrDef::new("mie", MIE).with_write(Warl::Mask(MEIP | MTIP | MSIP)),
+    CsrDef::new("mtvec", MTVEC),
+    CsrDef::new("mscratch", MSCRATCH),
+    // return addresses are word aligned
+    CsrDef::new("mepc", MEPC).with_write(Warl::Mask(!0b11)),
+    CsrDef::new("mcause", MCAUSE),
+    CsrDef::new("mtval", MTVAL),
+    CsrDef::new("mip", MIP).with_write(Warl::Mask(0)),
+    CsrDef::new("cycle", CYCLE),
+    CsrDef::new("time", TIME),
+    CsrDef::new("instret", INSTRET),
+    CsrDef::new("cycleh", CYCLEH),
+    CsrDef::new("timeh", TIMEH),
+    CsrDef::new("instreth", INSTRETH),
+    CsrDef::new("mvendorid", MVENDORID),
+    CsrDef::new("marchid", MARCHID),
+    CsrDef::new("mimpid", MIMPID),
+    CsrDef::new("mhartid", MHARTID),
+];
+
+/// Name of the [standard](STANDARD) CSR at `address`
+pub fn name(address: u32) -> Option<&'static str> {
+    STANDARD
+        .iter()
+        .find(|def| def.address == address)
+        .map(|def| def.name)
+}
+
+/// Address of a [standard](STANDARD) CSR by name, ignoring case
+pub fn address(name: &str) -> Option<u32> {
+    STANDARD
+        .iter()
+        .find(|def| def.name.eq_ignore_ascii_case(name))
+        .map(|def| def.address)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsrError {
+    /// CSR addresses are 12 bits
+    OutOfRange(u32),
+    /// There's already a CSR at this address
+    AddressTaken(u32),
+    /// There's already a CSR with this name
+    NameTaken(String),
+}
+
+impl Display for CsrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfRange(address) => {
+                write!(f, "0x{address:x} isn't a CSR address (they're 12 bits)")
+            }
+            Self::AddressTaken(address) => write!(f, "there's already a CSR at 0x{address:03x}"),
+            Self::NameTaken(name) => write!(f, "there's already a CSR called {name}"),
+        }
+    }
+}
+
+impl std::error::Error for CsrError {}
+
+/// The CSRs a CPU has, and their values; see the [module](self) docs.
+/// Indexing by address gets or sets a value directly, without applying
+/// [Warl], and panics if there's no CSR there.
+#[derive(Debug, Clone)]
+pub struct CsrFile {
+    /// In order of address
+    defs: Vec<CsrDef>,
+    /// The value of each CSR in `defs`
+    values: Vec<u32>,
+}
+
+impl Default for CsrFile {
+    /// The [standard](STANDARD) CSRs
+    fn default() -> Self {
+        let mut file = Self {
+            defs: vec![],
+            values: vec![],
+        };
+        for def in STANDARD {
+            file.define(*def).expect("the standard CSRs are distinct");
+        }
+        file
+    }
+}
+
+impl CsrFile {
+    /// Adds a CSR, set to its reset value
+    pub fn define(&mut self, def: CsrDef) -> Result<(), CsrError> {
+        if def.address > 0xfff {
+            return Err(CsrError::OutOfRange(def.address));
+        }
+        if self.find(def.name).is_some() {
+            return Err(CsrError::NameTaken(def.name.to_owned()));
+        }
+        match self.position(def.address) {
+            Ok(_) => Err(CsrError::AddressTaken(def.address)),
+            Err(index) => {
+                self.defs.insert(index, def);
+                self.values.insert(index, def.reset);
+                Ok(())
+            }
+        }
+    }
+
+    /// The CSR at `address`
+    pub fn get(&self, address: u32) -> Option<&CsrDef> {
+        self.position(address).ok().map(|i| &self.defs[i])
+    }
+
+    /// A CSR by name, ignoring case
+    pub fn find(&self, name: &str) -> Option<&CsrDef> {
+        self.defs
+            .iter()
+            .find(|def| def.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The stored value of a CSR
+    pub fn read(&self, address: u32) -> Option<u32> {
+        self.position(address).ok().map(|i| self.values[i])
+    }
+
+    /// Writes a CSR as an instruction would, so only the bits its [Warl]
+    /// allows change. Returns false if there's no CSR there.
+    pub fn write(&mut self, address: u32, value: u32) -> bool {
+        match self.position(address) {
+            Ok(i) => {
+                self.values[i] = self.defs[i].write.apply(self.values[i], value);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Sets a CSR's stored value as it is, without applying its [Warl].
+    /// Returns false if there's no CSR there.
+    pub fn set(&mut self, address: u32, value: u32) -> bool {
+        match self.position(address) {
+            Ok(i) => {
+                self.values[i] = value;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Every CSR and its stored value, in order of address
+    pub fn iter(&self) -> impl Iterator<Item = (&CsrDef, u32)> {
+        self.defs.iter().zip(self.values.iter().copied())
+    }
+
+    /// Puts every CSR back to its reset value
+    pub fn reset(&mut self) {
+        for (value, def) in self.values.iter_mut().zip(&self.defs) {
+            *value = def.reset;
+        }
+    }
+
+    /// Sets every stored value to zero, eg: before restoring the nonzero
+    /// ones from a snapshot
+    pub fn clear(&mut self) {
+        self.values.fill(0);
+    }
+
+    fn position(&self, address: u32) -> Result<usize, usize> {
+        self.defs.binary_search_by_key(&address, |def| def.address)
+    }
+}
+
+impl Index<u32> for CsrFile {
+    type Output = u32;
+
+    fn index(&self, address: u32) -> &u32 {
+        match self.position(address) {
+            Ok(i) => &self.values[i],
+            Err(_) => panic!("no CSR at 0x{address:03x}"),
+        }
+    }
+}
+
+impl IndexMut<u32> for CsrFile {
+    fn index_mut(&mut self, address: u32) -> &mut u32 {
+        match self.position(address) {
+            Ok(i) => &mut self.values[i],
+            Err(_) => panic!("no CSR at 0x{address:03x}"),
+        }
+    }
+}
+
+/// CSRs with the top two address bits set can't be written
+pub fn is_read_only(address: u32) -> bool {
+    address >> 10 == 0b11
+}
+
+/// The counters provided by Zicntr
+pub fn is_counter(address: u32) -> bool {
+    matches!(address, CYCLE | TIME | INSTRET | CYCLEH | TIMEH | INSTRETH)
+}
+
+/// Optional parts of the ISA that are enabled, reflected in `misa`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Extensions {
+    /// The `cycle`, `time`, and `instret` counters
+    pub zicntr: bool,
+    /// Integer multiplication and division
+    pub m: bool,
+    /// Atomic memory operations
+    pub a: bool,
+    /// 16-bit compressed instructions, which also lets jumps and branches
+    /// go to any even address. Off by default, so that a jump or branch
+    /// into the middle of an instruction is caught.
+    pub c: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self {
+            zicntr: true,
+            m: true,
+            a: true,
+            c: false,
+        }
+    }
+}
+
+impl Extensions {
+    /// The value of `misa`: 32-bit base (MXL = 1) plus a bit per standard
+    /// extension letter. Z extensions like Zicntr don't have a bit.
+    pub fn misa(&self) -> u32 {
+        let letters = [
+            self.a.then_some('A'),
+            self.c.then_some('C'),
+            Some('I'),
+            self.m.then_some('M'),
+        ];
+        let bits = letters
+            .iter()
+            .flatten()
+            .fold(0, |bits, letter| bits | 1 << (*letter as u32 - 'A' as u32));
+        (1 << 30) | bits
+    }
+
+    /// The extensions named by an ISA string like `rv32imac` (the base and
+    /// any of M, A, and C, in any case). Zicntr is always included.
+    pub fn from_isa(isa: &str) -> Option<Self> {
+        let letters = isa.to_lowercase();
+        let letters = letters.strip_prefix("rv32i")?;
+        if !letters.chars().all(|c| "mac".contains(c)) {
+            return None;
+        }
+        Some(Self {
+            zicntr: true,
+            m: letters.contains('m'),
+            a: letters.contains('a'),
+            c: letters.contains('c'),
+        })
+    }
+
+    /// What instruction addresses must be a multiple of: 4, or 2 with
+    /// compressed instructions
+    pub fn instruction_alignment(&self) -> u32 {
+        match self.c {
+            true => Instruction::COMPRESSED_LENGTH,
+            false => Instruction::LENGTH,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_access() {
+        assert_eq!(name(MSTATUS), Some("mstatus"));
+        assert_eq!(address("MISA"), Some(MISA));
+        assert_eq!(address("nope"), None);
+
+        assert!(is_read_only(CYCLE));
+        assert!(is_read_only(MHARTID));
+        assert!(!is_read_only(MSCRATCH));
+
+        assert_eq!(Extensions::default().misa(), 0x4000_1101);
+        let base = Extensions {
+            m: false,
+            a: false,
+            ..Extensions::default()
+        };
+        assert_eq!(base.misa(), 0x4000_0100);
+
+        let imac = Extensions::from_isa("RV32IMAC").unwrap();
+        assert!(imac.c);
+        assert_eq!(imac.misa(), 0x4000_1105);
+        assert_eq!(Extensions::from_isa("rv32i"), Some(base));
+        assert_eq!(Extensions::from_isa("rv32imf"), None);
+        assert_eq!(Extensions::from_isa("rv64i"), None);
+    }
+
+    #[test]
+    fn csr_file() {
+        let mut file = CsrFile::default();
+        assert_eq!(file.iter().count(), STANDARD.len());
+
+        // WARL: the mask, and mstatus.MPP keeping to implemented modes
+        assert!(file.write(MEPC, 0x103));
+        assert_eq!(file.read(MEPC), Some(0x100));
+        assert!(file.write(MSTATUS, u32::MAX));
+        assert_eq!(file[MSTATUS], MSTATUS_MASK);
+        file.write(MSTATUS, 0b01 << 11);
+        assert_eq!(file[MSTATUS], MSTATUS_MPP);
+        assert!(!file.write(0x7c0, 1));
+
+        let custom = CsrDef::new("custom", 0x7c0).with_reset(5);
+        file.define(custom).unwrap();
+        assert_eq!(file.read(0x7c0), Some(5));
+        assert_eq!(file.get(0x7c0).unwrap().name, "custom");
+        assert_eq!(
+            file.define(CsrDef::new("other", MSTATUS)),
+            Err(CsrError::AddressTaken(MSTATUS))
+        );
+        assert_eq!(
+            file.define(CsrDef::new("MSCRATCH", 0x7c1)),
+            Err(CsrError::NameTaken("MSCRATCH".to_owned()))
+        );
+        assert_eq!(
+            file.define(CsrDef::new("big", 0x1000)),
+            Err(CsrError::OutOfRange(0x1000))
+        );
+
+        // in order of address
+        let addresses: Vec<u32> = file.iter().map(|(def, _)| def.address).collect();
+        assert!(addresses.windows(2).all(|pair| pair[0] < pair[1]));
+
+        file[0x7c0] = 0;
+        file[MEPC] = 3;
+        file.reset();
+        assert_eq!((file[0x7c0], file[MEPC]), (5, 0));
+    }
+
+    #[test]
+    fn field_descriptions() {
+        assert_eq!(
+            describe_fields(MSTATUS, MSTATUS_MPP | MSTATUS_MIE).unwrap(),
+            "MPP=Machine, MPIE=0, MIE=1"
+        );
+        assert_eq!(
+            describe_fields(MTVEC, 0x101).unwrap(),
+            "BASE=0x100, MODE=Vectored"
+        );
+        assert_eq!(describe_fields(MTVEC, 0x102).unwrap(), "BASE=0x100, MODE=2");
+        assert_eq!(
+            describe_fields(MIP, 1 << 7).unwrap(),
+            "MEIP=0, MTIP=1, MSIP=0"
+        );
+        assert_eq!(describe_fields(MSCRATCH, 5), None);
+    }
+}
@@ -0,0 +1,622 @@
+//! Machine code for instructions: the 32-bit words a program is stored as.
+//!
+//! Every RV32I instruction has a 7-bit `opcode`, and most have a `funct3`
+//! (and R-type, a `funct7`) to tell apart instructions that share an opcode.
+//! Register numbers and immediates are packed around them according to the
+//! instruction's [Format](crate::metadata::Format).
+//!
+//! ```
+//! use brubeck::rv32_i::*;
+//!
+//! assert_eq!(Instruction::NOP.encode(), 0x0000_0013);
+//!
+//! let mut addi = IType::default();
+//! addi.rd = Register::X1;
+//! addi.imm.set_signed(-1).unwrap();
+//! assert_eq!(Instruction::ADDI(addi).encode(), 0xfff0_0093);
+//!
+//! // and back again
+//! assert_eq!(Instruction::decode(0xfff0_0093), Ok(Instruction::ADDI(addi)));
+//! assert_eq!(
+//!     Instruction::decode(0xffff_ffff).unwrap_err().to_string(),
+//!     "0xffffffff: unknown opcode 0b1111111"
+//! );
+//! ```
+//!
+//! Decoding is the inverse of encoding, except that `0x00000013`
+//! (`ADDI zero, zero, 0`) decodes to its usual name, [Instruction::NOP], and
+//! that the atomic instructions' `aq` and `rl` ordering bits are ignored
+//! (with one hart, every access is already in order) and encoded as zero.
+//!
+//! The tests check the round trip on random instructions, and that any word
+//! decodes (or doesn't) without panicking; `fuzz/` has the same check as a
+//! `cargo fuzz` target.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use super::*;
+use crate::bits::field as bits;
+use crate::Immediate;
+
+// Major opcodes, the bottom 7 bits of every (uncompressed) instruction
+pub const OP: u32 = 0b011_0011;
+pub const OP_IMM: u32 = 0b001_0011;
+pub const OP_32: u32 = 0b011_1011;
+pub const OP_IMM_32: u32 = 0b001_1011;
+pub const LOAD: u32 = 0b000_0011;
+pub const STORE: u32 = 0b010_0011;
+pub const BRANCH: u32 = 0b110_0011;
+pub const JAL: u32 = 0b110_1111;
+pub const JALR: u32 = 0b110_0111;
+pub const LUI: u32 = 0b011_0111;
+pub const AUIPC: u32 = 0b001_0111;
+pub const MISC_MEM: u32 = 0b000_1111;
+pub const SYSTEM: u32 = 0b111_0011;
+pub const AMO: u32 = 0b010_1111;
+
+/// The `aq` and `rl` bits at the bottom of an atomic instruction's `funct7`
+const ORDERING: u32 = 0b000_0011;
+
+/// What [Instruction::decode] looks words up in, built the first time it's
+/// needed
+struct DecodeTable {
+    /// The [EXACT](Instruction::EXACT) instructions, with their words
+    exact: Vec<(u32, Instruction)>,
+    /// The other instructions, indexed by opcode
+    by_opcode: Vec<Vec<Instruction>>,
+}
+
+impl DecodeTable {
+    fn get() -> &'static Self {
+        static TABLE: OnceLock<DecodeTable> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            let mut by_opcode = vec![vec![]; 1 << 7];
+            for instruction in Instruction::all() {
+                if !Instruction::EXACT.contains(&instruction) {
+                    by_opcode[instruction.fields().0 as usize].push(instruction);
+                }
+            }
+            Self {
+                exact: Instruction::EXACT.map(|i| (i.encode(), i)).to_vec(),
+                by_opcode,
+            }
+        })
+    }
+}
+
+impl Instruction {
+    /// The instruction as a 32-bit machine word
+    pub fn encode(&self) -> u32 {
+        let (opcode, funct3, funct7) = self.fields();
+
+        match self.operands() {
+            // every field but these (and a system instruction's immediate)
+            // is zero
+            Operands::None => self.system_function() << 20 | funct3 << 12 | opcode,
+            Operands::RType(r) => {
+                funct7 << 25
+                    | number(r.rs2) << 20
+                    | number(r.rs1) << 15
+                    | funct3 << 12
+                    | number(r.rd) << 7
+                    | opcode
+            }
+            Operands::IType(i) => {
+                // shifts by an immediate keep their funct7 in the top bits
+                let imm = (i.imm.as_u32() & 0xfff) | funct7 << 5;
+                imm << 20 | number(i.rs1) << 15 | funct3 << 12 | number(i.rd) << 7 | opcode
+            }
+            Operands::SType(s) => {
+                let imm = s.imm.as_u32();
+                bits(imm, 11, 5) << 25
+                    | number(s.rs2) << 20
+                    | number(s.rs1) << 15
+                    | funct3 << 12
+                    | bits(imm, 4, 0) << 7
+                    | opcode
+            }
+            Operands::BType(b) => {
+                // the immediate counts 2 byte steps; the encoding scatters
+                // bits 12..1 of the byte offset
+                let offset = b.imm.as_u32() << 1;
+                bits(offset, 12, 12) << 31
+                    | bits(offset, 10, 5) << 25
+                    | number(b.rs2) << 20
+                    | number(b.rs1) << 15
+                    | funct3 << 12
+                    | bits(offset, 4, 1) << 8
+                    | bits(offset, 11, 11) << 7
+                    | opcode
+            }
+            Operands::UType(u) => bits(u.imm.as_u32(), 19, 0) << 12 | number(u.rd) << 7 | opcode,
+            Operands::JType(j) => {
+                let offset = j.imm.as_u32() << 1;
+                bits(offset, 20, 20) << 31
+                    | bits(offset, 10, 1) << 21
+                    | bits(offset, 11, 11) << 20
+                    | bits(offset, 19, 12) << 12
+                    | number(j.rd) << 7
+                    | opcode
+            }
+        }
+    }
+
+    /// The instruction a 32-bit machine word encodes
+    pub fn decode(word: u32) -> Result<Self, DecodeError> {
+        let table = DecodeTable::get();
+        if let Some(&(_, exact)) = table.exact.iter().find(|(w, _)| *w == word) {
+            return Ok(exact);
+        }
+        if word == 0 {
+            return Err(DecodeError::Zero);
+        }
+        if bits(word, 1, 0) != 0b11 {
+            return Err(DecodeError::Compressed(word));
+        }
+
+        let opcode = bits(word, 6, 0);
+        let funct3 = bits(word, 14, 12);
+        let funct7 = match opcode {
+            AMO => bits(word, 31, 25) & !ORDERING,
+            // RV64's shift amounts take the bottom bit too
+            OP_IMM => bits(word, 31, 25) & !1,
+            _ => bits(word, 31, 25),
+        };
+
+        let candidates = &table.by_opcode[opcode as usize];
+        if candidates.is_empty() {
+            return Err(DecodeError::UnknownOpcode { word, opcode });
+        }
+
+        let instruction = *candidates
+            .iter()
+            .find(|i| {
+                let (_, f3, f7) = i.fields();
+                let operands = i.operands();
+                let has_funct3 = !matches!(operands, Operands::UType(_) | Operands::JType(_));
+                let has_funct7 = matches!(operands, Operands::RType(_)) || i.is_shift();
+                (!has_funct3 || f3 == funct3) && (!has_funct7 || f7 == funct7)
+            })
+            .ok_or(DecodeError::UnknownFunction {
+                word,
+                funct3,
+                funct7,
+            })?;
+
+        let rd = register(bits(word, 11, 7));
+        let rs1 = register(bits(word, 19, 15));
+        let rs2 = register(bits(word, 24, 20));
+
+        let operands = match instruction.operands() {
+            Operands::None => Operands::None,
+            Operands::RType(mut r) => {
+                (r.rd, r.rs1, r.rs2) = (rd, rs1, rs2);
+                Operands::RType(r)
+            }
+            Operands::IType(mut i) => {
+                (i.rd, i.rs1) = (rd, rs1);
+                // shifts keep their funct7 in the top of the immediate; RV64
+                // shifts by up to 63, except for the W forms
+                let imm = match instruction.fields().0 {
+                    OP_IMM if instruction.is_shift() => bits(word, 25, 20),
+                    _ if instruction.is_shift() => bits(word, 24, 20),
+                    _ => bits(word, 31, 20),
+                };
+                set(&mut i.imm, imm);
+                Operands::IType(i)
+            }
+            Operands::SType(mut s) => {
+                (s.rs1, s.rs2) = (rs1, rs2);
+                set(&mut s.imm, bits(word, 31, 25) << 5 | bits(word, 11, 7));
+                Operands::SType(s)
+            }
+            Operands::BType(mut b) => {
+                (b.rs1, b.rs2) = (rs1, rs2);
+                let offset = bits(word, 31, 31) << 12
+                    | bits(word, 7, 7) << 11
+                    | bits(word, 30, 25) << 5
+                    | bits(word, 11, 8) << 1;
+                set(&mut b.imm, offset >> 1);
+                Operands::BType(b)
+            }
+            Operands::UType(mut u) => {
+                u.rd = rd;
+                set(&mut u.imm, bits(word, 31, 12));
+                Operands::UType(u)
+            }
+            Operands::JType(mut j) => {
+                j.rd = rd;
+                let offset = bits(word, 31, 31) << 20
+                    | bits(word, 19, 12) << 12
+                    | bits(word, 20, 20) << 11
+                    | bits(word, 30, 21) << 1;
+                set(&mut j.imm, offset >> 1);
+                Operands::JType(j)
+            }
+        };
+
+        let decoded = instruction
+            .with_operands(operands)
+            .expect("operands come from the instruction");
+
+        // ECALL and EBREAK share everything but the immediate, and have no
+        // registers; anything else with their fields is illegal
+        match decoded {
+            Self::ECALL(i) | Self::EBREAK(i) => {
+                let mut ebreak = IType::default();
+                set(&mut ebreak.imm, 1);
+                if i == IType::default() {
+                    Ok(Self::ECALL(i))
+                } else if i == ebreak {
+                    Ok(Self::EBREAK(i))
+                } else {
+                    Err(DecodeError::UnknownFunction {
+                        word,
+                        funct3,
+                        funct7,
+                    })
+                }
+            }
+            _ => Ok(decoded),
+        }
+    }
+
+    /// Instructions with a single encoding, which are told apart from the
+    /// ones sharing their fields by the whole word
+    const EXACT: [Self; 3] = [Self::NOP, Self::MRET, Self::WFI];
+
+    /// The immediate of a system instruction without operands, which says
+    /// which one it is
+    fn system_function(&self) -> u32 {
+        match self {
+            Self::MRET => 0x302,
+            Self::WFI => 0x105,
+            _ => 0,
+        }
+    }
+
+    /// Shifts by an immediate, which have a `funct7` in their immediate
+    pub fn is_shift(&self) -> bool {
+        matches!(
+            self,
+            Self::SLLI(_)
+                | Self::SRLI(_)
+                | Self::SRAI(_)
+                | Self::SLLIW(_)
+                | Self::SRLIW(_)
+                | Self::SRAIW(_)
+        )
+    }
+}
+
+/// Register number (0 to 31)
+fn number(register: Register) -> u32 {
+    register as u32
+}
+
+/// The register with a number from an encoding (which is only 5 bits)
+fn register(number: u32) -> Register {
+    Register::ALL[number as usize]
+}
+
+/// Sets an immediate from the bits of an encoding, which always fit
+fn set(imm: &mut Immediate, value: u32) {
+    imm.set_unsigned(value)
+        .expect("the field is as wide as the immediate");
+}
+
+/// Why a word isn't an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// All zeros, which is defined to be illegal so that running into
+    /// cleared memory is caught
+    Zero,
+    /// The low bits aren't `0b11`, so it's a 16-bit compressed instruction;
+    /// see [Instruction::decode_compressed]
+    Compressed(u32),
+    /// A compressed instruction that's reserved, or needs an extension this
+    /// CPU doesn't have (eg: floating point)
+    IllegalCompressed(u16),
+    UnknownOpcode {
+        word: u32,
+        opcode: u32,
+    },
+    /// The opcode is known, but not with these `funct3` and `funct7`
+    /// fields (or, for ECALL and EBREAK, this immediate)
+    UnknownFunction {
+        word: u32,
+        funct3: u32,
+        funct7: u32,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Zero => write!(f, "0x00000000: all zeros is never an instruction"),
+            Self::Compressed(word) => write!(
+                f,
+                "0x{word:08x}: a compressed (16-bit) instruction, which needs the C extension"
+            ),
+            Self::IllegalCompressed(half) => {
+                write!(f, "0x{half:04x}: not a supported compressed instruction")
+            }
+            Self::UnknownOpcode { word, opcode } => {
+                write!(f, "0x{word:08x}: unknown opcode 0b{opcode:07b}")
+            }
+            Self::UnknownFunction {
+                word,
+                funct3,
+                funct7,
+            } => write!(
+                f,
+                "0x{word:08x}: no instruction has funct3 0b{funct3:03b} and funct7 \
+                 0b{funct7:07b} with this opcode"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::memory::splitmix64;
+
+    /// A random instruction, with random values for the operands in its
+    /// syntax (the fields it doesn't have are left zero, as an assembler
+    /// leaves them)
+    fn arbitrary(seed: u64) -> Instruction {
+        let mut state = seed;
+        let mut random = |bound: u64| {
+            state = splitmix64(state);
+            state % bound
+        };
+
+        let all = Instruction::all();
+        let instruction = all[random(all.len() as u64) as usize];
+        let info = instruction.info();
+        let (mut rd, mut rs1, mut rs2) = (Register::X0, Register::X0, Register::X0);
+        // the immediate, and whether it's unsigned
+        let mut imm = (0, false);
+        for operand in info.syntax().split(", ") {
+            match operand {
+                "rd" => rd = register(random(32) as u32),
+                // a CSR instruction's uimm is where rs1 would be
+                "rs1" | "uimm" => rs1 = register(random(32) as u32),
+                "rs2" => rs2 = register(random(32) as u32),
+                "csr" => imm = (random(0x1000) as i32, true),
+                "shamt" => imm = (random(32) as i32, true),
+                "imm" => {
+                    let range = info.immediate_range().unwrap();
+                    let span = (range.end() - range.start()) as u64 + 1;
+                    imm = (range.start() + random(span) as i32, false);
+                }
+                _ => {}
+            }
+        }
+        // EBREAK is ECALL with an immediate of 1
+        if let Instruction::EBREAK(_) = instruction {
+            imm = (1, true);
+        }
+
+        let set = |immediate: &mut Immediate| match imm {
+            (value, true) => immediate.set_unsigned(value as u32).unwrap(),
+            (value, false) => immediate.set_signed(value).unwrap(),
+        };
+        let operands = match instruction.operands() {
+            Operands::None => Operands::None,
+            Operands::RType(mut r) => {
+                (r.rd, r.rs1, r.rs2) = (rd, rs1, rs2);
+                Operands::RType(r)
+            }
+            Operands::IType(mut i) => {
+                (i.rd, i.rs1) = (rd, rs1);
+                set(&mut i.imm);
+                Operands::IType(i)
+            }
+            Operands::SType(mut s) => {
+                (s.rs1, s.rs2) = (rs1, rs2);
+                set(&mut s.imm);
+                Operands::SType(s)
+            }
+            Operands::BType(mut b) => {
+                (b.rs1, b.rs2) = (rs1, rs2);
+                set(&mut b.imm);
+                Operands::BType(b)
+            }
+            Operands::UType(mut u) => {
+                u.rd = rd;
+                set(&mut u.imm);
+                Operands::UType(u)
+            }
+            Operands::JType(mut j) => {
+                j.rd = rd;
+                set(&mut j.imm);
+                Operands::JType(j)
+            }
+        };
+        instruction.with_operands(operands).unwrap()
+    }
+
+    #[test]
+    fn encodings() {
+        // expected words are from the GNU assembler
+        let mut add = RType::default();
+        add.rd = Register::X3;
+        add.rs1 = Register::X1;
+        add.rs2 = Register::X2;
+        assert_eq!(Instruction::ADD(add).encode(), 0x0020_81b3);
+        assert_eq!(Instruction::SUB(add).encode(), 0x4020_81b3);
+
+        let mut srai = IType::default();
+        srai.rd = Register::X5;
+        srai.rs1 = Register::X5;
+        srai.imm.set_unsigned(3).unwrap();
+        assert_eq!(Instruction::SRAI(srai).encode(), 0x4032_d293);
+
+        let mut sw = SType::default();
+        sw.rs1 = Register::X2;
+        sw.rs2 = Register::X1;
+        sw.imm.set_signed(-4).unwrap();
+        assert_eq!(Instruction::SW(sw).encode(), 0xfe11_2e23);
+
+        // beq x1, x2, -8
+        let mut beq = BType::default();
+        beq.rs1 = Register::X1;
+        beq.rs2 = Register::X2;
+        beq.imm.set_signed(-4).unwrap();
+        assert_eq!(Instruction::BEQ(beq).encode(), 0xfe20_8ce3);
+
+        // jal ra, 2048
+        let mut jal = JType::default();
+        jal.rd = Register::X1;
+        jal.imm.set_signed(1024).unwrap();
+        assert_eq!(Instruction::JAL(jal).encode(), 0x0010_00ef);
+
+        let mut lui = UType::default();
+        lui.rd = Register::X10;
+        lui.imm.set_unsigned(0x80000).unwrap();
+        assert_eq!(Instruction::LUI(lui).encode(), 0x8000_0537);
+
+        let mut ebreak = IType::default();
+        ebreak.imm.set_unsigned(1).unwrap();
+        assert_eq!(Instruction::EBREAK(ebreak).encode(), 0x0010_0073);
+        assert_eq!(Instruction::ECALL(IType::default()).encode(), 0x0000_0073);
+
+        // csrrw ra, mscratch, sp
+        let mut csrrw = IType::default();
+        csrrw.rd = Register::X1;
+        csrrw.rs1 = Register::X2;
+        csrrw.imm.set_unsigned(csr::MSCRATCH).unwrap();
+        assert_eq!(Instruction::CSRRW(csrrw).encode(), 0x3401_10f3);
+
+        // mul x3, x1, x2 and remu x3, x1, x2
+        assert_eq!(Instruction::MUL(add).encode(), 0x0220_81b3);
+        assert_eq!(Instruction::REMU(add).encode(), 0x0220_f1b3);
+    }
+
+    #[test]
+    fn decodings() {
+        // the same words as above, from the GNU assembler
+        for word in [
+            0x0020_81b3,
+            0x4020_81b3,
+            0x4032_d293,
+            0xfe11_2e23,
+            0xfe20_8ce3,
+            0x0010_00ef,
+            0x8000_0537,
+            0x0010_0073,
+            0x0000_0073,
+            0x3401_10f3,
+            0x0220_81b3,
+            0x0220_f1b3,
+        ] {
+            let instruction = Instruction::decode(word).unwrap();
+            assert_eq!(instruction.encode(), word, "{instruction:?}");
+        }
+
+        let mut beq = BType::default();
+        beq.rs1 = Register::X1;
+        beq.rs2 = Register::X2;
+        beq.imm.set_signed(-4).unwrap();
+        assert_eq!(Instruction::decode(0xfe20_8ce3), Ok(Instruction::BEQ(beq)));
+
+        // every instruction survives a round trip
+        for instruction in Instruction::all() {
+            let decoded = Instruction::decode(instruction.encode()).unwrap();
+            match instruction {
+                Instruction::ADDI(_) => assert_eq!(decoded, Instruction::NOP),
+                // EBREAK is told apart by an immediate of 1, not the default
+                Instruction::EBREAK(_) => assert!(matches!(decoded, Instruction::ECALL(_))),
+                _ => assert_eq!(decoded, instruction),
+            }
+        }
+
+        assert_eq!(Instruction::decode(0), Err(DecodeError::Zero));
+        assert_eq!(
+            Instruction::decode(0x4501),
+            Err(DecodeError::Compressed(0x4501))
+        );
+        // SUB's funct7 with ADD's funct3, but XOR's
+        assert!(matches!(
+            Instruction::decode(0x4020_c1b3),
+            Err(DecodeError::UnknownFunction { funct3: 0b100, .. })
+        ));
+        // ECALL with a register set
+        assert!(Instruction::decode(0x0000_00f3).is_err());
+        // amoadd.w a0, a1, (a2) and lr.w.aqrl a0, (a2), from the GNU
+        // assembler; aq and rl are dropped
+        let mut amoadd = RType::default();
+        amoadd.rd = Register::X10;
+        amoadd.rs1 = Register::X12;
+        amoadd.rs2 = Register::X11;
+        assert_eq!(
+            Instruction::decode(0x00b6_252f),
+            Ok(Instruction::AMOADD_W(amoadd))
+        );
+        amoadd.rs2 = Register::X0;
+        assert_eq!(
+            Instruction::decode(0x1606_252f),
+            Ok(Instruction::LR_W(amoadd))
+        );
+        assert_eq!(Instruction::LR_W(amoadd).encode(), 0x1006_252f);
+
+        // MRET and WFI, from the LLVM assembler, which are ECALL's fields
+        // with another immediate
+        assert_eq!(Instruction::decode(0x3020_0073), Ok(Instruction::MRET));
+        assert_eq!(Instruction::decode(0x1050_0073), Ok(Instruction::WFI));
+        assert_eq!(Instruction::WFI.encode(), 0x1050_0073);
+        assert!(Instruction::decode(0x3030_0073).is_err());
+
+        // FENCE.I, which isn't FENCE
+        assert_eq!(Instruction::decode(0x0000_100f), Ok(Instruction::FENCE_I));
+        assert_eq!(Instruction::FENCE_I.encode(), 0x0000_100f);
+        assert!(matches!(
+            Instruction::decode(0x0ff0_000f),
+            Ok(Instruction::FENCE(_))
+        ));
+    }
+
+    #[test]
+    fn random_round_trips() {
+        for seed in 0..10_000 {
+            let instruction = arbitrary(seed);
+            let word = instruction.encode();
+            // ADDI zero, zero, 0 has its own name
+            let expected = match word == Instruction::NOP.encode() {
+                true => Instruction::NOP,
+                false => instruction,
+            };
+            assert_eq!(
+                Instruction::decode(word),
+                Ok(expected),
+                "seed {seed}: 0x{word:08x}"
+            );
+        }
+    }
+
+    #[test]
+    fn random_words() {
+        // whatever a word decodes to encodes to a word that decodes the same
+        // (if not always to the same word, since aq and rl are dropped)
+        for seed in 0..100_000 {
+            let word = splitmix64(seed) as u32;
+            if let Ok(instruction) = Instruction::decode(word) {
+                assert_eq!(
+                    Instruction::decode(instruction.encode()),
+                    Ok(instruction),
+                    "0x{word:08x}"
+                );
+            }
+        }
+        // and there are few enough halves to try them all
+        for half in 0..=u16::MAX {
+            let _ = Instruction::decode_compressed(half);
+        }
+    }
+}
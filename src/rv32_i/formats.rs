@@ -94,6 +94,14 @@ impl BType {
     }
 }
 
+impl BType {
+    /// Absolute branch target for the instruction at `pc`. The immediate
+    /// counts multiples of 2 bytes.
+    pub fn target(&self, pc: u32) -> u32 {
+        pc.wrapping_add(self.imm.as_u32() << 1)
+    }
+}
+
 impl Default for BType {
     fn default() -> Self {
         Self::new()
@@ -141,6 +149,12 @@ impl Default for JType {
 impl JType {
     const IMM_BITS: u8 = 20;
 
+    /// Absolute jump target for the instruction at `pc`. The immediate counts
+    /// multiples of 2 bytes, giving a range of ±1 MiB.
+    pub fn target(&self, pc: u32) -> u32 {
+        pc.wrapping_add(self.imm.as_u32() << 1)
+    }
+
     pub fn new() -> Self {
         Self {
             opcode: 0, // TODO
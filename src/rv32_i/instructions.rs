@@ -1,51 +1,289 @@
+use std::fmt::Display;
+
 use super::*;
+use crate::metadata;
+
+/// The single definition of the instruction set. Each entry is a variant of
+/// [Instruction] (with its operand format, if any, and its mnemonic if that
+/// isn't the variant's name), followed by its metadata: encoding format,
+/// [Category](crate::metadata::Category), encoding (the major
+/// [opcode](super::encoding::OP), then `funct3` and `funct7`, which are 0 if
+/// the format doesn't have them), and description.
+///
+/// `funct7` is `0b0100000` for the alternate forms (eg: `SUB`, `SRAI`) and
+/// `0b0000001` for the M extension. For the A extension it's a 5-bit `funct5`
+/// followed by the `aq` and `rl` bits, which are left as 0.
+///
+/// Everything that needs a list of instructions (the enum itself, mnemonic
+/// lookups, the [metadata](crate::metadata) table) is generated from this by
+/// passing in a macro to expand it with, so adding an instruction only means
+/// adding a line here (and implementing it in the [CPU]).
+macro_rules! rv32i_instructions {
+    ($expand:ident) => {
+        $expand! {
+            ADD(RType): R, Arithmetic, OP 0b000 0, "rd = rs1 + rs2";
+            ADDI(IType): I, Arithmetic, OP_IMM 0b000 0, "rd = rs1 + imm";
+            ADDIW(IType): I, Rv64, OP_IMM_32 0b000 0, "rd = rs1 + imm, in 32 bits sign extended";
+            ADDW(RType): R, Rv64, OP_32 0b000 0, "rd = rs1 + rs2, in 32 bits sign extended";
+            AMOADD_W(RType) as "AMOADD.W": R, Atomic, AMO 0b010 0b0000000, "rd = word at rs1; word at rs1 += rs2";
+            AMOAND_W(RType) as "AMOAND.W": R, Atomic, AMO 0b010 0b0110000, "rd = word at rs1; word at rs1 &= rs2";
+            AMOMAX_W(RType) as "AMOMAX.W": R, Atomic, AMO 0b010 0b1010000, "rd = word at rs1; word at rs1 = max(it, rs2) (signed)";
+            AMOMAXU_W(RType) as "AMOMAXU.W": R, Atomic, AMO 0b010 0b1110000, "rd = word at rs1; word at rs1 = max(it, rs2) (unsigned)";
+            AMOMIN_W(RType) as "AMOMIN.W": R, Atomic, AMO 0b010 0b1000000, "rd = word at rs1; word at rs1 = min(it, rs2) (signed)";
+            AMOMINU_W(RType) as "AMOMINU.W": R, Atomic, AMO 0b010 0b1100000, "rd = word at rs1; word at rs1 = min(it, rs2) (unsigned)";
+            AMOOR_W(RType) as "AMOOR.W": R, Atomic, AMO 0b010 0b0100000, "rd = word at rs1; word at rs1 |= rs2";
+            AMOSWAP_W(RType) as "AMOSWAP.W": R, Atomic, AMO 0b010 0b0000100, "rd = word at rs1; word at rs1 = rs2";
+            AMOXOR_W(RType) as "AMOXOR.W": R, Atomic, AMO 0b010 0b0010000, "rd = word at rs1; word at rs1 ^= rs2";
+            AND(RType): R, Logical, OP 0b111 0, "rd = rs1 & rs2";
+            ANDI(IType): I, Logical, OP_IMM 0b111 0, "rd = rs1 & imm";
+            AUIPC(UType): U, Arithmetic, AUIPC 0b000 0, "rd = pc + (imm << 12)";
+            BEQ(BType): B, Branch, BRANCH 0b000 0, "branch if rs1 == rs2";
+            BGE(BType): B, Branch, BRANCH 0b101 0, "branch if rs1 >= rs2 (signed)";
+            BGEU(BType): B, Branch, BRANCH 0b111 0, "branch if rs1 >= rs2 (unsigned)";
+            BLT(BType): B, Branch, BRANCH 0b100 0, "branch if rs1 < rs2 (signed)";
+            BLTU(BType): B, Branch, BRANCH 0b110 0, "branch if rs1 < rs2 (unsigned)";
+            BNE(BType): B, Branch, BRANCH 0b001 0, "branch if rs1 != rs2";
+            CSRRC(IType): I, Csr, SYSTEM 0b011 0, "rd = csr; csr &= !rs1";
+            CSRRCI(IType): I, Csr, SYSTEM 0b111 0, "rd = csr; csr &= !uimm";
+            CSRRS(IType): I, Csr, SYSTEM 0b010 0, "rd = csr; csr |= rs1";
+            CSRRSI(IType): I, Csr, SYSTEM 0b110 0, "rd = csr; csr |= uimm";
+            CSRRW(IType): I, Csr, SYSTEM 0b001 0, "rd = csr; csr = rs1";
+            CSRRWI(IType): I, Csr, SYSTEM 0b101 0, "rd = csr; csr = uimm";
+            DIV(RType): R, Multiply, OP 0b100 0b0000001, "rd = rs1 / rs2 (signed, rounding toward zero)";
+            DIVU(RType): R, Multiply, OP 0b101 0b0000001, "rd = rs1 / rs2 (unsigned)";
+            EBREAK(IType): I, System, SYSTEM 0b000 0, "stop at a breakpoint, or make a semihosting call";
+            ECALL(IType): I, System, SYSTEM 0b000 0, "call the execution environment";
+            FENCE(IType): I, System, MISC_MEM 0b000 0, "order memory accesses";
+            FENCE_I as "FENCE.I": I, System, MISC_MEM 0b001 0, "synchronize instruction fetches with stores to memory";
+            JAL(JType): J, Jump, JAL 0b000 0, "rd = pc + 4; pc += imm";
+            JALR(IType): I, Jump, JALR 0b000 0, "rd = pc + 4; pc = (rs1 + imm) & !1";
+            LB(IType): I, LoadStore, LOAD 0b000 0, "rd = byte at rs1 + imm";
+            LBU(IType): I, LoadStore, LOAD 0b100 0, "rd = byte at rs1 + imm, zero extended";
+            LD(IType): I, Rv64, LOAD 0b011 0, "rd = doubleword at rs1 + imm";
+            LH(IType): I, LoadStore, LOAD 0b001 0, "rd = halfword at rs1 + imm";
+            LHU(IType): I, LoadStore, LOAD 0b101 0, "rd = halfword at rs1 + imm, zero extended";
+            LR_W(RType) as "LR.W": R, Atomic, AMO 0b010 0b0001000, "rd = word at rs1, and reserve it";
+            LUI(UType): U, Arithmetic, LUI 0b000 0, "rd = imm << 12";
+            LW(IType): I, LoadStore, LOAD 0b010 0, "rd = word at rs1 + imm";
+            LWU(IType): I, Rv64, LOAD 0b110 0, "rd = word at rs1 + imm, zero extended";
+            MRET: I, System, SYSTEM 0b000 0, "return from a machine mode trap: pc = mepc, and restore the mode";
+            MUL(RType): R, Multiply, OP 0b000 0b0000001, "rd = low 32 bits of rs1 * rs2";
+            MULH(RType): R, Multiply, OP 0b001 0b0000001, "rd = high 32 bits of rs1 * rs2 (signed)";
+            MULHSU(RType): R, Multiply, OP 0b010 0b0000001, "rd = high 32 bits of rs1 * rs2 (signed * unsigned)";
+            MULHU(RType): R, Multiply, OP 0b011 0b0000001, "rd = high 32 bits of rs1 * rs2 (unsigned)";
+            NOP: I, Pseudo, OP_IMM 0b000 0, "do nothing (ADDI zero, zero, 0)";
+            OR(RType): R, Logical, OP 0b110 0, "rd = rs1 | rs2";
+            ORI(IType): I, Logical, OP_IMM 0b110 0, "rd = rs1 | imm";
+            REM(RType): R, Multiply, OP 0b110 0b0000001, "rd = rs1 % rs2 (signed, sign of rs1)";
+            REMU(RType): R, Multiply, OP 0b111 0b0000001, "rd = rs1 % rs2 (unsigned)";
+            SB(SType): S, LoadStore, STORE 0b000 0, "byte at rs1 + imm = rs2";
+            SC_W(RType) as "SC.W": R, Atomic, AMO 0b010 0b0001100, "if rs1 is reserved, word at rs1 = rs2 and rd = 0; else rd = 1";
+            SD(SType): S, Rv64, STORE 0b011 0, "doubleword at rs1 + imm = rs2";
+            SH(SType): S, LoadStore, STORE 0b001 0, "halfword at rs1 + imm = rs2";
+            SLL(RType): R, Logical, OP 0b001 0, "rd = rs1 << rs2";
+            SLLI(IType): I, Logical, OP_IMM 0b001 0, "rd = rs1 << shamt";
+            SLLIW(IType): I, Rv64, OP_IMM_32 0b001 0, "rd = rs1 << shamt, in 32 bits sign extended";
+            SLLW(RType): R, Rv64, OP_32 0b001 0, "rd = rs1 << rs2, in 32 bits sign extended";
+            SLT(RType): R, Arithmetic, OP 0b010 0, "rd = 1 if rs1 < rs2 (signed), else 0";
+            SLTI(IType): I, Arithmetic, OP_IMM 0b010 0, "rd = 1 if rs1 < imm (signed), else 0";
+            SLTIU(IType): I, Arithmetic, OP_IMM 0b011 0, "rd = 1 if rs1 < imm (unsigned), else 0";
+            SLTU(RType): R, Arithmetic, OP 0b011 0, "rd = 1 if rs1 < rs2 (unsigned), else 0";
+            SRA(RType): R, Logical, OP 0b101 0b0100000, "rd = rs1 >> rs2 (arithmetic)";
+            SRAI(IType): I, Logical, OP_IMM 0b101 0b0100000, "rd = rs1 >> shamt (arithmetic)";
+            SRAIW(IType): I, Rv64, OP_IMM_32 0b101 0b0100000, "rd = rs1 >> shamt (arithmetic), in 32 bits sign extended";
+            SRAW(RType): R, Rv64, OP_32 0b101 0b0100000, "rd = rs1 >> rs2 (arithmetic), in 32 bits sign extended";
+            SRL(RType): R, Logical, OP 0b101 0, "rd = rs1 >> rs2 (logical)";
+            SRLI(IType): I, Logical, OP_IMM 0b101 0, "rd = rs1 >> shamt (logical)";
+            SRLIW(IType): I, Rv64, OP_IMM_32 0b101 0, "rd = rs1 >> shamt (logical), in 32 bits sign extended";
+            SRLW(RType): R, Rv64, OP_32 0b101 0, "rd = rs1 >> rs2 (logical), in 32 bits sign extended";
+            SUB(RType): R, Arithmetic, OP 0b000 0b0100000, "rd = rs1 - rs2";
+            SUBW(RType): R, Rv64, OP_32 0b000 0b0100000, "rd = rs1 - rs2, in 32 bits sign extended";
+            SW(SType): S, LoadStore, STORE 0b010 0, "word at rs1 + imm = rs2";
+            WFI: I, System, SYSTEM 0b000 0, "wait for an interrupt (a hint; it carries on at once)";
+            XOR(RType): R, Logical, OP 0b100 0, "rd = rs1 ^ rs2";
+            XORI(IType): I, Logical, OP_IMM 0b100 0, "rd = rs1 ^ imm";
+        }
+    };
+}
+
+pub(crate) use rv32i_instructions;
+
+macro_rules! define_instructions {
+    ($($name:ident $(($format:ident))? $(as $mnemonic:literal)? :
+        $_f:ident, $category:ident, $opcode:ident $funct3:literal $funct7:literal, $_d:literal;)*) => {
+        #[derive(Debug, Copy, Clone, PartialEq)]
+        #[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+        pub enum Instruction {
+            $($name $(($format))?,)*
+        }
+
+        impl Instruction {
+            /// Every instruction, with default operands, in alphabetical order
+            pub fn all() -> Vec<Self> {
+                vec![$(Self::$name $(($format::default()))?,)*]
+            }
+
+            /// The mnemonic for the instruction, in upper case (eg: `ADDI`)
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $(Self::$name { .. } => define_instructions!(@mnemonic $name $($mnemonic)?),)*
+                }
+            }
+
+            /// What the instruction does, broadly
+            pub fn category(&self) -> metadata::Category {
+                match self {
+                    $(Self::$name { .. } => metadata::Category::$category,)*
+                }
+            }
+
+            /// `opcode`, `funct3`, and `funct7`; fields a format doesn't have
+            /// are 0
+            pub(crate) fn fields(&self) -> (u32, u32, u32) {
+                match self {
+                    $(Self::$name { .. } => (encoding::$opcode, $funct3, $funct7),)*
+                }
+            }
+
+            /// An instruction with default operands, from its upper case
+            /// mnemonic
+            pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+                match mnemonic {
+                    $(define_instructions!(@mnemonic $name $($mnemonic)?) =>
+                        Some(Self::$name $(($format::default()))?),)*
+                    _ => None,
+                }
+            }
+
+            /// The instruction's operands
+            pub fn operands(&self) -> Operands {
+                match self {
+                    $(define_instructions!(@pattern $name $(($format))?, operands) =>
+                        define_instructions!(@operands $($format)?, operands),)*
+                }
+            }
+
+            /// The same instruction with different operands; `None` if they
+            /// aren't the right format.
+            pub fn with_operands(&self, operands: Operands) -> Option<Self> {
+                match (self, operands) {
+                    $(define_instructions!(@replace $name $(($format))?, operands) =>
+                        Some(define_instructions!(@build $name $(($format))?, operands)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
 
+    (@mnemonic $name:ident) => { stringify!($name) };
+    (@mnemonic $name:ident $mnemonic:literal) => { $mnemonic };
+    (@pattern $name:ident ($format:ident), $bind:ident) => { Self::$name($bind) };
+    (@pattern $name:ident, $bind:ident) => { Self::$name };
+    (@operands $format:ident, $bind:ident) => { Operands::$format(*$bind) };
+    (@operands , $bind:ident) => { Operands::None };
+    (@replace $name:ident ($format:ident), $bind:ident) => {
+        (Self::$name(_), Operands::$format($bind))
+    };
+    (@replace $name:ident, $bind:ident) => { (Self::$name, Operands::None) };
+    (@build $name:ident ($format:ident), $bind:ident) => { Self::$name($bind) };
+    (@build $name:ident, $bind:ident) => { Self::$name };
+}
+
+rv32i_instructions!(define_instructions);
+
+/// The operands of an [Instruction], by format
 #[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Instruction {
-    // ✅ indicates it's implemented, not verified!
-    ADD(RType),   // ✅
-    ADDI(IType),  // ✅
-    AND(RType),   // ✅
-    ANDI(IType),  // ✅
-    AUIPC(UType), // ✅
-    BEQ(BType),   // ✅
-    BGE(BType),   // ✅
-    BGEU(BType),  // ✅
-    BLT(BType),   // ✅
-    BLTU(BType),  // ✅
-    BNE(BType),   // ✅
-    EBREAK(IType),
-    ECALL(IType),
-    FENCE(IType),
-    JAL(JType),   // ✅
-    JALR(IType),  // ✅
-    LB(IType),    // ✅
-    LBU(IType),   // ✅
-    LH(IType),    // ✅
-    LHU(IType),   // ✅
-    LUI(UType),   // ✅
-    LW(IType),    // ✅
-    NOP,          // ✅
-    OR(RType),    // ✅
-    ORI(IType),   // ✅
-    SB(SType),    // ✅
-    SH(SType),    // ✅
-    SLL(RType),   // ✅
-    SLLI(IType),  // ✅
-    SLT(RType),   // ✅
-    SLTI(IType),  // ✅
-    SLTIU(IType), // ✅
-    SLTU(RType),  // ✅
-    SRA(RType),   // ✅
-    SRAI(IType),  // ✅
-    SRL(RType),   // ✅
-    SRLI(IType),  // ✅
-    SUB(RType),   // ✅
-    SW(SType),    // ✅
-    XOR(RType),   // ✅
-    XORI(IType),  // ✅
+#[allow(clippy::enum_variant_names)]
+pub enum Operands {
+    None,
+    RType(RType),
+    IType(IType),
+    SType(SType),
+    BType(BType),
+    UType(UType),
+    JType(JType),
 }
 
 impl Instruction {
     pub const LENGTH: u32 = 4; // 4 bytes, 32 bits
+
+    /// Part of the M extension (multiplication and division)
+    pub fn is_multiply(&self) -> bool {
+        self.category() == metadata::Category::Multiply
+    }
+
+    /// Only in RV64I: the 32-bit `W` arithmetic, and doubleword loads and
+    /// stores (see [Xlen])
+    pub fn is_rv64(&self) -> bool {
+        self.category() == metadata::Category::Rv64
+    }
+
+    /// Part of the A extension (atomic memory operations)
+    pub fn is_atomic(&self) -> bool {
+        self.category() == metadata::Category::Atomic
+    }
+
+    /// Everything the [metadata](crate::metadata) table says about the
+    /// instruction
+    pub fn info(&self) -> &'static metadata::InstructionInfo {
+        metadata::lookup(self.mnemonic()).expect("every instruction is in the table")
+    }
+}
+
+/// Assembly syntax, as the [parser](crate::interpreter::parse_listing) reads it: a
+/// lower case mnemonic, then operands in the order of the instruction's
+/// [syntax](metadata::InstructionInfo::syntax), with ABI register names,
+/// CSRs by name, and immediates as the bits that are encoded (so `-1` in a
+/// 12-bit immediate is `4095`).
+///
+/// ```
+/// use brubeck::rv32_i::*;
+///
+/// let mut sw = SType::default();
+/// sw.rs1 = Register::X2;
+/// sw.rs2 = Register::X10;
+/// sw.imm.set_unsigned(8).unwrap();
+/// assert_eq!(Instruction::SW(sw).to_string(), "sw sp, a0, 8");
+/// ```
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let register = |register: Register| match register.abi() {
+            Some(abi) => abi.name().to_lowercase(),
+            None => register.to_string(),
+        };
+
+        let (rd, rs1, rs2, imm) = match self.operands() {
+            Operands::None => return write!(f, "{}", self.mnemonic().to_lowercase()),
+            Operands::RType(r) => (Some(r.rd), Some(r.rs1), Some(r.rs2), None),
+            Operands::IType(i) => (Some(i.rd), Some(i.rs1), None, Some(i.imm)),
+            Operands::SType(s) => (None, Some(s.rs1), Some(s.rs2), Some(s.imm)),
+            Operands::BType(b) => (None, Some(b.rs1), Some(b.rs2), Some(b.imm)),
+            Operands::UType(u) => (Some(u.rd), None, None, Some(u.imm)),
+            Operands::JType(j) => (Some(j.rd), None, None, Some(j.imm)),
+        };
+
+        let syntax = metadata::lookup(self.mnemonic()).map_or("", |info| info.syntax());
+        let operands: Vec<String> = syntax
+            .split(", ")
+            .filter_map(|operand| match operand {
+                "rd" => rd.map(register),
+                "rs1" => rs1.map(register),
+                "rs2" => rs2.map(register),
+                // the CSR instructions carry uimm in the rs1 field
+                "uimm" => rs1.map(|r| (r as u32).to_string()),
+                "csr" => imm.map(|imm| match csr::name(imm.encoded()) {
+                    Some(name) => name.to_owned(),
+                    None => imm.encoded().to_string(),
+                }),
+                "imm" | "shamt" => imm.map(|imm| imm.encoded().to_string()),
+                _ => None,
+            })
+            .collect();
+
+        write!(f, "{}", self.mnemonic().to_lowercase())?;
+        if !operands.is_empty() {
+            write!(f, " {}", operands.join(", "))?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,392 @@
+//! Translation from guest addresses to offsets in the [CPU](super::CPU)'s
+//! memory buffer.
+//!
+//! Memory doesn't have to start at address zero, or even be in one piece. Many
+//! RISC-V platforms put RAM at `0x8000_0000`, and a program might be linked
+//! with its code at one address and its stack far away at another. The
+//! [AddressMap] is a list of [Segment]s, each mapping a range of guest
+//! addresses onto a part of [CPU::memory](super::CPU::memory), so only the
+//! memory that's actually used needs to be allocated.
+//!
+//! ```
+//! use brubeck::rv32_i::*;
+//!
+//! let mut cpu = CPU::with_config(CpuConfig {
+//!     memory_size: 4096,
+//!     memory_base: 0x8000_0000,
+//!     reset_vector: 0x8000_0000,
+//!     ..CpuConfig::default()
+//! });
+//!
+//! // a small stack, a long way from the rest of memory
+//! cpu.map_segment(0xbfff_f000, 4096).unwrap();
+//! assert_eq!(cpu.memory.len(), 8192);
+//! assert_eq!(cpu.address_map.translate(0xbfff_f010, 4), Some(4112..4116));
+//! ```
+
+use std::ops::Range;
+
+use super::{CsrPolicy, CycleModel, EcallPolicy, Extensions, MisalignedPolicy, Xlen};
+
+/// A range of guest addresses, stored in memory starting at `offset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// First guest address
+    pub base: u32,
+    /// Last guest address (inclusive, so a segment can reach the top of the
+    /// address space)
+    pub last: u32,
+    /// Index in memory of the byte at `base`
+    pub offset: usize,
+}
+
+impl Segment {
+    pub fn contains(&self, address: u32) -> bool {
+        self.base <= address && address <= self.last
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressMap {
+    segments: Vec<Segment>,
+}
+
+impl Default for AddressMap {
+    /// Guest addresses are memory indexes; useful when memory is a plain
+    /// buffer starting at zero.
+    fn default() -> Self {
+        Self {
+            segments: vec![Segment {
+                base: 0,
+                last: u32::MAX,
+                offset: 0,
+            }],
+        }
+    }
+}
+
+impl AddressMap {
+    /// Maps `size` bytes starting at guest address `base` onto the start of
+    /// memory
+    pub fn new(base: u32, size: usize) -> Self {
+        let mut map = Self { segments: vec![] };
+        if size > 0 {
+            map.map(base, size, 0)
+                .expect("the first segment can't overlap");
+        }
+        map
+    }
+
+    /// Adds a segment: `size` bytes at guest address `base`, stored in
+    /// memory from `offset`.
+    pub fn map(&mut self, base: u32, size: usize, offset: usize) -> Result<(), MapError> {
+        let last = (size as u64)
+            .checked_sub(1)
+            .and_then(|s| base.checked_add(u32::try_from(s).ok()?))
+            .ok_or(MapError::OutOfRange(base))?;
+
+        if let Some(existing) = self
+            .segments
+            .iter()
+            .find(|s| s.base <= last && base <= s.last)
+        {
+            return Err(MapError::Overlap(existing.base));
+        }
+
+        self.segments.push(Segment { base, last, offset });
+        Ok(())
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Indexes into memory for `len` bytes starting at guest `address`, or
+    /// `None` if they aren't all within one segment.
+    pub fn translate(&self, address: u32, len: usize) -> Option<Range<usize>> {
+        let range = self.available(address)?;
+        (len <= range.len()).then(|| range.start..range.start + len)
+    }
+
+    /// Indexes into memory from guest `address` to the end of its segment.
+    /// A segment reaching past what `usize` counts (eg: all 4 GiB on a
+    /// 32-bit host) ends at `usize::MAX`, which no memory reaches anyway.
+    pub fn available(&self, address: u32) -> Option<Range<usize>> {
+        let segment = self.segments.iter().find(|s| s.contains(address))?;
+        let index = |offset: u64| {
+            (segment.offset as u64)
+                .checked_add(offset)
+                .and_then(|i| usize::try_from(i).ok())
+        };
+        let start = index(u64::from(address - segment.base))?;
+        let end = index(u64::from(segment.last - segment.base) + 1).unwrap_or(usize::MAX);
+        Some(start..end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    /// Overlaps the segment at this base address
+    Overlap(u32),
+    /// The segment starting here would extend past 4 GiB, or is empty
+    OutOfRange(u32),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overlap(base) => write!(f, "overlaps the segment at 0x{base:08x}"),
+            Self::OutOfRange(base) => write!(
+                f,
+                "the segment at 0x{base:08x} is empty or runs past the end of the address space"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// What memory is filled with when the CPU is created or reset.
+///
+/// Real memory doesn't start out zeroed, so a program that reads memory it
+/// never wrote can work by accident here and fail elsewhere. Filling memory
+/// with something recognizable (like `0xCC`) or random makes those bugs show
+/// up. Random fills are repeatable: the same seed gives the same contents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FillPattern {
+    Byte(u8),
+    Random { seed: u64 },
+}
+
+impl Default for FillPattern {
+    fn default() -> Self {
+        Self::Byte(0)
+    }
+}
+
+impl FillPattern {
+    /// Parses a pattern: a byte (eg: `0xCC` or `204`), `zero`, `random`, or
+    /// `random:<seed>`
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+
+        if input == "zero" {
+            return Some(Self::Byte(0));
+        }
+        if input == "random" {
+            return Some(Self::Random { seed: 0 });
+        }
+        if let Some(seed) = input.strip_prefix("random:") {
+            return seed.parse().ok().map(|seed| Self::Random { seed });
+        }
+
+        let byte = match input.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => input.parse(),
+        };
+        byte.ok().map(Self::Byte)
+    }
+
+    /// Fills `memory`; `offset` is where it starts in the whole memory buffer,
+    /// so a random fill gives each byte the same value however it's split up
+    pub fn fill(&self, memory: &mut [u8], offset: usize) {
+        match *self {
+            Self::Byte(b) => memory.fill(b),
+            Self::Random { seed } => {
+                for (i, byte) in memory.iter_mut().enumerate() {
+                    *byte = splitmix64(seed ^ (offset + i) as u64) as u8;
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for FillPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte(b) => write!(f, "0x{b:02X}"),
+            Self::Random { seed } => write!(f, "random:{seed}"),
+        }
+    }
+}
+
+/// What the stack pointer (`sp`) holds after a reset.
+///
+/// A program built with a C runtime sets up its own stack, but hand-written
+/// code (or a bare `main`) expects `sp` to already point somewhere useful;
+/// with it at zero, the first push writes just below 4 GiB, which is rarely
+/// memory. [Top](Self::Top) is the conventional choice: the end of memory,
+/// rounded down to the 16 bytes the calling convention aligns stacks to.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum StackPointer {
+    #[default]
+    Zero,
+    /// Just past the end of memory, so the stack grows down from there
+    Top,
+    At(u32),
+}
+
+impl StackPointer {
+    /// Parses `top`, `zero`, or an address (eg: `0x8010_0000`)
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().to_lowercase();
+        match input.as_str() {
+            "top" => Some(Self::Top),
+            "zero" => Some(Self::Zero),
+            _ => crate::expression::parse_number(&input).map(Self::At),
+        }
+    }
+}
+
+impl std::fmt::Display for StackPointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Zero => write!(f, "zero"),
+            Self::Top => write!(f, "top"),
+            Self::At(address) => write!(f, "0x{address:08x}"),
+        }
+    }
+}
+
+/// Scrambles a number; good enough for filling memory, not for anything that
+/// needs real randomness (eg: in tests)
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// How a [CPU](super::CPU) is set up: how much memory it has, where that
+/// memory is, what it starts out holding, and where execution starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuConfig {
+    /// Memory size in bytes
+    pub memory_size: usize,
+    /// Guest address of the first byte of memory
+    pub memory_base: u32,
+    /// Initial PC, and where [CPU::reset](super::CPU::reset) returns to
+    pub reset_vector: u32,
+    /// Initial `sp`, set again on every reset
+    pub stack_pointer: StackPointer,
+    /// Initial `gp`, set again on every reset; zero if not given. Code that
+    /// relaxes accesses to small data against `gp` expects the linker's
+    /// `__global_pointer$` here.
+    pub global_pointer: Option<u32>,
+    /// Initial memory contents
+    pub fill: FillPattern,
+    /// Enabled ISA extensions
+    pub extensions: Extensions,
+    /// What `ECALL` does
+    pub ecall: EcallPolicy,
+    /// What misaligned loads and stores do
+    pub misaligned: MisalignedPolicy,
+    /// What accessing a CSR that doesn't exist does
+    pub csr: CsrPolicy,
+    /// How many cycles each instruction takes
+    pub cycles: CycleModel,
+    /// The width of the integer registers
+    pub xlen: Xlen,
+}
+
+impl CpuConfig {
+    /// The end of memory, rounded down to 16 bytes; zero when memory runs up
+    /// to 4 GiB, which is where a stack growing down from there starts
+    pub fn top_of_memory(&self) -> u32 {
+        ((self.memory_base as u64 + self.memory_size as u64) & !0xf) as u32
+    }
+
+    /// What `sp` holds after a reset
+    pub fn initial_sp(&self) -> u32 {
+        match self.stack_pointer {
+            StackPointer::Zero => 0,
+            StackPointer::Top => self.top_of_memory(),
+            StackPointer::At(address) => address,
+        }
+    }
+}
+
+impl Default for CpuConfig {
+    /// One mebibyte of zeroed memory at address zero, starting execution at
+    /// zero
+    fn default() -> Self {
+        Self {
+            memory_size: 2usize.pow(20),
+            memory_base: 0,
+            reset_vector: 0,
+            stack_pointer: StackPointer::default(),
+            global_pointer: None,
+            fill: FillPattern::default(),
+            extensions: Extensions::default(),
+            ecall: EcallPolicy::default(),
+            misaligned: MisalignedPolicy::default(),
+            csr: CsrPolicy::default(),
+            cycles: CycleModel::default(),
+            xlen: Xlen::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments() {
+        let mut map = AddressMap::new(0x8000_0000, 0x100);
+        map.map(0x1000, 0x10, 0x100).unwrap();
+
+        assert_eq!(map.translate(0x8000_0000, 4), Some(0..4));
+        assert_eq!(map.translate(0x8000_00fc, 4), Some(0xfc..0x100));
+        assert_eq!(map.translate(0x1008, 8), Some(0x108..0x110));
+
+        // past the end of a segment, or in a gap
+        assert_eq!(map.translate(0x8000_00fe, 4), None);
+        assert_eq!(map.translate(0x1010, 1), None);
+        assert_eq!(map.translate(0, 1), None);
+
+        assert_eq!(
+            map.map(0x7fff_fff0, 0x20, 0x200),
+            Err(MapError::Overlap(0x8000_0000))
+        );
+        assert_eq!(
+            map.map(0xffff_fff0, 0x20, 0x200),
+            Err(MapError::OutOfRange(0xffff_fff0))
+        );
+
+        // right up to the top of the address space
+        map.map(0xffff_fff0, 0x10, 0x200).unwrap();
+        assert_eq!(map.translate(0xffff_ffff, 1), Some(0x20f..0x210));
+
+        // all 4 GiB, which a 32-bit usize can't count to
+        let whole = AddressMap::default().available(0xffff_fff0).unwrap();
+        assert_eq!(whole.start, 0xffff_fff0);
+        assert_eq!(whole.end, usize::try_from(1u64 << 32).unwrap_or(usize::MAX));
+    }
+
+    #[test]
+    fn fill_patterns() {
+        assert_eq!(FillPattern::parse("0xCC"), Some(FillPattern::Byte(0xcc)));
+        assert_eq!(FillPattern::parse("zero"), Some(FillPattern::Byte(0)));
+        assert_eq!(
+            FillPattern::parse("Random:7"),
+            Some(FillPattern::Random { seed: 7 })
+        );
+        assert_eq!(FillPattern::parse("0x100"), None);
+
+        let mut memory = [0u8; 64];
+        FillPattern::Byte(0xcc).fill(&mut memory, 0);
+        assert!(memory.iter().all(|b| *b == 0xcc));
+
+        // repeatable, however the memory is split up
+        let random = FillPattern::Random { seed: 42 };
+        let mut whole = [0u8; 64];
+        random.fill(&mut whole, 0);
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        random.fill(&mut a, 0);
+        random.fill(&mut b, 32);
+        assert_eq!(whole[..32], a);
+        assert_eq!(whole[32..], b);
+        assert_ne!(whole, [0u8; 64]);
+    }
+}
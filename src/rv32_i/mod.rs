@@ -2,17 +2,36 @@
 //!
 //! This includes the [CPU], [instructions](Instruction), encoding [formats](formats), and [registers](Register).
 
+pub mod compressed;
+pub mod counters;
 pub mod cpu;
+pub mod csr;
+pub mod encoding;
 pub mod formats;
 pub mod instructions;
+pub mod memory;
+pub mod pages;
+pub mod privilege;
 pub mod registers;
+pub mod rv64;
+pub mod semantics;
+pub mod trap;
 
+pub use counters::{Counters, CycleModel};
 pub use cpu::*;
+pub use csr::{CsrFile, Extensions};
+pub use encoding::DecodeError;
 pub use formats::*;
 pub use instructions::*;
+pub use memory::*;
+pub use pages::{Memory, Pages};
+pub use privilege::Privilege;
 pub use registers::*;
+pub use rv64::Xlen;
+pub use trap::TrapCause;
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -654,7 +673,7 @@ mod tests {
         let lh = Instruction::LH(load_inst);
         let result = cpu.execute(lh);
         assert!(result.is_ok());
-        assert_eq!(cpu.x3, 0b1111_1100_1111_1000);
+        assert_eq!(cpu.x3, 0b1111_1111_1111_1111_1111_1100_1111_1000);
     }
 
     #[test]
@@ -679,6 +698,685 @@ mod tests {
         let lb = Instruction::LB(load_inst);
         let result = cpu.execute(lb);
         assert!(result.is_ok());
-        assert_eq!(cpu.x3, 0b1111_1000);
+        assert_eq!(cpu.x3, 0b1111_1111_1111_1111_1111_1111_1111_1000);
+    }
+
+    #[test]
+    fn negative_loads_sign_extend() {
+        let mut cpu = CPU::default();
+
+        cpu.x1 = 100; // base address
+        cpu.x2 = -1i32 as u32; // value to store
+
+        let mut store_inst = SType::default();
+        store_inst.rs1 = Register::X1;
+        store_inst.rs2 = Register::X2;
+        assert!(cpu.execute(Instruction::SB(store_inst)).is_ok());
+
+        let mut load_inst = IType::default();
+        load_inst.rs1 = Register::X1;
+        load_inst.rd = Register::X3;
+
+        // LB and LH sign-extend, LBU and LHU don't
+        assert!(cpu.execute(Instruction::LB(load_inst)).is_ok());
+        assert_eq!(cpu.x3, 0xffff_ffff);
+        assert!(cpu.execute(Instruction::LBU(load_inst)).is_ok());
+        assert_eq!(cpu.x3, 0xff);
+
+        cpu.x2 = 0x8000;
+        assert!(cpu.execute(Instruction::SH(store_inst)).is_ok());
+        assert!(cpu.execute(Instruction::LH(load_inst)).is_ok());
+        assert_eq!(cpu.x3, 0xffff_8000);
+        assert!(cpu.execute(Instruction::LHU(load_inst)).is_ok());
+        assert_eq!(cpu.x3, 0x8000);
+    }
+
+    #[test]
+    fn mmio_load_store() {
+        use crate::devices::Clint;
+
+        let mut cpu = CPU::default();
+        cpu.devices.attach(Clint::BASE, Clint::default()).unwrap();
+
+        // x1 points at the CLINT; x2 holds the compare value
+        cpu.x1 = Clint::BASE + Clint::MTIMECMP;
+        cpu.x2 = 42;
+
+        let mut store_inst = SType::default();
+        store_inst.rs1 = Register::X1;
+        store_inst.rs2 = Register::X2;
+
+        let result = cpu.execute(Instruction::SW(store_inst));
+        assert!(result.is_ok());
+        assert_eq!(cpu.devices.get::<Clint>().unwrap().mtimecmp as u32, 42);
+
+        // mtime has ticked once for the SW
+        let mut load_inst = IType::default();
+        load_inst.rs1 = Register::X1;
+        load_inst.rd = Register::X3;
+
+        cpu.x1 = Clint::BASE + Clint::MTIME;
+        let result = cpu.execute(Instruction::LW(load_inst));
+        assert!(result.is_ok());
+        assert_eq!(cpu.x3, 1);
+
+        // memory is untouched
+        assert!(cpu.memory.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn interrupts() {
+        use crate::devices::{self, Clint};
+
+        let mut cpu = CPU::default();
+        cpu.devices.attach(Clint::BASE, Clint::default()).unwrap();
+        let nop = Instruction::ADDI(IType::default()).encode().to_le_bytes();
+        for address in [0, 4, 8, 12, 0x10c, 0x11c] {
+            cpu.write_memory(address, &nop).unwrap();
+        }
+
+        // vectored, at 0x100
+        cpu.write_csr(csr::MTVEC, 0x101).unwrap();
+        cpu.write_csr(csr::MIE, u32::MAX).unwrap();
+        assert_eq!(
+            cpu.read_csr(csr::MIE).unwrap(),
+            devices::MSIP | devices::MTIP | devices::MEIP
+        );
+        cpu.write_csr(csr::MIE, devices::MTIP).unwrap();
+        cpu.devices.get_mut::<Clint>().unwrap().mtimecmp = 2;
+
+        // pending, but not taken while mstatus.MIE is clear
+        cpu.run(2).unwrap();
+        assert_eq!(cpu.read_csr(csr::MIP).unwrap(), devices::MTIP);
+        assert_eq!(cpu.pending_interrupt(), None);
+
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MIE).unwrap();
+        assert_eq!(
+            cpu.pending_interrupt(),
+            Some(TrapCause::MachineTimerInterrupt)
+        );
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x120);
+        assert_eq!(cpu.read_csr(csr::MEPC).unwrap(), 8);
+        assert_eq!(
+            cpu.read_csr(csr::MCAUSE).unwrap(),
+            TrapCause::MachineTimerInterrupt.mcause()
+        );
+        assert_eq!(cpu.read_csr(csr::MSTATUS).unwrap() & csr::MSTATUS_MIE, 0);
+
+        // the handler isn't interrupted
+        assert_eq!(cpu.pending_interrupt(), None);
+
+        // software interrupts come before the timer, and direct mode always
+        // goes to the base
+        cpu.pc = 8;
+        cpu.write_csr(csr::MTVEC, 0x10c).unwrap();
+        cpu.write_csr(csr::MIE, devices::MSIP | devices::MTIP)
+            .unwrap();
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MIE).unwrap();
+        cpu.devices.get_mut::<Clint>().unwrap().msip = 1;
+        assert_eq!(
+            cpu.take_interrupt(),
+            Some(TrapCause::MachineSoftwareInterrupt)
+        );
+        assert_eq!(cpu.pc, 0x10c);
+    }
+
+    #[test]
+    fn misaligned_accesses() {
+        let mut cpu = CPU::new(64);
+        cpu.write_memory(0, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        cpu.x1 = 2;
+        cpu.x2 = 0x1234_5678;
+
+        let mut load = IType::default();
+        load.rs1 = Register::X1;
+        load.rd = Register::X3;
+        let mut store = SType::default();
+        store.rs1 = Register::X1;
+        store.rs2 = Register::X2;
+
+        // emulated by default
+        cpu.execute(Instruction::LW(load)).unwrap();
+        assert_eq!(cpu.x3, 0x0605_0403);
+
+        // bytes are never misaligned
+        cpu.config.misaligned = MisalignedPolicy::Error;
+        cpu.x1 = 3;
+        assert!(cpu.execute(Instruction::LB(load)).is_ok());
+        assert!(matches!(
+            cpu.execute(Instruction::LH(load)),
+            Err(Error::MisalignedAccess(Instruction::LH(_), 3))
+        ));
+
+        cpu.config.misaligned = MisalignedPolicy::Trap;
+        cpu.write_csr(csr::MTVEC, 0x20).unwrap();
+        cpu.pc = 8;
+        cpu.execute(Instruction::SH(store)).unwrap();
+        assert_eq!(cpu.pc, 0x20);
+        assert_eq!(cpu.read_csr(csr::MEPC).unwrap(), 8);
+        assert_eq!(
+            cpu.read_csr(csr::MCAUSE).unwrap(),
+            TrapCause::StoreAddressMisaligned.mcause()
+        );
+        assert_eq!(cpu.read_csr(csr::MTVAL).unwrap(), 3);
+        assert_eq!(cpu.read_mem_u32(0).unwrap(), 0x0403_0201);
+        assert!(cpu.last_writes().is_empty());
+
+        cpu.execute(Instruction::LW(load)).unwrap();
+        assert_eq!(
+            cpu.read_csr(csr::MCAUSE).unwrap(),
+            TrapCause::LoadAddressMisaligned.mcause()
+        );
+    }
+
+    #[test]
+    fn load_past_end_of_memory() {
+        let mut cpu = CPU::new(16);
+        let mut inst = IType::default();
+        inst.rs1 = Register::X1;
+        inst.rd = Register::X2;
+
+        cpu.x1 = 14;
+        let result = cpu.execute(Instruction::LW(inst));
+        assert!(matches!(result, Err(Error::AccessViolation(14))));
+
+        let result = cpu.execute(Instruction::LH(inst));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn fetch_decode_execute() {
+        let mut cpu = CPU::new(64);
+
+        // JAL zero, 0 loops forever, so runs out of fuel
+        cpu.write_memory(
+            0,
+            &Instruction::JAL(JType::default()).encode().to_le_bytes(),
+        )
+        .unwrap();
+        assert!(matches!(cpu.run(100), Ok(100)));
+        assert_eq!(cpu.pc, 0);
+
+        // ADDI a7, zero, 93; ECALL exits
+        let mut addi = IType::default();
+        addi.rd = ABI::A7.to_register();
+        addi.imm.set_unsigned(93).unwrap();
+        cpu.write_memory(0, &Instruction::ADDI(addi).encode().to_le_bytes())
+            .unwrap();
+        cpu.write_memory(
+            4,
+            &Instruction::ECALL(IType::default()).encode().to_le_bytes(),
+        )
+        .unwrap();
+        assert!(matches!(cpu.run(100), Ok(2)));
+        assert_eq!(cpu.exit_code, Some(0));
+        assert!(matches!(cpu.run(100), Ok(0)));
+
+        // cleared memory, a misaligned PC, and the end of memory
+        cpu.exit_code = None;
+        cpu.pc = 8;
+        assert!(matches!(
+            cpu.step(),
+            Err(Error::IllegalInstruction(DecodeError::Zero))
+        ));
+        cpu.pc = 2;
+        assert!(matches!(cpu.step(), Err(Error::MisalignedJump(2))));
+        cpu.pc = 64;
+        assert!(matches!(cpu.run(1), Err(Error::AccessViolation(64))));
+    }
+
+    #[test]
+    fn typed_memory_reads() {
+        let mut cpu = CPU::new(16);
+        cpu.write_memory(12, &[0x78, 0x56, 0x34, 0x12]).unwrap();
+
+        assert_eq!(cpu.read_mem_u8(15).unwrap(), 0x12);
+        assert_eq!(cpu.read_mem_u16(12).unwrap(), 0x5678);
+        assert_eq!(cpu.read_mem_u32(12).unwrap(), 0x1234_5678);
+        assert!(matches!(
+            cpu.read_mem_u32(13),
+            Err(Error::AccessViolation(13))
+        ));
+        assert!(matches!(
+            cpu.read_mem_u8(16),
+            Err(Error::AccessViolation(16))
+        ));
+
+        // images move the PC, unless they don't fit
+        cpu.load_binary(4, &0x0050_0513_u32.to_le_bytes()).unwrap();
+        assert_eq!(cpu.read_mem_u32(4).unwrap(), 0x0050_0513);
+        assert_eq!(cpu.pc, 4);
+        assert!(cpu.load_binary(8, &[0; 12]).is_err());
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn typed_memory_writes() {
+        let mut cpu = CPU::new(16);
+
+        cpu.write_mem_u32(12, 0x1234_5678).unwrap();
+        cpu.write_mem_u16(10, 0xbeef).unwrap();
+        cpu.write_mem_u8(9, 0x2a).unwrap();
+        assert_eq!(
+            &cpu.memory[9..],
+            &[0x2a, 0xef, 0xbe, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(cpu.last_writes().len(), 3);
+
+        // nothing is written when any byte is past the end
+        assert!(matches!(
+            cpu.write_mem_u32(14, 0),
+            Err(Error::AccessViolation(14))
+        ));
+        assert!(matches!(
+            cpu.write_mem_u16(15, 0),
+            Err(Error::AccessViolation(15))
+        ));
+        assert_eq!(cpu.read_mem_u16(14).unwrap(), 0x1234);
+
+        // loads and stores of the last bytes fail at the address they were
+        // given, rather than panicking
+        let mut load = IType::default();
+        load.rs1 = Register::X1;
+        load.rd = Register::X2;
+        let mut store = SType::default();
+        store.rs1 = Register::X1;
+        store.rs2 = Register::X2;
+
+        for address in 13..16 {
+            cpu.x1 = address;
+            assert!(matches!(
+                cpu.execute(Instruction::LW(load)),
+                Err(Error::AccessViolation(a)) if a == address
+            ));
+            assert!(matches!(
+                cpu.execute(Instruction::SW(store)),
+                Err(Error::AccessViolation(a)) if a == address
+            ));
+        }
+        cpu.x1 = 15;
+        assert!(cpu.execute(Instruction::LH(load)).is_err());
+        assert!(cpu.execute(Instruction::LBU(load)).is_ok());
+        assert_eq!(cpu.x2, 0x12);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for info in crate::metadata::INSTRUCTIONS {
+            let instruction = Instruction::from_mnemonic(info.name).unwrap();
+            assert_eq!(instruction.mnemonic(), info.name);
+            assert_eq!(
+                instruction.with_operands(instruction.operands()),
+                Some(instruction)
+            );
+        }
+        assert_eq!(Instruction::from_mnemonic("addi"), None);
+        assert_eq!(
+            Instruction::NOP.with_operands(Operands::RType(RType::default())),
+            None
+        );
+
+        for register in Register::ALL {
+            assert_eq!(Register::from_name(register.name()), Some(register));
+        }
+        assert_eq!(Register::from_name("FP"), Some(Register::X8));
+        assert_eq!(ABI::SP.name(), "SP");
+        assert_eq!(Register::X31.to_string(), "x31");
+    }
+
+    #[test]
+    fn memory_base_and_reset() {
+        let mut cpu = CPU::with_config(CpuConfig {
+            memory_size: 64,
+            memory_base: 0x8000_0000,
+            reset_vector: 0x8000_0000,
+            fill: FillPattern::Byte(0xcc),
+            ..CpuConfig::default()
+        });
+        assert_eq!(cpu.pc, 0x8000_0000);
+
+        cpu.x1 = 0x8000_0010;
+        cpu.x2 = 0x1234_5678;
+        let sw = Instruction::SW(SType {
+            rs1: Register::X1,
+            rs2: Register::X2,
+            ..SType::default()
+        });
+        assert!(cpu.execute(sw).is_ok());
+        assert_eq!(cpu.memory[16..20], [0x78, 0x56, 0x34, 0x12]);
+
+        // below the start of memory
+        cpu.x1 = 0x10;
+        assert!(matches!(cpu.execute(sw), Err(Error::AccessViolation(0x10))));
+
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x8000_0000);
+        assert_eq!(cpu.x1, 0);
+        assert_eq!(cpu.memory[16..20], [0xcc; 4]);
+    }
+
+    #[test]
+    fn step_at_top_of_memory() {
+        let mut cpu = CPU::with_config(CpuConfig {
+            memory_size: 64,
+            memory_base: 0xffff_ffc0,
+            reset_vector: 0xffff_fffc,
+            ..CpuConfig::default()
+        });
+        cpu.write_memory(0xffff_fffc, &0x0000_0013u32.to_le_bytes())
+            .unwrap();
+
+        // the PC wraps around past the last instruction
+        assert!(matches!(cpu.step(), Ok(Instruction::NOP)));
+        assert_eq!(cpu.pc, 0);
+    }
+
+    #[test]
+    fn branch_and_jump_targets() {
+        let mut b = BType::default();
+        b.imm.set_signed(-4).unwrap();
+        assert_eq!(b.target(0x100), 0xf8);
+        assert_eq!(b.target(0), 0xffff_fff8);
+
+        let mut j = JType::default();
+        j.imm.set_signed(0x800).unwrap();
+        assert_eq!(j.target(0x100), 0x1100);
+
+        // taken branches must land on a word boundary
+        let mut cpu = CPU::default();
+        b.imm.set_signed(3).unwrap();
+        assert!(matches!(
+            cpu.execute(Instruction::BEQ(b)),
+            Err(Error::MisalignedJump(6))
+        ));
+        assert!(cpu.execute(Instruction::BNE(b)).is_ok());
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn ecall_policies() {
+        let mut ecall = IType::default();
+        ecall.imm.set_unsigned(0).unwrap();
+
+        // the host emulates exit
+        let mut cpu = CPU::default();
+        cpu.x17 = CPU::SYSCALL_EXIT;
+        cpu.x10 = 7;
+        assert!(cpu.execute(Instruction::ECALL(ecall)).is_ok());
+        assert_eq!(cpu.exit_code, Some(7));
+
+        // the guest's handler runs instead
+        let mut cpu = CPU::with_config(CpuConfig {
+            ecall: EcallPolicy::TrapIntoGuestHandler,
+            ..CpuConfig::default()
+        });
+        cpu.x17 = CPU::SYSCALL_EXIT;
+        cpu.pc = 0x40;
+        cpu.write_csr(csr::MTVEC, 0x201).unwrap();
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MIE).unwrap();
+        assert!(cpu.execute(Instruction::ECALL(ecall)).is_ok());
+        assert_eq!(cpu.exit_code, None);
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.last_trap(), Some(TrapCause::MachineEcall.mcause()));
+        assert_eq!(cpu.read_csr(csr::MEPC).unwrap(), 0x40);
+        assert_eq!(cpu.read_csr(csr::MCAUSE).unwrap(), 11);
+        assert_eq!(
+            cpu.read_csr(csr::MSTATUS).unwrap(),
+            csr::MSTATUS_MPIE | csr::MSTATUS_MPP
+        );
+
+        let mut cpu = CPU::with_config(CpuConfig {
+            ecall: EcallPolicy::Error,
+            ..CpuConfig::default()
+        });
+        assert!(matches!(
+            cpu.execute(Instruction::ECALL(ecall)),
+            Err(Error::EnvironmentCall(0))
+        ));
+        assert_eq!(cpu.pc, 0);
+    }
+
+    #[test]
+    fn privilege_levels() {
+        let mut cpu = CPU::with_config(CpuConfig {
+            ecall: EcallPolicy::TrapIntoGuestHandler,
+            ..CpuConfig::default()
+        });
+        assert_eq!(cpu.privilege, Privilege::Machine);
+
+        // MPP can't hold supervisor mode, which isn't implemented
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MPP).unwrap();
+        cpu.write_csr(csr::MSTATUS, 0b01 << 11).unwrap();
+        assert_eq!(cpu.read_csr(csr::MSTATUS).unwrap(), csr::MSTATUS_MPP);
+
+        // drop to user mode at 0x40, with interrupts enabled once there
+        cpu.write_csr(csr::MSTATUS, csr::MSTATUS_MPIE).unwrap();
+        cpu.write_csr(csr::MEPC, 0x40).unwrap();
+        cpu.write_csr(csr::MTVEC, 0x200).unwrap();
+        cpu.execute(Instruction::MRET).unwrap();
+        assert_eq!(cpu.privilege, Privilege::User);
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(
+            cpu.read_csr(csr::MSTATUS).unwrap(),
+            csr::MSTATUS_MIE | csr::MSTATUS_MPIE
+        );
+
+        // user mode can read the counters, but not the machine CSRs, and
+        // can't MRET
+        let mut csrrs = IType::default();
+        csrrs.rd = Register::X10;
+        csrrs.imm.set_unsigned(csr::CYCLE).unwrap();
+        cpu.execute(Instruction::CSRRS(csrrs)).unwrap();
+        assert_eq!(cpu.last_trap(), None);
+        assert_eq!(cpu.pc, 0x44);
+
+        csrrs.imm.set_unsigned(csr::MSCRATCH).unwrap();
+        cpu.execute(Instruction::CSRRS(csrrs)).unwrap();
+        assert_eq!(
+            cpu.last_trap(),
+            Some(TrapCause::IllegalInstruction.mcause())
+        );
+        assert_eq!(cpu.privilege, Privilege::Machine);
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.read_csr(csr::MEPC).unwrap(), 0x44);
+        // the trap saved user mode, and that interrupts were enabled
+        assert_eq!(cpu.read_csr(csr::MSTATUS).unwrap(), csr::MSTATUS_MPIE);
+
+        cpu.execute(Instruction::MRET).unwrap();
+        cpu.execute(Instruction::MRET).unwrap();
+        assert_eq!(
+            cpu.last_trap(),
+            Some(TrapCause::IllegalInstruction.mcause())
+        );
+        assert_eq!(cpu.read_csr(csr::MTVAL).unwrap(), 0x3020_0073);
+
+        // an ECALL from user mode says so
+        cpu.execute(Instruction::MRET).unwrap();
+        cpu.execute(Instruction::WFI).unwrap();
+        assert_eq!(cpu.pc, 0x48);
+        cpu.execute(Instruction::ECALL(IType::default())).unwrap();
+        assert_eq!(cpu.last_trap(), Some(TrapCause::UserEcall.mcause()));
+
+        cpu.reset();
+        assert_eq!(cpu.privilege, Privilege::Machine);
+
+        // a CSR that doesn't exist stops with an error, unless it's to trap
+        // like hardware
+        let satp = 0x180;
+        csrrs.imm.set_unsigned(satp).unwrap();
+        assert!(matches!(
+            cpu.execute(Instruction::CSRRS(csrrs)),
+            Err(Error::IllegalCsr(0x180))
+        ));
+        cpu.config.csr = CsrPolicy::Trap;
+        cpu.write_csr(csr::MTVEC, 0x200).unwrap();
+        cpu.execute(Instruction::CSRRS(csrrs)).unwrap();
+        assert_eq!(
+            cpu.last_trap(),
+            Some(TrapCause::IllegalInstruction.mcause())
+        );
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        let mut r = RType::default();
+        r.rd = Register::X3;
+        r.rs1 = Register::X1;
+        r.rs2 = Register::X2;
+
+        let cases = [
+            // (instruction, rs1, rs2, rd)
+            (Instruction::MUL(r), 7, -3i32 as u32, -21i32 as u32),
+            (Instruction::MUL(r), 0x8000_0000, 2, 0),
+            (Instruction::MULH(r), -1i32 as u32, -1i32 as u32, 0),
+            (Instruction::MULH(r), 0x8000_0000, 0x8000_0000, 0x4000_0000),
+            (Instruction::MULHU(r), u32::MAX, u32::MAX, 0xffff_fffe),
+            (Instruction::MULHSU(r), -1i32 as u32, u32::MAX, u32::MAX),
+            (Instruction::DIV(r), -7i32 as u32, 2, -3i32 as u32),
+            (Instruction::DIV(r), 5, 0, u32::MAX),
+            (Instruction::DIV(r), 0x8000_0000, -1i32 as u32, 0x8000_0000),
+            (Instruction::DIVU(r), 5, 0, u32::MAX),
+            (Instruction::DIVU(r), u32::MAX, 2, 0x7fff_ffff),
+            (Instruction::REM(r), -7i32 as u32, 2, -1i32 as u32),
+            (Instruction::REM(r), 5, 0, 5),
+            (Instruction::REM(r), 0x8000_0000, -1i32 as u32, 0),
+            (Instruction::REMU(r), u32::MAX, 10, 5),
+            (Instruction::REMU(r), 5, 0, 5),
+        ];
+
+        for (instruction, a, b, expected) in cases {
+            let mut cpu = CPU::default();
+            cpu.x1 = a;
+            cpu.x2 = b;
+            let before = Register::ALL.map(|r| cpu.get_register(r));
+
+            cpu.execute(instruction).unwrap();
+            assert_eq!(cpu.x3, expected, "{instruction:?} {a:#x} {b:#x}");
+            assert_eq!(cpu.pc, 4);
+
+            // the independent model agrees
+            let after = semantics::outcome(instruction, Instruction::LENGTH, &before).unwrap();
+            assert_eq!(after, Register::ALL.map(|r| cpu.get_register(r)));
+        }
+
+        // with the extension switched off, they aren't instructions
+        let mut cpu = CPU::default();
+        cpu.config.extensions.m = false;
+        assert!(matches!(
+            cpu.execute(Instruction::MUL(r)),
+            Err(Error::NotImplemented(Instruction::MUL(_)))
+        ));
+        assert_eq!(cpu.read_csr(csr::MISA).unwrap(), 0x4000_0101);
+    }
+
+    #[test]
+    fn atomics() {
+        let mut cpu = CPU::new(64);
+        cpu.write_mem_u32(16, 10).unwrap();
+        cpu.x1 = 16;
+        cpu.x2 = 3;
+
+        let mut r = RType::default();
+        r.rd = Register::X3;
+        r.rs1 = Register::X1;
+        r.rs2 = Register::X2;
+
+        // each AMO returns the old word and stores op(old, rs2)
+        let cases = [
+            (Instruction::AMOADD_W(r), 10, 3, 13),
+            (Instruction::AMOSWAP_W(r), 10, 3, 3),
+            (Instruction::AMOXOR_W(r), 10, 3, 9),
+            (Instruction::AMOAND_W(r), 10, 3, 2),
+            (Instruction::AMOOR_W(r), 10, 3, 11),
+            (Instruction::AMOMIN_W(r), 10, -3i32 as u32, -3i32 as u32),
+            (Instruction::AMOMAX_W(r), 10, -3i32 as u32, 10),
+            (Instruction::AMOMINU_W(r), 10, -3i32 as u32, 10),
+            (Instruction::AMOMAXU_W(r), 10, -3i32 as u32, -3i32 as u32),
+        ];
+        for (instruction, old, operand, new) in cases {
+            cpu.write_mem_u32(16, old).unwrap();
+            cpu.x2 = operand;
+            cpu.execute(instruction).unwrap();
+            assert_eq!(cpu.x3, old, "{instruction:?}");
+            assert_eq!(cpu.read_mem_u32(16).unwrap(), new, "{instruction:?}");
+        }
+
+        // SC.W only stores while the LR.W's reservation holds
+        cpu.x2 = 42;
+        cpu.execute(Instruction::LR_W(r)).unwrap();
+        assert_eq!(cpu.x3, cpu.read_mem_u32(16).unwrap());
+        cpu.execute(Instruction::SC_W(r)).unwrap();
+        assert_eq!((cpu.x3, cpu.read_mem_u32(16).unwrap()), (0, 42));
+        cpu.x2 = 7;
+        cpu.execute(Instruction::SC_W(r)).unwrap();
+        assert_eq!((cpu.x3, cpu.read_mem_u32(16).unwrap()), (1, 42));
+
+        cpu.execute(Instruction::LR_W(r)).unwrap();
+        cpu.x1 = 20;
+        cpu.execute(Instruction::SC_W(r)).unwrap();
+        assert_eq!(cpu.x3, 1);
+        assert!(cpu.last_writes().is_empty());
+
+        // never emulated when misaligned
+        cpu.x1 = 18;
+        cpu.write_csr(csr::MTVEC, 0x20).unwrap();
+        cpu.execute(Instruction::AMOADD_W(r)).unwrap();
+        assert_eq!(cpu.pc, 0x20);
+        assert_eq!(
+            cpu.read_csr(csr::MCAUSE).unwrap(),
+            TrapCause::StoreAddressMisaligned.mcause()
+        );
+        cpu.config.misaligned = MisalignedPolicy::Error;
+        assert!(matches!(
+            cpu.execute(Instruction::LR_W(r)),
+            Err(Error::MisalignedAccess(Instruction::LR_W(_), 18))
+        ));
+
+        cpu.config.extensions.a = false;
+        assert!(matches!(
+            cpu.execute(Instruction::AMOADD_W(r)),
+            Err(Error::NotImplemented(_))
+        ));
+    }
+
+    #[test]
+    fn compressed() {
+        let mut cpu = CPU::new(64);
+        cpu.config.extensions.c = true;
+        let program: &[u8] = &[
+            0x0d, 0x45, // c.li a0, 3
+            0x7d, 0x15, // c.addi a0, -1
+            0x7d, 0xfd, // c.bnez a0, -2
+            0x93, 0x05, 0x70, 0x00, // addi a1, zero, 7
+            0x11, 0x20, // c.jal 4
+            0x01, 0x00, // c.nop
+            0x06, 0x86, // c.mv a2, ra
+        ];
+        cpu.write_memory(0, program).unwrap();
+
+        assert_eq!(cpu.run(10).unwrap(), 10);
+        assert_eq!((cpu.x10, cpu.x11), (0, 7));
+        // c.jal links to the instruction 2 bytes on
+        assert_eq!(cpu.x12, 12);
+        assert_eq!(cpu.pc, 16);
+        assert_eq!(cpu.counters.instret, 10);
+
+        // jumps and branches can go to any even address
+        let mut jal = JType::default();
+        jal.imm.set_signed(-1).unwrap();
+        cpu.execute(Instruction::JAL(jal)).unwrap();
+        assert_eq!(cpu.pc, 14);
+
+        // without the extension, it's not an instruction
+        cpu.config.extensions.c = false;
+        cpu.pc = 0;
+        assert!(matches!(
+            cpu.step(),
+            Err(Error::IllegalInstruction(DecodeError::Compressed(
+                0x157d_450d
+            )))
+        ));
     }
 }
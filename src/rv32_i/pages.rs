@@ -0,0 +1,275 @@
+//! The [CPU](super::CPU)'s memory, and copies of it that share what hasn't
+//! changed.
+//!
+//! [Memory] is a plain buffer of bytes (it derefs to `[u8]`) that also notes
+//! which pages of [PAGE] bytes have been written. A copy of it
+//! ([Memory::pages]) is a list of [Pages] that are shared rather than copied
+//! where they haven't been written since the last copy, so it costs the pages
+//! touched in between rather than the size of memory. Putting a copy back
+//! ([Memory::restore]) likewise only copies the pages that differ from it.
+//! Checkpoints and snapshots are both made this way.
+//!
+//! ```
+//! use brubeck::rv32_i::CPU;
+//!
+//! let mut cpu = CPU::new(1024 * 1024);
+//! let before = cpu.memory.pages();
+//! cpu.write_memory(0x100, &[1, 2, 3, 4]).unwrap();
+//!
+//! // only the page that was written isn't shared
+//! let after = cpu.memory.pages();
+//! assert_eq!(after.shared_with(&before), 255);
+//!
+//! cpu.memory.restore(&before);
+//! assert_eq!(cpu.memory[0x100], 0);
+//! ```
+//!
+//! Writes through [get_mut](Memory::get_mut) (as the CPU's stores and
+//! [write_memory](super::CPU::write_memory) make) note just the pages they
+//! touch. Anything else that borrows the buffer mutably (eg: `cpu.memory[3]
+//! = 7`) might have changed any of it, so every page counts as written.
+
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Bytes in each page
+pub const PAGE: usize = 4096;
+
+/// Numbers the buffers, so a copy of one isn't mistaken for a copy of another
+static BUFFERS: AtomicU64 = AtomicU64::new(1);
+
+/// Bytes of memory, with a note of when each page was last written
+#[derive(Debug)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    /// Which buffer this is
+    buffer: u64,
+    /// For each page, the write that last changed it
+    versions: Vec<u64>,
+    /// Writes so far
+    writes: u64,
+    /// The most recent copy of each page, for the next copy to share
+    copies: RefCell<Vec<Option<Page>>>,
+}
+
+/// A copy of a page: the write it was copied after, and its bytes
+type Page = (u64, Arc<[u8]>);
+
+/// A copy of [Memory], page by page
+#[derive(Debug, Clone)]
+pub struct Pages {
+    /// The buffer it was copied from
+    buffer: u64,
+    pages: Vec<Page>,
+}
+
+impl Memory {
+    /// A copy of memory as it is now
+    pub fn pages(&self) -> Pages {
+        let mut copies = self.copies.borrow_mut();
+        copies.resize(self.versions.len(), None);
+
+        let pages = self
+            .bytes
+            .chunks(PAGE)
+            .zip(&self.versions)
+            .zip(copies.iter_mut())
+            .map(|((bytes, &version), copy)| match copy {
+                Some(page) if page.0 == version => page.clone(),
+                _ => copy.insert((version, Arc::from(bytes))).clone(),
+            })
+            .collect();
+
+        Pages {
+            buffer: self.buffer,
+            pages,
+        }
+    }
+
+    /// Puts memory back as it was when `pages` was copied, copying only the
+    /// pages that have changed since. It must be the same size.
+    pub fn restore(&mut self, pages: &Pages) {
+        assert_eq!(self.bytes.len(), pages.len(), "memory is another size");
+        let ours = pages.buffer == self.buffer;
+
+        let copies = self.copies.get_mut();
+        copies.resize(self.versions.len(), None);
+        for (index, (version, bytes)) in pages.pages.iter().enumerate() {
+            if ours && self.versions[index] == *version {
+                continue;
+            }
+            let start = index * PAGE;
+            self.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+
+            // the page is as it was after that write again, so it can be
+            // shared with the copy; from another buffer, it's a new write
+            if ours {
+                self.versions[index] = *version;
+                copies[index] = Some((*version, bytes.clone()));
+            } else {
+                self.writes += 1;
+                self.versions[index] = self.writes;
+            }
+        }
+    }
+
+    /// The bytes in `range` to write to, if it's within memory
+    pub fn get_mut(&mut self, range: Range<usize>) -> Option<&mut [u8]> {
+        if range.end > self.bytes.len() {
+            return None;
+        }
+        self.written(range.clone());
+        self.bytes.get_mut(range)
+    }
+
+    /// Grows or shrinks memory to `len` bytes, with `value` in any new ones
+    pub fn resize(&mut self, len: usize, value: u8) {
+        let old = self.bytes.len();
+        self.bytes.resize(len, value);
+        self.versions.resize(len.div_ceil(PAGE), 0);
+        self.written(old.min(len)..len);
+    }
+
+    /// Notes that the pages with bytes in `range` have changed
+    fn written(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.writes += 1;
+        let pages = range.start / PAGE..=(range.end - 1) / PAGE;
+        self.versions[pages].fill(self.writes);
+    }
+}
+
+impl From<Vec<u8>> for Memory {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            versions: vec![0; bytes.len().div_ceil(PAGE)],
+            bytes,
+            buffer: BUFFERS.fetch_add(1, Ordering::Relaxed),
+            writes: 0,
+            copies: RefCell::default(),
+        }
+    }
+}
+
+impl Clone for Memory {
+    /// The same bytes, in a buffer of their own
+    fn clone(&self) -> Self {
+        Self::from(self.bytes.clone())
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl DerefMut for Memory {
+    /// All of memory, which counts as written
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.written(0..self.bytes.len());
+        &mut self.bytes
+    }
+}
+
+impl Pages {
+    /// Bytes copied
+    pub fn len(&self) -> usize {
+        self.pages.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// The pages, in order; the last may be shorter than [PAGE]
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.pages.iter().map(|(_, bytes)| &bytes[..])
+    }
+
+    /// All the bytes, in one buffer
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.iter().flatten().copied().collect()
+    }
+
+    /// How many pages are shared with another copy, rather than copied
+    pub fn shared_with(&self, other: &Pages) -> usize {
+        self.pages
+            .iter()
+            .zip(&other.pages)
+            .filter(|((_, a), (_, b))| Arc::ptr_eq(a, b))
+            .count()
+    }
+}
+
+impl From<Vec<u8>> for Pages {
+    /// Pages of bytes that weren't copied from memory (eg: read from a file)
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            buffer: 0,
+            pages: bytes
+                .chunks(PAGE)
+                .map(|page| (0, Arc::from(page)))
+                .collect(),
+        }
+    }
+}
+
+impl PartialEq for Pages {
+    fn eq(&self, other: &Self) -> bool {
+        self.pages.len() == other.pages.len()
+            && self
+                .pages
+                .iter()
+                .zip(&other.pages)
+                .all(|((_, a), (_, b))| Arc::ptr_eq(a, b) || a == b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_share_pages() {
+        let mut memory = Memory::from(vec![0; 4 * PAGE + 10]);
+        let first = memory.pages();
+        assert_eq!(first.len(), 4 * PAGE + 10);
+        assert_eq!(memory.pages().shared_with(&first), 5);
+
+        // a write across a page boundary, and one through the whole buffer
+        memory.get_mut(PAGE - 1..PAGE + 1).unwrap().fill(1);
+        let second = memory.pages();
+        assert_eq!(second.shared_with(&first), 3);
+        memory[4 * PAGE] = 2;
+        let third = memory.pages();
+        assert_eq!(third.shared_with(&second), 0);
+        assert_eq!(third.to_vec()[4 * PAGE], 2);
+        assert_ne!(third, second);
+
+        // back to the first, then the second: only what changed is copied,
+        // and the pages are shared with those copies again
+        memory.restore(&first);
+        assert!(memory.iter().all(|b| *b == 0));
+        assert_eq!(memory.pages().shared_with(&first), 5);
+        memory.restore(&second);
+        assert_eq!(memory[PAGE], 1);
+        assert_eq!(memory.pages().shared_with(&second), 5);
+
+        // a copy from somewhere else is all new
+        memory.restore(&Pages::from(vec![3; 4 * PAGE + 10]));
+        assert_eq!(memory.pages().shared_with(&first), 0);
+        assert_eq!(memory[PAGE], 3);
+
+        memory.resize(5 * PAGE, 4);
+        assert_eq!(memory.len(), 5 * PAGE);
+        assert_eq!(memory[4 * PAGE + 10], 4);
+        assert!(memory.get_mut(5 * PAGE - 1..5 * PAGE + 1).is_none());
+    }
+}
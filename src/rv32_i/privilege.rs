@@ -0,0 +1,67 @@
+//! Privilege levels ("modes"): what the running code is allowed to do.
+//!
+//! The CPU starts in machine mode, which can do anything. Machine mode code
+//! drops to user mode by setting `mstatus.MPP` to user and returning with
+//! `MRET`; a trap (eg: an `ECALL`) takes it back to machine mode, with the
+//! mode it came from saved in `MPP`. User mode can only reach the CSRs meant
+//! for it: bits 9 and 8 of a CSR's address are the lowest mode that may
+//! access it, so user code touching `mstatus` (0x300) traps as an illegal
+//! instruction, while reading `cycle` (0xc00) is fine.
+//!
+//! ```
+//! use brubeck::rv32_i::{csr, Privilege};
+//!
+//! assert_eq!(Privilege::required(csr::MSTATUS), Privilege::Machine);
+//! assert!(Privilege::User.can_access(csr::CYCLE));
+//! assert!(!Privilege::User.can_access(csr::MSCRATCH));
+//! ```
+//!
+//! Supervisor mode isn't implemented.
+
+use std::fmt::Display;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Privilege {
+    User = 0,
+    #[default]
+    Machine = 3,
+}
+
+impl Privilege {
+    /// The mode's encoding, as in `mstatus.MPP`
+    pub fn bits(self) -> u32 {
+        self as u32
+    }
+
+    /// The mode for an encoding, if it's implemented
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(Self::User),
+            3 => Some(Self::Machine),
+            _ => None,
+        }
+    }
+
+    /// The lowest mode that may access a CSR; supervisor and hypervisor
+    /// CSRs need machine mode here
+    pub fn required(csr: u32) -> Self {
+        match (csr >> 8) & 0b11 {
+            0 => Self::User,
+            _ => Self::Machine,
+        }
+    }
+
+    pub fn can_access(self, csr: u32) -> bool {
+        self >= Self::required(csr)
+    }
+}
+
+impl Display for Privilege {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::User => write!(f, "user"),
+            Self::Machine => write!(f, "machine"),
+        }
+    }
+}
@@ -50,8 +50,82 @@ pub enum Register {
     PC,
 }
 
+impl Register {
+    /// Every register, in order, with the PC last
+    pub const ALL: [Register; 33] = [
+        Register::X0,
+        Register::X1,
+        Register::X2,
+        Register::X3,
+        Register::X4,
+        Register::X5,
+        Register::X6,
+        Register::X7,
+        Register::X8,
+        Register::X9,
+        Register::X10,
+        Register::X11,
+        Register::X12,
+        Register::X13,
+        Register::X14,
+        Register::X15,
+        Register::X16,
+        Register::X17,
+        Register::X18,
+        Register::X19,
+        Register::X20,
+        Register::X21,
+        Register::X22,
+        Register::X23,
+        Register::X24,
+        Register::X25,
+        Register::X26,
+        Register::X27,
+        Register::X28,
+        Register::X29,
+        Register::X30,
+        Register::X31,
+        Register::PC,
+    ];
+
+    const NAMES: [&'static str; 33] = [
+        "X0", "X1", "X2", "X3", "X4", "X5", "X6", "X7", "X8", "X9", "X10", "X11", "X12", "X13",
+        "X14", "X15", "X16", "X17", "X18", "X19", "X20", "X21", "X22", "X23", "X24", "X25", "X26",
+        "X27", "X28", "X29", "X30", "X31", "PC",
+    ];
+
+    /// The register's name, in upper case (eg: `X1` or `PC`)
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    /// The register's ABI name (`S0` rather than `FP` for X8); the PC
+    /// doesn't have one
+    pub fn abi(&self) -> Option<ABI> {
+        ABI::NAMES
+            .iter()
+            .map(|(_, abi)| *abi)
+            .find(|abi| abi.to_register() == *self)
+    }
+
+    /// Finds a register by its upper case name (eg: `X1`), or its ABI name
+    /// (eg: `RA`)
+    pub fn from_name(name: &str) -> Option<Register> {
+        match Self::NAMES.iter().position(|n| *n == name) {
+            Some(i) => Some(Self::ALL[i]),
+            None => ABI::from_name(name).map(|abi| abi.to_register()),
+        }
+    }
+}
+
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(&self.name().to_lowercase())
+    }
+}
+
 /// ABI ("application binary interface") mapping for [CPU](crate::rv32_i::CPU) registers.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ABI {
     /// Always zero; X0 register
     Zero,
@@ -122,6 +196,59 @@ pub enum ABI {
 }
 
 impl ABI {
+    const NAMES: [(&'static str, ABI); 33] = [
+        ("ZERO", Self::Zero),
+        ("RA", Self::RA),
+        ("SP", Self::SP),
+        ("GP", Self::GP),
+        ("TP", Self::TP),
+        ("T0", Self::T0),
+        ("T1", Self::T1),
+        ("T2", Self::T2),
+        ("S0", Self::S0),
+        ("FP", Self::FP),
+        ("S1", Self::S1),
+        ("A0", Self::A0),
+        ("A1", Self::A1),
+        ("A2", Self::A2),
+        ("A3", Self::A3),
+        ("A4", Self::A4),
+        ("A5", Self::A5),
+        ("A6", Self::A6),
+        ("A7", Self::A7),
+        ("S2", Self::S2),
+        ("S3", Self::S3),
+        ("S4", Self::S4),
+        ("S5", Self::S5),
+        ("S6", Self::S6),
+        ("S7", Self::S7),
+        ("S8", Self::S8),
+        ("S9", Self::S9),
+        ("S10", Self::S10),
+        ("S11", Self::S11),
+        ("T3", Self::T3),
+        ("T4", Self::T4),
+        ("T5", Self::T5),
+        ("T6", Self::T6),
+    ];
+
+    /// Finds an ABI register by its upper case name (eg: `SP`)
+    pub fn from_name(name: &str) -> Option<ABI> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, abi)| *abi)
+    }
+
+    /// The ABI name, in upper case (eg: `SP`)
+    pub fn name(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, abi)| abi == self)
+            .map(|(n, _)| *n)
+            .expect("every ABI register has a name")
+    }
+
     /// Provides the cooresponding CPU register for the ABI register
     pub fn to_register(&self) -> Register {
         match self {
@@ -0,0 +1,405 @@
+//! RV64I: the same instructions on 64-bit registers.
+//!
+//! A CPU made with [CPU::new_rv64] (or with [Xlen::Rv64] in its
+//! [CpuConfig]) works on 64-bit values: immediates and 32-bit results are
+//! sign extended to 64 bits, comparisons and shifts see the whole register,
+//! and a few instructions are added. The `W` instructions (`ADDIW`, `ADDW`,
+//! `SUBW`, and the shifts) work on the low 32 bits and sign extend the
+//! result, as C's `int` arithmetic needs; `LD` and `SD` move doublewords;
+//! and `LWU` loads a word without sign extending it.
+//!
+//! ```
+//! use brubeck::rv32_i::*;
+//!
+//! let mut cpu = CPU::new_rv64();
+//! let mut addi = IType::default();
+//! addi.rd = Register::X10;
+//! addi.imm.set_signed(-1).unwrap();
+//! cpu.execute(Instruction::ADDI(addi)).unwrap();
+//!
+//! assert_eq!(cpu.get_x(Register::X10), u64::MAX);
+//! // the register fields hold the low half
+//! assert_eq!(cpu.x10, u32::MAX);
+//! ```
+//!
+//! The rest of the machine is still 32 bits wide: the PC and addresses (the
+//! top half of a computed address is dropped) and CSRs. The
+//! [history](crate::history), checkpoints, and snapshots keep whole
+//! registers, so undo puts back all 64 bits. The M, A, and C extensions
+//! aren't available in RV64 mode.
+
+use super::*;
+
+/// The width of the integer registers
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Xlen {
+    #[default]
+    Rv32,
+    Rv64,
+}
+
+impl Xlen {
+    pub fn bits(&self) -> u32 {
+        match self {
+            Self::Rv32 => 32,
+            Self::Rv64 => 64,
+        }
+    }
+}
+
+impl CPU {
+    /// Creates a CPU implementing RV64I, with 1 mebibyte of memory; see
+    /// [Xlen]
+    pub fn new_rv64() -> Self {
+        Self::with_config(CpuConfig {
+            xlen: Xlen::Rv64,
+            ..CpuConfig::default()
+        })
+    }
+
+    /// Executes an instruction with 64-bit registers. Those that only see
+    /// the low 32 bits of their registers (CSRs, jumps, and stores of a word
+    /// or less) are the same as in RV32I.
+    pub(super) fn execute_rv64(&mut self, instruction: Instruction) -> Result<(), Error> {
+        use Instruction::*;
+
+        match instruction {
+            ADD(r) => self.rv64i_op(r, u64::wrapping_add),
+            SUB(r) => self.rv64i_op(r, u64::wrapping_sub),
+            SLL(r) => self.rv64i_op(r, |a, b| a << (b & 0x3f)),
+            SLT(r) => self.rv64i_op(r, |a, b| u64::from((a as i64) < (b as i64))),
+            SLTU(r) => self.rv64i_op(r, |a, b| u64::from(a < b)),
+            XOR(r) => self.rv64i_op(r, |a, b| a ^ b),
+            SRL(r) => self.rv64i_op(r, |a, b| a >> (b & 0x3f)),
+            SRA(r) => self.rv64i_op(r, |a, b| ((a as i64) >> (b & 0x3f)) as u64),
+            OR(r) => self.rv64i_op(r, |a, b| a | b),
+            AND(r) => self.rv64i_op(r, |a, b| a & b),
+
+            ADDI(i) => self.rv64i_op_imm(i, u64::wrapping_add),
+            SLTI(i) => self.rv64i_op_imm(i, |a, b| u64::from((a as i64) < (b as i64))),
+            SLTIU(i) => self.rv64i_op_imm(i, |a, b| u64::from(a < b)),
+            XORI(i) => self.rv64i_op_imm(i, |a, b| a ^ b),
+            ORI(i) => self.rv64i_op_imm(i, |a, b| a | b),
+            ANDI(i) => self.rv64i_op_imm(i, |a, b| a & b),
+            SLLI(i) => self.rv64i_op_imm(i, |a, b| a << (b & 0x3f)),
+            SRLI(i) => self.rv64i_op_imm(i, |a, b| a >> (b & 0x3f)),
+            SRAI(i) => self.rv64i_op_imm(i, |a, b| ((a as i64) >> (b & 0x3f)) as u64),
+
+            ADDW(r) => self.rv64i_op_w(r, u32::wrapping_add),
+            SUBW(r) => self.rv64i_op_w(r, u32::wrapping_sub),
+            SLLW(r) => self.rv64i_op_w(r, |a, b| a << (b & 0x1f)),
+            SRLW(r) => self.rv64i_op_w(r, |a, b| a >> (b & 0x1f)),
+            SRAW(r) => self.rv64i_op_w(r, |a, b| ((a as i32) >> (b & 0x1f)) as u32),
+            ADDIW(i) => self.rv64i_op_imm_w(i, u32::wrapping_add),
+            SLLIW(i) => self.rv64i_op_imm_w(i, |a, b| a << (b & 0x1f)),
+            SRLIW(i) => self.rv64i_op_imm_w(i, |a, b| a >> (b & 0x1f)),
+            SRAIW(i) => self.rv64i_op_imm_w(i, |a, b| ((a as i32) >> (b & 0x1f)) as u32),
+
+            LUI(u) => {
+                self.set_x(u.rd, sign_extend(u.imm.as_u32() << 12));
+                self.increment_pc()
+            }
+            AUIPC(u) => {
+                let offset = sign_extend(u.imm.as_u32() << 12);
+                self.set_x(u.rd, u64::from(self.pc).wrapping_add(offset));
+                self.increment_pc()
+            }
+
+            BEQ(b) => self.rv64i_branch(b, |a, b| a == b),
+            BNE(b) => self.rv64i_branch(b, |a, b| a != b),
+            BLT(b) => self.rv64i_branch(b, |a, b| (a as i64) < (b as i64)),
+            BGE(b) => self.rv64i_branch(b, |a, b| (a as i64) >= (b as i64)),
+            BLTU(b) => self.rv64i_branch(b, |a, b| a < b),
+            BGEU(b) => self.rv64i_branch(b, |a, b| a >= b),
+
+            LB(i) => self.rv64i_load(LB(i), i, 1, |v| v as u8 as i8 as u64),
+            LBU(i) => self.rv64i_load(LBU(i), i, 1, |v| v as u8 as u64),
+            LH(i) => self.rv64i_load(LH(i), i, 2, |v| v as u16 as i16 as u64),
+            LHU(i) => self.rv64i_load(LHU(i), i, 2, |v| v as u16 as u64),
+            LW(i) => self.rv64i_load(LW(i), i, 4, |v| sign_extend(v as u32)),
+            LWU(i) => self.rv64i_load(LWU(i), i, 4, |v| v as u32 as u64),
+            LD(i) => self.rv64i_load(LD(i), i, 8, |v| v),
+            SD(s) => self.rv64i_sd(s),
+
+            _ if instruction.is_multiply() || instruction.is_atomic() => {
+                Err(Error::NotImplemented(instruction))
+            }
+            _ => self.execute_rv32(instruction),
+        }
+    }
+
+    fn rv64i_op(&mut self, instruction: RType, op: fn(u64, u64) -> u64) -> Result<(), Error> {
+        let a = self.get_x(instruction.rs1);
+        let b = self.get_x(instruction.rs2);
+        self.set_x(instruction.rd, op(a, b));
+        self.increment_pc()
+    }
+
+    /// The immediate is sign extended to 64 bits
+    fn rv64i_op_imm(&mut self, instruction: IType, op: fn(u64, u64) -> u64) -> Result<(), Error> {
+        let a = self.get_x(instruction.rs1);
+        let imm = sign_extend(instruction.imm.as_u32());
+        self.set_x(instruction.rd, op(a, imm));
+        self.increment_pc()
+    }
+
+    /// The `W` instructions work on the low 32 bits of their operands, and
+    /// sign extend the 32-bit result
+    fn rv64i_op_w(&mut self, instruction: RType, op: fn(u32, u32) -> u32) -> Result<(), Error> {
+        let a = self.get_register(instruction.rs1);
+        let b = self.get_register(instruction.rs2);
+        self.set_x(instruction.rd, sign_extend(op(a, b)));
+        self.increment_pc()
+    }
+
+    fn rv64i_op_imm_w(&mut self, instruction: IType, op: fn(u32, u32) -> u32) -> Result<(), Error> {
+        let a = self.get_register(instruction.rs1);
+        self.set_x(instruction.rd, sign_extend(op(a, instruction.imm.as_u32())));
+        self.increment_pc()
+    }
+
+    fn rv64i_branch(
+        &mut self,
+        instruction: BType,
+        taken: fn(u64, u64) -> bool,
+    ) -> Result<(), Error> {
+        let a = self.get_x(instruction.rs1);
+        let b = self.get_x(instruction.rs2);
+        match taken(a, b) {
+            true => self.branch(instruction),
+            false => self.increment_pc(),
+        }
+    }
+
+    /// Loads `bytes` (a doubleword as two words, low first), and extends
+    /// them to 64 bits with `extend`
+    fn rv64i_load(
+        &mut self,
+        load: Instruction,
+        instruction: IType,
+        bytes: u32,
+        extend: fn(u64) -> u64,
+    ) -> Result<(), Error> {
+        let address = self
+            .get_register(instruction.rs1)
+            .wrapping_add(instruction.imm.as_u32());
+        if self.misaligned(load, address, bytes)? {
+            return Ok(());
+        }
+
+        let value = match bytes {
+            8 => {
+                let low = self.load(address, 4)?;
+                let high = self.load(address.wrapping_add(4), 4)?;
+                u64::from(high) << 32 | u64::from(low)
+            }
+            _ => u64::from(self.load(address, bytes as usize)?),
+        };
+
+        self.set_x(instruction.rd, extend(value));
+        self.increment_pc()
+    }
+
+    /// SD stores the whole of rs2, as two words, low first
+    fn rv64i_sd(&mut self, instruction: SType) -> Result<(), Error> {
+        let address = self
+            .get_register(instruction.rs1)
+            .wrapping_add(instruction.imm.as_u32());
+        if self.misaligned(Instruction::SD(instruction), address, 8)? {
+            return Ok(());
+        }
+
+        let value = self.get_x(instruction.rs2);
+        let words = [
+            (address, value as u32),
+            (address.wrapping_add(4), (value >> 32) as u32),
+        ];
+        // a fault on the second word mustn't leave the first one written
+        for (address, _) in words {
+            if !self.devices.contains(address) {
+                self.read_mem_u32(address)?;
+            }
+        }
+        for (address, word) in words {
+            if !self.devices.write(address, 4, word) {
+                self.write_mem_u32(address, word)?;
+            }
+        }
+        self.increment_pc()
+    }
+}
+
+/// A 32-bit value sign extended to 64 bits
+fn sign_extend(value: u32) -> u64 {
+    value as i32 as i64 as u64
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{self, Command};
+
+    /// Runs instructions, written as the parser reads them
+    fn run(cpu: &mut CPU, program: &[&str]) {
+        for line in program {
+            match interpreter::parse(line) {
+                Ok(Command::Exec(instruction)) => cpu.execute(instruction).unwrap(),
+                other => panic!("{line}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rv64() {
+        let mut cpu = CPU::new_rv64();
+        run(
+            &mut cpu,
+            &[
+                "ADDI a0, zero, 4095", // -1, all 64 bits
+                "SRLI a1, a0, 32",     // 0x0000_0000_ffff_ffff
+                "ADDIW a2, a1, 0",     // the low word, sign extended: -1
+                "LUI a3, 524288",      // 0xffff_ffff_8000_0000
+                "SLLI a4, a1, 63",     // 0x8000_0000_0000_0000
+                "ADDW a5, a3, a3",     // 0 (the carry out of 32 bits is lost)
+                "ADD a6, a3, a3",      // 0xffff_ffff_0000_0000
+                "SLT a7, a4, zero",    // 1 (the top bit is the sign)
+                "SRAIW t0, a3, 4",     // 0xffff_ffff_f800_0000
+                "SUBW t1, zero, a0",   // 1
+            ],
+        );
+
+        assert_eq!(cpu.get_x(Register::X10), u64::MAX);
+        assert_eq!(cpu.get_x(Register::X11), 0xffff_ffff);
+        assert_eq!(cpu.get_x(Register::X12), u64::MAX);
+        assert_eq!(cpu.get_x(Register::X13), 0xffff_ffff_8000_0000);
+        assert_eq!(cpu.get_x(Register::X14), 0x8000_0000_0000_0000);
+        assert_eq!(cpu.get_x(Register::X15), 0);
+        assert_eq!(cpu.get_x(Register::X16), 0xffff_ffff_0000_0000);
+        assert_eq!(cpu.get_x(Register::X17), 1);
+        assert_eq!(cpu.get_x(Register::X5), 0xffff_ffff_f800_0000);
+        assert_eq!(cpu.get_x(Register::X6), 1);
+
+        // the branch sees the whole register: a1 and a0 only differ on top
+        let pc = cpu.pc;
+        run(&mut cpu, &["BEQ a0, a1, 8"]);
+        assert_eq!(cpu.pc, pc + 4);
+
+        // doublewords, and words with and without sign extension
+        run(
+            &mut cpu,
+            &[
+                "ADDI sp, zero, 256",
+                "SD sp, a3, 0",
+                "LD s0, sp, 0",
+                "LW s1, sp, 0",
+                "LWU s2, sp, 0",
+                "LW s3, sp, 4",
+            ],
+        );
+        assert_eq!(
+            cpu.memory[256..264],
+            [0, 0, 0, 0x80, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(cpu.get_x(Register::X8), 0xffff_ffff_8000_0000);
+        assert_eq!(cpu.get_x(Register::X9), 0xffff_ffff_8000_0000);
+        assert_eq!(cpu.get_x(Register::X18), 0x8000_0000);
+        assert_eq!(cpu.get_x(Register::X19), u64::MAX);
+
+        // 32-bit writes (eg: a CSR read) zero the top half
+        cpu.set_register(Register::X8, 5);
+        assert_eq!(cpu.get_x(Register::X8), 5);
+
+        // the RV64 instructions aren't in RV32I, and the M extension isn't
+        // in RV64 mode
+        let addw = Instruction::ADDW(RType::default());
+        assert!(matches!(
+            CPU::default().execute(addw),
+            Err(Error::NotImplemented(_))
+        ));
+        assert!(matches!(
+            cpu.execute(Instruction::MUL(RType::default())),
+            Err(Error::NotImplemented(_))
+        ));
+    }
+
+    #[test]
+    fn whole_registers_are_kept() {
+        let mut i = crate::Interpreter::new();
+        i.cpu.config.xlen = Xlen::Rv64;
+        i.interpret("ADDI a0, zero, 4095").unwrap();
+        i.checkpoint("all ones");
+        let snapshot = i.snapshot();
+        i.interpret("ADDI a0, zero, 1").unwrap();
+
+        i.undo().unwrap();
+        assert_eq!(i.cpu.get_x(Register::X10), u64::MAX);
+        i.redo().unwrap();
+        i.restore_checkpoint("all ones").unwrap();
+        assert_eq!(i.cpu.get_x(Register::X10), u64::MAX);
+
+        i.interpret("ADDI a0, zero, 1").unwrap();
+        let restored = crate::snapshot::Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        i.restore(&restored).unwrap();
+        assert_eq!(i.cpu.get_x(Register::X10), u64::MAX);
+
+        // and so does a saved session's history
+        i.interpret("ADDI a0, zero, 1").unwrap();
+        let mut session = crate::Interpreter::new();
+        session.cpu.config.xlen = Xlen::Rv64;
+        session.restore_bytes(&i.to_bytes()).unwrap();
+        session.undo().unwrap();
+        assert_eq!(session.cpu.get_x(Register::X10), u64::MAX);
+    }
+
+    #[test]
+    fn doublewords_are_stored_whole() {
+        let mut cpu = CPU::new_rv64();
+        let end = cpu.memory.len() as u32;
+        cpu.set_x(Register::X10, u64::MAX);
+        cpu.set_register(Register::X11, end - 4);
+        let Ok(Command::Exec(sd)) = interpreter::parse("SD a1, a0, 0") else {
+            panic!("SD doesn't parse");
+        };
+        assert!(matches!(cpu.execute(sd), Err(Error::AccessViolation(_))));
+        assert_eq!(cpu.read_mem_u32(end - 4).unwrap(), 0);
+    }
+
+    #[test]
+    fn shifts_past_31_need_rv64() {
+        // slli ra, ra, 32
+        let slli = Instruction::decode(0x0200_9093).unwrap();
+        let mut cpu = CPU::default();
+        cpu.set_register(Register::X1, 1);
+        assert!(matches!(
+            cpu.execute(slli),
+            Err(Error::IllegalInstruction(_))
+        ));
+        assert_eq!(cpu.get_register(Register::X1), 1);
+
+        cpu.config.xlen = Xlen::Rv64;
+        cpu.execute(slli).unwrap();
+        assert_eq!(cpu.get_x(Register::X1), 1 << 32);
+    }
+
+    #[test]
+    fn encodings() {
+        // from the LLVM assembler
+        for (word, text) in [
+            (0x0015_851b, "addiw a0, a1, 1"),
+            (0x00c5_853b, "addw a0, a1, a2"),
+            (0x40c5_853b, "subw a0, a1, a2"),
+            (0x0085_b503, "ld a0, a1, 8"),
+            (0x0085_e503, "lwu a0, a1, 8"),
+            (0x00a5_b423, "sd a1, a0, 8"),
+            (0x4015_d51b, "sraiw a0, a1, 1"),
+            (0x00c5_953b, "sllw a0, a1, a2"),
+            (0x0205_9513, "slli a0, a1, 32"),
+            (0x43f5_d513, "srai a0, a1, 63"),
+        ] {
+            let instruction = Instruction::decode(word).unwrap();
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(instruction.encode(), word, "{text}");
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! A second, deliberately minimal definition of what instructions do, used to
+//! double-check the [CPU].
+//!
+//! [outcome] is a pure function from an instruction and the registers before
+//! it ran to the registers after. It shares no code with the CPU, so when
+//! the [Interpreter](crate::Interpreter) runs with double-checking on (see
+//! [set_double_check](crate::Interpreter::set_double_check)) a difference
+//! between the two points at a bug in one of them.
+//!
+//! Only instructions whose effect depends on nothing but registers are
+//! covered; loads, stores, CSRs, and system instructions return `None`.
+
+use super::*;
+
+/// The values of every register, in [Register::ALL] order, as RV32 has them
+pub type Registers = [u32; 33];
+
+/// The registers after running `instruction`, which is `length` bytes long
+/// (2 if it was compressed), or None if it isn't covered
+pub fn outcome(instruction: Instruction, length: u32, before: &Registers) -> Option<Registers> {
+    let read = |register: Register| before[register as usize];
+    let pc = before[Register::PC as usize];
+    let next = pc.wrapping_add(length);
+
+    // (destination and its value, new pc)
+    let (write, pc): (Option<(Register, u32)>, u32) = match instruction {
+        Instruction::NOP => (None, next),
+        Instruction::LUI(u) => (Some((u.rd, u.imm.as_u32() << 12)), next),
+        Instruction::AUIPC(u) => (Some((u.rd, pc.wrapping_add(u.imm.as_u32() << 12))), next),
+        Instruction::JAL(j) => (Some((j.rd, next)), pc.wrapping_add(j.imm.as_u32() << 1)),
+        Instruction::JALR(i) => {
+            let target = read(i.rs1).wrapping_add(i.imm.as_u32()) & !1;
+            (Some((i.rd, next)), target)
+        }
+
+        Instruction::BEQ(b)
+        | Instruction::BNE(b)
+        | Instruction::BLT(b)
+        | Instruction::BGE(b)
+        | Instruction::BLTU(b)
+        | Instruction::BGEU(b) => {
+            let taken = compare(instruction, read(b.rs1), read(b.rs2))?;
+            let target = pc.wrapping_add(b.imm.as_u32() << 1);
+            (None, if taken { target } else { next })
+        }
+
+        Instruction::ADD(r)
+        | Instruction::SUB(r)
+        | Instruction::SLL(r)
+        | Instruction::SLT(r)
+        | Instruction::SLTU(r)
+        | Instruction::XOR(r)
+        | Instruction::SRL(r)
+        | Instruction::SRA(r)
+        | Instruction::OR(r)
+        | Instruction::AND(r)
+        | Instruction::MUL(r)
+        | Instruction::MULH(r)
+        | Instruction::MULHSU(r)
+        | Instruction::MULHU(r)
+        | Instruction::DIV(r)
+        | Instruction::DIVU(r)
+        | Instruction::REM(r)
+        | Instruction::REMU(r) => {
+            let value = arithmetic(instruction, read(r.rs1), read(r.rs2))?;
+            (Some((r.rd, value)), next)
+        }
+
+        Instruction::ADDI(i)
+        | Instruction::SLTI(i)
+        | Instruction::SLTIU(i)
+        | Instruction::XORI(i)
+        | Instruction::ORI(i)
+        | Instruction::ANDI(i)
+        | Instruction::SLLI(i)
+        | Instruction::SRLI(i)
+        | Instruction::SRAI(i) => {
+            let value = arithmetic(instruction, read(i.rs1), i.imm.as_u32())?;
+            (Some((i.rd, value)), next)
+        }
+
+        _ => return None,
+    };
+
+    let mut after = *before;
+    if let Some((rd, value)) = write.filter(|(rd, _)| *rd != Register::X0) {
+        after[rd as usize] = value;
+    }
+    after[Register::PC as usize] = pc;
+    Some(after)
+}
+
+/// Register-register and register-immediate operations; `b` is rs2 or the
+/// sign-extended immediate
+fn arithmetic(instruction: Instruction, a: u32, b: u32) -> Option<u32> {
+    let (signed_a, signed_b) = (a as i32 as i64, b as i32 as i64);
+    let shift = b % 32;
+
+    Some(match instruction {
+        Instruction::ADD(_) | Instruction::ADDI(_) => (signed_a + signed_b) as u32,
+        Instruction::SUB(_) => (signed_a - signed_b) as u32,
+        Instruction::SLT(_) | Instruction::SLTI(_) => (signed_a < signed_b) as u32,
+        Instruction::SLTU(_) | Instruction::SLTIU(_) => (a < b) as u32,
+        Instruction::XOR(_) | Instruction::XORI(_) => a ^ b,
+        Instruction::OR(_) | Instruction::ORI(_) => a | b,
+        Instruction::AND(_) | Instruction::ANDI(_) => a & b,
+        Instruction::SLL(_) | Instruction::SLLI(_) => ((a as u64) << shift) as u32,
+        Instruction::SRL(_) | Instruction::SRLI(_) => ((a as u64) >> shift) as u32,
+        Instruction::SRA(_) | Instruction::SRAI(_) => (signed_a >> shift) as u32,
+
+        // in 64 bits nothing overflows, and the spec's results for dividing
+        // by zero are spelled out
+        Instruction::MUL(_) => (signed_a * signed_b) as u32,
+        Instruction::MULH(_) => ((signed_a * signed_b) >> 32) as u32,
+        Instruction::MULHSU(_) => ((signed_a * b as i64) >> 32) as u32,
+        Instruction::MULHU(_) => ((a as u64 * b as u64) >> 32) as u32,
+        Instruction::DIV(_) if b == 0 => u32::MAX,
+        Instruction::DIV(_) => (signed_a / signed_b) as u32,
+        Instruction::DIVU(_) if b == 0 => u32::MAX,
+        Instruction::DIVU(_) => a / b,
+        Instruction::REM(_) if b == 0 => a,
+        Instruction::REM(_) => (signed_a % signed_b) as u32,
+        Instruction::REMU(_) if b == 0 => a,
+        Instruction::REMU(_) => a % b,
+        _ => return None,
+    })
+}
+
+/// Whether a branch is taken
+fn compare(instruction: Instruction, a: u32, b: u32) -> Option<bool> {
+    let (signed_a, signed_b) = (a as i32 as i64, b as i32 as i64);
+
+    Some(match instruction {
+        Instruction::BEQ(_) => a == b,
+        Instruction::BNE(_) => a != b,
+        Instruction::BLT(_) => signed_a < signed_b,
+        Instruction::BGE(_) => signed_a >= signed_b,
+        Instruction::BLTU(_) => (a as u64) < (b as u64),
+        Instruction::BGEU(_) => (a as u64) >= (b as u64),
+        _ => return None,
+    })
+}
@@ -0,0 +1,146 @@
+//! The standard reasons for a trap, as recorded in `mcause`.
+//!
+//! The top bit of `mcause` says whether the trap was an interrupt (set) or an
+//! exception (clear), and the rest is the exception code. Codes not listed
+//! here are reserved or for custom use.
+//!
+//! ```
+//! use brubeck::rv32_i::TrapCause;
+//!
+//! assert_eq!(TrapCause::from_mcause(2), Some(TrapCause::IllegalInstruction));
+//! assert_eq!(TrapCause::MachineTimerInterrupt.mcause(), 0x8000_0007);
+//! assert_eq!(TrapCause::describe(11), "environment call from M-mode (11)");
+//! assert_eq!(TrapCause::describe(42), "unknown cause (42)");
+//! ```
+
+use std::fmt::Display;
+
+/// Set in `mcause` for interrupts
+pub const INTERRUPT: u32 = 1 << 31;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TrapCause {
+    InstructionAddressMisaligned = 0,
+    InstructionAccessFault = 1,
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadAddressMisaligned = 4,
+    LoadAccessFault = 5,
+    StoreAddressMisaligned = 6,
+    StoreAccessFault = 7,
+    UserEcall = 8,
+    SupervisorEcall = 9,
+    MachineEcall = 11,
+    InstructionPageFault = 12,
+    LoadPageFault = 13,
+    StorePageFault = 15,
+
+    SupervisorSoftwareInterrupt = INTERRUPT | 1,
+    MachineSoftwareInterrupt = INTERRUPT | 3,
+    SupervisorTimerInterrupt = INTERRUPT | 5,
+    MachineTimerInterrupt = INTERRUPT | 7,
+    SupervisorExternalInterrupt = INTERRUPT | 9,
+    MachineExternalInterrupt = INTERRUPT | 11,
+}
+
+impl TrapCause {
+    pub const ALL: [Self; 20] = [
+        Self::InstructionAddressMisaligned,
+        Self::InstructionAccessFault,
+        Self::IllegalInstruction,
+        Self::Breakpoint,
+        Self::LoadAddressMisaligned,
+        Self::LoadAccessFault,
+        Self::StoreAddressMisaligned,
+        Self::StoreAccessFault,
+        Self::UserEcall,
+        Self::SupervisorEcall,
+        Self::MachineEcall,
+        Self::InstructionPageFault,
+        Self::LoadPageFault,
+        Self::StorePageFault,
+        Self::SupervisorSoftwareInterrupt,
+        Self::MachineSoftwareInterrupt,
+        Self::SupervisorTimerInterrupt,
+        Self::MachineTimerInterrupt,
+        Self::SupervisorExternalInterrupt,
+        Self::MachineExternalInterrupt,
+    ];
+
+    /// The cause for an `mcause` value, if it's a standard one
+    pub fn from_mcause(mcause: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.mcause() == mcause)
+    }
+
+    /// The value written to `mcause`
+    pub fn mcause(self) -> u32 {
+        self as u32
+    }
+
+    pub fn is_interrupt(self) -> bool {
+        self.mcause() & INTERRUPT != 0
+    }
+
+    /// Lower case description, as in the privileged spec
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::InstructionAddressMisaligned => "instruction address misaligned",
+            Self::InstructionAccessFault => "instruction access fault",
+            Self::IllegalInstruction => "illegal instruction",
+            Self::Breakpoint => "breakpoint",
+            Self::LoadAddressMisaligned => "load address misaligned",
+            Self::LoadAccessFault => "load access fault",
+            Self::StoreAddressMisaligned => "store/AMO address misaligned",
+            Self::StoreAccessFault => "store/AMO access fault",
+            Self::UserEcall => "environment call from U-mode",
+            Self::SupervisorEcall => "environment call from S-mode",
+            Self::MachineEcall => "environment call from M-mode",
+            Self::InstructionPageFault => "instruction page fault",
+            Self::LoadPageFault => "load page fault",
+            Self::StorePageFault => "store/AMO page fault",
+            Self::SupervisorSoftwareInterrupt => "supervisor software interrupt",
+            Self::MachineSoftwareInterrupt => "machine software interrupt",
+            Self::SupervisorTimerInterrupt => "supervisor timer interrupt",
+            Self::MachineTimerInterrupt => "machine timer interrupt",
+            Self::SupervisorExternalInterrupt => "supervisor external interrupt",
+            Self::MachineExternalInterrupt => "machine external interrupt",
+        }
+    }
+
+    /// Names any `mcause` value, with the raw code (in hex for interrupts)
+    pub fn describe(mcause: u32) -> String {
+        let name = Self::from_mcause(mcause).map_or("unknown cause", Self::name);
+        if mcause & INTERRUPT != 0 {
+            format!("{name} (0x{mcause:08x})")
+        } else {
+            format!("{name} ({mcause})")
+        }
+    }
+}
+
+impl Display for TrapCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog() {
+        for cause in TrapCause::ALL {
+            assert_eq!(TrapCause::from_mcause(cause.mcause()), Some(cause));
+        }
+        assert!(TrapCause::MachineExternalInterrupt.is_interrupt());
+        assert!(!TrapCause::MachineEcall.is_interrupt());
+
+        assert_eq!(TrapCause::from_mcause(10), None);
+        assert_eq!(
+            TrapCause::describe(0x8000_0007),
+            "machine timer interrupt (0x80000007)"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Scoped "what if?" execution that leaves no trace.
+//!
+//! [Interpreter::speculate](crate::Interpreter::speculate) hands a closure a
+//! [Sandbox] that starts from the current machine state. Instructions run in
+//! the sandbox change the CPU as usual, but when the sandbox is dropped
+//! everything is put back: registers, CSRs, counters, devices, the system
+//! call handler, the exit code, and every byte of memory that was stored to.
+//!
+//! Memory isn't copied up front. Stores are already recorded with their old
+//! values (see [CPU::last_writes]), so the sandbox keeps a journal of them
+//! and writes the old bytes back in reverse; the cost is proportional to the
+//! memory touched rather than the memory size. The other state is small and
+//! is simply copied.
+//!
+//! Semihosting is switched off inside a sandbox, since its file operations
+//! reach the host and can't be taken back.
+//!
+//! ```
+//! use brubeck::Interpreter;
+//!
+//! let mut i = Interpreter::new();
+//! let x1 = i.speculate(|sandbox| {
+//!     sandbox.interpret("ADDI x1, zero, 5").unwrap();
+//!     sandbox.interpret("SLLI x1, x1, 2").unwrap();
+//!     sandbox.cpu().x1
+//! });
+//!
+//! assert_eq!(x1, 20);
+//! assert_eq!(i.cpu.x1, 0);
+//! assert_eq!(i.cpu.pc, 0);
+//! ```
+
+use crate::devices::Bus;
+use crate::history::{self, Registers, StateDelta};
+use crate::interpreter::{self, Command};
+use crate::rv32_i::{Counters, CsrFile, Instruction, MemoryWrite, Privilege, CPU};
+use crate::semihosting::Semihosting;
+use crate::syscalls::SyscallHandler;
+
+/// A CPU that's restored when dropped; see the [module](self) docs
+pub struct Sandbox<'a> {
+    cpu: &'a mut CPU,
+    saved: Saved,
+    /// Every store made in the sandbox, oldest first
+    journal: Vec<MemoryWrite>,
+}
+
+/// What's copied when a sandbox starts
+struct Saved {
+    registers: Registers,
+    csrs: CsrFile,
+    privilege: Privilege,
+    counters: Counters,
+    devices: Bus,
+    semihosting: Option<Semihosting>,
+    syscalls: Option<Box<dyn SyscallHandler>>,
+    exit_code: Option<u32>,
+}
+
+impl<'a> Sandbox<'a> {
+    pub(crate) fn new(cpu: &'a mut CPU) -> Self {
+        let saved = Saved {
+            registers: history::registers(cpu),
+            csrs: cpu.csrs.clone(),
+            privilege: cpu.privilege,
+            counters: cpu.counters,
+            devices: cpu.devices.clone(),
+            semihosting: cpu.semihosting.take(),
+            syscalls: cpu.syscalls.clone(),
+            exit_code: cpu.exit_code,
+        };
+
+        Self {
+            cpu,
+            saved,
+            journal: vec![],
+        }
+    }
+
+    /// The sandbox's CPU, with any changes made so far
+    pub fn cpu(&self) -> &CPU {
+        self.cpu
+    }
+
+    /// Executes an instruction, returning what it changed
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StateDelta, interpreter::Error> {
+        let before = history::registers(self.cpu);
+        let result = self.cpu.execute(instruction);
+
+        // a failed store may still have written some bytes
+        self.journal.extend_from_slice(self.cpu.last_writes());
+
+        match result {
+            Ok(()) => Ok(StateDelta::between(&before, self.cpu)),
+            Err(e) => Err(interpreter::Error::Cpu(e)),
+        }
+    }
+
+    /// Parses and executes an instruction (eg: `ADDI x1, zero, 3`)
+    pub fn interpret(&mut self, input: &str) -> Result<StateDelta, interpreter::Error> {
+        match interpreter::parse_at(input, self.cpu.pc, self.cpu.config.xlen)? {
+            Command::Exec(instruction) => self.execute(instruction),
+            Command::Sequence(instructions) => {
+                let deltas: Vec<StateDelta> = instructions
+                    .into_iter()
+                    .map(|instruction| self.execute(instruction))
+                    .collect::<Result<_, _>>()?;
+                Ok(StateDelta::merge(&deltas))
+            }
+            _ => Err(interpreter::Error::NotAnInstruction(
+                input.trim().to_owned(),
+            )),
+        }
+    }
+}
+
+impl Drop for Sandbox<'_> {
+    fn drop(&mut self) {
+        for write in self.journal.iter().rev() {
+            self.cpu
+                .write_memory(write.address, &write.old)
+                .expect("journaled writes are within memory");
+        }
+
+        history::set_registers(self.cpu, &self.saved.registers);
+        self.cpu.csrs.clone_from(&self.saved.csrs);
+        self.cpu.privilege = self.saved.privilege;
+        self.cpu.counters = self.saved.counters;
+        std::mem::swap(&mut self.cpu.devices, &mut self.saved.devices);
+        self.cpu.semihosting = self.saved.semihosting.take();
+        self.cpu.syscalls = self.saved.syscalls.take();
+        self.cpu.exit_code = self.saved.exit_code;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    #[test]
+    fn restores_everything() {
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x2, zero, 7").unwrap();
+        let before = i.snapshot();
+
+        let changed = i.speculate(|sandbox| {
+            sandbox.interpret("SW zero, x2, 16").unwrap();
+            sandbox.interpret("SB zero, x2, 17").unwrap();
+            sandbox.interpret("CSRRW x3, mscratch, x2").unwrap();
+            assert!(sandbox.interpret("x2").is_err());
+            assert!(sandbox.interpret("LW x4, zero, 1048576").is_err());
+
+            sandbox.cpu().memory[16..18].to_vec()
+        });
+
+        assert_eq!(changed, vec![7, 7]);
+        assert_eq!(i.snapshot(), before);
+        assert!(i.cpu.semihosting.is_some());
+
+        // the history is untouched too
+        assert!(i.undo().is_ok());
+        assert!(i.undo().is_err());
+    }
+}
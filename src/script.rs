@@ -0,0 +1,343 @@
+//! Scripts: instructions and a few commands, run one line after another
+//! without a REPL, eg: to check exercises automatically.
+//!
+//! [Interpreter::run_script](crate::Interpreter::run_script) runs each line
+//! the way the REPL would, and stops at the first one that fails, reporting
+//! its line number. Besides instructions and register names, a script can
+//! use these commands:
+//!
+//! | command        | does                                                |
+//! |----------------|-----------------------------------------------------|
+//! | `/assert a op b` | checks an [Assertion], eg: `/assert x1 == 42`     |
+//! | `/run [max]`   | runs from memory until the program exits            |
+//! | `/step [n]`    | runs the next `n` (default 1, at most [MAX_STEPS])  |
+//! | `/reset`       | resets the CPU                                      |
+//!
+//! Anything after a `#` is a comment. A `/run` that gets to its step limit
+//! before the program exits fails, so a script that loops forever doesn't
+//! pass. `brubeck --script` reads its lines with [lines] too, but can use
+//! every REPL command.
+//!
+//! ```
+//! use brubeck::Interpreter;
+//!
+//! let mut i = Interpreter::new();
+//! let script = "ADDI x1, zero, 40   # the answer, nearly\n\
+//!               ADDI x1, x1, 2\n\
+//!               /assert x1 == 42\n\
+//!               /assert x1 * 2 > 80";
+//! assert_eq!(i.run_script(script).unwrap(), 2);
+//!
+//! let error = i.run_script("/assert x1 == 41").unwrap_err();
+//! assert_eq!(error.line, 1);
+//! assert_eq!(
+//!     error.to_string(),
+//!     "line 1: Assertion failed: x1 == 41, but x1 is 42 (0x0000002a)"
+//! );
+//! ```
+
+use std::fmt::Display;
+
+use crate::expression::parse_number;
+use crate::interpreter::{self, Error, Stop};
+use crate::Interpreter;
+
+/// How many instructions `/run` executes before giving up, unless told,
+/// and the most `/step` runs at once
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// How the two sides of an [Assertion] are compared
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Operators, longest first so `<=` isn't read as `<`
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    /// Compares two values as signed (two's complement) numbers
+    pub fn holds(self, left: u32, right: u32) -> bool {
+        let (left, right) = (left as i32, right as i32);
+        match self {
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// A claim about the machine state: two
+/// [expressions](crate::expression) and how they compare, eg: `x1 == 42`
+/// or `[sp + 4] != 0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub left: String,
+    pub comparison: Comparison,
+    pub right: String,
+}
+
+impl Assertion {
+    /// Splits `left op right` at the comparison operator
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let found = (0..input.len()).find_map(|i| {
+            let rest = input.get(i..)?;
+            let (op, comparison) = Comparison::OPERATORS
+                .into_iter()
+                .find(|(op, _)| rest.starts_with(op))?;
+
+            // `<<` and `>>` are shifts, not comparisons
+            let shift = |c: u8| matches!(c, b'<' | b'>');
+            let bytes = input.as_bytes();
+            let before = i.checked_sub(1).map(|j| bytes[j]);
+            let after = bytes.get(i + op.len()).copied();
+            if op.len() == 1 && (before.is_some_and(shift) || after.is_some_and(shift)) {
+                return None;
+            }
+            Some((i, op.len(), comparison))
+        });
+
+        let Some((at, len, comparison)) = found else {
+            return Err(Error::Generic(format!(
+                "Expected a comparison (==, !=, <, <=, >, >=): {}",
+                input.trim()
+            )));
+        };
+        let (left, right) = (input[..at].trim(), input[at + len..].trim());
+        if left.is_empty() || right.is_empty() {
+            return Err(Error::Generic(format!(
+                "Expected a value on both sides: {}",
+                input.trim()
+            )));
+        }
+
+        Ok(Self {
+            left: left.to_owned(),
+            comparison,
+            right: right.to_owned(),
+        })
+    }
+
+    /// Evaluates both sides; fails with [Error::AssertionFailed] if the
+    /// comparison doesn't hold
+    pub fn check(&self, interpreter: &Interpreter) -> Result<(), Error> {
+        let evaluate = |side: &str| {
+            interpreter
+                .evaluate(side)
+                .map_err(|e| Error::Generic(format!("{side}: {e}")))
+        };
+        let (left, right) = (evaluate(&self.left)?, evaluate(&self.right)?);
+
+        if self.comparison.holds(left, right) {
+            return Ok(());
+        }
+
+        // the values of the sides that aren't just numbers
+        let values: Vec<_> = [(&self.left, left), (&self.right, right)]
+            .into_iter()
+            .filter(|(side, _)| parse_number(side).is_none())
+            .map(|(side, value)| format!("{side} is {} (0x{value:08x})", value as i32))
+            .collect();
+        match values.is_empty() {
+            true => Err(Error::AssertionFailed(self.to_string())),
+            false => Err(Error::AssertionFailed(format!(
+                "{self}, but {}",
+                values.join(" and ")
+            ))),
+        }
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (op, _) = Comparison::OPERATORS
+            .into_iter()
+            .find(|(_, c)| *c == self.comparison)
+            .expect("every comparison has an operator");
+        write!(f, "{} {op} {}", self.left, self.right)
+    }
+}
+
+/// The line a script stopped at (counting from 1), and why
+#[derive(Debug)]
+pub struct ScriptError {
+    pub line: usize,
+    pub error: Error,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+/// The lines of a script that do something, numbered from 1, without their
+/// comments and surrounding whitespace
+pub fn lines(script: &str) -> impl Iterator<Item = (usize, &str)> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, interpreter::strip_comment(line).trim()))
+        .filter(|(_, code)| !code.is_empty())
+}
+
+/// Runs a script; see the [module](self) docs. Returns the number of
+/// assertions checked.
+pub(crate) fn run(interpreter: &mut Interpreter, script: &str) -> Result<usize, ScriptError> {
+    let mut assertions = 0;
+    for (line, code) in lines(script) {
+        run_line(interpreter, code).map_err(|error| ScriptError { line, error })?;
+        assertions += code.starts_with("/assert") as usize;
+    }
+    Ok(assertions)
+}
+
+fn run_line(interpreter: &mut Interpreter, code: &str) -> Result<(), Error> {
+    if !code.starts_with('/') {
+        return interpreter.interpret(code).map(|_| ());
+    }
+
+    let (command, args) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+    let args = args.trim();
+    let count = |default: usize| match args {
+        "" => Ok(default),
+        _ => parse_number(args)
+            .map(|n| n as usize)
+            .ok_or_else(|| Error::Generic(format!("Invalid count {args}"))),
+    };
+
+    match command {
+        "/assert" => Assertion::parse(args)?.check(interpreter),
+        "/run" => {
+            let run = interpreter.run(count(MAX_STEPS)?);
+            match run.stop {
+                Stop::Failed(e) => Err(e),
+                Stop::Cancelled => Err(Error::Cancelled),
+                Stop::StepLimit => Err(Error::Generic(run.to_string())),
+                Stop::Exited(_)
+                | Stop::Breakpoint(_)
+                | Stop::Watchpoint(_)
+                | Stop::HistoryStart => Ok(()),
+            }
+        }
+        "/step" => match count(1)? {
+            count if count > MAX_STEPS => Err(Error::Generic(format!(
+                "Can't step more than {MAX_STEPS} instructions at once"
+            ))),
+            count => (0..count).try_for_each(|_| interpreter.step().map(|_| ())),
+        },
+        "/reset" if args.is_empty() => {
+            interpreter.reset();
+            Ok(())
+        }
+        _ => Err(Error::Generic(format!(
+            "Scripts can only use /assert, /run, /step, and /reset: {code}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assertions() {
+        let parse = |input| Assertion::parse(input).unwrap();
+        assert_eq!(
+            parse("x1 << 2 >= [sp + 4]"),
+            Assertion {
+                left: "x1 << 2".to_owned(),
+                comparison: Comparison::GreaterOrEqual,
+                right: "[sp + 4]".to_owned(),
+            }
+        );
+        assert_eq!(parse("a0>>1<a1").comparison, Comparison::Less);
+        assert_eq!(parse("a0 != 0").to_string(), "a0 != 0");
+        assert!(Assertion::parse("x1 + 1").is_err());
+        assert!(Assertion::parse("== 3").is_err());
+
+        assert!(Comparison::Less.holds(u32::MAX, 0));
+        assert!(Comparison::GreaterOrEqual.holds(3, 3));
+
+        let mut i = Interpreter::new();
+        i.interpret("ADDI x1, zero, 4095").unwrap();
+        assert!(parse("x1 == -1").check(&i).is_ok());
+        assert!(matches!(
+            parse("x1 > 0").check(&i),
+            Err(Error::AssertionFailed(message))
+                if message == "x1 > 0, but x1 is -1 (0xffffffff)"
+        ));
+        i.interpret("SW zero, x1, 20").unwrap();
+        assert!(parse("mem[20] == x1").check(&i).is_ok());
+        assert!(matches!(
+            parse("mem[16] == x1").check(&i),
+            Err(Error::AssertionFailed(message)) if message == "mem[16] == x1, but mem[16] is \
+                0 (0x00000000) and x1 is -1 (0xffffffff)"
+        ));
+        assert!(matches!(
+            parse("x99 == 0").check(&i),
+            Err(Error::Generic(_))
+        ));
+    }
+
+    #[test]
+    fn scripts() {
+        let mut i = Interpreter::new();
+        i.load_program(
+            "ADDI a0, zero, 3\n\
+             ADDI a7, zero, 93\n\
+             ECALL",
+        )
+        .unwrap();
+
+        let script = "/step 2\n\
+                      /assert pc == 8\n\
+                      \n\
+                      # run it, then check\n\
+                      /run\n\
+                      /assert a0 == 3\n\
+                      ADDI a0, a0, 1\n\
+                      /assert a0 == 4";
+        assert_eq!(i.run_script(script).unwrap(), 3);
+        assert_eq!(i.run_script("/reset\n/assert a0 == 0").unwrap(), 1);
+
+        let error = i.run_script("NOP\n/mem 0").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error
+            .to_string()
+            .starts_with("line 2: Scripts can only use"));
+        assert_eq!(i.run_script("ADDI x1\n").unwrap_err().line, 1);
+        assert_eq!(i.run_script("/step 1000001").unwrap_err().line, 1);
+
+        let script = "# setup\n  ADDI x1, zero, '#'  # a hash\n\n/assert x1 == 35";
+        assert_eq!(
+            lines(script).collect::<Vec<_>>(),
+            [(2, "ADDI x1, zero, '#'"), (4, "/assert x1 == 35")]
+        );
+
+        // a program that doesn't exit in time fails
+        let mut i = Interpreter::new();
+        i.load_program("JAL zero, 0").unwrap();
+        let error = i.run_script("# forever\n/run 10").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            error.to_string(),
+            "line 2: Stopped at the limit of 10 instructions"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Finding values and byte strings in memory.
+//!
+//! A [Pattern] is a run of bytes: a little endian value of 1, 2, or 4 bytes,
+//! or some text. [find] returns the guest address of every match, looking
+//! only at memory (never devices) and only within each mapped segment, so a
+//! match never spans two segments that happen to be next to each other in
+//! the memory buffer.
+//!
+//! ```
+//! use brubeck::rv32_i::CPU;
+//! use brubeck::search::{find, Pattern};
+//!
+//! let mut cpu = CPU::new(64);
+//! cpu.write_memory(6, &0xdead_beef_u32.to_le_bytes()).unwrap();
+//! cpu.write_memory(40, b"abc").unwrap();
+//!
+//! let word = Pattern::value(0xdead_beef, 4).unwrap();
+//! assert_eq!(find(&cpu, &word, 1), vec![6]);
+//! assert_eq!(find(&cpu, &word, 4), vec![]);
+//! assert_eq!(find(&cpu, &Pattern::text("abc"), 1), vec![40]);
+//! ```
+
+use std::fmt::Display;
+
+use crate::rv32_i::CPU;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// Only bytes, halfwords, and words can be searched for
+    InvalidWidth(usize),
+    /// The value needs more bytes than the width allows
+    TooWide(u32, usize),
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidWidth(width) => write!(f, "width must be 1, 2, or 4, not {width}"),
+            Self::TooWide(value, width) => {
+                write!(f, "0x{value:x} doesn't fit in {width} byte(s)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    /// `value` as `width` little endian bytes
+    pub fn value(value: u32, width: usize) -> Result<Self, PatternError> {
+        if !matches!(width, 1 | 2 | 4) {
+            return Err(PatternError::InvalidWidth(width));
+        }
+        if width < 4 && value >> (width * 8) != 0 {
+            return Err(PatternError::TooWide(value, width));
+        }
+
+        Ok(Self {
+            bytes: value.to_le_bytes()[..width].to_vec(),
+        })
+    }
+
+    /// The UTF-8 bytes of `text`, without a terminator
+    pub fn text(text: &str) -> Self {
+        Self::bytes(text.as_bytes())
+    }
+
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// The address of every match of `pattern` in memory that's a multiple of
+/// `alignment`, lowest first within each segment. Matches may overlap. An
+/// empty pattern matches nothing.
+pub fn find(cpu: &CPU, pattern: &Pattern, alignment: u32) -> Vec<u32> {
+    let alignment = alignment.max(1);
+    let Some(&first) = pattern.bytes.first() else {
+        return vec![];
+    };
+
+    let mut found = vec![];
+    for segment in cpu.address_map.segments() {
+        let Some(range) = cpu.address_map.available(segment.base) else {
+            continue;
+        };
+        let end = range.end.min(cpu.memory.len());
+        let Some(memory) = cpu.memory.get(range.start..end) else {
+            continue;
+        };
+
+        // skip straight to each occurrence of the first byte, then compare
+        // the rest
+        let mut start = 0;
+        while let Some(position) = memory[start..].iter().position(|b| *b == first) {
+            let index = start + position;
+            start = index + 1;
+
+            let address = segment.base.wrapping_add(index as u32);
+            if address % alignment == 0 && memory[index..].starts_with(&pattern.bytes) {
+                found.push(address);
+            }
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::CpuConfig;
+
+    #[test]
+    fn finds_within_segments() {
+        let mut cpu = CPU::with_config(CpuConfig {
+            memory_size: 16,
+            memory_base: 0x8000_0000,
+            reset_vector: 0x8000_0000,
+            ..CpuConfig::default()
+        });
+        cpu.map_segment(0x4000_0000, 16).unwrap();
+
+        // "ab" at the end of the first segment and "c" at the start of the
+        // second are next to each other in the buffer, but not in the guest
+        cpu.write_memory(0x8000_000e, b"ab").unwrap();
+        cpu.write_memory(0x4000_0000, b"cabab").unwrap();
+
+        assert_eq!(
+            find(&cpu, &Pattern::text("ab"), 1),
+            vec![0x8000_000e, 0x4000_0001, 0x4000_0003]
+        );
+        assert_eq!(find(&cpu, &Pattern::text("abc"), 1), vec![]);
+        assert_eq!(find(&cpu, &Pattern::text("ab"), 2), vec![0x8000_000e]);
+        assert_eq!(find(&cpu, &Pattern::text("aba"), 1), vec![0x4000_0001]);
+        assert_eq!(find(&cpu, &Pattern::text(""), 1), vec![]);
+
+        assert_eq!(Pattern::value(0x1234, 2), Ok(Pattern::bytes(&[0x34, 0x12])));
+        assert_eq!(
+            Pattern::value(0x1234, 1),
+            Err(PatternError::TooWide(0x1234, 1))
+        );
+        assert_eq!(Pattern::value(1, 3), Err(PatternError::InvalidWidth(3)));
+    }
+}
@@ -0,0 +1,541 @@
+//! RISC-V semihosting: a way for guest programs to ask the host for console
+//! output, files, the time, and to exit.
+//!
+//! A semihosting call is an `EBREAK` wrapped in a "magic" instruction
+//! sequence, so debuggers can tell it apart from a regular breakpoint:
+//!
+//! ```text
+//! slli x0, x0, 0x1f   # 0x01f01013
+//! ebreak              # 0x00100073
+//! srai x0, x0, 7      # 0x40705013
+//! ```
+//!
+//! The operation number goes in `a0` and its argument (usually a pointer to a
+//! block of words in memory) goes in `a1`. The result comes back in `a0`.
+//! Operation numbers are shared with Arm semihosting, so programs built
+//! against newlib's semihosting support work unchanged.
+//!
+//! Console output is collected in [Semihosting::console] rather than printed,
+//! and console input comes from [Semihosting::input], so the host stays in
+//! control. File operations are only allowed within [Semihosting::root], and
+//! fail if it isn't set. Without the `host` feature (eg: in a browser), only
+//! the console can be opened, and the clock and time calls fail.
+
+use std::collections::VecDeque;
+use std::path::{Component, Path, PathBuf};
+#[cfg(feature = "host")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::rv32_i::CPU;
+
+/// `slli x0, x0, 0x1f`, the instruction before a semihosting `EBREAK`
+pub const ENTRY: u32 = 0x01f0_1013;
+
+/// `srai x0, x0, 7`, the instruction after a semihosting `EBREAK`
+pub const EXIT: u32 = 0x4070_5013;
+
+pub const SYS_OPEN: u32 = 0x01;
+pub const SYS_CLOSE: u32 = 0x02;
+pub const SYS_WRITEC: u32 = 0x03;
+pub const SYS_WRITE0: u32 = 0x04;
+pub const SYS_WRITE: u32 = 0x05;
+pub const SYS_READ: u32 = 0x06;
+pub const SYS_READC: u32 = 0x07;
+pub const SYS_ISTTY: u32 = 0x09;
+pub const SYS_SEEK: u32 = 0x0a;
+pub const SYS_FLEN: u32 = 0x0c;
+pub const SYS_CLOCK: u32 = 0x10;
+pub const SYS_TIME: u32 = 0x11;
+pub const SYS_ERRNO: u32 = 0x13;
+pub const SYS_EXIT: u32 = 0x18;
+pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// `SYS_EXIT` reason for a normal application exit
+pub const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x2_0026;
+
+/// Returned in `a0` when an operation fails
+const FAILURE: u32 = u32::MAX;
+
+/// The special file name for the console
+const CONSOLE: &str = ":tt";
+
+// errno values reported by SYS_ERRNO
+const ENOENT: u32 = 2;
+const EBADF: u32 = 9;
+const EACCES: u32 = 13;
+
+#[derive(Debug, Clone)]
+pub struct Semihosting {
+    /// Bytes written to the console, waiting for the host to display them
+    pub console: Vec<u8>,
+    /// Bytes waiting to be read from the console
+    pub input: VecDeque<u8>,
+    /// Directory that file operations are confined to; `None` disables them
+    pub root: Option<PathBuf>,
+    /// Set when the guest exits via `SYS_EXIT`; the CPU moves it to
+    /// [CPU::exit_code](crate::rv32_i::CPU::exit_code), which ends a run
+    pub exit_code: Option<u32>,
+    files: Vec<Option<OpenFile>>,
+    errno: u32,
+    #[cfg(feature = "host")]
+    started: Instant,
+}
+
+/// Files are reopened for each operation, which keeps [Semihosting] cloneable
+/// along with the rest of the machine.
+#[derive(Debug, Clone)]
+enum OpenFile {
+    Console,
+    Host {
+        path: PathBuf,
+        position: u64,
+        writable: bool,
+    },
+}
+
+impl Default for Semihosting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Semihosting {
+    pub fn new() -> Self {
+        Self {
+            console: vec![],
+            input: VecDeque::new(),
+            root: None,
+            exit_code: None,
+            files: vec![],
+            errno: 0,
+            #[cfg(feature = "host")]
+            started: Instant::now(),
+        }
+    }
+
+    /// Allows file operations within `root`
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: Some(root.into()),
+            ..Self::new()
+        }
+    }
+
+    /// Takes everything written to the console since the last call
+    pub fn take_console(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.console)
+    }
+
+    /// Performs semihosting operation `op` with argument `arg`, returning the
+    /// value for `a0`. Parameter blocks are read from the CPU's memory, and
+    /// what's read from a file goes back with [CPU::store_bytes], so it's in
+    /// the history like a store.
+    pub fn call(&mut self, cpu: &mut CPU, op: u32, arg: u32) -> u32 {
+        let mut guest = Guest { cpu };
+        match self.dispatch(&mut guest, op, arg) {
+            Some(value) => value,
+            None => FAILURE,
+        }
+    }
+
+    fn dispatch(&mut self, guest: &mut Guest, op: u32, arg: u32) -> Option<u32> {
+        match op {
+            SYS_OPEN => {
+                let name_ptr = guest.word(arg)?;
+                let mode = guest.word(arg.wrapping_add(4))?;
+                let name_len = guest.word(arg.wrapping_add(8))?;
+                let name = guest.bytes(name_ptr, name_len)?;
+                let name = String::from_utf8_lossy(name).to_string();
+                self.open(&name, mode)
+            }
+            SYS_CLOSE => {
+                let handle = guest.word(arg)?;
+                let file = self.files.get_mut(handle as usize)?;
+                file.take().map(|_| 0).or_else(|| self.fail(EBADF))
+            }
+            SYS_WRITEC => {
+                let c = guest.bytes(arg, 1)?[0];
+                self.console.push(c);
+                Some(0)
+            }
+            SYS_WRITE0 => {
+                let string = guest.c_string(arg)?;
+                self.console.extend_from_slice(string);
+                Some(0)
+            }
+            SYS_WRITE => {
+                let handle = guest.word(arg)?;
+                let buf = guest.word(arg.wrapping_add(4))?;
+                let len = guest.word(arg.wrapping_add(8))?;
+                let data = guest.bytes(buf, len)?.to_vec();
+                self.write(handle, &data)
+            }
+            SYS_READ => {
+                let handle = guest.word(arg)?;
+                let buf = guest.word(arg.wrapping_add(4))?;
+                let len = guest.word(arg.wrapping_add(8))?;
+                let data = self.read(handle, len)?;
+                guest.cpu.store_bytes(buf, &data).ok()?;
+                // the result is the number of bytes *not* read
+                Some(len - data.len() as u32)
+            }
+            SYS_READC => self.input.pop_front().map(|c| c as u32),
+            SYS_ISTTY => {
+                let handle = guest.word(arg)?;
+                match self.files.get(handle as usize)? {
+                    Some(OpenFile::Console) => Some(1),
+                    Some(_) => Some(0),
+                    None => self.fail(EBADF),
+                }
+            }
+            SYS_SEEK => {
+                let handle = guest.word(arg)?;
+                let target = guest.word(arg.wrapping_add(4))?;
+                match self.files.get_mut(handle as usize)? {
+                    Some(OpenFile::Host { position, .. }) => {
+                        *position = target as u64;
+                        Some(0)
+                    }
+                    _ => self.fail(EBADF),
+                }
+            }
+            SYS_FLEN => {
+                let handle = guest.word(arg)?;
+                match self.files.get(handle as usize)? {
+                    Some(OpenFile::Host { path, .. }) => {
+                        let len = std::fs::metadata(path).ok()?.len();
+                        Some(len as u32)
+                    }
+                    _ => self.fail(EBADF),
+                }
+            }
+            #[cfg(feature = "host")]
+            SYS_CLOCK => {
+                let centiseconds = self.started.elapsed().as_millis() / 10;
+                Some(centiseconds as u32)
+            }
+            #[cfg(feature = "host")]
+            SYS_TIME => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+                Some(now.as_secs() as u32)
+            }
+            SYS_ERRNO => Some(self.errno),
+            SYS_EXIT => {
+                // on 32-bit targets the argument is the reason itself
+                let code = if arg == ADP_STOPPED_APPLICATION_EXIT {
+                    0
+                } else {
+                    1
+                };
+                self.exit_code = Some(code);
+                Some(0)
+            }
+            SYS_EXIT_EXTENDED => {
+                let reason = guest.word(arg)?;
+                let subcode = guest.word(arg.wrapping_add(4))?;
+                let code = if reason == ADP_STOPPED_APPLICATION_EXIT {
+                    subcode
+                } else {
+                    1
+                };
+                self.exit_code = Some(code);
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
+    fn fail(&mut self, errno: u32) -> Option<u32> {
+        self.errno = errno;
+        None
+    }
+
+    fn open(&mut self, name: &str, mode: u32) -> Option<u32> {
+        let file = if name == CONSOLE {
+            OpenFile::Console
+        } else if !cfg!(feature = "host") {
+            return self.fail(EACCES);
+        } else {
+            let Some(path) = self.resolve(name) else {
+                return self.fail(EACCES);
+            };
+
+            // modes come in groups of four: r, r+, w, w+, a, a+ (and binary variants)
+            let writable = mode >= 4;
+            let truncate = (8..16).contains(&mode);
+            let append = mode >= 16;
+
+            if truncate && std::fs::write(&path, []).is_err() {
+                return self.fail(EACCES);
+            }
+
+            let position = match std::fs::metadata(&path) {
+                Ok(meta) if append => meta.len(),
+                Ok(_) => 0,
+                Err(_) if append => {
+                    std::fs::write(&path, []).ok()?;
+                    0
+                }
+                Err(_) => return self.fail(ENOENT),
+            };
+
+            OpenFile::Host {
+                path,
+                position,
+                writable,
+            }
+        };
+
+        self.files.push(Some(file));
+        Some(self.files.len() as u32 - 1)
+    }
+
+    /// Resolves a guest file name inside the root, refusing anything that
+    /// could escape it. Symbolic links are followed before checking, so a
+    /// link in the root can't lead out of it; a file that doesn't exist yet
+    /// is checked by its directory.
+    fn resolve(&self, name: &str) -> Option<PathBuf> {
+        let root = self.root.as_ref()?.canonicalize().ok()?;
+        let relative = Path::new(name);
+
+        let safe = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !safe {
+            return None;
+        }
+
+        let path = root.join(relative);
+        let real = match path.canonicalize() {
+            Ok(real) => real,
+            // a link to something that doesn't exist (yet)
+            Err(_) if path.symlink_metadata().is_ok() => return None,
+            Err(_) => path.parent()?.canonicalize().ok()?.join(path.file_name()?),
+        };
+        real.starts_with(&root).then_some(real)
+    }
+
+    fn write(&mut self, handle: u32, data: &[u8]) -> Option<u32> {
+        use std::io::{Seek, SeekFrom, Write};
+
+        match self.files.get_mut(handle as usize) {
+            Some(Some(OpenFile::Console)) => {
+                self.console.extend_from_slice(data);
+                Some(0)
+            }
+            Some(Some(OpenFile::Host {
+                path,
+                position,
+                writable: true,
+            })) => {
+                let mut file = std::fs::OpenOptions::new().write(true).open(path).ok()?;
+                file.seek(SeekFrom::Start(*position)).ok()?;
+                file.write_all(data).ok()?;
+                *position += data.len() as u64;
+                Some(0)
+            }
+            _ => self.fail(EBADF),
+        }
+    }
+
+    fn read(&mut self, handle: u32, len: u32) -> Option<Vec<u8>> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        match self.files.get_mut(handle as usize) {
+            Some(Some(OpenFile::Console)) => {
+                let count = (len as usize).min(self.input.len());
+                Some(self.input.drain(..count).collect())
+            }
+            Some(Some(OpenFile::Host { path, position, .. })) => {
+                let mut file = std::fs::File::open(path).ok()?;
+                file.seek(SeekFrom::Start(*position)).ok()?;
+                let mut data = vec![];
+                file.take(len as u64).read_to_end(&mut data).ok()?;
+                *position += data.len() as u64;
+                Some(data)
+            }
+            _ => {
+                self.errno = EBADF;
+                None
+            }
+        }
+    }
+}
+
+/// Guest memory, addressed the way the guest sees it
+struct Guest<'a> {
+    cpu: &'a mut CPU,
+}
+
+impl Guest<'_> {
+    fn bytes(&self, address: u32, len: u32) -> Option<&[u8]> {
+        let range = self.cpu.address_map.translate(address, len as usize)?;
+        self.cpu.memory.get(range)
+    }
+
+    fn word(&self, address: u32) -> Option<u32> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.bytes(address, 4)?);
+        Some(u32::from_le_bytes(buf))
+    }
+
+    /// The bytes up to (but not including) a NUL terminator
+    fn c_string(&self, address: u32) -> Option<&[u8]> {
+        let memory = &self.cpu.memory;
+        let range = self.cpu.address_map.available(address)?;
+        let rest = memory.get(range.start..range.end.min(memory.len()))?;
+        let len = rest.iter().position(|b| *b == 0)?;
+        Some(&rest[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::pages::PAGE;
+
+    fn put_words(memory: &mut [u8], address: usize, words: &[u32]) {
+        for (i, w) in words.iter().enumerate() {
+            let start = address + i * 4;
+            memory[start..start + 4].copy_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn console_output() {
+        let mut sh = Semihosting::new();
+        let mut cpu = CPU::new(256);
+
+        cpu.memory[16] = b'!';
+        assert_eq!(sh.call(&mut cpu, SYS_WRITEC, 16), 0);
+
+        cpu.memory[32..38].copy_from_slice(b"hello\0");
+        assert_eq!(sh.call(&mut cpu, SYS_WRITE0, 32), 0);
+
+        assert_eq!(sh.take_console(), b"!hello");
+    }
+
+    #[test]
+    fn console_file() {
+        let mut sh = Semihosting::new();
+        let mut cpu = CPU::new(256);
+
+        // open(":tt", "w")
+        cpu.memory[64..67].copy_from_slice(b":tt");
+        put_words(&mut cpu.memory, 0, &[64, 4, 3]);
+        let handle = sh.call(&mut cpu, SYS_OPEN, 0);
+        assert_eq!(handle, 0);
+        put_words(&mut cpu.memory, 16, &[handle]);
+        assert_eq!(sh.call(&mut cpu, SYS_ISTTY, 16), 1);
+
+        // write(handle, "hi", 2)
+        cpu.memory[80..82].copy_from_slice(b"hi");
+        put_words(&mut cpu.memory, 16, &[handle, 80, 2]);
+        assert_eq!(sh.call(&mut cpu, SYS_WRITE, 16), 0);
+        assert_eq!(sh.take_console(), b"hi");
+
+        // read(handle, buf, 4) with only 3 bytes waiting
+        sh.input.extend(b"abc");
+        put_words(&mut cpu.memory, 16, &[handle, 96, 4]);
+        assert_eq!(sh.call(&mut cpu, SYS_READ, 16), 1);
+        assert_eq!(&cpu.memory[96..99], b"abc");
+    }
+
+    #[test]
+    fn reads_are_stores() {
+        let mut sh = Semihosting::new();
+        let mut cpu = CPU::new(4 * PAGE);
+        cpu.memory[64..67].copy_from_slice(b":tt");
+        put_words(&mut cpu.memory, 0, &[64, 0, 3]);
+        let handle = sh.call(&mut cpu, SYS_OPEN, 0);
+        put_words(&mut cpu.memory, 16, &[handle, PAGE as u32 + 8, 2]);
+        let before = cpu.memory.pages();
+
+        // read(handle, buf, 2) lands in the history, and only its page changes
+        sh.input.extend(b"ok");
+        assert_eq!(sh.call(&mut cpu, SYS_READ, 16), 0);
+        let write = &cpu.last_writes()[0];
+        assert_eq!(
+            (write.address, &write.old[..], &write.new[..]),
+            (PAGE as u32 + 8, &[0, 0][..], &b"ok"[..])
+        );
+        assert_eq!(cpu.memory.pages().shared_with(&before), 3);
+
+        // console output doesn't write at all
+        let before = cpu.memory.pages();
+        assert_eq!(sh.call(&mut cpu, SYS_WRITEC, PAGE as u32 + 8), 0);
+        assert_eq!(cpu.memory.pages().shared_with(&before), 4);
+    }
+
+    #[test]
+    fn files_need_a_root() {
+        let mut sh = Semihosting::new();
+        let mut cpu = CPU::new(256);
+
+        cpu.memory[64..72].copy_from_slice(b"data.txt");
+        put_words(&mut cpu.memory, 0, &[64, 0, 8]);
+        assert_eq!(sh.call(&mut cpu, SYS_OPEN, 0), FAILURE);
+        assert_eq!(sh.call(&mut cpu, SYS_ERRNO, 0), EACCES);
+    }
+
+    #[test]
+    #[cfg(feature = "host")]
+    fn host_files() {
+        let root = std::env::temp_dir().join(format!("brubeck-semihosting-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let mut sh = Semihosting::with_root(&root);
+        let mut cpu = CPU::new(256);
+
+        // open("out.txt", "w"), write "data", close
+        cpu.memory[64..71].copy_from_slice(b"out.txt");
+        put_words(&mut cpu.memory, 0, &[64, 8, 7]);
+        let handle = sh.call(&mut cpu, SYS_OPEN, 0);
+        assert_ne!(handle, FAILURE);
+
+        cpu.memory[80..84].copy_from_slice(b"data");
+        put_words(&mut cpu.memory, 16, &[handle, 80, 4]);
+        assert_eq!(sh.call(&mut cpu, SYS_WRITE, 16), 0);
+        put_words(&mut cpu.memory, 16, &[handle]);
+        assert_eq!(sh.call(&mut cpu, SYS_CLOSE, 16), 0);
+
+        assert_eq!(std::fs::read(root.join("out.txt")).unwrap(), b"data");
+
+        // escaping the root isn't allowed
+        cpu.memory[64..74].copy_from_slice(b"../escaped");
+        put_words(&mut cpu.memory, 0, &[64, 8, 10]);
+        assert_eq!(sh.call(&mut cpu, SYS_OPEN, 0), FAILURE);
+
+        // not even through a link, to a file or a directory
+        #[cfg(unix)]
+        {
+            let outside = root.with_extension("outside");
+            std::fs::create_dir_all(&outside).unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+            std::os::unix::fs::symlink(outside.join("new"), root.join("dangling")).unwrap();
+
+            cpu.memory[64..72].copy_from_slice(b"link/out");
+            put_words(&mut cpu.memory, 0, &[64, 8, 8]);
+            assert_eq!(sh.call(&mut cpu, SYS_OPEN, 0), FAILURE);
+            cpu.memory[64..72].copy_from_slice(b"dangling");
+            assert_eq!(sh.call(&mut cpu, SYS_OPEN, 0), FAILURE);
+            assert!(!outside.join("out").exists() && !outside.join("new").exists());
+            std::fs::remove_dir_all(&outside).unwrap();
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn exit() {
+        let mut sh = Semihosting::new();
+        let mut cpu = CPU::new(16);
+
+        sh.call(&mut cpu, SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT);
+        assert_eq!(sh.exit_code, Some(0));
+
+        put_words(&mut cpu.memory, 0, &[ADP_STOPPED_APPLICATION_EXIT, 42]);
+        sh.call(&mut cpu, SYS_EXIT_EXTENDED, 0);
+        assert_eq!(sh.exit_code, Some(42));
+    }
+}
@@ -0,0 +1,462 @@
+//! Copies of the whole machine state that can be saved and restored later.
+//!
+//! A [Snapshot] holds the registers, CSRs, privilege level, counters,
+//! memory, and exit code of a [CPU]; device state isn't included. Snapshots
+//! can be written out as bytes (eg: to a file) and read back; the format
+//! starts with a magic number and a version so older files can be
+//! recognized.
+//!
+//! Taking a snapshot copies only the pages of memory written since the last
+//! copy was taken (see [pages]), so it's cheap however much memory there is.
+//!
+//! Most of memory usually still holds what it was filled with (see
+//! [FillPattern]), so it's written in pages of [PAGE] bytes, leaving out the
+//! pages that hold nothing else. A snapshot of a small program in the
+//! default 1 MiB of memory takes a few kilobytes.
+//!
+//! ```
+//! use brubeck::rv32_i::CPU;
+//! use brubeck::snapshot::Snapshot;
+//!
+//! let mut cpu = CPU::new(64);
+//! cpu.x1 = 5;
+//! let bytes = Snapshot::capture(&cpu).to_bytes();
+//!
+//! cpu.x1 = 0;
+//! Snapshot::from_bytes(&bytes).unwrap().restore(&mut cpu).unwrap();
+//! assert_eq!(cpu.x1, 5);
+//! ```
+
+use std::fmt::Display;
+
+use crate::history::{self, Registers};
+use crate::rv32_i::{pages, Counters, FillPattern, Pages, Privilege, CPU};
+
+/// Identifies snapshot data
+const MAGIC: &[u8; 4] = b"BRBK";
+
+/// The current version of the byte format. Version 1 (all of memory, and no
+/// counters), version 2 (no privilege level, so machine mode), version 3
+/// (32-bit registers), and version 4 (memory filled with zeros) can still be
+/// read.
+pub const VERSION: u16 = 5;
+
+/// Bytes in each page of memory as written by [Snapshot::to_bytes]; the
+/// same as the pages memory is copied in
+pub const PAGE: usize = pages::PAGE;
+
+/// The most memory a snapshot read back can have. The size is checked before
+/// any memory is allocated, so a corrupt file can't ask for 4 GiB.
+pub const MAX_MEMORY: usize = 1 << 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: Registers,
+    /// Every CSR with a nonzero value, by address
+    pub csrs: Vec<(u16, u32)>,
+    pub privilege: Privilege,
+    pub counters: Counters,
+    /// Shares pages with the CPU's other copies of its memory where they
+    /// haven't changed; see [pages]
+    pub memory: Pages,
+    /// What memory was filled with; the pages that still hold only that are
+    /// left out of the bytes
+    pub fill: FillPattern,
+    pub exit_code: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// The data doesn't start with the snapshot magic number
+    NotASnapshot,
+    /// Written by a different version of brubeck
+    UnsupportedVersion(u16),
+    /// The data ends early
+    Truncated,
+    /// The data has a value that can't be right, eg: a page past the end of
+    /// memory
+    Corrupt,
+    /// Memory is a different size than the CPU being restored
+    MemorySize { expected: usize, found: usize },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotASnapshot => write!(f, "not a brubeck snapshot"),
+            Self::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "snapshot version {v} isn't supported (expected {VERSION})"
+                )
+            }
+            Self::Truncated => write!(f, "snapshot is truncated"),
+            Self::Corrupt => write!(f, "snapshot is corrupt"),
+            Self::MemorySize { expected, found } => write!(
+                f,
+                "snapshot has {found} bytes of memory, but the CPU has {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    /// Copies the state of a CPU
+    pub fn capture(cpu: &CPU) -> Self {
+        let csrs = cpu
+            .csrs
+            .iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(def, value)| (def.address as u16, value))
+            .collect();
+
+        Self {
+            registers: history::registers(cpu),
+            csrs,
+            privilege: cpu.privilege,
+            counters: cpu.counters,
+            memory: cpu.memory.pages(),
+            fill: cpu.config.fill,
+            exit_code: cpu.exit_code,
+        }
+    }
+
+    /// Puts the CPU back in this state. Its memory must be the same size.
+    pub fn restore(&self, cpu: &mut CPU) -> Result<(), SnapshotError> {
+        if cpu.memory.len() != self.memory.len() {
+            return Err(SnapshotError::MemorySize {
+                expected: cpu.memory.len(),
+                found: self.memory.len(),
+            });
+        }
+
+        history::set_registers(cpu, &self.registers);
+        // CSRs the CPU doesn't have are left out
+        cpu.csrs.clear();
+        for (address, value) in &self.csrs {
+            cpu.csrs.set(u32::from(*address), *value);
+        }
+        cpu.memory.restore(&self.memory);
+        cpu.privilege = self.privilege;
+        cpu.counters = self.counters;
+        cpu.exit_code = self.exit_code;
+
+        Ok(())
+    }
+
+    /// Serializes the snapshot; all numbers are little endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes);
+        bytes
+    }
+
+    /// Appends the serialized snapshot to `bytes`
+    pub(crate) fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_le_bytes());
+
+        for value in self.registers {
+            bytes.extend(value.to_le_bytes());
+        }
+
+        bytes.push(self.exit_code.is_some() as u8);
+        bytes.extend(self.exit_code.unwrap_or(0).to_le_bytes());
+
+        bytes.extend((self.csrs.len() as u32).to_le_bytes());
+        for (address, value) in &self.csrs {
+            bytes.extend(address.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+        }
+
+        bytes.extend(self.counters.cycles.to_le_bytes());
+        bytes.extend(self.counters.instret.to_le_bytes());
+        bytes.push(self.privilege.bits() as u8);
+
+        match self.fill {
+            FillPattern::Byte(byte) => bytes.extend([0, byte]),
+            FillPattern::Random { seed } => {
+                bytes.push(1);
+                bytes.extend(seed.to_le_bytes());
+            }
+        }
+
+        // only the pages with something in them
+        let mut filled = vec![0; PAGE];
+        let pages: Vec<_> = self
+            .memory
+            .iter()
+            .enumerate()
+            .filter(|(index, page)| {
+                let filled = &mut filled[..page.len()];
+                self.fill.fill(filled, index * PAGE);
+                **page != *filled
+            })
+            .collect();
+        bytes.extend((self.memory.len() as u32).to_le_bytes());
+        bytes.extend((pages.len() as u32).to_le_bytes());
+        for (index, page) in pages {
+            bytes.extend((index as u32).to_le_bytes());
+            bytes.extend(page);
+        }
+    }
+
+    /// Reads a snapshot written by [to_bytes](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Self::read(&mut Reader { bytes })
+    }
+
+    /// Reads a snapshot from the front of `reader`
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Self::read_versioned(reader).map(|(snapshot, _)| snapshot)
+    }
+
+    /// Reads a snapshot from the front of `reader`, with the version it was
+    /// written in
+    pub(crate) fn read_versioned(reader: &mut Reader) -> Result<(Self, u16), SnapshotError> {
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = reader.u16()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut registers = [0; 33];
+        for register in registers.iter_mut() {
+            *register = match version {
+                ..=3 => reader.u32()?.into(),
+                _ => reader.u64()?,
+            };
+        }
+
+        let exited = reader.take(1)?[0] != 0;
+        let exit_code = reader.u32()?;
+
+        let count = reader.u32()?;
+        let csrs = (0..count)
+            .map(|_| Ok((reader.u16()?, reader.u32()?)))
+            .collect::<Result<_, SnapshotError>>()?;
+
+        if version == 1 {
+            let size = reader.u32()? as usize;
+            let snapshot = Self {
+                registers,
+                csrs,
+                privilege: Privilege::Machine,
+                counters: Counters::default(),
+                memory: reader.take(size)?.to_vec().into(),
+                fill: FillPattern::default(),
+                exit_code: exited.then_some(exit_code),
+            };
+            return Ok((snapshot, version));
+        }
+
+        let counters = Counters {
+            cycles: reader.u64()?,
+            instret: reader.u64()?,
+        };
+        let privilege = match version {
+            2 => Privilege::Machine,
+            _ => Privilege::from_bits(reader.u8()?.into()).ok_or(SnapshotError::Corrupt)?,
+        };
+        let fill = match version {
+            ..=4 => FillPattern::default(),
+            _ => match reader.u8()? {
+                0 => FillPattern::Byte(reader.u8()?),
+                1 => FillPattern::Random {
+                    seed: reader.u64()?,
+                },
+                _ => return Err(SnapshotError::Corrupt),
+            },
+        };
+
+        let size = reader.u32()? as usize;
+        if size > MAX_MEMORY {
+            return Err(SnapshotError::Corrupt);
+        }
+        let mut memory = vec![0; size];
+        fill.fill(&mut memory, 0);
+        for _ in 0..reader.u32()? {
+            let start = reader.u32()? as usize * PAGE;
+            let page = memory
+                .get_mut(start..(start + PAGE).min(size))
+                .ok_or(SnapshotError::Corrupt)?;
+            page.copy_from_slice(reader.take(page.len())?);
+        }
+
+        let snapshot = Self {
+            registers,
+            csrs,
+            privilege,
+            counters,
+            memory: memory.into(),
+            fill,
+            exit_code: exited.then_some(exit_code),
+        };
+        Ok((snapshot, version))
+    }
+}
+
+/// Reads values from the front of a byte slice
+pub(crate) struct Reader<'a> {
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{csr, CpuConfig};
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = CPU::new(16);
+        cpu.x5 = 0xdead_beef;
+        cpu.pc = 8;
+        cpu.memory[3] = 7;
+        cpu.exit_code = Some(2);
+        cpu.write_csr(csr::MSCRATCH, 9).unwrap();
+        cpu.counters.instret = 3;
+        cpu.privilege = Privilege::User;
+
+        let snapshot = Snapshot::capture(&cpu);
+        let bytes = snapshot.to_bytes();
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        let mut restored = CPU::new(16);
+        Snapshot::from_bytes(&bytes)
+            .unwrap()
+            .restore(&mut restored)
+            .unwrap();
+        assert_eq!(restored.x5, 0xdead_beef);
+        assert_eq!(restored.pc, 8);
+        assert_eq!(restored.memory[3], 7);
+        assert_eq!(restored.exit_code, Some(2));
+        assert_eq!(restored.read_csr(csr::MSCRATCH).unwrap(), 9);
+        assert_eq!(restored.counters.instret, 3);
+        assert_eq!(restored.privilege, Privilege::User);
+    }
+
+    #[test]
+    fn sparse_memory() {
+        let mut cpu = CPU::new(1024 * 1024);
+        cpu.memory[5 * PAGE + 1] = 1;
+        cpu.memory[1024 * 1024 - 1] = 2;
+
+        let snapshot = Snapshot::capture(&cpu);
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.len() < 3 * PAGE);
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        // a page that ends past the end of memory
+        let mut bytes = Snapshot::capture(&CPU::new(PAGE + 16)).to_bytes();
+        let pages = bytes.len() - 4;
+        bytes.truncate(pages);
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(2u32.to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&bytes), Err(SnapshotError::Corrupt));
+    }
+
+    #[test]
+    fn filled_memory() {
+        for fill in [FillPattern::Byte(0xaa), FillPattern::Random { seed: 3 }] {
+            let mut cpu = CPU::with_config(CpuConfig {
+                memory_size: 4 * PAGE + 16,
+                fill,
+                ..CpuConfig::default()
+            });
+            cpu.memory[PAGE] = 0;
+
+            let snapshot = Snapshot::capture(&cpu);
+            let bytes = snapshot.to_bytes();
+            assert!(bytes.len() < 2 * PAGE);
+            let read = Snapshot::from_bytes(&bytes).unwrap();
+            assert_eq!(read.memory.to_vec(), cpu.memory.to_vec());
+            assert_eq!(read, snapshot);
+        }
+    }
+
+    #[test]
+    fn version_1() {
+        let mut bytes = b"BRBK".to_vec();
+        bytes.extend(1u16.to_le_bytes());
+        bytes.extend([0; 33 * 4]);
+        bytes.extend([1, 7, 0, 0, 0]);
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(4u32.to_le_bytes());
+        bytes.extend([1, 2, 3, 4]);
+
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(snapshot.memory.to_vec(), [1, 2, 3, 4]);
+        assert_eq!(snapshot.exit_code, Some(7));
+        assert_eq!(snapshot.counters, Counters::default());
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = Snapshot::capture(&CPU::new(16)).to_bytes();
+
+        assert_eq!(
+            Snapshot::from_bytes(b"nope"),
+            Err(SnapshotError::NotASnapshot)
+        );
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+
+        // the memory size, followed by no pages
+        let mut huge = bytes.clone();
+        let end = bytes.len();
+        huge[end - 8..end - 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&huge), Err(SnapshotError::Corrupt));
+
+        let mut newer = bytes.clone();
+        newer[4] = 99;
+        assert_eq!(
+            Snapshot::from_bytes(&newer),
+            Err(SnapshotError::UnsupportedVersion(99))
+        );
+
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            snapshot.restore(&mut CPU::new(32)),
+            Err(SnapshotError::MemorySize {
+                expected: 32,
+                found: 16
+            })
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Execution statistics: what ran, how often, and where.
+//!
+//! The [Interpreter](crate::Interpreter) tallies every instruction it
+//! executes into an [ExecutionStats]: counts by mnemonic, how often each
+//! conditional branch was taken, how many times each address ran, and how
+//! many loads and stores were made. The hottest addresses are usually the
+//! inside of a loop, which is where moving work out of the loop pays off.
+//!
+//! The stats are a tally of the work done, so undoing an instruction (or
+//! rolling back a transaction) doesn't take it out of them; the
+//! [counters](crate::rv32_i::counters) are what follow the history.
+//!
+//! ```
+//! use brubeck::Interpreter;
+//!
+//! let mut i = Interpreter::new();
+//! i.load_program(
+//!     "ADDI a0, zero, 3\n\
+//!      ADDI a0, a0, 4095\n\
+//!      BNE a0, zero, 4094",
+//! )
+//! .unwrap();
+//! i.run(100);
+//!
+//! let stats = i.stats();
+//! assert_eq!(stats.count("ADDI"), 4);
+//! assert_eq!(stats.hottest(1), vec![(4, 3)]);
+//!
+//! let bne = stats.branches[&8];
+//! assert_eq!((bne.taken, bne.not_taken), (2, 1));
+//! ```
+
+use std::collections::HashMap;
+
+use crate::rv32_i::{Instruction, Operands};
+
+/// How often a conditional branch went each way
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCount {
+    pub fn total(&self) -> u64 {
+        self.taken + self.not_taken
+    }
+
+    /// The fraction of the time the branch was taken, from 0 to 1
+    pub fn taken_ratio(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.taken as f64 / total as f64,
+        }
+    }
+}
+
+/// Tallies of executed instructions; see the [module](self) docs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecutionStats {
+    /// Instructions executed, by mnemonic
+    pub instructions: HashMap<&'static str, u64>,
+    /// Each conditional branch, by address
+    pub branches: HashMap<u32, BranchCount>,
+    /// Instructions executed at each address
+    pub pcs: HashMap<u32, u64>,
+    /// Load instructions executed
+    pub loads: u64,
+    /// Store instructions executed
+    pub stores: u64,
+}
+
+impl ExecutionStats {
+    /// Counts an instruction executed at `pc`, which left the PC at `next`.
+    /// A branch that went on to the next instruction (4 bytes on, or 2 for
+    /// a compressed one) wasn't taken.
+    pub fn record(&mut self, pc: u32, instruction: Instruction, next: u32) {
+        *self.instructions.entry(instruction.mnemonic()).or_default() += 1;
+        *self.pcs.entry(pc).or_default() += 1;
+
+        match instruction {
+            Instruction::LB(_)
+            | Instruction::LBU(_)
+            | Instruction::LH(_)
+            | Instruction::LHU(_)
+            | Instruction::LW(_)
+            | Instruction::LWU(_)
+            | Instruction::LD(_) => self.loads += 1,
+            Instruction::SB(_) | Instruction::SH(_) | Instruction::SW(_) | Instruction::SD(_) => {
+                self.stores += 1
+            }
+            Instruction::LR_W(_) => self.loads += 1,
+            Instruction::SC_W(_) => self.stores += 1,
+            // an AMO is both
+            _ if instruction.is_atomic() => {
+                self.loads += 1;
+                self.stores += 1;
+            }
+            _ => (),
+        }
+
+        if let Operands::BType(_) = instruction.operands() {
+            let branch = self.branches.entry(pc).or_default();
+            let length = next.wrapping_sub(pc);
+            if length == Instruction::LENGTH || length == Instruction::COMPRESSED_LENGTH {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+            }
+        }
+    }
+
+    /// Instructions executed in all
+    pub fn total(&self) -> u64 {
+        self.instructions.values().sum()
+    }
+
+    /// Times an instruction was executed, by mnemonic (in upper case)
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.instructions.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    /// Mnemonics and their counts, most executed first (then
+    /// alphabetically)
+    pub fn by_instruction(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self.instructions.iter().map(|(m, c)| (*m, *c)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+
+    /// The `n` addresses executed most often, with their counts, hottest
+    /// first (then by address)
+    pub fn hottest(&self, n: usize) -> Vec<(u32, u64)> {
+        let mut pcs: Vec<_> = self.pcs.iter().map(|(pc, c)| (*pc, *c)).collect();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pcs.truncate(n);
+        pcs
+    }
+
+    /// Every conditional branch added together
+    pub fn all_branches(&self) -> BranchCount {
+        self.branches
+            .values()
+            .fold(BranchCount::default(), |sum, b| BranchCount {
+                taken: sum.taken + b.taken,
+                not_taken: sum.not_taken + b.not_taken,
+            })
+    }
+
+    /// Forgets everything counted so far
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{BType, IType, SType};
+
+    #[test]
+    fn tallies() {
+        let mut stats = ExecutionStats::default();
+        let beq = Instruction::BEQ(BType::default());
+
+        stats.record(0, Instruction::ADDI(IType::default()), 4);
+        stats.record(4, Instruction::LW(IType::default()), 8);
+        stats.record(8, beq, 12);
+        stats.record(12, Instruction::SW(SType::default()), 16);
+        stats.record(16, beq, 0);
+        stats.record(0, Instruction::ADDI(IType::default()), 4);
+        stats.record(4, Instruction::NOP, 8);
+        stats.record(8, beq, 0);
+
+        assert_eq!(stats.total(), 8);
+        assert_eq!(
+            stats.by_instruction(),
+            vec![("BEQ", 3), ("ADDI", 2), ("LW", 1), ("NOP", 1), ("SW", 1)]
+        );
+        assert_eq!(stats.hottest(3), vec![(0, 2), (4, 2), (8, 2)]);
+        assert_eq!((stats.loads, stats.stores), (1, 1));
+
+        assert_eq!(
+            stats.branches[&8],
+            BranchCount {
+                taken: 1,
+                not_taken: 1
+            }
+        );
+        let all = stats.all_branches();
+        assert_eq!((all.taken, all.not_taken), (2, 1));
+        assert!((all.taken_ratio() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(BranchCount::default().taken_ratio(), 0.0);
+
+        stats.clear();
+        assert_eq!(stats.total(), 0);
+    }
+}
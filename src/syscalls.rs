@@ -0,0 +1,227 @@
+//! System calls made with `ECALL`, for programs built against newlib or the
+//! Linux ABI.
+//!
+//! The call number goes in `a7`, the arguments in `a0` to `a5`, and the result
+//! comes back in `a0`. When the CPU's [EcallPolicy](crate::rv32_i::EcallPolicy)
+//! is `HostHandle`, the call goes to the CPU's [SyscallHandler]; the default,
+//! [Syscalls], understands just enough for "hello world" style programs:
+//!
+//! | `a7` | call             | arguments                          |
+//! |------|------------------|------------------------------------|
+//! | 63   | `read`           | fd (0), buffer, length             |
+//! | 64   | `write`          | fd (1 or 2), buffer, length        |
+//! | 93   | `exit`           | exit code                          |
+//! | 94   | `exit_group`     | exit code                          |
+//! | 169  | `gettimeofday`   | buffer for seconds and microseconds |
+//!
+//! Like [semihosting](crate::semihosting), output is collected rather than
+//! printed, and input comes from a queue, so the host stays in control.
+//! Without the `host` feature there's no clock to read, so `gettimeofday`
+//! fails with `-ENOSYS`.
+//!
+//! Memory a call writes (`read`'s buffer, `gettimeofday`'s) goes through
+//! [CPU::store_bytes], so it's in the history like a store and undo puts it
+//! back. The host's side isn't undone: output stays written, and input stays
+//! read.
+//!
+//! ```
+//! use brubeck::rv32_i::CPU;
+//! use brubeck::syscalls;
+//!
+//! let mut cpu = CPU::new(64);
+//! cpu.write_memory(32, b"hi\n").unwrap();
+//!
+//! // write(1, 32, 3)
+//! cpu.x17 = syscalls::WRITE;
+//! (cpu.x10, cpu.x11, cpu.x12) = (1, 32, 3);
+//! cpu.execute(brubeck::rv32_i::Instruction::ECALL(Default::default())).unwrap();
+//!
+//! assert_eq!(cpu.x10, 3);
+//! assert_eq!(cpu.take_syscall_output(), b"hi\n");
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+#[cfg(feature = "host")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rv32_i::CPU;
+
+pub const READ: u32 = 63;
+pub const WRITE: u32 = 64;
+pub const EXIT: u32 = 93;
+pub const EXIT_GROUP: u32 = 94;
+pub const GETTIMEOFDAY: u32 = 169;
+
+/// Returned in `a0` for a bad file descriptor (`-EBADF`)
+pub const EBADF: u32 = -9i32 as u32;
+
+/// Returned in `a0` for a buffer outside memory (`-EFAULT`)
+pub const EFAULT: u32 = -14i32 as u32;
+
+/// Returned in `a0` for a call that can't be made here (`-ENOSYS`)
+pub const ENOSYS: u32 = -38i32 as u32;
+
+/// Performs system calls for the guest. Implement this to give programs a
+/// different (or bigger) operating system.
+pub trait SyscallHandler: Debug + SyscallClone {
+    /// Performs call `number` (from `a7`), with the arguments in the CPU's
+    /// registers and memory. Returns the value for `a0`, or None if the call
+    /// isn't supported. To end the program, set [CPU::exit_code].
+    fn call(&mut self, number: u32, cpu: &mut CPU) -> Option<u32>;
+
+    /// Takes the output written since the last call
+    fn take_output(&mut self) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Queues bytes for the guest to read
+    fn push_input(&mut self, _input: &[u8]) {}
+}
+
+/// Cloning support for boxed [SyscallHandler]s, so the CPU stays cloneable.
+/// This is implemented automatically for any handler that is `Clone`.
+pub trait SyscallClone {
+    fn clone_box(&self) -> Box<dyn SyscallHandler>;
+}
+
+impl<T: SyscallHandler + Clone + 'static> SyscallClone for T {
+    fn clone_box(&self) -> Box<dyn SyscallHandler> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn SyscallHandler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// The default handler; see the [module](self) docs
+#[derive(Debug, Clone, Default)]
+pub struct Syscalls {
+    /// Bytes written to stdout and stderr, waiting for the host to show them
+    pub output: Vec<u8>,
+    /// Bytes waiting to be read from stdin
+    pub input: VecDeque<u8>,
+}
+
+impl SyscallHandler for Syscalls {
+    fn call(&mut self, number: u32, cpu: &mut CPU) -> Option<u32> {
+        let (a0, a1, a2) = (cpu.x10, cpu.x11, cpu.x12);
+
+        Some(match number {
+            EXIT | EXIT_GROUP => {
+                cpu.exit_code = Some(a0);
+                a0
+            }
+            WRITE if matches!(a0, 1 | 2) => {
+                let bytes: Option<Vec<u8>> = (0..a2)
+                    .map(|i| cpu.read_mem_u8(a1.wrapping_add(i)).ok())
+                    .collect();
+                match bytes {
+                    Some(bytes) => {
+                        self.output.extend_from_slice(&bytes);
+                        a2
+                    }
+                    None => EFAULT,
+                }
+            }
+            READ if a0 == 0 => {
+                // only what's already queued; an empty queue reads as end of file
+                let count = (a2 as usize).min(self.input.len());
+                let bytes: Vec<u8> = self.input.iter().take(count).copied().collect();
+                match cpu.store_bytes(a1, &bytes) {
+                    Ok(()) => {
+                        self.input.drain(..count);
+                        count as u32
+                    }
+                    Err(_) => EFAULT,
+                }
+            }
+            WRITE | READ => EBADF,
+            #[cfg(not(feature = "host"))]
+            GETTIMEOFDAY => ENOSYS,
+            #[cfg(feature = "host")]
+            GETTIMEOFDAY => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let mut timeval = (now.as_secs() as u32).to_le_bytes().to_vec();
+                timeval.extend_from_slice(&now.subsec_micros().to_le_bytes());
+                match cpu.store_bytes(a0, &timeval) {
+                    Ok(()) => 0,
+                    Err(_) => EFAULT,
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn push_input(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rv32_i::{Error, IType, Instruction};
+
+    fn ecall(cpu: &mut CPU, number: u32, args: [u32; 3]) -> Result<(), Error> {
+        cpu.x17 = number;
+        (cpu.x10, cpu.x11, cpu.x12) = (args[0], args[1], args[2]);
+        cpu.execute(Instruction::ECALL(IType::default()))
+    }
+
+    #[test]
+    fn default_calls() {
+        let mut cpu = CPU::new(64);
+        cpu.write_memory(16, b"hello").unwrap();
+
+        ecall(&mut cpu, WRITE, [2, 16, 5]).unwrap();
+        assert_eq!(cpu.x10, 5);
+        ecall(&mut cpu, WRITE, [1, 62, 4]).unwrap();
+        assert_eq!(cpu.x10, EFAULT);
+        ecall(&mut cpu, WRITE, [3, 16, 5]).unwrap();
+        assert_eq!(cpu.x10, EBADF);
+        assert_eq!(cpu.take_syscall_output(), b"hello");
+
+        // what a read stores is recorded like a store, so the history can
+        // put the memory back (but not the input)
+        cpu.push_syscall_input(b"abc");
+        ecall(&mut cpu, READ, [0, 32, 2]).unwrap();
+        assert_eq!(cpu.x10, 2);
+        assert_eq!(&cpu.memory[32..34], b"ab");
+        assert_eq!(cpu.last_writes()[0].old, [0, 0]);
+        assert_eq!(cpu.last_writes()[0].new, b"ab");
+        ecall(&mut cpu, READ, [0, 32, 8]).unwrap();
+        assert_eq!(cpu.x10, 1);
+        ecall(&mut cpu, READ, [0, 32, 8]).unwrap();
+        assert_eq!(cpu.x10, 0);
+
+        ecall(&mut cpu, GETTIMEOFDAY, [40, 0, 0]).unwrap();
+        if cfg!(feature = "host") {
+            assert_eq!(cpu.x10, 0);
+            assert!(cpu.read_mem_u32(40).unwrap() > 1_600_000_000);
+        } else {
+            assert_eq!(cpu.x10, ENOSYS);
+        }
+
+        assert!(matches!(
+            ecall(&mut cpu, 1234, [0; 3]),
+            Err(Error::NotImplemented(Instruction::ECALL(_)))
+        ));
+
+        ecall(&mut cpu, EXIT_GROUP, [3, 0, 0]).unwrap();
+        assert_eq!(cpu.exit_code, Some(3));
+
+        // without a handler, even exit isn't understood
+        cpu.syscalls = None;
+        assert!(ecall(&mut cpu, EXIT, [0; 3]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Tracing: a record of every instruction as it's executed.
+//!
+//! A [Tracer] set on the [Interpreter](crate::Interpreter) (see
+//! [set_tracer](crate::Interpreter::set_tracer)) is handed a [TraceRecord]
+//! for each instruction that completes: where it ran from, its machine word,
+//! and what it changed. [TextTracer] writes one readable line per record, and
+//! [JsonTracer] writes one JSON object per line, for other tools to read.
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! use brubeck::trace::TraceRecord;
+//! use brubeck::Interpreter;
+//!
+//! let lines = Rc::new(RefCell::new(vec![]));
+//! let log = lines.clone();
+//!
+//! let mut i = Interpreter::new();
+//! i.set_tracer(Box::new(move |record: &TraceRecord| {
+//!     log.borrow_mut().push(record.to_string())
+//! }));
+//!
+//! i.interpret("ADDI a0, zero, 5").unwrap();
+//! assert_eq!(
+//!     lines.borrow()[0],
+//!     "0x00000000  00500513  addi a0, zero, 5          a0: 0x0 -> 0x5"
+//! );
+//! ```
+
+use std::fmt::Display;
+use std::io::Write;
+
+use crate::history::{RegisterChange, StateDelta};
+use crate::json::{self, Object};
+use crate::rv32_i::{Instruction, Register};
+
+/// An executed instruction and what it changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Where it was executed from
+    pub pc: u32,
+    /// Its machine word, as it was in memory (the low 16 bits for a
+    /// compressed instruction), or as it would be encoded if it was typed in
+    pub word: u32,
+    /// 4, or 2 for a compressed instruction
+    pub length: u32,
+    pub instruction: Instruction,
+    pub delta: StateDelta,
+}
+
+impl TraceRecord {
+    /// The registers it changed, other than the PC
+    fn changed(&self) -> impl Iterator<Item = &RegisterChange> {
+        self.delta
+            .registers
+            .iter()
+            .filter(|c| c.register != Register::PC)
+    }
+}
+
+/// One line: the PC, the word, the disassembly, then the registers and
+/// memory it changed (the PC is left out; the next line shows where it went)
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let word = match self.length {
+            2 => format!("{:04x}    ", self.word),
+            _ => format!("{:08x}", self.word),
+        };
+        let mut changes: Vec<String> = self
+            .changed()
+            .map(|c| {
+                let name = c.register.abi().map_or("?", |abi| abi.name());
+                format!("{}: 0x{:x} -> 0x{:x}", name.to_lowercase(), c.old, c.new)
+            })
+            .collect();
+        changes.extend(self.delta.memory.iter().map(|w| {
+            format!(
+                "[0x{:08x}]: {} -> {}",
+                w.address,
+                json::hex(&w.old),
+                json::hex(&w.new)
+            )
+        }));
+
+        let line = format!(
+            "0x{:08x}  {word}  {:<24}  {}",
+            self.pc,
+            self.instruction.to_string(),
+            changes.join(", ")
+        );
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+/// Receives a [TraceRecord] for every executed instruction. Implemented for
+/// any `FnMut(&TraceRecord)` closure.
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord);
+}
+
+impl<F: FnMut(&TraceRecord)> Tracer for F {
+    fn trace(&mut self, record: &TraceRecord) {
+        self(record)
+    }
+}
+
+/// Writes each record as a line of text (see [TraceRecord]'s Display).
+/// Errors writing are ignored, so a trace never stops a program.
+pub struct TextTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let _ = writeln!(self.out, "{record}");
+    }
+}
+
+/// Writes each record as a JSON object on its own line ("JSON lines"), eg:
+///
+/// ```text
+/// {"pc":0,"word":5244179,"length":4,"instruction":"addi a0, zero, 5","registers":[{"register":"x10","old":0,"new":5}],"memory":[]}
+/// ```
+///
+/// The changes are as in [Object::delta]. Errors writing are ignored, as
+/// for [TextTracer].
+pub struct JsonTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let _ = writeln!(self.out, "{}", json(record));
+    }
+}
+
+/// The record as one line of JSON
+pub fn json(record: &TraceRecord) -> String {
+    Object::new()
+        .number("pc", record.pc)
+        .number("word", record.word)
+        .number("length", record.length)
+        .string("instruction", &record.instruction.to_string())
+        .delta(&record.delta)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A buffer the test can still read after the tracer is boxed up
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn text_and_json() {
+        let mut i = Interpreter::new();
+        let text = Shared::default();
+        i.set_tracer(Box::new(TextTracer::new(text.clone())));
+        i.interpret("ADDI sp, zero, 0x100; ADDI a0, zero, 5; SW sp, a0, 0")
+            .unwrap();
+        assert_eq!(
+            text.text(),
+            "0x00000000  10000113  addi sp, zero, 256        sp: 0x0 -> 0x100\n\
+             0x00000004  00500513  addi a0, zero, 5          a0: 0x0 -> 0x5\n\
+             0x00000008  00a12023  sw sp, a0, 0              [0x00000100]: 00000000 -> 05000000\n"
+        );
+
+        let json = Shared::default();
+        i.set_tracer(Box::new(JsonTracer::new(json.clone())));
+        i.interpret("SW sp, a0, 0; NOP").unwrap();
+        assert_eq!(
+            json.text(),
+            "{\"pc\":12,\"word\":10559523,\"length\":4,\"instruction\":\"sw sp, a0, 0\",\
+             \"registers\":[],\"memory\":[{\"address\":256,\"old\":\"05000000\",\"new\":\"05000000\"}]}\n\
+             {\"pc\":16,\"word\":19,\"length\":4,\"instruction\":\"nop\",\
+             \"registers\":[],\"memory\":[]}\n"
+        );
+
+        // nothing more once it's taken away
+        assert!(i.take_tracer().is_some());
+        i.interpret("NOP").unwrap();
+        assert_eq!(json.text().lines().count(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+//! A facade for running Brubeck in a browser (eg: a teaching playground),
+//! built with the `wasm` feature and without `host`:
+//!
+//! ```text
+//! wasm-pack build --target web --no-default-features --features wasm
+//! ```
+//!
+//! There's one machine per page. `init` creates it with the given amount of
+//! memory and the standard devices, and `eval` interprets a line of input
+//! (instructions, or a register to show, as in the REPL), returning an object
+//! like the REPL's `--json` results:
+//!
+//! ```text
+//! { "input": "ADDI a0, zero, 5", "ok": true, "output": "...",
+//!   "registers": [{ "register": "x10", "old": 0, "new": 5 }], "memory": [],
+//!   "console": "", "uart": "", "exit": null }
+//! ```
+//!
+//! A failed line has the error's `code`, `message`, and `span` (byte offsets
+//! into the input, as `[start, end]`) in place of `output`.
+
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+
+use crate::board::Board;
+use crate::interpreter::Interpreter;
+use crate::json::Object;
+
+thread_local! {
+    static INTERPRETER: RefCell<Option<Interpreter>> = const { RefCell::new(None) };
+}
+
+/// Creates the machine, with `memory_size` bytes of memory, replacing any
+/// there was
+#[wasm_bindgen]
+pub fn init(memory_size: usize) -> Result<(), JsValue> {
+    let board = Board {
+        memory_size,
+        ..Board::default()
+    };
+    let interpreter = Interpreter::with_board(&board).map_err(|e| e.to_string())?;
+    INTERPRETER.with(|cell| *cell.borrow_mut() = Some(interpreter));
+    Ok(())
+}
+
+/// Interprets a line of input on the machine (creating one with the default
+/// memory size if `init` hasn't been called), returning what happened
+#[wasm_bindgen]
+pub fn eval(input: &str) -> JsValue {
+    let object = INTERPRETER.with(|cell| {
+        let mut interpreter = cell.borrow_mut();
+        evaluate(interpreter.get_or_insert_with(Interpreter::new), input)
+    });
+    let text = object.to_string();
+    js_sys::JSON::parse(&text).unwrap_or_else(|_| JsValue::from_str(&text))
+}
+
+/// What `eval` returns, as JSON
+fn evaluate(interpreter: &mut Interpreter, input: &str) -> Object {
+    let epoch = interpreter.history().epoch();
+    let result = interpreter.interpret(input);
+    let delta = interpreter
+        .history()
+        .diff(epoch, interpreter.history().epoch())
+        .unwrap_or_default();
+
+    let object = Object::new()
+        .string("input", input)
+        .bool("ok", result.is_ok());
+    let object = match &result {
+        Ok(output) => object.string("output", output),
+        Err(e) => {
+            let object = object
+                .string("code", e.kind().code())
+                .string("message", &e.to_string());
+            match e.span() {
+                Some(span) => object.raw("span", format!("[{},{}]", span.start, span.end)),
+                None => object,
+            }
+        }
+    };
+
+    let console = interpreter.take_output();
+    let uart = interpreter.take_uart_output();
+    let exit = interpreter
+        .exit_code()
+        .map_or("null".to_owned(), |code| code.to_string());
+    object
+        .delta(&delta)
+        .string("console", &String::from_utf8_lossy(&console))
+        .string("uart", &String::from_utf8_lossy(&uart))
+        .raw("exit", exit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_as_json() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            evaluate(&mut interpreter, "ADDI a0, zero, 5").to_string(),
+            r#"{"input":"ADDI a0, zero, 5","ok":true,"output":"addi a0, zero, 5","registers":[{"register":"x10","old":0,"new":5}],"memory":[],"console":"","uart":"","exit":null}"#
+        );
+
+        let failed = evaluate(&mut interpreter, "ADDI a0, zero").to_string();
+        assert!(failed.starts_with(r#"{"input":"ADDI a0, zero","ok":false,"code":"E102""#));
+        assert!(failed.contains(r#""span":[5,13]"#));
+
+        let exited = evaluate(&mut interpreter, "li a7, 93; ecall").to_string();
+        assert!(exited.ends_with(r#""exit":5}"#));
+    }
+}
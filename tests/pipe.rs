@@ -0,0 +1,69 @@
+//! Piping input into the `brubeck` binary: plain output, and an exit status
+//! that says whether it all worked.
+
+#![cfg(feature = "host")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `brubeck` with `input` piped in (and stdout captured, so it isn't a
+/// terminal), returning its exit status and output
+fn pipe(args: &[&str], input: &str) -> (i32, String) {
+    // so a config file in the home directory doesn't change anything
+    let config = std::env::temp_dir().join(format!("brubeck-pipe-{}", std::process::id()));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brubeck"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", &config)
+        .env("XDG_DATA_HOME", &config)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let status = output.status.code().unwrap();
+    (status, String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn plain_output() {
+    // even when color is asked for, there's no banner and no escapes
+    let (status, output) = pipe(&["--color"], "ADDI a0, zero, 5\na0\n");
+    assert_eq!(status, 0);
+    assert_eq!(output, "=> ✅ addi a0, zero, 5\n=> ✅ X10: 5 (0x5)\n");
+    assert!(!output.contains('\x1b'));
+}
+
+#[test]
+fn failures_exit_with_1() {
+    // the rest of the input still runs
+    let (status, output) = pipe(&[], "ADDI a0, zero\nADDI a0, zero, 1\n");
+    assert_eq!(status, 1);
+    assert!(output.contains("addi a0, zero, 1"));
+    assert!(output.ends_with("1 line failed\n"));
+
+    let (status, output) = pipe(&[], "ADDI a0, zero, 1\n/assert a0 == 2\n/assert a0 == 1\n");
+    assert_eq!(status, 1);
+    assert!(output.ends_with("1 of 2 assertions failed\n"));
+}
+
+#[test]
+fn guest_exit_code() {
+    let exit = "ADDI a0, zero, 3; ADDI a7, zero, 93; ECALL\n";
+    let (status, output) = pipe(&[], &format!("{exit}ADDI a0, zero, 4\n"));
+    assert_eq!(status, 3);
+    // nothing runs after the exit
+    assert!(output.ends_with("Program exited with code 3\n"));
+
+    // unless a line failed first
+    let (status, _) = pipe(&[], &format!("/assert a0 == 1\n{exit}"));
+    assert_eq!(status, 1);
+}